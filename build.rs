@@ -0,0 +1,37 @@
+//! Makes the commit the bot is built from known to it as
+//! `PROCESSBOT_GIT_SHA`, taken from the environment variable of the same name
+//! if set, eg. when building without the repository, otherwise from git.
+
+use std::path::Path;
+use std::process::Command;
+
+fn main() {
+	println!("cargo:rerun-if-env-changed=PROCESSBOT_GIT_SHA");
+	// the commit changes with the branch checked out, and the branch as
+	// commits are made on it
+	if let Ok(head) = std::fs::read_to_string(".git/HEAD") {
+		println!("cargo:rerun-if-changed=.git/HEAD");
+		if let Some(branch) = head.trim().strip_prefix("ref: ") {
+			if Path::new(".git").join(branch).exists() {
+				println!("cargo:rerun-if-changed=.git/{}", branch);
+			}
+		}
+	}
+
+	let sha = std::env::var("PROCESSBOT_GIT_SHA")
+		.ok()
+		.filter(|sha| !sha.is_empty())
+		.or_else(|| {
+			Command::new("git")
+				.args(["rev-parse", "--short=7", "HEAD"])
+				.output()
+				.ok()
+				.filter(|output| output.status.success())
+				.map(|output| {
+					String::from_utf8_lossy(&output.stdout).trim().to_string()
+				})
+		});
+	if let Some(sha) = sha {
+		println!("cargo:rustc-env=PROCESSBOT_GIT_SHA={}", sha);
+	}
+}
@@ -0,0 +1,429 @@
+use crate::{
+	config::BotConfig,
+	github::{Label, Review, ReviewState},
+	github_bot::GithubBot,
+};
+use itertools::Itertools;
+
+/// How the members of the configured teams allow a merge.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TeamApproval {
+	/// A member of a lead team asked for the merge.
+	LeadRequested,
+	/// A member of a lead team approved.
+	LeadApproved,
+	/// Enough members of the review teams approved.
+	ReviewersApproved,
+}
+
+/// Logins of the members of the given teams of an organization.  Teams which
+/// cannot be fetched are skipped.
+pub async fn team_members(
+	github_bot: &GithubBot,
+	org: &str,
+	teams: &[String],
+) -> Vec<String> {
+	let mut logins = Vec::new();
+	for slug in teams {
+		match github_bot.team(org, slug).await {
+			Ok(team) => match github_bot.team_members(team.id).await {
+				Ok(members) => {
+					logins.extend(members.into_iter().map(|user| user.login))
+				}
+				Err(e) => {
+					log::error!("Error getting members of {}: {}", slug, e)
+				}
+			},
+			Err(e) => log::error!("Error getting team {}: {}", slug, e),
+		}
+	}
+	logins.sort();
+	logins.dedup();
+	logins
+}
+
+/// Approvals needed from the review teams; fewer for PRs with an insubstantial
+/// label.
+pub fn required_reviewers(bot_config: &BotConfig, labels: &[Label]) -> usize {
+	let insubstantial = labels.iter().any(|label| {
+		bot_config
+			.insubstantial_labels
+			.iter()
+			.any(|name| label.name.contains(name.as_str()))
+	});
+	if insubstantial {
+		bot_config.insubstantial_min_reviewers
+	} else {
+		bot_config.min_reviewers
+	}
+}
+
+/// The latest review of each user which approves, requests changes or was
+/// dismissed, oldest first.  Comments leave the state of a user's review as
+/// it was.
+pub fn latest_reviews(reviews: &[Review]) -> Vec<Review> {
+	let mut latest = reviews
+		.iter()
+		.filter(|r| {
+			!matches!(
+				r.state,
+				None | Some(ReviewState::Commented)
+					| Some(ReviewState::Pending)
+			)
+		})
+		.sorted_by_key(|r| r.submitted_at)
+		.rev()
+		.unique_by(|r| &r.user.login)
+		.cloned()
+		.collect::<Vec<_>>();
+	latest.reverse();
+	latest
+}
+
+/// The members whose latest review is in `state`.
+fn latest_in_state(
+	reviews: &[Review],
+	members: &[String],
+	state: ReviewState,
+) -> Vec<String> {
+	latest_reviews(reviews)
+		.into_iter()
+		.filter(|r| r.state == Some(state) && members.contains(&r.user.login))
+		.map(|r| r.user.login)
+		.collect()
+}
+
+/// The first of the members whose latest review requests changes.
+pub fn changes_requested_by(
+	reviews: &[Review],
+	members: &[String],
+) -> Option<String> {
+	latest_in_state(reviews, members, ReviewState::ChangesRequested)
+		.into_iter()
+		.next()
+}
+
+/// Number of distinct members whose latest review approves.
+pub fn approvals_by(reviews: &[Review], members: &[String]) -> usize {
+	latest_in_state(reviews, members, ReviewState::Approved).len()
+}
+
+/// Whether the lead and review teams allow the merge.  Without lead teams
+/// nobody can bypass the review quorum.
+pub fn team_approval(
+	requested_by: &str,
+	reviews: &[Review],
+	leads: &[String],
+	reviewers: &[String],
+	min_reviewers: usize,
+) -> Option<TeamApproval> {
+	if leads.iter().any(|lead| lead == requested_by) {
+		Some(TeamApproval::LeadRequested)
+	} else if approvals_by(reviews, leads) >= 1 {
+		Some(TeamApproval::LeadApproved)
+	} else if approvals_by(reviews, reviewers) >= min_reviewers {
+		Some(TeamApproval::ReviewersApproved)
+	} else {
+		None
+	}
+}
+
+/// Which of the conditions allowing a merge a PR meets, as `merge_allowed`
+/// decides it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApprovalReport {
+	/// a member of the lead or review teams whose latest review requests
+	/// changes, which blocks the merge whatever else approves it
+	pub changes_requested_by: Option<String>,
+	pub team_approval: Option<TeamApproval>,
+	pub reviewer_approvals: usize,
+	pub min_reviewers: usize,
+	/// whether an owner or delegate asked for the merge or approved; `None`
+	/// if the owners were not looked up
+	pub owner_approval: Option<bool>,
+}
+
+impl ApprovalReport {
+	pub fn allows_merge(&self) -> bool {
+		self.changes_requested_by.is_none()
+			&& (self.team_approval.is_some()
+				|| self.owner_approval == Some(true))
+	}
+
+	/// Approvals still needed from the review teams to meet the quorum.
+	pub fn missing_reviewers(&self) -> usize {
+		self.min_reviewers.saturating_sub(self.reviewer_approvals)
+	}
+}
+
+/// Evaluate the approvals of a PR whose merge `requested_by` asked for.  The
+/// owners are only consulted if given, as looking them up takes requests.
+pub fn evaluate(
+	requested_by: &str,
+	reviews: &[Review],
+	leads: &[String],
+	reviewers: &[String],
+	owners: Option<&[String]>,
+	min_reviewers: usize,
+) -> ApprovalReport {
+	let leads_and_reviewers =
+		leads.iter().chain(reviewers).cloned().collect::<Vec<_>>();
+	let owner_approval = owners.map(|owners| {
+		let is_owner = |login: &str| owners.iter().any(|o| o == login);
+		// an owner or delegate approves if their latest review is an
+		// approval
+		is_owner(requested_by)
+			|| latest_reviews(reviews).iter().any(|r| {
+				is_owner(&r.user.login)
+					&& r.state == Some(ReviewState::Approved)
+			})
+	});
+	ApprovalReport {
+		changes_requested_by: changes_requested_by(
+			reviews,
+			&leads_and_reviewers,
+		),
+		team_approval: team_approval(
+			requested_by,
+			reviews,
+			leads,
+			reviewers,
+			min_reviewers,
+		),
+		reviewer_approvals: approvals_by(reviews, reviewers),
+		min_reviewers,
+		owner_approval,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::github::User;
+
+	fn review(login: &str, state: ReviewState) -> Review {
+		Review {
+			user: User {
+				login: login.to_string(),
+				..User::default()
+			},
+			state: Some(state),
+			..Review::default()
+		}
+	}
+
+	/// A review submitted `minutes` after the first.
+	fn review_at(login: &str, state: ReviewState, minutes: i64) -> Review {
+		use chrono::TimeZone;
+		Review {
+			submitted_at: Some(
+				chrono::Utc.timestamp(1_600_000_000 + minutes * 60, 0),
+			),
+			..review(login, state)
+		}
+	}
+
+	fn label(name: &str) -> Label {
+		Label {
+			name: name.to_string(),
+			..Label::default()
+		}
+	}
+
+	fn logins(logins: &[&str]) -> Vec<String> {
+		logins.iter().map(|login| login.to_string()).collect()
+	}
+
+	#[test]
+	fn test_required_reviewers() {
+		let config = BotConfig {
+			min_reviewers: 3,
+			insubstantial_labels: logins(&["B0-silent", "trivial"]),
+			insubstantial_min_reviewers: 2,
+			..BotConfig::default()
+		};
+		assert_eq!(required_reviewers(&config, &[]), 3);
+		assert_eq!(
+			required_reviewers(&config, &[label("A0-please_review")]),
+			3
+		);
+		assert_eq!(required_reviewers(&config, &[label("B0-silent")]), 2);
+		assert_eq!(required_reviewers(&config, &[label("Q1-trivial")]), 2);
+		// the default label no longer applies once others are configured
+		assert_eq!(required_reviewers(&config, &[label("insubstantial")]), 3);
+	}
+
+	#[test]
+	fn test_custom_review_teams() {
+		let reviewers = logins(&["alice", "bob", "carol"]);
+		let reviews = vec![
+			review("alice", ReviewState::Approved),
+			// approving twice still counts once
+			review("alice", ReviewState::Approved),
+			review("bob", ReviewState::ChangesRequested),
+			review("mallory", ReviewState::Approved),
+		];
+		assert_eq!(approvals_by(&reviews, &reviewers), 1);
+		assert_eq!(team_approval("dave", &reviews, &[], &reviewers, 2), None);
+		assert_eq!(
+			team_approval("dave", &reviews, &[], &reviewers, 1),
+			Some(TeamApproval::ReviewersApproved)
+		);
+
+		let reviews = vec![
+			review("alice", ReviewState::Approved),
+			review("carol", ReviewState::Approved),
+		];
+		assert_eq!(
+			team_approval("dave", &reviews, &[], &reviewers, 2),
+			Some(TeamApproval::ReviewersApproved)
+		);
+	}
+
+	#[test]
+	fn test_approve_then_request_changes() {
+		let reviewers = logins(&["alice", "bob"]);
+		// listed out of order, as the latest review is what counts
+		let reviews = vec![
+			review_at("alice", ReviewState::ChangesRequested, 2),
+			review_at("alice", ReviewState::Approved, 1),
+			review_at("bob", ReviewState::Approved, 0),
+		];
+		assert_eq!(approvals_by(&reviews, &reviewers), 1);
+		assert_eq!(
+			changes_requested_by(&reviews, &reviewers).as_deref(),
+			Some("alice")
+		);
+		assert_eq!(team_approval("dave", &reviews, &[], &reviewers, 2), None);
+		// changes requested by others do not block
+		assert_eq!(changes_requested_by(&reviews, &logins(&["bob"])), None);
+	}
+
+	#[test]
+	fn test_request_changes_then_approve() {
+		let reviewers = logins(&["alice", "bob"]);
+		let reviews = vec![
+			review_at("alice", ReviewState::ChangesRequested, 0),
+			review_at("bob", ReviewState::Approved, 1),
+			review_at("alice", ReviewState::Approved, 2),
+			// a later comment leaves the approval standing
+			review_at("alice", ReviewState::Commented, 3),
+		];
+		assert_eq!(changes_requested_by(&reviews, &reviewers), None);
+		assert_eq!(approvals_by(&reviews, &reviewers), 2);
+		assert_eq!(
+			team_approval("dave", &reviews, &[], &reviewers, 2),
+			Some(TeamApproval::ReviewersApproved)
+		);
+	}
+
+	#[test]
+	fn test_dismissed_reviews() {
+		let leads = logins(&["gavofyork"]);
+		let reviewers = logins(&["alice", "bob"]);
+		// GitHub reports dismissed reviews with the state `DISMISSED`
+		let reviews = vec![
+			review_at("gavofyork", ReviewState::Dismissed, 0),
+			review_at("alice", ReviewState::Dismissed, 1),
+			review_at("bob", ReviewState::Approved, 2),
+		];
+		assert_eq!(approvals_by(&reviews, &leads), 0);
+		assert_eq!(approvals_by(&reviews, &reviewers), 1);
+		assert_eq!(changes_requested_by(&reviews, &reviewers), None);
+		assert_eq!(
+			team_approval("dave", &reviews, &leads, &reviewers, 2),
+			None
+		);
+		// dismissing a request for changes lifts the block
+		let reviews = vec![
+			review_at("alice", ReviewState::Dismissed, 0),
+			review_at("bob", ReviewState::Approved, 1),
+		];
+		assert_eq!(changes_requested_by(&reviews, &reviewers), None);
+		assert_eq!(
+			latest_reviews(&reviews)
+				.iter()
+				.map(|r| r.user.login.as_str())
+				.collect::<Vec<_>>(),
+			vec!["alice", "bob"]
+		);
+	}
+
+	#[test]
+	fn test_lead_teams() {
+		let leads = logins(&["gavofyork"]);
+		let reviewers = logins(&["alice", "bob"]);
+		assert_eq!(
+			team_approval("gavofyork", &[], &leads, &reviewers, 2),
+			Some(TeamApproval::LeadRequested)
+		);
+		assert_eq!(
+			team_approval(
+				"alice",
+				&[review("gavofyork", ReviewState::Approved)],
+				&leads,
+				&reviewers,
+				2
+			),
+			Some(TeamApproval::LeadApproved)
+		);
+	}
+
+	#[test]
+	fn test_evaluate() {
+		let leads = logins(&["gavofyork"]);
+		let reviewers = logins(&["alice", "bob"]);
+		let owners = logins(&["olivia"]);
+		let reviews = vec![review("alice", ReviewState::Approved)];
+		let report = evaluate("dave", &reviews, &leads, &reviewers, None, 2);
+		assert_eq!(
+			report,
+			ApprovalReport {
+				changes_requested_by: None,
+				team_approval: None,
+				reviewer_approvals: 1,
+				min_reviewers: 2,
+				owner_approval: None,
+			}
+		);
+		assert!(!report.allows_merge());
+		assert_eq!(report.missing_reviewers(), 1);
+
+		let report =
+			evaluate("dave", &reviews, &leads, &reviewers, Some(&owners), 2);
+		assert_eq!(report.owner_approval, Some(false));
+		assert!(!report.allows_merge());
+		// an owner asking for the merge is as good as their approval
+		let report =
+			evaluate("olivia", &reviews, &leads, &reviewers, Some(&owners), 2);
+		assert_eq!(report.owner_approval, Some(true));
+		assert!(report.allows_merge());
+
+		let reviews = vec![
+			review("olivia", ReviewState::Approved),
+			review("alice", ReviewState::Approved),
+			review("bob", ReviewState::ChangesRequested),
+		];
+		let report =
+			evaluate("dave", &reviews, &leads, &reviewers, Some(&owners), 1);
+		assert_eq!(report.changes_requested_by.as_deref(), Some("bob"));
+		assert_eq!(report.team_approval, Some(TeamApproval::ReviewersApproved));
+		assert_eq!(report.owner_approval, Some(true));
+		assert_eq!(report.missing_reviewers(), 0);
+		assert!(!report.allows_merge());
+	}
+
+	#[test]
+	fn test_no_lead_teams() {
+		// without a lead team, a lead's request or approval counts for nothing
+		// more than its membership of the review teams
+		let reviewers = logins(&["gavofyork", "alice"]);
+		let reviews = vec![review("gavofyork", ReviewState::Approved)];
+		assert_eq!(team_approval("gavofyork", &[], &[], &reviewers, 2), None);
+		assert_eq!(team_approval("alice", &reviews, &[], &reviewers, 2), None);
+		assert_eq!(
+			team_approval("alice", &reviews, &[], &reviewers, 1),
+			Some(TeamApproval::ReviewersApproved)
+		);
+	}
+}
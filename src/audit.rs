@@ -0,0 +1,319 @@
+use chrono::{DateTime, TimeZone, Utc};
+use rocksdb::DB;
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{
+	constants::*,
+	db::{self, Transaction},
+	error::*,
+	Result,
+};
+
+pub(crate) const KEY_PREFIX: &str = "audit/";
+
+/// Tells apart entries recorded in the same millisecond.
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+/// Commands which change something, and so are audited.  The others, eg.
+/// `bot burnin status`, only report.
+const PRIVILEGED_COMMANDS: [&str; 15] = [
+	AUTO_MERGE_REQUEST,
+	AUTO_MERGE_FORCE,
+	AUTO_MERGE_CANCEL,
+	AUTO_MERGE_CONFIRM,
+	AUTOMERGE_OFF,
+	REBASE,
+	BACKPORT,
+	CHERRY_PICK,
+	MERGE_FREEZE,
+	MERGE_UNFREEZE,
+	BURNIN_REQUEST,
+	BURNIN_CANCEL,
+	BOT_ADMIN,
+	BOT_IGNORE,
+	BOT_UNIGNORE,
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+	Succeeded,
+	Failed,
+}
+
+/// A privileged action taken by the bot on behalf of a user.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+	pub at: DateTime<Utc>,
+	/// login of the user who commanded the action
+	pub actor: String,
+	pub owner: String,
+	pub repo_name: String,
+	pub number: i64,
+	/// the comment commanding the bot, or `merge` for a merge the bot made
+	pub command: String,
+	pub outcome: Outcome,
+	pub error: Option<String>,
+}
+
+impl AuditEntry {
+	pub fn new<T>(
+		actor: &str,
+		owner: &str,
+		repo_name: &str,
+		number: i64,
+		command: &str,
+		result: &Result<T>,
+	) -> Self {
+		Self {
+			at: Utc::now(),
+			actor: actor.to_string(),
+			owner: owner.to_string(),
+			repo_name: repo_name.to_string(),
+			number,
+			command: command.trim().to_string(),
+			outcome: match result {
+				Ok(_) => Outcome::Succeeded,
+				Err(_) => Outcome::Failed,
+			},
+			error: result.as_ref().err().map(|e| e.to_string()),
+		}
+	}
+}
+
+/// Whether a bot command is audited.
+pub fn is_privileged(command: &str) -> bool {
+	let command = command.trim().to_lowercase();
+	// `bot burnin status` is not a burn-in request
+	command != BURNIN_STATUS
+		&& PRIVILEGED_COMMANDS.iter().any(|privileged| {
+			command == *privileged
+				|| command.starts_with(&format!("{} ", privileged))
+				|| command.starts_with(&format!("{}\n", privileged))
+		})
+}
+
+fn db_key(at: DateTime<Utc>) -> String {
+	format!(
+		"{}{:020}/{:010}",
+		KEY_PREFIX,
+		at.timestamp_millis().max(0),
+		SEQUENCE.fetch_add(1, Ordering::Relaxed)
+	)
+}
+
+/// Append an entry.  Entries are never changed or removed by the bot.
+pub fn record(tx: &mut Transaction, entry: &AuditEntry) -> Result<()> {
+	let bytes = serde_json::to_vec(entry).context(Json)?;
+	tx.put(db::AUDIT, db_key(entry.at).as_bytes(), &bytes)
+}
+
+/// Which entries to return from `query`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Filter {
+	/// `owner/repo`
+	pub repo: Option<String>,
+	pub since: Option<DateTime<Utc>>,
+}
+
+impl Filter {
+	fn matches(&self, entry: &AuditEntry) -> bool {
+		self.repo.as_ref().is_none_or(|repo| {
+			repo.eq_ignore_ascii_case(&format!(
+				"{}/{}",
+				entry.owner, entry.repo_name
+			))
+		}) && self.since.is_none_or(|since| entry.at >= since)
+	}
+}
+
+/// Parse `since` as either an RFC 3339 date or seconds since the epoch.
+pub fn parse_since(since: &str) -> std::result::Result<DateTime<Utc>, String> {
+	let since = since.trim();
+	match since.parse::<i64>() {
+		Ok(secs) => Ok(Utc.timestamp(secs, 0)),
+		Err(_) => DateTime::parse_from_rfc3339(since)
+			.map(|since| since.with_timezone(&Utc))
+			.map_err(|e| format!("invalid date {}: {}", since, e)),
+	}
+}
+
+/// The entries matching `filter`, oldest first.
+pub fn query(db: &DB, filter: &Filter) -> Result<Vec<AuditEntry>> {
+	let start = match filter.since {
+		Some(since) => {
+			format!("{}{:020}", KEY_PREFIX, since.timestamp_millis().max(0))
+		}
+		None => KEY_PREFIX.to_string(),
+	};
+	db::family(db, db::AUDIT)?
+		.iter_from(start.as_bytes(), KEY_PREFIX.as_bytes())
+		.map(|(_, v)| serde_json::from_slice::<AuditEntry>(&v).context(Json))
+		.filter(|entry| entry.as_ref().map_or(true, |e| filter.matches(e)))
+		.collect()
+}
+
+/// Quote a CSV field if it needs to be.
+fn csv_field(field: &str) -> String {
+	if field.contains([',', '"', '\n', '\r']) {
+		format!("\"{}\"", field.replace('"', "\"\""))
+	} else {
+		field.to_string()
+	}
+}
+
+/// The entries as CSV, with a header row.
+pub fn to_csv(entries: &[AuditEntry]) -> String {
+	let mut csv =
+		"at,actor,owner,repo_name,number,command,outcome,error\r\n".to_string();
+	for entry in entries {
+		let outcome = match entry.outcome {
+			Outcome::Succeeded => "succeeded",
+			Outcome::Failed => "failed",
+		};
+		let fields = [
+			entry.at.to_rfc3339(),
+			entry.actor.clone(),
+			entry.owner.clone(),
+			entry.repo_name.clone(),
+			entry.number.to_string(),
+			entry.command.clone(),
+			outcome.to_string(),
+			entry.error.clone().unwrap_or_default(),
+		];
+		csv.push_str(
+			&fields
+				.iter()
+				.map(|field| csv_field(field))
+				.collect::<Vec<_>>()
+				.join(","),
+		);
+		csv.push_str("\r\n");
+	}
+	csv
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::db::TempDb;
+
+	fn db(name: &str) -> TempDb {
+		TempDb::new(&format!("audit-{}", name))
+	}
+
+	fn entry(repo_name: &str, secs: i64, command: &str) -> AuditEntry {
+		AuditEntry {
+			at: Utc.timestamp(secs, 0),
+			actor: "alice".to_string(),
+			owner: "paritytech".to_string(),
+			repo_name: repo_name.to_string(),
+			number: 1,
+			command: command.to_string(),
+			outcome: Outcome::Succeeded,
+			error: None,
+		}
+	}
+
+	#[test]
+	fn test_is_privileged() {
+		assert!(is_privileged("bot merge"));
+		assert!(is_privileged(" Bot Merge Force "));
+		assert!(is_privileged("bot freeze release"));
+		assert!(is_privileged("bot burnin\n```toml\n```"));
+		assert!(is_privileged("bot admin deny mallory"));
+		assert!(!is_privileged("bot burnin status"));
+		assert!(!is_privileged("bot compare substrate"));
+		assert!(!is_privileged("bot mergeable"));
+	}
+
+	#[test]
+	fn test_query() {
+		let db = db("query");
+		let entries = vec![
+			entry("substrate", 100, "bot merge"),
+			entry("polkadot", 200, "bot merge force"),
+			entry("substrate", 200, "bot merge cancel"),
+			entry("substrate", 300, "bot rebase"),
+		];
+		for entry in &entries {
+			db::atomically(&db, |tx| record(tx, entry)).unwrap();
+		}
+		// an unrelated key after the audit entries
+		db::family(&db, db::AUDIT)
+			.unwrap()
+			.put(b"b-other", b"")
+			.unwrap();
+
+		assert_eq!(query(&db, &Filter::default()).unwrap(), entries);
+		assert_eq!(
+			query(
+				&db,
+				&Filter {
+					repo: Some("ParityTech/substrate".to_string()),
+					since: None,
+				}
+			)
+			.unwrap(),
+			vec![entries[0].clone(), entries[2].clone(), entries[3].clone()]
+		);
+		assert_eq!(
+			query(
+				&db,
+				&Filter {
+					repo: Some("paritytech/substrate".to_string()),
+					since: Some(parse_since("200").unwrap()),
+				}
+			)
+			.unwrap(),
+			vec![entries[2].clone(), entries[3].clone()]
+		);
+		assert_eq!(
+			query(
+				&db,
+				&Filter {
+					repo: None,
+					since: Some(
+						parse_since("1970-01-01T00:04:00+00:00").unwrap()
+					),
+				}
+			)
+			.unwrap(),
+			vec![entries[3].clone()]
+		);
+		assert!(parse_since("yesterday").is_err());
+	}
+
+	#[test]
+	fn test_failure_recorded() {
+		let result: Result<()> = Err(Error::Message {
+			msg: "Merge failed.".to_string(),
+		});
+		let entry = AuditEntry::new(
+			"alice",
+			"paritytech",
+			"substrate",
+			1,
+			" bot merge\n",
+			&result,
+		);
+		assert_eq!(entry.command, "bot merge");
+		assert_eq!(entry.outcome, Outcome::Failed);
+		assert_eq!(entry.error.as_deref(), Some("Error: Merge failed."));
+	}
+
+	#[test]
+	fn test_csv_escaping() {
+		let mut freeze = entry("substrate", 0, "bot freeze release, \"v0.9\"");
+		freeze.outcome = Outcome::Failed;
+		freeze.error = Some("line one\nline two".to_string());
+		assert_eq!(
+			to_csv(&[entry("substrate", 0, "bot merge"), freeze]),
+			"at,actor,owner,repo_name,number,command,outcome,error\r\n\
+			 1970-01-01T00:00:00+00:00,alice,paritytech,substrate,1,bot merge,succeeded,\r\n\
+			 1970-01-01T00:00:00+00:00,alice,paritytech,substrate,1,\"bot freeze release, \"\"v0.9\"\"\",failed,\"line one\nline two\"\r\n"
+		);
+	}
+}
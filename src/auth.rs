@@ -1,4 +1,13 @@
-use crate::{error::Error, github_bot::GithubBot, Result};
+use crate::{error::Error, github::Permission, github_bot::GithubBot, Result};
+
+/// What a user was found to be allowed to do on a repository.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Grant {
+	Permission(Permission),
+	/// whether the user is a member of the organization, if their permission
+	/// on the repository could not be read
+	OrgMember(bool),
+}
 
 // This is a lame alternative to an async closure.
 pub struct GithubUserAuthenticator {
@@ -6,6 +15,7 @@ pub struct GithubUserAuthenticator {
 	org: String,
 	repo_name: String,
 	pr_number: i64,
+	required_permission: Permission,
 }
 
 impl GithubUserAuthenticator {
@@ -14,48 +24,175 @@ impl GithubUserAuthenticator {
 		org: &str,
 		repo_name: &str,
 		pr_number: i64,
+		required_permission: Permission,
 	) -> Self {
 		Self {
 			username: username.to_string(),
 			org: org.to_string(),
 			repo_name: repo_name.to_string(),
 			pr_number,
+			required_permission,
 		}
 	}
 
-	pub async fn check_org_membership(
+	fn issue(&self) -> Option<(String, String, i64)> {
+		Some((self.org.clone(), self.repo_name.clone(), self.pr_number))
+	}
+
+	/// Check that the user has the required permission on the repository,
+	/// or, if it cannot be read, that they are a member of the organization.
+	pub async fn check_permission(&self, github_bot: &GithubBot) -> Result<()> {
+		let grant = match github_bot
+			.user_permission(&self.org, &self.repo_name, &self.username)
+			.await
+		{
+			Ok(permission) => Grant::Permission(permission),
+			Err(e) => {
+				log::warn!(
+					"Error getting the permission of {} on {}/{}; checking organization membership instead: {}",
+					self.username,
+					self.org,
+					self.repo_name,
+					e
+				);
+				Grant::OrgMember(self.check_org_membership(github_bot).await?)
+			}
+		};
+		self.authorize(grant)
+	}
+
+	async fn check_org_membership(
 		&self,
 		github_bot: &GithubBot,
-	) -> Result<()> {
-		let is_member = github_bot
+	) -> Result<bool> {
+		github_bot
 			.org_member(&self.org, &self.username)
 			.await
 			.map_err(|e| {
 				Error::OrganizationMembership {
 					source: Box::new(e),
 				}
-				.map_issue(Some((
-					self.org.clone(),
-					self.repo_name.clone(),
-					self.pr_number,
-				)))
-			})?;
-
-		if !is_member {
-			Err(Error::OrganizationMembership {
+				.map_issue(self.issue())
+			})
+	}
+
+	fn authorize(&self, grant: Grant) -> Result<()> {
+		match grant {
+			Grant::Permission(found) if found >= self.required_permission => {
+				Ok(())
+			}
+			Grant::Permission(found) => Err(Error::InsufficientPermission {
+				login: self.username.clone(),
+				owner: self.org.clone(),
+				repo_name: self.repo_name.clone(),
+				found,
+				required: self.required_permission,
+			}
+			.map_issue(self.issue())),
+			Grant::OrgMember(true) => Ok(()),
+			Grant::OrgMember(false) => Err(Error::OrganizationMembership {
 				source: Box::new(Error::Message {
 					msg: format!(
-						"{} is not a member of {}; aborting.",
-						self.username, self.org
+						"The permission of @{} on {}/{} could not be read, and they are not a member of {}; aborting.",
+						self.username, self.org, self.repo_name, self.org
 					),
 				}),
 			}
-			.map_issue(Some((
-				self.org.clone(),
-				self.repo_name.clone(),
-				self.pr_number,
-			))))?;
+			.map_issue(self.issue())),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn authenticator(
+		required_permission: Permission,
+	) -> GithubUserAuthenticator {
+		GithubUserAuthenticator::new(
+			"alice",
+			"paritytech",
+			"substrate",
+			1,
+			required_permission,
+		)
+	}
+
+	fn denial(result: Result<()>) -> String {
+		match result {
+			Err(Error::WithIssue { source, issue }) => {
+				assert_eq!(
+					issue,
+					Some((
+						"paritytech".to_string(),
+						"substrate".to_string(),
+						1
+					))
+				);
+				crate::error_comments::ErrorComment::for_error(
+					&source,
+					&Default::default(),
+				)
+				.render()
+			}
+			res => panic!("unexpected result: {:?}", res),
 		}
-		Ok(())
+	}
+
+	#[test]
+	fn test_permission_levels() {
+		let write = authenticator(Permission::Write);
+		assert!(write
+			.authorize(Grant::Permission(Permission::Admin))
+			.is_ok());
+		assert!(write
+			.authorize(Grant::Permission(Permission::Write))
+			.is_ok());
+		assert_eq!(
+			denial(write.authorize(Grant::Permission(Permission::Read))),
+			"@alice has read permission on paritytech/substrate, but write is required.\n\n**What you can do:**\n- Ask someone with write permission on paritytech/substrate to comment instead."
+		);
+		assert!(write
+			.authorize(Grant::Permission(Permission::None))
+			.is_err());
+
+		let admin = authenticator(Permission::Admin);
+		assert!(admin
+			.authorize(Grant::Permission(Permission::Admin))
+			.is_ok());
+		assert!(admin
+			.authorize(Grant::Permission(Permission::Write))
+			.is_err());
+
+		let read = authenticator(Permission::Read);
+		assert!(read.authorize(Grant::Permission(Permission::Read)).is_ok());
+		assert!(read.authorize(Grant::Permission(Permission::None)).is_err());
+	}
+
+	#[test]
+	fn test_org_membership_fallback() {
+		let auth = authenticator(Permission::Admin);
+		assert!(auth.authorize(Grant::OrgMember(true)).is_ok());
+		assert_eq!(
+			denial(auth.authorize(Grant::OrgMember(false))),
+			"The permission of @alice on paritytech/substrate could not be read, and they are not a member of paritytech; aborting.\n\n**What you can do:**\n- Ask a member of the organization to comment instead."
+		);
+	}
+
+	#[test]
+	fn test_parse_permission() {
+		let parse = |body| {
+			serde_json::from_str::<crate::github::RepositoryPermission>(body)
+				.unwrap()
+				.permission
+		};
+		assert_eq!(
+			parse(r#"{ "permission": "admin", "user": { "login": "alice" } }"#),
+			Permission::Admin
+		);
+		assert_eq!(parse(r#"{ "permission": "none" }"#), Permission::None);
+		assert_eq!("write".parse(), Ok(Permission::Write));
+		assert!("none".parse::<Permission>().is_err());
 	}
 }
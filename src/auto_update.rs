@@ -0,0 +1,152 @@
+use rocksdb::{Direction, IteratorMode, DB};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+
+use crate::{error::*, Result};
+
+const KEY_PREFIX: &str = "auto-update/";
+
+/// A PR labelled to be kept up to date with its base branch.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AutoUpdate {
+	pub owner: String,
+	pub repo_name: String,
+	pub number: i64,
+	/// the branch whose pushes update the PR
+	pub base: String,
+	/// when the bot last updated the PR, in seconds since the epoch
+	pub updated_at: Option<i64>,
+}
+
+impl AutoUpdate {
+	pub fn new(owner: &str, repo_name: &str, number: i64, base: &str) -> Self {
+		Self {
+			owner: owner.to_string(),
+			repo_name: repo_name.to_string(),
+			number,
+			base: base.to_string(),
+			updated_at: None,
+		}
+	}
+
+	/// Whether the PR may be updated again, at most once every `interval`
+	/// seconds.
+	pub fn due(&self, now: i64, interval: i64) -> bool {
+		self.updated_at
+			.is_none_or(|updated_at| now - updated_at >= interval)
+	}
+}
+
+fn repo_prefix(owner: &str, repo_name: &str) -> String {
+	format!(
+		"{}{}/{}/",
+		KEY_PREFIX,
+		owner.to_lowercase(),
+		repo_name.to_lowercase()
+	)
+}
+
+fn key(owner: &str, repo_name: &str, number: i64) -> String {
+	format!("{}{}", repo_prefix(owner, repo_name), number)
+}
+
+/// Record a PR, or its last update.
+pub fn record(db: &DB, entry: &AutoUpdate) -> Result<()> {
+	let bytes = serde_json::to_vec(entry).context(Json)?;
+	db.put(
+		key(&entry.owner, &entry.repo_name, entry.number).as_bytes(),
+		bytes,
+	)
+	.context(Db)
+}
+
+pub fn remove(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+) -> Result<()> {
+	db.delete(key(owner, repo_name, number).as_bytes())
+		.context(Db)
+}
+
+/// The PRs of a repository to update when `base` moves.
+pub fn list(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+	base: &str,
+) -> Result<Vec<AutoUpdate>> {
+	let prefix = repo_prefix(owner, repo_name);
+	db.iterator(IteratorMode::From(prefix.as_bytes(), Direction::Forward))
+		.take_while(|(k, _)| k.starts_with(prefix.as_bytes()))
+		.map(|(_, v)| serde_json::from_slice::<AutoUpdate>(&v).context(Json))
+		.filter(|entry| entry.as_ref().map_or(true, |e| e.base == base))
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::db::TempDb;
+
+	fn db(name: &str) -> TempDb {
+		TempDb::new(&format!("auto-update-{}", name))
+	}
+
+	#[test]
+	fn test_bookkeeping() {
+		let db = db("bookkeeping");
+		let first = AutoUpdate::new("paritytech", "substrate", 1, "master");
+		let release =
+			AutoUpdate::new("paritytech", "substrate", 2, "release-v0.9.x");
+		let other = AutoUpdate::new("paritytech", "substrate-two", 3, "master");
+		for entry in &[&first, &release, &other] {
+			record(&db, entry).unwrap();
+		}
+
+		assert_eq!(
+			list(&db, "ParityTech", "substrate", "master").unwrap(),
+			vec![first.clone()]
+		);
+		assert_eq!(
+			list(&db, "paritytech", "substrate", "release-v0.9.x").unwrap(),
+			vec![release.clone()]
+		);
+		assert_eq!(
+			list(&db, "paritytech", "substrate-two", "master").unwrap(),
+			vec![other]
+		);
+
+		// an update is recorded in place
+		let updated = AutoUpdate {
+			updated_at: Some(100),
+			..first
+		};
+		record(&db, &updated).unwrap();
+		assert_eq!(
+			list(&db, "paritytech", "substrate", "master").unwrap(),
+			vec![updated]
+		);
+
+		remove(&db, "paritytech", "substrate", 1).unwrap();
+		assert!(list(&db, "paritytech", "substrate", "master")
+			.unwrap()
+			.is_empty());
+		// removing a PR which is not recorded is fine
+		remove(&db, "paritytech", "substrate", 1).unwrap();
+	}
+
+	#[test]
+	fn test_due() {
+		let entry = AutoUpdate::new("paritytech", "substrate", 1, "master");
+		assert!(entry.due(0, 600));
+		let entry = AutoUpdate {
+			updated_at: Some(1000),
+			..entry
+		};
+		assert!(!entry.due(1000, 600));
+		assert!(!entry.due(1599, 600));
+		assert!(entry.due(1600, 600));
+	}
+}
@@ -0,0 +1,138 @@
+use rocksdb::DB;
+use snafu::ResultExt;
+
+use crate::{codeowners, error::*, github::PullRequestFile, Result};
+
+const OFF_KEY_PREFIX: &str = "automerge-off/";
+
+/// Whether a PR is merged without anyone commenting `bot merge`: its author
+/// is trusted, and every file it changes matches one of `allowed_files`, eg.
+/// lockfiles and manifests.  Patterns which cannot be understood match
+/// nothing.
+pub fn is_eligible(
+	trusted_authors: &[String],
+	allowed_files: &[String],
+	author: &str,
+	files: &[PullRequestFile],
+) -> bool {
+	if !trusted_authors
+		.iter()
+		.any(|trusted| trusted.eq_ignore_ascii_case(author))
+	{
+		return false;
+	}
+	let allowed = allowed_files
+		.iter()
+		.filter_map(|pattern| match codeowners::glob_regex(pattern) {
+			Ok(regex) => Some(regex),
+			Err(e) => {
+				log::warn!(
+					"Ignoring invalid automerge pattern '{}': {}",
+					pattern,
+					e
+				);
+				None
+			}
+		})
+		.collect::<Vec<_>>();
+	!files.is_empty()
+		&& files.iter().all(|file| {
+			allowed.iter().any(|regex| regex.is_match(&file.filename))
+		})
+}
+
+fn off_key(owner: &str, repo_name: &str, number: i64) -> String {
+	format!(
+		"{}{}/{}/{}",
+		OFF_KEY_PREFIX,
+		owner.to_lowercase(),
+		repo_name.to_lowercase(),
+		number
+	)
+}
+
+/// Whether someone commented `bot automerge off` on the PR.
+pub fn is_off(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+) -> Result<bool> {
+	db.get(off_key(owner, repo_name, number).as_bytes())
+		.context(Db)
+		.map(|bytes| bytes.is_some())
+}
+
+pub fn turn_off(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+) -> Result<()> {
+	db.put(off_key(owner, repo_name, number).as_bytes(), b"")
+		.context(Db)
+}
+
+/// Forget a PR, eg. once it is closed.
+pub fn remove(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+) -> Result<()> {
+	db.delete(off_key(owner, repo_name, number).as_bytes())
+		.context(Db)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::db::TempDb;
+
+	fn files(names: &[&str]) -> Vec<PullRequestFile> {
+		names
+			.iter()
+			.map(|name| PullRequestFile {
+				filename: name.to_string(),
+				status: "modified".to_string(),
+				..PullRequestFile::default()
+			})
+			.collect()
+	}
+
+	fn strings(values: &[&str]) -> Vec<String> {
+		values.iter().map(|value| value.to_string()).collect()
+	}
+
+	#[test]
+	fn test_is_eligible() {
+		let trusted = strings(&["dependabot[bot]", "parity-processbot"]);
+		let allowed = strings(&["Cargo.lock", "Cargo.toml"]);
+		let bump = files(&["Cargo.lock", "client/cli/Cargo.toml"]);
+		assert!(is_eligible(&trusted, &allowed, "dependabot[bot]", &bump));
+		assert!(is_eligible(&trusted, &allowed, "Dependabot[bot]", &bump));
+		// anyone else
+		assert!(!is_eligible(&trusted, &allowed, "gavofyork", &bump));
+		assert!(!is_eligible(&[], &allowed, "dependabot[bot]", &bump));
+		// a file besides lockfiles and manifests
+		assert!(!is_eligible(
+			&trusted,
+			&allowed,
+			"dependabot[bot]",
+			&files(&["Cargo.lock", "client/cli/src/lib.rs"])
+		));
+		assert!(!is_eligible(&trusted, &allowed, "dependabot[bot]", &[]));
+		assert!(!is_eligible(&trusted, &[], "dependabot[bot]", &bump));
+	}
+
+	#[test]
+	fn test_turn_off() {
+		let db = TempDb::new("automerge");
+		assert!(!is_off(&db, "paritytech", "substrate", 1).unwrap());
+		turn_off(&db, "paritytech", "substrate", 1).unwrap();
+		assert!(is_off(&db, "ParityTech", "Substrate", 1).unwrap());
+		assert!(!is_off(&db, "paritytech", "substrate", 2).unwrap());
+		remove(&db, "paritytech", "substrate", 1).unwrap();
+		assert!(!is_off(&db, "paritytech", "substrate", 1).unwrap());
+	}
+}
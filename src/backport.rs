@@ -0,0 +1,352 @@
+use itertools::Itertools;
+
+use crate::{
+	cmd::Kind, constants::*, error::*, github::PullRequest, rebase,
+	working_clone::WorkingClone, Result,
+};
+
+/// `bot backport <branch>...`: the release branches to open backports to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackportCommand {
+	pub branches: Vec<String>,
+}
+
+/// Parse a `bot backport` comment; an invalid one is an error explaining why.
+pub fn parse_command(
+	body: &str,
+) -> Option<std::result::Result<BackportCommand, String>> {
+	let body = body.trim();
+	let command = body.get(..BACKPORT.len())?;
+	let rest = body.get(BACKPORT.len()..)?;
+	if !command.eq_ignore_ascii_case(BACKPORT)
+		|| !(rest.is_empty() || rest.starts_with(char::is_whitespace))
+	{
+		return None;
+	}
+	let branches = rest.split_whitespace().unique().collect::<Vec<_>>();
+	if branches.is_empty() {
+		return Some(Err(format!(
+			"name the branches to backport to, eg. `{} release-v0.9.30`",
+			BACKPORT
+		)));
+	}
+	Some(
+		branches
+			.into_iter()
+			.map(|branch| validate_branch(branch).map(|()| branch.to_string()))
+			.collect::<std::result::Result<Vec<_>, _>>()
+			.map(|branches| BackportCommand { branches }),
+	)
+}
+
+/// Check that `branch` is a name git accepts for a branch, and cannot be
+/// taken for an option.
+pub fn validate_branch(branch: &str) -> std::result::Result<(), String> {
+	let invalid = |why: &str| Err(format!("`{}` {}", branch, why));
+	if branch.starts_with('-') {
+		return invalid("starts with a dash");
+	}
+	if branch == "@" || branch.contains("@{") {
+		return invalid("is not a valid branch name");
+	}
+	if branch.chars().any(|c| {
+		c.is_ascii_control() || c.is_whitespace() || "~^:?*[\\".contains(c)
+	}) {
+		return invalid("contains characters branch names cannot");
+	}
+	if branch.ends_with('/')
+		|| branch.ends_with('.')
+		|| branch.ends_with(".lock")
+		|| branch.contains("..")
+		|| branch
+			.split('/')
+			.any(|component| component.is_empty() || component.starts_with('.'))
+	{
+		return invalid("is not a valid branch name");
+	}
+	Ok(())
+}
+
+/// The bot's branch the commit is picked onto `target` in.
+pub fn working_branch(number: i64, target: &str) -> String {
+	format!("backport/{}-{}", number, target)
+}
+
+pub fn title(target: &str, pr: &PullRequest) -> String {
+	format!(
+		"[backport {}] {}",
+		target,
+		pr.title.as_deref().unwrap_or_default()
+	)
+}
+
+pub fn body(
+	target: &str,
+	pr: &PullRequest,
+	commit_sha: &str,
+	requested_by: &str,
+) -> String {
+	format!(
+		"Backport of {} to `{}`, cherry-picking {}.\n\nRequested by @{} with `{} {}`.",
+		pr.html_url, target, commit_sha, requested_by, BACKPORT, target
+	)
+}
+
+/// The commit a merged PR landed as: its merge commit, or the commit it was
+/// squashed or rebased into.
+pub fn landed_commit(pr: &PullRequest) -> Option<&str> {
+	if pr.merged == Some(true) {
+		pr.merge_commit_sha.as_deref()
+	} else {
+		None
+	}
+}
+
+/// How the backport to a branch went.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+	Opened {
+		branch: String,
+		html_url: String,
+	},
+	Conflict {
+		branch: String,
+		files: Vec<String>,
+	},
+	Failed {
+		branch: String,
+		reason: String,
+	},
+	/// in a dry run
+	Planned {
+		branch: String,
+	},
+}
+
+/// The comment listing how each backport went.
+pub fn summary(outcomes: &[Outcome]) -> String {
+	outcomes
+		.iter()
+		.map(|outcome| match outcome {
+			Outcome::Opened { branch, html_url } => {
+				format!("- `{}`: opened {}", branch, html_url)
+			}
+			Outcome::Conflict { branch, files } => format!(
+				"- `{}`: cherry-picking conflicts in {}; no PR was opened. Backport it by hand.",
+				branch,
+				files.iter().map(|file| format!("`{}`", file)).join(", ")
+			),
+			Outcome::Failed { branch, reason } => {
+				format!("- `{}`: failed; {}", branch, reason)
+			}
+			Outcome::Planned { branch } => {
+				format!("- `{}`: would have been opened", branch)
+			}
+		})
+		.join("\n")
+}
+
+/// Whether picking the commit onto the branch went through.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Picked {
+	Pushed,
+	/// the files which conflicted; the cherry-pick was aborted
+	Conflict(Vec<String>),
+}
+
+/// Cherry-pick `commit_sha` onto `target` in the bot's `working` branch of the
+/// clone and push it.
+pub async fn pick(
+	clone: &WorkingClone,
+	commit_sha: &str,
+	target: &str,
+	working: &str,
+) -> Result<Picked> {
+	let res = pick_inner(clone, commit_sha, target, working).await;
+	clone.check_out_default(working).await?;
+	res
+}
+
+async fn pick_inner(
+	clone: &WorkingClone,
+	commit_sha: &str,
+	target: &str,
+	working: &str,
+) -> Result<Picked> {
+	log::info!("Checking out {} from {}.", working, target);
+	let checkout = clone
+		.run(
+			Kind::Git,
+			&["checkout", "-B", working, &format!("origin/{}", target)],
+		)
+		.await?;
+	if !checkout.success() {
+		return Err(Error::Message {
+			msg: format!("there is no branch `{}`", target),
+		});
+	}
+	// a merge commit is picked relative to the branch it was merged into
+	let parents = clone
+		.output(Kind::Git, &["rev-list", "--parents", "-n", "1", commit_sha])
+		.await?;
+	let is_merge = String::from_utf8_lossy(&parents.stdout)
+		.split_whitespace()
+		.count()
+		> 2;
+	log::info!("Cherry-picking {} onto {}.", commit_sha, target);
+	let mut args = vec!["cherry-pick", "-x"];
+	if is_merge {
+		args.extend(&["-m", "1"]);
+	}
+	args.push(commit_sha);
+	let picked = clone.run(Kind::Git, &args).await?;
+	if !picked.success() {
+		// the conflicts are gone once the cherry-pick is aborted
+		let conflicts = rebase::conflicts(clone).await;
+		log::info!("Aborting cherry-pick.");
+		clone.run(Kind::Git, &["cherry-pick", "--abort"]).await?;
+		return match conflicts? {
+			files if files.is_empty() => Err(Error::Message {
+				msg: format!("cherry-picking {} failed; see logs", commit_sha),
+			}),
+			files => Ok(Picked::Conflict(
+				files.into_iter().map(|file| file.path).collect(),
+			)),
+		};
+	}
+	log::info!("Pushing {}.", working);
+	// the branch is the bot's own, left over from an earlier attempt if it
+	// exists
+	let push = clone
+		.run(Kind::Git, &["push", "--force", "origin", working])
+		.await?;
+	if push.success() {
+		Ok(Picked::Pushed)
+	} else {
+		Err(Error::Message {
+			msg: format!("pushing `{}` failed", working),
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_parse_command() {
+		let branches = |branches: &[&str]| {
+			Some(Ok(BackportCommand {
+				branches: branches.iter().map(|b| b.to_string()).collect(),
+			}))
+		};
+		assert_eq!(
+			parse_command("bot backport release-v0.9.30"),
+			branches(&["release-v0.9.30"])
+		);
+		assert_eq!(
+			parse_command(
+				" Bot Backport release-v0.9.30 release-v0.9.31 release-v0.9.30\n"
+			),
+			branches(&["release-v0.9.30", "release-v0.9.31"])
+		);
+		assert!(matches!(parse_command("bot backport"), Some(Err(_))));
+		assert!(matches!(
+			parse_command("bot backport release-v0.9.30 --force"),
+			Some(Err(_))
+		));
+		assert_eq!(parse_command("bot backports x"), None);
+		assert_eq!(parse_command("bot merge"), None);
+	}
+
+	#[test]
+	fn test_validate_branch() {
+		for branch in &["release-v0.9.30", "polkadot-v0.9.x", "release/v1"] {
+			assert_eq!(validate_branch(branch), Ok(()), "{}", branch);
+		}
+		for branch in &[
+			"-f",
+			"release..v1",
+			"release/",
+			"/release",
+			"release//v1",
+			"release.",
+			"release.lock",
+			".release",
+			"release/.v1",
+			"release~1",
+			"release^",
+			"a:b",
+			"release*",
+			"release[1]",
+			"a\\b",
+			"@",
+			"release@{1}",
+			"release\u{7}",
+		] {
+			assert!(validate_branch(branch).is_err(), "{}", branch);
+		}
+		assert_eq!(
+			validate_branch("--force"),
+			Err("`--force` starts with a dash".to_string())
+		);
+	}
+
+	fn merged_pr() -> PullRequest {
+		PullRequest {
+			number: 1234,
+			title: Some("Fix the fork choice".to_string()),
+			html_url: "https://github.com/paritytech/substrate/pull/1234"
+				.to_string(),
+			merged: Some(true),
+			merge_commit_sha: Some("abc".to_string()),
+			..PullRequest::default()
+		}
+	}
+
+	#[test]
+	fn test_pr_text() {
+		let pr = merged_pr();
+		assert_eq!(
+			title("release-v0.9.30", &pr),
+			"[backport release-v0.9.30] Fix the fork choice"
+		);
+		assert_eq!(
+			body("release-v0.9.30", &pr, "abc", "gavofyork"),
+			"Backport of https://github.com/paritytech/substrate/pull/1234 to `release-v0.9.30`, cherry-picking abc.\n\nRequested by @gavofyork with `bot backport release-v0.9.30`."
+		);
+		assert_eq!(
+			working_branch(1234, "release-v0.9.30"),
+			"backport/1234-release-v0.9.30"
+		);
+	}
+
+	#[test]
+	fn test_landed_commit() {
+		assert_eq!(landed_commit(&merged_pr()), Some("abc"));
+		let open = PullRequest {
+			merged: Some(false),
+			merge_commit_sha: Some("test-merge".to_string()),
+			..merged_pr()
+		};
+		assert_eq!(landed_commit(&open), None);
+	}
+
+	#[test]
+	fn test_summary() {
+		assert_eq!(
+			summary(&[
+				Outcome::Opened {
+					branch: "release-v0.9.30".to_string(),
+					html_url: "https://github.com/paritytech/substrate/pull/1300"
+						.to_string(),
+				},
+				Outcome::Conflict {
+					branch: "release-v0.9.31".to_string(),
+					files: vec!["Cargo.lock".to_string(), "src/lib.rs".to_string()],
+				},
+			]),
+			"- `release-v0.9.30`: opened https://github.com/paritytech/substrate/pull/1300\n- `release-v0.9.31`: cherry-picking conflicts in `Cargo.lock`, `src/lib.rs`; no PR was opened. Backport it by hand."
+		);
+	}
+}
@@ -0,0 +1,379 @@
+use chrono::{DateTime, Utc};
+use rocksdb::DB;
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{
+	burnin::BurninRecord,
+	db,
+	error::*,
+	freeze::MergeFreeze,
+	migrations::{self, Kind, Versioned},
+	queue::MergeRequest,
+	Result,
+};
+
+const FILE_PREFIX: &str = "processbot-backup-";
+
+/// A database entry in a backup.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+	/// The column family of the entry; backups taken before the database had
+	/// any only have the default one
+	#[serde(default = "default_family")]
+	pub family: String,
+	/// `None` for records stored in another format, or which cannot be
+	/// decoded; their value is the stored bytes in base64
+	pub kind: Option<Kind>,
+	pub key: String,
+	pub value: serde_json::Value,
+}
+
+fn default_family() -> String {
+	db::DEFAULT.to_string()
+}
+
+fn decode<T: Versioned>(bytes: &[u8]) -> Option<serde_json::Value> {
+	migrations::decode::<T>(bytes)
+		.ok()
+		.and_then(|record| serde_json::to_value(&record).ok())
+}
+
+fn encode<T: Versioned>(value: &serde_json::Value) -> Result<Vec<u8>> {
+	migrations::encode(
+		&serde_json::from_value::<T>(value.clone()).context(Json)?,
+	)
+}
+
+impl Record {
+	fn new(family: &str, key: &[u8], bytes: &[u8]) -> Result<Self> {
+		let key = String::from_utf8(key.to_vec()).context(Utf8)?;
+		let decoded = Kind::of(key.as_bytes()).and_then(|kind| {
+			match kind {
+				Kind::MergeRequest => decode::<MergeRequest>(bytes),
+				Kind::Burnin => decode::<BurninRecord>(bytes),
+				Kind::MergeFreeze => decode::<MergeFreeze>(bytes),
+			}
+			.map(|value| (kind, value))
+		});
+		Ok(match decoded {
+			Some((kind, value)) => Record {
+				family: family.to_string(),
+				kind: Some(kind),
+				key,
+				value,
+			},
+			None => Record {
+				family: family.to_string(),
+				kind: None,
+				key,
+				value: base64::encode(bytes).into(),
+			},
+		})
+	}
+
+	/// The bytes to store, versioned records being encoded as the current
+	/// version.
+	fn bytes(&self) -> Result<Vec<u8>> {
+		match self.kind {
+			Some(Kind::MergeRequest) => encode::<MergeRequest>(&self.value),
+			Some(Kind::Burnin) => encode::<BurninRecord>(&self.value),
+			Some(Kind::MergeFreeze) => encode::<MergeFreeze>(&self.value),
+			None => base64::decode(self.value.as_str().unwrap_or_default())
+				.context(Base64),
+		}
+	}
+}
+
+/// Every entry of the database.
+pub fn export(db: &DB) -> Result<Vec<Record>> {
+	let mut records = Vec::new();
+	for name in db::all_families() {
+		for (key, value) in db::family(db, name)?.iter() {
+			records.push(Record::new(name, &key, &value)?);
+		}
+	}
+	Ok(records)
+}
+
+/// Write every entry of the database to `path`.  Returns how many there were.
+pub fn write(db: &DB, path: &Path) -> Result<usize> {
+	let records = export(db)?;
+	let contents = serde_json::to_vec_pretty(&records).context(Json)?;
+	std::fs::write(path, contents).map_err(|source| Error::Backup {
+		path: path.display().to_string(),
+		source,
+	})?;
+	Ok(records.len())
+}
+
+/// Import a backup into an empty database.  Returns how many entries it had.
+pub fn restore(db: &DB, records: &[Record]) -> Result<usize> {
+	for name in db::all_families() {
+		if db::family(db, name)?.iter().next().is_some() {
+			return Err(Error::Message {
+				msg: "The database to restore into is not empty".to_string(),
+			});
+		}
+	}
+	db::atomically(db, |tx| {
+		for record in records {
+			tx.put(&record.family, record.key.as_bytes(), &record.bytes()?)?;
+		}
+		Ok(records.len())
+	})
+}
+
+pub fn read(path: &Path) -> Result<Vec<Record>> {
+	let contents = std::fs::read(path).map_err(|source| Error::Backup {
+		path: path.display().to_string(),
+		source,
+	})?;
+	serde_json::from_slice(&contents).context(Json)
+}
+
+fn file_name(now: DateTime<Utc>) -> String {
+	format!("{}{}.json", FILE_PREFIX, now.format("%Y%m%dT%H%M%SZ"))
+}
+
+/// Remove all but the latest `keep` backups in `dir`.  Returns those removed.
+fn rotate(dir: &Path, keep: usize) -> Result<Vec<PathBuf>> {
+	let io_error = |source| Error::Backup {
+		path: dir.display().to_string(),
+		source,
+	};
+	let mut backups = std::fs::read_dir(dir)
+		.map_err(io_error)?
+		.map(|entry| entry.map(|entry| entry.path()))
+		.collect::<std::io::Result<Vec<_>>>()
+		.map_err(io_error)?
+		.into_iter()
+		.filter(|path| {
+			path.file_name()
+				.and_then(|name| name.to_str())
+				.is_some_and(|name| name.starts_with(FILE_PREFIX))
+		})
+		.collect::<Vec<_>>();
+	// the names sort by the time they were written
+	backups.sort();
+	let stale = backups.len().saturating_sub(keep);
+	let removed = backups.into_iter().take(stale).collect::<Vec<_>>();
+	for path in &removed {
+		std::fs::remove_file(path).map_err(io_error)?;
+	}
+	Ok(removed)
+}
+
+/// Back the database up to `dir` every `interval`, keeping the latest `keep`
+/// backups.
+pub async fn periodic(
+	db: Arc<DB>,
+	dir: PathBuf,
+	interval: Duration,
+	keep: usize,
+) {
+	loop {
+		let path = dir.join(file_name(Utc::now()));
+		match std::fs::create_dir_all(&dir)
+			.map_err(|source| Error::Backup {
+				path: dir.display().to_string(),
+				source,
+			})
+			.and_then(|_| write(&db, &path))
+			.and_then(|count| rotate(&dir, keep).map(|_| count))
+		{
+			Ok(count) => log::info!(
+				"Backed up {} database entries to {}",
+				count,
+				path.display()
+			),
+			Err(e) => log::error!("Error backing up the database: {}", e),
+		}
+		tokio::time::delay_for(interval).await;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{audit, auto_update, burnin, freeze, queue};
+
+	fn temp_path(name: &str) -> PathBuf {
+		let path = std::env::temp_dir().join(format!(
+			"processbot-backup-test-{}-{}",
+			name,
+			std::process::id()
+		));
+		let _ = std::fs::remove_dir_all(&path);
+		let _ = std::fs::remove_file(&path);
+		path
+	}
+
+	fn entries(db: &DB) -> Vec<(String, Vec<u8>, Vec<u8>)> {
+		db::all_families()
+			.flat_map(|name| {
+				db::family(db, name)
+					.unwrap()
+					.iter()
+					.map(move |(key, value)| {
+						(name.to_string(), key.to_vec(), value.to_vec())
+					})
+			})
+			.collect()
+	}
+
+	#[test]
+	fn test_round_trip() {
+		let path = temp_path("source");
+		let db = db::open(&path).unwrap();
+		let sha = "0123456789abcdef0123456789abcdef01234567";
+		let mut tx = db::Transaction::new(&db);
+		queue::store(
+			&mut tx,
+			sha,
+			&MergeRequest {
+				owner: "paritytech".to_string(),
+				repo_name: "substrate".to_string(),
+				number: 1234,
+				html_url: String::new(),
+				requested_by: "gavofyork".to_string(),
+				created_at: Some(1_600_000_000),
+				origin: None,
+				not_before: None,
+			},
+		)
+		.unwrap();
+		burnin::store_record(
+			&db,
+			&BurninRecord {
+				owner: "paritytech".to_string(),
+				repo_name: "polkadot".to_string(),
+				number: 567,
+				html_url: String::new(),
+				requested_by: "gavofyork".to_string(),
+				commit_sha: sha.to_string(),
+				gitlab_path: "requests/567.toml".to_string(),
+				gitlab_commit: None,
+				pipeline_url: None,
+				created_at: 1_600_000_000,
+				state: burnin::BurninState::Submitted,
+			},
+		)
+		.unwrap();
+		freeze::store_freeze(
+			&db,
+			"paritytech",
+			&MergeFreeze {
+				repo: "substrate".to_string(),
+				branch: None,
+				start: None,
+				end: None,
+				until_further_notice: true,
+				reason: "release".to_string(),
+				frozen_by: None,
+			},
+		)
+		.unwrap();
+		audit::record(
+			&mut tx,
+			&audit::AuditEntry::new(
+				"gavofyork",
+				"paritytech",
+				"substrate",
+				1234,
+				"bot merge",
+				&Ok::<_, Error>(()),
+			),
+		)
+		.unwrap();
+		tx.commit().unwrap();
+		auto_update::record(
+			&db,
+			&auto_update::AutoUpdate::new(
+				"paritytech",
+				"substrate",
+				1234,
+				"master",
+			),
+		)
+		.unwrap();
+		// a record which cannot be decoded is kept as it is
+		db::family(&db, db::MERGE_REQUESTS)
+			.unwrap()
+			.put(sha.replace('0', "f").as_bytes(), b"garbage")
+			.unwrap();
+
+		let backup = temp_path("file");
+		assert_eq!(write(&db, &backup).unwrap(), entries(&db).len());
+		let records = read(&backup).unwrap();
+		let kinds = records.iter().map(|r| r.kind).collect::<Vec<_>>();
+		for kind in &[Kind::MergeRequest, Kind::Burnin, Kind::MergeFreeze] {
+			assert!(kinds.contains(&Some(*kind)), "{:?}", kind);
+		}
+		let garbage = records
+			.iter()
+			.find(|r| r.key == sha.replace('0', "f"))
+			.unwrap();
+		assert_eq!(garbage.family, db::MERGE_REQUESTS);
+		assert_eq!(garbage.kind, None);
+		assert_eq!(garbage.value, base64::encode("garbage"));
+
+		let restored_path = temp_path("restored");
+		let restored = db::open(&restored_path).unwrap();
+		assert_eq!(restore(&restored, &records).unwrap(), records.len());
+		assert_eq!(entries(&restored), entries(&db));
+		// only into an empty database
+		assert!(restore(&restored, &records).is_err());
+		// backups from before the column families
+		let old: Record =
+			serde_json::from_str(r#"{"kind":null,"key":"k","value":""}"#)
+				.unwrap();
+		assert_eq!(old.family, db::DEFAULT);
+
+		drop(db);
+		drop(restored);
+		let _ = std::fs::remove_dir_all(&path);
+		let _ = std::fs::remove_dir_all(&restored_path);
+		let _ = std::fs::remove_file(&backup);
+	}
+
+	#[test]
+	fn test_rotate() {
+		let dir = temp_path("rotate");
+		std::fs::create_dir_all(&dir).unwrap();
+		let names = (1..=4)
+			.map(|day| {
+				file_name(
+					DateTime::parse_from_rfc3339(&format!(
+						"2020-10-0{}T12:00:00Z",
+						day
+					))
+					.unwrap()
+					.with_timezone(&Utc),
+				)
+			})
+			.collect::<Vec<_>>();
+		for name in names.iter().chain(Some(&"other.json".to_string())) {
+			std::fs::write(dir.join(name), b"[]").unwrap();
+		}
+		assert_eq!(names[0], "processbot-backup-20201001T120000Z.json");
+
+		assert_eq!(
+			rotate(&dir, 2).unwrap(),
+			vec![dir.join(&names[0]), dir.join(&names[1])]
+		);
+		let mut left = std::fs::read_dir(&dir)
+			.unwrap()
+			.map(|entry| entry.unwrap().file_name().into_string().unwrap())
+			.collect::<Vec<_>>();
+		left.sort();
+		assert_eq!(
+			left,
+			vec!["other.json".to_string(), names[2].clone(), names[3].clone()]
+		);
+		let _ = std::fs::remove_dir_all(&dir);
+	}
+}
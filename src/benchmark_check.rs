@@ -0,0 +1,266 @@
+use crate::{
+	github::{CheckRunOutput, CheckRunRequest},
+	performance::{Decision, Regression},
+};
+
+/// The check run showing the performance gate on the head of a PR.  The bot
+/// runs the gate itself, so it never waits for this check.
+pub const NAME: &str = "parity-processbot/benchmarks";
+
+/// GitHub's limit on the summary and on the text of a check run output.
+const MAX_OUTPUT_LEN: usize = 65535;
+
+const TRUNCATED: &str = "\n\n*Truncated; the bot's logs have the rest.*";
+
+/// Cut `s` to at most `max` bytes, on a character boundary, saying so.
+pub fn truncate(s: &str, max: usize) -> String {
+	if s.len() <= max {
+		return s.to_string();
+	}
+	let mut end = max.saturating_sub(TRUNCATED.len());
+	while !s.is_char_boundary(end) {
+		end -= 1;
+	}
+	format!("{}{}", &s[..end], TRUNCATED)
+}
+
+fn request(head_sha: &str, status: &str) -> CheckRunRequest {
+	CheckRunRequest {
+		name: Some(NAME.to_string()),
+		head_sha: Some(head_sha.to_string()),
+		status: Some(status.to_string()),
+		..CheckRunRequest::default()
+	}
+}
+
+pub fn started(head_sha: &str) -> CheckRunRequest {
+	CheckRunRequest {
+		output: Some(CheckRunOutput {
+			title: "Running benchmarks".to_string(),
+			summary:
+				"Benchmarking the head against its merge base with master."
+					.to_string(),
+			text: None,
+		}),
+		..request(head_sha, "in_progress")
+	}
+}
+
+pub fn skipped(head_sha: &str, skipped_by: &str) -> CheckRunRequest {
+	CheckRunRequest {
+		conclusion: Some("skipped".to_string()),
+		output: Some(CheckRunOutput {
+			title: "Benchmarks skipped".to_string(),
+			summary: format!("Skipped by @{}.", skipped_by),
+			text: None,
+		}),
+		..request(head_sha, "completed")
+	}
+}
+
+/// A regression fails the check; a benchmark which could not be run does not
+/// hold up the merge, so neither does it fail the check.
+pub fn conclusion(decision: &Decision) -> &'static str {
+	match decision {
+		Decision::Passed { .. } => "success",
+		Decision::Regressed { .. } => "failure",
+		Decision::BenchFailed { .. } => "neutral",
+	}
+}
+
+/// The comparison table, worst regression first.
+fn table(regression: &Regression, threshold: f64) -> String {
+	let mut comparisons = regression.comparisons();
+	comparisons.sort_by(|a, b| {
+		b.factor()
+			.partial_cmp(&a.factor())
+			.unwrap_or(std::cmp::Ordering::Equal)
+	});
+	let mut table = format!(
+		"Merge base: `{}`\n\n| Benchmark | Merge base | Head | Factor |\n|---|---:|---:|---:|\n",
+		regression.merge_base
+	);
+	for comparison in comparisons {
+		let factor = comparison.factor();
+		table.push_str(&format!(
+			"| `{}` | {} | {} | {:.2}x{} |\n",
+			comparison.name,
+			comparison.base,
+			comparison.head,
+			factor,
+			if factor > threshold { " :x:" } else { "" }
+		));
+	}
+	table
+}
+
+/// The raw results, as a JSON code block.
+fn raw_results(regression: &Regression) -> String {
+	let json = serde_json::to_string_pretty(&serde_json::json!({
+		"merge_base": regression.merge_base,
+		"base": regression.base,
+		"head": regression.head,
+	}))
+	.unwrap_or_default();
+	let fence = "```json\n\n```";
+	format!(
+		"```json\n{}\n```",
+		truncate(&json, MAX_OUTPUT_LEN - fence.len())
+	)
+}
+
+pub fn completed(
+	head_sha: &str,
+	decision: &Decision,
+	regression: Option<&Regression>,
+	threshold: f64,
+) -> CheckRunRequest {
+	let title = match decision {
+		Decision::Passed { factor } => format!(
+			"No regression: {:.2}x at worst, within {}x",
+			factor, threshold
+		),
+		Decision::Regressed { factor } => {
+			format!("Regression: {:.2}x, over {}x", factor, threshold)
+		}
+		Decision::BenchFailed { .. } => {
+			"Benchmarks could not be completed".to_string()
+		}
+	};
+	let mut summary = match decision {
+		Decision::BenchFailed { reason } => {
+			format!("{}; the merge goes ahead without them.\n\n", reason)
+		}
+		_ => String::new(),
+	};
+	if let Some(regression) = regression {
+		summary.push_str(&table(regression, threshold));
+	}
+	CheckRunRequest {
+		conclusion: Some(conclusion(decision).to_string()),
+		output: Some(CheckRunOutput {
+			title,
+			summary: truncate(&summary, MAX_OUTPUT_LEN),
+			text: regression.map(raw_results),
+		}),
+		..request(head_sha, "completed")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::performance::BenchResult;
+
+	fn bench_result(name: &str, average: i64) -> BenchResult {
+		BenchResult {
+			name: name.to_string(),
+			raw_average: average,
+			average,
+		}
+	}
+
+	fn regression() -> Regression {
+		Regression {
+			merge_base: "abc".to_string(),
+			base: vec![
+				bench_result("import", 100),
+				bench_result("transfer", 100),
+			],
+			head: vec![
+				bench_result("import", 90),
+				bench_result("transfer", 250),
+			],
+		}
+	}
+
+	#[test]
+	fn test_truncate() {
+		assert_eq!(truncate("short", 10), "short");
+		let long = "a".repeat(MAX_OUTPUT_LEN + 1);
+		let truncated = truncate(&long, MAX_OUTPUT_LEN);
+		assert_eq!(truncated.len(), MAX_OUTPUT_LEN);
+		assert!(truncated.ends_with(TRUNCATED));
+		// never splits a character
+		let wide = "é".repeat(MAX_OUTPUT_LEN);
+		let truncated = truncate(&wide, MAX_OUTPUT_LEN);
+		assert!(truncated.len() <= MAX_OUTPUT_LEN);
+		assert!(truncated.ends_with(TRUNCATED));
+	}
+
+	#[test]
+	fn test_long_output_truncated() {
+		let mut regression = regression();
+		regression.head = (0..2000)
+			.map(|i| bench_result(&format!("benchmark-{:04}", i), 100))
+			.collect();
+		regression.base = regression.head.clone();
+		let request = completed(
+			"sha",
+			&Decision::Passed { factor: 1. },
+			Some(&regression),
+			2.,
+		);
+		let output = request.output.unwrap();
+		assert!(output.summary.len() <= MAX_OUTPUT_LEN);
+		assert!(output.summary.ends_with(TRUNCATED));
+		let text = output.text.unwrap();
+		assert!(text.len() <= MAX_OUTPUT_LEN);
+		assert!(text.starts_with("```json\n"));
+		assert!(text.ends_with("\n```"));
+	}
+
+	#[test]
+	fn test_status_transitions() {
+		let started = started("sha");
+		assert_eq!(started.name.as_deref(), Some(NAME));
+		assert_eq!(started.head_sha.as_deref(), Some("sha"));
+		assert_eq!(started.status.as_deref(), Some("in_progress"));
+		assert_eq!(started.conclusion, None);
+
+		let passed = completed(
+			"sha",
+			&Decision::Passed { factor: 1.2 },
+			Some(&regression()),
+			2.,
+		);
+		assert_eq!(passed.status.as_deref(), Some("completed"));
+		assert_eq!(passed.conclusion.as_deref(), Some("success"));
+
+		let regressed = completed(
+			"sha",
+			&Decision::Regressed { factor: 2.5 },
+			Some(&regression()),
+			2.,
+		);
+		assert_eq!(regressed.status.as_deref(), Some("completed"));
+		assert_eq!(regressed.conclusion.as_deref(), Some("failure"));
+		let output = regressed.output.unwrap();
+		assert_eq!(output.title, "Regression: 2.50x, over 2x");
+		assert_eq!(
+			output.summary,
+			"Merge base: `abc`\n\n| Benchmark | Merge base | Head | Factor |\n|---|---:|---:|---:|\n| `transfer` | 100 | 250 | 2.50x :x: |\n| `import` | 100 | 90 | 0.90x |\n"
+		);
+
+		let failed = completed(
+			"sha",
+			&Decision::BenchFailed {
+				reason: "timed out after 60 seconds".to_string(),
+			},
+			None,
+			2.,
+		);
+		assert_eq!(failed.status.as_deref(), Some("completed"));
+		assert_eq!(failed.conclusion.as_deref(), Some("neutral"));
+		let output = failed.output.unwrap();
+		assert_eq!(
+			output.summary,
+			"timed out after 60 seconds; the merge goes ahead without them.\n\n"
+		);
+		assert_eq!(output.text, None);
+
+		let skipped = skipped("sha", "gavofyork");
+		assert_eq!(skipped.status.as_deref(), Some("completed"));
+		assert_eq!(skipped.conclusion.as_deref(), Some("skipped"));
+	}
+}
@@ -0,0 +1,1324 @@
+use futures::Future;
+use itertools::Itertools;
+use ring::rand::{SecureRandom, SystemRandom};
+use rocksdb::DB;
+use serde::{Deserialize, Serialize};
+use snafu::OptionExt;
+
+use crate::{
+	command_prefix,
+	constants::*,
+	db,
+	error::*,
+	github::PullRequest,
+	gitlab_bot::GitlabBot,
+	migrations,
+	templates::{self, Templates},
+	Result,
+};
+
+/// Records older than this are considered stale and removed.
+const RECORD_TTL_SECS: i64 = 14 * 24 * 60 * 60;
+
+pub(crate) const RECORD_KEY_PREFIX: &str = "burnin/";
+
+/// Number of attempts at committing a request file to Gitlab.
+const SUBMIT_ATTEMPTS: usize = 5;
+
+/// A command posted in a PR comment starting with `bot burnin`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BurninCommand {
+	Request,
+	Status,
+	Cancel,
+}
+
+/// Parse a burn-in command from the body of a comment.
+pub fn parse_command(body: &str) -> Option<BurninCommand> {
+	let body = body.to_lowercase();
+	let body = body.trim();
+	if body == BURNIN_STATUS {
+		Some(BurninCommand::Status)
+	} else if body == BURNIN_CANCEL {
+		Some(BurninCommand::Cancel)
+	} else if body.starts_with(BURNIN_REQUEST) {
+		Some(BurninCommand::Request)
+	} else {
+		None
+	}
+}
+
+/// The comment telling how to request a burn-in, once a PR is labelled as
+/// needing one, with the command written with the configured `prefix`.
+pub fn prompt(
+	added_by: &str,
+	repo_owner: &str,
+	pr: &PullRequest,
+	prefix: &str,
+	templates: &Templates,
+) -> String {
+	templates.render(
+		templates::BURNIN_PROMPT,
+		&[
+			("added_by", added_by),
+			("org", repo_owner),
+			("pr_url", &pr.html_url),
+			("commit_sha", &pr.head.sha),
+			("burnin", &command_prefix::render(BURNIN_REQUEST, prefix)),
+		],
+	)
+}
+
+/// A burn-in request as submitted in a `bot burnin` comment.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BurninRequest {
+	pub pull_request: String,
+	pub commit_sha: String,
+	pub requested_by: String,
+	pub sync_from_scratch: bool,
+	pub node_types: NodeTypes,
+}
+
+/// Number of nodes of each type to deploy.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NodeTypes {
+	pub fullnode: u32,
+	pub sentry: u32,
+	pub validator: u32,
+}
+
+/// Limits on the nodes a burn-in may deploy.  Members of the ops team may
+/// exceed them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BurninLimits {
+	/// Node types which are available via automation at all.
+	pub allowed_node_types: Vec<String>,
+	pub max_fullnodes: u32,
+	pub max_sentries: u32,
+	pub max_validators: u32,
+}
+
+impl Default for BurninLimits {
+	fn default() -> Self {
+		Self {
+			allowed_node_types: vec!["fullnode".to_string()],
+			max_fullnodes: 4,
+			max_sentries: 0,
+			max_validators: 0,
+		}
+	}
+}
+
+impl BurninLimits {
+	fn node_counts(
+		&self,
+		node_types: &NodeTypes,
+	) -> Vec<(&'static str, u32, u32)> {
+		vec![
+			("fullnode", node_types.fullnode, self.max_fullnodes),
+			("sentry", node_types.sentry, self.max_sentries),
+			("validator", node_types.validator, self.max_validators),
+		]
+	}
+
+	fn is_allowed(&self, node_type: &str) -> bool {
+		self.allowed_node_types.iter().any(|t| t == node_type)
+	}
+
+	/// Describe the limits, eg. "at most 4 fullnode; no sentry or validator".
+	pub fn describe(&self) -> String {
+		let empty = NodeTypes {
+			fullnode: 0,
+			sentry: 0,
+			validator: 0,
+		};
+		let (available, unavailable): (Vec<_>, Vec<_>) =
+			self.node_counts(&empty).into_iter().partition(
+				|(node_type, _, max)| self.is_allowed(node_type) && *max > 0,
+			);
+		let mut parts = available
+			.into_iter()
+			.map(|(node_type, _, max)| format!("at most {} {}", max, node_type))
+			.collect::<Vec<_>>();
+		if !unavailable.is_empty() {
+			parts.push(format!(
+				"no {}",
+				unavailable
+					.into_iter()
+					.map(|(node_type, _, _)| node_type)
+					.join(" or ")
+			));
+		}
+		parts.join("; ")
+	}
+
+	/// Return the ways in which the requested nodes exceed the limits.
+	pub fn violations(&self, node_types: &NodeTypes) -> Vec<String> {
+		self.node_counts(node_types)
+			.into_iter()
+			.filter(|(_, count, _)| *count > 0)
+			.filter_map(|(node_type, count, max)| {
+				if !self.is_allowed(node_type) {
+					Some(format!(
+						"{} {} requested but {} nodes are not available via automation",
+						count, node_type, node_type
+					))
+				} else if count > max {
+					Some(format!(
+						"{} {} requested but at most {} are allowed",
+						count, node_type, max
+					))
+				} else {
+					None
+				}
+			})
+			.collect()
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LimitCheck {
+	Within,
+	/// The limits are exceeded by a member of the ops team.
+	Overridden(Vec<String>),
+	Exceeded(Vec<String>),
+}
+
+/// Check the nodes of a request against the limits.
+pub fn check_limits(
+	node_types: &NodeTypes,
+	limits: &BurninLimits,
+	requested_by_ops: bool,
+) -> LimitCheck {
+	let violations = limits.violations(node_types);
+	if violations.is_empty() {
+		LimitCheck::Within
+	} else if requested_by_ops {
+		LimitCheck::Overridden(violations)
+	} else {
+		LimitCheck::Exceeded(violations)
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum BurninState {
+	/// The request file has been committed to Gitlab.
+	Submitted,
+	/// A pipeline for the request is pending or running.
+	Running,
+	Completed,
+	Failed,
+	/// The request file was removed from Gitlab before a pipeline started.
+	Cancelled,
+}
+
+impl BurninState {
+	/// Map the status of a Gitlab pipeline to the state of a burn-in.
+	pub fn from_pipeline_status(status: &str) -> Self {
+		match status {
+			"success" => BurninState::Completed,
+			"failed" | "canceled" => BurninState::Failed,
+			_ => BurninState::Running,
+		}
+	}
+
+	pub fn is_finished(&self) -> bool {
+		matches!(
+			self,
+			BurninState::Completed
+				| BurninState::Failed
+				| BurninState::Cancelled
+		)
+	}
+}
+
+impl std::fmt::Display for BurninState {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		let s = match self {
+			BurninState::Submitted => "submitted",
+			BurninState::Running => "running",
+			BurninState::Completed => "completed",
+			BurninState::Failed => "failed",
+			BurninState::Cancelled => "cancelled",
+		};
+		write!(f, "{}", s)
+	}
+}
+
+/// This stores information about a burn-in request submitted to Gitlab.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BurninRecord {
+	pub owner: String,
+	pub repo_name: String,
+	pub number: i64,
+	pub html_url: String,
+	pub requested_by: String,
+	pub commit_sha: String,
+	/// Path of the request file in the Gitlab repository.
+	pub gitlab_path: String,
+	/// Commit in the Gitlab repository which added the request file.
+	pub gitlab_commit: Option<String>,
+	pub pipeline_url: Option<String>,
+	/// Unix timestamp of the submission.
+	pub created_at: i64,
+	pub state: BurninState,
+}
+
+impl BurninRecord {
+	pub fn db_key(owner: &str, repo_name: &str, number: i64) -> String {
+		format!("{}{}/{}/{}", RECORD_KEY_PREFIX, owner, repo_name, number)
+	}
+
+	pub fn is_expired(&self, now: i64) -> bool {
+		now - self.created_at > RECORD_TTL_SECS
+	}
+}
+
+/// Write a burn-in record to the database, replacing any previous record for the
+/// same PR.
+pub fn store_record(db: &DB, record: &BurninRecord) -> Result<()> {
+	let key =
+		BurninRecord::db_key(&record.owner, &record.repo_name, record.number);
+	let bytes = migrations::encode(record)?;
+	db::family(db, db::BURNIN)?.put(key.as_bytes(), &bytes)
+}
+
+/// Return the burn-in record for a PR, if one exists and has not expired.
+pub fn get_record(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+	now: i64,
+) -> Result<Option<BurninRecord>> {
+	let family = db::family(db, db::BURNIN)?;
+	let key = BurninRecord::db_key(owner, repo_name, number);
+	match family.get(key.as_bytes())? {
+		Some(bytes) => {
+			let record: BurninRecord = migrations::decode(&bytes)?;
+			if record.is_expired(now) {
+				family.delete(key.as_bytes())?;
+				Ok(None)
+			} else {
+				Ok(Some(record))
+			}
+		}
+		None => Ok(None),
+	}
+}
+
+/// Return all burn-in records in the database.
+pub fn all_records(db: &DB) -> Result<Vec<BurninRecord>> {
+	db::family(db, db::BURNIN)?
+		.iter_prefix(RECORD_KEY_PREFIX.as_bytes())
+		.map(|(_, v)| migrations::decode(&v))
+		.collect()
+}
+
+/// Delete records which have expired.
+pub fn remove_expired_records(db: &DB, now: i64) -> Result<()> {
+	db::atomically(db, |tx| {
+		for record in all_records(db)? {
+			if record.is_expired(now) {
+				log::info!(
+					"Removing stale burn-in record for {}",
+					record.html_url
+				);
+				let key = BurninRecord::db_key(
+					&record.owner,
+					&record.repo_name,
+					record.number,
+				);
+				tx.delete(db::BURNIN, key.as_bytes())?;
+			}
+		}
+		Ok(())
+	})
+}
+
+/// Path of a request file in the Gitlab repository.  The random suffix keeps
+/// requests submitted within the same second apart.
+pub fn request_path(
+	repo_name: &str,
+	number: i64,
+	now: i64,
+	suffix: &str,
+) -> String {
+	format!("requests/{}-{}-{}-{}.toml", repo_name, number, now, suffix)
+}
+
+fn random_suffix() -> Result<String> {
+	let mut bytes = [0u8; 4];
+	SystemRandom::new().fill(&mut bytes).ok().context(Message {
+		msg: "Error generating random request suffix".to_string(),
+	})?;
+	Ok(base16::encode_lower(&bytes))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RetryDecision {
+	Retry,
+	/// The path is taken; retry with another one.
+	RetryWithNewPath,
+	GiveUp,
+}
+
+/// Decide whether a failed request file submission is worth retrying.
+pub fn retry_decision(e: &Error) -> RetryDecision {
+	match e {
+		Error::GitlabApi { status, .. } if *status >= 500 || *status == 429 => {
+			RetryDecision::Retry
+		}
+		Error::GitlabApi {
+			status: 400,
+			message: Some(message),
+			..
+		} if message.contains("already exists") => RetryDecision::RetryWithNewPath,
+		// anything but a malformed request is a network error
+		Error::Http { source } if !source.is_builder() => RetryDecision::Retry,
+		_ => RetryDecision::GiveUp,
+	}
+}
+
+/// Commit a request file to Gitlab, retrying on server and network errors.
+/// Returns the path of the file.
+pub async fn submit_request(
+	gitlab_bot: &GitlabBot,
+	repo_name: &str,
+	number: i64,
+	now: i64,
+	content: &str,
+) -> Result<String> {
+	let commit_msg = commit_message(repo_name, number);
+	submit_with_retries(
+		|path| {
+			let path = path.to_string();
+			let commit_msg = &commit_msg;
+			async move {
+				gitlab_bot
+					.create_file(&path, "master", commit_msg, content)
+					.await
+			}
+		},
+		|path| {
+			let path = path.to_string();
+			async move { gitlab_bot.file_content(&path, "master").await }
+		},
+		|| Ok(request_path(repo_name, number, now, &random_suffix()?)),
+		content,
+		std::time::Duration::from_secs(1),
+	)
+	.await
+}
+
+/// Call `create` with the path of a request file until it succeeds or the
+/// attempts run out, waiting `backoff`, then twice as long each time, in
+/// between.  A request failing with a server or network error may still have
+/// committed the file, so `find` is asked for its content before giving up
+/// on the path, and a file found with `content` is not submitted again.
+async fn submit_with_retries<C, CFut, F, FFut, P>(
+	mut create: C,
+	mut find: F,
+	mut new_path: P,
+	content: &str,
+	backoff: std::time::Duration,
+) -> Result<String>
+where
+	C: FnMut(&str) -> CFut,
+	CFut: Future<Output = Result<()>>,
+	F: FnMut(&str) -> FFut,
+	FFut: Future<Output = Result<Option<String>>>,
+	P: FnMut() -> Result<String>,
+{
+	let mut path = new_path()?;
+	let mut attempt = 1;
+	// whether a request failed after maybe committing the file at `path`
+	let mut maybe_created = false;
+	loop {
+		let e = match create(&path).await {
+			Ok(()) => return Ok(path),
+			Err(e) => e,
+		};
+		let decision = retry_decision(&e);
+		maybe_created |= decision == RetryDecision::Retry;
+		if maybe_created {
+			match find(&path).await {
+				Ok(Some(found)) if found == content => {
+					log::info!(
+						"Burn-in request {} was committed despite: {}",
+						path,
+						e
+					);
+					return Ok(path);
+				}
+				Ok(_) => {}
+				Err(e) => log::warn!(
+					"Looking up burn-in request {} failed: {}",
+					path,
+					e
+				),
+			}
+		}
+		match decision {
+			RetryDecision::GiveUp => {
+				return Err(Error::GitlabRejected {
+					source: Box::new(e),
+				})
+			}
+			RetryDecision::RetryWithNewPath if attempt >= SUBMIT_ATTEMPTS => {
+				return Err(Error::GitlabPathsTaken {
+					attempts: attempt,
+					source: Box::new(e),
+				})
+			}
+			_ if attempt >= SUBMIT_ATTEMPTS => {
+				return Err(Error::GitlabUnreachable {
+					attempts: attempt,
+					source: Box::new(e),
+				})
+			}
+			RetryDecision::RetryWithNewPath => {
+				path = new_path()?;
+				maybe_created = false;
+			}
+			RetryDecision::Retry => {}
+		}
+		log::warn!(
+			"Submitting burn-in request failed (attempt {}); retrying: {}",
+			attempt,
+			e
+		);
+		tokio::time::delay_for(backoff * (1 << (attempt - 1)) as u32).await;
+		attempt += 1;
+	}
+}
+
+/// Message of the Gitlab commit which submits a burn-in request for a PR.
+pub fn commit_message(repo_name: &str, number: i64) -> String {
+	format!("Add request for {}#{}", repo_name, number)
+}
+
+/// Message of the Gitlab commit which removes a cancelled burn-in request.
+pub fn cancel_commit_message(repo_name: &str, number: i64) -> String {
+	format!("Cancel request for {}#{}", repo_name, number)
+}
+
+/// Inverse of `commit_message`.
+pub fn parse_commit_message(message: &str) -> Option<(&str, i64)> {
+	let first_line = message.lines().next()?.trim();
+	let (repo_name, number) = first_line
+		.strip_prefix("Add request for ")?
+		.rsplitn(2, '#')
+		.collect_tuple::<(&str, &str)>()
+		.map(|(number, repo_name)| (repo_name, number))?;
+	Some((repo_name, number.parse().ok()?))
+}
+
+/// Find the record of the burn-in submitted in a Gitlab commit, matching on the
+/// commit itself if it is already known or else on the commit message.
+/// Cancelled burn-ins are never matched.
+pub fn find_record_for_commit(
+	records: Vec<BurninRecord>,
+	sha: &str,
+	message: Option<&str>,
+	now: i64,
+) -> Option<BurninRecord> {
+	let mut records = records
+		.into_iter()
+		.filter(|record| {
+			!record.is_expired(now) && record.state != BurninState::Cancelled
+		})
+		.collect::<Vec<_>>();
+	if let Some(i) = records
+		.iter()
+		.position(|record| record.gitlab_commit.as_deref() == Some(sha))
+	{
+		return Some(records.swap_remove(i));
+	}
+	let (repo_name, number) = message.and_then(parse_commit_message)?;
+	records
+		.into_iter()
+		.filter(|record| {
+			record.gitlab_commit.is_none()
+				&& record.repo_name == repo_name
+				&& record.number == number
+		})
+		.max_by_key(|record| record.created_at)
+}
+
+/// Format a number of seconds as eg. "1h 2m 3s".
+pub fn format_duration(secs: i64) -> String {
+	let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+	if h > 0 {
+		format!("{}h {}m {}s", h, m, s)
+	} else if m > 0 {
+		format!("{}m {}s", m, s)
+	} else {
+		format!("{}s", s)
+	}
+}
+
+/// Return the contents of the first code block in a comment, without the
+/// language tag (eg. "toml") following the opening fence.
+pub fn extract_toml(comment_body: &str) -> Option<&str> {
+	let v: Vec<&str> = comment_body.splitn(3, "```").collect();
+	if v.len() < 3 {
+		return None;
+	}
+	let block = v[1];
+	match block.find('\n') {
+		Some(i) if block[..i].trim().chars().all(char::is_alphanumeric) => {
+			Some(&block[i + 1..])
+		}
+		_ => Some(block),
+	}
+}
+
+/// Deserialize a burn-in request.  On failure, return a description of the
+/// problem including the offending line, suitable for posting on the PR.
+pub fn parse_request(toml_str: &str) -> Result<BurninRequest, String> {
+	toml::from_str::<BurninRequest>(toml_str).map_err(|e| {
+		// missing fields are reported at the end of the enclosing table, which
+		// is not a useful line to quote
+		let msg = e.to_string();
+		let offending_line = e
+			.line_col()
+			.filter(|_| !msg.starts_with("missing field"))
+			.and_then(|(line, _)| toml_str.lines().nth(line))
+			.map(|line| line.trim())
+			.filter(|line| !line.is_empty());
+		match offending_line {
+			Some(line) => format!("{}\n\n```toml\n{}\n```", msg, line),
+			None => msg,
+		}
+	})
+}
+
+/// Return the full sha of the PR commit `requested` refers to, if any.
+/// Abbreviated shas are accepted as long as they are unambiguous.
+pub fn pinned_commit<'a>(
+	requested: &str,
+	pr_commits: &'a [String],
+) -> Option<&'a str> {
+	let requested = requested.trim().to_lowercase();
+	if requested.len() < 7 || !requested.chars().all(|c| c.is_ascii_hexdigit())
+	{
+		return None;
+	}
+	let mut matches = pr_commits
+		.iter()
+		.filter(|sha| sha.to_lowercase().starts_with(&requested));
+	match (matches.next(), matches.next()) {
+		(Some(sha), None) => Some(sha),
+		_ => None,
+	}
+}
+
+/// Replace the fields of a request which can be derived from the PR with
+/// authoritative values.  `commit_sha` is kept if it deliberately pins another
+/// commit of the PR, and otherwise replaced by the head of the PR.  Returns the
+/// normalized request along with notes on any values that were changed.
+pub fn normalize_request(
+	request: BurninRequest,
+	pr: &PullRequest,
+	requested_by: &str,
+	pr_commits: &[String],
+) -> (BurninRequest, Vec<String>) {
+	let mut notes = vec![];
+	if request.pull_request.trim_end_matches('/') != pr.html_url {
+		notes.push(format!(
+			"`pull_request` {} was replaced with {}",
+			request.pull_request, pr.html_url
+		));
+	}
+	if !request.requested_by.eq_ignore_ascii_case(requested_by) {
+		notes.push(format!(
+			"`requested_by` {} was replaced with {}",
+			request.requested_by, requested_by
+		));
+	}
+	let head_sha = pr.head.sha.as_str();
+	let commit_sha = match pinned_commit(&request.commit_sha, pr_commits) {
+		Some(sha) if sha != head_sha => {
+			notes.push(format!(
+				"`commit_sha` is pinned to {}, which is not the current head of this PR ({})",
+				sha, head_sha
+			));
+			sha
+		}
+		Some(_) => head_sha,
+		None if pinned_commit(&request.commit_sha, &[head_sha.to_string()])
+			.is_some() =>
+		{
+			head_sha
+		}
+		None => {
+			notes.push(format!(
+				"`commit_sha` {} is not a commit of this PR and was replaced with the current head ({})",
+				request.commit_sha, head_sha
+			));
+			head_sha
+		}
+	};
+	(
+		BurninRequest {
+			pull_request: pr.html_url.clone(),
+			commit_sha: commit_sha.to_string(),
+			requested_by: requested_by.to_string(),
+			..request
+		},
+		notes,
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::cell::{Cell, RefCell};
+	use std::collections::{HashMap, VecDeque};
+
+	fn record() -> BurninRecord {
+		BurninRecord {
+			owner: "paritytech".to_owned(),
+			repo_name: "polkadot".to_owned(),
+			number: 1234,
+			html_url: "https://github.com/paritytech/polkadot/pull/1234"
+				.to_owned(),
+			requested_by: "sjeohp".to_owned(),
+			commit_sha: "a7b3c1d".to_owned(),
+			gitlab_path: "requests/request-1610469388.toml".to_owned(),
+			gitlab_commit: None,
+			pipeline_url: None,
+			created_at: 1610469388,
+			state: BurninState::Submitted,
+		}
+	}
+
+	#[test]
+	fn test_record_serialization() {
+		let mut rec = record();
+		let bytes = bincode::serialize(&rec).unwrap();
+		assert_eq!(bincode::deserialize::<BurninRecord>(&bytes).unwrap(), rec);
+
+		rec.gitlab_commit = Some("f00ba4".to_owned());
+		rec.pipeline_url =
+			Some("https://gitlab.parity.io/parity/x/pipelines/1".to_owned());
+		rec.state = BurninState::Failed;
+		let bytes = bincode::serialize(&rec).unwrap();
+		assert_eq!(bincode::deserialize::<BurninRecord>(&bytes).unwrap(), rec);
+	}
+
+	#[test]
+	fn test_record_expiry() {
+		let rec = record();
+		assert!(!rec.is_expired(rec.created_at));
+		assert!(!rec.is_expired(rec.created_at + RECORD_TTL_SECS));
+		assert!(rec.is_expired(rec.created_at + RECORD_TTL_SECS + 1));
+	}
+
+	#[test]
+	fn test_state_from_pipeline_status() {
+		assert_eq!(
+			BurninState::from_pipeline_status("success"),
+			BurninState::Completed
+		);
+		assert_eq!(
+			BurninState::from_pipeline_status("failed"),
+			BurninState::Failed
+		);
+		assert_eq!(
+			BurninState::from_pipeline_status("canceled"),
+			BurninState::Failed
+		);
+		assert_eq!(
+			BurninState::from_pipeline_status("running"),
+			BurninState::Running
+		);
+		assert_eq!(
+			BurninState::from_pipeline_status("pending"),
+			BurninState::Running
+		);
+	}
+
+	#[test]
+	fn test_commit_message() {
+		let msg = commit_message("polkadot", 1234);
+		assert_eq!(parse_commit_message(&msg), Some(("polkadot", 1234)));
+		assert_eq!(
+			parse_commit_message("Add request for substrate#1\n\nbody"),
+			Some(("substrate", 1))
+		);
+		assert_eq!(parse_commit_message("Add request for polkadot"), None);
+		assert_eq!(parse_commit_message("Add request for polkadot#x"), None);
+		assert_eq!(parse_commit_message("Update README"), None);
+	}
+
+	#[test]
+	fn test_find_record_for_commit() {
+		let now = record().created_at;
+		let matched = BurninRecord {
+			number: 1,
+			gitlab_commit: Some("f00ba4".to_owned()),
+			..record()
+		};
+		let older = BurninRecord {
+			created_at: now - 10,
+			..record()
+		};
+		let newer = BurninRecord {
+			created_at: now + 10,
+			..record()
+		};
+		let records = vec![matched.clone(), older.clone(), newer.clone()];
+		let msg = commit_message("polkadot", 1234);
+
+		assert_eq!(
+			find_record_for_commit(records.clone(), "f00ba4", None, now),
+			Some(matched)
+		);
+		assert_eq!(
+			find_record_for_commit(records.clone(), "abc", Some(&msg), now),
+			Some(newer.clone())
+		);
+		assert_eq!(
+			find_record_for_commit(records.clone(), "abc", None, now),
+			None
+		);
+		assert_eq!(
+			find_record_for_commit(
+				records,
+				"abc",
+				Some("Add request for substrate#1234"),
+				now
+			),
+			None
+		);
+		assert_eq!(
+			find_record_for_commit(
+				vec![BurninRecord {
+					state: BurninState::Cancelled,
+					..newer
+				}],
+				"abc",
+				Some(&msg),
+				now
+			),
+			None
+		);
+		assert_eq!(
+			find_record_for_commit(
+				vec![older],
+				"abc",
+				Some(&msg),
+				now + RECORD_TTL_SECS + 1
+			),
+			None
+		);
+	}
+
+	#[test]
+	fn test_request_path() {
+		assert_eq!(
+			request_path("polkadot", 1234, 1610469388, "0a1b2c3d"),
+			"requests/polkadot-1234-1610469388-0a1b2c3d.toml"
+		);
+		let suffix = random_suffix().unwrap();
+		assert_eq!(suffix.len(), 8);
+		assert!(suffix.chars().all(|c| c.is_ascii_hexdigit()));
+
+		// requests for the same PR within the same second
+		let paths = (0..100)
+			.map(|_| {
+				request_path(
+					"polkadot",
+					1234,
+					1610469388,
+					&random_suffix().unwrap(),
+				)
+			})
+			.collect::<std::collections::HashSet<_>>();
+		assert_eq!(paths.len(), 100);
+	}
+
+	fn api_error(status: u32, body: &str) -> Error {
+		Error::GitlabApi {
+			method: "POST".to_string(),
+			url: "https://gitlab.parity.io/api/v4/projects/1".to_string(),
+			status,
+			body: body.to_string(),
+			message: crate::gitlab_bot::error_message(body),
+		}
+	}
+
+	#[test]
+	fn test_retry_decision() {
+		let table = vec![
+			(api_error(500, ""), RetryDecision::Retry),
+			(api_error(502, "Bad Gateway"), RetryDecision::Retry),
+			(api_error(503, ""), RetryDecision::Retry),
+			(api_error(429, "Retry later"), RetryDecision::Retry),
+			(
+				api_error(
+					400,
+					r#"{"message":"A file with this name already exists"}"#,
+				),
+				RetryDecision::RetryWithNewPath,
+			),
+			(
+				api_error(400, r#"{"error":"content is missing"}"#),
+				RetryDecision::GiveUp,
+			),
+			(api_error(401, "Unauthorized"), RetryDecision::GiveUp),
+			(api_error(403, "Forbidden"), RetryDecision::GiveUp),
+			(api_error(404, "Not Found"), RetryDecision::GiveUp),
+			(
+				Error::Message {
+					msg: "unexpected".to_string(),
+				},
+				RetryDecision::GiveUp,
+			),
+		];
+		for (e, expected) in table {
+			assert_eq!(retry_decision(&e), expected, "{}", e);
+		}
+	}
+
+	/// The request files of a Gitlab repository, with how the next attempts at
+	/// committing one fail and whether they commit it anyway.
+	struct FakeGitlab {
+		files: RefCell<HashMap<String, String>>,
+		failures: RefCell<VecDeque<(u32, bool)>>,
+		paths: Cell<usize>,
+	}
+
+	impl FakeGitlab {
+		fn new(taken: usize, failures: &[(u32, bool)]) -> Self {
+			Self {
+				files: RefCell::new(
+					(1..=taken)
+						.map(|n| (Self::path(n), "taken".to_string()))
+						.collect(),
+				),
+				failures: RefCell::new(failures.iter().copied().collect()),
+				paths: Cell::new(0),
+			}
+		}
+
+		fn path(n: usize) -> String {
+			format!("requests/polkadot-1234-{}.toml", n)
+		}
+
+		fn create(&self, path: &str) -> Result<()> {
+			if self.files.borrow().contains_key(path) {
+				return Err(api_error(
+					400,
+					r#"{"message":"A file with this name already exists"}"#,
+				));
+			}
+			let failure = self.failures.borrow_mut().pop_front();
+			if failure.is_none_or(|(_, committed)| committed) {
+				self.files
+					.borrow_mut()
+					.insert(path.to_string(), "request".to_string());
+			}
+			match failure {
+				Some((status, _)) => Err(api_error(status, "")),
+				None => Ok(()),
+			}
+		}
+
+		async fn submit(&self) -> Result<String> {
+			submit_with_retries(
+				|path| {
+					let res = self.create(path);
+					async move { res }
+				},
+				|path| {
+					let found = self.files.borrow().get(path).cloned();
+					async move { Ok(found) }
+				},
+				|| {
+					self.paths.set(self.paths.get() + 1);
+					Ok(Self::path(self.paths.get()))
+				},
+				"request",
+				std::time::Duration::from_millis(0),
+			)
+			.await
+		}
+
+		fn requests(&self) -> usize {
+			self.files
+				.borrow()
+				.values()
+				.filter(|content| *content == "request")
+				.count()
+		}
+	}
+
+	#[tokio::test]
+	async fn test_submit_with_retries() {
+		let gitlab = FakeGitlab::new(0, &[]);
+		assert_eq!(gitlab.submit().await.unwrap(), FakeGitlab::path(1));
+		assert_eq!(gitlab.requests(), 1);
+
+		let gitlab = FakeGitlab::new(0, &[(502, false), (503, false)]);
+		assert_eq!(gitlab.submit().await.unwrap(), FakeGitlab::path(1));
+		assert_eq!(gitlab.requests(), 1);
+
+		// committed although the response was an error: not committed again
+		let gitlab = FakeGitlab::new(0, &[(502, true)]);
+		assert_eq!(gitlab.submit().await.unwrap(), FakeGitlab::path(1));
+		assert_eq!(gitlab.requests(), 1);
+		assert_eq!(gitlab.paths.get(), 1);
+
+		let gitlab = FakeGitlab::new(2, &[]);
+		assert_eq!(gitlab.submit().await.unwrap(), FakeGitlab::path(3));
+		assert_eq!(gitlab.requests(), 1);
+
+		let gitlab = FakeGitlab::new(SUBMIT_ATTEMPTS, &[]);
+		match gitlab.submit().await {
+			Err(Error::GitlabPathsTaken { attempts, .. }) => {
+				assert_eq!(attempts, SUBMIT_ATTEMPTS)
+			}
+			other => panic!("unexpected {:?}", other),
+		}
+		assert_eq!(gitlab.requests(), 0);
+
+		let gitlab = FakeGitlab::new(0, &[(500, false); SUBMIT_ATTEMPTS]);
+		match gitlab.submit().await {
+			Err(Error::GitlabUnreachable { attempts, .. }) => {
+				assert_eq!(attempts, SUBMIT_ATTEMPTS)
+			}
+			other => panic!("unexpected {:?}", other),
+		}
+		assert_eq!(gitlab.requests(), 0);
+
+		let gitlab = FakeGitlab::new(0, &[(403, false)]);
+		assert!(matches!(
+			gitlab.submit().await,
+			Err(Error::GitlabRejected { .. })
+		));
+	}
+
+	fn node_types(fullnode: u32, sentry: u32, validator: u32) -> NodeTypes {
+		NodeTypes {
+			fullnode,
+			sentry,
+			validator,
+		}
+	}
+
+	#[test]
+	fn test_limit_violations() {
+		let limits = BurninLimits::default();
+		assert!(limits.violations(&node_types(1, 0, 0)).is_empty());
+		assert!(limits.violations(&node_types(4, 0, 0)).is_empty());
+		assert!(limits.violations(&node_types(0, 0, 0)).is_empty());
+
+		let violations = limits.violations(&node_types(5, 0, 0));
+		assert_eq!(violations.len(), 1);
+		assert!(violations[0].contains("at most 4"));
+
+		let violations = limits.violations(&node_types(1, 2, 50));
+		assert_eq!(violations.len(), 2);
+		assert!(violations[0].contains("sentry"));
+		assert!(violations[1].contains("50 validator"));
+
+		// a type that is not allowed is rejected regardless of its maximum
+		let limits = BurninLimits {
+			allowed_node_types: vec![],
+			..BurninLimits::default()
+		};
+		assert_eq!(limits.violations(&node_types(1, 0, 0)).len(), 1);
+
+		let limits = BurninLimits {
+			allowed_node_types: vec![
+				"fullnode".to_string(),
+				"sentry".to_string(),
+			],
+			max_sentries: 2,
+			..BurninLimits::default()
+		};
+		assert!(limits.violations(&node_types(4, 2, 0)).is_empty());
+	}
+
+	#[test]
+	fn test_describe_limits() {
+		assert_eq!(
+			BurninLimits::default().describe(),
+			"at most 4 fullnode; no sentry or validator"
+		);
+		let limits = BurninLimits {
+			allowed_node_types: vec![
+				"fullnode".to_string(),
+				"sentry".to_string(),
+				"validator".to_string(),
+			],
+			max_sentries: 1,
+			max_validators: 2,
+			..BurninLimits::default()
+		};
+		assert_eq!(
+			limits.describe(),
+			"at most 4 fullnode; at most 1 sentry; at most 2 validator"
+		);
+	}
+
+	#[test]
+	fn test_check_limits() {
+		let limits = BurninLimits::default();
+		assert_eq!(
+			check_limits(&node_types(1, 0, 0), &limits, false),
+			LimitCheck::Within
+		);
+		assert_eq!(
+			check_limits(&node_types(1, 0, 0), &limits, true),
+			LimitCheck::Within
+		);
+		match check_limits(&node_types(1, 0, 3), &limits, false) {
+			LimitCheck::Exceeded(v) => assert_eq!(v.len(), 1),
+			res => panic!("unexpected result: {:?}", res),
+		}
+		match check_limits(&node_types(1, 0, 3), &limits, true) {
+			LimitCheck::Overridden(v) => assert_eq!(v.len(), 1),
+			res => panic!("unexpected result: {:?}", res),
+		}
+	}
+
+	#[test]
+	fn test_format_duration() {
+		assert_eq!(format_duration(0), "0s");
+		assert_eq!(format_duration(59), "59s");
+		assert_eq!(format_duration(754), "12m 34s");
+		assert_eq!(format_duration(3600), "1h 0m 0s");
+		assert_eq!(format_duration(3723), "1h 2m 3s");
+	}
+
+	#[test]
+	fn test_parse_command() {
+		assert_eq!(
+			parse_command("bot burnin status"),
+			Some(BurninCommand::Status)
+		);
+		assert_eq!(
+			parse_command("  Bot Burnin Status\n"),
+			Some(BurninCommand::Status)
+		);
+		assert_eq!(
+			parse_command(&format!("bot burnin\n```toml\n{}```", VALID)),
+			Some(BurninCommand::Request)
+		);
+		assert_eq!(
+			parse_command("bot burnin cancel"),
+			Some(BurninCommand::Cancel)
+		);
+		assert_eq!(parse_command("bot burnin"), Some(BurninCommand::Request));
+		assert_eq!(parse_command("bot merge"), None);
+		assert_eq!(parse_command("please bot burnin status"), None);
+	}
+
+	const VALID: &str = r#"pull_request = "https://github.com/paritytech/polkadot/pull/1234"
+commit_sha = "a7b3c1d"
+requested_by = "sjeohp"
+sync_from_scratch = false
+
+[node_types]
+fullnode = 1
+sentry = 0
+validator = 0
+"#;
+
+	#[test]
+	fn test_extract_toml() {
+		let body = format!("bot burnin\n```toml\n{}```", VALID);
+		assert_eq!(extract_toml(&body), Some(VALID));
+		let body = format!("bot burnin\n```\n{}```", VALID);
+		assert_eq!(extract_toml(&body), Some(VALID));
+		assert_eq!(extract_toml("bot burnin"), None);
+		assert_eq!(extract_toml("bot burnin\n```toml\nunterminated"), None);
+	}
+
+	#[test]
+	fn test_parse_request() {
+		// (fixture, expected error substring or None if valid)
+		let fixtures: Vec<(String, Option<&str>)> = vec![
+			(VALID.to_owned(), None),
+			(VALID.replace("node_types", "node_typs"), Some("node_typs")),
+			(
+				VALID.replace("commit_sha = \"a7b3c1d\"\n", ""),
+				Some("commit_sha"),
+			),
+			(
+				VALID.replace("fullnode = 1", "fullnode = \"1\""),
+				Some("fullnode"),
+			),
+			(
+				VALID.replace("fullnode = 1", "fullnode = -1"),
+				Some("fullnode"),
+			),
+			(
+				VALID.replace(
+					"sync_from_scratch = false",
+					"sync_from_scratch = no",
+				),
+				Some("sync_from_scratch"),
+			),
+			(
+				VALID.replace("validator = 0", "validator = 0\narchive = 1"),
+				Some("archive"),
+			),
+			("".to_owned(), Some("missing field")),
+		];
+		for (fixture, expected) in fixtures {
+			match (parse_request(&fixture), expected) {
+				(Ok(req), None) => {
+					assert_eq!(req.node_types.fullnode, 1);
+					assert_eq!(req.requested_by, "sjeohp");
+				}
+				(Err(msg), Some(substring)) => assert!(
+					msg.contains(substring),
+					"expected '{}' in error: {}",
+					substring,
+					msg
+				),
+				(res, _) => {
+					panic!("unexpected result for {}: {:?}", fixture, res)
+				}
+			}
+		}
+	}
+
+	fn pr() -> PullRequest {
+		PullRequest {
+			html_url: "https://github.com/paritytech/polkadot/pull/1234"
+				.to_owned(),
+			head: crate::github::Head {
+				sha: "a7b3c1d5e6f7a8b9c0d1e2f3a4b5c6d7e8f9a0b1".to_owned(),
+				..Default::default()
+			},
+			..Default::default()
+		}
+	}
+
+	fn pr_commits() -> Vec<String> {
+		vec![
+			"0a0b0c0d0e0f0a0b0c0d0e0f0a0b0c0d0e0f0a0b".to_owned(),
+			"0a0b0c0d11111111111111111111111111111111".to_owned(),
+			"f00ba4f00ba4f00ba4f00ba4f00ba4f00ba4f00b".to_owned(),
+			pr().head.sha,
+		]
+	}
+
+	#[test]
+	fn test_pinned_commit() {
+		let commits = pr_commits();
+		// full and abbreviated shas of an ancestor of the head
+		assert_eq!(pinned_commit(&commits[2], &commits), Some(&*commits[2]));
+		assert_eq!(pinned_commit("F00BA4F", &commits), Some(&*commits[2]));
+		assert_eq!(pinned_commit("a7b3c1d", &commits), Some(&*commits[3]));
+		// ambiguous prefix
+		assert_eq!(pinned_commit("0a0b0c0d", &commits), None);
+		// too short to be meaningful
+		assert_eq!(pinned_commit("f00b", &commits), None);
+		// garbage
+		assert_eq!(pinned_commit("deadbeef", &commits), None);
+		assert_eq!(pinned_commit("not a sha", &commits), None);
+		assert_eq!(pinned_commit("", &commits), None);
+	}
+
+	#[test]
+	fn test_normalize_request() {
+		let commits = pr_commits();
+		let pr = pr();
+
+		let (req, notes) = normalize_request(
+			parse_request(VALID).unwrap(),
+			&pr,
+			"sjeohp",
+			&commits,
+		);
+		assert_eq!(req.commit_sha, pr.head.sha);
+		assert_eq!(req.pull_request, pr.html_url);
+		assert!(notes.is_empty(), "{:?}", notes);
+
+		let stale = BurninRequest {
+			pull_request: "https://github.com/paritytech/polkadot/pull/1"
+				.to_owned(),
+			commit_sha: "deadbeef".to_owned(),
+			requested_by: "someone-else".to_owned(),
+			..parse_request(VALID).unwrap()
+		};
+		let (req, notes) =
+			normalize_request(stale.clone(), &pr, "sjeohp", &commits);
+		assert_eq!(req.pull_request, pr.html_url);
+		assert_eq!(req.commit_sha, pr.head.sha);
+		assert_eq!(req.requested_by, "sjeohp");
+		assert_eq!(req.node_types, stale.node_types);
+		assert_eq!(notes.len(), 3);
+
+		let pinned = BurninRequest {
+			commit_sha: "f00ba4f".to_owned(),
+			..parse_request(VALID).unwrap()
+		};
+		let (req, notes) = normalize_request(pinned, &pr, "sjeohp", &commits);
+		assert_eq!(req.commit_sha, commits[2]);
+		assert_eq!(notes.len(), 1);
+		assert!(notes[0].contains("pinned"));
+
+		// the head is always accepted, even if the commits could not be listed
+		let (req, notes) = normalize_request(
+			parse_request(VALID).unwrap(),
+			&pr,
+			"sjeohp",
+			&[],
+		);
+		assert_eq!(req.commit_sha, pr.head.sha);
+		assert!(notes.is_empty(), "{:?}", notes);
+	}
+
+	#[test]
+	fn test_serialize_normalized_request() {
+		let (req, _) = normalize_request(
+			parse_request(VALID).unwrap(),
+			&pr(),
+			"sjeohp",
+			&pr_commits(),
+		);
+		let toml = toml::to_string(&req).unwrap();
+		assert_eq!(parse_request(&toml).unwrap(), req);
+		assert!(toml.contains("[node_types]"));
+	}
+
+	#[test]
+	fn test_prompt() {
+		for (prefix, command) in
+			&[("bot", "bot burnin"), ("/", "/burnin"), ("pb", "pb burnin")]
+		{
+			let prompt = prompt(
+				"gavofyork",
+				"paritytech",
+				&pr(),
+				prefix,
+				&Templates::default(),
+			);
+			assert!(prompt.starts_with("@gavofyork to request a burn-in"));
+			assert!(prompt.contains(&format!(
+				"The comment must start with \"{}\"",
+				command
+			)));
+			// the example is a request, once commented under the prefix
+			let example =
+				&prompt[prompt.find(&format!("\n{}\n", command)).unwrap()..];
+			let body =
+				crate::command_prefix::canonical(example, prefix, &[]).unwrap();
+			assert_eq!(parse_command(&body), Some(BurninCommand::Request));
+			let req = parse_request(extract_toml(&body).unwrap()).unwrap();
+			assert_eq!(req.pull_request, pr().html_url);
+			assert_eq!(req.commit_sha, pr().head.sha);
+			assert_eq!(req.requested_by, "gavofyork");
+		}
+	}
+}
@@ -0,0 +1,63 @@
+use parking_lot::Mutex;
+use std::collections::HashMap;
+
+/// Values fetched per repository, kept for a limited time to avoid fetching
+/// them for every event.
+pub struct RepoCache<T> {
+	ttl_secs: i64,
+	entries: Mutex<HashMap<String, Cached<T>>>,
+}
+
+struct Cached<T> {
+	fetched_at: i64,
+	value: T,
+}
+
+impl<T: Clone> RepoCache<T> {
+	pub fn new(ttl_secs: u64) -> Self {
+		Self {
+			ttl_secs: ttl_secs as i64,
+			entries: Mutex::new(HashMap::new()),
+		}
+	}
+
+	fn key(owner: &str, repo_name: &str) -> String {
+		format!("{}/{}", owner, repo_name)
+	}
+
+	/// Return the cached value for a repository unless it has expired.
+	pub fn get(&self, owner: &str, repo_name: &str, now: i64) -> Option<T> {
+		self.entries
+			.lock()
+			.get(&Self::key(owner, repo_name))
+			.filter(|cached| now - cached.fetched_at <= self.ttl_secs)
+			.map(|cached| cached.value.clone())
+	}
+
+	/// Return the cached value for a repository regardless of its age.
+	pub fn get_stale(&self, owner: &str, repo_name: &str) -> Option<T> {
+		self.entries
+			.lock()
+			.get(&Self::key(owner, repo_name))
+			.map(|cached| cached.value.clone())
+	}
+
+	pub fn insert(&self, owner: &str, repo_name: &str, value: T, now: i64) {
+		self.entries.lock().insert(
+			Self::key(owner, repo_name),
+			Cached {
+				fetched_at: now,
+				value,
+			},
+		);
+	}
+
+	/// Drop the cached value for a repository. Returns `true` if there was
+	/// one.
+	pub fn invalidate(&self, owner: &str, repo_name: &str) -> bool {
+		self.entries
+			.lock()
+			.remove(&Self::key(owner, repo_name))
+			.is_some()
+	}
+}
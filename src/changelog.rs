@@ -0,0 +1,142 @@
+use serde::Deserialize;
+
+use crate::{
+	codeowners,
+	github::{Label, PullRequestFile},
+};
+
+/// A changelog entry every PR of a repository must add before it is merged,
+/// unless it carries one of the exempt labels.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChangelogRequirement {
+	/// gitignore-style pattern of the entry, eg. `docs/changelog/*.md` or
+	/// `CHANGELOG.md`
+	pub path: String,
+	/// labels of PRs which need no entry, eg. `B0-silent`
+	#[serde(default)]
+	pub exempt_labels: Vec<String>,
+}
+
+impl ChangelogRequirement {
+	pub fn is_exempt(&self, labels: &[Label]) -> bool {
+		labels
+			.iter()
+			.any(|label| self.exempt_labels.contains(&label.name))
+	}
+
+	/// Whether any of `files` adds or changes an entry.  A pattern which
+	/// cannot be understood is not enforced.
+	pub fn is_met(&self, files: &[PullRequestFile]) -> bool {
+		match codeowners::glob_regex(&self.path) {
+			Ok(regex) => files.iter().any(|file| {
+				file.status != "removed" && regex.is_match(&file.filename)
+			}),
+			Err(e) => {
+				log::warn!(
+					"Ignoring invalid changelog pattern '{}': {}",
+					self.path,
+					e
+				);
+				true
+			}
+		}
+	}
+}
+
+/// Where the entry of PR `number` could go, eg. `docs/changelog/1234.md`.
+pub fn example_path(pattern: &str, number: i64) -> String {
+	pattern
+		.trim_start_matches('/')
+		.replace("**/", "")
+		.replace('*', &number.to_string())
+		.replace('?', "x")
+}
+
+/// A snippet to start the entry of PR `number` from.
+pub fn template(number: i64) -> String {
+	format!(
+		"```markdown\n- <What changed, as users should learn of it> (#{})\n```",
+		number
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn requirement(path: &str) -> ChangelogRequirement {
+		ChangelogRequirement {
+			path: path.to_string(),
+			exempt_labels: vec!["B0-silent".to_string()],
+		}
+	}
+
+	fn files(files: &[(&str, &str)]) -> Vec<PullRequestFile> {
+		files
+			.iter()
+			.map(|(filename, status)| PullRequestFile {
+				filename: filename.to_string(),
+				status: status.to_string(),
+				..PullRequestFile::default()
+			})
+			.collect()
+	}
+
+	#[test]
+	fn test_is_met() {
+		let fragments = requirement("docs/changelog/*.md");
+		assert!(fragments.is_met(&files(&[
+			("src/lib.rs", "modified"),
+			("docs/changelog/1234.md", "added"),
+			("Cargo.lock", "modified"),
+		])));
+		assert!(!fragments.is_met(&files(&[
+			("src/lib.rs", "modified"),
+			("Cargo.lock", "modified"),
+		])));
+		// neither deleting an entry, nor one in another directory, will do
+		assert!(!fragments.is_met(&files(&[
+			("docs/changelog/1000.md", "removed"),
+			("docs/changelog/old/1234.md", "added"),
+			("changelog/1234.md", "added"),
+		])));
+		assert!(!fragments.is_met(&[]));
+
+		let touch = requirement("CHANGELOG.md");
+		assert!(touch.is_met(&files(&[
+			("src/lib.rs", "modified"),
+			("CHANGELOG.md", "modified"),
+		])));
+		// unanchored, as in CODEOWNERS
+		assert!(touch.is_met(&files(&[("client/CHANGELOG.md", "modified")])));
+		assert!(!touch.is_met(&files(&[("CHANGELOG.txt", "modified")])));
+	}
+
+	#[test]
+	fn test_is_exempt() {
+		let label = |name: &str| Label {
+			name: name.to_string(),
+			..Label::default()
+		};
+		let fragments = requirement("docs/changelog/*.md");
+		assert!(fragments
+			.is_exempt(&[label("A0-pleasereview"), label("B0-silent")]));
+		assert!(!fragments.is_exempt(&[label("B1-releasenotes")]));
+		assert!(!fragments.is_exempt(&[]));
+		assert!(!requirement("CHANGELOG.md").is_exempt(&[label("b0-silent")]));
+	}
+
+	#[test]
+	fn test_example_path() {
+		assert_eq!(
+			example_path("docs/changelog/*.md", 1234),
+			"docs/changelog/1234.md"
+		);
+		assert_eq!(example_path("/CHANGELOG.md", 1234), "CHANGELOG.md");
+		assert_eq!(
+			example_path("**/changes/pr-*.md", 1234),
+			"changes/pr-1234.md"
+		);
+	}
+}
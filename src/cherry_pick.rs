@@ -0,0 +1,269 @@
+use itertools::Itertools;
+
+use crate::{
+	backport::{self, Outcome},
+	constants::*,
+	error::Error,
+	http::GithubApiError,
+};
+
+/// Characters of a commit's sha its working branch is named after.
+const SHORT_SHA_LEN: usize = 10;
+
+/// `bot cherry-pick <sha> to <branch>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CherryPickCommand {
+	pub sha: String,
+	pub branch: String,
+}
+
+/// Parse a `bot cherry-pick` comment; an invalid one is an error explaining
+/// why.
+pub fn parse_command(
+	body: &str,
+) -> Option<std::result::Result<CherryPickCommand, String>> {
+	let body = body.trim();
+	let command = body.get(..CHERRY_PICK.len())?;
+	let rest = body.get(CHERRY_PICK.len()..)?;
+	if !command.eq_ignore_ascii_case(CHERRY_PICK)
+		|| !(rest.is_empty() || rest.starts_with(char::is_whitespace))
+	{
+		return None;
+	}
+	let words = rest.split_whitespace().collect::<Vec<_>>();
+	Some(match words.as_slice() {
+		[sha, to, branch] if to.eq_ignore_ascii_case("to") => validate_sha(sha)
+			.and_then(|()| backport::validate_branch(branch))
+			.map(|()| CherryPickCommand {
+				sha: sha.to_lowercase(),
+				branch: branch.to_string(),
+			}),
+		_ => Err(format!(
+			"name the commit and the branch, eg. `{} 1a2b3c4d to release-v0.9.30`",
+			CHERRY_PICK
+		)),
+	})
+}
+
+/// Check that `sha` looks like a commit's sha, abbreviated or not.
+pub fn validate_sha(sha: &str) -> std::result::Result<(), String> {
+	if (7..=40).contains(&sha.len())
+		&& sha.chars().all(|c| c.is_ascii_hexdigit())
+	{
+		Ok(())
+	} else {
+		Err(format!("`{}` is not a commit sha", sha))
+	}
+}
+
+/// Whether looking up a commit failed because the repository has no such
+/// commit.
+pub fn is_unknown_commit(e: &Error) -> bool {
+	matches!(
+		e.github_api_error(),
+		Some(GithubApiError::NotFound(_)) | Some(GithubApiError::Validation(_))
+	)
+}
+
+fn short_sha(sha: &str) -> &str {
+	&sha[..sha.len().min(SHORT_SHA_LEN)]
+}
+
+/// The bot's branch the commit is picked onto `target` in.  It is none of the
+/// `taken` branches of the repository, such as that of an earlier cherry-pick
+/// of the commit whose PR may still be open.
+pub fn working_branch(sha: &str, target: &str, taken: &[String]) -> String {
+	let base = format!("cherry-pick/{}-{}", short_sha(sha), target);
+	std::iter::once(base.clone())
+		.chain((2..).map(|n| format!("{}-{}", base, n)))
+		.find(|branch| !taken.contains(branch))
+		.expect("endless names")
+}
+
+/// The title of the PR, after the first line of the commit message.
+pub fn title(target: &str, message: &str) -> String {
+	format!(
+		"[cherry-pick {}] {}",
+		target,
+		message.lines().next().unwrap_or_default()
+	)
+}
+
+pub fn body(
+	target: &str,
+	sha: &str,
+	requested_by: &str,
+	requested_in: &str,
+) -> String {
+	format!(
+		"Cherry-pick of {} onto `{}`.\n\nRequested by @{} in {}.",
+		sha, target, requested_by, requested_in
+	)
+}
+
+/// The comment saying how the cherry-pick went.
+pub fn comment(sha: &str, outcome: &Outcome) -> String {
+	let sha = short_sha(sha);
+	match outcome {
+		Outcome::Opened { branch, html_url } => format!(
+			"Opened {} with {} cherry-picked onto `{}`.",
+			html_url, sha, branch
+		),
+		Outcome::Conflict { branch, files } => format!(
+			"Cherry-picking {} onto `{}` conflicts in:\n\n{}\n\nNo PR was opened; cherry-pick it by hand.",
+			sha,
+			branch,
+			files.iter().map(|file| format!("- `{}`", file)).join("\n")
+		),
+		Outcome::Failed { branch, reason } => format!(
+			"Cherry-picking {} onto `{}` failed; {}",
+			sha, branch, reason
+		),
+		Outcome::Planned { branch } => format!(
+			"Would have opened a PR with {} cherry-picked onto `{}`.",
+			sha, branch
+		),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::http::ErrorBody;
+
+	#[test]
+	fn test_parse_command() {
+		assert_eq!(
+			parse_command("bot cherry-pick 1A2B3C4D to release-v0.9.30"),
+			Some(Ok(CherryPickCommand {
+				sha: "1a2b3c4d".to_string(),
+				branch: "release-v0.9.30".to_string(),
+			}))
+		);
+		assert!(matches!(
+			parse_command(" Bot Cherry-Pick 1a2b3c4d To release-v0.9.30\n"),
+			Some(Ok(_))
+		));
+		for invalid in &[
+			"bot cherry-pick",
+			"bot cherry-pick 1a2b3c4d",
+			"bot cherry-pick 1a2b3c4d release-v0.9.30",
+			"bot cherry-pick 1a2b3c4d onto release-v0.9.30",
+			"bot cherry-pick 1a2b3c4d to release-v0.9.30 now",
+			"bot cherry-pick master to release-v0.9.30",
+			"bot cherry-pick 1a2b3c4d to --force",
+		] {
+			assert!(
+				matches!(parse_command(invalid), Some(Err(_))),
+				"{}",
+				invalid
+			);
+		}
+		assert_eq!(parse_command("bot cherry-picks 1a2b3c4d to x"), None);
+		assert_eq!(parse_command("bot backport release-v0.9.30"), None);
+	}
+
+	#[test]
+	fn test_validate_sha() {
+		for sha in &["1a2b3c4", "1A2B3C4D", &"f".repeat(40)] {
+			assert_eq!(validate_sha(sha), Ok(()), "{}", sha);
+		}
+		for sha in &["1a2b3c", &"f".repeat(41), "1a2b3c4g", "HEAD~1", ""] {
+			assert!(validate_sha(sha).is_err(), "{}", sha);
+		}
+		assert_eq!(
+			validate_sha("master"),
+			Err("`master` is not a commit sha".to_string())
+		);
+	}
+
+	#[test]
+	fn test_is_unknown_commit() {
+		let response = |error| Error::Response {
+			status: reqwest::StatusCode::NOT_FOUND,
+			error,
+		};
+		assert!(is_unknown_commit(&response(GithubApiError::NotFound(
+			ErrorBody::default()
+		))));
+		assert!(is_unknown_commit(&response(GithubApiError::Validation(
+			ErrorBody {
+				message: "No commit found for SHA: 1a2b3c4d".to_string(),
+				..Default::default()
+			}
+		))));
+		assert!(!is_unknown_commit(&response(GithubApiError::Forbidden(
+			ErrorBody::default()
+		))));
+	}
+
+	#[test]
+	fn test_working_branch() {
+		let sha = "1a2b3c4d5e6f7a8b9c0d1a2b3c4d5e6f7a8b9c0d";
+		assert_eq!(
+			working_branch(sha, "release-v0.9.30", &[]),
+			"cherry-pick/1a2b3c4d5e-release-v0.9.30"
+		);
+		assert_eq!(
+			working_branch("1a2b3c4", "release-v0.9.30", &[]),
+			"cherry-pick/1a2b3c4-release-v0.9.30"
+		);
+		// earlier cherry-picks of the commit onto the branch
+		let taken = vec![
+			"master".to_string(),
+			"cherry-pick/1a2b3c4d5e-release-v0.9.30".to_string(),
+			"cherry-pick/1a2b3c4d5e-release-v0.9.30-2".to_string(),
+			"cherry-pick/1a2b3c4d5e-release-v0.9.31".to_string(),
+		];
+		assert_eq!(
+			working_branch(sha, "release-v0.9.30", &taken),
+			"cherry-pick/1a2b3c4d5e-release-v0.9.30-3"
+		);
+		assert_eq!(
+			working_branch(sha, "release-v0.9.32", &taken),
+			"cherry-pick/1a2b3c4d5e-release-v0.9.32"
+		);
+	}
+
+	#[test]
+	fn test_pr_text() {
+		assert_eq!(
+			title("release-v0.9.30", "Fix the fork choice\n\nIt was wrong."),
+			"[cherry-pick release-v0.9.30] Fix the fork choice"
+		);
+		assert_eq!(
+			body(
+				"release-v0.9.30",
+				"1a2b3c4d",
+				"gavofyork",
+				"https://github.com/paritytech/substrate/pull/1234"
+			),
+			"Cherry-pick of 1a2b3c4d onto `release-v0.9.30`.\n\nRequested by @gavofyork in https://github.com/paritytech/substrate/pull/1234."
+		);
+	}
+
+	#[test]
+	fn test_conflict_comment() {
+		assert_eq!(
+			comment(
+				"1a2b3c4d5e6f7a8b9c0d1a2b3c4d5e6f7a8b9c0d",
+				&Outcome::Conflict {
+					branch: "release-v0.9.30".to_string(),
+					files: vec!["Cargo.lock".to_string(), "src/lib.rs".to_string()],
+				}
+			),
+			"Cherry-picking 1a2b3c4d5e onto `release-v0.9.30` conflicts in:\n\n- `Cargo.lock`\n- `src/lib.rs`\n\nNo PR was opened; cherry-pick it by hand."
+		);
+		assert_eq!(
+			comment(
+				"1a2b3c4d",
+				&Outcome::Opened {
+					branch: "release-v0.9.30".to_string(),
+					html_url: "https://github.com/paritytech/substrate/pull/1300"
+						.to_string(),
+				}
+			),
+			"Opened https://github.com/paritytech/substrate/pull/1300 with 1a2b3c4d cherry-picked onto `release-v0.9.30`."
+		);
+	}
+}
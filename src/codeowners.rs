@@ -0,0 +1,388 @@
+use crate::{github::PullRequest, github_bot::GithubBot, process, Result};
+use regex::Regex;
+
+pub const CODEOWNERS_FILE: &str = ".github/CODEOWNERS";
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Owner {
+	/// `@login`
+	User(String),
+	/// `@org/team-slug`
+	Team { org: String, slug: String },
+	/// An email address; these cannot be resolved to a login and are ignored
+	/// when checking approvals.
+	Email(String),
+}
+
+impl Owner {
+	fn parse(s: &str) -> Option<Self> {
+		if let Some(name) = s.strip_prefix('@') {
+			let mut parts = name.splitn(2, '/');
+			match (parts.next(), parts.next()) {
+				(Some(login), None) if !login.is_empty() => {
+					Some(Owner::User(login.to_string()))
+				}
+				(Some(org), Some(slug))
+					if !org.is_empty() && !slug.is_empty() =>
+				{
+					Some(Owner::Team {
+						org: org.to_string(),
+						slug: slug.to_string(),
+					})
+				}
+				_ => None,
+			}
+		} else if s.contains('@') {
+			Some(Owner::Email(s.to_string()))
+		} else {
+			None
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+	pattern: String,
+	regex: Regex,
+	owners: Vec<Owner>,
+}
+
+/// The rules of a `CODEOWNERS` file, in the order they appear.
+#[derive(Debug, Clone, Default)]
+pub struct CodeOwners {
+	rules: Vec<Rule>,
+}
+
+impl CodeOwners {
+	/// Parse the contents of a `CODEOWNERS` file.  Lines that cannot be
+	/// understood are skipped.
+	pub fn parse(contents: &str) -> Self {
+		let rules = contents
+			.lines()
+			.map(str::trim)
+			.filter(|line| !line.is_empty() && !line.starts_with('#'))
+			.filter_map(|line| {
+				let mut fields = line.split_whitespace();
+				let pattern = fields.next()?;
+				let mut owners = Vec::new();
+				for field in fields {
+					if field.starts_with('#') {
+						break;
+					}
+					match Owner::parse(field) {
+						Some(owner) => owners.push(owner),
+						None => log::warn!(
+							"Ignoring invalid owner '{}' in {}",
+							field,
+							CODEOWNERS_FILE
+						),
+					}
+				}
+				Some(Rule {
+					pattern: pattern.to_string(),
+					regex: pattern_regex(pattern)?,
+					owners,
+				})
+			})
+			.collect();
+		CodeOwners { rules }
+	}
+
+	/// Owners of a path, relative to the repository root.  When several rules
+	/// match, the last one wins, and a matching rule without owners means the
+	/// path has no owners.
+	pub fn owners_of(&self, path: &str) -> &[Owner] {
+		let path = path.trim_start_matches('/');
+		self.rules
+			.iter()
+			.rev()
+			.find(|rule| rule.regex.is_match(path))
+			.map_or(&[], |rule| {
+				log::debug!("{} is owned per '{}'", path, rule.pattern);
+				&rule.owners
+			})
+	}
+
+	/// Owners of any of the given paths, without duplicates.
+	pub fn owners_of_files<'a>(
+		&self,
+		paths: impl Iterator<Item = &'a str>,
+	) -> Vec<Owner> {
+		let mut owners = Vec::new();
+		for owner in paths.flat_map(|path| self.owners_of(path)) {
+			if !owners.contains(owner) {
+				owners.push(owner.clone());
+			}
+		}
+		owners
+	}
+}
+
+fn pattern_regex(pattern: &str) -> Option<Regex> {
+	match glob_regex(pattern) {
+		Ok(regex) => Some(regex),
+		Err(e) => {
+			log::warn!(
+				"Ignoring invalid pattern '{}' in {}: {}",
+				pattern,
+				CODEOWNERS_FILE,
+				e
+			);
+			None
+		}
+	}
+}
+
+/// Translate a gitignore-style pattern into a regex matching paths relative
+/// to the repository root.
+pub(crate) fn glob_regex(pattern: &str) -> Result<Regex, regex::Error> {
+	let dir_only = pattern.ends_with('/');
+	let trimmed = pattern.trim_end_matches('/');
+	// a slash anywhere but at the end anchors the pattern to the root
+	let anchored = trimmed.contains('/');
+	let trimmed = trimmed.trim_start_matches('/');
+
+	let mut re = String::from("^");
+	if !anchored && !trimmed.starts_with("**") {
+		re.push_str("(?:.*/)?");
+	}
+	let chars = trimmed.chars().collect::<Vec<_>>();
+	let mut i = 0;
+	while i < chars.len() {
+		match chars[i] {
+			'*' if chars.get(i + 1) == Some(&'*') => {
+				if chars.get(i + 2) == Some(&'/') {
+					re.push_str("(?:.*/)?");
+					i += 3;
+				} else {
+					re.push_str(".*");
+					i += 2;
+				}
+			}
+			'*' => {
+				re.push_str("[^/]*");
+				i += 1;
+			}
+			'?' => {
+				re.push_str("[^/]");
+				i += 1;
+			}
+			c => {
+				re.push_str(&regex::escape(&c.to_string()));
+				i += 1;
+			}
+		}
+	}
+	// a pattern naming a directory covers everything inside it
+	re.push_str(if dir_only { "/.*$" } else { "(?:/.*)?$" });
+
+	Regex::new(&re)
+}
+
+/// Fetch the `CODEOWNERS` file of a repository at the given ref, if it has
+/// one.
+pub async fn fetch(
+	github_bot: &GithubBot,
+	owner: &str,
+	repo_name: &str,
+	ref_field: &str,
+) -> Result<Option<CodeOwners>> {
+	match github_bot
+		.contents(owner, repo_name, CODEOWNERS_FILE, ref_field)
+		.await
+	{
+		Ok(contents) => process::decode_contents(&contents)
+			.map(|contents| Some(CodeOwners::parse(&contents))),
+		Err(e) if e.is_not_found() => Ok(None),
+		Err(e) => Err(e),
+	}
+}
+
+/// Resolve owners to GitHub logins, expanding teams into their members.
+pub async fn resolve_logins(
+	github_bot: &GithubBot,
+	owners: &[Owner],
+) -> Result<Vec<String>> {
+	let mut logins = Vec::new();
+	for owner in owners {
+		match owner {
+			Owner::User(login) => logins.push(login.clone()),
+			Owner::Team { org, slug } => {
+				let team = github_bot.team(org, slug).await?;
+				logins.extend(
+					github_bot
+						.team_members(team.id)
+						.await?
+						.into_iter()
+						.map(|user| user.login),
+				);
+			}
+			Owner::Email(_) => {}
+		}
+	}
+	logins.sort();
+	logins.dedup();
+	Ok(logins)
+}
+
+/// Logins of the code owners of the files changed by a PR, according to the
+/// `CODEOWNERS` file of its base branch.  Returns `None` if there is no such
+/// file or it names no owners for the changed files.
+pub async fn pull_request_owners(
+	github_bot: &GithubBot,
+	owner: &str,
+	repo_name: &str,
+	pr: &PullRequest,
+) -> Result<Option<Vec<String>>> {
+	let codeowners =
+		match fetch(github_bot, owner, repo_name, &pr.base.ref_field).await? {
+			Some(codeowners) => codeowners,
+			None => return Ok(None),
+		};
+	let files = github_bot
+		.pull_request_files(owner, repo_name, pr.number)
+		.await?;
+	let owners =
+		codeowners.owners_of_files(files.iter().map(|f| f.filename.as_str()));
+	let logins = resolve_logins(github_bot, &owners).await?;
+	Ok(Some(logins).filter(|logins| !logins.is_empty()))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn user(login: &str) -> Owner {
+		Owner::User(login.to_string())
+	}
+
+	fn team(org: &str, slug: &str) -> Owner {
+		Owner::Team {
+			org: org.to_string(),
+			slug: slug.to_string(),
+		}
+	}
+
+	#[test]
+	fn test_parse_owners() {
+		let codeowners = CodeOwners::parse(
+			"
+			# comment
+			*       @default-owner @paritytech/core-devs docs@parity.io
+			/runtime/ @gavofyork  # trailing comment
+			invalid-owner-only nobody @
+			",
+		);
+		assert_eq!(codeowners.rules.len(), 3);
+		assert_eq!(
+			codeowners.rules[0].owners,
+			vec![
+				user("default-owner"),
+				team("paritytech", "core-devs"),
+				Owner::Email("docs@parity.io".to_string()),
+			]
+		);
+		assert_eq!(codeowners.rules[1].owners, vec![user("gavofyork")]);
+		assert!(codeowners.rules[2].owners.is_empty());
+	}
+
+	#[test]
+	fn test_later_rule_wins() {
+		let codeowners = CodeOwners::parse(
+			"
+			*          @default-owner
+			*.rs       @rust-dev
+			/client/   @client-dev
+			/client/unowned.rs
+			",
+		);
+		assert_eq!(codeowners.owners_of("README.md"), &[user("default-owner")]);
+		assert_eq!(codeowners.owners_of("runtime/lib.rs"), &[user("rust-dev")]);
+		assert_eq!(
+			codeowners.owners_of("client/lib.rs"),
+			&[user("client-dev")]
+		);
+		assert!(codeowners.owners_of("client/unowned.rs").is_empty());
+		assert!(CodeOwners::parse("").owners_of("README.md").is_empty());
+	}
+
+	#[test]
+	fn test_globbing() {
+		let codeowners = CodeOwners::parse(
+			"
+			/docs/*.md       @a
+			apps/            @b
+			/build/logs/     @c
+			**/tests/**      @d
+			/scripts/**/*.sh @e
+			Cargo.?ock       @f
+			/src/*/mod.rs    @g
+			",
+		);
+		let owner = |path| codeowners.owners_of(path).to_vec();
+
+		// `*` does not cross directories
+		assert_eq!(owner("docs/intro.md"), vec![user("a")]);
+		assert!(owner("docs/sub/intro.md").is_empty());
+		assert!(owner("other/docs/intro.md").is_empty());
+
+		// unanchored directories match at any depth
+		assert_eq!(owner("apps/main.rs"), vec![user("b")]);
+		assert_eq!(owner("frontend/apps/src/main.rs"), vec![user("b")]);
+		assert!(owner("apps").is_empty());
+
+		// anchored directories only match at the root
+		assert_eq!(owner("build/logs/a/b.log"), vec![user("c")]);
+		assert!(owner("x/build/logs/a.log").is_empty());
+
+		// `**` crosses directories
+		assert_eq!(owner("tests/a.rs"), vec![user("d")]);
+		assert_eq!(owner("a/b/tests/c/d.rs"), vec![user("d")]);
+		assert_eq!(owner("scripts/a.sh"), vec![user("e")]);
+		assert_eq!(owner("scripts/ci/gitlab/a.sh"), vec![user("e")]);
+		assert!(owner("scripts/a.py").is_empty());
+
+		// `?` matches a single character, unanchored file names match anywhere
+		assert_eq!(owner("Cargo.lock"), vec![user("f")]);
+		assert_eq!(owner("node/Cargo.lock"), vec![user("f")]);
+		assert!(owner("Cargo.ock").is_empty());
+		assert!(owner("Cargo.toml").is_empty());
+
+		assert_eq!(owner("src/github_bot/mod.rs"), vec![user("g")]);
+		assert!(owner("src/a/b/mod.rs").is_empty());
+
+		// special regex characters are taken literally
+		assert!(CodeOwners::parse("a+b @h").owners_of("aab").is_empty());
+		assert_eq!(CodeOwners::parse("a+b @h").owners_of("a+b"), &[user("h")]);
+	}
+
+	#[test]
+	fn test_owners_of_files() {
+		let codeowners = CodeOwners::parse(
+			"
+			*          @paritytech/core-devs
+			/runtime/  @gavofyork @paritytech/runtime
+			/client/   @paritytech/client @gavofyork
+			",
+		);
+		assert_eq!(
+			codeowners.owners_of_files(
+				vec![
+					"runtime/src/lib.rs",
+					"client/lib.rs",
+					"runtime/Cargo.toml"
+				]
+				.into_iter()
+			),
+			vec![
+				user("gavofyork"),
+				team("paritytech", "runtime"),
+				team("paritytech", "client"),
+			]
+		);
+		assert_eq!(
+			codeowners.owners_of_files(vec!["README.md"].into_iter()),
+			vec![team("paritytech", "core-devs")]
+		);
+	}
+}
@@ -0,0 +1,230 @@
+use rocksdb::DB;
+use snafu::ResultExt;
+
+use crate::{config::BotConfig, constants::*, error::*, Result};
+
+const KEY_PREFIX: &str = "command-denylist/";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AdminCommand {
+	Deny {
+		login: String,
+	},
+	Undeny {
+		login: String,
+	},
+	/// `bot admin` followed by anything else
+	Unknown,
+}
+
+pub fn parse_command(body: &str) -> Option<AdminCommand> {
+	let body = body.trim().to_lowercase();
+	let args = if body == BOT_ADMIN {
+		""
+	} else {
+		body.strip_prefix(&format!("{} ", BOT_ADMIN))?
+	};
+	let mut words = args.split_whitespace();
+	let command = match (words.next(), words.next(), words.next()) {
+		(Some("deny"), Some(login), None) => AdminCommand::Deny {
+			login: login.trim_start_matches('@').to_string(),
+		},
+		(Some("undeny"), Some(login), None) => AdminCommand::Undeny {
+			login: login.trim_start_matches('@').to_string(),
+		},
+		_ => AdminCommand::Unknown,
+	};
+	Some(command)
+}
+
+fn db_key(login: &str) -> String {
+	format!("{}{}", KEY_PREFIX, login.to_lowercase())
+}
+
+/// Deny `login` any command until `undeny`, without touching the
+/// configuration.
+pub fn deny(db: &DB, login: &str) -> Result<()> {
+	db.put(db_key(login).as_bytes(), b"").context(Db)
+}
+
+/// Lift a denial made with `deny`.  Returns whether there was one.
+pub fn undeny(db: &DB, login: &str) -> Result<bool> {
+	let key = db_key(login);
+	let existed = db.get(key.as_bytes()).context(Db)?.is_some();
+	db.delete(key.as_bytes()).context(Db)?;
+	Ok(existed)
+}
+
+fn denied_at_runtime(db: &DB, login: &str) -> Result<bool> {
+	Ok(db.get(db_key(login).as_bytes()).context(Db)?.is_some())
+}
+
+fn contains(logins: &[String], login: &str) -> bool {
+	logins.iter().any(|l| l.eq_ignore_ascii_case(login))
+}
+
+/// The allowlist of the most specific command `command` starts with, if any.
+fn command_allowlist<'a>(
+	config: &'a BotConfig,
+	command: &str,
+) -> Option<&'a Vec<String>> {
+	config
+		.command_allowlists
+		.iter()
+		.filter(|(prefix, _)| {
+			command == prefix.as_str()
+				|| command.starts_with(&format!("{} ", prefix))
+		})
+		.max_by_key(|(prefix, _)| prefix.len())
+		.map(|(_, logins)| logins)
+}
+
+/// Check that `login` may use `command`, before any other authorization:
+/// the denylists win over the allowlists, which are checked before the
+/// permission of the user on the repository.  The error does not say which
+/// list the user is on, if any.
+pub fn check(
+	config: &BotConfig,
+	db: &DB,
+	login: &str,
+	command: &str,
+) -> Result<()> {
+	let allowlist =
+		command_allowlist(config, command).unwrap_or(&config.command_allowlist);
+	let reason = if contains(&config.command_denylist, login) {
+		Some("is in COMMAND_DENYLIST")
+	} else if denied_at_runtime(db, login)? {
+		Some("was denied with `bot admin deny`")
+	} else if !allowlist.is_empty() && !contains(allowlist, login) {
+		Some("is not in the allowlist")
+	} else {
+		None
+	};
+	match reason {
+		Some(reason) => {
+			log::info!("Refusing `{}` from {}, who {}", command, login, reason);
+			Err(Error::CommandDenied {
+				login: login.to_string(),
+			})
+		}
+		None => Ok(()),
+	}
+}
+
+/// Whether `login` may use `bot admin`.
+pub fn is_admin(config: &BotConfig, login: &str) -> bool {
+	contains(&config.command_admins, login)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::db::TempDb;
+
+	fn db(name: &str) -> TempDb {
+		TempDb::new(&format!("command-access-{}", name))
+	}
+
+	fn logins(logins: &[&str]) -> Vec<String> {
+		logins.iter().map(|l| l.to_string()).collect()
+	}
+
+	fn allowed(
+		config: &BotConfig,
+		db: &DB,
+		login: &str,
+		command: &str,
+	) -> bool {
+		match check(config, db, login, command) {
+			Ok(()) => true,
+			Err(Error::CommandDenied { login: denied }) => {
+				assert_eq!(denied, login);
+				false
+			}
+			Err(e) => panic!("unexpected error: {}", e),
+		}
+	}
+
+	#[test]
+	fn test_parse_command() {
+		assert_eq!(
+			parse_command(" bot admin deny @Mallory\n"),
+			Some(AdminCommand::Deny {
+				login: "mallory".to_string()
+			})
+		);
+		assert_eq!(
+			parse_command("bot admin undeny mallory"),
+			Some(AdminCommand::Undeny {
+				login: "mallory".to_string()
+			})
+		);
+		assert_eq!(parse_command("bot admin"), Some(AdminCommand::Unknown));
+		assert_eq!(
+			parse_command("bot admin deny a b"),
+			Some(AdminCommand::Unknown)
+		);
+		assert_eq!(parse_command("bot administer"), None);
+		assert_eq!(parse_command("bot merge"), None);
+	}
+
+	#[test]
+	fn test_precedence() {
+		let db = db("precedence");
+		let config = BotConfig {
+			command_denylist: logins(&["mallory"]),
+			command_allowlist: logins(&["alice", "Mallory"]),
+			..BotConfig::default()
+		};
+		// denylist over allowlist
+		assert!(!allowed(&config, &db, "mallory", AUTO_MERGE_REQUEST));
+		// allowlist over the permission checks which follow
+		assert!(allowed(&config, &db, "Alice", AUTO_MERGE_REQUEST));
+		assert!(!allowed(&config, &db, "bob", AUTO_MERGE_REQUEST));
+		// no lists leaves it to the permission checks
+		assert!(allowed(&BotConfig::default(), &db, "bob", REBASE));
+	}
+
+	#[test]
+	fn test_command_allowlists() {
+		let db = db("commands");
+		let mut config = BotConfig {
+			command_denylist: logins(&["mallory"]),
+			..BotConfig::default()
+		};
+		config
+			.command_allowlists
+			.insert(AUTO_MERGE_FORCE.to_string(), logins(&["lead"]));
+		config
+			.command_allowlists
+			.insert(BURNIN_REQUEST.to_string(), logins(&["ops", "mallory"]));
+		assert!(allowed(&config, &db, "lead", AUTO_MERGE_FORCE));
+		assert!(!allowed(&config, &db, "alice", AUTO_MERGE_FORCE));
+		assert!(allowed(&config, &db, "alice", AUTO_MERGE_REQUEST));
+		assert!(allowed(&config, &db, "ops", "bot burnin\n```toml\n```"));
+		assert!(allowed(&config, &db, "ops", BURNIN_STATUS));
+		// the most specific command wins
+		config
+			.command_allowlists
+			.insert(BURNIN_STATUS.to_string(), logins(&["alice"]));
+		assert!(!allowed(&config, &db, "ops", BURNIN_STATUS));
+		assert!(allowed(&config, &db, "alice", BURNIN_STATUS));
+		assert!(!allowed(&config, &db, "alice", BURNIN_REQUEST));
+		assert!(!allowed(&config, &db, "mallory", BURNIN_REQUEST));
+	}
+
+	#[test]
+	fn test_runtime_denylist() {
+		let db = db("runtime");
+		let config = BotConfig {
+			command_allowlist: logins(&["alice"]),
+			..BotConfig::default()
+		};
+		assert!(allowed(&config, &db, "alice", AUTO_MERGE_REQUEST));
+		deny(&db, "Alice").unwrap();
+		assert!(!allowed(&config, &db, "alice", AUTO_MERGE_REQUEST));
+		assert!(undeny(&db, "alice").unwrap());
+		assert!(!undeny(&db, "alice").unwrap());
+		assert!(allowed(&config, &db, "alice", AUTO_MERGE_REQUEST));
+	}
+}
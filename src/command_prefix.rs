@@ -0,0 +1,157 @@
+/// The prefix commands are known by in the code, eg. `bot merge`, whichever
+/// prefixes they are commented with.
+pub const CANONICAL: &str = "bot";
+
+/// The comment as a command starting with `bot`, if it starts with one of the
+/// configured prefixes, eg. `/merge` for `bot merge` with the prefix `/`.  A
+/// prefix ending in a letter or digit is a word of its own.  The rest of the
+/// comment keeps its case.
+pub fn canonical(
+	body: &str,
+	prefix: &str,
+	aliases: &[String],
+) -> Option<String> {
+	let body = body.trim();
+	std::iter::once(prefix)
+		.chain(aliases.iter().map(String::as_str))
+		.filter(|prefix| !prefix.is_empty())
+		.find_map(|prefix| {
+			let head = body.get(..prefix.len())?;
+			let rest = body.get(prefix.len()..)?;
+			let separated = !prefix.ends_with(|c: char| c.is_alphanumeric())
+				|| rest.starts_with(char::is_whitespace);
+			let rest = rest.trim_start();
+			if head.eq_ignore_ascii_case(prefix)
+				&& separated && !rest.is_empty()
+			{
+				Some(format!("{} {}", CANONICAL, rest))
+			} else {
+				None
+			}
+		})
+}
+
+/// What a command starts with under `prefix`: the prefix, and a space if it
+/// is a word.
+fn lead(prefix: &str) -> String {
+	if prefix.ends_with(|c: char| c.is_alphanumeric()) {
+		format!("{} ", prefix)
+	} else {
+		prefix.to_string()
+	}
+}
+
+/// A command, as known in the code, written with `prefix`.
+pub fn render(command: &str, prefix: &str) -> String {
+	match command.strip_prefix(CANONICAL) {
+		Some(rest) if rest.starts_with(' ') => {
+			format!("{}{}", lead(prefix), rest.trim_start())
+		}
+		_ => command.to_string(),
+	}
+}
+
+/// Text quoting commands as known in the code, eg. "undo `bot freeze`",
+/// with them written with `prefix`.
+pub fn render_quoted(text: &str, prefix: &str) -> String {
+	text.replace(&format!("`{} ", CANONICAL), &format!("`{}", lead(prefix)))
+}
+
+/// Whether `prefix` can be configured: it has no whitespace, and so can be
+/// told apart from the command.
+pub fn validate(prefix: &str) -> std::result::Result<(), String> {
+	if prefix.is_empty() || prefix.contains(char::is_whitespace) {
+		Err(format!("`{}` is not a valid command prefix", prefix))
+	} else {
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_canonical() {
+		let aliases = vec!["processbot".to_string(), "/".to_string()];
+		for (body, expected) in &[
+			("bot merge", Some("bot merge")),
+			(
+				" Bot Merge Force CI is down\n",
+				Some("bot Merge Force CI is down"),
+			),
+			("processbot merge", Some("bot merge")),
+			(
+				"ProcessBot rebase onto release-v0.9.30",
+				Some("bot rebase onto release-v0.9.30"),
+			),
+			("/merge", Some("bot merge")),
+			("/ merge", Some("bot merge")),
+			("/burnin\n```toml\n```", Some("bot burnin\n```toml\n```")),
+			("bot", None),
+			("/", None),
+			("robot merge", None),
+			("botmerge", None),
+			("processbotmerge", None),
+			("LGTM", None),
+			("", None),
+		] {
+			assert_eq!(
+				canonical(body, "bot", &aliases).as_deref(),
+				*expected,
+				"{:?}",
+				body
+			);
+		}
+		// commands under a prefix not configured are not commands
+		assert_eq!(canonical("bot merge", "processbot", &[]), None);
+		assert_eq!(canonical("/merge", "processbot", &[]), None);
+		assert_eq!(
+			canonical("processbot merge", "processbot", &[]).as_deref(),
+			Some("bot merge")
+		);
+		assert_eq!(canonical("!merge", "!", &[]).as_deref(), Some("bot merge"));
+	}
+
+	#[test]
+	fn test_render() {
+		assert_eq!(render("bot merge force", "bot"), "bot merge force");
+		assert_eq!(
+			render("bot merge force", "processbot"),
+			"processbot merge force"
+		);
+		assert_eq!(render("bot merge force", "/"), "/merge force");
+		assert_eq!(render("bot burnin", "!"), "!burnin");
+		// not a command
+		assert_eq!(render("robot merge", "/"), "robot merge");
+		for prefix in &["bot", "processbot", "/", "!"] {
+			assert_eq!(
+				canonical(&render("bot rebase", prefix), prefix, &[])
+					.as_deref(),
+				Some("bot rebase")
+			);
+		}
+	}
+
+	#[test]
+	fn test_render_quoted() {
+		assert_eq!(
+			render_quoted("undo `bot freeze`, not `robot freeze`", "/"),
+			"undo `/freeze`, not `robot freeze`"
+		);
+		assert_eq!(
+			render_quoted("undo `bot freeze`", "processbot"),
+			"undo `processbot freeze`"
+		);
+	}
+
+	#[test]
+	fn test_validate() {
+		for prefix in &["bot", "/", "processbot", "@processbot"] {
+			assert_eq!(validate(prefix), Ok(()));
+		}
+		for prefix in &["", "process bot", "bot\n"] {
+			assert!(validate(prefix).is_err(), "{:?}", prefix);
+		}
+	}
+}
@@ -0,0 +1,236 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{http::GithubApiError, Result};
+
+/// How long comments are paused after one is rate limited, if GitHub does not
+/// say.
+const DEFAULT_PAUSE: Duration = Duration::from_secs(60);
+
+/// Pauses after which a comment is given up on.
+const MAX_PAUSES: u32 = 3;
+
+/// Ends a comment's turn when dropped.
+type Turn = oneshot::Sender<()>;
+
+/// Posts comments one at a time, at least `min_delay` apart and in the order
+/// they were sent, so that a burst of them, eg. when every pending merge
+/// fails at once, does not trip GitHub's secondary rate limit.  A comment
+/// tripping it all the same holds up those after it for as long as GitHub
+/// asks, and is then sent again.
+pub struct CommentQueue {
+	min_delay: Duration,
+	/// to the drain task, which is started with the first comment
+	sender: Mutex<Option<mpsc::UnboundedSender<oneshot::Sender<Turn>>>>,
+	/// comments waiting for their turn
+	depth: AtomicU64,
+	/// pauses for a rate limit, since starting
+	pauses: AtomicU64,
+}
+
+impl CommentQueue {
+	pub fn new(min_delay: Duration) -> Self {
+		Self {
+			min_delay,
+			sender: Mutex::new(None),
+			depth: AtomicU64::new(0),
+			pauses: AtomicU64::new(0),
+		}
+	}
+
+	pub fn depth(&self) -> u64 {
+		self.depth.load(Ordering::Relaxed)
+	}
+
+	pub fn pauses(&self) -> u64 {
+		self.pauses.load(Ordering::Relaxed)
+	}
+
+	/// Wait until the comments sent before are posted.
+	async fn turn(&self) -> Option<Turn> {
+		let (waiter, turn) = oneshot::channel();
+		{
+			let mut sender = self.sender.lock().expect("comment queue");
+			let waiter = match &*sender {
+				Some(sender) => sender
+					.send(waiter)
+					.err()
+					.map(|mpsc::error::SendError(waiter)| waiter),
+				None => Some(waiter),
+			};
+			// the first comment, or the drain task's runtime is gone
+			if let Some(waiter) = waiter {
+				let (new_sender, waiters) = mpsc::unbounded_channel();
+				tokio::spawn(drain(waiters, self.min_delay));
+				let _ = new_sender.send(waiter);
+				*sender = Some(new_sender);
+			}
+		}
+		self.depth.fetch_add(1, Ordering::Relaxed);
+		let turn = turn.await.ok();
+		self.depth.fetch_sub(1, Ordering::Relaxed);
+		turn
+	}
+
+	/// Post a comment with `attempt` once its turn comes.
+	pub async fn send<T, F, Fut>(&self, mut attempt: F) -> Result<T>
+	where
+		F: FnMut() -> Fut,
+		Fut: Future<Output = Result<T>>,
+	{
+		let _turn = self.turn().await;
+		let mut pauses = 0;
+		loop {
+			match attempt().await {
+				Err(e) if e.is_rate_limited() && pauses < MAX_PAUSES => {
+					let pause = e
+						.github_api_error()
+						.and_then(GithubApiError::retry_after)
+						.unwrap_or(DEFAULT_PAUSE);
+					log::warn!("Pausing comments for {:?}: {}", pause, e);
+					self.pauses.fetch_add(1, Ordering::Relaxed);
+					tokio::time::delay_for(pause).await;
+					pauses += 1;
+				}
+				res => return res,
+			}
+		}
+	}
+}
+
+/// Give each waiting comment its turn, in order, and wait `min_delay` after
+/// it ends.
+async fn drain(
+	mut waiters: mpsc::UnboundedReceiver<oneshot::Sender<Turn>>,
+	min_delay: Duration,
+) {
+	while let Some(waiter) = waiters.recv().await {
+		let (turn, ended) = oneshot::channel();
+		if waiter.send(turn).is_err() {
+			// the comment was dropped while waiting
+			continue;
+		}
+		let _ = ended.await;
+		tokio::time::delay_for(min_delay).await;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::error::Error;
+	use std::sync::Arc;
+	use std::time::Instant;
+
+	fn rate_limited(retry_after: Duration) -> Error {
+		Error::Response {
+			status: reqwest::StatusCode::FORBIDDEN,
+			error: GithubApiError::RateLimited {
+				body: crate::http::ErrorBody {
+					message: "You have exceeded a secondary rate limit."
+						.to_string(),
+					..Default::default()
+				},
+				retry_after: Some(retry_after),
+			},
+		}
+	}
+
+	#[test]
+	fn test_burst_is_ordered_and_paced() {
+		let queue = Arc::new(CommentQueue::new(Duration::from_millis(20)));
+		let posted = Arc::new(Mutex::new(Vec::new()));
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		let started = Instant::now();
+		rt.block_on(futures::future::join_all((0..5).map(|comment| {
+			let queue = Arc::clone(&queue);
+			let posted = Arc::clone(&posted);
+			async move {
+				queue
+					.send(|| async {
+						posted.lock().unwrap().push((comment, Instant::now()));
+						Ok(())
+					})
+					.await
+			}
+		})))
+		.into_iter()
+		.collect::<Result<Vec<()>>>()
+		.unwrap();
+		let posted = posted.lock().unwrap();
+		assert_eq!(
+			posted
+				.iter()
+				.map(|(comment, _)| *comment)
+				.collect::<Vec<_>>(),
+			vec![0, 1, 2, 3, 4]
+		);
+		for pair in posted.windows(2) {
+			assert!(
+				pair[1].1 - pair[0].1 >= Duration::from_millis(20),
+				"{:?}",
+				pair[1].1 - pair[0].1
+			);
+		}
+		assert!(started.elapsed() >= Duration::from_millis(80));
+		assert_eq!(queue.depth(), 0);
+	}
+
+	#[test]
+	fn test_rate_limit_pauses_queue() {
+		let queue = Arc::new(CommentQueue::new(Duration::from_millis(1)));
+		let posted = Arc::new(Mutex::new(Vec::new()));
+		let attempts = Arc::new(AtomicU64::new(0));
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		let results =
+			rt.block_on(futures::future::join_all((0..3).map(|comment| {
+				let queue = Arc::clone(&queue);
+				let posted = Arc::clone(&posted);
+				let attempts = Arc::clone(&attempts);
+				async move {
+					queue
+						.send(|| async {
+							// the first attempt of the first comment
+							if attempts.fetch_add(1, Ordering::Relaxed) == 0 {
+								return Err(rate_limited(
+									Duration::from_millis(100),
+								));
+							}
+							posted
+								.lock()
+								.unwrap()
+								.push((comment, Instant::now()));
+							Ok(())
+						})
+						.await
+				}
+			})));
+		assert!(results.iter().all(|result| result.is_ok()));
+		let posted = posted.lock().unwrap();
+		// sent again before the others
+		assert_eq!(
+			posted
+				.iter()
+				.map(|(comment, _)| *comment)
+				.collect::<Vec<_>>(),
+			vec![0, 1, 2]
+		);
+		assert_eq!(attempts.load(Ordering::Relaxed), 4);
+		assert_eq!(queue.pauses(), 1);
+	}
+
+	#[test]
+	fn test_gives_up_after_pauses() {
+		let queue = CommentQueue::new(Duration::from_millis(1));
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		let result = rt.block_on(queue.send(|| async {
+			Err::<(), _>(rate_limited(Duration::from_millis(1)))
+		}));
+		assert!(result.unwrap_err().is_rate_limited());
+		assert_eq!(queue.pauses(), u64::from(MAX_PAUSES));
+	}
+}
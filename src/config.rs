@@ -1,12 +1,28 @@
 /*
 Processbot looks for configuration variables in `.env` in the root directory. Eg. `MATRIX_USER=annoying_bot@parity.io`.
 
+`CONFIG_FILE`: Optional path to a TOML file with the variables below as lower-case keys, eg. `db_path = "/data/db"`. Keys may be grouped in tables, so
+`[matrix] homeserver = "..."` sets `MATRIX_HOMESERVER`, and lists may be written as arrays. String values may refer to environment variables as `${NAME}`, eg.
+`webhook_secret = "${WEBHOOK_SECRET_VALUE}"`. Environment variables take precedence over keys in the file.
+
+The bot tells configurations apart by a short hash of the variables it read, leaving out secrets: those whose names contain `SECRET` or `TOKEN`. It is
+shown at `/health` and by `bot version`.
+
 `PRIVATE_KEY_PATH`: Path to the private key associated with the installed Processbot app.
 
 `GITHUB_APP_ID`: App ID associated with the installed Processbot app.
 
+`INSTALLATION_LOGIN`: Account the app is installed on whose token is used for requests not about a repository or an organization. The app may be
+installed on other organizations too: requests about a repository or an organization use the token of the installation on its owner, and the bot
+remembers the installations GitHub tells it about with `installation` webhooks.
+
 `DB_PATH`: Path to an existing `rocksdb` database or that path at which a database will be created.
 
+`BACKUP_DIR`: Optional directory to which the database is backed up as JSON on startup and every `BACKUP_INTERVAL_SECS` (default 86400), keeping
+the latest `BACKUP_KEEP` backups (default 7). Restore one with `parity-processbot db restore`.
+
+`STATS_RETENTION_DAYS`: Days for which it is kept how requested merges ended, for `bot stats` and `/admin/stats` (default 365).
+
 `MAIN_TICK_SECS`: Seconds between cycles of the main bot loop.
 
 `BAMBOO_TOKEN`: API Key used to access the BambooHR API.
@@ -15,6 +31,29 @@ Processbot looks for configuration variables in `.env` in the root directory. Eg
 
 `MATRIX_SILENT`: If `true`, do not send Matrix notifications.
 
+`ALLOW_DEGRADED_START`: If `true`, keep running when the startup checks of the GitHub App, the Gitlab token or the Matrix session fail, instead of exiting
+(default `false`). Either way the results are served at `/health`.
+
+`READINESS_MAX_PENDING_WEBHOOKS`: `/readyz` reports the bot as not ready while more webhooks than this are waiting to be handled (default 20).
+
+`READINESS_CHECK_SECS`: Seconds between checks of whether the bot is ready, ie. that the database is writable and the last GitHub token refresh succeeded
+(default 10).
+
+`AUDIT_TOKEN`: Bearer token required to read the audit log of privileged bot actions at `/audit`. If unset, `/audit` is not served.
+
+`ADMIN_TOKEN`: Bearer token required to list pending merges with `GET /admin/queue`, cancel one with `DELETE /admin/queue/<owner>/<repo>/<number>`
+and read merge statistics with `GET /admin/stats?period=30d`. If unset, neither is served.
+
+`HTTP_MAX_RETRIES`: How many times a GitHub request which timed out or got a 5xx response is sent again (default 5). GET requests are retried, as are
+others which are safe to repeat, eg. closing an issue.
+
+`HTTP_RETRY_BACKOFF_MS`: Milliseconds before the first retry of a request, doubling for each retry after it, with jitter (default 500).
+
+`HTTP_RETRY_BUDGET_SECS`: Seconds after which a request is no longer retried, counted from its first attempt (default 30).
+
+`COMMENT_DELAY_MS`: Minimum milliseconds between two comments the bot posts, which are posted one at a time and in order (default 1000).
+A comment hitting GitHub's rate limit holds up the others for as long as GitHub asks, then is posted again.
+
 `MATRIX_HOMESERVER`: Matrix homeserver.
 
 `MATRIX_ACCESS_TOKEN`: Matrix access token.
@@ -45,23 +84,556 @@ pull request author or publicly to the default channel if the author's Matrix ha
 
 `TEST_REPO_NAME`: Name of a Github repository to be used for testing.
 
+`PROCESS_CACHE_TTL`: Seconds before a cached `Process.json` is fetched again (default 600).
+
+`PROCESS_MATCHING`: How pull requests are matched to `Process.json` entries: `columns-then-labels` (default), `columns` or `labels`.
+
+`REVIEW_TEAMS`: Comma-separated slugs of the teams whose approvals count towards `MIN_REVIEWERS` (default `core-devs`).
+
+`LEAD_TEAMS`: Comma-separated slugs of the teams whose members may merge without the approvals of the review teams (default `substrateteamleads`). If empty, nobody can.
+
+`FORCE_MERGE_TEAMS`: Comma-separated slugs of the teams whose members may merge without waiting for checks with `bot merge force` (default
+`substrateteamleads`). If empty, nobody can.
+
+`FORCE_MERGE_REQUIRE_REASON`: If `true`, `bot merge force` must be followed by a justification, eg. `bot merge force CI is down` (default `false`).
+
+`INSUBSTANTIAL_LABELS`: Comma-separated label names; a pull request with a label containing any of them needs only `INSUBSTANTIAL_MIN_REVIEWERS` approvals (default `insubstantial`).
+
+`INSUBSTANTIAL_MIN_REVIEWERS`: Approvals from the review teams needed by an insubstantial pull request (default 1).
+
+`MIN_REVIEWERS`, `REVIEW_TEAMS`, `LEAD_TEAMS`, `INSUBSTANTIAL_LABELS`, `INSUBSTANTIAL_MIN_REVIEWERS`, `BLOCKED_LABELS`, `ALLOWED_BASE_BRANCHES`, `IGNORED_CHECKS`, `MERGE_METHOD`, `REBASE_STRATEGY`, `NATIVE_MERGE_QUEUE` and `MERGE_GATE_STATUS` can be
+overridden per repository in a `.processbot.toml` on its default branch.
+
+`ORG_OVERRIDES`: The keys of `.processbot.toml` overridden for all the repositories of an organization, which a repository's own file overrides in
+turn. In the configuration file, eg.
+
+```toml
+[[org_overrides]]
+org = "paritytech-stg"
+min_reviewers = 1
+merge_method = "merge"
+```
+
+or the same as an inline TOML array in the environment variable.
+
+`BLOCKED_LABELS`: Comma-separated labels which prevent a pull request from being merged (default none).
+
+`ALLOWED_BASE_BRANCHES`: Comma-separated patterns of the base branches pull requests may be merged into, in which `*` stands for any
+characters, eg. `master,release-*` (default any branch).
+
+`IGNORED_CHECKS`: Comma-separated names of checks and status contexts which are not waited for before merging (default none).
+
+`MERGE_METHOD`: How pull requests are merged: `squash` (default), `merge` or `rebase`.
+
+`CONFIRM_MERGE_LINES`: Lines changed, added and deleted, past which `bot merge` must be confirmed with `bot merge confirm` by whoever
+requested it (default 0, no limit). `bot merge force` needs no confirmation.
+
+`CONFIRM_MERGE_FILES`: Files changed past which `bot merge` must be confirmed likewise (default 0, no limit).
+
+`CONFIRM_MERGE_WINDOW`: Seconds within which a merge must be confirmed, unless the pull request is pushed to first (default 600).
+
+`NATIVE_MERGE_QUEUE`: What the bot does with a pull request which GitHub's own auto-merge or merge queue is to merge: `refuse` (default)
+refuses `bot merge` and drops a pending merge once auto-merge is enabled, `proceed` merges all the same.
+
+`MERGE_GATE_STATUS`: If `true`, pull requests get a `processbot/merge-gate` status saying whether the bot would merge them, updated as they are
+reviewed or labelled (default `false`). `bot check` updates it either way.
+
+`REBASE_STRATEGY`: How `bot rebase` updates the head branch: `merge-commit` (default) merges the default branch of the repository into it, `rebase` rebases it onto that branch and force-pushes it.
+
+`REBASE_CONSENT`: Comma-separated logins of PR authors whose branches may be rebased with the `rebase` strategy at the request of others (default none). Otherwise only the author may ask for it.
+
+`COMMAND_PREFIX`: The word or sign commands start with (default `bot`), eg. `processbot` for `processbot merge`, or `/` for `/merge`.
+Comments starting with another prefix are ignored.
+
+`COMMAND_ALIASES`: Comma-separated prefixes commands may start with besides `COMMAND_PREFIX` (default none).
+
+`COMMAND_COOLDOWNS`: Comma-separated `command=seconds` pairs: a command commented again on the same pull request within its seconds of being
+run, eg. posted twice, is skipped, and the comment gets an :eyes: reaction (default `bot rebase=30,bot merge=30,bot burnin status=5`). A pair
+covers the commands starting with it, eg. `bot rebase onto <branch>`. `bot merge cancel` and `bot burnin cancel` are never skipped.
+
+`COMPANION_PLACEHOLDERS`: Comma-separated PR numbers which are never taken for a companion, as PR templates use them in examples, eg.
+`companion: paritytech/polkadot#123` (default `123`).
+
+`COMPANION_BRANCHES`: Comma-separated `repo=branch` pairs of the branches other than its default one which a companion in `repo` may target,
+eg. `polkadot=release-*`, where `*` stands for any characters (default none). A companion targeting another branch, or closed already, is not
+updated, and the Substrate PR says why.
+
+`AUTO_UPDATE_LABEL`: Label which makes the bot update the branch of a pull request whenever its base branch moves (default `C1-auto-update`). If empty, no pull request is updated.
+
+`AUTO_UPDATE_INTERVAL`: Minimum seconds between two updates of the same pull request (default 600).
+
+`PATH_LABELS_OPT_OUT`: Label which stops the bot labelling a pull request by the paths it changes, as configured with `path_labels` in a
+repository's `.processbot.toml` (default `no-path-labels`).
+
+`REVIEW_REQUEST_OPT_OUT`: Label which stops the bot asking the process owners of a pull request for their review when it is labelled
+or added to a project column (default `no-review-request`).
+
+`SIZE_LABELS`: Comma-separated `label=lines` pairs, eg. `size/XS=0,size/S=10,size/M=100,size/L=500,size/XL=1000`. A pull request
+changing at least `lines` lines, and fewer than the next pair's, gets `label` when it is opened or pushed to, and loses the label
+of its previous size. If empty, pull requests are not labelled by size.
+
+`SIZE_IGNORED_FILES`: Comma-separated gitignore-style patterns of files whose changes do not count towards the size of a pull request
+(default `Cargo.lock`).
+
+`WELCOME_TEMPLATE`: Path of a file on the default branch of a repository to welcome first-time contributors with, when they open a
+pull request, once per author. `{author}`, `{owner}` and `{repo}` in it are filled in. If unset, nobody is welcomed.
+
+`AUTOMERGE_AUTHORS`: Comma-separated logins of trusted PR authors, eg. `dependabot[bot]`, whose pull requests are merged without a
+`bot merge` once their checks pass, when they only change files matching `AUTOMERGE_FILES` (default none).
+
+`AUTOMERGE_FILES`: Comma-separated gitignore-style patterns of the files pull requests by `AUTOMERGE_AUTHORS` may change to be merged
+automatically (default `Cargo.lock,Cargo.toml`).
+
+`AUTOMERGE_IDENTITY`: Login the bot requests the automatic merges as, eg. in its comments and audit log (default `parity-processbot`).
+
+`BOT_LOGIN`: Login of the GitHub App's bot user (default `parity-processbot[bot]`). Comments it posts, and check runs it sets on commits it
+pushed, are not handled when their webhooks come back.
+
+`REPO_CONFIG_CACHE_TTL`: Seconds before a cached `.processbot.toml` is fetched again (default 600).
+
+`PROCESS_MATCHING_REPOS`: Comma-separated `repo=mode` pairs overriding `PROCESS_MATCHING` for individual repositories, eg. `polkadot=labels,substrate=columns`.
+
 `BURNIN_GITLAB_HOST`: Name of the Gitlab server used for burn-in automation.
 
 `BURNIN_GITLAB_PROJECT`: Name of the Gitlab project to which burn-in requests are submitted.
 
 `BURNIN_GITLAB_TOKEN`: Authentication token for the Gitlab server at BURNIN_GITLAB_HOST.
 
+`BURNIN_GITLAB_WEBHOOK_SECRET`: Secret token configured on the Gitlab webhook for pipeline and job events, sent in the `X-Gitlab-Token` header. Gitlab webhooks are all rejected while it is empty.
+
 `BURNIN_ROOM_ID`: Matrix room ID for notifications about burn-in requests
+
+`BURNIN_ALLOWED_NODE_TYPES`: Comma-separated node types which may be requested for a burn-in (default `fullnode`).
+
+`BURNIN_MAX_FULLNODES`, `BURNIN_MAX_SENTRIES`, `BURNIN_MAX_VALIDATORS`: Maximum number of nodes of each type per burn-in (defaults 4, 0 and 0).
+
+`BURNIN_OPS_TEAM`: Optional slug of a Github team whose members may exceed the burn-in limits.
+
+`MERGE_FREEZES`: Periods during which pull requests of a repository cannot be merged, except with `bot merge force` by a member of a lead team. In the
+configuration file, eg.
+
+```toml
+[[merge_freezes]]
+repo = "polkadot"
+branch = "release-*" # optional, all branches if unset
+start = "2020-11-01T00:00:00Z" # optional
+end = "2020-11-08T00:00:00Z" # or `until_further_notice = true`
+reason = "Release v0.8.26 is being cut"
+```
+
+or the same as an inline TOML array in the environment variable. Team leads can also freeze a repository with `bot freeze <reason>`.
+
+`PERFORMANCE_GATES`: Repositories whose pull requests are benchmarked against the default branch before `bot merge` merges them. A merge is aborted if the
+benchmark average grows by more than `regression_threshold` (default 2); if the benchmark cannot be run within `timeout` seconds (default 3600), the merge
+goes ahead. In the configuration file, eg.
+
+```toml
+[[performance_gates]]
+repo = "substrate"
+enabled = true # optional
+benchmark_command = "cargo run --release -p node-bench --quiet -- node::import::wasm::sr25519::transfer_keep_alive::rocksdb::medium --json"
+regression_threshold = 2.0 # optional
+timeout = 3600 # optional
+```
+
+or the same as an inline TOML array in the environment variable. Team leads can skip the benchmark with `bot merge skip-bench`. The pull request is
+compared with its merge base on the default branch, whose results are cached.
+
+`BENCH_CACHE_TTL`: Seconds before the cached benchmark results of a merge base are run again (default 604800, a week).
+
+`BENCH_CACHE_MAX_ENTRIES`: How many cached benchmark results of merge bases are kept (default 100).
+
+`DRY_RUN`: If `true`, do not merge, rebase, update companions or commit to Gitlab, but log what would have been done (default `false`).
+
+`DRY_RUN_COMMENT`: If `true` during a dry run, also post what would have been done as a comment on the PR (default `false`).
+
+`ERROR_REPORTING_DSN`: Optional Sentry DSN, eg. `https://<key>@sentry.example.com/<project>`. Errors which are not explained on a pull request, and panics,
+are reported there, tagged with the repository, pull request, event type, delivery id and bot version.
+
+`RECORD_PAYLOADS_DIR`: Optional directory to which each verified GitHub webhook is written, with signatures and tokens removed, for replaying with
+`parity-processbot replay`.
+
+`ERROR_COMMENT_WINDOW`: Seconds during which the same error is not commented on the same pull request again, eg. when redundant status webhooks
+fail a merge repeatedly (default 3600).
+
+`FORCE_MERGE_ERROR_COMMENT_WINDOW`: As `ERROR_COMMENT_WINDOW`, for errors handling `bot merge force` (default 0, ie. always comment).
+
+`CHECKS_DEBOUNCE_SECS`: Seconds during which further status and check webhooks for a commit whose checks were just evaluated are coalesced, and
+evaluated once more when they are over, rather than each evaluating the checks again (default 5; 0 evaluates them for each).
+
+`TEMPLATES_DIR`: Optional directory of texts the bot posts, overriding its built-in ones, each in a file named after its template, eg.
+`burnin_prompt.md`. The templates are `burnin_prompt`, `burnin_limits_exceeded`, `burnin_submit_failed`, `burnin_cancel_failed`, `process_help`,
+`approval_reviewers` and `approval_help`. `{{name}}` in a text is replaced by the value of the placeholder `name`: any template may use
+`{{faq_url}}`, `{{burnin_contact}}` and `{{min_reviewers}}`, and each has its own, eg. `{{org}}` in `burnin_prompt`; see `src/templates.rs`. A file
+naming no template, or using a placeholder its template is not rendered with, is a configuration error.
+
+`FAQ_URL`: Where the bot's comments point to for how the process applies (default the FAQ in the README of the bot).
+
+`BURNIN_CONTACT`: Whom the burn-in comments tell to contact for what automation does not cover (default `Staking Ops on Matrix`).
+
+`SCRATCH_DIR`: Directory the bot's clones of repositories are kept in, for companion updates, rebases, backports and performance gates
+(default the working directory).
+
+`SCRATCH_MIN_FREE_MB`: Megabytes which must be free in `SCRATCH_DIR` before a clone is worked on (default 2048).
+
+`GIT_TIMEOUT_SECS`, `CARGO_TIMEOUT_SECS`: Seconds after which a `git` or `cargo` command run on a clone is killed, along with anything it
+started (defaults 600 and 1800).  The benchmark command of a performance gate is killed after `CARGO_TIMEOUT_SECS` too.
+
+`REQUIRED_PERMISSION`: Permission on a repository needed to command the bot on its pull requests: `read`, `write` (default) or `admin`. If the
+permission cannot be read, membership of the organization is required instead.
+
+`COMMAND_DENYLIST`: Comma-separated logins which may not command the bot at all, whatever their permission.
+
+`COMMAND_ALLOWLIST`: Comma-separated logins which alone may command the bot (default empty, ie. anyone with `REQUIRED_PERMISSION`). The denylist wins
+over it, and allowed users still need `REQUIRED_PERMISSION`.
+
+`COMMAND_ALLOWLISTS`: Comma-separated `command=login` pairs naming the only users who may use a command, instead of `COMMAND_ALLOWLIST`, eg.
+`bot merge force=gavofyork,bot merge force=rphmeier`.
+
+`COMMAND_ADMINS`: Comma-separated logins which may deny others any command with `bot admin deny <login>`, and lift that with
+`bot admin undeny <login>`.
+
+`RELEASE_NOTES_CATEGORIES`: Comma-separated `label=heading` pairs grouping the PRs listed by `bot release-notes <from> <to>`, eg.
+`B1-runtime=Runtime,B3-client=Client`. A PR goes under the first pair whose label it has, and under "Other" if none.
+
+`RELEASE_NOTES_DRAFT_RELEASE`: If true, `bot release-notes` creates a draft release of `<to>` with the notes and comments a link to it, instead of
+commenting the notes (default false).
 */
 
+use crate::burnin::BurninLimits;
+use crate::changelog::ChangelogRequirement;
+use crate::cmd::Sandbox;
+use crate::command_prefix;
+use crate::cooldown;
+use crate::error::Error;
+use crate::freeze::{self, MergeFreeze};
+use crate::github::{MergeMethod, Permission};
+use crate::http::RetryPolicy;
+use crate::native_queue::NativeQueuePolicy;
+use crate::path_labels::PathLabel;
+use crate::performance::{self, PerformanceGate};
+use crate::process::ProcessMatching;
+use crate::rebase::RebaseStrategy;
+use crate::repo_config::{self, RepoConfig};
+use crate::reporting::SentryDsn;
+use crate::sensitive_paths::SensitivePaths;
+use crate::size_labels::SizeBucket;
+use crate::spec_version::SpecVersionCheck;
+use crate::templates::{self, Templates};
+use crate::Result;
+use regex::Regex;
+use ring::digest;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+type EnvLookup = Box<dyn Fn(&str) -> Option<String>>;
+
+/// Where configuration values come from: the environment first, then the
+/// configuration file, if any.
+pub struct ConfigSource {
+	env: EnvLookup,
+	/// values from the file, keyed by upper-case variable name
+	file: HashMap<String, String>,
+	/// placeholders in the file which could not be expanded
+	errors: Vec<String>,
+}
+
+impl ConfigSource {
+	/// Only the environment, including `.env`.
+	pub fn env() -> Self {
+		dotenv::dotenv().ok();
+		Self::with_env(Box::new(|key| dotenv::var(key).ok()))
+	}
+
+	/// `CONFIG_FILE` if it is set, otherwise the environment alone.
+	pub fn load() -> Result<Self> {
+		dotenv::dotenv().ok();
+		match dotenv::var("CONFIG_FILE") {
+			Ok(path) => Self::file(&path),
+			Err(_) => Ok(Self::env()),
+		}
+	}
+
+	/// A configuration file, overridden by the environment.
+	pub fn file(path: &str) -> Result<Self> {
+		let contents =
+			std::fs::read_to_string(path).map_err(|e| Error::Config {
+				errors: vec![format!("reading {}: {}", path, e)],
+			})?;
+		Self::env().parse(&contents)
+	}
+
+	fn with_env(env: EnvLookup) -> Self {
+		Self {
+			env,
+			file: HashMap::new(),
+			errors: Vec::new(),
+		}
+	}
+
+	/// Add the keys of a TOML configuration file, expanding `${NAME}`
+	/// placeholders from the environment.
+	fn parse(mut self, contents: &str) -> Result<Self> {
+		let table =
+			toml::from_str::<toml::value::Table>(contents).map_err(|e| {
+				Error::Config {
+					errors: vec![format!("parsing configuration file: {}", e)],
+				}
+			})?;
+		let mut values = Vec::new();
+		flatten_table("", table, &mut values);
+		let placeholder = Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}")
+			.expect("placeholder regex");
+		for (key, value) in values {
+			let value =
+				placeholder.replace_all(&value, |caps: &regex::Captures| {
+					(self.env)(&caps[1]).unwrap_or_else(|| {
+						self.errors.push(format!(
+							"{}: environment variable {} is not set",
+							key, &caps[1]
+						));
+						String::new()
+					})
+				});
+			self.file.insert(key, value.into_owned());
+		}
+		Ok(self)
+	}
+
+	pub fn var(&self, key: &str) -> Option<String> {
+		(self.env)(key).or_else(|| self.file.get(key).cloned())
+	}
+}
+
+/// Flatten nested tables into upper-case keys joined by `_`, so that
+/// `[matrix] homeserver` becomes `MATRIX_HOMESERVER`.  Arrays become
+/// comma-separated lists, except arrays of tables, which are kept as inline
+/// TOML.
+fn flatten_table(
+	prefix: &str,
+	table: toml::value::Table,
+	values: &mut Vec<(String, String)>,
+) {
+	for (key, value) in table {
+		let key = if prefix.is_empty() {
+			key.to_uppercase()
+		} else {
+			format!("{}_{}", prefix, key.to_uppercase())
+		};
+		match value {
+			toml::Value::Table(table) => flatten_table(&key, table, values),
+			value => values.push((key, toml_to_string(value))),
+		}
+	}
+}
+
+fn toml_to_string(value: toml::Value) -> String {
+	match value {
+		toml::Value::String(s) => s,
+		toml::Value::Array(items)
+			if items.iter().any(|item| item.is_table()) =>
+		{
+			inline_toml(&toml::Value::Array(items))
+		}
+		toml::Value::Array(items) => items
+			.into_iter()
+			.map(toml_to_string)
+			.collect::<Vec<_>>()
+			.join(","),
+		value => value.to_string(),
+	}
+}
+
+fn inline_toml(value: &toml::Value) -> String {
+	match value {
+		toml::Value::Array(items) => format!(
+			"[{}]",
+			items.iter().map(inline_toml).collect::<Vec<_>>().join(", ")
+		),
+		toml::Value::Table(table) => format!(
+			"{{ {} }}",
+			table
+				.iter()
+				.map(|(key, value)| format!("{} = {}", key, inline_toml(value)))
+				.collect::<Vec<_>>()
+				.join(", ")
+		),
+		value => value.to_string(),
+	}
+}
+
+/// Whether the value of a variable is a secret, which is left out of the
+/// fingerprint of the configuration.
+fn is_secret(key: &str) -> bool {
+	key.contains("SECRET") || key.contains("TOKEN")
+}
+
+/// Reads values from a `ConfigSource`, collecting every missing or invalid
+/// one instead of stopping at the first.
+struct Loader<'a> {
+	source: &'a ConfigSource,
+	used: HashSet<String>,
+	errors: Vec<String>,
+}
+
+impl<'a> Loader<'a> {
+	fn new(source: &'a ConfigSource) -> Self {
+		Self {
+			source,
+			used: HashSet::new(),
+			errors: source.errors.clone(),
+		}
+	}
+
+	fn var(&mut self, key: &str) -> Option<String> {
+		self.used.insert(key.to_string());
+		self.source.var(key)
+	}
+
+	fn parse<T>(&mut self, key: &str, value: &str) -> Option<T>
+	where
+		T: FromStr,
+		T::Err: Display,
+	{
+		match value.trim().parse::<T>() {
+			Ok(value) => Some(value),
+			Err(e) => {
+				self.errors.push(format!("{}: {}", key, e));
+				None
+			}
+		}
+	}
+
+	fn required<T>(&mut self, key: &str) -> T
+	where
+		T: FromStr + Default,
+		T::Err: Display,
+	{
+		match self.var(key) {
+			Some(value) => self.parse(key, &value).unwrap_or_default(),
+			None => {
+				self.errors.push(format!("{}: missing", key));
+				T::default()
+			}
+		}
+	}
+
+	fn optional<T>(&mut self, key: &str, default: T) -> T
+	where
+		T: FromStr,
+		T::Err: Display,
+	{
+		match self.var(key) {
+			Some(value) => self.parse(key, &value).unwrap_or(default),
+			None => default,
+		}
+	}
+
+	/// A comma-separated list, empty if the variable is not set.
+	fn list(&mut self, key: &str) -> Option<Vec<String>> {
+		self.var(key).map(|s| {
+			s.split(',')
+				.map(|item| item.trim().to_string())
+				.filter(|item| !item.is_empty())
+				.collect()
+		})
+	}
+
+	/// A short hash of the values read, but for secrets, so that two
+	/// configurations compare equal if only their secrets differ.
+	fn fingerprint(&self) -> String {
+		let mut keys = self
+			.used
+			.iter()
+			.filter(|key| !is_secret(key))
+			.collect::<Vec<_>>();
+		keys.sort();
+		let mut context = digest::Context::new(&digest::SHA256);
+		for key in keys {
+			if let Some(value) = self.source.var(key) {
+				context.update(format!("{}={}\n", key, value).as_bytes());
+			}
+		}
+		base16::encode_lower(&context.finish().as_ref()[..6])
+	}
+
+	fn finish(self) -> Result<()> {
+		let used = self.used;
+		let mut errors = self.errors;
+		let mut unknown = self
+			.source
+			.file
+			.keys()
+			.filter(|key| !used.contains(*key))
+			.map(|key| format!("{}: unknown key", key.to_lowercase()))
+			.collect::<Vec<_>>();
+		unknown.sort();
+		errors.extend(unknown);
+		if errors.is_empty() {
+			Ok(())
+		} else {
+			Err(Error::Config { errors })
+		}
+	}
+}
+
+/// All of the configuration, validated at once.
+#[derive(Debug, Clone)]
+pub struct Config {
+	pub main: MainConfig,
+	pub bot: BotConfig,
+	/// tells configurations apart, without giving secrets away
+	pub fingerprint: String,
+}
+
+impl Config {
+	/// Load the configuration from `CONFIG_FILE` if it is set, otherwise from
+	/// the environment alone.
+	pub fn load() -> Result<Self> {
+		Self::from_source(&ConfigSource::load()?)
+	}
+
+	pub fn from_file(path: &str) -> Result<Self> {
+		Self::from_source(&ConfigSource::file(path)?)
+	}
+
+	pub fn from_env() -> Result<Self> {
+		Self::from_source(&ConfigSource::env())
+	}
+
+	pub fn from_source(source: &ConfigSource) -> Result<Self> {
+		let mut loader = Loader::new(source);
+		let main = MainConfig::load(&mut loader);
+		let bot = BotConfig::load(&mut loader);
+		let fingerprint = loader.fingerprint();
+		loader.finish()?;
+		Ok(Self {
+			main,
+			bot,
+			fingerprint,
+		})
+	}
+}
+
 #[derive(Debug, Clone)]
 pub struct MainConfig {
 	pub environment: String,
 	pub test_repo: String,
 	pub installation_login: String,
 	pub webhook_secret: String,
-	pub webhook_port: String,
+	pub webhook_port: u16,
 	pub db_path: String,
+	/// directory the database is backed up to, if any
+	pub backup_dir: Option<String>,
+	/// seconds between backups
+	pub backup_interval_secs: u64,
+	/// how many backups are kept
+	pub backup_keep: usize,
+	/// days for which merge outcomes are kept
+	pub stats_retention_days: i64,
 	pub bamboo_token: String,
 	pub private_key: Vec<u8>,
 	pub matrix_homeserver: String,
@@ -74,76 +646,80 @@ pub struct MainConfig {
 	pub burnin_gitlab_host: String,
 	pub burnin_gitlab_project: String,
 	pub burnin_gitlab_token: String,
+	pub burnin_gitlab_webhook_secret: String,
+	/// if true then start even if a startup check fails
+	pub allow_degraded_start: bool,
+	/// webhooks waiting to be handled before the bot is not ready
+	pub readiness_max_pending_webhooks: usize,
+	/// seconds between readiness checks
+	pub readiness_check_secs: u64,
+	/// bearer token for `/audit`, which is not served if empty
+	pub audit_token: String,
+	/// bearer token for `/admin/queue`, which is not served if empty
+	pub admin_token: String,
+	/// how failed github requests are retried
+	pub http_retry_policy: RetryPolicy,
+	/// minimum time between two comments
+	pub comment_delay: std::time::Duration,
 }
 
 impl MainConfig {
-	pub fn from_env() -> Self {
-		dotenv::dotenv().ok();
-
-		let environment = dotenv::var("ENVIRONMENT").expect("ENVIRONMENT");
-		let test_repo = dotenv::var("TEST_REPO_NAME").expect("TEST_REPO_NAME");
-		let installation_login =
-			dotenv::var("INSTALLATION_LOGIN").expect("INSTALLATION_LOGIN");
-		let webhook_secret =
-			dotenv::var("WEBHOOK_SECRET").expect("WEBHOOK_SECRET");
-		let webhook_port = dotenv::var("WEBHOOK_PORT").expect("WEBHOOK_PORT");
-		let db_path = dotenv::var("DB_PATH").expect("DB_PATH");
-		let bamboo_token = dotenv::var("BAMBOO_TOKEN").expect("BAMBOO_TOKEN");
-		let matrix_homeserver =
-			dotenv::var("MATRIX_HOMESERVER").expect("MATRIX_HOMESERVER");
-		let matrix_access_token =
-			dotenv::var("MATRIX_ACCESS_TOKEN").expect("MATRIX_ACCESS_TOKEN");
-		let matrix_default_channel_id =
-			dotenv::var("MATRIX_DEFAULT_CHANNEL_ID")
-				.expect("MATRIX_DEFAULT_CHANNEL_ID");
-		let main_tick_secs = dotenv::var("MAIN_TICK_SECS")
-			.expect("MAIN_TICK_SECS")
-			.parse::<u64>()
-			.expect("parse MAIN_TICK_SECS");
-		let bamboo_tick_secs = dotenv::var("BAMBOO_TICK_SECS")
-			.expect("BAMBOO_TICK_SECS")
-			.parse::<u64>()
-			.expect("parse BAMBOO_TICK_SECS");
-		let matrix_silent = dotenv::var("MATRIX_SILENT")
-			.expect("MATRIX_SILENT")
-			.parse::<bool>()
-			.expect("failed parsing MATRIX_SILENT");
-
-		let private_key_path =
-			dotenv::var("PRIVATE_KEY_PATH").expect("PRIVATE_KEY_PATH");
-		let private_key = std::fs::read(&private_key_path)
-			.expect("Couldn't find private key.");
-
-		let burnin_gitlab_host =
-			dotenv::var("BURNIN_GITLAB_HOST").expect("BURNIN_GITLAB_HOST");
-		let burnin_gitlab_project = dotenv::var("BURNIN_GITLAB_PROJECT")
-			.expect("BURNIN_GITLAB_PROJECT");
-		let burnin_gitlab_token =
-			dotenv::var("BURNIN_GITLAB_TOKEN").expect("BURNIN_GITLAB_TOKEN");
+	fn load(loader: &mut Loader) -> Self {
+		let private_key_path: String = loader.required("PRIVATE_KEY_PATH");
+		let private_key = if private_key_path.is_empty() {
+			Vec::new()
+		} else {
+			std::fs::read(&private_key_path).unwrap_or_else(|e| {
+				loader.errors.push(format!(
+					"PRIVATE_KEY_PATH: reading {}: {}",
+					private_key_path, e
+				));
+				Vec::new()
+			})
+		};
 
 		Self {
-			environment,
-			test_repo,
-			installation_login,
-			webhook_secret,
-			webhook_port,
-			db_path,
-			bamboo_token,
+			environment: loader.required("ENVIRONMENT"),
+			test_repo: loader.required("TEST_REPO_NAME"),
+			installation_login: loader.required("INSTALLATION_LOGIN"),
+			webhook_secret: loader.required("WEBHOOK_SECRET"),
+			webhook_port: loader.required("WEBHOOK_PORT"),
+			db_path: loader.required("DB_PATH"),
+			backup_dir: loader.var("BACKUP_DIR").filter(|s| !s.is_empty()),
+			backup_interval_secs: loader
+				.optional("BACKUP_INTERVAL_SECS", 86400),
+			backup_keep: loader.optional("BACKUP_KEEP", 7),
+			stats_retention_days: loader.optional("STATS_RETENTION_DAYS", 365),
+			bamboo_token: loader.required("BAMBOO_TOKEN"),
 			private_key,
-			matrix_homeserver,
-			matrix_access_token,
-			matrix_default_channel_id,
-			main_tick_secs,
-			bamboo_tick_secs,
-			matrix_silent,
-			burnin_gitlab_host,
-			burnin_gitlab_project,
-			burnin_gitlab_token,
+			matrix_homeserver: loader.required("MATRIX_HOMESERVER"),
+			matrix_access_token: loader.required("MATRIX_ACCESS_TOKEN"),
+			matrix_default_channel_id: loader
+				.required("MATRIX_DEFAULT_CHANNEL_ID"),
+			main_tick_secs: loader.required("MAIN_TICK_SECS"),
+			bamboo_tick_secs: loader.required("BAMBOO_TICK_SECS"),
+			matrix_silent: loader.required("MATRIX_SILENT"),
+			burnin_gitlab_host: loader.required("BURNIN_GITLAB_HOST"),
+			burnin_gitlab_project: loader.required("BURNIN_GITLAB_PROJECT"),
+			burnin_gitlab_token: loader.required("BURNIN_GITLAB_TOKEN"),
+			burnin_gitlab_webhook_secret: loader
+				.required("BURNIN_GITLAB_WEBHOOK_SECRET"),
+			allow_degraded_start: loader
+				.optional("ALLOW_DEGRADED_START", false),
+			readiness_max_pending_webhooks: loader
+				.optional("READINESS_MAX_PENDING_WEBHOOKS", 20),
+			readiness_check_secs: loader.optional("READINESS_CHECK_SECS", 10),
+			audit_token: loader.optional("AUDIT_TOKEN", String::new()),
+			admin_token: loader.optional("ADMIN_TOKEN", String::new()),
+			http_retry_policy: http_retry_policy(loader),
+			comment_delay: std::time::Duration::from_millis(
+				loader.optional("COMMENT_DELAY_MS", 1000),
+			),
 		}
 	}
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct BotConfig {
 	/// seconds between pings
 	pub status_failure_ping: u64,
@@ -169,100 +745,1030 @@ pub struct BotConfig {
 	pub public_review_reminder_delay: u64,
 	/// mininum number of reviewers
 	pub min_reviewers: usize,
+	/// slugs of the teams whose approvals count towards `min_reviewers`
+	pub review_teams: Vec<String>,
+	/// slugs of the teams whose members may bypass the review teams
+	pub lead_teams: Vec<String>,
+	/// slugs of the teams whose members may use `bot merge force`
+	pub force_merge_teams: Vec<String>,
+	/// if true then `bot merge force` needs a justification
+	pub force_merge_require_reason: bool,
+	/// labels which make a pull request insubstantial
+	pub insubstantial_labels: Vec<String>,
+	/// mininum number of reviewers of an insubstantial pull request
+	pub insubstantial_min_reviewers: usize,
+	/// labels which prevent a merge
+	pub blocked_labels: Vec<String>,
+	/// patterns of the base branches merged into, eg. `release-*`; any if
+	/// empty
+	pub allowed_base_branches: Vec<String>,
+	/// periods during which merges are refused
+	pub merge_freezes: Vec<MergeFreeze>,
+	/// benchmarks run before merging
+	pub performance_gates: Vec<PerformanceGate>,
+	/// seconds before a cached benchmark of a merge base is run again
+	pub bench_cache_ttl: i64,
+	/// benchmarks of merge bases kept
+	pub bench_cache_max_entries: usize,
+	/// checks and status contexts not waited for before merging
+	pub ignored_checks: Vec<String>,
+	/// how pull requests are merged
+	pub merge_method: MergeMethod,
+	/// how `bot rebase` updates the head branch
+	pub rebase_strategy: RebaseStrategy,
+	/// size of PRs past which `bot merge` must be confirmed; 0 is no limit
+	pub confirm_merge_lines: u64,
+	pub confirm_merge_files: u64,
+	/// seconds within which a merge must be confirmed
+	pub confirm_merge_window: i64,
+	/// what to do with PRs GitHub is to merge by itself
+	pub native_merge_queue: NativeQueuePolicy,
+	/// whether reviews and labels update the merge gate status of a PR
+	pub merge_gate_status: bool,
+	/// changelog entry a PR must add before it is merged; only set per
+	/// repository, in `.processbot.toml`
+	pub require_changelog: Option<ChangelogRequirement>,
+	/// paths whose changes a team must audit before a PR is merged; only set
+	/// per repository, in `.processbot.toml`
+	pub sensitive_paths: Option<SensitivePaths>,
+	/// runtimes whose `spec_version` PRs changing them must bump; only set
+	/// per repository, in `.processbot.toml`
+	pub spec_version: Option<SpecVersionCheck>,
+	/// labels added to PRs by the paths they change; only set per
+	/// repository, in `.processbot.toml`
+	pub path_labels: Vec<PathLabel>,
+	/// label which stops a PR being labelled by its paths
+	pub path_labels_opt_out: String,
+	/// label which stops the process owners of a PR being asked to review it
+	pub review_request_opt_out: String,
+	/// file in a repository to welcome first-time contributors with
+	pub welcome_template: Option<String>,
+	/// the texts the bot posts
+	pub templates: Templates,
+	/// in ascending order
+	pub size_labels: Vec<SizeBucket>,
+	pub size_ignored_files: Vec<String>,
+	/// logins of PR authors whose PRs are merged without a command
+	pub automerge_authors: Vec<String>,
+	/// files those PRs may change
+	pub automerge_files: Vec<String>,
+	/// login requesting those merges
+	pub automerge_identity: String,
+	/// login of the app's bot user, whose comments and checks are its own
+	pub bot_login: String,
+	/// logins of PR authors who let others rebase their branches
+	pub rebase_consent: Vec<String>,
+	/// what commands start with, as written in the bot's comments
+	pub command_prefix: String,
+	/// other prefixes commands may start with
+	pub command_aliases: Vec<String>,
+	/// seconds within which a command is not run again on the same PR
+	pub command_cooldowns: Vec<(String, i64)>,
+	/// PR numbers of example companions in PR templates
+	pub companion_placeholders: Vec<i64>,
+	/// per repository, patterns of the branches besides the default one
+	/// companions may target
+	pub companion_branches: HashMap<String, Vec<String>>,
+	/// label keeping a PR up to date with its base branch; empty disables it
+	pub auto_update_label: String,
+	/// minimum seconds between two updates of a labelled PR
+	pub auto_update_interval: i64,
 	/// name of repo for issues without a project
 	pub core_sorting_repo_name: String,
 	/// matrix room id for sending app logs
 	pub logs_room_id: String,
+	/// seconds before a cached Process.json is fetched again
+	pub process_cache_ttl: u64,
+	/// seconds before a cached .processbot.toml is fetched again
+	pub repo_config_cache_ttl: u64,
+	/// how PRs are matched to Process.json entries, unless overridden
+	pub process_matching: ProcessMatching,
+	/// per-repository overrides of `process_matching`
+	pub process_matching_repos: HashMap<String, ProcessMatching>,
 	/// matrix room id for notifications about burn-in requests
 	pub burnin_room_id: String,
+	/// nodes a burn-in may deploy
+	pub burnin_limits: BurninLimits,
+	/// slug of the github team whose members may exceed the burn-in limits
+	pub burnin_ops_team: Option<String>,
+	/// if true then changes are logged instead of made
+	pub dry_run: bool,
+	/// if true then a dry run also comments what it would have done
+	pub dry_run_comment: bool,
+	/// where unhandled errors are reported, if anywhere
+	pub error_reporting_dsn: Option<SentryDsn>,
+	/// directory verified webhooks are recorded to, if any
+	pub record_payloads_dir: Option<String>,
+	/// seconds before the same error is commented on a PR again
+	pub error_comment_window: i64,
+	/// seconds before the same `bot merge force` error is commented again
+	pub force_merge_error_comment_window: i64,
+	/// seconds during which status and check webhooks for a commit are
+	/// coalesced
+	pub checks_debounce_secs: i64,
+	/// where the clones are, and limits on the commands run on them
+	pub sandbox: Sandbox,
+	/// permission on a repository needed to command the bot there
+	pub required_permission: Permission,
+	/// logins which may not command the bot
+	pub command_denylist: Vec<String>,
+	/// if not empty, the only logins which may command the bot
+	pub command_allowlist: Vec<String>,
+	/// per command, the only logins which may use it
+	pub command_allowlists: HashMap<String, Vec<String>>,
+	/// logins which may deny others commands with `bot admin deny`
+	pub command_admins: Vec<String>,
+	/// `(label, heading)` of the categories of `bot release-notes`, in order
+	pub release_notes_categories: Vec<(String, String)>,
+	/// if true then `bot release-notes` creates a draft release
+	pub release_notes_draft_release: bool,
+	/// per-organization overrides, by lower-case login
+	pub org_overrides: HashMap<String, RepoConfig>,
 }
 
 impl BotConfig {
-	pub fn from_env() -> Self {
-		dotenv::dotenv().ok();
+	/// The configuration with the overrides of the organization `owner`
+	/// applied, if it has any.
+	pub fn for_org(&self, owner: &str) -> BotConfig {
+		match self.org_overrides.get(&owner.to_lowercase()) {
+			Some(overrides) => overrides.apply(self),
+			None => self.clone(),
+		}
+	}
+
+	/// The comment as a `bot` command, if it starts with a configured prefix.
+	pub fn command(&self, body: &str) -> Option<String> {
+		command_prefix::canonical(
+			body,
+			&self.command_prefix,
+			&self.command_aliases,
+		)
+	}
+
+	fn load(loader: &mut Loader) -> Self {
+		let min_reviewers = loader.required("MIN_REVIEWERS");
 		Self {
-			status_failure_ping: dotenv::var("STATUS_FAILURE_PING")
-				.expect("STATUS_FAILURE_PING")
-				.parse::<u64>()
-				.expect("failed parsing STATUS_FAILURE_PING"),
-
-			issue_not_addressed_ping: dotenv::var("ISSUE_NOT_ADDRESSED_PING")
-				.expect("ISSUE_NOT_ADDRESSED_PING")
-				.parse::<u64>()
-				.expect("failed parsing ISSUE_NOT_ADDRESSED_PING"),
-
-			issue_not_assigned_to_pr_author_ping: dotenv::var(
-				"ISSUE_NOT_ASSIGNED_TO_PR_AUTHOR_PING",
-			)
-			.expect("ISSUE_NOT_ASSIGNED_TO_PR_AUTHOR_PING")
-			.parse::<u64>()
-			.expect("failed parsing ISSUE_NOT_ASSIGNED_TO_PR_AUTHOR_PING"),
-
-			no_project_author_is_core_ping: dotenv::var(
-				"NO_PROJECT_AUTHOR_IS_CORE_PING",
-			)
-			.expect("NO_PROJECT_AUTHOR_IS_CORE_PING")
-			.parse::<u64>()
-			.expect("failed parsing NO_PROJECT_AUTHOR_IS_CORE_PING"),
-
-			no_project_author_is_core_close_pr: dotenv::var(
-				"NO_PROJECT_AUTHOR_IS_CORE_CLOSE_PR",
-			)
-			.expect("NO_PROJECT_AUTHOR_IS_CORE_CLOSE_PR")
-			.parse::<u64>()
-			.expect("failed parsing NO_PROJECT_AUTHOR_IS_CORE_CLOSE_PR"),
-
-			no_project_author_unknown_close_pr: dotenv::var(
-				"NO_PROJECT_AUTHOR_UNKNOWN_CLOSE_PR",
-			)
-			.expect("NO_PROJECT_AUTHOR_UNKNOWN_CLOSE_PR")
-			.parse::<u64>()
-			.expect("failed parsing NO_PROJECT_AUTHOR_UNKNOWN_CLOSE_PR"),
-
-			project_confirmation_timeout: dotenv::var(
-				"PROJECT_CONFIRMATION_TIMEOUT",
-			)
-			.expect("PROJECT_CONFIRMATION_TIMEOUT")
-			.parse::<u64>()
-			.expect("failed parsing PROJECT_CONFIRMATION_TIMEOUT"),
-
-			review_request_ping: dotenv::var("REVIEW_REQUEST_PING")
-				.expect("REVIEW_REQUEST_PING")
-				.parse::<u64>()
-				.expect("failed parsing REVIEW_REQUEST_PING"),
-
-			private_review_reminder_ping: dotenv::var(
-				"PRIVATE_REVIEW_REMINDER_PING",
-			)
-			.expect("PRIVATE_REVIEW_REMINDER_PING")
-			.parse::<u64>()
-			.expect("failed parsing PRIVATE_REVIEW_REMINDER_PING"),
-
-			public_review_reminder_ping: dotenv::var(
-				"PUBLIC_REVIEW_REMINDER_PING",
-			)
-			.expect("PUBLIC_REVIEW_REMINDER_PING")
-			.parse::<u64>()
-			.expect("failed parsing PUBLIC_REVIEW_REMINDER_PING"),
-
-			public_review_reminder_delay: dotenv::var(
-				"PUBLIC_REVIEW_REMINDER_DELAY",
-			)
-			.expect("PUBLIC_REVIEW_REMINDER_DELAY")
-			.parse::<u64>()
-			.expect("failed parsing PUBLIC_REVIEW_REMINDER_DELAY"),
-
-			min_reviewers: dotenv::var("MIN_REVIEWERS")
-				.expect("MIN_REVIEWERS")
-				.parse::<usize>()
-				.expect("failed parsing MIN_REVIEWERS"),
-
-			core_sorting_repo_name: dotenv::var("CORE_SORTING_REPO_NAME")
-				.expect("CORE_SORTING_REPO_NAME"),
-
-			logs_room_id: dotenv::var("LOGS_ROOM_ID").expect("LOGS_ROOM_ID"),
-			burnin_room_id: dotenv::var("BURNIN_ROOM_ID")
-				.expect("BURNIN_ROOM_ID"),
+			status_failure_ping: loader.required("STATUS_FAILURE_PING"),
+			issue_not_addressed_ping: loader
+				.required("ISSUE_NOT_ADDRESSED_PING"),
+			issue_not_assigned_to_pr_author_ping: loader
+				.required("ISSUE_NOT_ASSIGNED_TO_PR_AUTHOR_PING"),
+			no_project_author_is_core_ping: loader
+				.required("NO_PROJECT_AUTHOR_IS_CORE_PING"),
+			no_project_author_is_core_close_pr: loader
+				.required("NO_PROJECT_AUTHOR_IS_CORE_CLOSE_PR"),
+			no_project_author_unknown_close_pr: loader
+				.required("NO_PROJECT_AUTHOR_UNKNOWN_CLOSE_PR"),
+			project_confirmation_timeout: loader
+				.required("PROJECT_CONFIRMATION_TIMEOUT"),
+			review_request_ping: loader.required("REVIEW_REQUEST_PING"),
+			private_review_reminder_ping: loader
+				.required("PRIVATE_REVIEW_REMINDER_PING"),
+			public_review_reminder_ping: loader
+				.required("PUBLIC_REVIEW_REMINDER_PING"),
+			public_review_reminder_delay: loader
+				.required("PUBLIC_REVIEW_REMINDER_DELAY"),
+			min_reviewers,
+			review_teams: loader
+				.list("REVIEW_TEAMS")
+				.unwrap_or_else(|| vec!["core-devs".to_string()]),
+			lead_teams: loader
+				.list("LEAD_TEAMS")
+				.unwrap_or_else(|| vec!["substrateteamleads".to_string()]),
+			force_merge_teams: loader
+				.list("FORCE_MERGE_TEAMS")
+				.unwrap_or_else(|| vec!["substrateteamleads".to_string()]),
+			force_merge_require_reason: loader
+				.optional("FORCE_MERGE_REQUIRE_REASON", false),
+			insubstantial_labels: loader
+				.list("INSUBSTANTIAL_LABELS")
+				.unwrap_or_else(|| vec!["insubstantial".to_string()]),
+			insubstantial_min_reviewers: loader
+				.optional("INSUBSTANTIAL_MIN_REVIEWERS", 1),
+			blocked_labels: loader.list("BLOCKED_LABELS").unwrap_or_default(),
+			allowed_base_branches: loader
+				.list("ALLOWED_BASE_BRANCHES")
+				.unwrap_or_default(),
+			merge_freezes: merge_freezes(loader),
+			performance_gates: performance_gates(loader),
+			bench_cache_ttl: loader.optional("BENCH_CACHE_TTL", 604800),
+			bench_cache_max_entries: loader
+				.optional("BENCH_CACHE_MAX_ENTRIES", 100),
+			ignored_checks: loader.list("IGNORED_CHECKS").unwrap_or_default(),
+			merge_method: loader
+				.optional("MERGE_METHOD", MergeMethod::default()),
+			rebase_strategy: loader
+				.optional("REBASE_STRATEGY", RebaseStrategy::default()),
+			confirm_merge_lines: loader.optional("CONFIRM_MERGE_LINES", 0),
+			confirm_merge_files: loader.optional("CONFIRM_MERGE_FILES", 0),
+			confirm_merge_window: loader.optional("CONFIRM_MERGE_WINDOW", 600),
+			native_merge_queue: loader
+				.optional("NATIVE_MERGE_QUEUE", NativeQueuePolicy::default()),
+			merge_gate_status: loader.optional("MERGE_GATE_STATUS", false),
+			require_changelog: None,
+			sensitive_paths: None,
+			spec_version: None,
+			path_labels: Vec::new(),
+			path_labels_opt_out: loader
+				.var("PATH_LABELS_OPT_OUT")
+				.unwrap_or_else(|| "no-path-labels".to_string()),
+			review_request_opt_out: loader
+				.var("REVIEW_REQUEST_OPT_OUT")
+				.unwrap_or_else(|| "no-review-request".to_string()),
+			welcome_template: loader
+				.var("WELCOME_TEMPLATE")
+				.filter(|path| !path.is_empty()),
+			templates: templates(loader, min_reviewers),
+			size_labels: size_labels(loader),
+			size_ignored_files: loader
+				.list("SIZE_IGNORED_FILES")
+				.unwrap_or_else(|| vec!["Cargo.lock".to_string()]),
+			automerge_authors: loader
+				.list("AUTOMERGE_AUTHORS")
+				.unwrap_or_default(),
+			automerge_files: loader.list("AUTOMERGE_FILES").unwrap_or_else(
+				|| vec!["Cargo.lock".to_string(), "Cargo.toml".to_string()],
+			),
+			automerge_identity: loader
+				.var("AUTOMERGE_IDENTITY")
+				.unwrap_or_else(|| "parity-processbot".to_string()),
+			bot_login: loader
+				.var("BOT_LOGIN")
+				.unwrap_or_else(|| "parity-processbot[bot]".to_string()),
+			rebase_consent: loader.list("REBASE_CONSENT").unwrap_or_default(),
+			command_prefix: command_prefix(loader),
+			command_aliases: command_aliases(loader),
+			command_cooldowns: command_cooldowns(loader),
+			companion_placeholders: companion_placeholders(loader),
+			companion_branches: companion_branches(loader),
+			auto_update_label: loader
+				.var("AUTO_UPDATE_LABEL")
+				.unwrap_or_else(|| "C1-auto-update".to_string()),
+			auto_update_interval: loader.optional("AUTO_UPDATE_INTERVAL", 600),
+			core_sorting_repo_name: loader.required("CORE_SORTING_REPO_NAME"),
+			logs_room_id: loader.required("LOGS_ROOM_ID"),
+			process_cache_ttl: loader.optional("PROCESS_CACHE_TTL", 600),
+			repo_config_cache_ttl: loader
+				.optional("REPO_CONFIG_CACHE_TTL", 600),
+			process_matching: loader
+				.optional("PROCESS_MATCHING", ProcessMatching::default()),
+			process_matching_repos: process_matching_repos(loader),
+			burnin_room_id: loader.required("BURNIN_ROOM_ID"),
+			burnin_limits: burnin_limits(loader),
+			burnin_ops_team: loader
+				.var("BURNIN_OPS_TEAM")
+				.filter(|s| !s.is_empty()),
+			dry_run: loader.optional("DRY_RUN", false),
+			dry_run_comment: loader.optional("DRY_RUN_COMMENT", false),
+			error_reporting_dsn: match loader.var("ERROR_REPORTING_DSN") {
+				Some(dsn) if !dsn.is_empty() => {
+					loader.parse("ERROR_REPORTING_DSN", &dsn)
+				}
+				_ => None,
+			},
+			record_payloads_dir: loader
+				.var("RECORD_PAYLOADS_DIR")
+				.filter(|s| !s.is_empty()),
+			error_comment_window: loader.optional("ERROR_COMMENT_WINDOW", 3600),
+			force_merge_error_comment_window: loader
+				.optional("FORCE_MERGE_ERROR_COMMENT_WINDOW", 0),
+			checks_debounce_secs: loader.optional("CHECKS_DEBOUNCE_SECS", 5),
+			sandbox: sandbox(loader),
+			required_permission: loader
+				.optional("REQUIRED_PERMISSION", Permission::default()),
+			command_denylist: loader
+				.list("COMMAND_DENYLIST")
+				.unwrap_or_default(),
+			command_allowlist: loader
+				.list("COMMAND_ALLOWLIST")
+				.unwrap_or_default(),
+			command_allowlists: command_allowlists(loader),
+			command_admins: loader.list("COMMAND_ADMINS").unwrap_or_default(),
+			release_notes_categories: release_notes_categories(loader),
+			release_notes_draft_release: loader
+				.optional("RELEASE_NOTES_DRAFT_RELEASE", false),
+			org_overrides: org_overrides(loader),
+		}
+	}
+
+	/// Whether PRs based on `branch` may be merged.
+	pub fn allows_base_branch(&self, branch: &str) -> bool {
+		self.allowed_base_branches.is_empty()
+			|| self
+				.allowed_base_branches
+				.iter()
+				.any(|pattern| freeze::branch_matches(pattern, branch))
+	}
+
+	/// How PRs of a repository are matched to Process.json entries.
+	pub fn process_matching(&self, repo_name: &str) -> ProcessMatching {
+		self.process_matching_repos
+			.get(repo_name)
+			.copied()
+			.unwrap_or(self.process_matching)
+	}
+}
+
+fn process_matching_repos(
+	loader: &mut Loader,
+) -> HashMap<String, ProcessMatching> {
+	let key = "PROCESS_MATCHING_REPOS";
+	let mut repos = HashMap::new();
+	for pair in loader.list(key).unwrap_or_default() {
+		let mut parts = pair.splitn(2, '=');
+		let repo = parts.next().unwrap_or_default().trim();
+		match parts.next() {
+			Some(mode) => {
+				if let Some(mode) = loader.parse(key, mode) {
+					repos.insert(repo.to_string(), mode);
+				}
+			}
+			None => loader
+				.errors
+				.push(format!("{}: expected repo=mode, got {}", key, pair)),
+		}
+	}
+	repos
+}
+
+fn release_notes_categories(loader: &mut Loader) -> Vec<(String, String)> {
+	let key = "RELEASE_NOTES_CATEGORIES";
+	let mut categories = Vec::new();
+	for pair in loader.list(key).unwrap_or_default() {
+		let mut parts = pair.splitn(2, '=');
+		match (parts.next(), parts.next()) {
+			(Some(label), Some(heading))
+				if !label.trim().is_empty() && !heading.trim().is_empty() =>
+			{
+				categories.push((
+					label.trim().to_string(),
+					heading.trim().to_string(),
+				));
+			}
+			_ => loader
+				.errors
+				.push(format!("{}: expected label=heading, got {}", key, pair)),
+		}
+	}
+	categories
+}
+
+fn command_prefix(loader: &mut Loader) -> String {
+	let key = "COMMAND_PREFIX";
+	let prefix = loader
+		.var(key)
+		.map(|prefix| prefix.trim().to_string())
+		.unwrap_or_else(|| command_prefix::CANONICAL.to_string());
+	if let Err(e) = command_prefix::validate(&prefix) {
+		loader.errors.push(format!("{}: {}", key, e));
+	}
+	prefix
+}
+
+fn command_aliases(loader: &mut Loader) -> Vec<String> {
+	let key = "COMMAND_ALIASES";
+	let aliases = loader.list(key).unwrap_or_default();
+	for alias in &aliases {
+		if let Err(e) = command_prefix::validate(alias) {
+			loader.errors.push(format!("{}: {}", key, e));
+		}
+	}
+	aliases
+}
+
+fn command_cooldowns(loader: &mut Loader) -> Vec<(String, i64)> {
+	let key = "COMMAND_COOLDOWNS";
+	let pairs = match loader.list(key) {
+		Some(pairs) => pairs,
+		None => return cooldown::default_cooldowns(),
+	};
+	let mut cooldowns = Vec::new();
+	for pair in pairs {
+		let mut parts = pair.splitn(2, '=');
+		match (parts.next().map(str::trim), parts.next()) {
+			(Some(command), Some(secs)) if !command.is_empty() => {
+				if let Some(secs) = loader.parse(key, secs) {
+					cooldowns.push((command.to_lowercase(), secs));
+				}
+			}
+			_ => loader.errors.push(format!(
+				"{}: expected command=seconds, got {}",
+				key, pair
+			)),
+		}
+	}
+	cooldowns
+}
+
+fn companion_placeholders(loader: &mut Loader) -> Vec<i64> {
+	let key = "COMPANION_PLACEHOLDERS";
+	match loader.list(key) {
+		Some(numbers) => numbers
+			.iter()
+			.filter_map(|number| loader.parse(key, number))
+			.collect(),
+		None => vec![123],
+	}
+}
+
+fn companion_branches(loader: &mut Loader) -> HashMap<String, Vec<String>> {
+	let key = "COMPANION_BRANCHES";
+	let mut branches = HashMap::<String, Vec<String>>::new();
+	for pair in loader.list(key).unwrap_or_default() {
+		let mut parts = pair.splitn(2, '=');
+		match (parts.next().map(str::trim), parts.next().map(str::trim)) {
+			(Some(repo), Some(branch))
+				if !repo.is_empty() && !branch.is_empty() =>
+			{
+				branches
+					.entry(repo.to_string())
+					.or_default()
+					.push(branch.to_string())
+			}
+			_ => loader
+				.errors
+				.push(format!("{}: expected repo=branch, got {}", key, pair)),
+		}
+	}
+	branches
+}
+
+fn size_labels(loader: &mut Loader) -> Vec<SizeBucket> {
+	let key = "SIZE_LABELS";
+	let mut buckets = Vec::new();
+	for pair in loader.list(key).unwrap_or_default() {
+		let mut parts = pair.rsplitn(2, '=');
+		match (
+			parts.next().and_then(|lines| lines.trim().parse().ok()),
+			parts.next(),
+		) {
+			(Some(min_lines), Some(label)) if !label.trim().is_empty() => {
+				buckets.push(SizeBucket {
+					label: label.trim().to_string(),
+					min_lines,
+				})
+			}
+			_ => loader
+				.errors
+				.push(format!("{}: expected label=lines, got {}", key, pair)),
+		}
+	}
+	buckets.sort_by_key(|bucket| bucket.min_lines);
+	buckets
+}
+
+fn command_allowlists(loader: &mut Loader) -> HashMap<String, Vec<String>> {
+	let key = "COMMAND_ALLOWLISTS";
+	let mut commands = HashMap::<String, Vec<String>>::new();
+	for pair in loader.list(key).unwrap_or_default() {
+		let mut parts = pair.rsplitn(2, '=');
+		match (parts.next(), parts.next()) {
+			(Some(login), Some(command)) if !login.trim().is_empty() => {
+				commands
+					.entry(command.trim().to_lowercase())
+					.or_default()
+					.push(login.trim().to_string());
+			}
+			_ => loader
+				.errors
+				.push(format!("{}: expected command=login, got {}", key, pair)),
+		}
+	}
+	commands
+}
+
+fn merge_freezes(loader: &mut Loader) -> Vec<MergeFreeze> {
+	let key = "MERGE_FREEZES";
+	match loader.var(key).map(|s| freeze::parse_freezes(&s)) {
+		Some(Ok(freezes)) => freezes,
+		Some(Err(e)) => {
+			loader.errors.push(format!("{}: {}", key, e));
+			Vec::new()
+		}
+		None => Vec::new(),
+	}
+}
+
+fn org_overrides(loader: &mut Loader) -> HashMap<String, RepoConfig> {
+	let key = "ORG_OVERRIDES";
+	match loader
+		.var(key)
+		.map(|s| repo_config::parse_org_overrides(&s))
+	{
+		Some(Ok(overrides)) => overrides,
+		Some(Err(e)) => {
+			loader.errors.push(format!("{}: {}", key, e));
+			HashMap::new()
+		}
+		None => HashMap::new(),
+	}
+}
+
+fn performance_gates(loader: &mut Loader) -> Vec<PerformanceGate> {
+	let key = "PERFORMANCE_GATES";
+	match loader.var(key).map(|s| performance::parse_gates(&s)) {
+		Some(Ok(gates)) => gates,
+		Some(Err(e)) => {
+			loader.errors.push(format!("{}: {}", key, e));
+			Vec::new()
+		}
+		None => Vec::new(),
+	}
+}
+
+fn burnin_limits(loader: &mut Loader) -> BurninLimits {
+	let defaults = BurninLimits::default();
+	BurninLimits {
+		allowed_node_types: loader
+			.list("BURNIN_ALLOWED_NODE_TYPES")
+			.unwrap_or(defaults.allowed_node_types),
+		max_fullnodes: loader
+			.optional("BURNIN_MAX_FULLNODES", defaults.max_fullnodes),
+		max_sentries: loader
+			.optional("BURNIN_MAX_SENTRIES", defaults.max_sentries),
+		max_validators: loader
+			.optional("BURNIN_MAX_VALIDATORS", defaults.max_validators),
+	}
+}
+
+fn sandbox(loader: &mut Loader) -> Sandbox {
+	let defaults = Sandbox::default();
+	Sandbox {
+		dir: loader
+			.var("SCRATCH_DIR")
+			.filter(|s| !s.is_empty())
+			.map_or(defaults.dir, PathBuf::from),
+		git_timeout: Duration::from_secs(
+			loader.optional("GIT_TIMEOUT_SECS", defaults.git_timeout.as_secs()),
+		),
+		cargo_timeout: Duration::from_secs(
+			loader.optional(
+				"CARGO_TIMEOUT_SECS",
+				defaults.cargo_timeout.as_secs(),
+			),
+		),
+		min_free_mb: loader
+			.optional("SCRATCH_MIN_FREE_MB", defaults.min_free_mb),
+	}
+}
+
+fn templates(loader: &mut Loader, min_reviewers: usize) -> Templates {
+	let faq_url = loader
+		.var("FAQ_URL")
+		.filter(|s| !s.is_empty())
+		.unwrap_or_else(|| templates::DEFAULT_FAQ_URL.to_string());
+	let burnin_contact = loader
+		.var("BURNIN_CONTACT")
+		.filter(|s| !s.is_empty())
+		.unwrap_or_else(|| templates::DEFAULT_BURNIN_CONTACT.to_string());
+	let templates = Templates::new(&faq_url, &burnin_contact, min_reviewers);
+	let key = "TEMPLATES_DIR";
+	match loader.var(key).filter(|s| !s.is_empty()) {
+		Some(dir) => templates
+			.clone()
+			.load_dir(Path::new(&dir))
+			.unwrap_or_else(|errors| {
+				loader.errors.extend(
+					errors.into_iter().map(|e| format!("{}: {}", key, e)),
+				);
+				templates
+			}),
+		None => templates,
+	}
+}
+
+fn http_retry_policy(loader: &mut Loader) -> RetryPolicy {
+	let defaults = RetryPolicy::default();
+	RetryPolicy {
+		max_retries: loader.optional("HTTP_MAX_RETRIES", defaults.max_retries),
+		initial_backoff: Duration::from_millis(loader.optional(
+			"HTTP_RETRY_BACKOFF_MS",
+			defaults.initial_backoff.as_millis() as u64,
+		)),
+		budget: Duration::from_secs(
+			loader
+				.optional("HTTP_RETRY_BUDGET_SECS", defaults.budget.as_secs()),
+		),
+		..defaults
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn source<K: ToString, V: ToString>(
+		env: &[(K, V)],
+		file: Option<&str>,
+	) -> ConfigSource {
+		let env = env
+			.iter()
+			.map(|(k, v)| (k.to_string(), v.to_string()))
+			.collect::<HashMap<_, _>>();
+		let source =
+			ConfigSource::with_env(Box::new(move |key| env.get(key).cloned()));
+		match file {
+			Some(contents) => source.parse(contents).unwrap(),
+			None => source,
+		}
+	}
+
+	fn key_path() -> String {
+		let path = std::env::temp_dir().join("processbot-config-test.pem");
+		std::fs::write(&path, b"private key").unwrap();
+		path.to_str().unwrap().to_string()
+	}
+
+	fn errors(result: Result<Config>) -> Vec<String> {
+		match result {
+			Err(Error::Config { errors }) => errors,
+			other => panic!("expected configuration errors, got {:?}", other),
+		}
+	}
+
+	const FILE: &str = r#"
+environment = "production"
+test_repo_name = "processbot-test"
+installation_login = "paritytech"
+webhook_secret = "${WEBHOOK_SECRET_VALUE}"
+webhook_port = 8080
+db_path = "/data/db"
+bamboo_token = ""
+private_key_path = "${KEY_PATH}"
+main_tick_secs = 60
+bamboo_tick_secs = 3600
+
+status_failure_ping = 1
+issue_not_addressed_ping = 2
+issue_not_assigned_to_pr_author_ping = 3
+no_project_author_is_core_ping = 4
+no_project_author_is_core_close_pr = 5
+no_project_author_unknown_close_pr = 6
+project_confirmation_timeout = 7
+review_request_ping = 8
+private_review_reminder_ping = 9
+public_review_reminder_ping = 10
+public_review_reminder_delay = 11
+min_reviewers = 2
+blocked_labels = ["A1-onice", "A3-inprogress"]
+merge_method = "merge"
+core_sorting_repo_name = "core-sorting"
+logs_room_id = "!logs:matrix.parity.io"
+process_matching_repos = ["polkadot=labels", "substrate=columns"]
+command_denylist = ["mallory"]
+command_allowlists = ["bot merge force=gavofyork", "Bot Merge Force=rphmeier"]
+release_notes_categories = ["B1-runtime=Runtime", "B3-client = Client"]
+size_labels = ["size/L=500", "size/S=10", "size/XS=0"]
+
+[matrix]
+homeserver = "https://matrix.parity.io"
+access_token = "${MATRIX_TOKEN}"
+default_channel_id = "!default:matrix.parity.io"
+silent = true
+
+[burnin]
+room_id = "!burnin:matrix.parity.io"
+gitlab_host = "gitlab.parity.io"
+gitlab_project = "parity/infrastructure/burnin"
+gitlab_token = "${GITLAB_TOKEN}"
+gitlab_webhook_secret = "${GITLAB_TOKEN}"
+max_sentries = 1
+
+[[merge_freezes]]
+repo = "polkadot"
+branch = "release-*"
+end = "2020-11-08T00:00:00Z"
+reason = "Release v0.8.26 is being cut"
+
+[[merge_freezes]]
+repo = "substrate"
+until_further_notice = true
+reason = "Audit"
+
+[[performance_gates]]
+repo = "substrate"
+benchmark_command = "./bench.sh --json"
+regression_threshold = 1.5
+
+[[org_overrides]]
+org = "ParityTech-Stg"
+min_reviewers = 1
+merge_method = "rebase"
+"#;
+
+	fn secrets() -> Vec<(String, String)> {
+		vec![
+			("WEBHOOK_SECRET_VALUE", "webhook-secret".to_string()),
+			("KEY_PATH", key_path()),
+			("MATRIX_TOKEN", "matrix-token".to_string()),
+			("GITLAB_TOKEN", "gitlab-token".to_string()),
+		]
+		.into_iter()
+		.map(|(k, v)| (k.to_string(), v))
+		.collect()
+	}
+
+	#[test]
+	fn test_from_file() {
+		let config =
+			Config::from_source(&source(&secrets(), Some(FILE))).unwrap();
+
+		assert_eq!(config.main.webhook_secret, "webhook-secret");
+		assert_eq!(config.main.webhook_port, 8080);
+		assert_eq!(config.main.private_key, b"private key");
+		assert_eq!(config.main.matrix_homeserver, "https://matrix.parity.io");
+		assert_eq!(config.main.matrix_access_token, "matrix-token");
+		assert!(config.main.matrix_silent);
+		assert_eq!(config.main.burnin_gitlab_token, "gitlab-token");
+		assert_eq!(config.main.readiness_max_pending_webhooks, 20);
+		assert_eq!(config.main.http_retry_policy, RetryPolicy::default());
+
+		assert_eq!(config.bot.min_reviewers, 2);
+		assert_eq!(
+			config.bot.blocked_labels,
+			vec!["A1-onice", "A3-inprogress"]
+		);
+		assert!(config.bot.ignored_checks.is_empty());
+		assert_eq!(config.bot.merge_method, MergeMethod::Merge);
+		assert_eq!(config.bot.process_cache_ttl, 600);
+		assert_eq!(config.bot.error_comment_window, 3600);
+		assert_eq!(config.bot.force_merge_error_comment_window, 0);
+		assert_eq!(config.bot.checks_debounce_secs, 5);
+		assert_eq!(config.bot.sandbox, Sandbox::default());
+		assert_eq!(config.bot.required_permission, Permission::Write);
+		assert_eq!(config.bot.command_denylist, vec!["mallory"]);
+		assert!(config.bot.command_allowlist.is_empty());
+		assert_eq!(
+			config.bot.command_allowlists["bot merge force"],
+			vec!["gavofyork", "rphmeier"]
+		);
+		assert_eq!(
+			config.bot.release_notes_categories,
+			vec![
+				("B1-runtime".to_string(), "Runtime".to_string()),
+				("B3-client".to_string(), "Client".to_string()),
+			]
+		);
+		assert!(!config.bot.release_notes_draft_release);
+		assert_eq!(
+			config
+				.bot
+				.size_labels
+				.iter()
+				.map(|bucket| (bucket.label.as_str(), bucket.min_lines))
+				.collect::<Vec<_>>(),
+			vec![("size/XS", 0), ("size/S", 10), ("size/L", 500)]
+		);
+		assert_eq!(config.bot.size_ignored_files, vec!["Cargo.lock"]);
+		assert_eq!(config.bot.companion_placeholders, vec![123]);
+		assert_eq!(config.bot.command_prefix, "bot");
+		assert_eq!(config.bot.command_cooldowns, cooldown::default_cooldowns());
+		assert!(config.bot.command_aliases.is_empty());
+		assert_eq!(
+			config.bot.process_matching("polkadot"),
+			ProcessMatching::Labels
+		);
+		assert_eq!(
+			config.bot.process_matching("cumulus"),
+			ProcessMatching::ColumnsThenLabels
+		);
+		assert_eq!(config.bot.burnin_room_id, "!burnin:matrix.parity.io");
+		assert_eq!(config.bot.burnin_limits.max_sentries, 1);
+		assert_eq!(config.bot.burnin_limits.max_fullnodes, 4);
+		assert_eq!(config.bot.burnin_ops_team, None);
+		assert_eq!(config.bot.merge_freezes.len(), 2);
+		assert_eq!(
+			config.bot.merge_freezes[0].branch.as_deref(),
+			Some("release-*")
+		);
+		assert!(config.bot.merge_freezes[1].until_further_notice);
+		assert_eq!(config.bot.performance_gates.len(), 1);
+		assert_eq!(config.bot.performance_gates[0].regression_threshold, 1.5);
+		assert_eq!(config.bot.performance_gates[0].timeout, 3600);
+		let staging = config.bot.for_org("paritytech-stg");
+		assert_eq!(staging.min_reviewers, 1);
+		assert_eq!(staging.merge_method, MergeMethod::Rebase);
+		assert_eq!(staging.blocked_labels, config.bot.blocked_labels);
+		assert_eq!(config.bot.for_org("paritytech").min_reviewers, 2);
+	}
+
+	#[test]
+	fn test_allows_base_branch() {
+		let mut config = BotConfig::default();
+		assert!(config.allows_base_branch("gav-long-lived-feature"));
+		config.allowed_base_branches =
+			vec!["master".to_string(), "release-*".to_string()];
+		for branch in &["master", "release-v0.9.30", "release-"] {
+			assert!(config.allows_base_branch(branch), "{}", branch);
+		}
+		for branch in &[
+			"gav-long-lived-feature",
+			"master-backup",
+			"old-master",
+			"polkadot-release-v0.9.30",
+			"Release-v0.9.30",
+		] {
+			assert!(!config.allows_base_branch(branch), "{}", branch);
+		}
+	}
+
+	#[test]
+	fn test_env_overrides_file() {
+		let mut env = secrets();
+		for (k, v) in &[
+			("MIN_REVIEWERS", "3"),
+			("MATRIX_HOMESERVER", "https://matrix.org"),
+			("BLOCKED_LABELS", ""),
+			("HTTP_RETRY_BUDGET_SECS", "60"),
+			("COMPANION_PLACEHOLDERS", "123, 1234"),
+			("COMMAND_PREFIX", "processbot"),
+			("COMMAND_ALIASES", "/, pb"),
+			("COMMAND_COOLDOWNS", "Bot Rebase=60, bot merge force=10"),
+			("SCRATCH_DIR", "/scratch"),
+			("CARGO_TIMEOUT_SECS", "3600"),
+			(
+				"COMPANION_BRANCHES",
+				"polkadot=release-*, polkadot=rococo-v1, cumulus=polkadot-v*",
+			),
+		] {
+			env.push((k.to_string(), v.to_string()));
+		}
+		let config = Config::from_source(&source(&env, Some(FILE))).unwrap();
+
+		assert_eq!(config.bot.min_reviewers, 3);
+		assert_eq!(config.main.matrix_homeserver, "https://matrix.org");
+		assert!(config.bot.blocked_labels.is_empty());
+		assert_eq!(config.bot.companion_placeholders, vec![123, 1234]);
+		assert_eq!(config.bot.command_prefix, "processbot");
+		assert_eq!(config.bot.command_aliases, vec!["/", "pb"]);
+		assert_eq!(
+			config.bot.sandbox.clone_dir("polkadot"),
+			"/scratch/polkadot"
+		);
+		assert_eq!(config.bot.sandbox.cargo_timeout, Duration::from_secs(3600));
+		assert_eq!(config.bot.sandbox.git_timeout, Duration::from_secs(600));
+		assert_eq!(
+			config.bot.command_cooldowns,
+			vec![
+				("bot rebase".to_string(), 60),
+				("bot merge force".to_string(), 10)
+			]
+		);
+		assert_eq!(
+			config.bot.companion_branches["polkadot"],
+			vec!["release-*", "rococo-v1"]
+		);
+		assert_eq!(
+			config.bot.companion_branches["cumulus"],
+			vec!["polkadot-v*"]
+		);
+		assert_eq!(
+			config.main.http_retry_policy.budget,
+			Duration::from_secs(60)
+		);
+		assert_eq!(config.main.http_retry_policy.max_retries, 5);
+		// keys only in the file are still used
+		assert_eq!(config.main.db_path, "/data/db");
+	}
+
+	#[test]
+	fn test_fingerprint() {
+		let fingerprint = |env: &[(String, String)]| {
+			Config::from_source(&source(env, Some(FILE)))
+				.unwrap()
+				.fingerprint
+		};
+		let original = fingerprint(&secrets());
+		assert_eq!(original.len(), 12);
+		assert_eq!(fingerprint(&secrets()), original);
+
+		// other secrets
+		let mut env = secrets();
+		for (key, value) in env.iter_mut() {
+			if key != "KEY_PATH" {
+				value.push_str("-rotated");
+			}
+		}
+		env.push(("AUDIT_TOKEN".to_string(), "audit".to_string()));
+		assert_eq!(fingerprint(&env), original);
+
+		let mut env = secrets();
+		env.push(("MIN_REVIEWERS".to_string(), "3".to_string()));
+		assert_ne!(fingerprint(&env), original);
+	}
+
+	#[test]
+	fn test_env_only() {
+		// every key of the file, as an environment variable
+		let env = source(&secrets(), Some(FILE))
+			.file
+			.into_iter()
+			.collect::<Vec<_>>();
+		let config = Config::from_source(&source(&env, None)).unwrap();
+
+		assert_eq!(config.main.webhook_secret, "webhook-secret");
+		assert_eq!(
+			config.bot.blocked_labels,
+			vec!["A1-onice", "A3-inprogress"]
+		);
+		assert_eq!(
+			config.bot.process_matching("substrate"),
+			ProcessMatching::Columns
+		);
+		assert_eq!(config.bot.merge_freezes.len(), 2);
+	}
+
+	#[test]
+	fn test_templates_dir() {
+		let dir = std::env::temp_dir().join(format!(
+			"processbot-config-templates-{}",
+			std::process::id()
+		));
+		let _ = std::fs::remove_dir_all(&dir);
+		std::fs::create_dir_all(&dir).unwrap();
+		std::fs::write(
+			dir.join("burnin_cancel_failed.md"),
+			"@{{requested_by}} ask {{burnin_contact}}.",
+		)
+		.unwrap();
+		let mut env = secrets();
+		env.push(("TEMPLATES_DIR".to_string(), dir.display().to_string()));
+		env.push(("BURNIN_CONTACT".to_string(), "#ops".to_string()));
+		let config = Config::from_source(&source(&env, Some(FILE))).unwrap();
+		assert_eq!(
+			config.bot.templates.render(
+				templates::BURNIN_CANCEL_FAILED,
+				&[("requested_by", "alice")]
+			),
+			"@alice ask #ops."
+		);
+
+		// a placeholder the template is not rendered with fails the check
+		std::fs::write(dir.join("burnin_prompt.md"), "{{troubleshoot_url}}")
+			.unwrap();
+		let errors = errors(Config::from_source(&source(&env, Some(FILE))));
+		assert_eq!(
+			errors,
+			vec![format!(
+				"TEMPLATES_DIR: {}: unknown placeholders {{{{troubleshoot_url}}}}",
+				dir.join("burnin_prompt.md").display()
+			)]
+		);
+		let _ = std::fs::remove_dir_all(&dir);
+	}
+
+	#[test]
+	fn test_all_errors_reported() {
+		let errors = errors(Config::from_source(&source(
+			&[
+				("MAIN_TICK_SECS", "often"),
+				("MERGE_METHOD", "fast-forward"),
+				("REQUIRED_PERMISSION", "none"),
+				("COMPANION_PLACEHOLDERS", "123,NNNN"),
+				("COMMAND_PREFIX", "process bot"),
+				("COMMAND_COOLDOWNS", "bot rebase=soon, bot merge"),
+				("COMPANION_BRANCHES", "polkadot"),
+			],
+			Some(
+				r#"
+min_reviewer = 1
+webhook_secret = "${UNSET_SECRET}"
+process_matching_repos = "polkadot"
+"#,
+			),
+		)));
+		for expected in &[
+			"PRIVATE_KEY_PATH: missing",
+			"DB_PATH: missing",
+			"MATRIX_HOMESERVER: missing",
+			"MIN_REVIEWERS: missing",
+			"BURNIN_ROOM_ID: missing",
+			"WEBHOOK_SECRET: environment variable UNSET_SECRET is not set",
+			"PROCESS_MATCHING_REPOS: expected repo=mode, got polkadot",
+			"COMPANION_BRANCHES: expected repo=branch, got polkadot",
+			"COMMAND_PREFIX: `process bot` is not a valid command prefix",
+			"COMMAND_COOLDOWNS: expected command=seconds, got bot merge",
+			"min_reviewer: unknown key",
+		] {
+			assert!(
+				errors.iter().any(|e| e == expected),
+				"{} not in {:?}",
+				expected,
+				errors
+			);
 		}
+		assert!(errors.iter().any(|e| e.starts_with("MAIN_TICK_SECS: ")));
+		assert!(errors.iter().any(|e| e.starts_with("MERGE_METHOD: ")));
+		assert!(errors
+			.iter()
+			.any(|e| e.starts_with("REQUIRED_PERMISSION: ")));
+		assert!(errors
+			.iter()
+			.any(|e| e.starts_with("COMPANION_PLACEHOLDERS: ")));
+		assert!(errors
+			.iter()
+			.any(|e| e.starts_with("COMMAND_COOLDOWNS: invalid digit")));
+		// WEBHOOK_SECRET is set, even if to an invalid value
+		assert!(!errors.iter().any(|e| e == "WEBHOOK_SECRET: missing"));
 	}
 }
@@ -1,8 +1,14 @@
 pub const AUTO_MERGE_REQUEST: &str = "bot merge";
 pub const AUTO_MERGE_FORCE: &str = "bot merge force";
 pub const AUTO_MERGE_CANCEL: &str = "bot merge cancel";
-
-pub const AUTO_MERGE_FAILED: &str = "Cannot merge; please ensure the pull request is mergeable and has approval from the project owner or at least {min_reviewers} core devs.";
+pub const AUTO_MERGE_CONFIRM: &str = "bot merge confirm";
+pub const AUTO_MERGE_SKIP_BENCH: &str = "bot merge skip-bench";
+pub const AUTO_MERGE_AT: &str = "bot merge at";
+pub const AUTOMERGE_OFF: &str = "bot automerge off";
+pub const MERGE_FREEZE: &str = "bot freeze";
+pub const MERGE_UNFREEZE: &str = "bot unfreeze";
+
+pub const AUTO_MERGE_FAILED: &str = "Cannot merge; please ensure the pull request is mergeable and has approval from a project owner, a delegated reviewer or at least {min_reviewers} core devs.";
 pub const AUTO_MERGE_CHECKS_FAILED: &str = "Checks failed; cannot auto-merge.";
 pub const AUTO_MERGE_CHECKS_ERROR: &str =
 	"Checks returned an error; cannot auto-merge.";
@@ -11,7 +17,21 @@ pub const AUTO_MERGE_INVALIDATED: &str =
 
 pub const COMPARE_RELEASE_REQUEST: &str = "bot compare substrate";
 pub const REBASE: &str = "bot rebase";
+pub const BACKPORT: &str = "bot backport";
+pub const CHERRY_PICK: &str = "bot cherry-pick";
+pub const REFRESH_PROCESS: &str = "bot refresh-process";
 pub const BURNIN_REQUEST: &str = "bot burnin";
+pub const BURNIN_STATUS: &str = "bot burnin status";
+pub const BURNIN_CANCEL: &str = "bot burnin cancel";
+pub const BOT_ADMIN: &str = "bot admin";
+pub const BOT_STATS: &str = "bot stats";
+pub const BOT_IGNORE: &str = "bot ignore";
+pub const BOT_UNIGNORE: &str = "bot unignore";
+pub const RELEASE_NOTES: &str = "bot release-notes";
+pub const BOT_HELP: &str = "bot help";
+pub const BOT_CHECK: &str = "bot check";
+pub const BOT_PROCESS: &str = "bot process";
+pub const BOT_VERSION: &str = "bot version";
 
 pub const FEATURES_KEY: &str = "features";
 
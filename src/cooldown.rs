@@ -0,0 +1,156 @@
+use rocksdb::DB;
+use snafu::ResultExt;
+
+use crate::{constants::*, error::*, Result};
+
+const KEY_PREFIX: &str = "command-cooldown/";
+
+/// Commands which are never throttled, as taking back a command must work
+/// however soon after it.
+pub const NEVER_THROTTLED: [&str; 2] = [AUTO_MERGE_CANCEL, BURNIN_CANCEL];
+
+/// The windows used if `COMMAND_COOLDOWNS` is not set.
+pub fn default_cooldowns() -> Vec<(String, i64)> {
+	vec![
+		(REBASE.to_string(), 30),
+		(AUTO_MERGE_REQUEST.to_string(), 30),
+		(BURNIN_STATUS.to_string(), 5),
+	]
+}
+
+fn key(owner: &str, repo_name: &str, number: i64, command: &str) -> String {
+	format!(
+		"{}{}/{}/{}/{}",
+		KEY_PREFIX,
+		owner.to_lowercase(),
+		repo_name.to_lowercase(),
+		number,
+		command
+	)
+}
+
+/// The command as compared with an earlier one: lower case, with its words
+/// separated by single spaces.
+fn normalize(command: &str) -> String {
+	command
+		.split_whitespace()
+		.collect::<Vec<_>>()
+		.join(" ")
+		.to_lowercase()
+}
+
+/// The seconds within which the command is not run again, from the window of
+/// the longest of `cooldowns` it starts with, eg. `bot rebase` for
+/// `bot rebase onto release-v0.9.30`.
+pub fn window(cooldowns: &[(String, i64)], command: &str) -> Option<i64> {
+	let command = normalize(command);
+	if NEVER_THROTTLED.iter().any(|never| {
+		command == *never || command.starts_with(&format!("{} ", never))
+	}) {
+		return None;
+	}
+	cooldowns
+		.iter()
+		.filter(|(prefix, _)| {
+			command == *prefix || command.starts_with(&format!("{} ", prefix))
+		})
+		.max_by_key(|(prefix, _)| prefix.len())
+		.map(|(_, secs)| *secs)
+		.filter(|secs| *secs > 0)
+}
+
+/// Whether the same command was run on the PR within its window before `now`,
+/// in seconds.  If not, it is recorded as run `now`.
+pub fn throttled(
+	db: &DB,
+	cooldowns: &[(String, i64)],
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+	command: &str,
+	now: i64,
+) -> Result<bool> {
+	let window = match window(cooldowns, command) {
+		Some(window) => window,
+		None => return Ok(false),
+	};
+	let key = key(owner, repo_name, number, &normalize(command));
+	let last = db
+		.get(key.as_bytes())
+		.context(Db)?
+		.and_then(|bytes| String::from_utf8(bytes).ok())
+		.and_then(|last| last.parse::<i64>().ok());
+	if last.is_some_and(|last| now - last < window) {
+		return Ok(true);
+	}
+	db.put(key.as_bytes(), now.to_string().as_bytes())
+		.context(Db)?;
+	Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::db::TempDb;
+
+	#[test]
+	fn test_window() {
+		let cooldowns = default_cooldowns();
+		assert_eq!(window(&cooldowns, "bot rebase"), Some(30));
+		assert_eq!(
+			window(&cooldowns, " Bot  Rebase onto release-v0.9.30"),
+			Some(30)
+		);
+		assert_eq!(window(&cooldowns, "bot merge"), Some(30));
+		assert_eq!(window(&cooldowns, "bot burnin status"), Some(5));
+		assert_eq!(window(&cooldowns, "bot burnin"), None);
+		assert_eq!(window(&cooldowns, "bot rebased"), None);
+		// never throttled, however configured
+		let all = vec![
+			("bot merge".to_string(), 30),
+			("bot merge cancel".to_string(), 30),
+			("bot burnin".to_string(), 30),
+		];
+		for command in &NEVER_THROTTLED {
+			assert_eq!(window(&all, command), None, "{}", command);
+			assert_eq!(window(&cooldowns, command), None, "{}", command);
+		}
+		assert_eq!(window(&all, "bot merge force"), Some(30));
+		assert_eq!(window(&[("bot merge".to_string(), 0)], "bot merge"), None);
+	}
+
+	#[test]
+	fn test_throttled() {
+		let db = TempDb::new("cooldown");
+		let cooldowns = default_cooldowns();
+		let throttled = |command: &str, number: i64, now: i64| {
+			throttled(
+				&db,
+				&cooldowns,
+				"paritytech",
+				"substrate",
+				number,
+				command,
+				now,
+			)
+			.unwrap()
+		};
+		assert!(!throttled("bot rebase", 1, 1000));
+		// posted twice
+		assert!(throttled("bot rebase", 1, 1000));
+		assert!(throttled("Bot Rebase ", 1, 1029));
+		// the window ends 30s after the run, not after the throttled ones
+		assert!(!throttled("bot rebase", 1, 1030));
+		assert!(throttled("bot rebase", 1, 1059));
+		// another PR or command
+		assert!(!throttled("bot rebase", 2, 1030));
+		assert!(!throttled("bot rebase onto release-v0.9.30", 1, 1030));
+		assert!(!throttled("bot burnin status", 1, 1000));
+		assert!(throttled("bot burnin status", 1, 1004));
+		assert!(!throttled("bot burnin status", 1, 1005));
+		for _ in 0..2 {
+			assert!(!throttled("bot merge cancel", 1, 1000));
+			assert!(!throttled("bot burnin", 1, 1000));
+		}
+	}
+}
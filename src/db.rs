@@ -0,0 +1,338 @@
+use rocksdb::{
+	ColumnFamily, DBIterator, Direction, IteratorMode, Options, WriteBatch, DB,
+};
+use snafu::{OptionExt, ResultExt};
+use std::path::Path;
+
+use crate::{audit, burnin, error::*, queue, Result};
+
+/// The family of records which have none of their own.
+pub const DEFAULT: &str = "default";
+/// Pending merges and their index.
+pub const MERGE_REQUESTS: &str = "merge_requests";
+/// The audit log of privileged bot actions.
+pub const AUDIT: &str = "audit";
+/// Burn-in requests.
+pub const BURNIN: &str = "burnin";
+/// How requested merges ended, for `bot stats`.
+pub const MERGE_OUTCOMES: &str = "merge_outcomes";
+
+/// The column families besides the default one, which keeps the records of
+/// other kinds.
+pub const FAMILIES: [&str; 4] = [MERGE_REQUESTS, AUDIT, BURNIN, MERGE_OUTCOMES];
+
+/// Every column family, the default one first.
+pub fn all_families() -> impl Iterator<Item = &'static str> {
+	std::iter::once(DEFAULT).chain(FAMILIES.iter().copied())
+}
+
+/// The family of a record an older bot kept in the default family.
+fn family_of(key: &[u8]) -> Option<&'static str> {
+	if queue::is_queue_key(key)
+		|| key.starts_with(queue::INDEX_PREFIX.as_bytes())
+	{
+		Some(MERGE_REQUESTS)
+	} else if key.starts_with(audit::KEY_PREFIX.as_bytes()) {
+		Some(AUDIT)
+	} else if key.starts_with(burnin::RECORD_KEY_PREFIX.as_bytes()) {
+		Some(BURNIN)
+	} else {
+		None
+	}
+}
+
+/// Open the database with its column families, creating it if needed.
+/// Records an older bot kept in the default family are moved into theirs.
+pub fn open(path: impl AsRef<Path>) -> Result<DB> {
+	let mut options = Options::default();
+	options.create_if_missing(true);
+	options.create_missing_column_families(true);
+	let db = DB::open_cf(&options, path, FAMILIES).context(Db)?;
+	let moved = move_to_families(&db)?;
+	if moved > 0 {
+		log::info!("Moved {} records into their column families", moved);
+	}
+	Ok(db)
+}
+
+/// Returns how many records were moved.
+fn move_to_families(db: &DB) -> Result<usize> {
+	atomically(db, |tx| {
+		for (key, value) in db.iterator(IteratorMode::Start) {
+			if let Some(name) = family_of(&key) {
+				tx.put(name, &key, &value)?;
+				tx.delete(DEFAULT, &key)?;
+			}
+		}
+		// each record is both put and deleted
+		Ok(tx.len() / 2)
+	})
+}
+
+/// A column family of the database.
+#[derive(Clone, Copy)]
+pub struct Family<'a> {
+	db: &'a DB,
+	cf: &'a ColumnFamily,
+}
+
+/// Fails if the database was not opened with `open`.
+pub fn family<'a>(db: &'a DB, name: &str) -> Result<Family<'a>> {
+	let cf = db.cf_handle(name).context(Message {
+		msg: format!("Missing column family {}", name),
+	})?;
+	Ok(Family { db, cf })
+}
+
+impl<'a> Family<'a> {
+	pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+		self.db.get_cf(self.cf, key).context(Db)
+	}
+
+	pub fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+		self.db.put_cf(self.cf, key, value).context(Db)
+	}
+
+	pub fn delete(&self, key: &[u8]) -> Result<()> {
+		self.db.delete_cf(self.cf, key).context(Db)
+	}
+
+	pub fn iter(&self) -> DBIterator<'a> {
+		self.db.iterator_cf(self.cf, IteratorMode::Start)
+	}
+
+	/// The records from `start` on, while their keys start with `prefix`.
+	pub fn iter_from<'b>(
+		&self,
+		start: &[u8],
+		prefix: &'b [u8],
+	) -> impl Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'b
+	where
+		'a: 'b,
+	{
+		self.db
+			.iterator_cf(self.cf, IteratorMode::From(start, Direction::Forward))
+			.take_while(move |(key, _)| key.starts_with(prefix))
+	}
+
+	/// The records whose keys start with `prefix`.
+	pub fn iter_prefix<'b>(
+		&self,
+		prefix: &'b [u8],
+	) -> impl Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'b
+	where
+		'a: 'b,
+	{
+		self.iter_from(prefix, prefix)
+	}
+}
+
+/// Changes to several records, written all at once by `commit`.  Dropping it
+/// before then writes none of them.
+pub struct Transaction<'a> {
+	db: &'a DB,
+	batch: WriteBatch,
+}
+
+impl<'a> Transaction<'a> {
+	pub fn new(db: &'a DB) -> Self {
+		Transaction {
+			db,
+			batch: WriteBatch::default(),
+		}
+	}
+
+	/// The database to read from, which does not see the changes until they
+	/// are committed.
+	pub fn db(&self) -> &'a DB {
+		self.db
+	}
+
+	pub fn put(&mut self, name: &str, key: &[u8], value: &[u8]) -> Result<()> {
+		self.batch.put_cf(family(self.db, name)?.cf, key, value);
+		Ok(())
+	}
+
+	pub fn delete(&mut self, name: &str, key: &[u8]) -> Result<()> {
+		self.batch.delete_cf(family(self.db, name)?.cf, key);
+		Ok(())
+	}
+
+	/// How many changes there are.
+	pub fn len(&self) -> usize {
+		self.batch.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.batch.is_empty()
+	}
+
+	pub fn commit(self) -> Result<()> {
+		if self.batch.is_empty() {
+			return Ok(());
+		}
+		self.db.write(self.batch).context(Db)
+	}
+}
+
+/// Run `f` on a new transaction, which is committed only if `f` succeeds.
+pub fn atomically<T>(
+	db: &DB,
+	f: impl FnOnce(&mut Transaction) -> Result<T>,
+) -> Result<T> {
+	let mut tx = Transaction::new(db);
+	let value = f(&mut tx)?;
+	tx.commit()?;
+	Ok(value)
+}
+
+/// A database in a temporary directory for tests, opened like the bot's own
+/// with `open`, and removed once dropped.
+#[cfg(test)]
+pub struct TempDb {
+	db: Option<DB>,
+	path: std::path::PathBuf,
+}
+
+#[cfg(test)]
+impl TempDb {
+	/// `name` keeps apart the databases of tests running at once.
+	pub fn new(name: &str) -> Self {
+		let path = std::env::temp_dir().join(format!(
+			"processbot-{}-{}",
+			name,
+			std::process::id()
+		));
+		let _ = std::fs::remove_dir_all(&path);
+		let db = open(&path).expect("temporary database");
+		Self { db: Some(db), path }
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TempDb {
+	type Target = DB;
+
+	fn deref(&self) -> &DB {
+		self.db.as_ref().expect("open until dropped")
+	}
+}
+
+#[cfg(test)]
+impl Drop for TempDb {
+	fn drop(&mut self) {
+		// closed before its files are removed
+		drop(self.db.take());
+		let _ = std::fs::remove_dir_all(&self.path);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_open() {
+		let path = std::env::temp_dir()
+			.join(format!("processbot-db-{}", std::process::id()));
+		let _ = std::fs::remove_dir_all(&path);
+		let sha = "0123456789abcdef0123456789abcdef01234567";
+		{
+			// as left by an older bot
+			let db = DB::open_default(&path).unwrap();
+			db.put(sha, b"merge").unwrap();
+			db.put("idx/paritytech/substrate/1", sha).unwrap();
+			db.put("audit/00000000000000000001/0000000000", b"{}")
+				.unwrap();
+			db.put("burnin/paritytech/polkadot/2", b"burnin").unwrap();
+			db.put("merge-freeze/paritytech/substrate", b"freeze")
+				.unwrap();
+		}
+
+		let db = open(&path).unwrap();
+		let keys = |name: &str| {
+			family(&db, name)
+				.unwrap()
+				.iter()
+				.map(|(key, _)| String::from_utf8(key.to_vec()).unwrap())
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(
+			keys(MERGE_REQUESTS),
+			vec![sha, "idx/paritytech/substrate/1"]
+		);
+		assert_eq!(keys(AUDIT), vec!["audit/00000000000000000001/0000000000"]);
+		assert_eq!(keys(BURNIN), vec!["burnin/paritytech/polkadot/2"]);
+		let default = db
+			.iterator(IteratorMode::Start)
+			.map(|(key, _)| String::from_utf8(key.to_vec()).unwrap())
+			.collect::<Vec<_>>();
+		assert_eq!(default, vec!["merge-freeze/paritytech/substrate"]);
+		assert_eq!(
+			family(&db, MERGE_REQUESTS)
+				.unwrap()
+				.get(sha.as_bytes())
+				.unwrap()
+				.as_deref(),
+			Some(&b"merge"[..])
+		);
+
+		// the families stay apart
+		let burnin = family(&db, BURNIN).unwrap();
+		burnin.put(b"idx/other", b"burnin").unwrap();
+		assert_eq!(
+			family(&db, MERGE_REQUESTS)
+				.unwrap()
+				.iter_prefix(b"idx/")
+				.count(),
+			1
+		);
+		assert_eq!(burnin.iter_prefix(b"idx/").count(), 1);
+
+		// changes are written all at once or not at all
+		let merge = |tx: &mut Transaction| {
+			tx.put(
+				MERGE_REQUESTS,
+				b"idx/paritytech/polkadot/2",
+				sha.as_bytes(),
+			)?;
+			tx.delete(MERGE_REQUESTS, sha.as_bytes())
+		};
+		let mut tx = Transaction::new(&db);
+		merge(&mut tx).unwrap();
+		assert_eq!(tx.len(), 2);
+		// as if the bot crashed before committing
+		drop(tx);
+		assert_eq!(
+			keys(MERGE_REQUESTS),
+			vec![sha, "idx/paritytech/substrate/1"]
+		);
+		assert!(atomically(&db, |tx| {
+			merge(tx)?;
+			Err::<(), _>(Error::Message {
+				msg: "failed".to_string(),
+			})
+		})
+		.is_err());
+		assert_eq!(
+			keys(MERGE_REQUESTS),
+			vec![sha, "idx/paritytech/substrate/1"]
+		);
+		atomically(&db, merge).unwrap();
+		assert_eq!(
+			keys(MERGE_REQUESTS),
+			vec!["idx/paritytech/polkadot/2", "idx/paritytech/substrate/1"]
+		);
+
+		// moving happens once
+		drop(db);
+		let db = open(&path).unwrap();
+		assert_eq!(move_to_families(&db).unwrap(), 0);
+		drop(db);
+		let _ = std::fs::remove_dir_all(&path);
+	}
+}
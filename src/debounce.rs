@@ -0,0 +1,146 @@
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Coalesces the burst of status and check webhooks received for a commit as
+/// its CI finishes, so that its checks are evaluated once rather than for
+/// each.  Those received within `window_secs` of the last evaluation are
+/// evaluated once more when the window is over, as they may have changed the
+/// outcome.
+///
+/// It is kept outside the lock on `AppState`, so that what is due can be
+/// found without waiting for the webhook being handled.
+pub struct ChecksDebounce {
+	window_secs: i64,
+	entries: Mutex<HashMap<String, Entry>>,
+	coalesced: AtomicU64,
+}
+
+struct Entry {
+	evaluated_at: i64,
+	/// whether an event was coalesced since
+	recheck: bool,
+}
+
+impl ChecksDebounce {
+	/// Nothing is coalesced with a window of 0.
+	pub fn new(window_secs: i64) -> Self {
+		Self {
+			window_secs,
+			entries: Mutex::new(HashMap::new()),
+			coalesced: AtomicU64::new(0),
+		}
+	}
+
+	/// Whether the checks of `sha` are to be evaluated for an event received
+	/// at `now`; if not, they are evaluated again once `due`.
+	pub fn admit(&self, sha: &str, now: i64) -> bool {
+		let window_secs = self.window_secs;
+		if window_secs <= 0 {
+			return true;
+		}
+		let mut entries = self.entries.lock();
+		// entries only matter within their window, or until rechecked
+		entries.retain(|_, entry| {
+			entry.recheck || now < entry.evaluated_at + window_secs
+		});
+		match entries.get_mut(sha) {
+			Some(entry) if now < entry.evaluated_at + window_secs => {
+				entry.recheck = true;
+				self.coalesced.fetch_add(1, Ordering::Relaxed);
+				false
+			}
+			_ => {
+				entries.insert(
+					sha.to_string(),
+					Entry {
+						evaluated_at: now,
+						recheck: false,
+					},
+				);
+				true
+			}
+		}
+	}
+
+	/// The commits with coalesced events whose window is over at `now`,
+	/// whose checks are to be evaluated again.  Each is returned once, and
+	/// counts as evaluated at `now`.
+	pub fn due(&self, now: i64) -> Vec<String> {
+		let mut due = self
+			.entries
+			.lock()
+			.iter_mut()
+			.filter(|(_, entry)| {
+				entry.recheck && now >= entry.evaluated_at + self.window_secs
+			})
+			.map(|(sha, entry)| {
+				entry.evaluated_at = now;
+				entry.recheck = false;
+				sha.clone()
+			})
+			.collect::<Vec<_>>();
+		due.sort();
+		due
+	}
+
+	/// How many events were coalesced, for `/health`.
+	pub fn coalesced(&self) -> u64 {
+		self.coalesced.load(Ordering::Relaxed)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const SHA: &str = "0123456789abcdef0123456789abcdef01234567";
+
+	#[test]
+	fn test_burst() {
+		let debounce = ChecksDebounce::new(5);
+		let mut evaluations = 0;
+		// 20 statuses within a second of CI finishing
+		for i in 0..20 {
+			if debounce.admit(SHA, 1000 + i / 10) {
+				evaluations += 1;
+			}
+		}
+		assert_eq!(evaluations, 1);
+		assert_eq!(debounce.coalesced(), 19);
+		assert!(debounce.due(1004).is_empty());
+		assert_eq!(debounce.due(1005), vec![SHA.to_string()]);
+		assert!(debounce.due(1010).is_empty());
+
+		// a late arrival within the window of the follow-up
+		assert!(!debounce.admit(SHA, 1007));
+		assert!(debounce.due(1009).is_empty());
+		assert_eq!(debounce.due(1010), vec![SHA.to_string()]);
+
+		// then a new burst is evaluated at once
+		assert!(debounce.admit(SHA, 1020));
+		assert!(debounce.due(1030).is_empty());
+	}
+
+	#[test]
+	fn test_commits_apart() {
+		let debounce = ChecksDebounce::new(5);
+		let other = "f".repeat(40);
+		assert!(debounce.admit(SHA, 1000));
+		assert!(debounce.admit(&other, 1001));
+		assert!(!debounce.admit(SHA, 1002));
+		assert_eq!(debounce.due(1005), vec![SHA.to_string()]);
+		// expired entries are dropped
+		assert!(debounce.admit(SHA, 1100));
+		assert_eq!(debounce.entries.lock().len(), 1);
+	}
+
+	#[test]
+	fn test_disabled() {
+		let debounce = ChecksDebounce::new(0);
+		assert!(debounce.admit(SHA, 1000));
+		assert!(debounce.admit(SHA, 1000));
+		assert!(debounce.due(1000).is_empty());
+		assert_eq!(debounce.entries.lock().len(), 0);
+	}
+}
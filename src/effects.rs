@@ -0,0 +1,428 @@
+use crate::github::MergeMethod;
+use parking_lot::Mutex;
+use std::fmt;
+
+/// A change the bot makes to a repository or to Gitlab.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Effect {
+	Merge {
+		owner: String,
+		repo_name: String,
+		number: i64,
+		head_sha: String,
+		merge_method: MergeMethod,
+	},
+	Rebase {
+		owner: String,
+		repo_name: String,
+		number: i64,
+		branch: String,
+		/// the branch merged into `branch`
+		onto: String,
+	},
+	/// Cherry-picking a merged PR onto a release branch and opening a PR with
+	/// it.
+	Backport {
+		owner: String,
+		repo_name: String,
+		number: i64,
+		branch: String,
+	},
+	/// Cherry-picking a commit onto a branch and opening a PR with it, for
+	/// `bot cherry-pick` commented on PR `number`.
+	CherryPick {
+		owner: String,
+		repo_name: String,
+		number: i64,
+		sha: String,
+		branch: String,
+	},
+	/// Changing the branch a PR is to be merged into, after `bot rebase onto`.
+	UpdatePullRequestBase {
+		owner: String,
+		repo_name: String,
+		number: i64,
+		base: String,
+	},
+	/// Updating the branch of a PR labelled to be kept up to date.
+	UpdateBranch {
+		owner: String,
+		repo_name: String,
+		number: i64,
+	},
+	/// Merging master into the head branch of a companion PR and pushing it.
+	CompanionUpdate {
+		owner: String,
+		repo_name: String,
+		number: i64,
+		branch: String,
+	},
+	CreateGitlabFile {
+		owner: String,
+		repo_name: String,
+		number: i64,
+		commit_message: String,
+	},
+	DeleteGitlabFile {
+		owner: String,
+		repo_name: String,
+		number: i64,
+		path: String,
+	},
+	/// Labelling a PR by the paths it changes.
+	AddLabels {
+		owner: String,
+		repo_name: String,
+		number: i64,
+		labels: Vec<String>,
+	},
+	/// Removing the size label a PR has outgrown.
+	RemoveLabel {
+		owner: String,
+		repo_name: String,
+		number: i64,
+		label: String,
+	},
+	/// Creating a draft release with the notes of `bot release-notes`.
+	CreateDraftRelease {
+		owner: String,
+		repo_name: String,
+		number: i64,
+		tag_name: String,
+	},
+	/// Asking the process owners of a PR for their review.
+	RequestReviewers {
+		owner: String,
+		repo_name: String,
+		number: i64,
+		users: Vec<String>,
+	},
+}
+
+impl Effect {
+	/// The PR this effect was requested for.
+	pub fn pull_request(&self) -> (&str, &str, i64) {
+		match self {
+			Effect::Merge {
+				owner,
+				repo_name,
+				number,
+				..
+			}
+			| Effect::Rebase {
+				owner,
+				repo_name,
+				number,
+				..
+			}
+			| Effect::Backport {
+				owner,
+				repo_name,
+				number,
+				..
+			}
+			| Effect::CherryPick {
+				owner,
+				repo_name,
+				number,
+				..
+			}
+			| Effect::UpdatePullRequestBase {
+				owner,
+				repo_name,
+				number,
+				..
+			}
+			| Effect::UpdateBranch {
+				owner,
+				repo_name,
+				number,
+			}
+			| Effect::CompanionUpdate {
+				owner,
+				repo_name,
+				number,
+				..
+			}
+			| Effect::CreateGitlabFile {
+				owner,
+				repo_name,
+				number,
+				..
+			}
+			| Effect::DeleteGitlabFile {
+				owner,
+				repo_name,
+				number,
+				..
+			}
+			| Effect::AddLabels {
+				owner,
+				repo_name,
+				number,
+				..
+			}
+			| Effect::RemoveLabel {
+				owner,
+				repo_name,
+				number,
+				..
+			}
+			| Effect::CreateDraftRelease {
+				owner,
+				repo_name,
+				number,
+				..
+			}
+			| Effect::RequestReviewers {
+				owner,
+				repo_name,
+				number,
+				..
+			} => (owner, repo_name, *number),
+		}
+	}
+}
+
+impl fmt::Display for Effect {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Effect::Merge {
+				owner,
+				repo_name,
+				number,
+				head_sha,
+				merge_method,
+			} => write!(
+				f,
+				"merge {}/{}#{} at {} ({})",
+				owner,
+				repo_name,
+				number,
+				head_sha,
+				merge_method.as_str()
+			),
+			Effect::Rebase {
+				owner,
+				repo_name,
+				number,
+				branch,
+				onto,
+			} => write!(
+				f,
+				"merge {} into `{}` of {}/{}#{} and push it",
+				onto, branch, owner, repo_name, number
+			),
+			Effect::Backport {
+				owner,
+				repo_name,
+				number,
+				branch,
+			} => write!(
+				f,
+				"backport {}/{}#{} to `{}` and open a PR with it",
+				owner, repo_name, number, branch
+			),
+			Effect::CherryPick {
+				owner,
+				repo_name,
+				sha,
+				branch,
+				..
+			} => write!(
+				f,
+				"cherry-pick {} onto `{}` of {}/{} and open a PR with it",
+				sha, branch, owner, repo_name
+			),
+			Effect::UpdatePullRequestBase {
+				owner,
+				repo_name,
+				number,
+				base,
+			} => write!(
+				f,
+				"change the base of {}/{}#{} to `{}`",
+				owner, repo_name, number, base
+			),
+			Effect::UpdateBranch {
+				owner,
+				repo_name,
+				number,
+			} => write!(
+				f,
+				"update the branch of {}/{}#{} with its base",
+				owner, repo_name, number
+			),
+			Effect::CompanionUpdate {
+				owner,
+				repo_name,
+				number,
+				branch,
+			} => write!(
+				f,
+				"update companion {}/{}#{} by merging master into `{}` and pushing it",
+				owner, repo_name, number, branch
+			),
+			Effect::CreateGitlabFile { commit_message, .. } => {
+				write!(f, "commit a file to Gitlab: {}", commit_message)
+			}
+			Effect::DeleteGitlabFile { path, .. } => {
+				write!(f, "delete `{}` from Gitlab", path)
+			}
+			Effect::AddLabels {
+				owner,
+				repo_name,
+				number,
+				labels,
+			} => write!(
+				f,
+				"label {}/{}#{} {}",
+				owner,
+				repo_name,
+				number,
+				labels
+					.iter()
+					.map(|label| format!("`{}`", label))
+					.collect::<Vec<_>>()
+					.join(", ")
+			),
+			Effect::RemoveLabel {
+				owner,
+				repo_name,
+				number,
+				label,
+			} => write!(
+				f,
+				"unlabel {}/{}#{} `{}`",
+				owner, repo_name, number, label
+			),
+			Effect::CreateDraftRelease {
+				owner,
+				repo_name,
+				tag_name,
+				..
+			} => write!(
+				f,
+				"create a draft release of `{}` in {}/{}",
+				tag_name, owner, repo_name
+			),
+			Effect::RequestReviewers {
+				owner,
+				repo_name,
+				number,
+				users,
+			} => write!(
+				f,
+				"request reviews on {}/{}#{} from {}",
+				owner,
+				repo_name,
+				number,
+				users
+					.iter()
+					.map(|user| format!("@{}", user))
+					.collect::<Vec<_>>()
+					.join(", ")
+			),
+		}
+	}
+}
+
+/// Side-effecting calls are checked with `allow` before they are made.  In a
+/// dry run they are recorded instead, so the plan can be reported.
+#[derive(Debug, Default)]
+pub struct Effects {
+	dry_run: bool,
+	planned: Mutex<Vec<Effect>>,
+}
+
+impl Effects {
+	pub fn new(dry_run: bool) -> Self {
+		Self {
+			dry_run,
+			planned: Mutex::new(Vec::new()),
+		}
+	}
+
+	pub fn is_dry_run(&self) -> bool {
+		self.dry_run
+	}
+
+	/// Whether the effect should be carried out.
+	pub fn allow(&self, effect: Effect) -> bool {
+		if self.dry_run {
+			log::info!("Dry run; would {}", effect);
+			self.planned.lock().push(effect);
+			false
+		} else {
+			true
+		}
+	}
+
+	/// The effects recorded since the last call.
+	pub fn take_plan(&self) -> Vec<Effect> {
+		std::mem::take(&mut *self.planned.lock())
+	}
+}
+
+pub fn plan_message(plan: &[Effect]) -> String {
+	format!(
+		"**Dry run**: nothing was changed. Otherwise the bot would have:\n{}",
+		plan.iter()
+			.map(|effect| format!("- {}", effect))
+			.collect::<Vec<_>>()
+			.join("\n")
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn merge() -> Effect {
+		Effect::Merge {
+			owner: "paritytech".to_string(),
+			repo_name: "substrate".to_string(),
+			number: 1234,
+			head_sha: "abc".to_string(),
+			merge_method: MergeMethod::Squash,
+		}
+	}
+
+	fn companion_update() -> Effect {
+		Effect::CompanionUpdate {
+			owner: "paritytech".to_string(),
+			repo_name: "polkadot".to_string(),
+			number: 567,
+			branch: "companion-branch".to_string(),
+		}
+	}
+
+	#[test]
+	fn test_live_run() {
+		let effects = Effects::new(false);
+		assert!(effects.allow(merge()));
+		assert!(effects.take_plan().is_empty());
+	}
+
+	#[test]
+	fn test_dry_run() {
+		let effects = Effects::new(true);
+		assert!(!effects.allow(merge()));
+		assert!(!effects.allow(companion_update()));
+		let plan = effects.take_plan();
+		assert_eq!(plan, vec![merge(), companion_update()]);
+		assert_eq!(plan[1].pull_request(), ("paritytech", "polkadot", 567));
+		// each command starts with an empty plan
+		assert!(effects.take_plan().is_empty());
+	}
+
+	#[test]
+	fn test_plan_message() {
+		assert_eq!(
+			plan_message(&[merge(), companion_update()]),
+			"**Dry run**: nothing was changed. Otherwise the bot would have:
+- merge paritytech/substrate#1234 at abc (squash)
+- update companion paritytech/polkadot#567 by merging master into `companion-branch` and pushing it"
+		);
+	}
+}
@@ -25,6 +25,42 @@ pub enum Error {
 	#[snafu(display("Checks failed for {}", commit_sha))]
 	ChecksFailed {
 		commit_sha: String,
+		/// the statuses and check runs which failed
+		failed: Vec<crate::github::FailedCheck>,
+	},
+
+	#[snafu(display(
+		"Performance regression: the benchmark average of {} is {:.2}x that of master, over the threshold of {}x.",
+		commit_sha,
+		factor,
+		threshold
+	))]
+	PerformanceRegression {
+		commit_sha: String,
+		factor: f64,
+		threshold: f64,
+	},
+
+	#[snafu(display(
+		"Merging {} conflicts in {}",
+		rev,
+		files.iter().map(|file| file.path.as_str()).collect::<Vec<_>>().join(", ")
+	))]
+	RebaseConflict {
+		/// the revision merged into the head branch, eg. `origin/master`
+		rev: String,
+		files: Vec<crate::rebase::ConflictFile>,
+	},
+
+	#[snafu(display(
+		"The bot cannot push to {}/{}, as maintainers are not allowed to edit this PR.",
+		head_owner,
+		head_repo
+	))]
+	MaintainerEditsDisabled {
+		head_owner: String,
+		head_repo: String,
+		author: String,
 	},
 
 	#[snafu(display("Head SHA changed from {}", commit_sha))]
@@ -37,6 +73,51 @@ pub enum Error {
 		source: Box<Error>,
 	},
 
+	#[snafu(display(
+		"@{} has {} permission on {}/{}, but {} is required.",
+		login,
+		found,
+		owner,
+		repo_name,
+		required
+	))]
+	InsufficientPermission {
+		login: String,
+		owner: String,
+		repo_name: String,
+		found: crate::github::Permission,
+		required: crate::github::Permission,
+	},
+
+	#[snafu(display(
+		"The bot's GitHub App lacks the {} read permission, which it needs to {}.",
+		permission,
+		purpose
+	))]
+	MissingPermission {
+		permission: String,
+		purpose: String,
+	},
+
+	/// Refused by the command allow- or denylists, without saying which.
+	#[snafu(display("@{} is not allowed to use this command.", login))]
+	CommandDenied {
+		login: String,
+	},
+
+	#[snafu(display(
+		"@{} is not a member of a team allowed to force merges ({}).",
+		login,
+		teams.join(", ")
+	))]
+	ForceMergeRestricted {
+		login: String,
+		teams: Vec<String>,
+	},
+
+	#[snafu(display("A forced merge needs a justification."))]
+	ForceMergeReasonMissing {},
+
 	#[snafu(display("Error getting process info: {}", source))]
 	ProcessFile {
 		source: Box<Error>,
@@ -45,19 +126,79 @@ pub enum Error {
 	#[snafu(display("Missing process info."))]
 	ProcessInfo {},
 
+	#[snafu(display("Merging into {} is frozen: {}", branch, freeze.reason))]
+	MergeFrozen {
+		freeze: crate::freeze::MergeFreeze,
+		branch: String,
+	},
+
+	#[snafu(display("Missing changelog entry matching {}.", path))]
+	MissingChangelog {
+		/// The pattern the entry must match.
+		path: String,
+		number: i64,
+	},
+
+	#[snafu(display("Changes to {} need an audit by {}.", files.join(", "), team))]
+	Unaudited {
+		/// The changed files needing the audit.
+		files: Vec<String>,
+		/// The label of audited PRs.
+		label: String,
+		/// `org/team` of the auditors.
+		team: String,
+	},
+
+	#[snafu(display("Runtime changed without a spec_version bump."))]
+	SpecVersionNotBumped {
+		/// Every changed runtime, bumped or not.
+		versions: Vec<crate::spec_version::RuntimeVersions>,
+	},
+
+	#[snafu(display("GitHub's merge queue is to merge this PR."))]
+	NativelyQueued {},
+
 	#[snafu(display("Missing approval."))]
-	Approval {},
+	Approval {
+		/// Logins whose approval would allow the merge.
+		owners: Vec<String>,
+		/// Approvals still needed from the review teams.
+		missing_reviewers: usize,
+	},
+
+	#[snafu(display("Changes requested by {}.", reviewer))]
+	ChangesRequested {
+		reviewer: String,
+	},
+
+	#[snafu(display("Base branch {} is not merged into.", branch))]
+	BaseBranchNotAllowed {
+		branch: String,
+		/// Patterns of the branches which are merged into.
+		allowed: Vec<String>,
+	},
+
+	#[snafu(display("Labelled {}, which blocks merging.", label))]
+	BlockedLabel {
+		label: String,
+	},
 
 	#[snafu(display("Error: {}", msg))]
 	Message {
 		msg: String,
 	},
 
+	/// Missing or invalid configuration values, all of them at once.
+	#[snafu(display("Invalid configuration:\n{}", errors.join("\n")))]
+	Config {
+		errors: Vec<String>,
+	},
+
 	/// An error occurred with an integration service (e.g. GitHub).
-	#[snafu(display("Status code: {}\nBody:\n{:#?}", status, body,))]
+	#[snafu(display("Status code: {}: {}", status, error))]
 	Response {
 		status: reqwest::StatusCode,
-		body: serde_json::Value,
+		error: crate::http::GithubApiError,
 	},
 
 	/// An error occurred while sending or receiving a HTTP request or response
@@ -67,6 +208,25 @@ pub enum Error {
 		source: reqwest::Error,
 	},
 
+	#[snafu(display("`{}` timed out after {} seconds.", command, secs))]
+	CommandTimedOut {
+		/// with any credentials redacted
+		command: String,
+		secs: u64,
+	},
+
+	#[snafu(display(
+		"Only {} MB are free in {}, fewer than the {} MB needed to work on a clone.",
+		free_mb,
+		dir,
+		min_mb
+	))]
+	DiskSpace {
+		dir: String,
+		free_mb: u64,
+		min_mb: u64,
+	},
+
 	/// An error occurred in a Tokio call.
 	#[snafu(display("Source: {}", source))]
 	Tokio {
@@ -101,6 +261,12 @@ pub enum Error {
 		source: toml::de::Error,
 	},
 
+	/// An error occurred while parsing YAML.
+	#[snafu(display("Source: {}", source))]
+	Yaml {
+		source: serde_yaml::Error,
+	},
+
 	/// An error occurred while parsing TOML.
 	#[snafu(display("Source: {}", source))]
 	Base64 {
@@ -142,17 +308,47 @@ pub enum Error {
 
 	// generic error for Gitlab API requests
 	#[snafu(display(
-		"{} {} failed with HTTP status {} and body: {}",
+		"{} {} failed with HTTP status {}: {}",
 		method,
 		url,
 		status,
-		body
+		message.as_ref().unwrap_or(body)
 	))]
 	GitlabApi {
 		method: String,
 		url: String,
 		status: u32,
 		body: String,
+		/// The `message` of a JSON error body.
+		message: Option<String>,
+	},
+
+	/// Gitlab refused a request and retrying would not help.
+	#[snafu(display("Gitlab rejected the request: {}", source))]
+	GitlabRejected {
+		source: Box<Error>,
+	},
+
+	/// Gitlab kept failing with server or network errors.
+	#[snafu(display(
+		"Gitlab is unreachable; gave up after {} attempts: {}",
+		attempts,
+		source
+	))]
+	GitlabUnreachable {
+		attempts: usize,
+		source: Box<Error>,
+	},
+
+	/// Every path tried for a request file was already taken.
+	#[snafu(display(
+		"Gitlab already had a file at each of the {} paths tried: {}",
+		attempts,
+		source
+	))]
+	GitlabPathsTaken {
+		attempts: usize,
+		source: Box<Error>,
 	},
 
 	#[snafu(display("Failed parsing URL: {}", source))]
@@ -164,6 +360,18 @@ pub enum Error {
 	UrlCannotBeBase {
 		url: String,
 	},
+
+	#[snafu(display("Error accessing recorded payload {}: {}", path, source))]
+	Recording {
+		path: String,
+		source: std::io::Error,
+	},
+
+	#[snafu(display("Error accessing backup {}: {}", path, source))]
+	Backup {
+		path: String,
+		source: std::io::Error,
+	},
 }
 
 impl Error {
@@ -173,6 +381,99 @@ impl Error {
 			issue: issue,
 		}
 	}
+
+	/// Whether a Github API request failed because the resource does not
+	/// exist.
+	pub fn is_not_found(&self) -> bool {
+		matches!(
+			self.github_api_error(),
+			Some(crate::http::GithubApiError::NotFound(_))
+		)
+	}
+
+	/// Whether GitHub refused a request because of a rate limit.
+	pub fn is_rate_limited(&self) -> bool {
+		matches!(
+			self.github_api_error(),
+			Some(crate::http::GithubApiError::RateLimited { .. })
+		)
+	}
+
+	pub fn github_api_error(&self) -> Option<&crate::http::GithubApiError> {
+		match self {
+			Error::Response { error, .. } => Some(error),
+			_ => None,
+		}
+	}
+
+	/// Name of the variant, to tell errors with the same message apart.
+	pub fn kind(&self) -> &'static str {
+		match self {
+			Error::WithIssue { .. } => "WithIssue",
+			Error::Companion { .. } => "Companion",
+			Error::Merge { .. } => "Merge",
+			Error::ChecksFailed { .. } => "ChecksFailed",
+			Error::PerformanceRegression { .. } => "PerformanceRegression",
+			Error::RebaseConflict { .. } => "RebaseConflict",
+			Error::MaintainerEditsDisabled { .. } => "MaintainerEditsDisabled",
+			Error::HeadChanged { .. } => "HeadChanged",
+			Error::OrganizationMembership { .. } => "OrganizationMembership",
+			Error::InsufficientPermission { .. } => "InsufficientPermission",
+			Error::MissingPermission { .. } => "MissingPermission",
+			Error::CommandDenied { .. } => "CommandDenied",
+			Error::ForceMergeRestricted { .. } => "ForceMergeRestricted",
+			Error::ForceMergeReasonMissing {} => "ForceMergeReasonMissing",
+			Error::ProcessFile { .. } => "ProcessFile",
+			Error::ProcessInfo { .. } => "ProcessInfo",
+			Error::MergeFrozen { .. } => "MergeFrozen",
+			Error::MissingChangelog { .. } => "MissingChangelog",
+			Error::Unaudited { .. } => "Unaudited",
+			Error::SpecVersionNotBumped { .. } => "SpecVersionNotBumped",
+			Error::NativelyQueued {} => "NativelyQueued",
+			Error::Approval { .. } => "Approval",
+			Error::ChangesRequested { .. } => "ChangesRequested",
+			Error::BlockedLabel { .. } => "BlockedLabel",
+			Error::BaseBranchNotAllowed { .. } => "BaseBranchNotAllowed",
+			Error::Message { .. } => "Message",
+			Error::Config { .. } => "Config",
+			Error::Response { .. } => "Response",
+			Error::Http { .. } => "Http",
+			Error::Tokio { .. } => "Tokio",
+			Error::CommandTimedOut { .. } => "CommandTimedOut",
+			Error::DiskSpace { .. } => "DiskSpace",
+			Error::MissingData { .. } => "MissingData",
+			Error::Db { .. } => "Db",
+			Error::Utf8 { .. } => "Utf8",
+			Error::Json { .. } => "Json",
+			Error::Toml { .. } => "Toml",
+			Error::Yaml { .. } => "Yaml",
+			Error::Base64 { .. } => "Base64",
+			Error::Curl { .. } => "Curl",
+			Error::Jwt { .. } => "Jwt",
+			Error::Bincode { .. } => "Bincode",
+			Error::GitlabJobNotFound { .. } => "GitlabJobNotFound",
+			Error::StartingGitlabJobFailed { .. } => "StartingGitlabJobFailed",
+			Error::GitlabApi { .. } => "GitlabApi",
+			Error::GitlabRejected { .. } => "GitlabRejected",
+			Error::GitlabUnreachable { .. } => "GitlabUnreachable",
+			Error::GitlabPathsTaken { .. } => "GitlabPathsTaken",
+			Error::ParseUrl { .. } => "ParseUrl",
+			Error::UrlCannotBeBase { .. } => "UrlCannotBeBase",
+			Error::Recording { .. } => "Recording",
+			Error::Backup { .. } => "Backup",
+		}
+	}
+
+	/// Whether a request failed in a way that sending it again may fix.
+	pub fn is_transient(&self) -> bool {
+		match self {
+			Error::Http { source } => {
+				source.is_timeout() || source.is_connect()
+			}
+			Error::Response { status, .. } => status.is_server_error(),
+			_ => false,
+		}
+	}
 }
 
 impl From<curl::Error> for Error {
@@ -0,0 +1,137 @@
+use crate::{config::BotConfig, constants::*, error::*, Result};
+
+/// `bot merge force`, with the justification given after it, if any.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForceMerge {
+	pub reason: String,
+}
+
+pub fn parse_command(body: &str) -> Option<ForceMerge> {
+	let body = body.trim();
+	let lowercase = body.to_lowercase();
+	if lowercase == AUTO_MERGE_FORCE
+		|| lowercase.starts_with(&format!("{} ", AUTO_MERGE_FORCE))
+		|| lowercase.starts_with(&format!("{}\n", AUTO_MERGE_FORCE))
+	{
+		Some(ForceMerge {
+			reason: body[AUTO_MERGE_FORCE.len()..].trim().to_string(),
+		})
+	} else {
+		None
+	}
+}
+
+/// Check that `requested_by`, being one of `members` or not, may force the
+/// merge.  `members` are the members of the `force_merge_teams`.
+pub fn check(
+	bot_config: &BotConfig,
+	requested_by: &str,
+	members: &[String],
+	command: &ForceMerge,
+) -> Result<()> {
+	if !members
+		.iter()
+		.any(|member| member.eq_ignore_ascii_case(requested_by))
+	{
+		Err(Error::ForceMergeRestricted {
+			login: requested_by.to_string(),
+			teams: bot_config.force_merge_teams.clone(),
+		})
+	} else if bot_config.force_merge_require_reason && command.reason.is_empty()
+	{
+		Err(Error::ForceMergeReasonMissing {})
+	} else {
+		Ok(())
+	}
+}
+
+/// The comment posted before a forced merge.
+pub fn comment(requested_by: &str, command: &ForceMerge) -> String {
+	if command.reason.is_empty() {
+		format!(
+			"Trying merge, forced by @{} without waiting for checks.",
+			requested_by
+		)
+	} else {
+		format!(
+			"Trying merge, forced by @{} without waiting for checks: {}",
+			requested_by, command.reason
+		)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn force(reason: &str) -> ForceMerge {
+		ForceMerge {
+			reason: reason.to_string(),
+		}
+	}
+
+	#[test]
+	fn test_parse_command() {
+		assert_eq!(parse_command("bot merge force"), Some(force("")));
+		assert_eq!(parse_command(" Bot Merge Force \n"), Some(force("")));
+		assert_eq!(
+			parse_command("bot merge force CI is down, Fix Needed"),
+			Some(force("CI is down, Fix Needed"))
+		);
+		assert_eq!(
+			parse_command("bot merge force\nhotfix for #123"),
+			Some(force("hotfix for #123"))
+		);
+		assert_eq!(parse_command("bot merge"), None);
+		assert_eq!(parse_command("bot merge forced"), None);
+		assert_eq!(parse_command("bot merge cancel"), None);
+	}
+
+	#[test]
+	fn test_check() {
+		let config = BotConfig {
+			force_merge_teams: vec!["substrateteamleads".to_string()],
+			..BotConfig::default()
+		};
+		let leads = vec!["gavofyork".to_string()];
+		assert!(check(&config, "GavofYork", &leads, &force("")).is_ok());
+		match check(&config, "alice", &leads, &force("urgent")) {
+			Err(Error::ForceMergeRestricted { login, teams }) => {
+				assert_eq!(login, "alice");
+				assert_eq!(teams, vec!["substrateteamleads"]);
+			}
+			other => panic!("expected ForceMergeRestricted, got {:?}", other),
+		}
+		// without force merge teams, nobody can
+		assert!(check(&config, "alice", &[], &force("urgent")).is_err());
+
+		let config = BotConfig {
+			force_merge_require_reason: true,
+			..config
+		};
+		assert!(matches!(
+			check(&config, "gavofyork", &leads, &force("")),
+			Err(Error::ForceMergeReasonMissing {})
+		));
+		assert!(
+			check(&config, "gavofyork", &leads, &force("CI is down")).is_ok()
+		);
+		// a non-lead is told about the restriction before the reason
+		assert!(matches!(
+			check(&config, "alice", &leads, &force("")),
+			Err(Error::ForceMergeRestricted { .. })
+		));
+	}
+
+	#[test]
+	fn test_comment() {
+		assert_eq!(
+			comment("gavofyork", &force("")),
+			"Trying merge, forced by @gavofyork without waiting for checks."
+		);
+		assert_eq!(
+			comment("gavofyork", &force("CI is down")),
+			"Trying merge, forced by @gavofyork without waiting for checks: CI is down"
+		);
+	}
+}
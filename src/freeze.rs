@@ -0,0 +1,349 @@
+use crate::{constants::*, error::*, migrations, Result};
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use rocksdb::DB;
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+
+pub(crate) const KEY_PREFIX: &str = "merge-freeze/";
+
+/// A period during which PRs of a repository cannot be merged, except with
+/// `bot merge force` by a team lead.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MergeFreeze {
+	/// name of the repository
+	pub repo: String,
+	/// base branches the freeze applies to, eg. `release-*`; all if unset
+	#[serde(default)]
+	pub branch: Option<String>,
+	#[serde(default)]
+	pub start: Option<DateTime<Utc>>,
+	#[serde(default)]
+	pub end: Option<DateTime<Utc>>,
+	/// must be set if there is no `end`, so that a forgotten end does not
+	/// freeze a repository forever
+	#[serde(default)]
+	pub until_further_notice: bool,
+	pub reason: String,
+	/// login of the team lead who froze the repository with `bot freeze`
+	#[serde(default)]
+	pub frozen_by: Option<String>,
+}
+
+impl MergeFreeze {
+	pub fn applies_to(&self, repo_name: &str, branch: &str) -> bool {
+		self.repo == repo_name
+			&& match &self.branch {
+				Some(pattern) => branch_matches(pattern, branch),
+				None => true,
+			}
+	}
+
+	pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+		self.start.into_iter().all(|start| start <= now)
+			&& self.end.into_iter().all(|end| now < end)
+	}
+
+	/// Sorts freezes by how long they last; until further notice is longest.
+	fn end_key(&self) -> (bool, Option<DateTime<Utc>>) {
+		(self.end.is_none(), self.end)
+	}
+}
+
+/// Match a branch against a pattern in which `*` stands for any characters.
+pub(crate) fn branch_matches(pattern: &str, branch: &str) -> bool {
+	let re = format!(
+		"^{}$",
+		pattern
+			.split('*')
+			.map(regex::escape)
+			.collect::<Vec<_>>()
+			.join(".*")
+	);
+	match Regex::new(&re) {
+		Ok(re) => re.is_match(branch),
+		Err(_) => false,
+	}
+}
+
+/// The freeze which currently applies to a branch, if any.  When several
+/// overlap, the one lasting longest is returned.
+pub fn active_freeze<'a>(
+	freezes: impl IntoIterator<Item = &'a MergeFreeze>,
+	repo_name: &str,
+	branch: &str,
+	now: DateTime<Utc>,
+) -> Option<&'a MergeFreeze> {
+	freezes
+		.into_iter()
+		.filter(|freeze| {
+			freeze.applies_to(repo_name, branch) && freeze.is_active(now)
+		})
+		.max_by_key(|freeze| freeze.end_key())
+}
+
+/// Parse the `MERGE_FREEZES` setting, a TOML array of freezes.
+pub fn parse_freezes(s: &str) -> std::result::Result<Vec<MergeFreeze>, String> {
+	#[derive(Deserialize)]
+	struct Freezes {
+		merge_freezes: Vec<MergeFreeze>,
+	}
+	let freezes = toml::from_str::<Freezes>(&format!("merge_freezes = {}", s))
+		.map_err(|e| e.to_string())?
+		.merge_freezes;
+	for freeze in &freezes {
+		match (freeze.start, freeze.end) {
+			(_, None) if !freeze.until_further_notice => {
+				return Err(format!(
+					"freeze of {} needs an end or until_further_notice = true",
+					freeze.repo
+				))
+			}
+			(Some(start), Some(end)) if end <= start => {
+				return Err(format!(
+					"freeze of {} ends before it starts",
+					freeze.repo
+				))
+			}
+			_ => {}
+		}
+	}
+	Ok(freezes)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FreezeCommand {
+	Freeze { reason: String },
+	Unfreeze,
+}
+
+pub fn parse_command(body: &str) -> Option<FreezeCommand> {
+	let body = body.trim();
+	let lowercase = body.to_lowercase();
+	if lowercase == MERGE_UNFREEZE {
+		Some(FreezeCommand::Unfreeze)
+	} else if lowercase == MERGE_FREEZE
+		|| lowercase.starts_with(&format!("{} ", MERGE_FREEZE))
+	{
+		Some(FreezeCommand::Freeze {
+			reason: body[MERGE_FREEZE.len()..].trim().to_string(),
+		})
+	} else {
+		None
+	}
+}
+
+fn db_key(owner: &str, repo_name: &str) -> String {
+	format!("{}{}/{}", KEY_PREFIX, owner, repo_name)
+}
+
+/// The freeze set with `bot freeze`, if any.
+pub fn get_freeze(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+) -> Result<Option<MergeFreeze>> {
+	match db.get(db_key(owner, repo_name).as_bytes()).context(Db)? {
+		Some(bytes) => Ok(Some(migrations::decode(&bytes)?)),
+		None => Ok(None),
+	}
+}
+
+pub fn store_freeze(db: &DB, owner: &str, freeze: &MergeFreeze) -> Result<()> {
+	let bytes = migrations::encode(freeze)?;
+	db.put(db_key(owner, &freeze.repo).as_bytes(), bytes)
+		.context(Db)
+}
+
+/// Remove the freeze set with `bot freeze`.  Returns whether there was one.
+pub fn remove_freeze(db: &DB, owner: &str, repo_name: &str) -> Result<bool> {
+	let key = db_key(owner, repo_name);
+	let existed = db.get(key.as_bytes()).context(Db)?.is_some();
+	db.delete(key.as_bytes()).context(Db)?;
+	Ok(existed)
+}
+
+/// Why merging into `branch` is refused, in one line.
+pub fn frozen_summary(freeze: &MergeFreeze, branch: &str) -> String {
+	let until = match freeze.end {
+		Some(end) => end.format("%Y-%m-%d %H:%M UTC").to_string(),
+		None => "further notice".to_string(),
+	};
+	let by = freeze
+		.frozen_by
+		.as_ref()
+		.map(|login| format!(" by @{}", login))
+		.unwrap_or_default();
+	format!(
+		"Merging into `{}` is frozen{} until {}: {}",
+		branch, by, until, freeze.reason
+	)
+}
+
+pub fn frozen_message(freeze: &MergeFreeze, branch: &str) -> String {
+	format!(
+		"{}\n\nTeam leads can still merge with `{}`.",
+		frozen_summary(freeze, branch),
+		AUTO_MERGE_FORCE
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use chrono::TimeZone;
+
+	fn at(day: u32) -> DateTime<Utc> {
+		Utc.ymd(2020, 11, day).and_hms(0, 0, 0)
+	}
+
+	fn freeze(
+		branch: Option<&str>,
+		start: Option<u32>,
+		end: Option<u32>,
+	) -> MergeFreeze {
+		MergeFreeze {
+			repo: "polkadot".to_string(),
+			branch: branch.map(str::to_string),
+			start: start.map(at),
+			end: end.map(at),
+			until_further_notice: end.is_none(),
+			reason: "release".to_string(),
+			frozen_by: None,
+		}
+	}
+
+	#[test]
+	fn test_parse_freezes() {
+		assert_eq!(
+			parse_freezes(
+				r#"[
+					{ repo = "polkadot", branch = "release-*", reason = "v0.8.26", start = "2020-11-01T00:00:00Z", end = "2020-11-08T00:00:00Z" },
+					{ repo = "substrate", reason = "audit", until_further_notice = true },
+				]"#
+			)
+			.unwrap(),
+			vec![
+				MergeFreeze {
+					reason: "v0.8.26".to_string(),
+					until_further_notice: false,
+					..freeze(Some("release-*"), Some(1), Some(8))
+				},
+				MergeFreeze {
+					repo: "substrate".to_string(),
+					reason: "audit".to_string(),
+					..freeze(None, None, None)
+				},
+			]
+		);
+		assert!(parse_freezes("[]").unwrap().is_empty());
+		assert!(
+			parse_freezes(r#"[{ repo = "polkadot", reason = "x" }]"#).is_err()
+		);
+		assert!(parse_freezes(
+			r#"[{ repo = "polkadot", reason = "x", start = "2020-11-08T00:00:00Z", end = "2020-11-01T00:00:00Z" }]"#
+		)
+		.is_err());
+		assert!(parse_freezes(
+			r#"[{ repo = "polkadot", reason = "x", until = "2020-11-08T00:00:00Z" }]"#
+		)
+		.is_err());
+	}
+
+	#[test]
+	fn test_applies_to() {
+		let all = freeze(None, None, None);
+		assert!(all.applies_to("polkadot", "master"));
+		assert!(!all.applies_to("substrate", "master"));
+
+		let release = freeze(Some("release-*"), None, None);
+		assert!(release.applies_to("polkadot", "release-v0.8.26"));
+		assert!(!release.applies_to("polkadot", "master"));
+		assert!(!release.applies_to("polkadot", "pre-release-v1"));
+
+		let exact = freeze(Some("v1.0"), None, None);
+		assert!(exact.applies_to("polkadot", "v1.0"));
+		assert!(!exact.applies_to("polkadot", "v1x0"));
+	}
+
+	#[test]
+	fn test_is_active() {
+		let window = freeze(None, Some(2), Some(5));
+		assert!(!window.is_active(at(1)));
+		assert!(window.is_active(at(2)));
+		assert!(window.is_active(at(4)));
+		assert!(!window.is_active(at(5)));
+		assert!(freeze(None, Some(2), None).is_active(at(30)));
+	}
+
+	#[test]
+	fn test_overlapping_freezes() {
+		let config = vec![
+			freeze(None, Some(2), Some(5)),
+			freeze(Some("release-*"), Some(1), Some(10)),
+		];
+		let mut command = freeze(None, None, None);
+		command.frozen_by = Some("gavofyork".to_string());
+
+		// only the configured window
+		assert_eq!(
+			active_freeze(&config, "polkadot", "master", at(3)),
+			Some(&config[0])
+		);
+		assert_eq!(active_freeze(&config, "polkadot", "master", at(6)), None);
+		// the branch-specific freeze lasts longer
+		assert_eq!(
+			active_freeze(&config, "polkadot", "release-v1", at(3)),
+			Some(&config[1])
+		);
+		// a freeze until further notice outlasts any configured one
+		let all = config.iter().chain(Some(&command));
+		assert_eq!(
+			active_freeze(all, "polkadot", "release-v1", at(3)),
+			Some(&command)
+		);
+		// after `bot unfreeze`, the configured freezes still apply
+		assert_eq!(
+			active_freeze(&config, "polkadot", "release-v1", at(6)),
+			Some(&config[1])
+		);
+		assert_eq!(active_freeze(&config, "substrate", "master", at(3)), None);
+	}
+
+	#[test]
+	fn test_parse_command() {
+		assert_eq!(
+			parse_command(" bot freeze Release v0.8.26 "),
+			Some(FreezeCommand::Freeze {
+				reason: "Release v0.8.26".to_string()
+			})
+		);
+		assert_eq!(
+			parse_command("bot freeze"),
+			Some(FreezeCommand::Freeze {
+				reason: String::new()
+			})
+		);
+		assert_eq!(
+			parse_command("Bot Unfreeze"),
+			Some(FreezeCommand::Unfreeze)
+		);
+		assert_eq!(parse_command("bot freezer"), None);
+		assert_eq!(parse_command("bot merge"), None);
+	}
+
+	#[test]
+	fn test_frozen_message() {
+		assert_eq!(
+			frozen_message(&freeze(None, Some(1), Some(8)), "master"),
+			"Merging into `master` is frozen until 2020-11-08 00:00 UTC: release\n\nTeam leads can still merge with `bot merge force`."
+		);
+		let mut command = freeze(None, None, None);
+		command.frozen_by = Some("gavofyork".to_string());
+		assert_eq!(
+			frozen_message(&command, "master"),
+			"Merging into `master` is frozen by @gavofyork until further notice: release\n\nTeam leads can still merge with `bot merge force`."
+		);
+	}
+}
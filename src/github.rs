@@ -55,8 +55,44 @@ pub struct PullRequest {
 	pub links: Option<Links>,
 	pub author_association: Option<String>,
 	pub draft: Option<bool>,
+	/// whether the author of a PR from a fork allows maintainers to push to it
+	pub maintainer_can_modify: Option<bool>,
 	#[serde(rename = "repo")]
 	pub repository: Option<Repository>,
+	/// lines added, deleted and files changed, which only the single pull
+	/// request endpoint and webhooks include
+	pub additions: Option<u64>,
+	pub deletions: Option<u64>,
+	pub changed_files: Option<u64>,
+	/// set while GitHub's own auto-merge, or its merge queue, is to merge
+	/// the PR
+	pub auto_merge: Option<AutoMerge>,
+	/// eg. `clean`, `blocked` or `behind`; only the single pull request
+	/// endpoint and webhooks include it
+	pub mergeable_state: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AutoMerge {
+	pub enabled_by: User,
+	pub merge_method: String,
+	pub commit_title: Option<String>,
+	pub commit_message: Option<String>,
+}
+
+impl PullRequest {
+	/// Whether the bot may push to the head branch: it belongs to the owner of
+	/// the base repository, or the author allows edits by maintainers.  If
+	/// GitHub does not say, the push is tried.
+	pub fn head_pushable(&self) -> bool {
+		let same_owner = match (&self.head.repo.owner, &self.base.repo.owner) {
+			(Some(head), Some(base)) => {
+				head.login.eq_ignore_ascii_case(&base.login)
+			}
+			_ => false,
+		};
+		same_owner || self.maintainer_can_modify != Some(false)
+	}
 }
 
 impl GithubIssue for PullRequest {
@@ -282,6 +318,18 @@ pub struct Comment {
 	pub updated_at: chrono::DateTime<chrono::Utc>,
 }
 
+/// The `changes` of an edited comment.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommentChanges {
+	/// absent if the body is unchanged
+	pub body: Option<ChangedFrom>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChangedFrom {
+	pub from: String,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ProjectCard {
 	pub id: Option<i64>,
@@ -291,6 +339,24 @@ pub struct ProjectCard {
 	pub column_name: Option<String>,
 	pub previous_column_name: Option<String>,
 	pub column_url: Option<String>,
+	pub column_id: Option<i64>,
+	/// The API URL of the issue or PR on the card, if it is not a note.
+	pub content_url: Option<String>,
+}
+
+impl ProjectCard {
+	/// The owner, repository and number of the issue or PR on the card.
+	pub fn issue(&self) -> Option<(String, String, i64)> {
+		let path = self.content_url.as_deref()?.split("/repos/").nth(1)?;
+		match path.split('/').collect::<Vec<_>>().as_slice() {
+			[owner, repo_name, "issues", number] => Some((
+				owner.to_string(),
+				repo_name.to_string(),
+				number.parse().ok()?,
+			)),
+			_ => None,
+		}
+	}
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -363,6 +429,13 @@ pub struct IssuePullRequest {
 	pub patch_url: Option<String>,
 }
 
+/// The branch of a PR, in the repository it was pushed to.
+pub struct HeadBranch<'a> {
+	pub owner: &'a str,
+	pub repo: &'a str,
+	pub branch: &'a str,
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Head {
 	pub label: Option<String>,
@@ -565,6 +638,48 @@ pub struct Commit {
 	pub committer: User,
 }
 
+/// A file as listed by the pull request files endpoint.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PullRequestFile {
+	pub filename: String,
+	/// One of `added`, `removed`, `modified`, `renamed`, ...
+	#[serde(default)]
+	pub status: String,
+	#[serde(default)]
+	pub additions: u64,
+	#[serde(default)]
+	pub deletions: u64,
+}
+
+/// A commit as listed by the pull request commits endpoint.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PullRequestCommit {
+	pub sha: String,
+}
+
+/// A commit as listed by the commits and compare endpoints.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RepoCommit {
+	pub sha: String,
+	pub commit: GitCommit,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GitCommit {
+	pub message: String,
+}
+
+/// The commits between two refs, as returned by the compare endpoint.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Comparison {
+	/// how many commits `head` is ahead of the merge base, which may be more
+	/// than `commits` holds
+	pub total_commits: usize,
+	pub merge_base_commit: RepoCommit,
+	#[serde(default)]
+	pub commits: Vec<RepoCommit>,
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Permissions {
 	admin: Option<bool>,
@@ -580,6 +695,61 @@ pub struct CombinedStatus {
 	pub statuses: Vec<Status>,
 }
 
+impl CombinedStatus {
+	/// Only the latest status of each context, with the combined state of
+	/// those: a stale status, eg. of an earlier pipeline, may still be listed
+	/// and count towards the state GitHub combines.
+	pub fn latest(mut self) -> Self {
+		if self.statuses.is_empty() {
+			return self;
+		}
+		let order = |status: &Status| (status.updated_at, status.id);
+		let statuses = self
+			.statuses
+			.iter()
+			.filter(|status| {
+				!self.statuses.iter().any(|other| {
+					other.context == status.context
+						&& order(other) > order(status)
+				})
+			})
+			.cloned()
+			.collect();
+		self.statuses = statuses;
+		self.recompute_state();
+		self
+	}
+
+	/// Set the combined state and count from the statuses: the worst of their
+	/// states.
+	pub fn recompute_state(&mut self) {
+		let any = |state| self.statuses.iter().any(|s| s.state == state);
+		self.state = if any(StatusState::Failure) {
+			StatusState::Failure
+		} else if any(StatusState::Error) {
+			StatusState::Error
+		} else if any(StatusState::Pending) {
+			StatusState::Pending
+		} else {
+			StatusState::Success
+		};
+		self.total_count = self.statuses.len() as i64;
+	}
+
+	pub fn failed_checks(&self) -> Vec<FailedCheck> {
+		self.statuses
+			.iter()
+			.filter(|s| {
+				s.state == StatusState::Failure || s.state == StatusState::Error
+			})
+			.map(|s| FailedCheck {
+				name: s.context.clone(),
+				url: s.target_url.clone(),
+			})
+			.collect()
+	}
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Status {
 	pub id: Option<i64>,
@@ -591,6 +761,106 @@ pub struct Status {
 	pub state: StatusState,
 	pub creator: Option<User>,
 	pub context: String,
+	/// where the details of the status can be found, eg. a CI job
+	pub target_url: Option<String>,
+}
+
+/// A status or check run which did not succeed, for explaining a failed
+/// merge.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailedCheck {
+	pub name: String,
+	pub url: Option<String>,
+}
+
+/// How a pull request is merged.
+#[derive(Debug, Copy, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeMethod {
+	Merge,
+	#[default]
+	Squash,
+	Rebase,
+}
+
+impl MergeMethod {
+	pub fn as_str(self) -> &'static str {
+		match self {
+			MergeMethod::Merge => "merge",
+			MergeMethod::Squash => "squash",
+			MergeMethod::Rebase => "rebase",
+		}
+	}
+}
+
+impl std::str::FromStr for MergeMethod {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.trim() {
+			"merge" => Ok(MergeMethod::Merge),
+			"squash" => Ok(MergeMethod::Squash),
+			"rebase" => Ok(MergeMethod::Rebase),
+			s => Err(format!("unknown merge method '{}'", s)),
+		}
+	}
+}
+
+/// The permission of a user on a repository, from least to most.
+#[derive(
+	Debug,
+	Copy,
+	Clone,
+	PartialEq,
+	Eq,
+	PartialOrd,
+	Ord,
+	Default,
+	Serialize,
+	Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+	None,
+	Read,
+	#[default]
+	Write,
+	Admin,
+}
+
+impl Permission {
+	pub fn as_str(self) -> &'static str {
+		match self {
+			Permission::None => "none",
+			Permission::Read => "read",
+			Permission::Write => "write",
+			Permission::Admin => "admin",
+		}
+	}
+}
+
+impl std::fmt::Display for Permission {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.write_str(self.as_str())
+	}
+}
+
+impl std::str::FromStr for Permission {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.trim() {
+			"read" => Ok(Permission::Read),
+			"write" => Ok(Permission::Write),
+			"admin" => Ok(Permission::Admin),
+			s => Err(format!("unknown permission '{}'", s)),
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RepositoryPermission {
+	pub permission: Permission,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
@@ -710,8 +980,9 @@ pub struct InstallationToken {
 pub struct Release {
 	pub url: String,
 	pub html_url: String,
-	pub tarball_url: String,
-	pub zipball_url: String,
+	/// not set for drafts
+	pub tarball_url: Option<String>,
+	pub zipball_url: Option<String>,
 	pub id: i64,
 	pub tag_name: String,
 	pub target_commitish: String,
@@ -738,6 +1009,24 @@ pub struct Diff {
 	pub diff_url: Option<String>,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PullRequestReviewAction {
+	Submitted,
+	Edited,
+	Dismissed,
+}
+
+/// A review, as sent in `pull_request_review` events, where its state is in
+/// lower case, unlike in the API.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PayloadReview {
+	pub id: i64,
+	/// eg. `approved`
+	#[serde(default)]
+	pub state: Option<String>,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum IssueCommentAction {
@@ -746,6 +1035,41 @@ pub enum IssueCommentAction {
 	Deleted,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProjectCardAction {
+	Created,
+	Edited,
+	Moved,
+	Converted,
+	Deleted,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InstallationAction {
+	Created,
+	Deleted,
+	Suspend,
+	Unsuspend,
+	NewPermissionsAccepted,
+	/// repositories were added to the installation
+	Added,
+	Removed,
+}
+
+/// The installation of the app on an account, as webhooks about it name it.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AppInstallation {
+	pub id: i64,
+	pub account: InstallationAccount,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InstallationAccount {
+	pub login: String,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum CheckRunAction {
@@ -782,6 +1106,44 @@ pub struct CheckRuns {
 	pub check_runs: Vec<CheckRun>,
 }
 
+impl CheckRuns {
+	/// The latest run of each check, as the runs a check was rerun after stay
+	/// listed.  A run not started yet is later than any that started.
+	pub fn latest(self) -> Self {
+		let order =
+			|run: &CheckRun| (run.started_at.is_none(), run.started_at, run.id);
+		let check_runs = self
+			.check_runs
+			.iter()
+			.filter(|run| {
+				!self.check_runs.iter().any(|other| {
+					other.name == run.name && order(other) > order(run)
+				})
+			})
+			.cloned()
+			.collect::<Vec<_>>();
+		Self {
+			total_count: check_runs.len() as i64,
+			check_runs,
+		}
+	}
+
+	/// Completed check runs whose conclusion does not allow a merge.
+	pub fn failed_checks(&self) -> Vec<FailedCheck> {
+		self.check_runs
+			.iter()
+			.filter(|r| {
+				r.status == "completed"
+					&& r.conclusion.as_deref() != Some("success")
+			})
+			.map(|r| FailedCheck {
+				name: r.name.clone(),
+				url: r.html_url.clone(),
+			})
+			.collect()
+	}
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub struct CheckRunPR {
@@ -797,15 +1159,65 @@ pub struct HeadRepo {
 	pub url: String,
 	pub name: String,
 	pub owner: Option<User>,
+	/// included for the repositories of PRs, but not in every payload
+	#[serde(default)]
+	pub default_branch: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub struct CheckRun {
+	#[serde(default)]
+	pub id: i64,
+	#[serde(default)]
+	pub name: String,
 	pub status: String,
 	pub conclusion: Option<String>,
 	pub head_sha: String,
 	pub pull_requests: Vec<CheckRunPR>,
+	pub html_url: Option<String>,
+	/// unset while the run is queued
+	#[serde(default)]
+	pub started_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Body of a request setting a commit status.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatusRequest {
+	pub state: StatusState,
+	/// at most 140 characters
+	pub description: String,
+	pub context: String,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub target_url: Option<String>,
+}
+
+/// Body of a request creating or updating a check run.  Unset fields are
+/// left as they are.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CheckRunRequest {
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub name: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub head_sha: Option<String>,
+	/// `queued`, `in_progress` or `completed`
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub status: Option<String>,
+	/// eg. `success`, `failure`, `neutral` or `skipped`; needed once completed
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub conclusion: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub output: Option<CheckRunOutput>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CheckRunOutput {
+	pub title: String,
+	/// markdown, at most 65535 characters
+	pub summary: String,
+	/// markdown, at most 65535 characters
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub text: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -834,6 +1246,7 @@ pub enum PullRequestAction {
 	ReadyForReview,
 	Labeled,
 	Unlabeled,
+	#[serde(rename = "synchronize")]
 	Synchronized,
 	Locked,
 	Unlocked,
@@ -849,16 +1262,27 @@ pub enum Payload {
 		pull_request: PullRequest,
 		repository: Repository,
 		sender: User,
-		label: Label,
+		label: Option<Label>,
 	},
 	IssueComment {
 		action: IssueCommentAction,
 		issue: Issue,
 		comment: Comment,
+		/// what an edit changed
+		#[serde(default)]
+		changes: Option<CommentChanges>,
+	},
+	PullRequestReview {
+		action: PullRequestReviewAction,
+		review: PayloadReview,
+		pull_request: PullRequest,
+		repository: Repository,
 	},
 	CommitStatus {
 		sha: String,
 		state: StatusState,
+		#[serde(default)]
+		context: String,
 		description: String,
 		target_url: String,
 		repository: Repository,
@@ -867,5 +1291,224 @@ pub enum Payload {
 	CheckRun {
 		action: CheckRunAction,
 		check_run: CheckRun,
+		/// who created or updated the check run
+		#[serde(default)]
+		sender: Option<Sender>,
+	},
+	Push {
+		#[serde(rename = "ref")]
+		ref_field: String,
+		commits: Vec<PushCommit>,
+		repository: PushRepository,
+	},
+	ProjectCard {
+		action: ProjectCardAction,
+		project_card: ProjectCard,
+	},
+	/// The `installation` and `installation_repositories` events.
+	Installation {
+		action: InstallationAction,
+		installation: AppInstallation,
 	},
 }
+
+/// Whoever caused an event.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sender {
+	pub login: String,
+}
+
+/// A commit in a push event.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PushCommit {
+	pub id: String,
+	#[serde(default)]
+	pub added: Vec<String>,
+	#[serde(default)]
+	pub modified: Vec<String>,
+	#[serde(default)]
+	pub removed: Vec<String>,
+}
+
+impl PushCommit {
+	pub fn changed_files(&self) -> impl Iterator<Item = &str> {
+		self.added
+			.iter()
+			.chain(self.modified.iter())
+			.chain(self.removed.iter())
+			.map(|file| file.as_str())
+	}
+}
+
+/// The repository of a push event, which differs from `Repository` in using
+/// integer timestamps.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PushRepository {
+	pub name: String,
+	pub owner: PushRepositoryOwner,
+	pub default_branch: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PushRepositoryOwner {
+	pub login: String,
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use chrono::TimeZone;
+
+	fn run(
+		id: i64,
+		name: &str,
+		started_at: Option<i64>,
+		conclusion: Option<&str>,
+	) -> CheckRun {
+		CheckRun {
+			id,
+			name: name.to_string(),
+			status: if conclusion.is_some() {
+				"completed"
+			} else if started_at.is_some() {
+				"in_progress"
+			} else {
+				"queued"
+			}
+			.to_string(),
+			conclusion: conclusion.map(String::from),
+			head_sha: "abc".to_string(),
+			pull_requests: vec![],
+			html_url: None,
+			started_at: started_at.map(|at| chrono::Utc.timestamp(at, 0)),
+		}
+	}
+
+	fn latest(runs: Vec<CheckRun>) -> Vec<CheckRun> {
+		CheckRuns {
+			total_count: runs.len() as i64,
+			check_runs: runs,
+		}
+		.latest()
+		.check_runs
+	}
+
+	#[test]
+	fn test_latest_check_runs() {
+		let lint = run(1, "lint", Some(100), Some("success"));
+		// failed, then passed on a rerun, listed either way round
+		let failed = run(2, "build", Some(100), Some("failure"));
+		let passed = run(3, "build", Some(200), Some("success"));
+		assert_eq!(
+			latest(vec![failed.clone(), lint.clone(), passed.clone()]),
+			vec![lint.clone(), passed.clone()]
+		);
+		assert_eq!(
+			latest(vec![passed.clone(), failed.clone(), lint.clone()]),
+			vec![passed.clone(), lint.clone()]
+		);
+		// passed, then rerun and pending
+		let running = run(4, "build", Some(300), None);
+		assert_eq!(
+			latest(vec![passed.clone(), running.clone()]),
+			vec![running.clone()]
+		);
+		let queued = run(5, "build", None, None);
+		assert_eq!(
+			latest(vec![queued.clone(), passed.clone(), running.clone()]),
+			vec![queued]
+		);
+		// started in the same second
+		let rerun = run(6, "build", Some(200), Some("failure"));
+		assert_eq!(latest(vec![rerun.clone(), passed]), vec![rerun]);
+		let runs = CheckRuns {
+			total_count: 3,
+			check_runs: vec![failed, lint, run(7, "build", Some(200), None)],
+		}
+		.latest();
+		assert_eq!(runs.total_count, 2);
+	}
+
+	fn status(
+		id: i64,
+		context: &str,
+		updated_at: i64,
+		state: StatusState,
+	) -> Status {
+		Status {
+			id: Some(id),
+			node_id: None,
+			avatar_url: None,
+			url: None,
+			created_at: None,
+			updated_at: Some(chrono::Utc.timestamp(updated_at, 0)),
+			state,
+			creator: None,
+			context: context.to_string(),
+			target_url: None,
+		}
+	}
+
+	fn combined(state: StatusState, statuses: Vec<Status>) -> CombinedStatus {
+		CombinedStatus {
+			state,
+			sha: "abc".to_string(),
+			total_count: statuses.len() as i64,
+			statuses,
+		}
+	}
+
+	#[test]
+	fn test_latest_statuses() {
+		let stale = status(1, "gitlab-ci", 100, StatusState::Failure);
+		let current = status(2, "gitlab-ci", 200, StatusState::Success);
+		let labels = status(3, "check-labels", 150, StatusState::Success);
+		// newest first, as GitHub lists them, or out of order
+		for statuses in &[
+			vec![current.clone(), labels.clone(), stale.clone()],
+			vec![stale.clone(), labels.clone(), current.clone()],
+		] {
+			let latest =
+				combined(StatusState::Failure, statuses.clone()).latest();
+			assert_eq!(latest.state, StatusState::Success);
+			assert_eq!(latest.total_count, 2);
+			assert!(!latest.statuses.contains(&stale));
+		}
+		// a pipeline rerun after passing
+		let rerun = status(4, "gitlab-ci", 300, StatusState::Pending);
+		let latest = combined(
+			StatusState::Success,
+			vec![current.clone(), rerun.clone(), labels.clone()],
+		)
+		.latest();
+		assert_eq!(latest.state, StatusState::Pending);
+		assert_eq!(latest.statuses, vec![rerun, labels.clone()]);
+		// updated in the same second
+		let failed = status(5, "gitlab-ci", 200, StatusState::Failure);
+		let latest =
+			combined(StatusState::Success, vec![failed.clone(), current])
+				.latest();
+		assert_eq!(latest.state, StatusState::Failure);
+		assert_eq!(latest.failed_checks().len(), 1);
+		// GitHub's state stands for no statuses
+		assert_eq!(
+			combined(StatusState::Pending, vec![]).latest().state,
+			StatusState::Pending
+		);
+	}
+
+	#[test]
+	fn test_deserialize_check_run() {
+		let mut json =
+			serde_json::to_value(run(1, "build", None, None)).unwrap();
+		json["started_at"] = "2021-01-01T00:00:00Z".into();
+		let run = serde_json::from_value::<CheckRun>(json.clone()).unwrap();
+		assert_eq!(
+			run.started_at,
+			Some(chrono::Utc.ymd(2021, 1, 1).and_hms(0, 0, 0))
+		);
+		json.as_object_mut().unwrap().remove("started_at");
+		let run = serde_json::from_value::<CheckRun>(json).unwrap();
+		assert_eq!(run.started_at, None);
+	}
+}
@@ -0,0 +1,22 @@
+use crate::{github, Result};
+
+use super::GithubBot;
+
+impl GithubBot {
+	/// Returns a branch in a repository.
+	pub async fn branch(
+		&self,
+		owner: &str,
+		repo_name: &str,
+		branch: &str,
+	) -> Result<github::Branch> {
+		let url = format!(
+			"{base_url}/repos/{owner}/{repo}/branches/{branch}",
+			base_url = Self::BASE_URL,
+			owner = owner,
+			repo = repo_name,
+			branch = branch,
+		);
+		self.client.get(url).await
+	}
+}
@@ -0,0 +1,58 @@
+use crate::{error, github, http::RequestOptions, Result};
+
+use reqwest::Method;
+use snafu::ResultExt;
+
+use super::GithubBot;
+
+impl GithubBot {
+	/// Creates a check run on a commit.
+	pub async fn create_check_run(
+		&self,
+		owner: &str,
+		repo_name: &str,
+		check_run: &github::CheckRunRequest,
+	) -> Result<github::CheckRun> {
+		let url = format!(
+			"{base_url}/repos/{owner}/{repo}/check-runs",
+			base_url = Self::BASE_URL,
+			owner = owner,
+			repo = repo_name,
+		);
+		// not retried, as that could create the check run twice
+		self.client
+			.send_with(Method::POST, &url, check_run, RequestOptions::default())
+			.await?
+			.json()
+			.await
+			.context(error::Http)
+	}
+
+	/// Updates a check run, eg. to complete it.
+	pub async fn update_check_run(
+		&self,
+		owner: &str,
+		repo_name: &str,
+		check_run_id: i64,
+		check_run: &github::CheckRunRequest,
+	) -> Result<github::CheckRun> {
+		let url = format!(
+			"{base_url}/repos/{owner}/{repo}/check-runs/{check_run_id}",
+			base_url = Self::BASE_URL,
+			owner = owner,
+			repo = repo_name,
+			check_run_id = check_run_id,
+		);
+		self.client
+			.send_with(
+				Method::PATCH,
+				&url,
+				check_run,
+				RequestOptions::idempotent(),
+			)
+			.await?
+			.json()
+			.await
+			.context(error::Http)
+	}
+}
@@ -0,0 +1,107 @@
+use crate::{github, http::RequestOptions, Result};
+
+use reqwest::Method;
+
+use super::GithubBot;
+
+/// The most commits GitHub lists per page.
+pub const PER_PAGE: usize = 100;
+
+impl GithubBot {
+	/// Returns the commits `head` is ahead of `base` by, across all pages.  The
+	/// compare endpoint lists at most 250 commits however many pages are asked
+	/// for, so `total_commits` may be more than were returned.
+	pub async fn compare_commits(
+		&self,
+		owner: &str,
+		repo_name: &str,
+		base: &str,
+		head: &str,
+	) -> Result<github::Comparison> {
+		let url = |page: usize| {
+			format!(
+				"{base_url}/repos/{owner}/{repo}/compare/{base}...{head}?per_page={per_page}&page={page}",
+				base_url = Self::BASE_URL,
+				owner = owner,
+				repo = repo_name,
+				base = base,
+				head = head,
+				per_page = PER_PAGE,
+				page = page,
+			)
+		};
+		let mut comparison: github::Comparison =
+			self.client.get(url(1)).await?;
+		let mut page = 1;
+		let mut last_page_len = comparison.commits.len();
+		while last_page_len == PER_PAGE
+			&& comparison.commits.len() < comparison.total_commits
+		{
+			page += 1;
+			let mut next: github::Comparison =
+				self.client.get(url(page)).await?;
+			last_page_len = next.commits.len();
+			comparison.commits.append(&mut next.commits);
+		}
+		Ok(comparison)
+	}
+
+	/// Returns the commit `sha`, which may be abbreviated.
+	pub async fn commit(
+		&self,
+		owner: &str,
+		repo_name: &str,
+		sha: &str,
+	) -> Result<github::RepoCommit> {
+		let url = format!(
+			"{base_url}/repos/{owner}/{repo}/commits/{sha}",
+			base_url = Self::BASE_URL,
+			owner = owner,
+			repo = repo_name,
+			sha = sha,
+		);
+		self.client.get(url).await
+	}
+
+	/// Returns a page of the commits reachable from `head`, newest first.
+	pub async fn commits(
+		&self,
+		owner: &str,
+		repo_name: &str,
+		head: &str,
+		page: usize,
+	) -> Result<Vec<github::RepoCommit>> {
+		let url = format!(
+			"{base_url}/repos/{owner}/{repo}/commits?sha={head}&per_page={per_page}&page={page}",
+			base_url = Self::BASE_URL,
+			owner = owner,
+			repo = repo_name,
+			head = head,
+			per_page = PER_PAGE,
+			page = page,
+		);
+		self.client.get(url).await
+	}
+
+	/// Sets a status of the commit `sha`.  GitHub shows the latest status of
+	/// each context, so setting one again replaces it.
+	pub async fn create_status(
+		&self,
+		owner: &str,
+		repo_name: &str,
+		sha: &str,
+		status: &github::StatusRequest,
+	) -> Result<()> {
+		let url = format!(
+			"{base_url}/repos/{owner}/{repo}/statuses/{sha}",
+			base_url = Self::BASE_URL,
+			owner = owner,
+			repo = repo_name,
+			sha = sha,
+		);
+		self.client
+			.send_with(Method::POST, &url, status, RequestOptions::idempotent())
+			.await
+			.map(|_| ())
+	}
+}
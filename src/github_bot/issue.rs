@@ -1,5 +1,6 @@
-use crate::{error, github, Result};
+use crate::{error, github, http::RequestOptions, Result};
 
+use reqwest::Method;
 use snafu::{OptionExt, ResultExt};
 
 use super::GithubBot;
@@ -149,7 +150,8 @@ impl GithubBot {
 		self.client.get_all(&url).await
 	}
 
-	/// Adds a comment to an issue.
+	/// Adds a comment to an issue.  Retried like an idempotent request, as
+	/// a comment posted twice is better than a notification lost.
 	pub async fn create_issue_comment(
 		&self,
 		owner: &str,
@@ -157,6 +159,34 @@ impl GithubBot {
 		issue_number: i64,
 		comment: &str,
 	) -> Result<()> {
+		self.send_issue_comment(owner, repo_name, issue_number, comment)
+			.await
+			.map(|_| ())
+	}
+
+	/// Adds a comment to an issue like `create_issue_comment`, returning it
+	/// so that it can be edited later.
+	pub async fn post_issue_comment(
+		&self,
+		owner: &str,
+		repo_name: &str,
+		issue_number: i64,
+		comment: &str,
+	) -> Result<github::Comment> {
+		self.send_issue_comment(owner, repo_name, issue_number, comment)
+			.await?
+			.json()
+			.await
+			.context(error::Http)
+	}
+
+	async fn send_issue_comment(
+		&self,
+		owner: &str,
+		repo_name: &str,
+		issue_number: i64,
+		comment: &str,
+	) -> Result<reqwest::Response> {
 		let url = format!(
 			"{base}/repos/{owner}/{repo}/issues/{issue_number}/comments",
 			base = Self::BASE_URL,
@@ -164,12 +194,137 @@ impl GithubBot {
 			repo = repo_name,
 			issue_number = issue_number
 		);
+		let body = serde_json::json!({ "body": comment });
+		self.comments
+			.send(|| {
+				self.client.send_with(
+					Method::POST,
+					&url,
+					&body,
+					RequestOptions::idempotent(),
+				)
+			})
+			.await
+	}
+
+	/// Replaces the body of a comment on an issue or pull request.
+	pub async fn edit_issue_comment(
+		&self,
+		owner: &str,
+		repo_name: &str,
+		comment_id: i64,
+		comment: &str,
+	) -> Result<()> {
+		let url = format!(
+			"{base}/repos/{owner}/{repo}/issues/comments/{comment_id}",
+			base = Self::BASE_URL,
+			owner = owner,
+			repo = repo_name,
+			comment_id = comment_id
+		);
+		let body = serde_json::json!({ "body": comment });
+		self.comments
+			.send(|| {
+				self.client.send_with(
+					Method::PATCH,
+					&url,
+					&body,
+					RequestOptions::idempotent(),
+				)
+			})
+			.await
+			.map(|_| ())
+	}
+
+	/// Reacts to a comment on an issue or pull request, eg. with `eyes`.  A
+	/// reaction made already is not made again.
+	pub async fn create_comment_reaction(
+		&self,
+		owner: &str,
+		repo_name: &str,
+		comment_id: i64,
+		content: &str,
+	) -> Result<()> {
+		let url = format!(
+			"{base}/repos/{owner}/{repo}/issues/comments/{comment_id}/reactions",
+			base = Self::BASE_URL,
+			owner = owner,
+			repo = repo_name,
+			comment_id = comment_id
+		);
 		self.client
-			.post_response(&url, &serde_json::json!({ "body": comment }))
+			.send_with(
+				Method::POST,
+				&url,
+				&serde_json::json!({ "content": content }),
+				RequestOptions::idempotent(),
+			)
 			.await
 			.map(|_| ())
 	}
 
+	/// Adds labels to an issue or pull request, keeping those it has.
+	pub async fn add_labels(
+		&self,
+		owner: &str,
+		repo_name: &str,
+		issue_number: i64,
+		labels: &[String],
+	) -> Result<()> {
+		let url = format!(
+			"{base}/repos/{owner}/{repo}/issues/{issue_number}/labels",
+			base = Self::BASE_URL,
+			owner = owner,
+			repo = repo_name,
+			issue_number = issue_number
+		);
+		self.client
+			.send_with(
+				Method::POST,
+				&url,
+				&serde_json::json!({ "labels": labels }),
+				RequestOptions::idempotent(),
+			)
+			.await
+			.map(|_| ())
+	}
+
+	/// Removes a label from an issue or pull request.  Succeeds if it does not
+	/// have the label.
+	pub async fn remove_label(
+		&self,
+		owner: &str,
+		repo_name: &str,
+		issue_number: i64,
+		label: &str,
+	) -> Result<()> {
+		let url = format!(
+			"{base}/repos/{owner}/{repo}/issues/{issue_number}/labels/{label}",
+			base = Self::BASE_URL,
+			owner = owner,
+			repo = repo_name,
+			issue_number = issue_number,
+			// eg. `size/M`
+			label = url::form_urlencoded::byte_serialize(label.as_bytes())
+				.collect::<String>()
+				.replace('+', "%20")
+		);
+		match self
+			.client
+			.send_with(
+				Method::DELETE,
+				&url,
+				&serde_json::json!({}),
+				RequestOptions::idempotent(),
+			)
+			.await
+		{
+			Ok(_) => Ok(()),
+			Err(e) if e.is_not_found() => Ok(()),
+			Err(e) => Err(e),
+		}
+	}
+
 	pub async fn assign_issue<A, B>(
 		&self,
 		owner: &str,
@@ -189,9 +344,11 @@ impl GithubBot {
 			issue_number = issue_number
 		);
 		self.client
-			.post_response(
+			.send_with(
+				Method::POST,
 				&url,
 				&serde_json::json!({ "assignees": [assignee_login.as_ref()] }),
+				RequestOptions::idempotent(),
 			)
 			.await
 			.map(|_| ())
@@ -214,7 +371,12 @@ impl GithubBot {
 			issue_number = issue_number
 		);
 		self.client
-			.patch_response(&url, &serde_json::json!({ "state": "closed" }))
+			.send_with(
+				Method::PATCH,
+				&url,
+				&serde_json::json!({ "state": "closed" }),
+				RequestOptions::idempotent(),
+			)
 			.await?
 			.json()
 			.await
@@ -1,5 +1,8 @@
 use crate::{github, Result};
 
+pub mod branch;
+pub mod check_run;
+pub mod commit;
 pub mod issue;
 pub mod project;
 pub mod pull_request;
@@ -11,11 +14,17 @@ pub mod team;
 
 pub struct GithubBot {
 	pub client: crate::http::Client,
+	/// paces the comments the bot posts
+	pub comments: crate::comment_queue::CommentQueue,
+	/// repositories fetched, for their default branch
+	pub repositories: crate::cache::RepoCache<github::Repository>,
 }
 
 impl GithubBot {
 	pub(crate) const BASE_URL: &'static str = "https://api.github.com";
 	pub(crate) const BASE_HTML_URL: &'static str = "https://github.com";
+	/// How long a repository is cached; its default branch seldom changes.
+	pub(crate) const REPOSITORY_TTL_SECS: u64 = 3600;
 
 	/// Creates a new instance of `GithubBot` from a GitHub organization defined
 	/// by `org`, and a GitHub authenication key defined by `auth_key`.
@@ -31,11 +40,15 @@ impl GithubBot {
 			installation_login.to_owned(),
 		);
 
-		Ok(Self { client })
-	}
-
-	pub fn owner_from_html_url(url: &str) -> Option<&str> {
-		url.split("/").skip(3).next()
+		Ok(Self {
+			client,
+			comments: crate::comment_queue::CommentQueue::new(
+				std::time::Duration::from_millis(1000),
+			),
+			repositories: crate::cache::RepoCache::new(
+				Self::REPOSITORY_TTL_SECS,
+			),
+		})
 	}
 
 	pub async fn installation_repositories(
@@ -117,6 +130,16 @@ impl GithubBot {
 		)
 	}
 
+	/// Returns an organization.
+	pub async fn org(&self, org: &str) -> Result<github::Organization> {
+		let url = format!(
+			"{base_url}/orgs/{org}",
+			base_url = Self::BASE_URL,
+			org = org,
+		);
+		self.client.get(url).await
+	}
+
 	/// Returns true if the user is a member of the org.
 	pub async fn org_member(&self, org: &str, username: &str) -> Result<bool> {
 		let url = &format!(
@@ -1,5 +1,6 @@
-use crate::{error, github, Result};
+use crate::{error, github, http::RequestOptions, Result};
 
+use reqwest::Method;
 use snafu::{OptionExt, ResultExt};
 
 use super::GithubBot;
@@ -32,6 +33,42 @@ impl GithubBot {
 			.await
 	}
 
+	/// Returns the commits of a pull request, oldest first.
+	pub async fn pull_request_commits(
+		&self,
+		owner: &str,
+		repo_name: &str,
+		pull_number: i64,
+	) -> Result<Vec<github::PullRequestCommit>> {
+		self.client
+			.get_all(format!(
+				"{base_url}/repos/{owner}/{repo}/pulls/{pull_number}/commits",
+				base_url = Self::BASE_URL,
+				owner = owner,
+				repo = repo_name,
+				pull_number = pull_number
+			))
+			.await
+	}
+
+	/// Returns the files changed by a pull request.
+	pub async fn pull_request_files(
+		&self,
+		owner: &str,
+		repo_name: &str,
+		pull_number: i64,
+	) -> Result<Vec<github::PullRequestFile>> {
+		self.client
+			.get_all(format!(
+				"{base_url}/repos/{owner}/{repo}/pulls/{pull_number}/files",
+				base_url = Self::BASE_URL,
+				owner = owner,
+				repo = repo_name,
+				pull_number = pull_number
+			))
+			.await
+	}
+
 	pub async fn pull_request_with_head(
 		&self,
 		owner: &str,
@@ -90,6 +127,7 @@ impl GithubBot {
 		repo_name: &str,
 		number: i64,
 		head_sha: &str,
+		merge_method: github::MergeMethod,
 	) -> Result<()> {
 		let url = format!(
 			"{base_url}/repos/{owner}/{repo}/pulls/{number}/merge",
@@ -100,7 +138,55 @@ impl GithubBot {
 		);
 		let params = serde_json::json!({
 			"sha": head_sha,
-			"merge_method": "squash"
+			"merge_method": merge_method.as_str()
+		});
+		self.client.put_response(&url, &params).await.map(|_| ())
+	}
+
+	/// Changes the branch a pull request is to be merged into.
+	pub async fn update_pull_request_base(
+		&self,
+		owner: &str,
+		repo_name: &str,
+		pull_number: i64,
+		base: &str,
+	) -> Result<()> {
+		let url = format!(
+			"{base_url}/repos/{owner}/{repo}/pulls/{pull_number}",
+			base_url = Self::BASE_URL,
+			owner = owner,
+			repo = repo_name,
+			pull_number = pull_number
+		);
+		self.client
+			.send_with(
+				Method::PATCH,
+				&url,
+				&base_params(base),
+				RequestOptions::idempotent(),
+			)
+			.await
+			.map(|_| ())
+	}
+
+	/// Merges the base branch of a pull request into its head, if the head is
+	/// still at `expected_head_sha`.
+	pub async fn update_pull_request_branch(
+		&self,
+		owner: &str,
+		repo_name: &str,
+		pull_number: i64,
+		expected_head_sha: &str,
+	) -> Result<()> {
+		let url = format!(
+			"{base_url}/repos/{owner}/{repo}/pulls/{pull_number}/update-branch",
+			base_url = Self::BASE_URL,
+			owner = owner,
+			repo = repo_name,
+			pull_number = pull_number
+		);
+		let params = serde_json::json!({
+			"expected_head_sha": expected_head_sha,
 		});
 		self.client.put_response(&url, &params).await.map(|_| ())
 	}
@@ -123,16 +209,33 @@ impl GithubBot {
 			pull_number = pull_number
 		);
 		self.client
-			.patch_response(&url, &serde_json::json!({ "state": "closed" }))
+			.send_with(
+				Method::PATCH,
+				&url,
+				&serde_json::json!({ "state": "closed" }),
+				RequestOptions::idempotent(),
+			)
 			.await
 			.map(|_| ())
 	}
 }
 
+fn base_params(base: &str) -> serde_json::Value {
+	serde_json::json!({ "base": base })
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
 
+	#[test]
+	fn test_base_params() {
+		assert_eq!(
+			base_params("release-v0.9.x").to_string(),
+			r#"{"base":"release-v0.9.x"}"#
+		);
+	}
+
 	#[ignore]
 	#[test]
 	fn test_get_pr() {
@@ -19,6 +19,33 @@ impl GithubBot {
 		self.client.get(url).await
 	}
 
+	/// Creates a draft release of a tag, to be published by hand.
+	pub async fn create_draft_release(
+		&self,
+		owner: &str,
+		repo_name: &str,
+		tag_name: &str,
+		body: &str,
+	) -> Result<github::Release> {
+		let url = format!(
+			"{base_url}/repos/{owner}/{repo}/releases",
+			base_url = Self::BASE_URL,
+			owner = owner,
+			repo = repo_name,
+		);
+		self.client
+			.post(
+				url,
+				&serde_json::json!({
+					"tag_name": tag_name,
+					"name": tag_name,
+					"body": body,
+					"draft": true,
+				}),
+			)
+			.await
+	}
+
 	pub async fn substrate_commit_from_polkadot_commit(
 		&self,
 		ref_field: &str,
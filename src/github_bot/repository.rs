@@ -3,7 +3,8 @@ use crate::{github, Result};
 use super::GithubBot;
 
 impl GithubBot {
-	/// Returns a repository with the given name.
+	/// Returns a repository with the given name, cached for
+	/// `REPOSITORY_TTL_SECS`.
 	pub async fn repository<A>(
 		&self,
 		owner: &str,
@@ -12,13 +13,102 @@ impl GithubBot {
 	where
 		A: std::fmt::Display,
 	{
+		let repo_name = repo_name.to_string();
+		let now = chrono::Utc::now().timestamp();
+		if let Some(repository) = self.repositories.get(owner, &repo_name, now)
+		{
+			return Ok(repository);
+		}
 		let url = format!(
 			"{base_url}/repos/{owner}/{repo_name}",
 			base_url = Self::BASE_URL,
 			owner = owner,
 			repo_name = repo_name
 		);
-		self.client.get(url).await
+		let repository: github::Repository = self.client.get(url).await?;
+		self.repositories
+			.insert(owner, &repo_name, repository.clone(), now);
+		Ok(repository)
+	}
+
+	/// The branch PRs of a repository are merged into unless they say
+	/// otherwise, `master` if GitHub does not tell.
+	pub async fn default_branch(
+		&self,
+		owner: &str,
+		repo_name: &str,
+	) -> Result<String> {
+		Ok(self
+			.repository(owner, repo_name)
+			.await?
+			.default_branch
+			.unwrap_or_else(|| "master".to_string()))
+	}
+
+	/// Returns the permission of `login` on a repository.
+	pub async fn user_permission(
+		&self,
+		owner: &str,
+		repo_name: &str,
+		login: &str,
+	) -> Result<github::Permission> {
+		let url = format!(
+			"{base_url}/repos/{owner}/{repo_name}/collaborators/{login}/permission",
+			base_url = Self::BASE_URL,
+			owner = owner,
+			repo_name = repo_name,
+			login = login,
+		);
+		self.client
+			.get::<_, github::RepositoryPermission>(url)
+			.await
+			.map(|p| p.permission)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_default_branch_cached() {
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		rt.block_on(async {
+			let github_bot = GithubBot::new(Vec::new(), "paritytech")
+				.await
+				.expect("github_bot");
+			let now = chrono::Utc::now().timestamp();
+			github_bot.repositories.insert(
+				"paritytech",
+				"substrate",
+				github::Repository {
+					default_branch: Some("main".to_string()),
+					..github::Repository::default()
+				},
+				now,
+			);
+			github_bot.repositories.insert(
+				"paritytech",
+				"polkadot",
+				github::Repository::default(),
+				now,
+			);
+			// answered from the cache, without asking GitHub
+			assert_eq!(
+				github_bot
+					.default_branch("paritytech", "substrate")
+					.await
+					.unwrap(),
+				"main"
+			);
+			assert_eq!(
+				github_bot
+					.default_branch("paritytech", "polkadot")
+					.await
+					.unwrap(),
+				"master"
+			);
+		});
 	}
 }
 
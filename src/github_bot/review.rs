@@ -1,4 +1,7 @@
-use crate::{github, Result};
+use crate::{error, github, http::RequestOptions, Result};
+
+use reqwest::Method;
+use snafu::ResultExt;
 
 use super::GithubBot;
 
@@ -26,7 +29,39 @@ impl GithubBot {
 	) -> Result<github::PullRequest> {
 		let url = format!("{}/requested_reviewers", pull_request.url);
 		let body = &serde_json::json!({ "reviewers": reviewers });
-		self.client.post(url, body).await
+		self.client
+			.send_with(Method::POST, url, body, RequestOptions::idempotent())
+			.await?
+			.json()
+			.await
+			.context(error::Http)
+	}
+
+	/// Requests reviews on a pull request from users and teams, by the
+	/// slugs of the latter.
+	pub async fn request_reviewers(
+		&self,
+		owner: &str,
+		repo_name: &str,
+		number: i64,
+		users: &[String],
+		teams: &[String],
+	) -> Result<()> {
+		let url = format!(
+			"{base}/repos/{owner}/{repo}/pulls/{number}/requested_reviewers",
+			base = Self::BASE_URL,
+			owner = owner,
+			repo = repo_name,
+			number = number
+		);
+		let body = &serde_json::json!({
+			"reviewers": users,
+			"team_reviewers": teams,
+		});
+		self.client
+			.send_with(Method::POST, &url, body, RequestOptions::idempotent())
+			.await
+			.map(|_| ())
 	}
 }
 
@@ -0,0 +1,194 @@
+use serde::Deserialize;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "object_kind", rename_all = "snake_case")]
+pub enum Payload {
+	Pipeline(PipelineEvent),
+	#[serde(rename = "build")]
+	Job(JobEvent),
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct PipelineEvent {
+	pub object_attributes: PipelineAttributes,
+	pub project: Project,
+	pub commit: Option<PipelineCommit>,
+}
+
+impl PipelineEvent {
+	/// Link to the pipeline in the Gitlab web UI.
+	pub fn web_url(&self) -> String {
+		self.object_attributes.url.clone().unwrap_or_else(|| {
+			format!(
+				"{}/-/pipelines/{}",
+				self.project.web_url, self.object_attributes.id
+			)
+		})
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct PipelineAttributes {
+	pub id: i64,
+	#[serde(rename = "ref")]
+	pub ref_field: String,
+	pub sha: String,
+	pub status: String,
+	/// Seconds the pipeline ran for, if it has finished.
+	pub duration: Option<i64>,
+	pub url: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Project {
+	pub id: i64,
+	pub path_with_namespace: String,
+	pub web_url: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct PipelineCommit {
+	pub id: String,
+	pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct JobEvent {
+	pub build_id: i64,
+	pub build_name: String,
+	pub build_status: String,
+	pub pipeline_id: Option<i64>,
+	pub sha: String,
+	pub commit: JobCommit,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct JobCommit {
+	pub sha: String,
+	pub message: String,
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const PIPELINE_EVENT: &str = r#"{
+		"object_kind": "pipeline",
+		"object_attributes": {
+			"id": 1213,
+			"ref": "master",
+			"tag": false,
+			"sha": "bcbb5ec396a2c0f828686f14fac9b80b780504f2",
+			"before_sha": "bcbb5ec396a2c0f828686f14fac9b80b780504f2",
+			"source": "push",
+			"status": "success",
+			"detailed_status": "passed",
+			"stages": ["build", "deploy"],
+			"created_at": "2021-01-12 15:23:28 UTC",
+			"finished_at": "2021-01-12 15:26:29 UTC",
+			"duration": 754,
+			"variables": []
+		},
+		"user": {
+			"name": "processbot",
+			"username": "processbot"
+		},
+		"project": {
+			"id": 1,
+			"name": "burnin-requests",
+			"web_url": "https://gitlab.parity.io/parity/burnin-requests",
+			"path_with_namespace": "parity/burnin-requests",
+			"default_branch": "master"
+		},
+		"commit": {
+			"id": "bcbb5ec396a2c0f828686f14fac9b80b780504f2",
+			"message": "Add request for polkadot#1234",
+			"timestamp": "2021-01-12T15:23:20+00:00",
+			"url": "https://gitlab.parity.io/parity/burnin-requests/-/commit/bcbb5ec396a2c0f828686f14fac9b80b780504f2",
+			"author": {
+				"name": "processbot",
+				"email": "processbot@parity.io"
+			}
+		},
+		"builds": []
+	}"#;
+
+	const JOB_EVENT: &str = r#"{
+		"object_kind": "build",
+		"ref": "master",
+		"tag": false,
+		"before_sha": "bcbb5ec396a2c0f828686f14fac9b80b780504f2",
+		"sha": "bcbb5ec396a2c0f828686f14fac9b80b780504f2",
+		"build_id": 3580,
+		"build_name": "deploy-fullnode",
+		"build_stage": "deploy",
+		"build_status": "running",
+		"build_duration": null,
+		"pipeline_id": 1213,
+		"project_id": 1,
+		"project_name": "parity / burnin-requests",
+		"commit": {
+			"id": 1213,
+			"sha": "bcbb5ec396a2c0f828686f14fac9b80b780504f2",
+			"message": "Add request for polkadot#1234",
+			"author_name": "processbot",
+			"status": "running"
+		},
+		"repository": {
+			"name": "burnin-requests",
+			"homepage": "https://gitlab.parity.io/parity/burnin-requests"
+		}
+	}"#;
+
+	#[test]
+	fn test_pipeline_event() {
+		match serde_json::from_str::<Payload>(PIPELINE_EVENT).unwrap() {
+			Payload::Pipeline(event) => {
+				assert_eq!(event.object_attributes.id, 1213);
+				assert_eq!(event.object_attributes.status, "success");
+				assert_eq!(event.object_attributes.duration, Some(754));
+				assert_eq!(
+					event.commit.unwrap().message,
+					"Add request for polkadot#1234"
+				);
+			}
+			payload => panic!("unexpected payload: {:?}", payload),
+		}
+	}
+
+	#[test]
+	fn test_pipeline_web_url() {
+		let mut event =
+			match serde_json::from_str::<Payload>(PIPELINE_EVENT).unwrap() {
+				Payload::Pipeline(event) => event,
+				payload => panic!("unexpected payload: {:?}", payload),
+			};
+		assert_eq!(
+			event.web_url(),
+			"https://gitlab.parity.io/parity/burnin-requests/-/pipelines/1213"
+		);
+		event.object_attributes.url = Some("https://example.com/1".to_owned());
+		assert_eq!(event.web_url(), "https://example.com/1");
+	}
+
+	#[test]
+	fn test_job_event() {
+		match serde_json::from_str::<Payload>(JOB_EVENT).unwrap() {
+			Payload::Job(event) => {
+				assert_eq!(event.build_name, "deploy-fullnode");
+				assert_eq!(event.build_status, "running");
+				assert_eq!(event.pipeline_id, Some(1213));
+				assert_eq!(event.commit.sha, event.sha);
+			}
+			payload => panic!("unexpected payload: {:?}", payload),
+		}
+	}
+
+	#[test]
+	fn test_unknown_event() {
+		assert!(serde_json::from_str::<Payload>(
+			r#"{"object_kind": "push", "ref": "master"}"#
+		)
+		.is_err());
+	}
+}
@@ -1,8 +1,19 @@
 use crate::{error::*, Result};
-use reqwest::{header, Client};
-use serde::Deserialize;
+use futures::Future;
+use reqwest::{header, Client, Method, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::time::Duration;
 use url::Url;
 
+/// Number of times a rate limited request is retried.
+const RATE_LIMIT_RETRIES: usize = 3;
+
+/// Longest wait before retrying a rate limited request.
+const MAX_RETRY_AFTER_SECS: u64 = 60;
+
+/// Page size requested from list endpoints.
+const PER_PAGE: &str = "100";
+
 pub struct GitlabBot {
 	urls: UrlBuilder,
 	client: Client,
@@ -22,17 +33,35 @@ pub struct Job {
 	pub url: String,
 }
 
-#[derive(Deserialize, Debug)]
-struct GitlabJob {
-	id: i64,
-	name: String,
-	status: String,
-	web_url: String,
+#[derive(Deserialize, Debug, Clone)]
+pub struct GitlabJob {
+	pub id: i64,
+	pub name: String,
+	pub stage: String,
+	pub status: String,
+	pub web_url: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Pipeline {
+	pub id: i64,
+	pub sha: String,
+	pub status: String,
+	pub web_url: String,
 }
 
 #[derive(Deserialize, Debug, Clone)]
-struct Pipeline {
-	id: i64,
+pub struct Commit {
+	pub id: String,
+	pub title: String,
+}
+
+/// The parts of a Gitlab API response the bot looks at.
+#[derive(Debug, Clone)]
+struct GitlabResponse {
+	status: StatusCode,
+	headers: header::HeaderMap,
+	body: String,
 }
 
 impl GitlabBot {
@@ -55,14 +84,14 @@ impl GitlabBot {
 			.timeout(std::time::Duration::from_secs(30))
 			.build()?;
 
-		// This request is just for checking that Gitlab is available and the token is valid.
-		let project_url = urls.project_url()?;
-		client.get(project_url).send().await?;
+		Ok(Self { urls, client })
+	}
 
-		Ok(Self {
-			urls,
-			client: client.to_owned(),
-		})
+	/// Check that Gitlab is available and the token can read the project.
+	pub async fn check_project(&self) -> Result<()> {
+		self.request(Method::GET, &self.urls.project_url()?, None)
+			.await
+			.map(|_| ())
 	}
 
 	pub async fn create_file(
@@ -79,10 +108,264 @@ impl GitlabBot {
 			"content": content
 		});
 
-		let url = self.urls.create_file_url(path)?;
-		self.client.post(url).json(&body).send().await?;
+		let url = self.urls.file_url(path)?;
+		self.request(Method::POST, &url, Some(&body)).await?;
 		Ok(())
 	}
+
+	/// Return the content of a file on `branch`, if it exists.
+	pub async fn file_content(
+		&self,
+		path: &str,
+		branch: &str,
+	) -> Result<Option<String>> {
+		let url = self.urls.raw_file_url(path, branch)?;
+		match self.request(Method::GET, &url, None).await {
+			Ok(res) => Ok(Some(res.body)),
+			Err(Error::GitlabApi { status: 404, .. }) => Ok(None),
+			Err(e) => Err(e),
+		}
+	}
+
+	/// Delete a file from `branch`. Returns `false` if the file does not exist.
+	pub async fn delete_file(
+		&self,
+		path: &str,
+		branch: &str,
+		commit_msg: &str,
+	) -> Result<bool> {
+		let body = serde_json::json!({
+			"author_name": "processbot",
+			"branch": branch,
+			"commit_message": commit_msg,
+		});
+
+		let url = self.urls.file_url(path)?;
+		match self.request(Method::DELETE, &url, Some(&body)).await {
+			Ok(_) => Ok(true),
+			Err(Error::GitlabApi { status: 404, .. }) => Ok(false),
+			Err(Error::GitlabApi {
+				status: 400,
+				message: Some(message),
+				..
+			}) if message.contains("doesn't exist") => Ok(false),
+			Err(e) => Err(e),
+		}
+	}
+
+	/// Return the most recent commit on `branch` touching `path`, if any.
+	pub async fn last_commit_for_path(
+		&self,
+		path: &str,
+		branch: &str,
+	) -> Result<Option<Commit>> {
+		let url = self.urls.commits_url(path, branch)?;
+		let commits: Vec<Commit> = self.get_json(&url).await?;
+		Ok(commits.into_iter().next())
+	}
+
+	/// Return the pipelines triggered for a commit, most recent first.
+	pub async fn pipelines_for_commit(
+		&self,
+		sha: &str,
+	) -> Result<Vec<Pipeline>> {
+		let url = self.urls.pipelines_url(sha)?;
+		self.get_json(&url).await
+	}
+
+	/// Return all pipelines for a branch or tag, most recent first.
+	pub async fn list_pipelines(
+		&self,
+		ref_name: &str,
+	) -> Result<Vec<Pipeline>> {
+		let url = self.urls.list_pipelines_url(ref_name)?;
+		self.get_all(&url).await
+	}
+
+	/// Return the jobs of a pipeline.
+	pub async fn pipeline_jobs(
+		&self,
+		pipeline_id: i64,
+	) -> Result<Vec<GitlabJob>> {
+		let url = self.urls.pipeline_jobs_url(pipeline_id)?;
+		self.get_all(&url).await
+	}
+
+	/// Return the last `lines` lines of the log of a job.
+	pub async fn job_log_tail(
+		&self,
+		job_id: i64,
+		lines: usize,
+	) -> Result<String> {
+		let url = self.urls.job_trace_url(job_id)?;
+		let res = self.request(Method::GET, &url, None).await?;
+		Ok(tail(&res.body, lines).to_string())
+	}
+
+	async fn get_json<T: DeserializeOwned>(&self, url: &Url) -> Result<T> {
+		let res = self.request(Method::GET, url, None).await?;
+		Ok(serde_json::from_str(&res.body)?)
+	}
+
+	/// Fetch every page of a list endpoint.
+	async fn get_all<T: DeserializeOwned>(&self, url: &Url) -> Result<Vec<T>> {
+		collect_pages(|page| {
+			let url = page_url(url, &page);
+			async move {
+				let res = self.request(Method::GET, &url, None).await?;
+				let items = serde_json::from_str::<Vec<T>>(&res.body)?;
+				Ok((items, next_page(&res.headers)))
+			}
+		})
+		.await
+	}
+
+	async fn send(
+		&self,
+		method: Method,
+		url: &Url,
+		body: Option<&serde_json::Value>,
+	) -> Result<GitlabResponse> {
+		let mut req = self.client.request(method, url.clone());
+		if let Some(body) = body {
+			req = req.json(body);
+		}
+		let res = req.send().await?;
+		Ok(GitlabResponse {
+			status: res.status(),
+			headers: res.headers().clone(),
+			body: res.text().await?,
+		})
+	}
+
+	/// Send a request, waiting and retrying while rate limited.  Unsuccessful
+	/// responses are returned as `Error::GitlabApi`.
+	async fn request(
+		&self,
+		method: Method,
+		url: &Url,
+		body: Option<&serde_json::Value>,
+	) -> Result<GitlabResponse> {
+		let res =
+			with_rate_limit_retries(|| self.send(method.clone(), url, body))
+				.await?;
+		check_status(&method, url, res)
+	}
+}
+
+/// Call `send` until it is no longer rate limited or the retries run out.
+async fn with_rate_limit_retries<F, Fut>(mut send: F) -> Result<GitlabResponse>
+where
+	F: FnMut() -> Fut,
+	Fut: Future<Output = Result<GitlabResponse>>,
+{
+	let mut retries = 0;
+	loop {
+		let res = send().await?;
+		if res.status != StatusCode::TOO_MANY_REQUESTS
+			|| retries >= RATE_LIMIT_RETRIES
+		{
+			return Ok(res);
+		}
+		let wait = retry_after(&res.headers);
+		log::warn!("Rate limited by Gitlab; retrying in {:?}", wait);
+		tokio::time::delay_for(wait).await;
+		retries += 1;
+	}
+}
+
+/// Read the `Retry-After` header, which Gitlab sends in seconds.
+fn retry_after(headers: &header::HeaderMap) -> Duration {
+	let secs = headers
+		.get(header::RETRY_AFTER)
+		.and_then(|v| v.to_str().ok())
+		.and_then(|v| v.trim().parse::<u64>().ok())
+		.unwrap_or(1);
+	Duration::from_secs(secs.min(MAX_RETRY_AFTER_SECS))
+}
+
+/// Concatenate the items of consecutive pages, starting at page 1, until
+/// `fetch_page` reports there is no next page.
+async fn collect_pages<T, F, Fut>(mut fetch_page: F) -> Result<Vec<T>>
+where
+	F: FnMut(String) -> Fut,
+	Fut: Future<Output = Result<(Vec<T>, Option<String>)>>,
+{
+	let mut items = vec![];
+	let mut page = "1".to_string();
+	loop {
+		let (mut page_items, next) = fetch_page(page).await?;
+		items.append(&mut page_items);
+		match next {
+			Some(next) => page = next,
+			None => return Ok(items),
+		}
+	}
+}
+
+/// Read the `X-Next-Page` header, which is empty on the last page.
+fn next_page(headers: &header::HeaderMap) -> Option<String> {
+	headers
+		.get("x-next-page")
+		.and_then(|v| v.to_str().ok())
+		.map(|v| v.trim().to_string())
+		.filter(|v| !v.is_empty())
+}
+
+fn page_url(url: &Url, page: &str) -> Url {
+	let mut page_url = url.clone();
+	let pairs = url
+		.query_pairs()
+		.filter(|(k, _)| k != "page" && k != "per_page")
+		.map(|(k, v)| (k.into_owned(), v.into_owned()))
+		.collect::<Vec<_>>();
+	page_url
+		.query_pairs_mut()
+		.clear()
+		.extend_pairs(pairs)
+		.append_pair("per_page", PER_PAGE)
+		.append_pair("page", page);
+	page_url
+}
+
+/// Extract the error message from a Gitlab error body, which is either
+/// `{"message": ...}` (where the message may itself be an object, eg. for
+/// validation errors) or `{"error": "..."}`.
+pub fn error_message(body: &str) -> Option<String> {
+	let value = serde_json::from_str::<serde_json::Value>(body).ok()?;
+	match value.get("message").or_else(|| value.get("error"))? {
+		serde_json::Value::String(s) => Some(s.clone()),
+		other => Some(other.to_string()),
+	}
+}
+
+/// Map an unsuccessful response to `Error::GitlabApi`.
+fn check_status(
+	method: &Method,
+	url: &Url,
+	res: GitlabResponse,
+) -> Result<GitlabResponse> {
+	if res.status.is_success() {
+		Ok(res)
+	} else {
+		Err(Error::GitlabApi {
+			method: method.to_string(),
+			url: url.to_string(),
+			status: res.status.as_u16().into(),
+			message: error_message(&res.body),
+			body: res.body,
+		})
+	}
+}
+
+/// Return the last `lines` lines of `text`.
+fn tail(text: &str, lines: usize) -> &str {
+	let text = text.trim_end_matches('\n');
+	match text.rmatch_indices('\n').nth(lines.saturating_sub(1)) {
+		Some((i, _)) if lines > 0 => &text[i + 1..],
+		_ if lines == 0 => "",
+		_ => text,
+	}
 }
 
 struct UrlBuilder {
@@ -124,12 +407,12 @@ impl UrlBuilder {
 		Ok(project_url)
 	}
 
-	pub fn create_file_url(&self, path: &str) -> Result<Url> {
-		let mut create_file_url = self.base_url.clone();
+	pub fn file_url(&self, path: &str) -> Result<Url> {
+		let mut file_url = self.base_url.clone();
 
 		{
 			let mut path_segments =
-				create_file_url.path_segments_mut().or_else(|()| {
+				file_url.path_segments_mut().or_else(|()| {
 					Err(Error::UrlCannotBeBase {
 						url: self.base_url.to_string(),
 					})
@@ -139,7 +422,92 @@ impl UrlBuilder {
 			path_segments.extend(&["repository", "files", &path.to_string()]);
 		}
 
-		Ok(create_file_url)
+		Ok(file_url)
+	}
+
+	pub fn raw_file_url(&self, path: &str, branch: &str) -> Result<Url> {
+		let mut raw_file_url = self.file_url(path)?;
+
+		raw_file_url
+			.path_segments_mut()
+			.map_err(|()| Error::UrlCannotBeBase {
+				url: self.base_url.to_string(),
+			})?
+			.push("raw");
+		raw_file_url.query_pairs_mut().append_pair("ref", branch);
+
+		Ok(raw_file_url)
+	}
+
+	pub fn commits_url(&self, path: &str, branch: &str) -> Result<Url> {
+		let mut commits_url = self.project_url()?;
+
+		commits_url
+			.path_segments_mut()
+			.map_err(|()| Error::UrlCannotBeBase {
+				url: self.base_url.to_string(),
+			})?
+			.extend(&["repository", "commits"]);
+		commits_url
+			.query_pairs_mut()
+			.append_pair("ref_name", branch)
+			.append_pair("path", path);
+
+		Ok(commits_url)
+	}
+
+	pub fn list_pipelines_url(&self, ref_name: &str) -> Result<Url> {
+		let mut pipelines_url = self.project_url()?;
+
+		pipelines_url
+			.path_segments_mut()
+			.map_err(|()| Error::UrlCannotBeBase {
+				url: self.base_url.to_string(),
+			})?
+			.push("pipelines");
+		pipelines_url.query_pairs_mut().append_pair("ref", ref_name);
+
+		Ok(pipelines_url)
+	}
+
+	pub fn pipeline_jobs_url(&self, pipeline_id: i64) -> Result<Url> {
+		let mut jobs_url = self.project_url()?;
+
+		jobs_url
+			.path_segments_mut()
+			.map_err(|()| Error::UrlCannotBeBase {
+				url: self.base_url.to_string(),
+			})?
+			.extend(&["pipelines", &pipeline_id.to_string(), "jobs"]);
+
+		Ok(jobs_url)
+	}
+
+	pub fn job_trace_url(&self, job_id: i64) -> Result<Url> {
+		let mut trace_url = self.project_url()?;
+
+		trace_url
+			.path_segments_mut()
+			.map_err(|()| Error::UrlCannotBeBase {
+				url: self.base_url.to_string(),
+			})?
+			.extend(&["jobs", &job_id.to_string(), "trace"]);
+
+		Ok(trace_url)
+	}
+
+	pub fn pipelines_url(&self, sha: &str) -> Result<Url> {
+		let mut pipelines_url = self.project_url()?;
+
+		pipelines_url
+			.path_segments_mut()
+			.map_err(|()| Error::UrlCannotBeBase {
+				url: self.base_url.to_string(),
+			})?
+			.push("pipelines");
+		pipelines_url.query_pairs_mut().append_pair("sha", sha);
+
+		Ok(pipelines_url)
 	}
 }
 
@@ -168,13 +536,252 @@ mod tests {
 	}
 
 	#[test]
-	fn test_create_file_url() {
-		let cf_url =
-			builder().create_file_url("requests/request-1610469388.toml");
+	fn test_file_url() {
+		let cf_url = builder().file_url("requests/request-1610469388.toml");
 
 		assert_url(
 			cf_url,
 			"https://gitlab.parity.io/api/v4/projects/parity%2Fprocessbot-test-repo/repository/files/requests%2Frequest-1610469388.toml"
 		);
 	}
+
+	#[test]
+	fn test_raw_file_url() {
+		let raw_file_url = builder()
+			.raw_file_url("requests/request-1610469388.toml", "master");
+
+		assert_url(
+			raw_file_url,
+			"https://gitlab.parity.io/api/v4/projects/parity%2Fprocessbot-test-repo/repository/files/requests%2Frequest-1610469388.toml/raw?ref=master"
+		);
+	}
+
+	#[test]
+	fn test_commits_url() {
+		let commits_url =
+			builder().commits_url("requests/request-1610469388.toml", "master");
+
+		assert_url(
+			commits_url,
+			"https://gitlab.parity.io/api/v4/projects/parity%2Fprocessbot-test-repo/repository/commits?ref_name=master&path=requests%2Frequest-1610469388.toml"
+		);
+	}
+
+	#[test]
+	fn test_pipelines_url() {
+		let pipelines_url = builder().pipelines_url("a7b3c1d");
+
+		assert_url(
+			pipelines_url,
+			"https://gitlab.parity.io/api/v4/projects/parity%2Fprocessbot-test-repo/pipelines?sha=a7b3c1d"
+		);
+	}
+
+	#[test]
+	fn test_list_pipelines_url() {
+		assert_url(
+			builder().list_pipelines_url("master"),
+			"https://gitlab.parity.io/api/v4/projects/parity%2Fprocessbot-test-repo/pipelines?ref=master"
+		);
+	}
+
+	#[test]
+	fn test_pipeline_jobs_url() {
+		assert_url(
+			builder().pipeline_jobs_url(1213),
+			"https://gitlab.parity.io/api/v4/projects/parity%2Fprocessbot-test-repo/pipelines/1213/jobs"
+		);
+	}
+
+	#[test]
+	fn test_job_trace_url() {
+		assert_url(
+			builder().job_trace_url(3580),
+			"https://gitlab.parity.io/api/v4/projects/parity%2Fprocessbot-test-repo/jobs/3580/trace"
+		);
+	}
+
+	#[test]
+	fn test_page_url() {
+		let url = builder().list_pipelines_url("master").unwrap();
+		let page_2 = page_url(&url, "2");
+		assert_eq!(page_2.query(), Some("ref=master&per_page=100&page=2"));
+		assert_eq!(
+			page_url(&page_2, "3").query(),
+			Some("ref=master&per_page=100&page=3")
+		);
+	}
+
+	fn response(
+		status: u16,
+		headers: &[(&'static str, &str)],
+		body: &str,
+	) -> GitlabResponse {
+		let mut header_map = header::HeaderMap::new();
+		for (k, v) in headers {
+			header_map.insert(*k, header::HeaderValue::from_str(v).unwrap());
+		}
+		GitlabResponse {
+			status: StatusCode::from_u16(status).unwrap(),
+			headers: header_map,
+			body: body.to_string(),
+		}
+	}
+
+	#[test]
+	fn test_next_page() {
+		assert_eq!(
+			next_page(&response(200, &[("x-next-page", "2")], "").headers),
+			Some("2".to_string())
+		);
+		assert_eq!(
+			next_page(&response(200, &[("x-next-page", "")], "").headers),
+			None
+		);
+		assert_eq!(next_page(&response(200, &[], "").headers), None);
+	}
+
+	#[tokio::test]
+	async fn test_collect_pages() {
+		// mocked responses of a list endpoint with three pages
+		let pages = vec![
+			response(200, &[("x-next-page", "2")], "[1, 2]"),
+			response(200, &[("x-next-page", "3")], "[3, 4]"),
+			response(200, &[("x-next-page", "")], "[5]"),
+		];
+		let mut requested = vec![];
+		let items = collect_pages(|page| {
+			requested.push(page.clone());
+			let res = pages[page.parse::<usize>().unwrap() - 1].clone();
+			async move {
+				let items = serde_json::from_str::<Vec<i64>>(&res.body)?;
+				Ok((items, next_page(&res.headers)))
+			}
+		})
+		.await
+		.unwrap();
+		assert_eq!(items, vec![1, 2, 3, 4, 5]);
+		assert_eq!(requested, vec!["1", "2", "3"]);
+
+		// an error on any page is returned
+		let res = collect_pages::<i64, _, _>(|page| async move {
+			if page == "1" {
+				Ok((vec![1], Some("2".to_string())))
+			} else {
+				Err(Error::Message {
+					msg: "boom".to_string(),
+				})
+			}
+		})
+		.await;
+		assert!(res.is_err());
+	}
+
+	#[test]
+	fn test_error_message() {
+		assert_eq!(
+			error_message(r#"{"message":"404 Project Not Found"}"#),
+			Some("404 Project Not Found".to_string())
+		);
+		assert_eq!(
+			error_message(r#"{"message":{"branch":["is invalid"]}}"#),
+			Some(r#"{"branch":["is invalid"]}"#.to_string())
+		);
+		assert_eq!(
+			error_message(r#"{"error":"content is missing"}"#),
+			Some("content is missing".to_string())
+		);
+		assert_eq!(error_message("<html>502 Bad Gateway</html>"), None);
+		assert_eq!(error_message(r#"{"id": 1}"#), None);
+	}
+
+	#[test]
+	fn test_check_status() {
+		let url = builder().project_url().unwrap();
+		assert!(
+			check_status(&Method::GET, &url, response(200, &[], "{}")).is_ok()
+		);
+		match check_status(
+			&Method::POST,
+			&url,
+			response(
+				400,
+				&[],
+				r#"{"message":"A file with this name already exists"}"#,
+			),
+		) {
+			Err(Error::GitlabApi {
+				method,
+				status,
+				message,
+				..
+			}) => {
+				assert_eq!(method, "POST");
+				assert_eq!(status, 400);
+				assert_eq!(
+					message.as_deref(),
+					Some("A file with this name already exists")
+				);
+			}
+			res => panic!("unexpected result: {:?}", res),
+		}
+	}
+
+	#[test]
+	fn test_retry_after() {
+		assert_eq!(
+			retry_after(&response(429, &[("retry-after", "5")], "").headers),
+			Duration::from_secs(5)
+		);
+		assert_eq!(
+			retry_after(&response(429, &[("retry-after", "3600")], "").headers),
+			Duration::from_secs(MAX_RETRY_AFTER_SECS)
+		);
+		assert_eq!(
+			retry_after(&response(429, &[], "").headers),
+			Duration::from_secs(1)
+		);
+	}
+
+	#[tokio::test]
+	async fn test_rate_limit_retries() {
+		let rate_limited = response(429, &[("retry-after", "0")], "");
+
+		let mut calls = 0;
+		let res = with_rate_limit_retries(|| {
+			calls += 1;
+			let res = if calls < 3 {
+				rate_limited.clone()
+			} else {
+				response(200, &[], "[]")
+			};
+			async move { Ok(res) }
+		})
+		.await
+		.unwrap();
+		assert_eq!(res.status, StatusCode::OK);
+		assert_eq!(calls, 3);
+
+		// gives up and returns the rate limited response eventually
+		let mut calls = 0;
+		let res = with_rate_limit_retries(|| {
+			calls += 1;
+			let res = rate_limited.clone();
+			async move { Ok(res) }
+		})
+		.await
+		.unwrap();
+		assert_eq!(res.status, StatusCode::TOO_MANY_REQUESTS);
+		assert_eq!(calls, RATE_LIMIT_RETRIES + 1);
+	}
+
+	#[test]
+	fn test_tail() {
+		let log = "one\ntwo\nthree\n";
+		assert_eq!(tail(log, 2), "two\nthree");
+		assert_eq!(tail(log, 3), "one\ntwo\nthree");
+		assert_eq!(tail(log, 10), "one\ntwo\nthree");
+		assert_eq!(tail(log, 0), "");
+		assert_eq!(tail("", 5), "");
+	}
 }
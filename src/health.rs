@@ -0,0 +1,90 @@
+use crate::{
+	github_bot::GithubBot, gitlab_bot::GitlabBot, matrix_bot::MatrixBot,
+};
+use futures::future::{self, BoxFuture, FutureExt};
+use serde::Serialize;
+
+/// A service the bot depends on, checked once at startup.
+pub trait StartupCheck: Sync {
+	fn name(&self) -> &'static str;
+
+	/// Fails with a message explaining what is wrong.
+	fn check(&self) -> BoxFuture<'_, Result<(), String>>;
+}
+
+/// The GitHub App can mint an installation token and read the organization it
+/// is installed on.
+impl StartupCheck for GithubBot {
+	fn name(&self) -> &'static str {
+		"github"
+	}
+
+	fn check(&self) -> BoxFuture<'_, Result<(), String>> {
+		async move {
+			self.client
+				.auth_key()
+				.await
+				.map_err(|e| format!("minting an installation token: {}", e))?;
+			let org = self.client.installation_login();
+			self.org(org)
+				.await
+				.map_err(|e| format!("reading {}: {}", org, e))?;
+			Ok(())
+		}
+		.boxed()
+	}
+}
+
+/// The token can read the project burn-in requests are committed to.
+impl StartupCheck for GitlabBot {
+	fn name(&self) -> &'static str {
+		"gitlab"
+	}
+
+	fn check(&self) -> BoxFuture<'_, Result<(), String>> {
+		async move { self.check_project().await.map_err(|e| e.to_string()) }
+			.boxed()
+	}
+}
+
+/// The session can resolve the default room.
+impl StartupCheck for MatrixBot {
+	fn name(&self) -> &'static str {
+		"matrix"
+	}
+
+	fn check(&self) -> BoxFuture<'_, Result<(), String>> {
+		future::ready(self.check_default_room().map_err(|e| e.to_string()))
+			.boxed()
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CheckResult {
+	pub name: String,
+	/// `None` if the check passed
+	pub error: Option<String>,
+}
+
+/// The results of the startup checks, served at `/health`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Health {
+	pub checks: Vec<CheckResult>,
+}
+
+impl Health {
+	pub fn is_ok(&self) -> bool {
+		self.checks.iter().all(|check| check.error.is_none())
+	}
+
+	pub fn failures(&self) -> impl Iterator<Item = &CheckResult> {
+		self.checks.iter().filter(|check| check.error.is_some())
+	}
+
+	pub fn payload(&self) -> serde_json::Value {
+		serde_json::json!({
+			"status": if self.is_ok() { "ok" } else { "degraded" },
+			"checks": self.checks,
+		})
+	}
+}
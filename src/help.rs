@@ -0,0 +1,187 @@
+use itertools::Itertools;
+
+use crate::{command_prefix, constants::*};
+
+/// The commands, as used, and what they do; see README.md for the details.
+const COMMANDS: [(&str, &str); 28] = [
+	(AUTO_MERGE_REQUEST, "merge the PR once its checks pass"),
+	(
+		AUTO_MERGE_CONFIRM,
+		"confirm a `bot merge` which needs confirming",
+	),
+	(
+		AUTO_MERGE_SKIP_BENCH,
+		"merge the PR without its performance gate",
+	),
+	(
+		AUTO_MERGE_FORCE,
+		"merge the PR without waiting for its checks",
+	),
+	(
+		"bot merge at <time>",
+		"merge the PR once its checks pass, not before `<time>`, eg. `2024-05-01T14:00Z` or `in 3h`",
+	),
+	(AUTO_MERGE_CANCEL, "cancel a pending `bot merge`"),
+	(AUTOMERGE_OFF, "stop the PR from being merged automatically"),
+	(
+		BOT_CHECK,
+		"set the `processbot/merge-gate` status to whether the PR would be merged",
+	),
+	(
+		BOT_PROCESS,
+		"see the projects, owners and approvals the bot finds for the PR",
+	),
+	(BOT_IGNORE, "make the bot leave the PR alone"),
+	(BOT_UNIGNORE, "undo `bot ignore`"),
+	(
+		COMPARE_RELEASE_REQUEST,
+		"diff the Substrate of the PR and of the latest Polkadot release",
+	),
+	(REBASE, "merge the default branch into the PR"),
+	(
+		"bot rebase onto <branch>",
+		"merge `<branch>` into the PR and make it its base",
+	),
+	(
+		"bot backport <branch>...",
+		"open PRs cherry-picking the merged PR onto each branch",
+	),
+	(
+		"bot cherry-pick <sha> to <branch>",
+		"open a PR cherry-picking a commit onto `<branch>`",
+	),
+	(
+		REFRESH_PROCESS,
+		"discard the cached `Process.json` of the repository",
+	),
+	(BURNIN_REQUEST, "build and deploy the PR for a burn-in test"),
+	(BURNIN_STATUS, "see the state of the last burn-in of the PR"),
+	(BURNIN_CANCEL, "withdraw the last burn-in of the PR"),
+	("bot freeze <reason>", "stop all merges into the repository"),
+	(MERGE_UNFREEZE, "undo `bot freeze`"),
+	(
+		"bot admin deny <login>",
+		"refuse any further command from a user",
+	),
+	("bot admin undeny <login>", "undo `bot admin deny`"),
+	("bot stats [30d|2w]", "see how merges went over a period"),
+	(
+		"bot release-notes <from> <to>",
+		"list the PRs merged between two tags",
+	),
+	(
+		BOT_VERSION,
+		"see the build and configuration the bot runs with",
+	),
+	(BOT_HELP, "list the commands"),
+];
+
+/// The first two words of a command, which tell it apart from the others, eg.
+/// `bot merge` for `bot merge force`.
+fn command_name(body: &str) -> String {
+	body.split_whitespace().take(2).join(" ").to_lowercase()
+}
+
+/// Whether a comment is one of the bot's commands, valid or not.
+pub fn is_command(body: &str) -> bool {
+	let name = command_name(body);
+	COMMANDS
+		.iter()
+		.any(|(command, _)| command_name(command) == name)
+}
+
+/// The list of commands, for `bot help`, written with the configured
+/// `prefix`.
+pub fn help(prefix: &str) -> String {
+	format!(
+		"Commands, to be commented on a pull request:\n\n{}\n\nSee the README of the bot for more.",
+		COMMANDS
+			.iter()
+			.map(|(command, what)| {
+				format!(
+					"- `{}`: {}",
+					command_prefix::render(command, prefix),
+					command_prefix::render_quoted(what, prefix)
+				)
+			})
+			.join("\n")
+	)
+}
+
+/// The reply to a comment on an issue which is not a PR: the help for
+/// `bot help`, and for the other commands that they are for PRs only.
+/// Other comments get no reply.
+pub fn issue_reply(body: &str, prefix: &str) -> Option<String> {
+	if command_name(body) == BOT_HELP {
+		Some(help(prefix))
+	} else if is_command(body) {
+		Some(format!(
+			"`{}` only applies to pull requests, and this is an issue.",
+			command_prefix::render(&command_name(body), prefix)
+		))
+	} else {
+		None
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_issue_reply() {
+		assert_eq!(
+			issue_reply("bot merge", "bot").as_deref(),
+			Some("`bot merge` only applies to pull requests, and this is an issue.")
+		);
+		for command in &[
+			" Bot Merge Force\n",
+			"bot rebase onto release-v0.9.30",
+			"bot backport release-v0.9.30",
+			"bot cherry-pick 1a2b3c4d to release-v0.9.30",
+			"bot freeze release",
+			"bot stats 2w",
+			// invalid, but a command all the same
+			"bot merge now",
+			"bot burnin",
+		] {
+			assert!(
+				issue_reply(command, "bot")
+					.unwrap()
+					.contains("only applies"),
+				"{}",
+				command
+			);
+		}
+		assert_eq!(issue_reply(" bot  HELP ", "bot"), Some(help("bot")));
+		assert_eq!(
+			issue_reply("bot merge", "/").as_deref(),
+			Some(
+				"`/merge` only applies to pull requests, and this is an issue."
+			)
+		);
+		for comment in &[
+			"",
+			"LGTM",
+			"bot",
+			"bots merge",
+			"the bot merge failed",
+			"bot please merge",
+			"merge bot",
+		] {
+			assert_eq!(issue_reply(comment, "bot"), None, "{}", comment);
+		}
+	}
+
+	#[test]
+	fn test_help_lists_commands() {
+		let help = help("bot");
+		for (command, _) in &COMMANDS {
+			assert!(help.contains(&format!("`{}`", command)), "{}", command);
+		}
+		let slash = super::help("/");
+		assert!(slash.contains("- `/merge force`: "));
+		assert!(slash.contains("- `/rebase onto <branch>`: "));
+		assert!(!slash.contains("`bot "));
+	}
+}
@@ -1,23 +1,292 @@
 use std::borrow::Cow;
-use std::time::SystemTime;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
 
 use crate::{error, github, Result};
 
 use chrono::{DateTime, Duration, Utc};
 use hyperx::header::TypedHeaders;
 use reqwest::{header, IntoUrl, Method, RequestBuilder, Response};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use snafu::{OptionExt, ResultExt};
 
 #[derive(Default)]
 pub struct Client {
 	pub client: reqwest::Client,
 	private_key: Vec<u8>,
+	/// the account requests not about a repository or an organization are
+	/// sent for
 	installation_login: String,
+	/// installation ids, by lower-case account login
+	installations: parking_lot::RwLock<HashMap<String, i64>>,
+	tokens: TokenCache,
+	/// whether the last attempt to mint an installation token failed
+	token_refresh_failed: Arc<AtomicBool>,
+	retry_policy: RetryPolicy,
+	/// requests sent again after a transient failure
+	retries: AtomicU64,
+}
+
+/// Whether sending a request twice has the same effect as sending it once.
+/// Only idempotent requests are retried.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Idempotency {
+	Idempotent,
+	NotIdempotent,
+}
+
+/// The fields GitHub error responses have in common.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ErrorBody {
+	#[serde(default)]
+	pub message: String,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub documentation_url: Option<String>,
+	/// what was wrong with the request, for validation errors
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub errors: Vec<serde_json::Value>,
+}
+
+/// An unsuccessful response from the GitHub API, told apart by its status,
+/// headers and body.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GithubApiError {
+	/// The primary or secondary (abuse) rate limit was exceeded, so the
+	/// request was not acted on.
+	RateLimited {
+		body: ErrorBody,
+		/// how long GitHub asked to wait before sending requests again
+		retry_after: Option<std::time::Duration>,
+	},
+	/// The token lacks a permission the request needs.
+	Forbidden(ErrorBody),
+	NotFound(ErrorBody),
+	/// GitHub refused to merge a pull request, eg. because branch protection
+	/// requires a review or a status, or it has conflicts.
+	MergeBlocked(ErrorBody),
+	/// The head of a pull request moved while it was being merged.
+	Conflict(ErrorBody),
+	/// The request was invalid; `errors` says which fields and why.
+	Validation(ErrorBody),
+	/// Any other response, with its body as received.
+	Other(serde_json::Value),
+}
+
+impl GithubApiError {
+	pub fn new(
+		status: reqwest::StatusCode,
+		headers: &header::HeaderMap,
+		body: serde_json::Value,
+	) -> Self {
+		let header = |name: &str| {
+			headers
+				.get(name)
+				.and_then(|value| value.to_str().ok())
+				.and_then(|value| value.trim().parse::<i64>().ok())
+		};
+		let parsed = serde_json::from_value::<ErrorBody>(body.clone())
+			.ok()
+			.filter(|parsed| !parsed.message.is_empty());
+		let rate_limited = header("x-ratelimit-remaining") == Some(0)
+			|| parsed.as_ref().is_some_and(|parsed| {
+				let message = parsed.message.to_lowercase();
+				message.contains("rate limit") || message.contains("abuse")
+			});
+		if status == reqwest::StatusCode::TOO_MANY_REQUESTS
+			|| (status == reqwest::StatusCode::FORBIDDEN && rate_limited)
+		{
+			let retry_after = header("retry-after")
+				.or_else(|| {
+					header("x-ratelimit-reset")
+						.map(|reset| reset - Utc::now().timestamp())
+				})
+				.map(|secs| std::time::Duration::from_secs(secs.max(0) as u64));
+			return GithubApiError::RateLimited {
+				body: parsed.unwrap_or_default(),
+				retry_after,
+			};
+		}
+		// the status alone says that a resource was not found
+		if status == reqwest::StatusCode::NOT_FOUND {
+			return GithubApiError::NotFound(parsed.unwrap_or_default());
+		}
+		let parsed = match parsed {
+			Some(parsed) => parsed,
+			None => return GithubApiError::Other(body),
+		};
+		match status.as_u16() {
+			403 => GithubApiError::Forbidden(parsed),
+			405 => GithubApiError::MergeBlocked(parsed),
+			409 => GithubApiError::Conflict(parsed),
+			422 => GithubApiError::Validation(parsed),
+			_ => GithubApiError::Other(body),
+		}
+	}
+
+	pub fn body(&self) -> Option<&ErrorBody> {
+		match self {
+			GithubApiError::RateLimited { body, .. }
+			| GithubApiError::Forbidden(body)
+			| GithubApiError::NotFound(body)
+			| GithubApiError::MergeBlocked(body)
+			| GithubApiError::Conflict(body)
+			| GithubApiError::Validation(body) => Some(body),
+			GithubApiError::Other(_) => None,
+		}
+	}
+
+	/// The `message` of the response, or the whole body if it has none.
+	pub fn message(&self) -> String {
+		match self {
+			GithubApiError::Other(body) => {
+				match body.get("message").or_else(|| body.get("error_message"))
+				{
+					Some(serde_json::Value::String(message)) => message.clone(),
+					_ => body.to_string(),
+				}
+			}
+			_ => self.body().map_or_else(String::new, |b| b.message.clone()),
+		}
+	}
+
+	/// The body of the response, as far as it was understood.
+	pub fn to_json(&self) -> serde_json::Value {
+		match self {
+			GithubApiError::Other(body) => body.clone(),
+			_ => self
+				.body()
+				.and_then(|body| serde_json::to_value(body).ok())
+				.unwrap_or_default(),
+		}
+	}
+
+	pub fn retry_after(&self) -> Option<std::time::Duration> {
+		match self {
+			GithubApiError::RateLimited { retry_after, .. } => *retry_after,
+			_ => None,
+		}
+	}
+}
+
+impl std::fmt::Display for GithubApiError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "{}", self.message())
+	}
+}
+
+/// How requests which fail transiently, ie. time out or get a 5xx response,
+/// are retried.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+	pub max_retries: u32,
+	/// backoff before the first retry, doubled for each one after
+	pub initial_backoff: std::time::Duration,
+	pub max_backoff: std::time::Duration,
+	/// time allowed for all attempts and the backoffs between them
+	pub budget: std::time::Duration,
+}
+
+impl Default for RetryPolicy {
+	fn default() -> Self {
+		Self {
+			max_retries: 5,
+			initial_backoff: std::time::Duration::from_millis(500),
+			max_backoff: std::time::Duration::from_secs(10),
+			budget: std::time::Duration::from_secs(30),
+		}
+	}
+}
+
+impl RetryPolicy {
+	/// Half of the exponential backoff, plus up to the other half depending
+	/// on `jitter` (between 0 and 1), so that backoffs grow at least until
+	/// they reach `max_backoff`.
+	fn backoff(&self, retry: u32, jitter: f64) -> std::time::Duration {
+		let backoff = self
+			.initial_backoff
+			.checked_mul(1 << retry.min(31))
+			.map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff));
+		backoff / 2 + backoff.mul_f64(jitter.clamp(0.0, 1.0)) / 2
+	}
+}
+
+/// Overrides of the client's defaults for a single request.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RequestOptions {
+	/// by default only GET requests are idempotent
+	pub idempotency: Option<Idempotency>,
+	pub retry_policy: Option<RetryPolicy>,
+}
+
+impl RequestOptions {
+	/// Retry the request even if its method is not idempotent.
+	pub fn idempotent() -> Self {
+		Self {
+			idempotency: Some(Idempotency::Idempotent),
+			..Self::default()
+		}
+	}
+}
+
+fn jitter() -> f64 {
+	let mut bytes = [0u8; 4];
+	let _ = ring::rand::SecureRandom::fill(
+		&ring::rand::SystemRandom::new(),
+		&mut bytes,
+	);
+	f64::from(u32::from_le_bytes(bytes)) / f64::from(u32::MAX)
+}
+
+/// Call `attempt` until it succeeds, fails for good, or the retries allowed
+/// by `policy` run out, counting retries in `retries`.  Rate limited requests
+/// were not acted on, so they are retried even if not idempotent, waiting at
+/// least as long as GitHub asked.
+async fn with_retries<T, F, Fut>(
+	policy: &RetryPolicy,
+	idempotency: Idempotency,
+	retries: &AtomicU64,
+	mut attempt: F,
+) -> Result<T>
+where
+	F: FnMut() -> Fut,
+	Fut: Future<Output = Result<T>>,
+{
+	let started = Instant::now();
+	let mut retry = 0;
+	loop {
+		let e = match attempt().await {
+			Err(e)
+				if e.is_rate_limited()
+					|| (idempotency == Idempotency::Idempotent
+						&& e.is_transient()) =>
+			{
+				e
+			}
+			res => return res,
+		};
+		let backoff = policy.backoff(retry, jitter()).max(
+			e.github_api_error()
+				.and_then(GithubApiError::retry_after)
+				.unwrap_or_default(),
+		);
+		if retry >= policy.max_retries
+			|| started.elapsed() + backoff > policy.budget
+		{
+			log::warn!("Giving up after {} retries: {}", retry, e);
+			return Err(e);
+		}
+		log::debug!("Request failed; retrying in {:?}: {}", backoff, e);
+		retries.fetch_add(1, Ordering::Relaxed);
+		tokio::time::delay_for(backoff).await;
+		retry += 1;
+	}
 }
 
 macro_rules! impl_methods_with_body {
-	($($method:ident : $method_response_fn:ident),*) => {
+	($($method:ident : $method_response_fn:ident : $http_method:ident),*) => {
 		$(
 			pub async fn $method<'b, I, B, T>(&self, url: I, body: &B) -> Result<T>
 			where
@@ -42,25 +311,13 @@ macro_rules! impl_methods_with_body {
 				I: Into<Cow<'b, str>> + Clone,
 				B: Serialize + Clone,
 			{
-				// retry up to 5 times if request times out
-				let mut retries = 0;
-				'retry: loop {
-					let res = self.execute(
-						self.client
-						.$method(&*url.clone().into())
-						.json(&body.clone()),
-					)
-					.await;
-					// retry if timeout
-					if let Err(error::Error::Http { source: e, .. }) = res.as_ref() {
-						if e.is_timeout() && retries < 5 {
-							log::debug!("Request timed out; retrying");
-							retries += 1;
-							continue 'retry;
-						}
-					}
-					return res;
-				}
+				self.send_with(
+					Method::$http_method,
+					url,
+					body,
+					RequestOptions::default(),
+				)
+				.await
 			}
 
 		)*
@@ -74,6 +331,7 @@ async fn handle_response(response: Response) -> Result<Response> {
 	if status.is_success() {
 		Ok(response)
 	} else {
+		let headers = response.headers().clone();
 		let text = response.text().await.context(error::Http)?;
 
 		// Try to decode the response error as JSON otherwise store
@@ -86,7 +344,56 @@ async fn handle_response(response: Response) -> Result<Response> {
 			serde_json::json!({ "error_message": text })
 		};
 
-		error::Response { status, body }.fail()
+		error::Response {
+			status,
+			error: GithubApiError::new(status, &headers, body),
+		}
+		.fail()
+	}
+}
+
+/// Installation tokens, by the account they were minted for, as each
+/// installation of the app has its own.
+#[derive(Default)]
+pub struct TokenCache {
+	tokens: parking_lot::Mutex<HashMap<String, (DateTime<Utc>, String)>>,
+}
+
+impl TokenCache {
+	/// The token of `account`, unless it has expired by `now`.
+	pub fn get(&self, account: &str, now: DateTime<Utc>) -> Option<String> {
+		self.tokens
+			.lock()
+			.get(&account.to_lowercase())
+			.filter(|(expiry, _)| *expiry > now)
+			.map(|(_, token)| token.clone())
+	}
+
+	pub fn insert(&self, account: &str, expiry: DateTime<Utc>, token: String) {
+		self.tokens
+			.lock()
+			.insert(account.to_lowercase(), (expiry, token));
+	}
+
+	pub fn remove(&self, account: &str) {
+		self.tokens.lock().remove(&account.to_lowercase());
+	}
+}
+
+/// The account an API request is about, from its URL: the owner of
+/// `/repos/{owner}/...` or the organization of `/orgs/{org}/...`.
+pub fn api_url_account(url: &str) -> Option<&str> {
+	let path = url
+		.strip_prefix(crate::github_bot::GithubBot::BASE_URL)?
+		.trim_start_matches('/');
+	let mut segments = path.split(['/', '?']);
+	match (segments.next(), segments.next()) {
+		(Some("repos"), Some(account)) | (Some("orgs"), Some(account))
+			if !account.is_empty() =>
+		{
+			Some(account)
+		}
+		_ => None,
 	}
 }
 
@@ -100,11 +407,83 @@ impl Client {
 		}
 	}
 
+	/// The account the app is installed on which requests not about a
+	/// repository or an organization are sent for.
+	pub fn installation_login(&self) -> &str {
+		&self.installation_login
+	}
+
+	/// Remember the installation of the app on `account`, eg. when GitHub
+	/// says it was installed.
+	pub fn set_installation(&self, account: &str, id: i64) {
+		let previous = self
+			.installations
+			.write()
+			.insert(account.to_lowercase(), id);
+		if previous.is_some_and(|previous| previous != id) {
+			self.tokens.remove(account);
+		}
+	}
+
+	pub fn remove_installation(&self, account: &str) {
+		self.installations.write().remove(&account.to_lowercase());
+		self.tokens.remove(account);
+	}
+
+	pub fn installation_id(&self, account: &str) -> Option<i64> {
+		self.installations
+			.read()
+			.get(&account.to_lowercase())
+			.copied()
+	}
+
 	impl_methods_with_body! {
-		post: post_response,
-		put: put_response,
-		patch: patch_response,
-		delete: delete_response
+		post: post_response: POST,
+		put: put_response: PUT,
+		patch: patch_response: PATCH,
+		delete: delete_response: DELETE
+	}
+
+	pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+		self.retry_policy = retry_policy;
+	}
+
+	/// Requests sent again after a transient failure, since starting.
+	pub fn retries(&self) -> u64 {
+		self.retries.load(Ordering::Relaxed)
+	}
+
+	/// Sends a request with `body`, retrying it if it is idempotent.
+	pub async fn send_with<'b, I, B>(
+		&self,
+		method: Method,
+		url: I,
+		body: &B,
+		options: RequestOptions,
+	) -> Result<Response>
+	where
+		I: Into<Cow<'b, str>>,
+		B: Serialize,
+	{
+		let idempotency =
+			options.idempotency.unwrap_or(if method == Method::GET {
+				Idempotency::Idempotent
+			} else {
+				Idempotency::NotIdempotent
+			});
+		let url = url.into();
+		with_retries(
+			&options.retry_policy.unwrap_or(self.retry_policy),
+			idempotency,
+			&self.retries,
+			|| {
+				self.execute(
+					&url,
+					self.client.request(method.clone(), &*url).json(body),
+				)
+			},
+		)
+		.await
 	}
 
 	pub async fn request(
@@ -115,45 +494,73 @@ impl Client {
 		self.client.request(method, url)
 	}
 
-	pub async fn auth_key(&self) -> Result<String> {
-		log::debug!("auth_key");
-		lazy_static::lazy_static! {
-			static ref TOKEN_CACHE: parking_lot::Mutex<Option<(DateTime<Utc>, String)>> = {
-				parking_lot::Mutex::new(None)
-			};
-		}
+	/// Set whenever an installation token is minted, to whether that failed.
+	pub fn token_refresh_failed(&self) -> Arc<AtomicBool> {
+		Arc::clone(&self.token_refresh_failed)
+	}
 
-		let token = {
-			TOKEN_CACHE
-				.lock()
-				.as_ref()
-				// Ensure token is not expired if set.
-				.filter(|(time, _)| time > &Utc::now())
-				.map(|(_, token)| token.clone())
-		};
+	/// Use `token` for the installation on `account` until `expiry`, as if it
+	/// were minted.
+	#[cfg(test)]
+	pub fn set_token(&self, account: &str, expiry: DateTime<Utc>, token: &str) {
+		self.tokens.insert(account, expiry, token.to_string());
+	}
+
+	/// A token of the installation on `installation_login`.
+	pub async fn auth_key(&self) -> Result<String> {
+		self.auth_key_for(&self.installation_login).await
+	}
 
-		if let Some(token) = token {
+	/// A token of the installation on `account`, eg. the owner of the
+	/// repository to clone.
+	pub async fn auth_key_for(&self, account: &str) -> Result<String> {
+		log::debug!("auth_key_for {}", account);
+		if let Some(token) = self.tokens.get(account, Utc::now()) {
 			return Ok(token);
 		}
 
+		let minted = self.mint_installation_token(account).await;
+		self.token_refresh_failed
+			.store(minted.is_err(), Ordering::Relaxed);
+		let (expiry, token) = minted?;
+
+		self.tokens.insert(account, expiry, token.clone());
+		Ok(token)
+	}
+
+	/// The id of the installation on `account`, looked up among the app's
+	/// installations unless it is known already.
+	async fn find_installation(&self, account: &str) -> Result<i64> {
+		if let Some(id) = self.installation_id(account) {
+			return Ok(id);
+		}
 		let installations: Vec<github::Installation> = self
 			.jwt_get(&format!(
 				"{}/app/installations",
 				crate::github_bot::GithubBot::BASE_URL
 			))
 			.await?;
-
-		let installation = installations
+		let id = installations
 			.iter()
-			.find(|inst| inst.account.login == self.installation_login)
-			.context(error::MissingData)?;
+			.find(|inst| inst.account.login.eq_ignore_ascii_case(account))
+			.context(error::MissingData)?
+			.id;
+		self.set_installation(account, id);
+		Ok(id)
+	}
+
+	async fn mint_installation_token(
+		&self,
+		account: &str,
+	) -> Result<(DateTime<Utc>, String)> {
+		let installation_id = self.find_installation(account).await?;
 
 		let install_token: github::InstallationToken = self
 			.jwt_post(
 				&format!(
 					"{}/app/installations/{}/access_tokens",
 					crate::github_bot::GithubBot::BASE_URL,
-					installation.id
+					installation_id
 				),
 				&serde_json::json!({}),
 			)
@@ -163,18 +570,19 @@ impl Client {
 		let expiry = install_token
 			.expires_at
 			.map_or(default_exp, |t| t.parse().unwrap_or(default_exp));
-		let token = install_token.token;
-
-		{
-			*TOKEN_CACHE.lock() = Some((expiry.clone(), token.clone()))
-		};
-
-		Ok(token)
+		Ok((expiry, install_token.token))
 	}
 
-	async fn execute(&self, builder: RequestBuilder) -> Result<Response> {
+	/// Send a request to `url` with the token of the installation it is
+	/// about.
+	async fn execute(
+		&self,
+		url: &str,
+		builder: RequestBuilder,
+	) -> Result<Response> {
+		let account = api_url_account(url).unwrap_or(&self.installation_login);
 		let request = builder
-			.bearer_auth(&self.auth_key().await?)
+			.bearer_auth(&self.auth_key_for(account).await?)
 			.header(
 				header::ACCEPT,
 				"application/vnd.github.starfox-preview+json",
@@ -338,24 +746,8 @@ impl Client {
 		P: Serialize + Clone,
 	{
 		log::debug!("get_response");
-		// retry up to 5 times if request times out
-		let mut retries = 0;
-		'retry: loop {
-			let res = self
-				.execute(
-					self.client.get(&*url.clone().into()).json(&params.clone()),
-				)
-				.await;
-			// retry if timeout
-			if let Err(error::Error::Http { source: e, .. }) = res.as_ref() {
-				if e.is_timeout() && retries < 5 {
-					log::debug!("Request timed out; retrying");
-					retries += 1;
-					continue 'retry;
-				}
-			}
-			return res;
-		}
+		self.send_with(Method::GET, url, &params, RequestOptions::default())
+			.await
 	}
 
 	// Originally adapted from:
@@ -398,3 +790,440 @@ impl Client {
 		Ok(entities)
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::time::Duration;
+
+	/// Answers with each of `statuses` in turn, repeating the last one, and
+	/// remembers when it was called.
+	struct MockTransport {
+		statuses: parking_lot::Mutex<Vec<u16>>,
+		calls: parking_lot::Mutex<Vec<Instant>>,
+	}
+
+	impl MockTransport {
+		fn new(statuses: &[u16]) -> Self {
+			Self {
+				statuses: parking_lot::Mutex::new(statuses.to_vec()),
+				calls: parking_lot::Mutex::new(Vec::new()),
+			}
+		}
+
+		async fn send(&self) -> Result<Response> {
+			self.calls.lock().push(Instant::now());
+			let status = {
+				let mut statuses = self.statuses.lock();
+				if statuses.len() > 1 {
+					statuses.remove(0)
+				} else {
+					statuses[0]
+				}
+			};
+			let response = hyper::http::Response::builder()
+				.status(status)
+				.body("{}")
+				.unwrap();
+			handle_response(response.into()).await
+		}
+
+		fn gaps(&self) -> Vec<Duration> {
+			let calls = self.calls.lock();
+			calls.windows(2).map(|w| w[1] - w[0]).collect()
+		}
+	}
+
+	fn policy(max_retries: u32, budget_ms: u64) -> RetryPolicy {
+		RetryPolicy {
+			max_retries,
+			initial_backoff: Duration::from_millis(20),
+			max_backoff: Duration::from_millis(200),
+			budget: Duration::from_millis(budget_ms),
+		}
+	}
+
+	fn send(
+		transport: &MockTransport,
+		policy: &RetryPolicy,
+		idempotency: Idempotency,
+		retries: &AtomicU64,
+	) -> Result<Response> {
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		rt.block_on(with_retries(policy, idempotency, retries, || {
+			transport.send()
+		}))
+	}
+
+	fn status(res: Result<Response>) -> u16 {
+		match res {
+			Ok(response) => response.status().as_u16(),
+			Err(error::Error::Response { status, .. }) => status.as_u16(),
+			Err(e) => panic!("unexpected error: {}", e),
+		}
+	}
+
+	#[test]
+	fn test_backoff() {
+		let policy = policy(10, 1000);
+		let millis = |retry, jitter| policy.backoff(retry, jitter).as_millis();
+		assert_eq!(
+			(0..5).map(|retry| millis(retry, 0.0)).collect::<Vec<_>>(),
+			vec![10, 20, 40, 80, 100]
+		);
+		assert_eq!(
+			(0..5).map(|retry| millis(retry, 1.0)).collect::<Vec<_>>(),
+			vec![20, 40, 80, 160, 200]
+		);
+		assert_eq!(millis(40, 1.0), 200);
+	}
+
+	#[test]
+	fn test_retries_with_growing_backoff() {
+		let transport = MockTransport::new(&[503, 502, 500, 200]);
+		let retries = AtomicU64::new(0);
+		let res = send(
+			&transport,
+			&policy(5, 1000),
+			Idempotency::Idempotent,
+			&retries,
+		);
+		assert_eq!(status(res), 200);
+		assert_eq!(retries.load(Ordering::Relaxed), 3);
+		let gaps = transport.gaps();
+		assert_eq!(gaps.len(), 3);
+		for (retry, gap) in gaps.into_iter().enumerate() {
+			assert!(gap >= Duration::from_millis(10 << retry), "{:?}", gap);
+		}
+	}
+
+	#[test]
+	fn test_gives_up_after_max_retries() {
+		let transport = MockTransport::new(&[503]);
+		let retries = AtomicU64::new(0);
+		let res = send(
+			&transport,
+			&policy(2, 1000),
+			Idempotency::Idempotent,
+			&retries,
+		);
+		assert_eq!(status(res), 503);
+		assert_eq!(transport.calls.lock().len(), 3);
+	}
+
+	#[test]
+	fn test_budget() {
+		let transport = MockTransport::new(&[503]);
+		let retries = AtomicU64::new(0);
+		let policy = policy(10, 100);
+		let started = Instant::now();
+		let res = send(&transport, &policy, Idempotency::Idempotent, &retries);
+		assert_eq!(status(res), 503);
+		// the first two retries always fit in the budget, a fourth never does
+		let calls = transport.calls.lock().len();
+		assert!(calls == 3 || calls == 4, "{} calls", calls);
+		assert!(started.elapsed() < policy.budget + Duration::from_millis(50));
+	}
+
+	#[test]
+	fn test_rate_limited_requests_retried() {
+		let transport = MockTransport::new(&[429, 429, 200]);
+		let retries = AtomicU64::new(0);
+		let res = send(
+			&transport,
+			&policy(5, 1000),
+			Idempotency::NotIdempotent,
+			&retries,
+		);
+		assert_eq!(status(res), 200);
+		assert_eq!(retries.load(Ordering::Relaxed), 2);
+	}
+
+	fn parse(
+		status: u16,
+		headers: &[(&'static str, &str)],
+		body: &str,
+	) -> GithubApiError {
+		let mut header_map = header::HeaderMap::new();
+		for (name, value) in headers {
+			header_map.insert(*name, value.parse().unwrap());
+		}
+		GithubApiError::new(
+			reqwest::StatusCode::from_u16(status).unwrap(),
+			&header_map,
+			serde_json::from_str(body).unwrap(),
+		)
+	}
+
+	fn body(message: &str, documentation_url: &str) -> ErrorBody {
+		ErrorBody {
+			message: message.to_string(),
+			documentation_url: Some(documentation_url.to_string()),
+			errors: vec![],
+		}
+	}
+
+	#[test]
+	fn test_parse_rate_limited() {
+		let secondary = parse(
+			403,
+			&[("retry-after", "60")],
+			r#"{
+				"message": "You have exceeded a secondary rate limit. Please wait a few minutes before you try again.",
+				"documentation_url": "https://docs.github.com/rest/overview/resources-in-the-rest-api#secondary-rate-limits"
+			}"#,
+		);
+		assert_eq!(secondary.retry_after(), Some(Duration::from_secs(60)));
+
+		let abuse = parse(
+			403,
+			&[],
+			r#"{
+				"message": "You have triggered an abuse detection mechanism. Please wait a few minutes before you try again.",
+				"documentation_url": "https://developer.github.com/v3/#abuse-rate-limits"
+			}"#,
+		);
+		assert_eq!(
+			abuse,
+			GithubApiError::RateLimited {
+				body: body(
+					"You have triggered an abuse detection mechanism. Please wait a few minutes before you try again.",
+					"https://developer.github.com/v3/#abuse-rate-limits"
+				),
+				retry_after: None,
+			}
+		);
+
+		let reset = (Utc::now().timestamp() + 120).to_string();
+		let primary = parse(
+			403,
+			&[
+				("x-ratelimit-remaining", "0"),
+				("x-ratelimit-reset", &reset),
+			],
+			r#"{
+				"message": "API rate limit exceeded for installation ID 1234.",
+				"documentation_url": "https://docs.github.com/rest/overview/resources-in-the-rest-api#rate-limiting"
+			}"#,
+		);
+		let retry_after = primary.retry_after().unwrap();
+		assert!(retry_after > Duration::from_secs(110), "{:?}", retry_after);
+		assert!(retry_after <= Duration::from_secs(120), "{:?}", retry_after);
+
+		assert_eq!(
+			parse(429, &[], "{}"),
+			GithubApiError::RateLimited {
+				body: ErrorBody::default(),
+				retry_after: None,
+			}
+		);
+	}
+
+	#[test]
+	fn test_parse_forbidden_and_not_found() {
+		assert_eq!(
+			parse(
+				403,
+				&[("x-ratelimit-remaining", "4999")],
+				r#"{
+					"message": "Resource not accessible by integration",
+					"documentation_url": "https://docs.github.com/rest/reference/pulls#merge-a-pull-request"
+				}"#,
+			),
+			GithubApiError::Forbidden(body(
+				"Resource not accessible by integration",
+				"https://docs.github.com/rest/reference/pulls#merge-a-pull-request"
+			))
+		);
+		let not_found = parse(
+			404,
+			&[],
+			r#"{
+				"message": "Not Found",
+				"documentation_url": "https://docs.github.com/rest/reference/repos#get-repository-content"
+			}"#,
+		);
+		assert_eq!(
+			not_found,
+			GithubApiError::NotFound(body(
+				"Not Found",
+				"https://docs.github.com/rest/reference/repos#get-repository-content"
+			))
+		);
+		assert!(error::Error::Response {
+			status: reqwest::StatusCode::NOT_FOUND,
+			error: not_found,
+		}
+		.is_not_found());
+	}
+
+	#[test]
+	fn test_parse_merge_blocked_and_conflict() {
+		assert_eq!(
+			parse(
+				405,
+				&[],
+				r#"{
+					"message": "At least 1 approving review is required by reviewers with write access.",
+					"documentation_url": "https://docs.github.com/articles/about-protected-branches"
+				}"#,
+			),
+			GithubApiError::MergeBlocked(body(
+				"At least 1 approving review is required by reviewers with write access.",
+				"https://docs.github.com/articles/about-protected-branches"
+			))
+		);
+		assert_eq!(
+			parse(
+				409,
+				&[],
+				r#"{
+					"message": "Head branch was modified. Review and try the merge again.",
+					"documentation_url": "https://docs.github.com/rest/reference/pulls#merge-a-pull-request"
+				}"#,
+			),
+			GithubApiError::Conflict(body(
+				"Head branch was modified. Review and try the merge again.",
+				"https://docs.github.com/rest/reference/pulls#merge-a-pull-request"
+			))
+		);
+	}
+
+	#[test]
+	fn test_parse_validation() {
+		let validation = parse(
+			422,
+			&[],
+			r#"{
+				"message": "Validation Failed",
+				"errors": [
+					{
+						"resource": "IssueComment",
+						"code": "custom",
+						"field": "body",
+						"message": "body is too long (maximum is 65536 characters)"
+					}
+				],
+				"documentation_url": "https://docs.github.com/rest/reference/issues#create-an-issue-comment"
+			}"#,
+		);
+		match &validation {
+			GithubApiError::Validation(body) => {
+				assert_eq!(body.message, "Validation Failed");
+				assert_eq!(body.errors.len(), 1);
+				assert_eq!(body.errors[0]["field"], "body");
+			}
+			e => panic!("unexpected error: {:?}", e),
+		}
+		assert_eq!(validation.to_string(), "Validation Failed");
+	}
+
+	#[test]
+	fn test_parse_other() {
+		let bad_gateway =
+			serde_json::json!({ "error_message": "<html>502</html>" });
+		let other = GithubApiError::new(
+			reqwest::StatusCode::BAD_GATEWAY,
+			&header::HeaderMap::new(),
+			bad_gateway.clone(),
+		);
+		assert_eq!(other, GithubApiError::Other(bad_gateway.clone()));
+		assert_eq!(other.message(), "<html>502</html>");
+		assert_eq!(other.to_json(), bad_gateway);
+		assert_eq!(
+			parse(422, &[], r#"{ "unexpected": true }"#),
+			GithubApiError::Other(serde_json::json!({ "unexpected": true }))
+		);
+	}
+
+	#[test]
+	fn test_only_idempotent_requests_retried() {
+		let retries = AtomicU64::new(0);
+		let transport = MockTransport::new(&[503, 200]);
+		let res = send(
+			&transport,
+			&policy(5, 1000),
+			Idempotency::NotIdempotent,
+			&retries,
+		);
+		assert_eq!(status(res), 503);
+		assert_eq!(transport.calls.lock().len(), 1);
+
+		let transport = MockTransport::new(&[404, 200]);
+		let res = send(
+			&transport,
+			&policy(5, 1000),
+			Idempotency::Idempotent,
+			&retries,
+		);
+		assert_eq!(status(res), 404);
+		assert_eq!(transport.calls.lock().len(), 1);
+		assert_eq!(retries.load(Ordering::Relaxed), 0);
+	}
+
+	#[test]
+	fn test_token_cache_keyed_by_account() {
+		let cache = TokenCache::default();
+		let now = Utc::now();
+		let expiry = now + chrono::Duration::minutes(40);
+		cache.insert("paritytech", expiry, "ghs_parity".to_string());
+		cache.insert("paritytech-stg", expiry, "ghs_staging".to_string());
+		assert_eq!(cache.get("ParityTech", now).as_deref(), Some("ghs_parity"));
+		assert_eq!(
+			cache.get("paritytech-stg", now).as_deref(),
+			Some("ghs_staging")
+		);
+		assert_eq!(cache.get("tetcoin", now), None);
+		// expired
+		assert_eq!(cache.get("paritytech", expiry), None);
+		cache.remove("paritytech-stg");
+		assert_eq!(cache.get("paritytech-stg", now), None);
+		assert!(cache.get("paritytech", now).is_some());
+	}
+
+	#[test]
+	fn test_changed_installation_drops_token() {
+		let client = Client::new(Vec::new(), "paritytech".to_string());
+		let expiry = Utc::now() + chrono::Duration::minutes(40);
+		client.set_installation("ParityTech", 1);
+		client
+			.tokens
+			.insert("paritytech", expiry, "ghs_abc".to_string());
+		client.set_installation("paritytech", 1);
+		assert!(client.tokens.get("paritytech", Utc::now()).is_some());
+		client.set_installation("paritytech", 2);
+		assert_eq!(client.tokens.get("paritytech", Utc::now()), None);
+		assert_eq!(client.installation_id("PARITYTECH"), Some(2));
+		client.remove_installation("paritytech");
+		assert_eq!(client.installation_id("paritytech"), None);
+	}
+
+	#[test]
+	fn test_api_url_account() {
+		assert_eq!(
+			api_url_account(
+				"https://api.github.com/repos/paritytech-stg/substrate/pulls/1"
+			),
+			Some("paritytech-stg")
+		);
+		assert_eq!(
+			api_url_account(
+				"https://api.github.com/orgs/paritytech/teams/core-devs/members"
+			),
+			Some("paritytech")
+		);
+		assert_eq!(
+			api_url_account("https://api.github.com/orgs/tetcoin?per_page=1"),
+			Some("tetcoin")
+		);
+		assert_eq!(
+			api_url_account("https://api.github.com/installation/repositories"),
+			None
+		);
+		assert_eq!(api_url_account("https://api.github.com/repos/"), None);
+		assert_eq!(
+			api_url_account("https://example.com/repos/paritytech/substrate"),
+			None
+		);
+	}
+}
@@ -0,0 +1,129 @@
+use rocksdb::DB;
+use snafu::ResultExt;
+
+use crate::{
+	command_access, constants::*, error::*, force_merge, freeze, Result,
+};
+
+const KEY_PREFIX: &str = "ignored/";
+
+fn key(owner: &str, repo_name: &str, number: i64) -> String {
+	format!(
+		"{}{}/{}/{}",
+		KEY_PREFIX,
+		owner.to_lowercase(),
+		repo_name.to_lowercase(),
+		number
+	)
+}
+
+/// Whether someone commented `bot ignore` on the PR, and not `bot unignore`
+/// since.
+pub fn is_ignored(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+) -> Result<bool> {
+	db.get(key(owner, repo_name, number).as_bytes())
+		.context(Db)
+		.map(|bytes| bytes.is_some())
+}
+
+pub fn ignore(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+) -> Result<()> {
+	db.put(key(owner, repo_name, number).as_bytes(), b"")
+		.context(Db)
+}
+
+/// Returns whether the PR was ignored.
+pub fn unignore(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+) -> Result<bool> {
+	let ignored = is_ignored(db, owner, repo_name, number)?;
+	db.delete(key(owner, repo_name, number).as_bytes())
+		.context(Db)?;
+	Ok(ignored)
+}
+
+/// Whether `automation` leaves the PR alone, as it is ignored.
+pub fn skips(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+	automation: &str,
+) -> Result<bool> {
+	let ignored = is_ignored(db, owner, repo_name, number)?;
+	if ignored {
+		log::debug!(
+			"{}/{}#{} is ignored; skipping {}",
+			owner,
+			repo_name,
+			number,
+			automation
+		);
+	}
+	Ok(ignored)
+}
+
+/// Whether a command is still handled on an ignored PR: `bot unignore`, a
+/// forced merge, cancelling a merge, and the commands about the bot rather
+/// than the PR.
+pub fn allowed_while_ignored(body: &str) -> bool {
+	let command = body.trim().to_lowercase();
+	command == BOT_IGNORE
+		|| command == BOT_UNIGNORE
+		|| command == AUTO_MERGE_CANCEL
+		|| force_merge::parse_command(body).is_some()
+		|| freeze::parse_command(body).is_some()
+		|| command_access::parse_command(body).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::db::TempDb;
+
+	#[test]
+	fn test_ignore_and_unignore() {
+		let db = TempDb::new("ignore");
+		assert!(!skips(&db, "paritytech", "substrate", 1, "labels").unwrap());
+		ignore(&db, "paritytech", "substrate", 1).unwrap();
+		assert!(is_ignored(&db, "ParityTech", "Substrate", 1).unwrap());
+		assert!(skips(&db, "paritytech", "substrate", 1, "labels").unwrap());
+		assert!(!is_ignored(&db, "paritytech", "substrate", 2).unwrap());
+		assert!(unignore(&db, "paritytech", "substrate", 1).unwrap());
+		assert!(!skips(&db, "paritytech", "substrate", 1, "labels").unwrap());
+		assert!(!unignore(&db, "paritytech", "substrate", 1).unwrap());
+	}
+
+	#[test]
+	fn test_allowed_while_ignored() {
+		for command in &[
+			"bot unignore",
+			"bot ignore",
+			"bot merge cancel",
+			"bot merge force CI is down",
+			"bot freeze",
+			"bot admin deny @spammer",
+		] {
+			assert!(allowed_while_ignored(command), "{}", command);
+		}
+		for command in &[
+			"bot merge",
+			"bot merge skip-bench",
+			"bot rebase",
+			"bot burnin",
+		] {
+			assert!(!allowed_while_ignored(command), "{}", command);
+		}
+	}
+}
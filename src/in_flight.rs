@@ -0,0 +1,280 @@
+use rocksdb::{Direction, IteratorMode, DB};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+
+use crate::{
+	db,
+	error::*,
+	github::PullRequest,
+	migrations,
+	queue::{self, MergeRequest},
+	scheduled_merge, Result,
+};
+
+const KEY_PREFIX: &str = "merging/";
+
+/// How long a marker stands, so that one left behind, eg. by an error while
+/// clearing it, does not turn merges of the PR away for good.  Those left by
+/// a crash are cleared once the bot starts again.
+pub const MARKER_TTL_SECS: i64 = 600;
+
+/// Marks a PR the bot is merging right now, from before it says so on the PR
+/// until the merge is done, however it went.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Marker {
+	pub requested_by: String,
+	/// seconds since the epoch
+	pub started_at: i64,
+}
+
+/// A merge of a PR already under way when another is asked for.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InFlight {
+	/// The bot is merging it.
+	Merging { requested_by: String },
+	/// It waits for its checks to pass.
+	Pending { requested_by: String },
+	/// It was merged.
+	Merged,
+}
+
+impl InFlight {
+	/// The reply to the merge command turned away.
+	pub fn comment(&self) -> String {
+		match self {
+			InFlight::Merging { requested_by } => format!(
+				"A merge of this PR requested by @{} is already in progress.",
+				requested_by
+			),
+			InFlight::Pending { requested_by } => format!(
+				"A merge of this PR requested by @{} is already in progress; it will be merged once its checks pass.",
+				requested_by
+			),
+			InFlight::Merged => "This PR is already merged.".to_string(),
+		}
+	}
+}
+
+fn key(owner: &str, repo_name: &str, number: i64) -> String {
+	format!(
+		"{}{}/{}/{}",
+		KEY_PREFIX,
+		owner.to_lowercase(),
+		repo_name.to_lowercase(),
+		number
+	)
+}
+
+pub fn mark(db: &DB, request: &MergeRequest, now: i64) -> Result<()> {
+	let marker = Marker {
+		requested_by: request.requested_by.clone(),
+		started_at: now,
+	};
+	let bytes = serde_json::to_vec(&marker).context(Json)?;
+	db.put(
+		key(&request.owner, &request.repo_name, request.number).as_bytes(),
+		bytes,
+	)
+	.context(Db)
+}
+
+pub fn clear(db: &DB, request: &MergeRequest) -> Result<()> {
+	db.delete(
+		key(&request.owner, &request.repo_name, request.number).as_bytes(),
+	)
+	.context(Db)
+}
+
+/// Clear the markers of all PRs, left behind by the last run of the bot, as
+/// none is being merged when it starts.  Returns how many there were.
+pub fn clear_all(db: &DB) -> Result<usize> {
+	let keys = db
+		.iterator(IteratorMode::From(
+			KEY_PREFIX.as_bytes(),
+			Direction::Forward,
+		))
+		.map(|(k, _)| k)
+		.take_while(|k| k.starts_with(KEY_PREFIX.as_bytes()))
+		.collect::<Vec<_>>();
+	db::atomically(db, |tx| {
+		for key in &keys {
+			tx.delete(db::DEFAULT, key)?;
+		}
+		Ok(keys.len())
+	})
+}
+
+/// The marker of a PR, unless it expired.
+pub fn marker(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+	now: i64,
+) -> Result<Option<Marker>> {
+	let bytes = match db.get(key(owner, repo_name, number).as_bytes()) {
+		Ok(Some(bytes)) => bytes,
+		Ok(None) => return Ok(None),
+		Err(e) => return Err(e).context(Db),
+	};
+	let marker = serde_json::from_slice::<Marker>(&bytes).context(Json)?;
+	Ok(Some(marker).filter(|marker| now < marker.started_at + MARKER_TTL_SECS))
+}
+
+/// The merge of the PR already under way, if any: one the bot is carrying
+/// out, or one waiting for the checks of its head.  A merge scheduled for
+/// later does not count, as asking for one now replaces it.
+pub fn check(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+	pr: &PullRequest,
+	now: i64,
+) -> Result<Option<InFlight>> {
+	if pr.merged == Some(true) {
+		return Ok(Some(InFlight::Merged));
+	}
+	if let Some(marker) = marker(db, owner, repo_name, pr.number, now)? {
+		return Ok(Some(InFlight::Merging {
+			requested_by: marker.requested_by,
+		}));
+	}
+	Ok(queue::get(db, pr.head.sha.trim())?
+		.and_then(|bytes| migrations::decode::<MergeRequest>(&bytes).ok())
+		.filter(|request| {
+			request.number == pr.number
+				&& request.repo_name.eq_ignore_ascii_case(repo_name)
+				&& !scheduled_merge::is_waiting(request, now)
+		})
+		.map(|request| InFlight::Pending {
+			requested_by: request.requested_by,
+		}))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::db::TempDb;
+	use crate::{db, github::Head};
+
+	const SHA: &str = "0123456789abcdef0123456789abcdef01234567";
+
+	fn open(name: &str) -> TempDb {
+		TempDb::new(&format!("in-flight-{}", name))
+	}
+
+	fn pr() -> PullRequest {
+		PullRequest {
+			number: 1234,
+			head: Head {
+				sha: SHA.to_string(),
+				..Head::default()
+			},
+			..PullRequest::default()
+		}
+	}
+
+	fn request(requested_by: &str) -> MergeRequest {
+		MergeRequest {
+			owner: "paritytech".to_string(),
+			repo_name: "substrate".to_string(),
+			number: 1234,
+			html_url: String::new(),
+			requested_by: requested_by.to_string(),
+			created_at: Some(1000),
+			origin: None,
+			not_before: None,
+		}
+	}
+
+	fn check(db: &DB, pr: &PullRequest, now: i64) -> Option<InFlight> {
+		super::check(db, "paritytech", "substrate", pr, now).unwrap()
+	}
+
+	#[test]
+	fn test_marker() {
+		let db = open("marker");
+		assert_eq!(check(&db, &pr(), 1000), None);
+		mark(&db, &request("gavofyork"), 1000).unwrap();
+		assert_eq!(
+			check(&db, &pr(), 1000 + MARKER_TTL_SECS - 1),
+			Some(InFlight::Merging {
+				requested_by: "gavofyork".to_string()
+			})
+		);
+		// other PRs are not affected
+		let other = PullRequest {
+			number: 1235,
+			..pr()
+		};
+		assert_eq!(check(&db, &other, 1000), None);
+		// one left behind expires
+		assert_eq!(check(&db, &pr(), 1000 + MARKER_TTL_SECS), None);
+		clear(&db, &request("gavofyork")).unwrap();
+		assert_eq!(
+			marker(&db, "paritytech", "substrate", 1234, 1000).unwrap(),
+			None
+		);
+	}
+
+	#[test]
+	fn test_clear_all() {
+		let db = open("clear-all");
+		assert_eq!(clear_all(&db).unwrap(), 0);
+		mark(&db, &request("gavofyork"), 1000).unwrap();
+		mark(
+			&db,
+			&MergeRequest {
+				repo_name: "polkadot".to_string(),
+				..request("alice")
+			},
+			1000,
+		)
+		.unwrap();
+		// not a marker
+		db.put("merge-freeze/paritytech/substrate", b"frozen")
+			.unwrap();
+		assert_eq!(clear_all(&db).unwrap(), 2);
+		assert_eq!(check(&db, &pr(), 1000), None);
+		assert!(db
+			.get("merge-freeze/paritytech/substrate")
+			.unwrap()
+			.is_some());
+	}
+
+	#[test]
+	fn test_pending_and_merged() {
+		let db = open("pending");
+		db::atomically(&db, |tx| queue::store(tx, SHA, &request("alice")))
+			.unwrap();
+		assert_eq!(
+			check(&db, &pr(), 1000),
+			Some(InFlight::Pending {
+				requested_by: "alice".to_string()
+			})
+		);
+		// a push leaves the request behind
+		let pushed = PullRequest {
+			head: Head {
+				sha: "f".repeat(40),
+				..Head::default()
+			},
+			..pr()
+		};
+		assert_eq!(check(&db, &pushed, 1000), None);
+		// a merge scheduled for later is replaced by one asked for now
+		let scheduled = MergeRequest {
+			not_before: Some(2000),
+			..request("alice")
+		};
+		db::atomically(&db, |tx| queue::store(tx, SHA, &scheduled)).unwrap();
+		assert_eq!(check(&db, &pr(), 1000), None);
+		assert!(check(&db, &pr(), 2000).is_some());
+
+		let merged = PullRequest {
+			merged: Some(true),
+			..pr()
+		};
+		assert_eq!(check(&db, &merged, 1000), Some(InFlight::Merged));
+	}
+}
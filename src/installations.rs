@@ -0,0 +1,63 @@
+use rocksdb::{Direction, IteratorMode, DB};
+use snafu::ResultExt;
+
+use crate::{error::*, Result};
+
+const KEY_PREFIX: &str = "installation/";
+
+fn key(account: &str) -> String {
+	format!("{}{}", KEY_PREFIX, account.to_lowercase())
+}
+
+/// Remember the installation of the app on `account`, so that its tokens can
+/// be minted without looking it up.
+pub fn record(db: &DB, account: &str, id: i64) -> Result<()> {
+	db.put(key(account).as_bytes(), id.to_string().as_bytes())
+		.context(Db)
+}
+
+pub fn remove(db: &DB, account: &str) -> Result<()> {
+	db.delete(key(account).as_bytes()).context(Db)
+}
+
+/// The installations of the app, by lower-case account login.
+pub fn all(db: &DB) -> Result<Vec<(String, i64)>> {
+	Ok(db
+		.iterator(IteratorMode::From(
+			KEY_PREFIX.as_bytes(),
+			Direction::Forward,
+		))
+		.take_while(|(k, _)| k.starts_with(KEY_PREFIX.as_bytes()))
+		.filter_map(|(k, v)| {
+			let account =
+				String::from_utf8_lossy(&k[KEY_PREFIX.len()..]).into_owned();
+			let id = std::str::from_utf8(&v).ok()?.parse().ok()?;
+			Some((account, id))
+		})
+		.collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::db::TempDb;
+
+	#[test]
+	fn test_installations() {
+		let db = TempDb::new("installations");
+		assert!(all(&db).unwrap().is_empty());
+		record(&db, "ParityTech", 1).unwrap();
+		record(&db, "paritytech-stg", 2).unwrap();
+		// reinstalled
+		record(&db, "paritytech-stg", 3).unwrap();
+		assert_eq!(
+			all(&db).unwrap(),
+			vec![
+				("paritytech".to_string(), 1),
+				("paritytech-stg".to_string(), 3)
+			]
+		);
+		remove(&db, "paritytech").unwrap();
+		assert_eq!(all(&db).unwrap(), vec![("paritytech-stg".to_string(), 3)]);
+	}
+}
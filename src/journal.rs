@@ -0,0 +1,185 @@
+use rocksdb::{Direction, IteratorMode, DB};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+
+use crate::{error::*, queue::MergeRequest, Result};
+
+const KEY_PREFIX: &str = "merge-journal/";
+
+/// The side-effecting phases of a merge, in order.
+#[derive(
+	Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+	Merge,
+	/// removing the merge from the queue and recording its outcome
+	Cleanup,
+	Companion,
+}
+
+/// A merge in progress, kept until all its phases are done so that one
+/// interrupted by a restart can be finished, or rolled back.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+	pub request: MergeRequest,
+	pub head_sha: String,
+	/// whether the merge waited in the queue, so that cleaning up after it
+	/// removes it from there
+	pub queued: bool,
+	/// the phase started last
+	pub phase: Phase,
+}
+
+/// How to recover a merge interrupted by a restart.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Recovery {
+	/// Carry on from the phase it was in, after the merge.
+	Finish,
+	/// The PR was not merged; forget the attempt.
+	RollBack,
+}
+
+fn key(owner: &str, repo_name: &str, number: i64) -> String {
+	format!(
+		"{}{}/{}/{}",
+		KEY_PREFIX,
+		owner.to_lowercase(),
+		repo_name.to_lowercase(),
+		number
+	)
+}
+
+/// Mark the start of the entry's phase.
+pub fn record(db: &DB, entry: &JournalEntry) -> Result<()> {
+	let request = &entry.request;
+	let bytes = serde_json::to_vec(entry).context(Json)?;
+	db.put(
+		key(&request.owner, &request.repo_name, request.number).as_bytes(),
+		bytes,
+	)
+	.context(Db)
+}
+
+/// Forget the merge of a PR once all its phases are done.
+pub fn finish(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+) -> Result<()> {
+	db.delete(key(owner, repo_name, number).as_bytes())
+		.context(Db)
+}
+
+/// The merges which were in progress.
+pub fn entries(db: &DB) -> Result<Vec<JournalEntry>> {
+	db.iterator(IteratorMode::From(
+		KEY_PREFIX.as_bytes(),
+		Direction::Forward,
+	))
+	.take_while(|(k, _)| k.starts_with(KEY_PREFIX.as_bytes()))
+	.map(|(_, v)| serde_json::from_slice(&v).context(Json))
+	.collect()
+}
+
+/// Whether to finish an interrupted merge, given whether GitHub has the PR
+/// merged.  Once the merge phase is past, the PR is known to be merged.
+pub fn recovery(entry: &JournalEntry, merged: bool) -> Recovery {
+	if entry.phase == Phase::Merge && !merged {
+		Recovery::RollBack
+	} else {
+		Recovery::Finish
+	}
+}
+
+/// The comment posted on a PR once its interrupted merge is recovered.
+pub fn recovery_comment(entry: &JournalEntry, recovery: Recovery) -> String {
+	match recovery {
+		Recovery::Finish => format!(
+			"The bot restarted while merging the PR for @{}; it was merged, and the bot finished what was left to do after the merge.",
+			entry.request.requested_by
+		),
+		Recovery::RollBack if entry.queued => format!(
+			"The bot restarted while merging the PR for @{}, before it was merged. The merge is still pending and will be attempted again once checks next complete.",
+			entry.request.requested_by
+		),
+		Recovery::RollBack => format!(
+			"The bot restarted while merging the PR for @{}, before it was merged. Comment `bot merge` to try again.",
+			entry.request.requested_by
+		),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::db::TempDb;
+
+	fn entry(number: i64, phase: Phase) -> JournalEntry {
+		JournalEntry {
+			request: MergeRequest {
+				owner: "paritytech".to_string(),
+				repo_name: "substrate".to_string(),
+				number,
+				html_url: format!(
+					"https://github.com/paritytech/substrate/pull/{}",
+					number
+				),
+				requested_by: "gavofyork".to_string(),
+				created_at: Some(100),
+				origin: None,
+				not_before: None,
+			},
+			head_sha: "abc".to_string(),
+			queued: true,
+			phase,
+		}
+	}
+
+	#[test]
+	fn test_journal() {
+		let db = TempDb::new("journal");
+		assert!(entries(&db).unwrap().is_empty());
+		record(&db, &entry(1, Phase::Merge)).unwrap();
+		record(&db, &entry(2, Phase::Merge)).unwrap();
+		// a later phase replaces the earlier one
+		record(&db, &entry(1, Phase::Cleanup)).unwrap();
+		assert_eq!(
+			entries(&db).unwrap(),
+			vec![entry(1, Phase::Cleanup), entry(2, Phase::Merge)]
+		);
+		finish(&db, "ParityTech", "Substrate", 1).unwrap();
+		assert_eq!(entries(&db).unwrap(), vec![entry(2, Phase::Merge)]);
+	}
+
+	#[test]
+	fn test_recovery() {
+		assert_eq!(
+			recovery(&entry(1, Phase::Merge), false),
+			Recovery::RollBack
+		);
+		assert_eq!(recovery(&entry(1, Phase::Merge), true), Recovery::Finish);
+		for phase in &[Phase::Cleanup, Phase::Companion] {
+			assert_eq!(recovery(&entry(1, *phase), true), Recovery::Finish);
+			// merged for sure, whatever GitHub says
+			assert_eq!(recovery(&entry(1, *phase), false), Recovery::Finish);
+		}
+	}
+
+	#[test]
+	fn test_recovery_comment() {
+		let mut immediate = entry(1, Phase::Merge);
+		immediate.queued = false;
+		assert!(recovery_comment(&immediate, Recovery::RollBack)
+			.contains("`bot merge`"));
+		assert!(
+			recovery_comment(&entry(1, Phase::Merge), Recovery::RollBack)
+				.contains("still pending")
+		);
+		assert!(
+			recovery_comment(&entry(1, Phase::Cleanup), Recovery::Finish)
+				.contains("@gavofyork")
+		);
+	}
+}
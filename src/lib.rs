@@ -1,19 +1,76 @@
+pub mod approval;
+pub mod audit;
 mod auth;
+pub mod auto_update;
+pub mod automerge;
+pub mod backport;
+pub mod backup;
 pub mod bamboo;
+pub mod benchmark_check;
+pub mod burnin;
+pub mod cache;
+pub mod changelog;
+pub mod cherry_pick;
+pub mod cmd;
+pub mod codeowners;
+pub mod command_access;
+pub mod command_prefix;
+pub mod comment_queue;
 pub mod companion;
 pub mod config;
 pub mod constants;
+pub mod cooldown;
+pub mod db;
+pub mod debounce;
+pub mod effects;
 pub mod error;
+pub mod error_comments;
+pub mod force_merge;
+pub mod freeze;
 pub mod github;
 pub mod github_bot;
+pub mod gitlab;
 pub mod gitlab_bot;
+pub mod health;
+pub mod help;
 pub mod http;
+pub mod ignore;
+pub mod in_flight;
+pub mod installations;
+pub mod journal;
 pub mod matrix;
 pub mod matrix_bot;
+pub mod merge_confirmation;
+pub mod merge_gate;
+pub mod merge_policy;
+pub mod migrations;
+pub mod native_queue;
+pub mod own_activity;
+pub mod path_labels;
 pub mod performance;
+pub mod permissions;
 pub mod process;
+pub mod process_report;
+pub mod quarantine;
+pub mod queue;
+pub mod queue_position;
+pub mod readiness;
 pub mod rebase;
+pub mod recording;
+pub mod release_notes;
+pub mod repo_config;
+pub mod repo_ref;
+pub mod reporting;
+pub mod scheduled_merge;
+pub mod sensitive_paths;
 pub mod server;
+pub mod size_labels;
+pub mod spec_version;
+pub mod stats;
+pub mod templates;
+pub mod version;
 pub mod webhook;
+pub mod welcome;
+pub mod working_clone;
 
 pub type Result<T, E = error::Error> = std::result::Result<T, E>;
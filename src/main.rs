@@ -1,55 +1,396 @@
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use rocksdb::DB;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::time::Duration;
 
 use parity_processbot::{
-	config::{BotConfig, MainConfig},
-	github_bot, gitlab_bot, matrix_bot,
+	audit, backup,
+	comment_queue::CommentQueue,
+	config::{BotConfig, Config, ConfigSource, MainConfig},
+	db,
+	debounce::ChecksDebounce,
+	effects::Effects,
+	github_bot, gitlab_bot,
+	health::{CheckResult, Health, StartupCheck},
+	installations, matrix_bot, migrations, permissions,
+	process::ProcessCache,
+	quarantine, queue,
+	readiness::{self, Readiness},
+	recording,
+	repo_config::RepoConfigCache,
+	reporting::{ErrorContext, ErrorSink, SentrySink},
 	server::*,
-	webhook::*,
+	stats, version,
+	webhook::{self, *},
 };
 
+fn cli() -> App<'static, 'static> {
+	let db = Arg::with_name("db")
+		.long("db")
+		.takes_value(true)
+		.value_name("PATH")
+		.help("Database to open instead of DB_PATH");
+	App::new("parity-processbot")
+		.setting(AppSettings::VersionlessSubcommands)
+		.subcommand(
+			SubCommand::with_name("serve")
+				.about("Handle webhooks; the default without a subcommand"),
+		)
+		.subcommand(
+			SubCommand::with_name("queue")
+				.about(
+					"Inspect or repair the pending merges; stop the bot first, \
+					 as it keeps the database locked",
+				)
+				.setting(AppSettings::SubcommandRequiredElseHelp)
+				.subcommand(
+					SubCommand::with_name("list")
+						.about("Print the pending merges")
+						.arg(db.clone())
+						.arg(Arg::with_name("json").long("json").help(
+							"Print JSON instead of a table",
+						)),
+				)
+				.subcommand(
+					SubCommand::with_name("remove")
+						.about("Remove the pending merge of a PR")
+						.arg(db.clone())
+						.arg(
+							Arg::with_name("target")
+								.required(true)
+								.value_name("owner/repo#number|sha")
+								.validator(|s| {
+									s.parse::<queue::Target>().map(|_| ())
+								}),
+						),
+				)
+				.subcommand(
+					SubCommand::with_name("clear")
+						.about("Remove all pending merges")
+						.arg(db.clone()),
+				),
+		)
+		.subcommand(
+			SubCommand::with_name("audit")
+				.about(
+					"Read the audit log of privileged bot actions; stop the \
+					 bot first, as it keeps the database locked",
+				)
+				.setting(AppSettings::SubcommandRequiredElseHelp)
+				.subcommand(
+					SubCommand::with_name("export")
+						.about("Write the audit log as CSV")
+						.arg(db.clone())
+						.arg(
+							Arg::with_name("repo")
+								.long("repo")
+								.takes_value(true)
+								.value_name("owner/repo")
+								.help("Only actions on this repository"),
+						)
+						.arg(
+							Arg::with_name("since")
+								.long("since")
+								.takes_value(true)
+								.value_name("RFC3339|SECONDS")
+								.help("Only actions at or after this time")
+								.validator(|s| {
+									audit::parse_since(&s).map(|_| ())
+								}),
+						)
+						.arg(
+							Arg::with_name("output")
+								.long("output")
+								.short("o")
+								.takes_value(true)
+								.value_name("FILE")
+								.help("Write to FILE instead of stdout"),
+						),
+				),
+		)
+		.subcommand(
+			SubCommand::with_name("db")
+				.about(
+					"Back up, restore or inspect the whole database; stop \
+					 the bot first, as it keeps the database locked",
+				)
+				.setting(AppSettings::SubcommandRequiredElseHelp)
+				.subcommand(
+					SubCommand::with_name("backup")
+						.about("Write every entry of the database as JSON")
+						.arg(db.clone())
+						.arg(
+							Arg::with_name("out")
+								.long("out")
+								.required(true)
+								.takes_value(true)
+								.value_name("FILE"),
+						),
+				)
+				.subcommand(
+					SubCommand::with_name("restore")
+						.about("Import a backup into an empty database")
+						.arg(db.clone())
+						.arg(
+							Arg::with_name("file")
+								.required(true)
+								.value_name("FILE"),
+						),
+				)
+				.subcommand(
+					SubCommand::with_name("corrupt")
+						.about("Entries quarantined as they could not be read")
+						.setting(AppSettings::SubcommandRequiredElseHelp)
+						.subcommand(
+							SubCommand::with_name("list")
+								.about("List the quarantined entries")
+								.arg(db.clone())
+								.arg(
+									Arg::with_name("json")
+										.long("json")
+										.help("Print as JSON, with their contents"),
+								),
+						)
+						.subcommand(
+							SubCommand::with_name("purge")
+								.about("Delete the quarantined entries")
+								.arg(db.clone()),
+						),
+				),
+		)
+		.subcommand(
+			SubCommand::with_name("replay")
+				.about(
+					"Handle webhooks recorded to RECORD_PAYLOADS_DIR, without \
+					 checking signatures",
+				)
+				.arg(
+					Arg::with_name("path")
+						.required(true)
+						.value_name("FILE|DIR")
+						.help("A recorded payload, or a directory of them"),
+				)
+				.arg(db)
+				.arg(
+					Arg::with_name("dry-run")
+						.long("dry-run")
+						.help("Log changes instead of making them"),
+				),
+		)
+		.subcommand(
+			SubCommand::with_name("check-config")
+				.about("Validate the configuration and exit"),
+		)
+}
+
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
-	match run().await {
-		Err(error) => panic!("{}", error),
-		_ => Ok(()),
+	let matches = cli().get_matches();
+	let result = match matches.subcommand() {
+		("queue", Some(args)) => queue(args),
+		("audit", Some(args)) => audit(args),
+		("db", Some(args)) => db(args),
+		("check-config", _) => check_config(),
+		("replay", Some(args)) => replay(args).await,
+		_ => match run().await {
+			Err(error) => panic!("{}", error),
+			_ => Ok(()),
+		},
+	};
+	if let Err(error) = result {
+		eprintln!("{}", error);
+		std::process::exit(1);
+	}
+	Ok(())
+}
+
+fn check_config() -> anyhow::Result<()> {
+	Config::load()?;
+	println!("Configuration is valid");
+	Ok(())
+}
+
+/// Open the database at `--db`, or else at DB_PATH.
+fn open_db(args: &ArgMatches) -> anyhow::Result<DB> {
+	let db_path = match args.value_of("db") {
+		Some(path) => path.to_string(),
+		None => ConfigSource::load()?.var("DB_PATH").ok_or_else(|| {
+			anyhow::anyhow!("Pass --db or set DB_PATH to open the database")
+		})?,
+	};
+	Ok(db::open(&db_path)?)
+}
+
+fn queue(args: &ArgMatches) -> anyhow::Result<()> {
+	let (command, args) = match args.subcommand() {
+		(command, Some(args)) => (command, args),
+		_ => unreachable!("a subcommand is required"),
+	};
+	let db = open_db(args)?;
+
+	match command {
+		"list" => {
+			let entries = queue::entries(&db)?;
+			if args.is_present("json") {
+				println!("{}", serde_json::to_string_pretty(&entries)?);
+			} else {
+				println!("{}", queue::format_table(&entries));
+			}
+		}
+		"remove" => {
+			let target = args.value_of("target").expect("required");
+			// merges stored by an older bot are found through the index too
+			queue::reindex(&db)?;
+			let parsed = target.parse().map_err(anyhow::Error::msg)?;
+			let removed = db::atomically(&db, |tx| queue::remove(tx, &parsed))?;
+			if removed.is_empty() {
+				anyhow::bail!("No pending merge matches {}", target);
+			}
+			for entry in removed {
+				println!("Removed {}", entry.sha);
+			}
+		}
+		"clear" => println!("Removed {} pending merges", queue::clear(&db)?),
+		_ => unreachable!("unknown queue subcommand"),
+	}
+	Ok(())
+}
+
+fn audit(args: &ArgMatches) -> anyhow::Result<()> {
+	let args = match args.subcommand() {
+		("export", Some(args)) => args,
+		_ => unreachable!("unknown audit subcommand"),
+	};
+	let db = open_db(args)?;
+	let filter = audit::Filter {
+		repo: args.value_of("repo").map(str::to_string),
+		since: args
+			.value_of("since")
+			.map(audit::parse_since)
+			.transpose()
+			.map_err(anyhow::Error::msg)?,
+	};
+	let csv = audit::to_csv(&audit::query(&db, &filter)?);
+	match args.value_of("output") {
+		Some(path) => std::fs::write(path, csv)?,
+		None => print!("{}", csv),
+	}
+	Ok(())
+}
+
+fn db(args: &ArgMatches) -> anyhow::Result<()> {
+	match args.subcommand() {
+		("backup", Some(args)) => {
+			let db = open_db(args)?;
+			let out = args.value_of("out").expect("required");
+			let count = backup::write(&db, Path::new(out))?;
+			println!("Wrote {} entries to {}", count, out);
+		}
+		("restore", Some(args)) => {
+			let db = open_db(args)?;
+			let records = backup::read(Path::new(
+				args.value_of("file").expect("required"),
+			))?;
+			println!("Restored {} entries", backup::restore(&db, &records)?);
+		}
+		("corrupt", Some(args)) => match args.subcommand() {
+			("list", Some(args)) => {
+				let records = quarantine::list(&open_db(args)?)?;
+				if args.is_present("json") {
+					println!("{}", serde_json::to_string_pretty(&records)?);
+				} else {
+					println!("{}", quarantine::format_table(&records));
+				}
+			}
+			("purge", Some(args)) => println!(
+				"Deleted {} quarantined entries",
+				quarantine::purge(&open_db(args)?)?
+			),
+			_ => unreachable!("unknown db corrupt subcommand"),
+		},
+		_ => unreachable!("unknown db subcommand"),
 	}
+	Ok(())
 }
 
 async fn run() -> anyhow::Result<()> {
-	let config = MainConfig::from_env();
+	let Config {
+		main: config,
+		bot: bot_config,
+		fingerprint,
+	} = Config::load()?;
 	env_logger::from_env(env_logger::Env::default().default_filter_or("info"))
 		.init();
-
-	let db = DB::open_default(&config.db_path)?;
-
 	log::info!(
-		"Connecting to Matrix homeserver {}",
-		config.matrix_homeserver,
+		"Starting {} with configuration {}",
+		version::describe(),
+		fingerprint
 	);
-	let matrix_bot = matrix_bot::MatrixBot::new_with_token(
-		&config.matrix_homeserver,
-		&config.matrix_access_token,
-		&config.matrix_default_channel_id,
-		config.matrix_silent,
-	)?;
 
-	log::info!("Connecting to Github account {}", config.installation_login);
-	let github_bot = github_bot::GithubBot::new(
-		config.private_key.clone(),
-		&config.installation_login,
-	)
-	.await?;
+	let error_sink = bot_config
+		.error_reporting_dsn
+		.clone()
+		.map(|dsn| Arc::new(SentrySink::new(dsn)) as Arc<dyn ErrorSink>);
+	if let Some(sink) = error_sink.clone() {
+		let default_hook = std::panic::take_hook();
+		std::panic::set_hook(Box::new(move |info| {
+			sink.report(&ErrorContext::default().report(info));
+			default_hook(info);
+		}));
+	}
 
-	log::info!("Connecting to Gitlab https://{}", config.burnin_gitlab_host);
-	let gitlab_bot = gitlab_bot::GitlabBot::new_with_token(
-		&config.burnin_gitlab_host,
-		&config.burnin_gitlab_project,
-		&config.burnin_gitlab_token,
+	let mut state = app_state(&config, bot_config, fingerprint).await?;
+
+	let self_test = signature::SelfTest(&state.webhook_secret);
+	let mut checks: Vec<&dyn StartupCheck> =
+		vec![&state.github_bot, &state.gitlab_bot, &self_test];
+	if !config.matrix_silent {
+		checks.push(&state.matrix_bot);
+	}
+	state.health = startup_checks(&checks, config.allow_degraded_start).await?;
+	// missing permissions are explained where they are needed, rather than
+	// stopping the bot
+	state.capabilities = permissions::probe(
+		&state.github_bot,
+		state.github_bot.client.installation_login(),
+		state
+			.bot_config
+			.lead_teams
+			.iter()
+			.chain(&state.bot_config.review_teams)
+			.next()
+			.map(String::as_str),
+		&state.bot_config.bot_login,
 	)
-	.await?;
+	.await;
+	state.error_sink = error_sink;
+
+	// before handling webhooks, which could act on the same PRs
+	webhook::recover_merges(&state).await;
+
+	tokio::spawn(readiness::monitor(
+		Arc::clone(&state.readiness),
+		Arc::clone(&state.db),
+		state.github_bot.client.token_refresh_failed(),
+		Duration::from_secs(config.readiness_check_secs),
+	));
+
+	tokio::spawn(stats::periodic(
+		Arc::clone(&state.db),
+		config.stats_retention_days,
+		Duration::from_secs(24 * 60 * 60),
+	));
+
+	if let Some(dir) = &config.backup_dir {
+		tokio::spawn(backup::periodic(
+			Arc::clone(&state.db),
+			PathBuf::from(dir),
+			Duration::from_secs(config.backup_interval_secs),
+			config.backup_keep,
+		));
+	}
 
 	// the bamboo queries can take a long time so only wait for it
 	// on launch. subsequently update in the background.
@@ -93,27 +434,256 @@ async fn run() -> anyhow::Result<()> {
 	});
 	*/
 
-	let app_state = Arc::new(Mutex::new(AppState {
-		db: db,
-		github_bot: github_bot,
-		matrix_bot: matrix_bot,
-		gitlab_bot: gitlab_bot,
-		bot_config: BotConfig::from_env(),
-		webhook_secret: config.webhook_secret,
-	}));
-
 	let socket = SocketAddr::new(
 		IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
-		config.webhook_port.parse::<u16>().expect("webhook port"),
+		config.webhook_port,
+	);
+
+	init_server(socket, state).await
+}
+
+/// Open the database and connect to the services the bot uses.
+async fn app_state(
+	config: &MainConfig,
+	bot_config: BotConfig,
+	config_fingerprint: String,
+) -> anyhow::Result<AppState> {
+	let db = Arc::new(db::open(&config.db_path)?);
+	let report = migrations::migrate(&db)?;
+	if report.migrated > 0 || report.quarantined > 0 {
+		log::info!(
+			"Migrated {} database records, quarantined {} which could not be read",
+			report.migrated,
+			report.quarantined
+		);
+	}
+	let indexed = queue::reindex(&db)?;
+	if indexed > 0 {
+		log::info!("Indexed {} pending merges", indexed);
+	}
+
+	log::info!(
+		"Connecting to Matrix homeserver {}",
+		config.matrix_homeserver,
 	);
+	let matrix_bot = matrix_bot::MatrixBot::new_with_token(
+		&config.matrix_homeserver,
+		&config.matrix_access_token,
+		&config.matrix_default_channel_id,
+		config.matrix_silent,
+	);
+
+	log::info!("Connecting to Github account {}", config.installation_login);
+	let mut github_bot = github_bot::GithubBot::new(
+		config.private_key.clone(),
+		&config.installation_login,
+	)
+	.await?;
+	github_bot.client.set_retry_policy(config.http_retry_policy);
+	github_bot.comments = CommentQueue::new(config.comment_delay);
+	for (account, id) in installations::all(&db)? {
+		github_bot.client.set_installation(&account, id);
+	}
+
+	log::info!("Connecting to Gitlab https://{}", config.burnin_gitlab_host);
+	let gitlab_bot = gitlab_bot::GitlabBot::new_with_token(
+		&config.burnin_gitlab_host,
+		&config.burnin_gitlab_project,
+		&config.burnin_gitlab_token,
+	)
+	.await?;
+
+	let process_cache = ProcessCache::new(bot_config.process_cache_ttl);
+	let repo_config_cache =
+		RepoConfigCache::new(bot_config.repo_config_cache_ttl);
+	let effects = Effects::new(bot_config.dry_run);
+	let checks_debounce =
+		Arc::new(ChecksDebounce::new(bot_config.checks_debounce_secs));
+	if effects.is_dry_run() {
+		log::info!("Dry run; no changes will be made");
+	}
+
+	Ok(AppState {
+		db,
+		github_bot,
+		matrix_bot,
+		gitlab_bot,
+		bot_config,
+		capabilities: Default::default(),
+		process_cache,
+		repo_config_cache,
+		effects,
+		health: Health::default(),
+		readiness: Arc::new(Readiness::new(
+			config.readiness_max_pending_webhooks,
+		)),
+		checks_debounce,
+		error_sink: None,
+		suppressed_error_comments: Default::default(),
+		quarantined_merge_requests: Default::default(),
+		skipped_own_events: Default::default(),
+		webhook_secret: signature::normalize_secret(&config.webhook_secret)
+			.map_err(anyhow::Error::msg)?,
+		gitlab_webhook_secret: config.burnin_gitlab_webhook_secret.clone(),
+		audit_token: config.audit_token.clone(),
+		admin_token: config.admin_token.clone(),
+		config_fingerprint,
+		started_at: chrono::Utc::now(),
+	})
+}
+
+/// Handle recorded webhooks, in the order they were received.
+async fn replay(args: &ArgMatches<'_>) -> anyhow::Result<()> {
+	let Config {
+		main: mut config,
+		bot: mut bot_config,
+		fingerprint,
+	} = Config::load()?;
+	env_logger::from_env(env_logger::Env::default().default_filter_or("info"))
+		.init();
+	if let Some(db_path) = args.value_of("db") {
+		config.db_path = db_path.to_string();
+	}
+	if args.is_present("dry-run") {
+		bot_config.dry_run = true;
+	}
+	let envelopes =
+		recording::load(Path::new(args.value_of("path").expect("required")))?;
+	let state = app_state(&config, bot_config, fingerprint).await?;
+
+	let mut failed = 0;
+	for (path, envelope) in envelopes {
+		log::info!("Replaying {}", path.display());
+		if let Err(e) = webhook::replay(envelope, &state).await {
+			log::error!("Error replaying {}: {}", path.display(), e);
+			failed += 1;
+		}
+	}
+	if failed > 0 {
+		anyhow::bail!("{} payloads failed; see the log", failed);
+	}
+	Ok(())
+}
+
+/// Check that the bot can reach the services it depends on, so that a bad
+/// token shows up now rather than as a failure to handle the first webhook.
+/// Fails if any check does, unless a degraded start is allowed.
+async fn startup_checks(
+	checks: &[&dyn StartupCheck],
+	allow_degraded_start: bool,
+) -> anyhow::Result<Health> {
+	let mut health = Health::default();
+	for check in checks {
+		let error = check.check().await.err();
+		match &error {
+			None => log::info!("Startup check {} passed", check.name()),
+			Some(e) => {
+				log::error!("Startup check {} failed: {}", check.name(), e)
+			}
+		}
+		health.checks.push(CheckResult {
+			name: check.name().to_string(),
+			error,
+		});
+	}
 
-	init_server(socket, app_state).await
+	if health.is_ok() {
+		Ok(health)
+	} else if allow_degraded_start {
+		log::warn!("Starting in degraded mode; see /health");
+		Ok(health)
+	} else {
+		anyhow::bail!(
+			"Startup checks failed:\n{}",
+			health
+				.failures()
+				.map(|check| format!(
+					"{}: {}",
+					check.name,
+					check.error.as_deref().unwrap_or_default()
+				))
+				.collect::<Vec<_>>()
+				.join("\n")
+		)
+	}
 }
 
 #[cfg(test)]
 mod tests {
+	use super::*;
+	use futures::future::{BoxFuture, FutureExt};
 	use regex::Regex;
 
+	struct MockCheck {
+		name: &'static str,
+		error: Option<&'static str>,
+	}
+
+	impl StartupCheck for MockCheck {
+		fn name(&self) -> &'static str {
+			self.name
+		}
+
+		fn check(&self) -> BoxFuture<'_, Result<(), String>> {
+			let result = match self.error {
+				Some(e) => Err(e.to_string()),
+				None => Ok(()),
+			};
+			async move { result }.boxed()
+		}
+	}
+
+	fn mocks() -> Vec<MockCheck> {
+		vec![
+			MockCheck {
+				name: "github",
+				error: None,
+			},
+			MockCheck {
+				name: "gitlab",
+				error: Some("401 Unauthorized"),
+			},
+			MockCheck {
+				name: "matrix",
+				error: Some("M_FORBIDDEN: not in room"),
+			},
+		]
+	}
+
+	#[test]
+	fn test_startup_checks() {
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		let mocks = mocks();
+		let checks = mocks
+			.iter()
+			.map(|mock| mock as &dyn StartupCheck)
+			.collect::<Vec<_>>();
+
+		let health = rt.block_on(startup_checks(&checks[..1], false)).unwrap();
+		assert!(health.is_ok());
+		assert_eq!(health.payload()["status"], "ok");
+
+		let error = rt.block_on(startup_checks(&checks, false)).unwrap_err();
+		assert_eq!(
+			error.to_string(),
+			"Startup checks failed:\ngitlab: 401 Unauthorized\nmatrix: M_FORBIDDEN: not in room"
+		);
+
+		let health = rt.block_on(startup_checks(&checks, true)).unwrap();
+		assert!(!health.is_ok());
+		assert_eq!(
+			health.payload(),
+			serde_json::json!({
+				"status": "degraded",
+				"checks": [
+					{ "name": "github", "error": null },
+					{ "name": "gitlab", "error": "401 Unauthorized" },
+					{ "name": "matrix", "error": "M_FORBIDDEN: not in room" },
+				],
+			})
+		);
+	}
+
 	#[test]
 	fn test_replace_whitespace_in_toml_key() {
 		let mut s = String::from("[Smart Contracts Ok]\nwhitelist = []");
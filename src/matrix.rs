@@ -167,6 +167,65 @@ pub fn send_html_message(
 	handle.perform().map_err(From::from)
 }
 
+/// GET a client API endpoint, turning a Matrix error response into an error.
+fn get_json(url: &str) -> Result<serde_json::Value> {
+	let mut dst = Vec::new();
+	let mut handle = Easy::new();
+	handle.url(url)?;
+	{
+		let mut transfer = handle.transfer();
+		transfer.write_function(|data| {
+			dst.extend_from_slice(data);
+			Ok(data.len())
+		})?;
+		transfer.perform()?;
+	}
+	let body: serde_json::Value =
+		String::from_utf8(dst)
+			.context(error::Utf8)
+			.and_then(|s| serde_json::from_str(&s).context(error::Json))?;
+	match body["errcode"].as_str() {
+		Some(errcode) => Err(error::Error::Message {
+			msg: format!(
+				"{}: {}",
+				errcode,
+				body["error"].as_str().unwrap_or_default()
+			),
+		}),
+		None => Ok(body),
+	}
+}
+
+/// Resolve a room id or `#alias:server` to a room id, and check that the
+/// access token is valid and its user has joined the room.
+pub fn resolve_room(
+	homeserver: &str,
+	access_token: &str,
+	room: &str,
+) -> Result<String> {
+	let room_id = if room.starts_with('#') {
+		let body = get_json(&format!(
+			"{}/_matrix/client/r0/directory/room/{}?access_token={}",
+			homeserver,
+			room.replace('#', "%23"),
+			access_token
+		))?;
+		body["room_id"]
+			.as_str()
+			.ok_or_else(|| error::Error::Message {
+				msg: format!("Matrix did not resolve {}", room),
+			})?
+			.to_string()
+	} else {
+		room.to_string()
+	};
+	get_json(&format!(
+		"{}/_matrix/client/r0/rooms/{}/joined_members?access_token={}",
+		homeserver, room_id, access_token
+	))?;
+	Ok(room_id)
+}
+
 /// If the pattern is recognised, return the full matrix id.
 /// Otherwise, return None.
 pub fn parse_id(matrix_id: &str) -> Option<String> {
@@ -15,23 +15,30 @@ pub struct MatrixBot {
 }
 
 impl MatrixBot {
+	/// The token is not checked until the first message; see
+	/// `check_default_room`.
 	pub fn new_with_token(
 		homeserver: &str,
 		access_token: &str,
 		default_channel_id: &str,
 		silent: bool,
-	) -> Result<Self> {
-		matrix::sync(homeserver, access_token)
-			.map(|_| Self {
-				homeserver: homeserver.to_owned(),
-				access_token: access_token.to_owned(),
-				default_channel_id: default_channel_id.to_owned(),
-				silent: silent,
-			})
-			.map_err(|e| {
-				log::error!("Error syncing with Matrix: {}", e);
-				e
-			})
+	) -> Self {
+		Self {
+			homeserver: homeserver.to_owned(),
+			access_token: access_token.to_owned(),
+			default_channel_id: default_channel_id.to_owned(),
+			silent,
+		}
+	}
+
+	/// Check that the session can resolve and has joined the default room.
+	pub fn check_default_room(&self) -> Result<()> {
+		matrix::resolve_room(
+			&self.homeserver,
+			&self.access_token,
+			&self.default_channel_id,
+		)
+		.map(|_| ())
 	}
 
 	pub fn new_with_credentials(
@@ -0,0 +1,258 @@
+use rocksdb::DB;
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+
+use crate::{error::*, github::PullRequest, Result};
+
+const KEY_PREFIX: &str = "merge-confirmation/";
+
+/// A `bot merge` or `bot merge at` of a large PR, waiting for its requester
+/// to confirm it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingConfirmation {
+	pub requested_by: String,
+	/// the head the merge was requested at; a push invalidates it
+	pub head_sha: String,
+	/// whether `bot merge skip-bench` was requested
+	pub skip_bench: bool,
+	/// seconds since the epoch
+	pub expires_at: i64,
+	/// when the merge was scheduled for with `bot merge at`, in seconds since
+	/// the epoch
+	#[serde(default)]
+	pub not_before: Option<i64>,
+}
+
+/// Whether a `bot merge` of the PR must be confirmed: it changes more than
+/// `max_lines` lines or `max_files` files, where 0 is no limit.  A forced
+/// merge needs no confirmation, nor does a PR whose size is unknown.
+pub fn needs_confirmation(
+	pr: &PullRequest,
+	max_lines: u64,
+	max_files: u64,
+	force: bool,
+) -> bool {
+	if force {
+		return false;
+	}
+	let lines = match (pr.additions, pr.deletions) {
+		(Some(additions), Some(deletions)) => Some(additions + deletions),
+		_ => None,
+	};
+	let exceeds = |size: Option<u64>, max: u64| {
+		max > 0 && size.is_some_and(|size| size > max)
+	};
+	exceeds(lines, max_lines) || exceeds(pr.changed_files, max_files)
+}
+
+/// What `bot merge confirm` finds.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Confirmation {
+	/// The merge may proceed, or be scheduled if it was asked for at a time.
+	Confirmed {
+		skip_bench: bool,
+		not_before: Option<i64>,
+	},
+	/// Nothing to confirm, or the request expired or was pushed over.
+	Missing,
+	/// Someone other than the requester tried to confirm.
+	WrongUser { requested_by: String },
+}
+
+fn key(owner: &str, repo_name: &str, number: i64) -> String {
+	format!(
+		"{}{}/{}/{}",
+		KEY_PREFIX,
+		owner.to_lowercase(),
+		repo_name.to_lowercase(),
+		number
+	)
+}
+
+pub fn request(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+	pending: &PendingConfirmation,
+) -> Result<()> {
+	let bytes = serde_json::to_vec(pending).context(Json)?;
+	db.put(key(owner, repo_name, number).as_bytes(), bytes)
+		.context(Db)
+}
+
+/// Confirm the pending merge of a PR as `login`, at `head_sha`.  A request is
+/// used up by its confirmation, and dropped once expired or pushed over; a
+/// confirmation by someone else leaves it be.
+pub fn confirm(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+	login: &str,
+	head_sha: &str,
+	now: i64,
+) -> Result<Confirmation> {
+	let key = key(owner, repo_name, number);
+	let pending = match db.get(key.as_bytes()).context(Db)? {
+		Some(bytes) => serde_json::from_slice::<PendingConfirmation>(&bytes)
+			.context(Json)?,
+		None => return Ok(Confirmation::Missing),
+	};
+	if pending.expires_at <= now || pending.head_sha != head_sha {
+		db.delete(key.as_bytes()).context(Db)?;
+		return Ok(Confirmation::Missing);
+	}
+	if !pending.requested_by.eq_ignore_ascii_case(login) {
+		return Ok(Confirmation::WrongUser {
+			requested_by: pending.requested_by,
+		});
+	}
+	db.delete(key.as_bytes()).context(Db)?;
+	Ok(Confirmation::Confirmed {
+		skip_bench: pending.skip_bench,
+		not_before: pending.not_before,
+	})
+}
+
+/// Drop the pending merge of a PR, eg. when it is pushed to or closed.
+pub fn remove(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+) -> Result<()> {
+	db.delete(key(owner, repo_name, number).as_bytes())
+		.context(Db)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::db::TempDb;
+
+	fn pr(additions: u64, deletions: u64, changed_files: u64) -> PullRequest {
+		PullRequest {
+			additions: Some(additions),
+			deletions: Some(deletions),
+			changed_files: Some(changed_files),
+			..PullRequest::default()
+		}
+	}
+
+	fn pending(requested_by: &str, expires_at: i64) -> PendingConfirmation {
+		PendingConfirmation {
+			requested_by: requested_by.to_string(),
+			head_sha: "abc".to_string(),
+			skip_bench: false,
+			expires_at,
+			not_before: None,
+		}
+	}
+
+	fn db(name: &str) -> TempDb {
+		TempDb::new(&format!("confirmation-{}", name))
+	}
+
+	#[test]
+	fn test_needs_confirmation() {
+		assert!(needs_confirmation(&pr(8000, 2001, 3), 10000, 0, false));
+		assert!(!needs_confirmation(&pr(8000, 2000, 3), 10000, 0, false));
+		assert!(needs_confirmation(&pr(10, 0, 201), 10000, 200, false));
+		// no limits
+		assert!(!needs_confirmation(&pr(100000, 0, 1000), 0, 0, false));
+		// unknown size
+		assert!(!needs_confirmation(&PullRequest::default(), 1, 1, false));
+	}
+
+	#[test]
+	fn test_force_bypasses() {
+		assert!(!needs_confirmation(&pr(100000, 0, 1000), 10000, 200, true));
+	}
+
+	#[test]
+	fn test_confirm() {
+		let db = db("confirm");
+		let confirm = |login: &str, head_sha: &str, now: i64| {
+			confirm(&db, "paritytech", "substrate", 1, login, head_sha, now)
+				.unwrap()
+		};
+		assert_eq!(confirm("gavofyork", "abc", 100), Confirmation::Missing);
+
+		request(
+			&db,
+			"paritytech",
+			"substrate",
+			1,
+			&pending("gavofyork", 700),
+		)
+		.unwrap();
+		assert_eq!(
+			confirm("rphmeier", "abc", 100),
+			Confirmation::WrongUser {
+				requested_by: "gavofyork".to_string()
+			}
+		);
+		// still pending for the requester
+		assert_eq!(
+			confirm("GavofYork", "abc", 100),
+			Confirmation::Confirmed {
+				skip_bench: false,
+				not_before: None
+			}
+		);
+		// used up
+		assert_eq!(confirm("gavofyork", "abc", 100), Confirmation::Missing);
+
+		// a scheduled merge stays scheduled
+		request(
+			&db,
+			"paritytech",
+			"substrate",
+			1,
+			&PendingConfirmation {
+				not_before: Some(5000),
+				..pending("gavofyork", 700)
+			},
+		)
+		.unwrap();
+		assert_eq!(
+			confirm("gavofyork", "abc", 100),
+			Confirmation::Confirmed {
+				skip_bench: false,
+				not_before: Some(5000)
+			}
+		);
+	}
+
+	#[test]
+	fn test_confirm_expired_or_pushed_over() {
+		let db = db("expiry");
+		let pending = pending("gavofyork", 700);
+		request(&db, "paritytech", "substrate", 1, &pending).unwrap();
+		assert_eq!(
+			confirm(&db, "paritytech", "substrate", 1, "gavofyork", "abc", 700)
+				.unwrap(),
+			Confirmation::Missing
+		);
+		request(&db, "paritytech", "substrate", 1, &pending).unwrap();
+		assert_eq!(
+			confirm(&db, "paritytech", "substrate", 1, "gavofyork", "def", 100)
+				.unwrap(),
+			Confirmation::Missing
+		);
+		// dropped along the way
+		assert_eq!(
+			confirm(&db, "paritytech", "substrate", 1, "gavofyork", "abc", 100)
+				.unwrap(),
+			Confirmation::Missing
+		);
+		request(&db, "paritytech", "substrate", 1, &pending).unwrap();
+		remove(&db, "paritytech", "substrate", 1).unwrap();
+		assert_eq!(
+			confirm(&db, "paritytech", "substrate", 1, "gavofyork", "abc", 100)
+				.unwrap(),
+			Confirmation::Missing
+		);
+	}
+}
@@ -0,0 +1,253 @@
+use crate::{
+	error::Error,
+	github::{StatusRequest, StatusState},
+	Result,
+};
+
+/// The commit status showing on the head of a PR whether the bot would merge
+/// it.  It is the bot's own verdict, so the bot never waits for it, nor looks
+/// at its checks again when it is set.
+pub const CONTEXT: &str = "processbot/merge-gate";
+
+const TARGET_URL: &str =
+	"https://github.com/paritytech/parity-processbot#merge-gate-status";
+
+/// GitHub's limit on the description of a status.
+const MAX_DESCRIPTION_LEN: usize = 140;
+
+/// Cut `s` to at most `MAX_DESCRIPTION_LEN` characters.
+fn truncate(s: &str) -> String {
+	if s.chars().count() <= MAX_DESCRIPTION_LEN {
+		return s.to_string();
+	}
+	let mut cut = s.chars().take(MAX_DESCRIPTION_LEN - 1).collect::<String>();
+	cut.push('…');
+	cut
+}
+
+/// How the review teams are named in the description, eg. `core-devs`.
+fn reviewers(review_teams: &[String]) -> &str {
+	match review_teams {
+		[team] => team,
+		_ => "reviewer",
+	}
+}
+
+/// The state of the merge gate and why, from what `merge_allowed` decided:
+/// pending while it waits for people, failed while the PR itself must
+/// change.  `None` for errors which say nothing of the PR, eg. network ones.
+pub fn verdict(
+	outcome: &Result<()>,
+	review_teams: &[String],
+) -> Option<(StatusState, String)> {
+	fn of_error(
+		e: &Error,
+		review_teams: &[String],
+	) -> Option<(StatusState, String)> {
+		Some(match e {
+			Error::WithIssue { source, .. } => {
+				return of_error(source, review_teams)
+			}
+			Error::Approval {
+				missing_reviewers: 0,
+				..
+			} => (
+				StatusState::Pending,
+				"needs an owner's approval".to_string(),
+			),
+			Error::Approval {
+				missing_reviewers, ..
+			} => (
+				StatusState::Pending,
+				format!(
+					"needs {} more {} approval{}",
+					missing_reviewers,
+					reviewers(review_teams),
+					if *missing_reviewers == 1 { "" } else { "s" }
+				),
+			),
+			Error::ChangesRequested { reviewer } => (
+				StatusState::Failure,
+				format!("changes requested by @{}", reviewer),
+			),
+			Error::Unaudited { team, .. } => {
+				(StatusState::Pending, format!("needs an audit by @{}", team))
+			}
+			Error::MergeFrozen { branch, .. } => {
+				(StatusState::Pending, format!("{} is frozen", branch))
+			}
+			Error::BlockedLabel { label } => {
+				(StatusState::Failure, format!("blocked by label {}", label))
+			}
+			Error::BaseBranchNotAllowed { branch, .. } => (
+				StatusState::Failure,
+				format!("{} is not merged into by the bot", branch),
+			),
+			Error::MissingChangelog { .. } => {
+				(StatusState::Failure, "needs a changelog entry".to_string())
+			}
+			Error::SpecVersionNotBumped { .. } => (
+				StatusState::Failure,
+				"needs a spec_version bump".to_string(),
+			),
+			Error::ProcessInfo {} => (
+				StatusState::Failure,
+				"no owners are known for the changes".to_string(),
+			),
+			// eg. the PR is unmergeable
+			Error::Message { msg } => (StatusState::Failure, msg.clone()),
+			_ => return None,
+		})
+	}
+
+	match outcome {
+		Ok(()) => Some((
+			StatusState::Success,
+			"allowed to merge once checks pass".to_string(),
+		)),
+		Err(e) => of_error(e, review_teams),
+	}
+}
+
+/// The merge gate status for what `merge_allowed` decided, if it decided.
+pub fn status(
+	outcome: &Result<()>,
+	review_teams: &[String],
+) -> Option<StatusRequest> {
+	verdict(outcome, review_teams).map(|(state, description)| StatusRequest {
+		state,
+		description: truncate(&description),
+		context: CONTEXT.to_string(),
+		target_url: Some(TARGET_URL.to_string()),
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn verdict_of(e: Error) -> Option<(StatusState, String)> {
+		verdict(
+			&Err(e.map_issue(Some((
+				"paritytech".to_string(),
+				"substrate".to_string(),
+				1234,
+			)))),
+			&["core-devs".to_string()],
+		)
+	}
+
+	fn pending(description: &str) -> Option<(StatusState, String)> {
+		Some((StatusState::Pending, description.to_string()))
+	}
+
+	fn failure(description: &str) -> Option<(StatusState, String)> {
+		Some((StatusState::Failure, description.to_string()))
+	}
+
+	#[test]
+	fn test_verdict() {
+		assert_eq!(
+			verdict(&Ok(()), &[]),
+			Some((
+				StatusState::Success,
+				"allowed to merge once checks pass".to_string()
+			))
+		);
+		let approval = |missing_reviewers| Error::Approval {
+			owners: vec!["gavofyork".to_string()],
+			missing_reviewers,
+		};
+		assert_eq!(
+			verdict_of(approval(1)),
+			pending("needs 1 more core-devs approval")
+		);
+		assert_eq!(
+			verdict_of(approval(2)),
+			pending("needs 2 more core-devs approvals")
+		);
+		assert_eq!(
+			verdict_of(approval(0)),
+			pending("needs an owner's approval")
+		);
+		assert_eq!(
+			verdict(
+				&Err(approval(1)),
+				&["core-devs".to_string(), "polkadot-devs".to_string()]
+			),
+			pending("needs 1 more reviewer approval")
+		);
+		assert_eq!(
+			verdict_of(Error::ChangesRequested {
+				reviewer: "rphmeier".to_string(),
+			}),
+			failure("changes requested by @rphmeier")
+		);
+		assert_eq!(
+			verdict_of(Error::Unaudited {
+				files: vec!["runtime/src/lib.rs".to_string()],
+				label: "D1-audited".to_string(),
+				team: "paritytech/auditors".to_string(),
+			}),
+			pending("needs an audit by @paritytech/auditors")
+		);
+		assert_eq!(
+			verdict_of(Error::BlockedLabel {
+				label: "B7-locked".to_string(),
+			}),
+			failure("blocked by label B7-locked")
+		);
+		assert_eq!(
+			verdict_of(Error::BaseBranchNotAllowed {
+				branch: "gav-feature".to_string(),
+				allowed: vec!["master".to_string()],
+			}),
+			failure("gav-feature is not merged into by the bot")
+		);
+		assert_eq!(
+			verdict_of(Error::MissingChangelog {
+				path: "changelog/*.md".to_string(),
+				number: 1234,
+			}),
+			failure("needs a changelog entry")
+		);
+		assert_eq!(
+			verdict_of(Error::Message {
+				msg: "The PR is currently unmergeable.".to_string(),
+			}),
+			failure("The PR is currently unmergeable.")
+		);
+		// says nothing of the PR
+		assert_eq!(
+			verdict_of(Error::ProcessFile {
+				source: Box::new(Error::Message {
+					msg: "timed out".to_string(),
+				}),
+			}),
+			None
+		);
+		assert_eq!(verdict_of(Error::MissingData {}), None);
+	}
+
+	#[test]
+	fn test_status() {
+		let status = status(&Ok(()), &[]).unwrap();
+		assert_eq!(status.context, CONTEXT);
+		assert_eq!(status.state, StatusState::Success);
+		let status = super::status(
+			&Err(Error::BlockedLabel {
+				label: "B".repeat(200),
+			}),
+			&[],
+		)
+		.unwrap();
+		assert_eq!(status.description.chars().count(), MAX_DESCRIPTION_LEN);
+		assert!(status.description.starts_with("blocked by label BBB"));
+		assert!(status.description.ends_with('…'));
+	}
+
+	#[test]
+	fn test_context_is_own() {
+		assert!(crate::own_activity::is_own_context(CONTEXT));
+	}
+}
@@ -0,0 +1,1014 @@
+use chrono::{DateTime, Utc};
+
+use crate::{
+	approval::{self, TeamApproval},
+	config::BotConfig,
+	freeze::{self, MergeFreeze},
+	github::{Label, PullRequestFile, Review},
+	process::CombinedProcessInfo,
+	spec_version::{self, RuntimeVersions},
+	Result,
+};
+
+/// What a merge is asked for.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MergeIntent {
+	Merge,
+	/// `bot merge force`, which gets past some of the gates
+	Force,
+	/// The merge gate status: nothing is posted on the PR, nor are owners
+	/// pinged.
+	Check,
+	/// The merge the bot asks for itself for a PR by a trusted author: every
+	/// gate applies but the approval.
+	Automerge,
+}
+
+/// Everything the merge policy decides on, as fetched for a PR.
+#[derive(Debug)]
+pub struct MergeInputs {
+	/// organization of the repository
+	pub owner: String,
+	pub repo_name: String,
+	pub labels: Vec<Label>,
+	pub base_branch: String,
+	pub mergeable: bool,
+	pub requested_by: String,
+	pub intent: MergeIntent,
+	/// the files the PR changes; only fetched if a rule looks at them
+	pub files: Vec<PullRequestFile>,
+	/// members of the team auditing the sensitive paths; only fetched if the
+	/// PR changes any
+	pub auditors: Vec<String>,
+	/// the versions of the runtimes the PR changes
+	pub spec_versions: Vec<RuntimeVersions>,
+	/// the freeze set with `bot freeze`, besides the configured ones
+	pub stored_freeze: Option<MergeFreeze>,
+	pub now: DateTime<Utc>,
+	pub team_leads: Vec<String>,
+	pub reviewers: Vec<String>,
+	pub reviews: Vec<Review>,
+	/// `None` if not looked up, as the teams allow the merge without owners
+	pub process: Option<Result<CombinedProcessInfo>>,
+	/// the code owners of the PR, looked up if the process names no owners
+	pub codeowners: Option<Vec<String>>,
+}
+
+impl MergeInputs {
+	/// Logins whose approval allows the merge: the owners and delegates of
+	/// the projects of the PR, else its code owners.
+	pub fn owners(&self) -> Option<Vec<String>> {
+		match &self.process {
+			Some(Ok(process)) if !process.is_empty() => {
+				Some(process.iter_owners().cloned().collect())
+			}
+			_ => self.codeowners.clone(),
+		}
+	}
+}
+
+/// Why a merge is allowed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Allowance {
+	Team(TeamApproval),
+	/// an owner or delegate asked for the merge or approved
+	Owner,
+	/// the PR is by a trusted author, which needs no approval
+	TrustedAuthor,
+}
+
+/// A rule which stops a merge.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+	Label(String),
+	BaseBranch {
+		branch: String,
+		allowed: Vec<String>,
+	},
+	MissingChangelog {
+		path: String,
+	},
+	Unaudited {
+		files: Vec<String>,
+		label: String,
+		/// `org/team` of the auditors
+		team: String,
+	},
+	SpecVersionNotBumped {
+		versions: Vec<RuntimeVersions>,
+	},
+	Frozen(MergeFreeze),
+	Unmergeable,
+	ChangesRequested(String),
+	/// neither the process file nor CODEOWNERS name owners of the PR
+	NoOwners,
+	Approval {
+		owners: Vec<String>,
+		missing_reviewers: usize,
+	},
+}
+
+/// A rule the PR breaks without it stopping the merge, to be explained on
+/// the PR.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Note {
+	/// `bot merge force` got past the missing changelog entry
+	ChangelogForced { path: String },
+	/// merged anyway, as forced or as the repository only warns about it
+	SpecVersionNotBumped {
+		versions: Vec<RuntimeVersions>,
+		forced: bool,
+	},
+}
+
+impl Note {
+	/// The comment explaining the note, for a merge `requested_by` asked for.
+	pub fn message(&self, requested_by: &str) -> String {
+		match self {
+			Note::ChangelogForced { path } => format!(
+				"Merging without a changelog entry matching `{}`, as @{} forced the merge.",
+				path, requested_by
+			),
+			Note::SpecVersionNotBumped { versions, forced } => {
+				let reason = if *forced {
+					format!("as @{} forced the merge", requested_by)
+				} else {
+					"as this repository only warns about it".to_string()
+				};
+				format!(
+					"The PR changes runtime source without bumping its `spec_version`:\n\n{}\n\nMerging anyway, {}.",
+					spec_version::report(versions),
+					reason
+				)
+			}
+		}
+	}
+}
+
+/// What the merge policy decides.  The notes are those of the rules checked
+/// before the first which blocks, as the merge stops there.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MergeVerdict {
+	Allowed {
+		reason: Allowance,
+		notes: Vec<Note>,
+	},
+	Blocked {
+		/// in the order the rules are checked
+		reasons: Vec<Block>,
+		notes: Vec<Note>,
+	},
+}
+
+impl MergeVerdict {
+	/// The reason given for a blocked merge: the first rule which blocks it.
+	pub fn blocked_by(&self) -> Option<&Block> {
+		match self {
+			MergeVerdict::Allowed { .. } => None,
+			MergeVerdict::Blocked { reasons, .. } => reasons.first(),
+		}
+	}
+
+	pub fn notes(&self) -> &[Note] {
+		match self {
+			MergeVerdict::Allowed { notes, .. }
+			| MergeVerdict::Blocked { notes, .. } => notes,
+		}
+	}
+}
+
+/// The rules checked first, which need nothing fetched for the PR: blocking
+/// labels, then the base branch.
+pub fn unfetched_blocks(
+	labels: &[Label],
+	base_branch: &str,
+	config: &BotConfig,
+) -> Vec<Block> {
+	let mut reasons = Vec::new();
+
+	if let Some(label) = labels
+		.iter()
+		.find(|label| config.blocked_labels.contains(&label.name))
+	{
+		reasons.push(Block::Label(label.name.clone()));
+	}
+
+	if !config.allows_base_branch(base_branch) {
+		reasons.push(Block::BaseBranch {
+			branch: base_branch.to_string(),
+			allowed: config.allowed_base_branches.clone(),
+		});
+	}
+
+	reasons
+}
+
+/// Decide whether a PR may be merged, checking in order: blocking labels,
+/// the base branch, the changelog entry, the audit of sensitive paths, the
+/// `spec_version` bump, merge freezes, mergeability, requests for changes,
+/// then the approval of the teams or else of an owner, unless the merge is
+/// an automatic one.
+pub fn evaluate_merge_policy(
+	inputs: &MergeInputs,
+	config: &BotConfig,
+) -> MergeVerdict {
+	let force = inputs.intent == MergeIntent::Force;
+	let mut reasons =
+		unfetched_blocks(&inputs.labels, &inputs.base_branch, config);
+	let mut notes = Vec::new();
+
+	if let Some(requirement) = config
+		.require_changelog
+		.as_ref()
+		.filter(|requirement| !requirement.is_exempt(&inputs.labels))
+	{
+		if requirement.is_met(&inputs.files) {
+			// nothing to say
+		} else if !force {
+			reasons.push(Block::MissingChangelog {
+				path: requirement.path.clone(),
+			});
+		} else if reasons.is_empty() {
+			notes.push(Note::ChangelogForced {
+				path: requirement.path.clone(),
+			});
+		}
+	}
+
+	// a PR labelled audited needs no audit
+	if let Some(sensitive) = config
+		.sensitive_paths
+		.as_ref()
+		.filter(|sensitive| !sensitive.is_labelled(&inputs.labels))
+	{
+		let touched = sensitive.matching_files(&inputs.files);
+		if !touched.is_empty()
+			&& !sensitive.is_approved(&inputs.reviews, &inputs.auditors)
+		{
+			reasons.push(Block::Unaudited {
+				files: touched,
+				label: sensitive.label.clone(),
+				team: format!("{}/{}", inputs.owner, sensitive.team),
+			});
+		}
+	}
+
+	if let Some(check) = &config.spec_version {
+		if inputs.spec_versions.iter().any(|v| v.is_not_bumped()) {
+			if check.block && !force {
+				reasons.push(Block::SpecVersionNotBumped {
+					versions: inputs.spec_versions.clone(),
+				});
+			} else if inputs.intent != MergeIntent::Check && reasons.is_empty()
+			{
+				notes.push(Note::SpecVersionNotBumped {
+					versions: inputs.spec_versions.clone(),
+					forced: check.block,
+				});
+			}
+		}
+	}
+
+	if let Some(active) = freeze::active_freeze(
+		config.merge_freezes.iter().chain(&inputs.stored_freeze),
+		&inputs.repo_name,
+		&inputs.base_branch,
+		inputs.now,
+	) {
+		// only a team lead may force a merge through a freeze
+		let lead_forced = force
+			&& inputs
+				.team_leads
+				.iter()
+				.any(|lead| lead == &inputs.requested_by);
+		if !lead_forced {
+			reasons.push(Block::Frozen(active.clone()));
+		}
+	}
+
+	if !inputs.mergeable {
+		reasons.push(Block::Unmergeable);
+	}
+
+	let owners = inputs.owners();
+	let approval = approval::evaluate(
+		&inputs.requested_by,
+		&inputs.reviews,
+		&inputs.team_leads,
+		&inputs.reviewers,
+		owners.as_deref(),
+		approval::required_reviewers(config, &inputs.labels),
+	);
+	let automerge = inputs.intent == MergeIntent::Automerge;
+	if let Some(reviewer) = &approval.changes_requested_by {
+		reasons.push(Block::ChangesRequested(reviewer.clone()));
+	} else if approval.team_approval.is_none() && !automerge {
+		match owners {
+			None => reasons.push(Block::NoOwners),
+			Some(owners) if approval.owner_approval != Some(true) => reasons
+				.push(Block::Approval {
+					owners,
+					missing_reviewers: approval.missing_reviewers(),
+				}),
+			Some(_) => {}
+		}
+	}
+
+	if reasons.is_empty() {
+		MergeVerdict::Allowed {
+			reason: match approval.team_approval {
+				Some(team_approval) => Allowance::Team(team_approval),
+				None if automerge => Allowance::TrustedAuthor,
+				None => Allowance::Owner,
+			},
+			notes,
+		}
+	} else {
+		MergeVerdict::Blocked { reasons, notes }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		changelog::ChangelogRequirement,
+		error::Error,
+		github::{ReviewState, User},
+		process::ProcessEntry,
+		sensitive_paths::SensitivePaths,
+		spec_version::SpecVersionCheck,
+	};
+	use chrono::TimeZone;
+
+	fn logins(logins: &[&str]) -> Vec<String> {
+		logins.iter().map(|login| login.to_string()).collect()
+	}
+
+	fn label(name: &str) -> Label {
+		Label {
+			name: name.to_string(),
+			..Label::default()
+		}
+	}
+
+	fn file(filename: &str) -> PullRequestFile {
+		PullRequestFile {
+			filename: filename.to_string(),
+			status: "modified".to_string(),
+			..PullRequestFile::default()
+		}
+	}
+
+	/// A review submitted `minutes` after the first.
+	fn review(login: &str, state: ReviewState, minutes: i64) -> Review {
+		Review {
+			user: User {
+				login: login.to_string(),
+				..User::default()
+			},
+			state: Some(state),
+			submitted_at: Some(
+				Utc.ymd(2021, 1, 1).and_hms(0, 0, 0)
+					+ chrono::Duration::minutes(minutes),
+			),
+			..Review::default()
+		}
+	}
+
+	fn process(owners: &[&str]) -> CombinedProcessInfo {
+		CombinedProcessInfo::from(vec![ProcessEntry {
+			project_name: "Polkadot".to_string(),
+			owners: logins(owners),
+			delegated_reviewer: None,
+			delegated_reviewers: vec![],
+			whitelist: vec![],
+			matching_labels: vec![],
+			matrix_room_id: "!abc:matrix.parity.io".to_string(),
+			backlog: None,
+		}])
+	}
+
+	fn config() -> BotConfig {
+		BotConfig {
+			min_reviewers: 2,
+			insubstantial_labels: logins(&["insubstantial"]),
+			insubstantial_min_reviewers: 1,
+			..BotConfig::default()
+		}
+	}
+
+	/// A mergeable PR without reviews, whose merge `dave`, who is in no
+	/// team, asked for; the leads are `gavofyork`, the reviewers `alice`
+	/// and `bob`, and the process owner `olivia`.
+	fn inputs() -> MergeInputs {
+		MergeInputs {
+			owner: "paritytech".to_string(),
+			repo_name: "polkadot".to_string(),
+			labels: vec![],
+			base_branch: "master".to_string(),
+			mergeable: true,
+			requested_by: "dave".to_string(),
+			intent: MergeIntent::Merge,
+			files: vec![],
+			auditors: vec![],
+			spec_versions: vec![],
+			stored_freeze: None,
+			now: Utc.ymd(2021, 1, 1).and_hms(12, 0, 0),
+			team_leads: logins(&["gavofyork"]),
+			reviewers: logins(&["alice", "bob"]),
+			reviews: vec![],
+			process: Some(Ok(process(&["olivia"]))),
+			codeowners: None,
+		}
+	}
+
+	fn allowed(reason: Allowance) -> MergeVerdict {
+		MergeVerdict::Allowed {
+			reason,
+			notes: vec![],
+		}
+	}
+
+	fn blocked(reasons: Vec<Block>) -> MergeVerdict {
+		MergeVerdict::Blocked {
+			reasons,
+			notes: vec![],
+		}
+	}
+
+	fn approval_block(missing_reviewers: usize) -> Block {
+		Block::Approval {
+			owners: logins(&["olivia"]),
+			missing_reviewers,
+		}
+	}
+
+	#[test]
+	fn test_review_quorum() {
+		let config = config();
+		let inputs = MergeInputs {
+			reviews: vec![review("alice", ReviewState::Approved, 0)],
+			..inputs()
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			blocked(vec![approval_block(1)])
+		);
+		let inputs = MergeInputs {
+			reviews: vec![
+				review("alice", ReviewState::Approved, 0),
+				review("bob", ReviewState::Approved, 1),
+			],
+			..inputs
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			allowed(Allowance::Team(TeamApproval::ReviewersApproved))
+		);
+		// approvals of others do not count towards it
+		let inputs = MergeInputs {
+			reviews: vec![
+				review("alice", ReviewState::Approved, 0),
+				review("erin", ReviewState::Approved, 1),
+			],
+			..inputs
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			blocked(vec![approval_block(1)])
+		);
+	}
+
+	#[test]
+	fn test_insubstantial_label() {
+		let config = config();
+		let inputs = MergeInputs {
+			reviews: vec![review("alice", ReviewState::Approved, 0)],
+			labels: vec![label("B0-insubstantial")],
+			..inputs()
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			allowed(Allowance::Team(TeamApproval::ReviewersApproved))
+		);
+		let inputs = MergeInputs {
+			reviews: vec![],
+			..inputs
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			blocked(vec![approval_block(1)])
+		);
+	}
+
+	#[test]
+	fn test_team_lead_bypass() {
+		let config = config();
+		// a lead asking for the merge needs no review
+		let inputs = MergeInputs {
+			requested_by: "gavofyork".to_string(),
+			..inputs()
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			allowed(Allowance::Team(TeamApproval::LeadRequested))
+		);
+		let inputs = MergeInputs {
+			requested_by: "dave".to_string(),
+			reviews: vec![review("gavofyork", ReviewState::Approved, 0)],
+			..inputs
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			allowed(Allowance::Team(TeamApproval::LeadApproved))
+		);
+		// the teams allowing it, owners need not be looked up
+		let inputs = MergeInputs {
+			process: None,
+			..inputs
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			allowed(Allowance::Team(TeamApproval::LeadApproved))
+		);
+		// but a lead cannot override a request for changes
+		let inputs = MergeInputs {
+			requested_by: "gavofyork".to_string(),
+			reviews: vec![review("alice", ReviewState::ChangesRequested, 0)],
+			..inputs
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			blocked(vec![Block::ChangesRequested("alice".to_string())])
+		);
+	}
+
+	#[test]
+	fn test_owner_approval() {
+		let config = config();
+		let inputs = MergeInputs {
+			reviews: vec![review("olivia", ReviewState::Approved, 0)],
+			..inputs()
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			allowed(Allowance::Owner)
+		);
+		// an owner asking is as good as their approval
+		let inputs = MergeInputs {
+			requested_by: "olivia".to_string(),
+			reviews: vec![],
+			..inputs
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			allowed(Allowance::Owner)
+		);
+		// the team quorum does not wait for owners
+		let inputs = MergeInputs {
+			requested_by: "dave".to_string(),
+			reviews: vec![
+				review("alice", ReviewState::Approved, 0),
+				review("bob", ReviewState::Approved, 1),
+			],
+			..inputs
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			allowed(Allowance::Team(TeamApproval::ReviewersApproved))
+		);
+	}
+
+	#[test]
+	fn test_latest_owner_review_wins() {
+		let config = config();
+		// listed out of order, as the latest review is what counts
+		let inputs = MergeInputs {
+			reviews: vec![
+				review("olivia", ReviewState::ChangesRequested, 2),
+				review("olivia", ReviewState::Approved, 1),
+			],
+			..inputs()
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			blocked(vec![approval_block(2)])
+		);
+		let inputs = MergeInputs {
+			reviews: vec![
+				review("olivia", ReviewState::Approved, 2),
+				review("olivia", ReviewState::ChangesRequested, 1),
+			],
+			..inputs
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			allowed(Allowance::Owner)
+		);
+		// a later comment leaves the approval standing, a dismissal does not
+		let inputs = MergeInputs {
+			reviews: vec![
+				review("olivia", ReviewState::Approved, 1),
+				review("olivia", ReviewState::Commented, 2),
+			],
+			..inputs
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			allowed(Allowance::Owner)
+		);
+		let inputs = MergeInputs {
+			reviews: vec![
+				review("olivia", ReviewState::Approved, 1),
+				review("olivia", ReviewState::Dismissed, 2),
+			],
+			..inputs
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			blocked(vec![approval_block(2)])
+		);
+	}
+
+	#[test]
+	fn test_changes_requested() {
+		let config = config();
+		let inputs = MergeInputs {
+			reviews: vec![
+				review("olivia", ReviewState::Approved, 0),
+				review("gavofyork", ReviewState::ChangesRequested, 1),
+			],
+			..inputs()
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			blocked(vec![Block::ChangesRequested("gavofyork".to_string())])
+		);
+		// approving later lifts the block
+		let mut reviews = inputs.reviews.clone();
+		reviews.push(review("gavofyork", ReviewState::Approved, 2));
+		let inputs = MergeInputs { reviews, ..inputs };
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			allowed(Allowance::Team(TeamApproval::LeadApproved))
+		);
+		// changes requested by others do not block
+		let inputs = MergeInputs {
+			reviews: vec![
+				review("olivia", ReviewState::Approved, 0),
+				review("erin", ReviewState::ChangesRequested, 1),
+			],
+			..inputs
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			allowed(Allowance::Owner)
+		);
+	}
+
+	#[test]
+	fn test_owners_fallback() {
+		let config = config();
+		let inputs = MergeInputs {
+			process: Some(Ok(CombinedProcessInfo::from(vec![]))),
+			codeowners: Some(logins(&["carol"])),
+			reviews: vec![review("carol", ReviewState::Approved, 0)],
+			..inputs()
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			allowed(Allowance::Owner)
+		);
+		// the process takes precedence over CODEOWNERS
+		let inputs = MergeInputs {
+			process: Some(Ok(process(&["olivia"]))),
+			..inputs
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			blocked(vec![approval_block(2)])
+		);
+		let inputs = MergeInputs {
+			process: Some(Err(Error::MissingData {})),
+			..inputs
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			allowed(Allowance::Owner)
+		);
+		let inputs = MergeInputs {
+			codeowners: None,
+			..inputs
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			blocked(vec![Block::NoOwners])
+		);
+	}
+
+	#[test]
+	fn test_order_of_blocks() {
+		let config = BotConfig {
+			blocked_labels: logins(&["A3-in_progress"]),
+			allowed_base_branches: logins(&["master", "release-*"]),
+			..config()
+		};
+		let inputs = MergeInputs {
+			labels: vec![label("A3-in_progress")],
+			base_branch: "feature".to_string(),
+			mergeable: false,
+			..inputs()
+		};
+		let verdict = evaluate_merge_policy(&inputs, &config);
+		assert_eq!(
+			verdict,
+			blocked(vec![
+				Block::Label("A3-in_progress".to_string()),
+				Block::BaseBranch {
+					branch: "feature".to_string(),
+					allowed: logins(&["master", "release-*"]),
+				},
+				Block::Unmergeable,
+				approval_block(2),
+			])
+		);
+		assert_eq!(
+			verdict.blocked_by(),
+			Some(&Block::Label("A3-in_progress".to_string()))
+		);
+		let inputs = MergeInputs {
+			labels: vec![],
+			base_branch: "release-v0.9.30".to_string(),
+			requested_by: "olivia".to_string(),
+			..inputs
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			blocked(vec![Block::Unmergeable])
+		);
+	}
+
+	#[test]
+	fn test_changelog() {
+		let config = BotConfig {
+			require_changelog: Some(ChangelogRequirement {
+				path: "docs/changelog/*.md".to_string(),
+				exempt_labels: logins(&["B0-silent"]),
+			}),
+			..config()
+		};
+		let inputs = MergeInputs {
+			requested_by: "gavofyork".to_string(),
+			files: vec![file("src/lib.rs")],
+			..inputs()
+		};
+		let missing = Block::MissingChangelog {
+			path: "docs/changelog/*.md".to_string(),
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			blocked(vec![missing.clone()])
+		);
+		let inputs = MergeInputs {
+			intent: MergeIntent::Force,
+			..inputs
+		};
+		let verdict = evaluate_merge_policy(&inputs, &config);
+		assert_eq!(
+			verdict,
+			MergeVerdict::Allowed {
+				reason: Allowance::Team(TeamApproval::LeadRequested),
+				notes: vec![Note::ChangelogForced {
+					path: "docs/changelog/*.md".to_string(),
+				}],
+			}
+		);
+		assert_eq!(
+			verdict.notes()[0].message("gavofyork"),
+			"Merging without a changelog entry matching `docs/changelog/*.md`, as @gavofyork forced the merge."
+		);
+		// no note for a merge blocked before the changelog is looked at
+		let blocked_config = BotConfig {
+			blocked_labels: logins(&["A3-in_progress"]),
+			..config.clone()
+		};
+		let inputs = MergeInputs {
+			labels: vec![label("A3-in_progress")],
+			..inputs
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &blocked_config),
+			blocked(vec![Block::Label("A3-in_progress".to_string())])
+		);
+		let inputs = MergeInputs {
+			labels: vec![label("B0-silent")],
+			intent: MergeIntent::Merge,
+			..inputs
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			allowed(Allowance::Team(TeamApproval::LeadRequested))
+		);
+		let inputs = MergeInputs {
+			labels: vec![],
+			files: vec![file("src/lib.rs"), file("docs/changelog/fix.md")],
+			..inputs
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			allowed(Allowance::Team(TeamApproval::LeadRequested))
+		);
+	}
+
+	#[test]
+	fn test_sensitive_paths() {
+		let config = BotConfig {
+			sensitive_paths: Some(SensitivePaths {
+				paths: logins(&["runtime/*/src/migrations*"]),
+				label: "D1-audited".to_string(),
+				team: "auditors".to_string(),
+			}),
+			..config()
+		};
+		let inputs = MergeInputs {
+			requested_by: "gavofyork".to_string(),
+			files: vec![file("runtime/polkadot/src/migrations.rs")],
+			auditors: logins(&["zed"]),
+			// forcing does not get past an audit
+			intent: MergeIntent::Force,
+			..inputs()
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			blocked(vec![Block::Unaudited {
+				files: logins(&["runtime/polkadot/src/migrations.rs"]),
+				label: "D1-audited".to_string(),
+				team: "paritytech/auditors".to_string(),
+			}])
+		);
+		let inputs = MergeInputs {
+			reviews: vec![review("zed", ReviewState::Approved, 0)],
+			..inputs
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			allowed(Allowance::Team(TeamApproval::LeadRequested))
+		);
+		let inputs = MergeInputs {
+			reviews: vec![],
+			labels: vec![label("D1-audited")],
+			..inputs
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			allowed(Allowance::Team(TeamApproval::LeadRequested))
+		);
+	}
+
+	#[test]
+	fn test_spec_version() {
+		let versions = vec![RuntimeVersions {
+			runtime: "runtime/polkadot".to_string(),
+			base: Some(9300),
+			head: Some(9300),
+		}];
+		let config = |block| BotConfig {
+			spec_version: Some(SpecVersionCheck {
+				runtimes: logins(&["runtime/*"]),
+				block,
+			}),
+			..config()
+		};
+		let inputs = MergeInputs {
+			requested_by: "gavofyork".to_string(),
+			spec_versions: versions.clone(),
+			..inputs()
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config(true)),
+			blocked(vec![Block::SpecVersionNotBumped {
+				versions: versions.clone(),
+			}])
+		);
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config(false)).notes(),
+			&[Note::SpecVersionNotBumped {
+				versions: versions.clone(),
+				forced: false,
+			}]
+		);
+		let inputs = MergeInputs {
+			intent: MergeIntent::Force,
+			..inputs
+		};
+		let verdict = evaluate_merge_policy(&inputs, &config(true));
+		assert_eq!(
+			verdict.notes(),
+			&[Note::SpecVersionNotBumped {
+				versions: versions.clone(),
+				forced: true,
+			}]
+		);
+		assert!(verdict.notes()[0]
+			.message("gavofyork")
+			.ends_with("Merging anyway, as @gavofyork forced the merge."));
+		// a check posts nothing
+		let inputs = MergeInputs {
+			intent: MergeIntent::Check,
+			..inputs
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config(false)),
+			allowed(Allowance::Team(TeamApproval::LeadRequested))
+		);
+	}
+
+	#[test]
+	fn test_freeze() {
+		let freeze = MergeFreeze {
+			repo: "polkadot".to_string(),
+			branch: None,
+			start: None,
+			end: None,
+			until_further_notice: true,
+			reason: "release".to_string(),
+			frozen_by: None,
+		};
+		let config = config();
+		let inputs = MergeInputs {
+			requested_by: "gavofyork".to_string(),
+			stored_freeze: Some(freeze.clone()),
+			..inputs()
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			blocked(vec![Block::Frozen(freeze.clone())])
+		);
+		// a lead may force a merge through it
+		let inputs = MergeInputs {
+			intent: MergeIntent::Force,
+			..inputs
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			allowed(Allowance::Team(TeamApproval::LeadRequested))
+		);
+		// others may not
+		let inputs = MergeInputs {
+			requested_by: "olivia".to_string(),
+			..inputs
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			blocked(vec![Block::Frozen(freeze.clone())])
+		);
+		// other repositories are not frozen
+		let inputs = MergeInputs {
+			repo_name: "substrate".to_string(),
+			..inputs
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			allowed(Allowance::Owner)
+		);
+	}
+
+	#[test]
+	fn test_automerge() {
+		let freeze = MergeFreeze {
+			repo: "polkadot".to_string(),
+			branch: None,
+			start: None,
+			end: None,
+			until_further_notice: true,
+			reason: "release".to_string(),
+			frozen_by: None,
+		};
+		let config = config();
+		let inputs = MergeInputs {
+			requested_by: "parity-processbot".to_string(),
+			intent: MergeIntent::Automerge,
+			process: None,
+			..inputs()
+		};
+		// no approval is needed
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			allowed(Allowance::TrustedAuthor)
+		);
+		// but the other gates still apply
+		let inputs = MergeInputs {
+			stored_freeze: Some(freeze.clone()),
+			reviews: vec![review("alice", ReviewState::ChangesRequested, 0)],
+			..inputs
+		};
+		assert_eq!(
+			evaluate_merge_policy(&inputs, &config),
+			blocked(vec![
+				Block::Frozen(freeze),
+				Block::ChangesRequested("alice".to_string())
+			])
+		);
+	}
+}
@@ -0,0 +1,410 @@
+use chrono::{DateTime, Utc};
+use rocksdb::DB;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use snafu::ResultExt;
+
+use crate::{
+	burnin::{self, BurninRecord},
+	db::{self, Transaction},
+	error::*,
+	freeze::{self, MergeFreeze},
+	quarantine,
+	queue::{self, MergeOrigin, MergeRequest},
+	Result,
+};
+
+/// Marks a versioned record, followed by its version and the bincode payload.
+/// Records written before versioning are bare payloads of version 0; those
+/// all start with the length of a string, which is never this large.
+const MAGIC: &[u8] = b"\xffPB";
+
+/// The kinds of versioned records.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Kind {
+	MergeRequest,
+	Burnin,
+	MergeFreeze,
+}
+
+impl Kind {
+	/// The kind of record stored at `key`, if it is versioned.
+	pub fn of(key: &[u8]) -> Option<Kind> {
+		if queue::is_queue_key(key) {
+			Some(Kind::MergeRequest)
+		} else if key.starts_with(burnin::RECORD_KEY_PREFIX.as_bytes()) {
+			Some(Kind::Burnin)
+		} else if key.starts_with(freeze::KEY_PREFIX.as_bytes()) {
+			Some(Kind::MergeFreeze)
+		} else {
+			None
+		}
+	}
+}
+
+/// A record stored in the database.
+pub trait Versioned: Serialize + DeserializeOwned {
+	/// Bump whenever the fields change, and read the previous version in
+	/// `migrate`.
+	const VERSION: u8;
+
+	/// Read the payload of an older version.
+	fn migrate(version: u8, payload: &[u8]) -> Result<Self>;
+}
+
+/// A merge request up to version 1, before it had `created_at`.
+#[derive(Deserialize)]
+struct MergeRequestV1 {
+	owner: String,
+	repo_name: String,
+	number: i64,
+	html_url: String,
+	requested_by: String,
+}
+
+/// A merge request of version 2, before it had `origin`.
+#[derive(Deserialize)]
+struct MergeRequestV2 {
+	owner: String,
+	repo_name: String,
+	number: i64,
+	html_url: String,
+	requested_by: String,
+	created_at: Option<i64>,
+}
+
+/// A merge request of version 3, before it had `not_before`.
+#[derive(Deserialize)]
+struct MergeRequestV3 {
+	owner: String,
+	repo_name: String,
+	number: i64,
+	html_url: String,
+	requested_by: String,
+	created_at: Option<i64>,
+	origin: Option<MergeOrigin>,
+}
+
+impl Versioned for MergeRequest {
+	const VERSION: u8 = 4;
+
+	fn migrate(version: u8, payload: &[u8]) -> Result<Self> {
+		let v3 = if version < 3 {
+			Self::migrate_v2(version, payload)?
+		} else {
+			bincode::deserialize(payload).context(Bincode)?
+		};
+		let MergeRequestV3 {
+			owner,
+			repo_name,
+			number,
+			html_url,
+			requested_by,
+			created_at,
+			origin,
+		} = v3;
+		Ok(MergeRequest {
+			owner,
+			repo_name,
+			number,
+			html_url,
+			requested_by,
+			created_at,
+			origin,
+			not_before: None,
+		})
+	}
+}
+
+impl MergeRequest {
+	/// Read a merge request of version 2 or older.
+	fn migrate_v2(version: u8, payload: &[u8]) -> Result<MergeRequestV3> {
+		let v2 = if version < 2 {
+			let MergeRequestV1 {
+				owner,
+				repo_name,
+				number,
+				html_url,
+				requested_by,
+			} = bincode::deserialize(payload).context(Bincode)?;
+			MergeRequestV2 {
+				owner,
+				repo_name,
+				number,
+				html_url,
+				requested_by,
+				created_at: None,
+			}
+		} else {
+			bincode::deserialize(payload).context(Bincode)?
+		};
+		let MergeRequestV2 {
+			owner,
+			repo_name,
+			number,
+			html_url,
+			requested_by,
+			created_at,
+		} = v2;
+		Ok(MergeRequestV3 {
+			owner,
+			repo_name,
+			number,
+			html_url,
+			requested_by,
+			created_at,
+			origin: None,
+		})
+	}
+}
+
+impl Versioned for BurninRecord {
+	const VERSION: u8 = 1;
+
+	fn migrate(_version: u8, payload: &[u8]) -> Result<Self> {
+		bincode::deserialize(payload).context(Bincode)
+	}
+}
+
+impl Versioned for MergeFreeze {
+	const VERSION: u8 = 1;
+
+	fn migrate(_version: u8, payload: &[u8]) -> Result<Self> {
+		bincode::deserialize(payload).context(Bincode)
+	}
+}
+
+pub fn encode<T: Versioned>(record: &T) -> Result<Vec<u8>> {
+	let mut bytes = MAGIC.to_vec();
+	bytes.push(T::VERSION);
+	bytes.extend(bincode::serialize(record).context(Bincode)?);
+	Ok(bytes)
+}
+
+/// Read a record of any version up to the current one.
+pub fn decode<T: Versioned>(bytes: &[u8]) -> Result<T> {
+	decode_version(bytes).map(|(record, _)| record)
+}
+
+/// The record along with the version it was stored as.
+fn decode_version<T: Versioned>(bytes: &[u8]) -> Result<(T, u8)> {
+	let (version, payload) = match bytes.strip_prefix(MAGIC) {
+		Some([version, payload @ ..]) => (*version, payload),
+		_ => (0, bytes),
+	};
+	let record = if version == T::VERSION {
+		bincode::deserialize(payload).context(Bincode)?
+	} else if version < T::VERSION {
+		T::migrate(version, payload)?
+	} else {
+		return Err(Error::Message {
+			msg: format!(
+				"Record version {} is newer than {}",
+				version,
+				T::VERSION
+			),
+		});
+	};
+	Ok((record, version))
+}
+
+/// The record encoded as the current version, if it is stored as an older one.
+fn upgrade<T: Versioned>(bytes: &[u8]) -> Result<Option<Vec<u8>>> {
+	let (record, version) = decode_version::<T>(bytes)?;
+	if version == T::VERSION {
+		Ok(None)
+	} else {
+		encode(&record).map(Some)
+	}
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Report {
+	pub migrated: usize,
+	pub quarantined: usize,
+}
+
+/// Rewrite the records stored as an older version, and quarantine those
+/// which cannot be read.
+pub fn migrate(db: &DB) -> Result<Report> {
+	let now = Utc::now();
+	db::atomically(db, |tx| {
+		let mut report = Report::default();
+		for name in db::all_families() {
+			migrate_family(tx, name, now, &mut report)?;
+		}
+		Ok(report)
+	})
+}
+
+fn migrate_family(
+	tx: &mut Transaction,
+	name: &str,
+	now: DateTime<Utc>,
+	report: &mut Report,
+) -> Result<()> {
+	for (key, value) in db::family(tx.db(), name)?.iter() {
+		let upgraded = match Kind::of(&key) {
+			Some(Kind::MergeRequest) => upgrade::<MergeRequest>(&value),
+			Some(Kind::Burnin) => upgrade::<BurninRecord>(&value),
+			Some(Kind::MergeFreeze) => upgrade::<MergeFreeze>(&value),
+			None => continue,
+		};
+		match upgraded {
+			Ok(None) => {}
+			Ok(Some(bytes)) => {
+				tx.put(name, &key, &bytes)?;
+				report.migrated += 1;
+			}
+			Err(e) => {
+				log::warn!(
+					"Quarantining record {}: {}",
+					String::from_utf8_lossy(&key),
+					e
+				);
+				quarantine::quarantine(tx, name, &key, &value, now)?;
+				report.quarantined += 1;
+			}
+		}
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::db::TempDb;
+	use crate::queue::MergeOrigin;
+
+	const SHA: &str = "0123456789abcdef0123456789abcdef01234567";
+
+	/// A merge request as stored before versioning.
+	const MERGE_REQUEST_V0: &[u8] = b"\
+		\x0a\0\0\0\0\0\0\0paritytech\
+		\x09\0\0\0\0\0\0\0substrate\
+		\xd2\x04\0\0\0\0\0\0\
+		\0\0\0\0\0\0\0\0\
+		\x09\0\0\0\0\0\0\0gavofyork";
+
+	fn merge_request() -> MergeRequest {
+		MergeRequest {
+			owner: "paritytech".to_string(),
+			repo_name: "substrate".to_string(),
+			number: 1234,
+			html_url: String::new(),
+			requested_by: "gavofyork".to_string(),
+			created_at: None,
+			origin: None,
+			not_before: None,
+		}
+	}
+
+	#[test]
+	fn test_decode() {
+		assert_eq!(
+			decode::<MergeRequest>(MERGE_REQUEST_V0).unwrap(),
+			merge_request()
+		);
+		let v1 = [b"\xffPB\x01", MERGE_REQUEST_V0].concat();
+		assert_eq!(decode::<MergeRequest>(&v1).unwrap(), merge_request());
+		let v2 = [
+			&b"\xffPB\x02"[..],
+			MERGE_REQUEST_V0,
+			b"\x01",
+			&1_600_000_000i64.to_le_bytes(),
+		]
+		.concat();
+		assert_eq!(
+			decode::<MergeRequest>(&v2).unwrap(),
+			MergeRequest {
+				created_at: Some(1_600_000_000),
+				..merge_request()
+			}
+		);
+
+		let v3 = MergeRequest {
+			created_at: Some(1_600_000_000),
+			origin: Some(MergeOrigin {
+				owner: "paritytech".to_string(),
+				repo_name: "substrate".to_string(),
+				number: 1000,
+				html_url: "https://github.com/paritytech/substrate/pull/1000"
+					.to_string(),
+			}),
+			..merge_request()
+		};
+		let mut bytes = encode(&v3).unwrap();
+		// without `not_before`
+		bytes.truncate(bytes.len() - 1);
+		bytes[3] = 3;
+		assert_eq!(decode::<MergeRequest>(&bytes).unwrap(), v3);
+
+		let current = MergeRequest {
+			not_before: Some(1_700_000_000),
+			..v3
+		};
+		let bytes = encode(&current).unwrap();
+		assert_eq!(&bytes[..4], b"\xffPB\x04");
+		assert_eq!(decode::<MergeRequest>(&bytes).unwrap(), current);
+
+		let mut newer = bytes;
+		newer[3] = 5;
+		assert!(decode::<MergeRequest>(&newer).is_err());
+		assert!(decode::<MergeRequest>(b"garbage").is_err());
+	}
+
+	#[test]
+	fn test_migrate() {
+		let db = TempDb::new("migrations");
+		let merges = db::family(&db, db::MERGE_REQUESTS).unwrap();
+		let other = SHA.replace('0', "f");
+		let freeze = MergeFreeze {
+			repo: "substrate".to_string(),
+			branch: None,
+			start: None,
+			end: None,
+			until_further_notice: true,
+			reason: "release".to_string(),
+			frozen_by: None,
+		};
+		merges.put(SHA.as_bytes(), MERGE_REQUEST_V0).unwrap();
+		merges.put(other.as_bytes(), b"garbage").unwrap();
+		db.put(
+			"merge-freeze/paritytech/substrate",
+			encode(&freeze).unwrap(),
+		)
+		.unwrap();
+		db.put("auto-update/paritytech/substrate/1", b"{}").unwrap();
+
+		assert_eq!(
+			migrate(&db).unwrap(),
+			Report {
+				migrated: 1,
+				quarantined: 1
+			}
+		);
+		assert_eq!(
+			merges.get(SHA.as_bytes()).unwrap().unwrap(),
+			encode(&merge_request()).unwrap()
+		);
+		assert!(merges.get(other.as_bytes()).unwrap().is_none());
+		let quarantined = quarantine::list(&db).unwrap();
+		assert_eq!(quarantined.len(), 1);
+		assert_eq!(quarantined[0].family, db::MERGE_REQUESTS);
+		assert_eq!(quarantined[0].key, other);
+		assert_eq!(quarantined[0].value, base64::encode("garbage"));
+		assert_eq!(
+			freeze::get_freeze(&db, "paritytech", "substrate").unwrap(),
+			Some(freeze)
+		);
+		// records of other kinds are left alone
+		assert_eq!(
+			db.get("auto-update/paritytech/substrate/1")
+				.unwrap()
+				.unwrap(),
+			b"{}"
+		);
+
+		assert_eq!(migrate(&db).unwrap(), Report::default());
+	}
+}
@@ -0,0 +1,118 @@
+use serde::Deserialize;
+
+use crate::github::PullRequest;
+
+/// `mergeable_state`s of a PR waiting in GitHub's merge queue.
+const QUEUED_STATES: [&str; 2] = ["queued", "merge_queue"];
+
+/// What the bot does with a PR which GitHub's auto-merge or merge queue is to
+/// merge already.
+#[derive(Debug, Copy, Clone, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NativeQueuePolicy {
+	/// Refuse `bot merge`, and drop pending merges, so that the PR is not
+	/// merged twice.
+	#[default]
+	Refuse,
+	/// Merge all the same.
+	Proceed,
+}
+
+impl std::str::FromStr for NativeQueuePolicy {
+	type Err = String;
+
+	fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+		match s.trim() {
+			"refuse" => Ok(NativeQueuePolicy::Refuse),
+			"proceed" => Ok(NativeQueuePolicy::Proceed),
+			s => Err(format!("unknown native merge queue policy '{}'", s)),
+		}
+	}
+}
+
+/// Whether GitHub is to merge the PR by itself.
+pub fn is_natively_queued(pr: &PullRequest) -> bool {
+	pr.auto_merge.is_some()
+		|| pr
+			.mergeable_state
+			.as_deref()
+			.is_some_and(|state| QUEUED_STATES.contains(&state))
+}
+
+/// Whether the bot leaves merging the PR to GitHub.
+pub fn steps_aside(policy: NativeQueuePolicy, pr: &PullRequest) -> bool {
+	policy == NativeQueuePolicy::Refuse && is_natively_queued(pr)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// A PR as GitHub sends it, with `fields` set.
+	fn pr(fields: serde_json::Value) -> PullRequest {
+		let mut json = serde_json::to_value(PullRequest::default()).unwrap();
+		for (key, value) in fields.as_object().unwrap() {
+			json[key] = value.clone();
+		}
+		serde_json::from_value(json).unwrap()
+	}
+
+	fn auto_merge() -> serde_json::Value {
+		serde_json::json!({
+			"auto_merge": {
+				"enabled_by": serde_json::to_value(crate::github::User {
+					login: "gavofyork".to_string(),
+					..Default::default()
+				})
+				.unwrap(),
+				"merge_method": "squash",
+				"commit_title": "Bump tokio (#7000)",
+				"commit_message": null
+			}
+		})
+	}
+
+	#[test]
+	fn test_parse_auto_merge() {
+		let queued = pr(auto_merge());
+		let auto_merge = queued.auto_merge.unwrap();
+		assert_eq!(auto_merge.enabled_by.login, "gavofyork");
+		assert_eq!(auto_merge.merge_method, "squash");
+		assert_eq!(
+			pr(serde_json::json!({ "auto_merge": null })).auto_merge,
+			None
+		);
+		let state = pr(serde_json::json!({ "mergeable_state": "blocked" }));
+		assert_eq!(state.mergeable_state.as_deref(), Some("blocked"));
+	}
+
+	#[test]
+	fn test_is_natively_queued() {
+		assert!(is_natively_queued(&pr(auto_merge())));
+		assert!(is_natively_queued(&pr(
+			serde_json::json!({ "mergeable_state": "queued" })
+		)));
+		assert!(!is_natively_queued(&pr(
+			serde_json::json!({ "mergeable_state": "clean" })
+		)));
+		assert!(!is_natively_queued(&PullRequest::default()));
+	}
+
+	#[test]
+	fn test_steps_aside() {
+		let queued = pr(auto_merge());
+		let clean = pr(serde_json::json!({ "mergeable_state": "clean" }));
+		assert!(steps_aside(NativeQueuePolicy::Refuse, &queued));
+		assert!(!steps_aside(NativeQueuePolicy::Refuse, &clean));
+		assert!(!steps_aside(NativeQueuePolicy::Proceed, &queued));
+		assert!(!steps_aside(NativeQueuePolicy::Proceed, &clean));
+	}
+
+	#[test]
+	fn test_parse_policy() {
+		assert_eq!("refuse".parse(), Ok(NativeQueuePolicy::Refuse));
+		assert_eq!("proceed".parse(), Ok(NativeQueuePolicy::Proceed));
+		assert!("ignore".parse::<NativeQueuePolicy>().is_err());
+		assert_eq!(NativeQueuePolicy::default(), NativeQueuePolicy::Refuse);
+	}
+}
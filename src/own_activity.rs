@@ -0,0 +1,98 @@
+use rocksdb::{Direction, IteratorMode, DB};
+use snafu::ResultExt;
+
+use crate::{db, error::*, Result};
+
+const KEY_PREFIX: &str = "pushed-sha/";
+
+/// Seconds for which a commit the bot pushed is known as its own.
+pub const PUSHED_SHA_TTL: i64 = 600;
+
+/// What the contexts of the commit statuses the bot sets start with, eg.
+/// `processbot/merge-gate`.
+pub const CONTEXT_PREFIX: &str = "processbot/";
+
+fn key(sha: &str) -> String {
+	format!("{}{}", KEY_PREFIX, sha)
+}
+
+fn pushed_at(bytes: &[u8]) -> Option<i64> {
+	std::str::from_utf8(bytes).ok()?.parse().ok()
+}
+
+/// Whether a status is one the bot set itself.
+pub fn is_own_context(context: &str) -> bool {
+	context.starts_with(CONTEXT_PREFIX)
+}
+
+/// Whether `login` is the bot's, eg. the author of a comment.
+pub fn is_own_login(login: &str, bot_login: &str) -> bool {
+	!bot_login.is_empty() && login.eq_ignore_ascii_case(bot_login)
+}
+
+/// Remember that the bot pushed `sha` `now`, and forget the commits pushed
+/// longer ago than `PUSHED_SHA_TTL`.
+pub fn record_push(db: &DB, sha: &str, now: i64) -> Result<()> {
+	let expired = db
+		.iterator(IteratorMode::From(
+			KEY_PREFIX.as_bytes(),
+			Direction::Forward,
+		))
+		.take_while(|(k, _)| k.starts_with(KEY_PREFIX.as_bytes()))
+		.filter(|(_, v)| {
+			!matches!(pushed_at(v), Some(pushed_at) if now - pushed_at < PUSHED_SHA_TTL)
+		})
+		.map(|(k, _)| k)
+		.collect::<Vec<_>>();
+	db::atomically(db, |tx| {
+		for k in expired {
+			tx.delete(db::DEFAULT, &k)?;
+		}
+		tx.put(db::DEFAULT, key(sha).as_bytes(), now.to_string().as_bytes())
+	})
+}
+
+/// Whether the bot pushed `sha` within `PUSHED_SHA_TTL` seconds before `now`.
+pub fn recently_pushed(db: &DB, sha: &str, now: i64) -> Result<bool> {
+	Ok(db
+		.get(key(sha).as_bytes())
+		.context(Db)?
+		.and_then(|bytes| pushed_at(&bytes))
+		.is_some_and(|pushed_at| now - pushed_at < PUSHED_SHA_TTL))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::db::TempDb;
+
+	#[test]
+	fn test_recently_pushed() {
+		let db = TempDb::new("own-activity");
+		record_push(&db, "abc", 1000).unwrap();
+		assert!(recently_pushed(&db, "abc", 1000).unwrap());
+		assert!(recently_pushed(&db, "abc", 1000 + PUSHED_SHA_TTL - 1).unwrap());
+		assert!(!recently_pushed(&db, "abc", 1000 + PUSHED_SHA_TTL).unwrap());
+		assert!(!recently_pushed(&db, "def", 1000).unwrap());
+		// pushed again
+		record_push(&db, "abc", 2000).unwrap();
+		assert!(recently_pushed(&db, "abc", 2000 + PUSHED_SHA_TTL - 1).unwrap());
+		// expired pushes are forgotten on the next one
+		record_push(&db, "def", 2000 + PUSHED_SHA_TTL).unwrap();
+		assert!(db.get(key("abc").as_bytes()).unwrap().is_none());
+		assert!(db.get(key("def").as_bytes()).unwrap().is_some());
+	}
+
+	#[test]
+	fn test_is_own() {
+		assert!(is_own_context("processbot/merge-gate"));
+		assert!(!is_own_context("continuous-integration/gitlab-test"));
+		assert!(!is_own_context("parity-processbot/benchmarks"));
+		assert!(is_own_login(
+			"Parity-Processbot[bot]",
+			"parity-processbot[bot]"
+		));
+		assert!(!is_own_login("dependabot[bot]", "parity-processbot[bot]"));
+		assert!(!is_own_login("", ""));
+	}
+}
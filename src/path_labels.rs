@@ -0,0 +1,263 @@
+use rocksdb::DB;
+use serde::Deserialize;
+use snafu::ResultExt;
+
+use crate::{
+	codeowners,
+	error::*,
+	github::{Label, PullRequestFile},
+	Result,
+};
+
+const KEY_PREFIX: &str = "path-labels/";
+
+/// A label added to the PRs which change a file matching `pattern`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PathLabel {
+	/// gitignore-style, as in CODEOWNERS
+	pub pattern: String,
+	pub label: String,
+}
+
+/// The labels of the rules matching any of `files`, in the order of the
+/// rules.  Rules whose pattern cannot be understood are skipped.
+pub fn matching_labels(
+	rules: &[PathLabel],
+	files: &[PullRequestFile],
+) -> Vec<String> {
+	let mut labels = Vec::<String>::new();
+	for rule in rules {
+		let regex = match codeowners::glob_regex(&rule.pattern) {
+			Ok(regex) => regex,
+			Err(e) => {
+				log::warn!(
+					"Ignoring invalid path label pattern '{}': {}",
+					rule.pattern,
+					e
+				);
+				continue;
+			}
+		};
+		if !labels.contains(&rule.label)
+			&& files.iter().any(|file| regex.is_match(&file.filename))
+		{
+			labels.push(rule.label.clone());
+		}
+	}
+	labels
+}
+
+/// The labels of `wanted` to add to a PR: those it does not have, and which
+/// were neither added by the bot nor removed from it before, so that a label
+/// someone removed stays removed.
+pub fn labels_to_add(
+	wanted: &[String],
+	current: &[Label],
+	handled: &[String],
+) -> Vec<String> {
+	wanted
+		.iter()
+		.filter(|label| !current.iter().any(|current| &current.name == *label))
+		.filter(|label| !handled.contains(label))
+		.cloned()
+		.collect()
+}
+
+fn key(owner: &str, repo_name: &str, number: i64) -> String {
+	format!(
+		"{}{}/{}/{}",
+		KEY_PREFIX,
+		owner.to_lowercase(),
+		repo_name.to_lowercase(),
+		number
+	)
+}
+
+/// The labels the bot added to a PR, or which were removed from it.
+pub fn handled(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+) -> Result<Vec<String>> {
+	match db
+		.get(key(owner, repo_name, number).as_bytes())
+		.context(Db)?
+	{
+		Some(bytes) => serde_json::from_slice(&bytes).context(Json),
+		None => Ok(Vec::new()),
+	}
+}
+
+/// Note that `labels` were added to a PR by the bot, or removed from it.
+pub fn record(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+	labels: &[String],
+) -> Result<()> {
+	let mut handled = handled(db, owner, repo_name, number)?;
+	let before = handled.len();
+	for label in labels {
+		if !handled.contains(label) {
+			handled.push(label.clone());
+		}
+	}
+	if handled.len() == before {
+		return Ok(());
+	}
+	let bytes = serde_json::to_vec(&handled).context(Json)?;
+	db.put(key(owner, repo_name, number).as_bytes(), bytes)
+		.context(Db)
+}
+
+/// Forget a PR, eg. once it is closed.
+pub fn remove(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+) -> Result<()> {
+	db.delete(key(owner, repo_name, number).as_bytes())
+		.context(Db)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::db::TempDb;
+
+	fn rules() -> Vec<PathLabel> {
+		[
+			("frame/", "A-runtime"),
+			("bin/node/runtime/**", "A-runtime"),
+			("client/network/**/*.rs", "A-networking"),
+			("*.md", "A-docs"),
+		]
+		.iter()
+		.map(|(pattern, label)| PathLabel {
+			pattern: pattern.to_string(),
+			label: label.to_string(),
+		})
+		.collect()
+	}
+
+	fn files(names: &[&str]) -> Vec<PullRequestFile> {
+		names
+			.iter()
+			.map(|name| PullRequestFile {
+				filename: name.to_string(),
+				status: "modified".to_string(),
+				..PullRequestFile::default()
+			})
+			.collect()
+	}
+
+	fn labels(names: &[&str]) -> Vec<Label> {
+		names
+			.iter()
+			.map(|name| Label {
+				name: name.to_string(),
+				..Label::default()
+			})
+			.collect()
+	}
+
+	fn strings(names: &[&str]) -> Vec<String> {
+		names.iter().map(|name| name.to_string()).collect()
+	}
+
+	#[test]
+	fn test_matching_labels() {
+		let rules = rules();
+		assert_eq!(
+			matching_labels(
+				&rules,
+				&files(&[
+					"client/network/src/protocol/sync.rs",
+					"bin/node/runtime/src/lib.rs",
+					"frame/balances/src/lib.rs",
+					"docs/CONTRIBUTING.md",
+				])
+			),
+			vec!["A-runtime", "A-networking", "A-docs"]
+		);
+		assert_eq!(
+			matching_labels(&rules, &files(&["client/network/Cargo.toml"])),
+			Vec::<String>::new()
+		);
+		// not anchored, as the pattern has no slash but at the end
+		assert_eq!(
+			matching_labels(&rules, &files(&["bin/frame/lib.rs"])),
+			vec!["A-runtime"]
+		);
+		assert!(matching_labels(&rules, &[]).is_empty());
+		assert!(matching_labels(&[], &files(&["README.md"])).is_empty());
+	}
+
+	#[test]
+	fn test_labels_to_add() {
+		let wanted = strings(&["A-runtime", "A-networking", "A-docs"]);
+		assert_eq!(labels_to_add(&wanted, &[], &[]), wanted);
+		// applied by a human
+		assert_eq!(
+			labels_to_add(&wanted, &labels(&["A-networking"]), &[]),
+			strings(&["A-runtime", "A-docs"])
+		);
+		// added by the bot, or removed by a human, before
+		assert_eq!(
+			labels_to_add(
+				&wanted,
+				&labels(&["A-runtime"]),
+				&strings(&["A-runtime", "A-docs"])
+			),
+			strings(&["A-networking"])
+		);
+	}
+
+	#[test]
+	fn test_idempotency() {
+		let db = TempDb::new("path-labels");
+		let wanted = strings(&["A-runtime", "A-networking"]);
+
+		// opened
+		let added = labels_to_add(
+			&wanted,
+			&[],
+			&handled(&db, "paritytech", "substrate", 1).unwrap(),
+		);
+		assert_eq!(added, wanted);
+		record(&db, "paritytech", "substrate", 1, &added).unwrap();
+
+		// someone removes a label, and a commit is pushed
+		record(&db, "paritytech", "substrate", 1, &strings(&["A-runtime"]))
+			.unwrap();
+		let handled_now = handled(&db, "ParityTech", "Substrate", 1).unwrap();
+		assert_eq!(handled_now, wanted);
+		assert!(labels_to_add(
+			&wanted,
+			&labels(&["A-networking"]),
+			&handled_now
+		)
+		.is_empty());
+		// a PR changing more gets only the new label
+		assert_eq!(
+			labels_to_add(
+				&strings(&["A-runtime", "A-networking", "A-docs"]),
+				&labels(&["A-networking"]),
+				&handled_now
+			),
+			strings(&["A-docs"])
+		);
+		assert!(handled(&db, "paritytech", "substrate", 2)
+			.unwrap()
+			.is_empty());
+
+		remove(&db, "paritytech", "substrate", 1).unwrap();
+		assert!(handled(&db, "paritytech", "substrate", 1)
+			.unwrap()
+			.is_empty());
+	}
+}
@@ -0,0 +1,229 @@
+use serde::Serialize;
+
+use crate::{error::*, github_bot::GithubBot, http::GithubApiError, Result};
+
+/// What the bot does through the GitHub API that needs a permission its
+/// GitHub App can be installed without.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+	/// eg. to count the approvals of the review teams
+	ReadTeams,
+	/// to know whether the checks of a PR pass before merging it
+	ReadCheckRuns,
+	/// eg. to let members of the organization ask for release notes
+	ReadOrgMembership,
+}
+
+impl Capability {
+	pub const ALL: [Capability; 3] = [
+		Capability::ReadTeams,
+		Capability::ReadCheckRuns,
+		Capability::ReadOrgMembership,
+	];
+
+	/// The read permission of the GitHub App granting it, as named in its
+	/// settings.
+	pub fn permission(self) -> &'static str {
+		match self {
+			Capability::ReadTeams | Capability::ReadOrgMembership => "Members",
+			Capability::ReadCheckRuns => "Checks",
+		}
+	}
+
+	/// What the bot cannot do without it.
+	pub fn purpose(self) -> &'static str {
+		match self {
+			Capability::ReadTeams => "read the members of teams",
+			Capability::ReadCheckRuns => "read the check runs of commits",
+			Capability::ReadOrgMembership => {
+				"check who is a member of the organization"
+			}
+		}
+	}
+}
+
+/// The capabilities the startup probe found missing.  All are assumed to be
+/// available until it has run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Capabilities {
+	missing: Vec<Capability>,
+}
+
+impl Capabilities {
+	pub fn without(missing: &[Capability]) -> Self {
+		Self {
+			missing: missing.to_vec(),
+		}
+	}
+
+	pub fn has(&self, capability: Capability) -> bool {
+		!self.missing.contains(&capability)
+	}
+
+	/// Fails, saying which permission to grant, if `capability` is missing.
+	pub fn require(&self, capability: Capability) -> Result<()> {
+		if self.has(capability) {
+			Ok(())
+		} else {
+			Err(Error::MissingPermission {
+				permission: capability.permission().to_string(),
+				purpose: capability.purpose().to_string(),
+			})
+		}
+	}
+
+	/// The missing capabilities, for `/health`.
+	pub fn payload(&self) -> serde_json::Value {
+		serde_json::Value::Array(
+			self.missing
+				.iter()
+				.map(|capability| {
+					serde_json::json!({
+						"capability": capability,
+						"permission": capability.permission(),
+					})
+				})
+				.collect(),
+		)
+	}
+}
+
+/// Whether GitHub refused a request because the token lacks a permission,
+/// rather than eg. because what was asked for does not exist.
+fn is_denied(e: &Error) -> bool {
+	matches!(
+		e,
+		Error::Response {
+			error: GithubApiError::Forbidden(_),
+			..
+		}
+	)
+}
+
+/// Check runs on the default branch of the first repository the GitHub App is
+/// installed on; `None` if it is installed on none.
+async fn probe_check_runs(github_bot: &GithubBot) -> Option<Result<()>> {
+	let repositories = match github_bot.installation_repositories().await {
+		Ok(installed) => installed.repositories,
+		Err(e) => return Some(Err(e)),
+	};
+	let repository = repositories.first()?;
+	Some(
+		github_bot
+			.check_runs(&repository.owner.login, &repository.name, "HEAD")
+			.await
+			.map(|_| ()),
+	)
+}
+
+/// Try each capability out, to find those the permissions of the GitHub App
+/// do not allow: read `team` of `org`, list check runs, and check whether
+/// `bot_login` is a member of `org`.  Only refused requests count, as others
+/// fail eg. for a team which does not exist or a network error, which say
+/// nothing of the permissions.
+pub async fn probe(
+	github_bot: &GithubBot,
+	org: &str,
+	team: Option<&str>,
+	bot_login: &str,
+) -> Capabilities {
+	let mut missing = Vec::new();
+	for &capability in Capability::ALL.iter() {
+		let result = match capability {
+			Capability::ReadTeams => match team {
+				Some(team) => {
+					Some(github_bot.team(org, team).await.map(|_| ()))
+				}
+				None => None,
+			},
+			Capability::ReadCheckRuns => probe_check_runs(github_bot).await,
+			Capability::ReadOrgMembership => {
+				Some(github_bot.org_member(org, bot_login).await.map(|_| ()))
+			}
+		};
+		match result {
+			None => log::info!(
+				"Nothing to try to {} with; assuming the GitHub App can",
+				capability.purpose()
+			),
+			Some(Ok(())) => {
+				log::info!("The GitHub App can {}", capability.purpose())
+			}
+			Some(Err(e)) if is_denied(&e) => {
+				log::warn!(
+					"The GitHub App lacks the {} read permission, needed to {}: {}",
+					capability.permission(),
+					capability.purpose(),
+					e
+				);
+				missing.push(capability);
+			}
+			Some(Err(e)) => log::warn!(
+				"Could not tell whether the GitHub App can {}: {}",
+				capability.purpose(),
+				e
+			),
+		}
+	}
+	Capabilities { missing }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::http::ErrorBody;
+
+	#[test]
+	fn test_require() {
+		let capabilities = Capabilities::default();
+		for &capability in Capability::ALL.iter() {
+			assert!(capabilities.require(capability).is_ok());
+		}
+		assert_eq!(capabilities.payload(), serde_json::json!([]));
+
+		let capabilities = Capabilities::without(&[Capability::ReadTeams]);
+		assert!(capabilities.require(Capability::ReadCheckRuns).is_ok());
+		match capabilities.require(Capability::ReadTeams) {
+			Err(Error::MissingPermission {
+				permission,
+				purpose,
+			}) => {
+				assert_eq!(permission, "Members");
+				assert_eq!(purpose, "read the members of teams");
+			}
+			result => panic!("unexpected result {:?}", result),
+		}
+		assert_eq!(
+			capabilities.payload(),
+			serde_json::json!([
+				{ "capability": "read_teams", "permission": "Members" },
+			])
+		);
+	}
+
+	#[test]
+	fn test_is_denied() {
+		let response = |status, error| Error::Response { status, error };
+		assert!(is_denied(&response(
+			reqwest::StatusCode::FORBIDDEN,
+			GithubApiError::Forbidden(ErrorBody {
+				message: "Resource not accessible by integration".to_string(),
+				..Default::default()
+			}),
+		)));
+		// says nothing of the permissions
+		assert!(!is_denied(&response(
+			reqwest::StatusCode::FORBIDDEN,
+			GithubApiError::RateLimited {
+				body: ErrorBody::default(),
+				retry_after: None,
+			},
+		)));
+		assert!(!is_denied(&response(
+			reqwest::StatusCode::NOT_FOUND,
+			GithubApiError::NotFound(ErrorBody::default()),
+		)));
+		assert!(!is_denied(&Error::MissingData {}));
+	}
+}
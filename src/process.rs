@@ -1,9 +1,135 @@
-use crate::{error, github, github_bot::GithubBot, process, Result};
+use crate::{
+	cache::RepoCache, error, github, github_bot::GithubBot, process, Result,
+};
+use chrono::Utc;
+use itertools::Itertools;
+use regex::Regex;
+use rocksdb::DB;
 use serde::Deserialize;
 use snafu::ResultExt;
+use std::collections::HashMap;
+
+/// Formats a process file can be written in, in order of precedence when a
+/// repository has more than one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProcessFormat {
+	Toml,
+	Yaml,
+	Json,
+}
+
+/// A TOML process file lists its entries as `[[project]]` tables.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TomlProcess {
+	#[serde(default)]
+	project: Vec<ProcessEntry>,
+}
+
+impl ProcessFormat {
+	pub const ALL: [ProcessFormat; 3] = [
+		ProcessFormat::Toml,
+		ProcessFormat::Yaml,
+		ProcessFormat::Json,
+	];
+
+	pub fn file_name(self) -> &'static str {
+		match self {
+			ProcessFormat::Toml => "Process.toml",
+			ProcessFormat::Yaml => "Process.yaml",
+			ProcessFormat::Json => "Process.json",
+		}
+	}
+
+	/// The format of a process file at the root of a repository.
+	pub fn from_path(path: &str) -> Option<Self> {
+		Self::ALL
+			.iter()
+			.copied()
+			.find(|format| format.file_name() == path)
+	}
+
+	pub fn parse(self, contents: &str) -> Result<Vec<ProcessEntry>> {
+		match self {
+			ProcessFormat::Toml => toml::from_str::<TomlProcess>(contents)
+				.map(|process| process.project)
+				.context(error::Toml),
+			ProcessFormat::Yaml => {
+				serde_yaml::from_str(contents).context(error::Yaml)
+			}
+			ProcessFormat::Json => {
+				serde_json::from_str(contents).context(error::Json)
+			}
+		}
+	}
+
+	/// Parse a process file into a JSON value, without interpreting its
+	/// entries, for `validate`.
+	fn parse_value(
+		self,
+		contents: &str,
+	) -> std::result::Result<serde_json::Value, String> {
+		match self {
+			ProcessFormat::Toml => {
+				let mut table = toml::from_str::<toml::value::Table>(contents)
+					.map_err(|e| e.to_string())?;
+				let project = table
+					.remove("project")
+					.unwrap_or_else(|| toml::Value::Array(vec![]));
+				if let Some(key) = table.keys().next() {
+					return Err(format!(
+						"unknown top-level key `{}`; entries belong in `[[project]]` tables",
+						key
+					));
+				}
+				serde_json::to_value(project).map_err(|e| e.to_string())
+			}
+			ProcessFormat::Yaml => {
+				serde_yaml::from_str(contents).map_err(|e| e.to_string())
+			}
+			ProcessFormat::Json => {
+				serde_json::from_str(contents).map_err(|e| e.to_string())
+			}
+		}
+	}
+}
+
+impl std::fmt::Display for ProcessFormat {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			ProcessFormat::Toml => write!(f, "TOML"),
+			ProcessFormat::Yaml => write!(f, "YAML"),
+			ProcessFormat::Json => write!(f, "JSON"),
+		}
+	}
+}
+
+/// Minimum time between two Matrix pings about missing approval on a PR.
+pub const APPROVAL_PING_INTERVAL_SECS: i64 = 12 * 60 * 60;
+
+const APPROVAL_PING_KEY_PREFIX: &str = "approval-ping/";
+
+const REVIEW_REQUEST_KEY_PREFIX: &str = "review-request/";
+
+/// Parsed process file entries per repository, kept for a limited time to
+/// avoid fetching the file for every merge request.
+pub type ProcessCache = RepoCache<Vec<ProcessEntry>>;
+
+/// Check if a process file is among a list of changed files.
+pub fn touches_process_file<'a>(
+	mut files: impl Iterator<Item = &'a str>,
+) -> bool {
+	files.any(|file| ProcessFormat::from_path(file).is_some())
+}
 
 #[derive(Clone, Debug)]
-pub struct CombinedProcessInfo(Vec<ProcessInfo>);
+pub struct CombinedProcessInfo(Vec<ProcessEntry>);
+
+impl From<Vec<ProcessEntry>> for CombinedProcessInfo {
+	fn from(entries: Vec<ProcessEntry>) -> Self {
+		Self(entries)
+	}
+}
 
 impl CombinedProcessInfo {
 	pub fn len(&self) -> usize {
@@ -14,23 +140,27 @@ impl CombinedProcessInfo {
 		self.len() == 0
 	}
 
-	pub fn iter(&self) -> impl Iterator<Item = &ProcessInfo> {
+	pub fn iter(&self) -> impl Iterator<Item = &ProcessEntry> {
 		self.0.iter()
 	}
 
-	pub fn get(&self, project_name: &str) -> Option<&ProcessInfo> {
+	pub fn get(&self, project_name: &str) -> Option<&ProcessEntry> {
 		self.0.iter().find(|x| x.project_name == project_name)
 	}
 
+	/// Iterate over everyone whose approval satisfies the project owner
+	/// requirement: owners and delegated reviewers of every project.
 	pub fn iter_owners(&self) -> impl Iterator<Item = &String> {
-		self.0.iter().map(|p| p.owner_or_delegate())
+		self.0.iter().flat_map(|p| p.iter_approvers())
 	}
 
 	pub fn iter_room_ids(&self) -> impl Iterator<Item = &String> {
 		self.0.iter().map(|p| &p.matrix_room_id)
 	}
 
-	pub fn is_owner(&self, login: &str) -> bool {
+	/// Checks if the login is an owner or delegated reviewer of any of the
+	/// projects.
+	pub fn is_owner_or_delegate(&self, login: &str) -> bool {
 		self.iter_owners().any(|p| p == login)
 	}
 
@@ -41,45 +171,86 @@ impl CombinedProcessInfo {
 	}
 
 	pub fn is_special(&self, login: &str) -> bool {
-		self.is_owner(login) || self.is_whitelisted(login)
+		self.is_owner_or_delegate(login) || self.is_whitelisted(login)
+	}
+
+	/// The owners and delegated reviewers to ask to review a PR by `author`,
+	/// each once and never the author.
+	pub fn reviewers(&self, author: &str) -> Vec<String> {
+		self.iter_owners()
+			.filter(|login| !login.eq_ignore_ascii_case(author))
+			.unique_by(|login| login.to_lowercase())
+			.cloned()
+			.collect()
 	}
 }
 
 #[derive(Clone, Debug, Deserialize, PartialEq)]
-pub struct ProcessInfo {
+pub struct ProcessEntry {
 	pub project_name: String,
-	pub owner: String,
+	/// A single login or a list of co-owners.
+	#[serde(rename = "owner", deserialize_with = "one_or_many")]
+	pub owners: Vec<String>,
 	pub delegated_reviewer: Option<String>,
 	#[serde(default)]
+	pub delegated_reviewers: Vec<String>,
+	#[serde(default)]
 	pub whitelist: Vec<String>,
+	/// PRs carrying any of these labels belong to the project, whether or not
+	/// they are attached to a project column.
+	#[serde(default)]
+	pub matching_labels: Vec<String>,
 	pub matrix_room_id: String,
 	pub backlog: Option<String>,
 }
 
-impl ProcessInfo {
-	pub fn owner_or_delegate(&self) -> &String {
-		self.delegated_reviewer.as_ref().unwrap_or(&self.owner)
+/// Accept either `"login"` or `["login", ...]`.
+fn one_or_many<'de, D>(
+	deserializer: D,
+) -> std::result::Result<Vec<String>, D::Error>
+where
+	D: serde::Deserializer<'de>,
+{
+	#[derive(Deserialize)]
+	#[serde(untagged)]
+	enum OneOrMany {
+		One(String),
+		Many(Vec<String>),
 	}
 
-	/// Checks if the owner of the project matches the login given.
+	Ok(match OneOrMany::deserialize(deserializer)? {
+		OneOrMany::One(login) => vec![login],
+		OneOrMany::Many(logins) => logins,
+	})
+}
+
+impl ProcessEntry {
+	/// Iterate over the delegated reviewers of the project.
+	pub fn iter_delegates(&self) -> impl Iterator<Item = &String> {
+		self.delegated_reviewer
+			.iter()
+			.chain(self.delegated_reviewers.iter())
+	}
+
+	/// Iterate over the owners and delegated reviewers of the project.
+	pub fn iter_approvers(&self) -> impl Iterator<Item = &String> {
+		self.owners.iter().chain(self.iter_delegates())
+	}
+
+	/// Checks if an owner or delegated reviewer of the project matches the
+	/// login given.
 	pub fn is_owner_or_delegate(&self, login: &str) -> bool {
-		&self.owner == login
-			|| self
-				.delegated_reviewer
-				.as_ref()
-				.map_or(false, |delegate| delegate == login)
+		self.iter_approvers().any(|approver| approver == login)
 	}
 
-	/// Checks if the owner of the project matches the login given.
+	/// Checks if an owner of the project matches the login given.
 	pub fn is_owner(&self, login: &str) -> bool {
-		&self.owner == login
+		self.owners.iter().any(|owner| owner == login)
 	}
 
-	/// Checks if the delegated reviewer matches the login given.
+	/// Checks if a delegated reviewer matches the login given.
 	pub fn is_delegated_reviewer(&self, login: &str) -> bool {
-		self.delegated_reviewer
-			.as_deref()
-			.map_or(false, |reviewer| reviewer == login)
+		self.iter_delegates().any(|reviewer| reviewer == login)
 	}
 
 	/// Checks that the login is contained within the whitelist.
@@ -94,18 +265,243 @@ impl ProcessInfo {
 	}
 }
 
+/// Whether the owners of a PR may be pinged again about missing approval.
+pub fn approval_ping_due(last_ping: Option<i64>, now: i64) -> bool {
+	match last_ping {
+		Some(last_ping) => now - last_ping >= APPROVAL_PING_INTERVAL_SECS,
+		None => true,
+	}
+}
+
+fn approval_ping_key(owner: &str, repo_name: &str, number: i64) -> String {
+	format!(
+		"{}{}/{}/{}",
+		APPROVAL_PING_KEY_PREFIX, owner, repo_name, number
+	)
+}
+
+/// When the owners of a PR were last pinged about missing approval.
+pub fn last_approval_ping(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+) -> Result<Option<i64>> {
+	let key = approval_ping_key(owner, repo_name, number);
+	match db.get(key.as_bytes()).context(error::Db)? {
+		Some(bytes) => bincode::deserialize(&bytes)
+			.context(error::Bincode)
+			.map(Some),
+		None => Ok(None),
+	}
+}
+
+pub fn record_approval_ping(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+	now: i64,
+) -> Result<()> {
+	let key = approval_ping_key(owner, repo_name, number);
+	let bytes = bincode::serialize(&now).context(error::Bincode)?;
+	db.put(key.as_bytes(), bytes).context(error::Db)
+}
+
+fn review_request_key(owner: &str, repo_name: &str, number: i64) -> String {
+	format!(
+		"{}{}/{}/{}",
+		REVIEW_REQUEST_KEY_PREFIX, owner, repo_name, number
+	)
+}
+
+/// Whether the process owners of a PR were asked for their review already.
+pub fn review_requested(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+) -> Result<bool> {
+	let key = review_request_key(owner, repo_name, number);
+	db.get(key.as_bytes())
+		.context(error::Db)
+		.map(|bytes| bytes.is_some())
+}
+
+pub fn record_review_request(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+	now: i64,
+) -> Result<()> {
+	let key = review_request_key(owner, repo_name, number);
+	let bytes = bincode::serialize(&now).context(error::Bincode)?;
+	db.put(key.as_bytes(), bytes).context(error::Db)
+}
+
+/// Forget a PR's review request, eg. once it is closed.
+pub fn remove_review_request(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+) -> Result<()> {
+	let key = review_request_key(owner, repo_name, number);
+	db.delete(key.as_bytes()).context(error::Db)
+}
+
+/// Matrix message asking the owners of a project to review a PR.
+pub fn approval_ping_message(
+	pr_url: &str,
+	requested_by: &str,
+	process: &ProcessEntry,
+) -> String {
+	format!(
+		"{pr_url} in project '{project}' needs approval from one of {owners} before it can be merged (requested by {requested_by}).",
+		pr_url = pr_url,
+		project = process.project_name,
+		owners = process.iter_approvers().join(", "),
+		requested_by = requested_by,
+	)
+}
+
+/// Return the `Process.json` entries of a repository, from the cache if
+/// possible.  If fetching the file fails, expired entries are used rather than
+/// failing outright.
+pub async fn process_entries(
+	github_bot: &GithubBot,
+	cache: &ProcessCache,
+	owner: &str,
+	repo_name: &str,
+) -> Result<Vec<ProcessEntry>> {
+	let now = Utc::now().timestamp();
+	if let Some(process) = cache.get(owner, repo_name, now) {
+		return Ok(process);
+	}
+	// get process file from master
+	match fetch_process_file(github_bot, owner, repo_name).await {
+		Ok(process) => {
+			cache.insert(owner, repo_name, process.clone(), now);
+			Ok(process)
+		}
+		Err(e) => match cache.get_stale(owner, repo_name) {
+			Some(process) => {
+				log::warn!(
+					"Error fetching process file for {}/{}; using cached entries: {}",
+					owner,
+					repo_name,
+					e
+				);
+				Ok(process)
+			}
+			None => Err(e),
+		},
+	}
+}
+
+/// How the process entry of a PR is determined.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ProcessMatching {
+	/// Match by the project column the PR is attached to, falling back to its
+	/// labels.
+	#[default]
+	ColumnsThenLabels,
+	/// Match by the project column the PR is attached to.
+	Columns,
+	/// Match by the labels of the PR.
+	Labels,
+}
+
+impl std::str::FromStr for ProcessMatching {
+	type Err = String;
+
+	fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+		match s.trim() {
+			"columns-then-labels" => Ok(ProcessMatching::ColumnsThenLabels),
+			"columns" => Ok(ProcessMatching::Columns),
+			"labels" => Ok(ProcessMatching::Labels),
+			s => Err(format!("unknown process matching mode '{}'", s)),
+		}
+	}
+}
+
+/// Return the first process entry declaring one of the labels of a PR.
+fn process_matching_labels(
+	processes: &[ProcessEntry],
+	labels: &[github::Label],
+) -> Option<ProcessEntry> {
+	let mut matching = processes.iter().filter(|proc| {
+		labels
+			.iter()
+			.any(|label| proc.matching_labels.contains(&label.name))
+	});
+	let first = matching.next()?;
+	let others = matching.map(|proc| &proc.project_name).join("', '");
+	if !others.is_empty() {
+		log::warn!(
+			"Labels match several process entries; using '{}' rather than '{}'",
+			first.project_name,
+			others
+		);
+	}
+	Some(first.clone())
+}
+
 pub async fn get_process(
 	github_bot: &GithubBot,
+	cache: &ProcessCache,
 	owner: &str,
 	repo_name: &str,
 	issue_number: i64,
+	labels: &[github::Label],
+	matching: ProcessMatching,
 ) -> Result<CombinedProcessInfo> {
-	// get Process file from master
-	let process = github_bot
-		.contents(owner, repo_name, "Process.json", "master")
+	let process = process_entries(github_bot, cache, owner, repo_name).await?;
+
+	if matching != ProcessMatching::Labels {
+		match process_matching_columns(
+			github_bot,
+			owner,
+			repo_name,
+			issue_number,
+			process.clone(),
+		)
 		.await
-		.and_then(process::process_from_contents)?;
+		{
+			Ok(by_columns)
+				if !by_columns.is_empty()
+					|| matching == ProcessMatching::Columns =>
+			{
+				return Ok(by_columns)
+			}
+			Ok(_) => {}
+			Err(e) if matching == ProcessMatching::Columns => return Err(e),
+			// eg. the repository has no classic projects any more
+			Err(e) => log::warn!(
+				"Error matching #{} in {}/{} by project column; trying labels: {}",
+				issue_number,
+				owner,
+				repo_name,
+				e
+			),
+		}
+	}
+
+	Ok(CombinedProcessInfo(
+		process_matching_labels(&process, labels)
+			.into_iter()
+			.collect(),
+	))
+}
 
+async fn process_matching_columns(
+	github_bot: &GithubBot,
+	owner: &str,
+	repo_name: &str,
+	issue_number: i64,
+	process: Vec<ProcessEntry>,
+) -> Result<CombinedProcessInfo> {
 	// repos with no projects can have no valid process info
 	let projects = github_bot.projects(owner, repo_name).await?;
 
@@ -124,7 +520,7 @@ pub async fn get_process(
 			}
 			keep
 		})
-		.collect::<Vec<ProcessInfo>>();
+		.collect::<Vec<ProcessEntry>>();
 
 	combined_process_info(
 		github_bot,
@@ -137,13 +533,198 @@ pub async fn get_process(
 	.await
 }
 
-fn process_from_contents(c: github::Contents) -> Result<Vec<ProcessInfo>> {
+/// Decode the text of a file returned by the contents API.
+pub fn decode_contents(c: &github::Contents) -> Result<String> {
 	base64::decode(&c.content.replace("\n", ""))
 		.context(error::Base64)
-		.and_then(|b| {
-			let s = String::from_utf8(b).context(error::Utf8)?;
-			serde_json::from_str(&s).context(error::Json)
-		})
+		.and_then(|b| String::from_utf8(b).context(error::Utf8))
+}
+
+/// Fetch and parse the process file of a repository, in whichever format it
+/// is written.  If there is none, the error for the missing `Process.json` is
+/// returned.
+async fn fetch_process_file(
+	github_bot: &GithubBot,
+	owner: &str,
+	repo_name: &str,
+) -> Result<Vec<ProcessEntry>> {
+	let default_branch = github_bot.default_branch(owner, repo_name).await?;
+	let mut found = Vec::new();
+	let mut not_found = None;
+	for format in ProcessFormat::ALL.iter().copied() {
+		match github_bot
+			.contents(owner, repo_name, format.file_name(), &default_branch)
+			.await
+		{
+			Ok(contents) => found.push((format, contents)),
+			Err(e) if e.is_not_found() => not_found = Some(e),
+			Err(e) => return Err(e),
+		}
+	}
+	if found.len() > 1 {
+		log::warn!(
+			"{}/{} has several process files ({}); using {}",
+			owner,
+			repo_name,
+			found
+				.iter()
+				.map(|(format, _)| format.file_name())
+				.join(", "),
+			found[0].0.file_name()
+		);
+	}
+	match found.into_iter().next() {
+		Some((format, contents)) => {
+			decode_contents(&contents).and_then(|s| format.parse(&s))
+		}
+		None => Err(not_found.expect("one format is always tried")),
+	}
+}
+
+const PROCESS_FIELDS: &[&str] = &[
+	"project_name",
+	"owner",
+	"delegated_reviewer",
+	"delegated_reviewers",
+	"whitelist",
+	"matching_labels",
+	"matrix_room_id",
+	"backlog",
+];
+
+/// Check a proposed process file for problems that would otherwise only
+/// surface when a merge is attempted.  `projects` maps the name of each
+/// project in the repository to the names of its columns.  Returns a
+/// description of every problem found; an empty list means the file is valid.
+pub fn validate(
+	contents: &str,
+	format: ProcessFormat,
+	projects: &HashMap<String, Vec<String>>,
+) -> Vec<String> {
+	let entries = match format.parse_value(contents) {
+		Ok(serde_json::Value::Array(entries)) => entries,
+		Ok(_) => {
+			return vec!["The file must contain a list of projects.".to_string()]
+		}
+		Err(e) => {
+			return vec![format!("The file is not valid {}: {}", format, e)]
+		}
+	};
+
+	let login = Regex::new(r"^[[:alnum:]](?:[[:alnum:]]|-[[:alnum:]]){0,38}$")
+		.expect("login regex");
+	let room_id =
+		Regex::new(r"^![^\s:]+:[^\s:]+(:[0-9]+)?$").expect("room id regex");
+
+	let mut problems = Vec::new();
+	let mut seen = HashMap::new();
+	for (i, entry) in entries.iter().enumerate() {
+		let fields = match entry.as_object() {
+			Some(fields) => fields,
+			None => {
+				problems.push(format!("Entry {} is not an object.", i));
+				continue;
+			}
+		};
+		let name = match fields.get("project_name") {
+			Some(serde_json::Value::String(name)) => name.clone(),
+			_ => format!("entry {}", i),
+		};
+
+		for key in fields.keys() {
+			if !PROCESS_FIELDS.contains(&key.as_str()) {
+				problems.push(format!("'{}': unknown field `{}`.", name, key));
+			}
+		}
+		for key in &["project_name", "owner", "matrix_room_id"] {
+			if !fields.contains_key(*key) {
+				problems.push(format!(
+					"'{}': missing required field `{}`.",
+					name, key
+				));
+			}
+		}
+
+		let info = match serde_json::from_value::<ProcessEntry>(entry.clone()) {
+			Ok(info) => info,
+			Err(e) => {
+				// missing fields have been reported above
+				if !e.to_string().starts_with("missing field") {
+					problems.push(format!("'{}': {}.", name, e));
+				}
+				continue;
+			}
+		};
+
+		if info.project_name.trim().is_empty() {
+			problems.push(format!("Entry {} has an empty `project_name`.", i));
+		} else if let Some(first) = seen.insert(info.project_name.clone(), i) {
+			problems.push(format!(
+				"'{}': project name is used by entries {} and {}.",
+				name, first, i
+			));
+		}
+		if info.owners.is_empty() {
+			problems.push(format!("'{}': `owner` lists nobody.", name));
+		}
+		for user in info
+			.iter_approvers()
+			.chain(info.whitelist.iter())
+			.filter(|user| !login.is_match(user))
+		{
+			problems.push(format!(
+				"'{}': `{}` is not a valid GitHub login.",
+				name, user
+			));
+		}
+		if !room_id.is_match(&info.matrix_room_id) {
+			problems.push(format!(
+				"'{}': `{}` is not a Matrix room id (expected `!id:server`).",
+				name, info.matrix_room_id
+			));
+		}
+		match projects.get(&info.project_name) {
+			// entries matched by label need no project
+			None if !info.matching_labels.is_empty() => {}
+			None => problems.push(format!(
+				"'{}': no project with this name exists in the repository.",
+				name
+			)),
+			Some(columns) => {
+				if let Some(backlog) = info.backlog.as_ref().filter(|backlog| {
+					!columns
+						.iter()
+						.any(|c| c.to_lowercase() == backlog.to_lowercase())
+				}) {
+					problems.push(format!(
+						"'{}': the project has no column named '{}'.",
+						name, backlog
+					));
+				}
+			}
+		}
+	}
+	problems
+}
+
+/// Collect the names of the repository's projects and their columns, for
+/// `validate`.
+pub async fn project_columns(
+	github_bot: &GithubBot,
+	owner: &str,
+	repo_name: &str,
+) -> Result<HashMap<String, Vec<String>>> {
+	let mut columns = HashMap::new();
+	for project in github_bot.projects(owner, repo_name).await? {
+		let names = github_bot
+			.project_columns(&project)
+			.await?
+			.into_iter()
+			.filter_map(|column| column.name)
+			.collect();
+		columns.insert(project.name, names);
+	}
+	Ok(columns)
 }
 
 /// Return a CombinedProcessInfo struct representing together each process entry that matches a
@@ -154,18 +735,18 @@ async fn combined_process_info(
 	repo_name: &str,
 	number: i64,
 	projects: &[github::Project],
-	processes: &[process::ProcessInfo],
+	processes: &[process::ProcessEntry],
 ) -> Result<CombinedProcessInfo> {
 	/// Return process entries matching the given projects, in the order of the projects.
 	fn process_matching_projects(
-		processes: &[ProcessInfo],
+		processes: &[ProcessEntry],
 		projects: &[github::Project],
-	) -> Vec<ProcessInfo> {
+	) -> Vec<ProcessEntry> {
 		/// Return the process entry matching a given project in the repo.
 		fn process_matching_project<'a>(
-			processes: &'a [ProcessInfo],
+			processes: &'a [ProcessEntry],
 			project: &github::Project,
-		) -> Option<&'a ProcessInfo> {
+		) -> Option<&'a ProcessEntry> {
 			processes
 				.iter()
 				.find(|proc| project.name == proc.project_name)
@@ -208,23 +789,660 @@ async fn combined_process_info(
 #[cfg(test)]
 mod tests {
 	use super::*;
+	use crate::db::TempDb;
 
 	#[test]
 	fn test_process_json() {
-		let proc = serde_json::from_str::<Vec<ProcessInfo>>(include_str!(
+		let proc = serde_json::from_str::<Vec<ProcessEntry>>(include_str!(
 			"../Process.json"
 		))
 		.expect("parse json");
 		assert_eq!(
 			proc,
-			vec![ProcessInfo {
+			vec![ProcessEntry {
 				project_name: format!("parity-processbot"),
-				owner: format!("sjeohp"),
+				owners: vec![format!("sjeohp")],
 				delegated_reviewer: None,
+				delegated_reviewers: vec![],
 				whitelist: vec![],
+				matching_labels: vec![],
 				matrix_room_id: format!("!VDLSjvBpNqqgyMDIBO:matrix.parity.io"),
 				backlog: None,
 			},]
 		);
 	}
+
+	const PROCESS_TOML: &str = r#"
+# comments are allowed
+[[project]]
+project_name = "Networking"
+owner = "tomaka"
+matrix_room_id = "!vUADSGcyXmxhKLeDsW:matrix.parity.io"
+
+[[project]]
+project_name = "Runtime"
+owner = ["gavofyork", "shawntabrizi"]
+delegated_reviewers = ["kianenigma"]
+matching_labels = ["B1-runtime"]
+matrix_room_id = "!yBKstWVBkwzUkPslsp:matrix.parity.io"
+backlog = "Backlog"
+"#;
+
+	const PROCESS_YAML: &str = r#"
+# comments are allowed
+- project_name: Networking
+  owner: tomaka
+  matrix_room_id: "!vUADSGcyXmxhKLeDsW:matrix.parity.io"
+- project_name: Runtime
+  owner:
+    - gavofyork
+    - shawntabrizi
+  delegated_reviewers: [kianenigma]
+  matching_labels: [B1-runtime]
+  matrix_room_id: "!yBKstWVBkwzUkPslsp:matrix.parity.io"
+  backlog: Backlog
+"#;
+
+	const PROCESS_JSON: &str = r#"[
+	{
+		"project_name": "Networking",
+		"owner": "tomaka",
+		"matrix_room_id": "!vUADSGcyXmxhKLeDsW:matrix.parity.io"
+	},
+	{
+		"project_name": "Runtime",
+		"owner": ["gavofyork", "shawntabrizi"],
+		"delegated_reviewers": ["kianenigma"],
+		"matching_labels": ["B1-runtime"],
+		"matrix_room_id": "!yBKstWVBkwzUkPslsp:matrix.parity.io",
+		"backlog": "Backlog"
+	}
+]"#;
+
+	#[test]
+	fn test_process_formats() {
+		let json = ProcessFormat::Json.parse(PROCESS_JSON).expect("json");
+		assert_eq!(json.len(), 2);
+		assert_eq!(json[1].owners, vec!["gavofyork", "shawntabrizi"]);
+		assert_eq!(json[1].backlog.as_deref(), Some("Backlog"));
+		assert_eq!(
+			ProcessFormat::Toml.parse(PROCESS_TOML).expect("toml"),
+			json
+		);
+		assert_eq!(
+			ProcessFormat::Yaml.parse(PROCESS_YAML).expect("yaml"),
+			json
+		);
+		assert_eq!(ProcessFormat::Toml.parse("").expect("empty"), vec![]);
+	}
+
+	#[test]
+	fn test_process_malformed() {
+		match ProcessFormat::Toml.parse(
+			r#"
+[[project]]
+project_name = "Runtime"
+owner = "gavofyork",
+matrix_room_id = "!a:b"
+"#,
+		) {
+			Err(error::Error::Toml { .. }) => {}
+			result => panic!("unexpected result: {:?}", result),
+		}
+		// entries outside `[[project]]` tables would be silently ignored
+		match ProcessFormat::Toml
+			.parse("[[projects]]\nproject_name = \"Runtime\"\n")
+		{
+			Err(error::Error::Toml { .. }) => {}
+			result => panic!("unexpected result: {:?}", result),
+		}
+		match ProcessFormat::Yaml.parse("- project_name: [Runtime") {
+			Err(error::Error::Yaml { .. }) => {}
+			result => panic!("unexpected result: {:?}", result),
+		}
+		match ProcessFormat::Json.parse("[{]") {
+			Err(error::Error::Json { .. }) => {}
+			result => panic!("unexpected result: {:?}", result),
+		}
+	}
+
+	#[test]
+	fn test_process_format_from_path() {
+		assert_eq!(
+			ProcessFormat::from_path("Process.toml"),
+			Some(ProcessFormat::Toml)
+		);
+		assert_eq!(
+			ProcessFormat::from_path("Process.yaml"),
+			Some(ProcessFormat::Yaml)
+		);
+		assert_eq!(
+			ProcessFormat::from_path("Process.json"),
+			Some(ProcessFormat::Json)
+		);
+		assert_eq!(ProcessFormat::from_path("Process.yml"), None);
+		assert_eq!(ProcessFormat::from_path("docs/Process.toml"), None);
+	}
+
+	#[test]
+	fn test_process_owner_formats() {
+		let proc = serde_json::from_str::<Vec<ProcessEntry>>(
+			r#"[
+				{
+					"project_name": "single",
+					"owner": "alice",
+					"matrix_room_id": "!a:matrix.parity.io"
+				},
+				{
+					"project_name": "co-owned",
+					"owner": ["bob", "carol"],
+					"delegated_reviewers": ["dave"],
+					"matrix_room_id": "!b:matrix.parity.io"
+				},
+				{
+					"project_name": "legacy-delegate",
+					"owner": "erin",
+					"delegated_reviewer": "frank",
+					"delegated_reviewers": ["grace"],
+					"whitelist": ["heidi"],
+					"matrix_room_id": "!c:matrix.parity.io"
+				}
+			]"#,
+		)
+		.expect("parse json");
+		assert_eq!(proc.len(), 3);
+
+		assert_eq!(proc[0].owners, vec!["alice".to_string()]);
+		assert!(proc[0].delegated_reviewers.is_empty());
+		assert!(proc[0].is_owner("alice"));
+		assert!(!proc[0].is_delegated_reviewer("alice"));
+
+		assert_eq!(
+			proc[1].owners,
+			vec!["bob".to_string(), "carol".to_string()]
+		);
+		assert!(proc[1].is_owner("bob"));
+		assert!(proc[1].is_owner("carol"));
+		assert!(!proc[1].is_owner("dave"));
+		assert!(proc[1].is_delegated_reviewer("dave"));
+		assert!(proc[1].is_owner_or_delegate("dave"));
+
+		assert!(proc[2].is_delegated_reviewer("frank"));
+		assert!(proc[2].is_delegated_reviewer("grace"));
+		assert_eq!(
+			proc[2].iter_approvers().collect::<Vec<_>>(),
+			vec!["erin", "frank", "grace"]
+		);
+		assert!(proc[2].is_special("heidi"));
+		assert!(!proc[2].is_owner_or_delegate("heidi"));
+
+		let combined = CombinedProcessInfo(proc);
+		for login in
+			&["alice", "bob", "carol", "dave", "erin", "frank", "grace"]
+		{
+			assert!(combined.is_owner_or_delegate(login), "{}", login);
+		}
+		assert!(!combined.is_owner_or_delegate("heidi"));
+		assert!(combined.is_special("heidi"));
+	}
+
+	#[test]
+	fn test_process_owner_invalid() {
+		assert!(serde_json::from_str::<ProcessEntry>(
+			r#"{"project_name": "p", "owner": 1, "matrix_room_id": "!a"}"#
+		)
+		.is_err());
+		assert!(serde_json::from_str::<ProcessEntry>(
+			r#"{"project_name": "p", "matrix_room_id": "!a"}"#
+		)
+		.is_err());
+	}
+
+	fn label(name: &str) -> github::Label {
+		github::Label {
+			id: None,
+			node_id: None,
+			url: None,
+			name: name.to_string(),
+			description: None,
+			color: String::new(),
+			default: false,
+		}
+	}
+
+	fn labelled_entries() -> Vec<ProcessEntry> {
+		serde_json::from_str(
+			r#"[
+				{
+					"project_name": "Client",
+					"owner": "a",
+					"matrix_room_id": "!a:b"
+				},
+				{
+					"project_name": "Runtime",
+					"owner": "b",
+					"matching_labels": ["B1-runtime", "B2-pallets"],
+					"matrix_room_id": "!a:b"
+				},
+				{
+					"project_name": "Pallets",
+					"owner": "c",
+					"matching_labels": ["B2-pallets"],
+					"matrix_room_id": "!a:b"
+				}
+			]"#,
+		)
+		.unwrap()
+	}
+
+	#[test]
+	fn test_process_matching_labels() {
+		let entries = labelled_entries();
+		assert_eq!(
+			process_matching_labels(
+				&entries,
+				&[label("A0-please_review"), label("B1-runtime")]
+			),
+			Some(entries[1].clone())
+		);
+		assert_eq!(
+			process_matching_labels(&entries, &[label("B2-pallets")]),
+			Some(entries[1].clone())
+		);
+	}
+
+	#[test]
+	fn test_process_matching_labels_none() {
+		let entries = labelled_entries();
+		assert_eq!(process_matching_labels(&entries, &[]), None);
+		assert_eq!(
+			process_matching_labels(&entries, &[label("b1-runtime")]),
+			None
+		);
+		assert_eq!(process_matching_labels(&entries, &[label("Client")]), None);
+	}
+
+	#[test]
+	fn test_process_matching_mode() {
+		assert_eq!(
+			"columns-then-labels".parse(),
+			Ok(ProcessMatching::ColumnsThenLabels)
+		);
+		assert_eq!(" columns".parse(), Ok(ProcessMatching::Columns));
+		assert_eq!("labels".parse(), Ok(ProcessMatching::Labels));
+		assert!("projects".parse::<ProcessMatching>().is_err());
+		assert_eq!(
+			ProcessMatching::default(),
+			ProcessMatching::ColumnsThenLabels
+		);
+	}
+
+	fn repo_projects() -> HashMap<String, Vec<String>> {
+		vec![
+			(
+				"Networking".to_string(),
+				vec!["Backlog".to_string(), "In progress".to_string()],
+			),
+			("Runtime".to_string(), vec![]),
+		]
+		.into_iter()
+		.collect()
+	}
+
+	#[test]
+	fn test_validate_valid() {
+		assert_eq!(
+			validate(
+				r#"[
+					{
+						"project_name": "Networking",
+						"owner": ["tomaka", "some-dev"],
+						"delegated_reviewers": ["other-dev1"],
+						"whitelist": ["a"],
+						"matrix_room_id": "!vUADSGcyXmxhKLeDsW:matrix.parity.io",
+						"backlog": "backlog"
+					},
+					{
+						"project_name": "Runtime",
+						"owner": "gavofyork",
+						"matrix_room_id": "!yBKstWVBkwzUkPslsp:matrix.parity.io:8448"
+					}
+				]"#,
+				ProcessFormat::Json,
+				&repo_projects(),
+			),
+			Vec::<String>::new()
+		);
+		assert_eq!(
+			validate("[]", ProcessFormat::Json, &HashMap::new()),
+			Vec::<String>::new()
+		);
+		// entries matched by label need no project
+		assert_eq!(
+			validate(
+				r#"[{"project_name": "Pallets", "owner": "a", "matching_labels": ["B2-pallets"], "matrix_room_id": "!a:b"}]"#,
+				ProcessFormat::Json,
+				&repo_projects(),
+			),
+			Vec::<String>::new()
+		);
+	}
+
+	#[test]
+	fn test_validate_repository_file() {
+		let projects = vec![("parity-processbot".to_string(), vec![])]
+			.into_iter()
+			.collect();
+		assert_eq!(
+			validate(
+				include_str!("../Process.json"),
+				ProcessFormat::Json,
+				&projects
+			),
+			Vec::<String>::new()
+		);
+	}
+
+	#[test]
+	fn test_validate_formats() {
+		let projects = vec![
+			("Networking".to_string(), vec![]),
+			("Runtime".to_string(), vec!["Backlog".to_string()]),
+		]
+		.into_iter()
+		.collect();
+		for format in ProcessFormat::ALL.iter().copied() {
+			let contents = match format {
+				ProcessFormat::Toml => PROCESS_TOML,
+				ProcessFormat::Yaml => PROCESS_YAML,
+				ProcessFormat::Json => PROCESS_JSON,
+			};
+			assert_eq!(
+				validate(contents, format, &projects),
+				Vec::<String>::new(),
+				"{}",
+				format
+			);
+		}
+
+		assert_eq!(
+			validate(
+				"[[project]]\nproject_name = \"Runtime\"\nowners = \"a\"\nmatrix_room_id = \"!a:b\"\n",
+				ProcessFormat::Toml,
+				&projects
+			),
+			vec![
+				"'Runtime': unknown field `owners`.".to_string(),
+				"'Runtime': missing required field `owner`.".to_string(),
+			]
+		);
+		assert_eq!(
+			validate(
+				"[[projects]]\nproject_name = \"Runtime\"\n",
+				ProcessFormat::Toml,
+				&projects
+			),
+			vec!["The file is not valid TOML: unknown top-level key `projects`; entries belong in `[[project]]` tables".to_string()]
+		);
+		let problems = validate(
+			"project_name = \"Runtime\"\nowner = ",
+			ProcessFormat::Toml,
+			&projects,
+		);
+		assert_eq!(problems.len(), 1);
+		assert!(problems[0].starts_with("The file is not valid TOML: "));
+		assert_eq!(
+			validate("project_name: Runtime", ProcessFormat::Yaml, &projects),
+			vec!["The file must contain a list of projects.".to_string()]
+		);
+	}
+
+	#[test]
+	fn test_validate_syntax() {
+		let problems = validate(
+			r#"[{"project_name": "Runtime", "owner": "a", "matrix_room_id": "!a:b",}]"#,
+			ProcessFormat::Json,
+			&repo_projects(),
+		);
+		assert_eq!(problems.len(), 1);
+		assert!(problems[0].starts_with("The file is not valid JSON"));
+
+		assert_eq!(
+			validate(
+				r#"{"project_name": "Runtime"}"#,
+				ProcessFormat::Json,
+				&repo_projects()
+			),
+			vec!["The file must contain a list of projects.".to_string()]
+		);
+		assert_eq!(
+			validate(r#"["Runtime"]"#, ProcessFormat::Json, &repo_projects()),
+			vec!["Entry 0 is not an object.".to_string()]
+		);
+	}
+
+	#[test]
+	fn test_validate_fields() {
+		assert_eq!(
+			validate(
+				r#"[{"project_name": "Runtime", "owners": "a", "matrix_room": "!a:b"}]"#,
+				ProcessFormat::Json,
+				&repo_projects(),
+			),
+			vec![
+				"'Runtime': unknown field `matrix_room`.".to_string(),
+				"'Runtime': unknown field `owners`.".to_string(),
+				"'Runtime': missing required field `owner`.".to_string(),
+				"'Runtime': missing required field `matrix_room_id`."
+					.to_string(),
+			]
+		);
+		assert_eq!(
+			validate(
+				r#"[{"owner": "a", "matrix_room_id": "!a:b"}]"#,
+				ProcessFormat::Json,
+				&repo_projects()
+			),
+			vec![
+				"'entry 0': missing required field `project_name`.".to_string()
+			]
+		);
+		let problems = validate(
+			r#"[{"project_name": "Runtime", "owner": 5, "matrix_room_id": "!a:b"}]"#,
+			ProcessFormat::Json,
+			&repo_projects(),
+		);
+		assert_eq!(problems.len(), 1);
+		assert!(problems[0].starts_with("'Runtime': "));
+	}
+
+	#[test]
+	fn test_validate_values() {
+		assert_eq!(
+			validate(
+				r##"[
+					{
+						"project_name": "Runtime",
+						"owner": [],
+						"delegated_reviewer": "-dev",
+						"whitelist": ["@dev", "a--b"],
+						"matrix_room_id": "#runtime:matrix.parity.io"
+					},
+					{
+						"project_name": " ",
+						"owner": "dev",
+						"matrix_room_id": "!a:b"
+					}
+				]"##,
+				ProcessFormat::Json, &repo_projects(),
+			),
+			vec![
+				"'Runtime': `owner` lists nobody.".to_string(),
+				"'Runtime': `-dev` is not a valid GitHub login.".to_string(),
+				"'Runtime': `@dev` is not a valid GitHub login.".to_string(),
+				"'Runtime': `a--b` is not a valid GitHub login.".to_string(),
+				"'Runtime': `#runtime:matrix.parity.io` is not a Matrix room id (expected `!id:server`).".to_string(),
+				"Entry 1 has an empty `project_name`.".to_string(),
+				"' ': no project with this name exists in the repository."
+					.to_string(),
+			]
+		);
+		assert_eq!(
+			validate(
+				&format!(
+					r#"[{{"project_name": "Runtime", "owner": "{}", "matrix_room_id": "!a:b"}}]"#,
+					"a".repeat(40)
+				),
+				ProcessFormat::Json,
+				&repo_projects(),
+			),
+			vec![format!(
+				"'Runtime': `{}` is not a valid GitHub login.",
+				"a".repeat(40)
+			)]
+		);
+	}
+
+	#[test]
+	fn test_validate_projects() {
+		assert_eq!(
+			validate(
+				r#"[
+					{"project_name": "Runtime", "owner": "a", "matrix_room_id": "!a:b"},
+					{"project_name": "Runtime", "owner": "b", "matrix_room_id": "!a:b"},
+					{"project_name": "Client", "owner": "c", "matrix_room_id": "!a:b"},
+					{"project_name": "Networking", "owner": "d", "matrix_room_id": "!a:b", "backlog": "Todo"}
+				]"#,
+				ProcessFormat::Json,
+				&repo_projects(),
+			),
+			vec![
+				"'Runtime': project name is used by entries 0 and 1."
+					.to_string(),
+				"'Client': no project with this name exists in the repository."
+					.to_string(),
+				"'Networking': the project has no column named 'Todo'."
+					.to_string(),
+			]
+		);
+	}
+
+	#[test]
+	fn test_approval_ping_due() {
+		assert!(approval_ping_due(None, 0));
+		assert!(!approval_ping_due(Some(100), 100));
+		assert!(!approval_ping_due(
+			Some(100),
+			100 + APPROVAL_PING_INTERVAL_SECS - 1
+		));
+		assert!(approval_ping_due(
+			Some(100),
+			100 + APPROVAL_PING_INTERVAL_SECS
+		));
+	}
+
+	#[test]
+	fn test_approval_ping_message() {
+		let process = serde_json::from_str::<ProcessEntry>(
+			r#"{
+				"project_name": "Runtime",
+				"owner": ["gavofyork", "shawntabrizi"],
+				"delegated_reviewer": "kianenigma",
+				"matrix_room_id": "!a:matrix.parity.io"
+			}"#,
+		)
+		.unwrap();
+		assert_eq!(
+			approval_ping_message(
+				"https://github.com/paritytech/substrate/pull/1",
+				"sjeohp",
+				&process
+			),
+			"https://github.com/paritytech/substrate/pull/1 in project 'Runtime' needs approval from one of gavofyork, shawntabrizi, kianenigma before it can be merged (requested by sjeohp)."
+		);
+	}
+
+	fn entries() -> Vec<ProcessEntry> {
+		serde_json::from_str(include_str!("../Process.json")).unwrap()
+	}
+
+	#[test]
+	fn test_reviewers() {
+		let process = CombinedProcessInfo(
+			ProcessFormat::Toml.parse(PROCESS_TOML).unwrap(),
+		);
+		assert_eq!(
+			process.reviewers("tomaka"),
+			vec!["gavofyork", "shawntabrizi", "kianenigma"]
+		);
+		// logins are case-insensitive
+		assert_eq!(
+			process.reviewers("GavOfYork"),
+			vec!["tomaka", "shawntabrizi", "kianenigma"]
+		);
+		let mut entries = ProcessFormat::Toml.parse(PROCESS_TOML).unwrap();
+		entries[0].owners = vec!["KianEnigma".to_string()];
+		assert_eq!(
+			CombinedProcessInfo(entries).reviewers("sjeohp"),
+			vec!["KianEnigma", "gavofyork", "shawntabrizi"]
+		);
+		assert!(CombinedProcessInfo(vec![]).reviewers("sjeohp").is_empty());
+	}
+
+	#[test]
+	fn test_review_requested_once() {
+		let db = TempDb::new("review-request");
+		assert!(!review_requested(&db, "paritytech", "substrate", 1).unwrap());
+		record_review_request(&db, "paritytech", "substrate", 1, 100).unwrap();
+		assert!(review_requested(&db, "paritytech", "substrate", 1).unwrap());
+		assert!(!review_requested(&db, "paritytech", "substrate", 2).unwrap());
+		assert!(!review_requested(&db, "paritytech", "polkadot", 1).unwrap());
+		// forgotten once closed
+		remove_review_request(&db, "paritytech", "substrate", 1).unwrap();
+		assert!(!review_requested(&db, "paritytech", "substrate", 1).unwrap());
+	}
+
+	#[test]
+	fn test_process_cache_ttl() {
+		let cache = ProcessCache::new(60);
+		assert_eq!(cache.get("paritytech", "polkadot", 0), None);
+
+		cache.insert("paritytech", "polkadot", entries(), 100);
+		assert_eq!(cache.get("paritytech", "polkadot", 100), Some(entries()));
+		assert_eq!(cache.get("paritytech", "polkadot", 160), Some(entries()));
+		assert_eq!(cache.get("paritytech", "polkadot", 161), None);
+		// expired entries remain available as a fallback
+		assert_eq!(cache.get_stale("paritytech", "polkadot"), Some(entries()));
+		// entries are per repository
+		assert_eq!(cache.get("paritytech", "substrate", 100), None);
+
+		cache.insert("paritytech", "polkadot", vec![], 200);
+		assert_eq!(cache.get("paritytech", "polkadot", 200), Some(vec![]));
+	}
+
+	#[test]
+	fn test_process_cache_invalidate() {
+		let cache = ProcessCache::new(60);
+		cache.insert("paritytech", "polkadot", entries(), 100);
+		cache.insert("paritytech", "substrate", entries(), 100);
+
+		assert!(cache.invalidate("paritytech", "polkadot"));
+		assert_eq!(cache.get("paritytech", "polkadot", 100), None);
+		assert_eq!(cache.get_stale("paritytech", "polkadot"), None);
+		assert!(!cache.invalidate("paritytech", "polkadot"));
+		assert_eq!(cache.get("paritytech", "substrate", 100), Some(entries()));
+	}
+
+	#[test]
+	fn test_touches_process_file() {
+		assert!(touches_process_file(
+			vec!["README.md", "Process.json"].into_iter()
+		));
+		assert!(touches_process_file(vec!["Process.toml"].into_iter()));
+		assert!(touches_process_file(vec!["Process.yaml"].into_iter()));
+		assert!(!touches_process_file(
+			vec!["README.md", "docs/Process.json"].into_iter()
+		));
+		assert!(!touches_process_file(std::iter::empty()));
+	}
 }
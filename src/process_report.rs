@@ -0,0 +1,219 @@
+use itertools::Itertools;
+
+use crate::{
+	approval::{ApprovalReport, TeamApproval},
+	process::{ProcessEntry, ProcessMatching},
+};
+
+/// What `bot process` replies about a PR.
+#[derive(Debug, Clone)]
+pub struct ProcessReport {
+	/// the entries of the process file the PR resolved to
+	pub projects: Vec<ProcessEntry>,
+	/// the names of the projects of the process file, listed if the PR
+	/// resolved to none
+	pub defined: Vec<String>,
+	pub matching: ProcessMatching,
+	/// the code owners of the PR, which stand in for the owners of a project
+	/// if it resolved to none
+	pub codeowners: Option<Vec<String>>,
+	/// who asked, as the approval is evaluated as if they asked for the merge
+	pub requested_by: String,
+	pub approval: ApprovalReport,
+}
+
+fn mentions(logins: &[String]) -> String {
+	logins.iter().map(|login| format!("@{}", login)).join(", ")
+}
+
+fn matching(matching: ProcessMatching) -> &'static str {
+	match matching {
+		ProcessMatching::ColumnsThenLabels => {
+			"by project column, then by label"
+		}
+		ProcessMatching::Columns => "by project column",
+		ProcessMatching::Labels => "by label",
+	}
+}
+
+fn project(entry: &ProcessEntry) -> String {
+	let mut line = format!(
+		"- `{}`: owned by {}",
+		entry.project_name,
+		mentions(&entry.owners)
+	);
+	let delegates = entry.iter_delegates().cloned().collect::<Vec<_>>();
+	if !delegates.is_empty() {
+		line.push_str(&format!(", delegated to {}", mentions(&delegates)));
+	}
+	line.push_str(&format!("; Matrix room `{}`", entry.matrix_room_id));
+	line
+}
+
+fn condition(met: bool, what: &str) -> String {
+	format!("- {} {}", if met { "✅" } else { "❌" }, what)
+}
+
+impl ProcessReport {
+	/// The comment replying to `bot process`.
+	pub fn render(&self) -> String {
+		let mut sections = Vec::new();
+		if self.projects.is_empty() {
+			let mut none = format!(
+				"The PR resolves to no project of the process file, matching {}.",
+				matching(self.matching)
+			);
+			if self.defined.is_empty() {
+				none.push_str(" The process file defines no projects.");
+			} else {
+				none.push_str(&format!(
+					" It defines {}; attach the PR to one of their project columns, or add one of their matching labels.",
+					self.defined
+						.iter()
+						.map(|name| format!("`{}`", name))
+						.join(", ")
+				));
+			}
+			if let Some(codeowners) =
+				self.codeowners.as_ref().filter(|owners| !owners.is_empty())
+			{
+				none.push_str(&format!(
+					"\n\nIts owners are taken from CODEOWNERS instead: {}.",
+					mentions(codeowners)
+				));
+			}
+			sections.push(none);
+		} else {
+			sections.push(format!(
+				"The PR resolves to these projects, matching {}:\n\n{}",
+				matching(self.matching),
+				self.projects.iter().map(project).join("\n")
+			));
+		}
+
+		let approval = &self.approval;
+		let mut conditions = vec![
+			condition(
+				approval.reviewer_approvals >= approval.min_reviewers,
+				&format!(
+					"core-dev quorum: {} of {} approvals from the review teams",
+					approval.reviewer_approvals, approval.min_reviewers
+				),
+			),
+			condition(
+				matches!(
+					approval.team_approval,
+					Some(TeamApproval::LeadRequested)
+						| Some(TeamApproval::LeadApproved)
+				),
+				"lead approval",
+			),
+			match approval.owner_approval {
+				Some(met) => condition(met, "owner approval"),
+				None => "- ❔ owner approval: no owners are known".to_string(),
+			},
+		];
+		if let Some(reviewer) = &approval.changes_requested_by {
+			conditions.push(format!(
+				"- ⛔ @{} requested changes, which blocks the merge",
+				reviewer
+			));
+		}
+		sections.push(format!(
+			"Approval, as if @{} asked for the merge:\n\n{}\n\nThe approval {} enough to merge.",
+			self.requested_by,
+			conditions.join("\n"),
+			if approval.allows_merge() { "is" } else { "is not" }
+		));
+		sections.join("\n\n")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn entry(name: &str) -> ProcessEntry {
+		ProcessEntry {
+			project_name: name.to_string(),
+			owners: vec!["alice".to_string(), "bob".to_string()],
+			delegated_reviewer: Some("carol".to_string()),
+			delegated_reviewers: vec![],
+			whitelist: vec![],
+			matching_labels: vec![],
+			matrix_room_id: "!abc:matrix.parity.io".to_string(),
+			backlog: None,
+		}
+	}
+
+	fn approval(owner_approval: Option<bool>) -> ApprovalReport {
+		ApprovalReport {
+			changes_requested_by: None,
+			team_approval: None,
+			reviewer_approvals: 1,
+			min_reviewers: 2,
+			owner_approval,
+		}
+	}
+
+	#[test]
+	fn test_render_projects() {
+		let report = ProcessReport {
+			projects: vec![entry("Polkadot")],
+			defined: vec!["Polkadot".to_string(), "Substrate".to_string()],
+			matching: ProcessMatching::ColumnsThenLabels,
+			codeowners: None,
+			requested_by: "dave".to_string(),
+			approval: approval(Some(true)),
+		};
+		assert_eq!(
+			report.render(),
+			"The PR resolves to these projects, matching by project column, then by label:\n\n\
+			- `Polkadot`: owned by @alice, @bob, delegated to @carol; Matrix room `!abc:matrix.parity.io`\n\n\
+			Approval, as if @dave asked for the merge:\n\n\
+			- ❌ core-dev quorum: 1 of 2 approvals from the review teams\n\
+			- ❌ lead approval\n\
+			- ✅ owner approval\n\n\
+			The approval is enough to merge."
+		);
+	}
+
+	#[test]
+	fn test_render_no_project() {
+		let report = ProcessReport {
+			projects: vec![],
+			defined: vec!["Polkadot".to_string(), "Substrate".to_string()],
+			matching: ProcessMatching::Labels,
+			codeowners: None,
+			requested_by: "dave".to_string(),
+			approval: ApprovalReport {
+				changes_requested_by: Some("erin".to_string()),
+				team_approval: Some(TeamApproval::LeadApproved),
+				..approval(None)
+			},
+		};
+		assert_eq!(
+			report.render(),
+			"The PR resolves to no project of the process file, matching by label. \
+			It defines `Polkadot`, `Substrate`; attach the PR to one of their project columns, or add one of their matching labels.\n\n\
+			Approval, as if @dave asked for the merge:\n\n\
+			- ❌ core-dev quorum: 1 of 2 approvals from the review teams\n\
+			- ✅ lead approval\n\
+			- ❔ owner approval: no owners are known\n\
+			- ⛔ @erin requested changes, which blocks the merge\n\n\
+			The approval is not enough to merge."
+		);
+
+		let report = ProcessReport {
+			defined: vec![],
+			codeowners: Some(vec!["frank".to_string()]),
+			approval: approval(Some(false)),
+			..report
+		};
+		let rendered = report.render();
+		assert!(rendered.contains("The process file defines no projects."));
+		assert!(rendered
+			.contains("Its owners are taken from CODEOWNERS instead: @frank."));
+		assert!(rendered.contains("- ❌ owner approval\n"));
+	}
+}
@@ -0,0 +1,185 @@
+use chrono::{DateTime, TimeZone, Utc};
+use rocksdb::DB;
+use serde::Serialize;
+
+use crate::{
+	db::{self, Transaction},
+	Result,
+};
+
+/// Records which cannot be read are moved under this prefix in their column
+/// family, followed by when they were moved and the key they had.
+const PREFIX: &str = "corrupt/";
+
+fn quarantine_key(key: &[u8], at: DateTime<Utc>) -> Vec<u8> {
+	[
+		PREFIX.as_bytes(),
+		format!("{:020}/", at.timestamp().max(0)).as_bytes(),
+		key,
+	]
+	.concat()
+}
+
+/// Move the record at `key`, which cannot be read, out of the way of the
+/// records which can.
+pub fn quarantine(
+	tx: &mut Transaction,
+	family: &str,
+	key: &[u8],
+	value: &[u8],
+	at: DateTime<Utc>,
+) -> Result<()> {
+	tx.delete(family, key)?;
+	tx.put(family, &quarantine_key(key, at), value)
+}
+
+/// A record which was moved out of the way.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Quarantined {
+	pub family: String,
+	/// the key it had
+	pub key: String,
+	/// `None` for records quarantined before the time was kept
+	pub at: Option<DateTime<Utc>>,
+	/// the stored bytes, in base64
+	pub value: String,
+}
+
+impl Quarantined {
+	fn new(family: &str, key: &[u8], value: &[u8]) -> Self {
+		let key = String::from_utf8_lossy(&key[PREFIX.len()..]).into_owned();
+		let at = key
+			.split_once('/')
+			.filter(|(secs, _)| {
+				secs.len() == 20 && secs.bytes().all(|b| b.is_ascii_digit())
+			})
+			.and_then(|(secs, key)| {
+				Some((Utc.timestamp_opt(secs.parse().ok()?, 0).single()?, key))
+			});
+		Quarantined {
+			family: family.to_string(),
+			key: at.map_or(key.as_str(), |(_, key)| key).to_string(),
+			at: at.map(|(at, _)| at),
+			value: base64::encode(value),
+		}
+	}
+}
+
+/// Every quarantined record, oldest first within each column family.
+pub fn list(db: &DB) -> Result<Vec<Quarantined>> {
+	let mut records = Vec::new();
+	for name in db::all_families() {
+		for (key, value) in db::family(db, name)?.iter_prefix(PREFIX.as_bytes())
+		{
+			records.push(Quarantined::new(name, &key, &value));
+		}
+	}
+	Ok(records)
+}
+
+/// Delete every quarantined record.  Returns how many there were.
+pub fn purge(db: &DB) -> Result<usize> {
+	db::atomically(db, |tx| {
+		for name in db::all_families() {
+			for (key, _) in db::family(db, name)?.iter_prefix(PREFIX.as_bytes())
+			{
+				tx.delete(name, &key)?;
+			}
+		}
+		Ok(tx.len())
+	})
+}
+
+/// One line per record, with its column family, key and when it was moved.
+pub fn format_table(records: &[Quarantined]) -> String {
+	let rows = records
+		.iter()
+		.map(|record| {
+			(
+				record.family.as_str(),
+				record.at.map_or("-".to_string(), |at| at.to_rfc3339()),
+				record.key.as_str(),
+			)
+		})
+		.collect::<Vec<_>>();
+	let family_width = rows
+		.iter()
+		.map(|(family, _, _)| family.len())
+		.chain(Some("FAMILY".len()))
+		.max()
+		.unwrap_or_default();
+	let at_width = rows
+		.iter()
+		.map(|(_, at, _)| at.len())
+		.chain(Some("QUARANTINED".len()))
+		.max()
+		.unwrap_or_default();
+	Some(("FAMILY", "QUARANTINED".to_string(), "KEY"))
+		.into_iter()
+		.chain(rows)
+		.map(|(family, at, key)| {
+			format!(
+				"{:<family_width$}  {:<at_width$}  {}",
+				family,
+				at,
+				key,
+				family_width = family_width,
+				at_width = at_width
+			)
+		})
+		.collect::<Vec<_>>()
+		.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::db::TempDb;
+
+	#[test]
+	fn test_quarantine() {
+		let db = TempDb::new("quarantine");
+		let sha = "0123456789abcdef0123456789abcdef01234567";
+		let merges = db::family(&db, db::MERGE_REQUESTS).unwrap();
+		merges.put(sha.as_bytes(), b"garbage").unwrap();
+		// as quarantined before the time was kept
+		db.put("corrupt/merge-freeze/paritytech/substrate", b"old")
+			.unwrap();
+		let at = Utc.timestamp(1_600_000_000, 0);
+		db::atomically(&db, |tx| {
+			quarantine(tx, db::MERGE_REQUESTS, sha.as_bytes(), b"garbage", at)
+		})
+		.unwrap();
+
+		assert!(merges.get(sha.as_bytes()).unwrap().is_none());
+		assert_eq!(
+			list(&db).unwrap(),
+			vec![
+				Quarantined {
+					family: db::DEFAULT.to_string(),
+					key: "merge-freeze/paritytech/substrate".to_string(),
+					at: None,
+					value: base64::encode("old"),
+				},
+				Quarantined {
+					family: db::MERGE_REQUESTS.to_string(),
+					key: sha.to_string(),
+					at: Some(at),
+					value: base64::encode("garbage"),
+				},
+			]
+		);
+		assert_eq!(
+			format_table(&list(&db).unwrap()),
+			format!(
+				"FAMILY          QUARANTINED                KEY
+default         -                          merge-freeze/paritytech/substrate
+merge_requests  2020-09-13T12:26:40+00:00  {}",
+				sha
+			)
+		);
+
+		assert_eq!(purge(&db).unwrap(), 2);
+		assert!(list(&db).unwrap().is_empty());
+	}
+}
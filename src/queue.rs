@@ -0,0 +1,537 @@
+use crate::{
+	db::{self, Transaction},
+	migrations, Result,
+};
+use rocksdb::DB;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// This stores information about a pull request while we wait for checks to complete.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MergeRequest {
+	pub owner: String,
+	pub repo_name: String,
+	pub number: i64,
+	pub html_url: String,
+	pub requested_by: String,
+	/// when the merge was requested, in seconds since the epoch; unknown for
+	/// merges requested before it was recorded
+	pub created_at: Option<i64>,
+	/// the PR whose merge this one was requested for, eg. the Substrate PR of a
+	/// companion
+	#[serde(default)]
+	pub origin: Option<MergeOrigin>,
+	/// when a merge requested with `bot merge at` is due, in seconds since the
+	/// epoch; it is not merged before, even if its checks pass
+	#[serde(default)]
+	pub not_before: Option<i64>,
+}
+
+/// A PR told how the merge requested for it goes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MergeOrigin {
+	pub owner: String,
+	pub repo_name: String,
+	pub number: i64,
+	pub html_url: String,
+}
+
+impl MergeRequest {
+	/// Whether `login` may cancel the merge without the permission otherwise
+	/// needed: whoever requested it, or the author of the PR.
+	pub fn may_cancel(&self, login: &str, pr_author: &str) -> bool {
+		login.eq_ignore_ascii_case(&self.requested_by)
+			|| login.eq_ignore_ascii_case(pr_author)
+	}
+}
+
+/// Index entries point from a PR at the head sha its merge is keyed by, so
+/// that the merges of a repository can be found without a full scan.
+pub(crate) const INDEX_PREFIX: &str = "idx/";
+
+fn repo_index_prefix(owner: &str, repo_name: &str) -> String {
+	format!(
+		"{}{}/{}/",
+		INDEX_PREFIX,
+		owner.to_lowercase(),
+		repo_name.to_lowercase()
+	)
+}
+
+fn index_key(request: &MergeRequest) -> String {
+	format!(
+		"{}{}",
+		repo_index_prefix(&request.owner, &request.repo_name),
+		request.number
+	)
+}
+
+/// A pending merge, keyed by the head sha of its PR.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QueueEntry {
+	pub sha: String,
+	/// `None` if the entry could not be deserialized
+	pub request: Option<MergeRequest>,
+}
+
+/// Merge requests are keyed by a bare commit sha, their index entries by a
+/// prefix.
+pub(crate) fn is_queue_key(key: &[u8]) -> bool {
+	key.len() == 40 && key.iter().all(u8::is_ascii_hexdigit)
+}
+
+/// All pending merges, including those which fail to deserialize.
+pub fn entries(db: &DB) -> Result<Vec<QueueEntry>> {
+	Ok(db::family(db, db::MERGE_REQUESTS)?
+		.iter()
+		.filter(|(key, _)| is_queue_key(key))
+		.map(|(key, value)| QueueEntry {
+			sha: String::from_utf8_lossy(&key).into_owned(),
+			request: migrations::decode(&value).ok(),
+		})
+		.collect())
+}
+
+/// The pending merge keyed by `sha`, as it is stored.
+pub fn get(db: &DB, sha: &str) -> Result<Option<Vec<u8>>> {
+	db::family(db, db::MERGE_REQUESTS)?.get(sha.as_bytes())
+}
+
+/// Store a pending merge along with its index entry.
+pub fn store(
+	tx: &mut Transaction,
+	sha: &str,
+	request: &MergeRequest,
+) -> Result<()> {
+	let bytes = migrations::encode(request)?;
+	tx.put(db::MERGE_REQUESTS, sha.as_bytes(), &bytes)?;
+	tx.put(
+		db::MERGE_REQUESTS,
+		index_key(request).as_bytes(),
+		sha.as_bytes(),
+	)
+}
+
+/// Remove a pending merge along with its index entry, unless that already
+/// points at a later merge of the same PR.
+pub fn delete(tx: &mut Transaction, sha: &str) -> Result<()> {
+	let family = db::family(tx.db(), db::MERGE_REQUESTS)?;
+	if let Some(request) = family
+		.get(sha.as_bytes())?
+		.and_then(|b| migrations::decode::<MergeRequest>(&b).ok())
+	{
+		let key = index_key(&request);
+		if family.get(key.as_bytes())?.as_deref() == Some(sha.as_bytes()) {
+			tx.delete(db::MERGE_REQUESTS, key.as_bytes())?;
+		}
+	}
+	tx.delete(db::MERGE_REQUESTS, sha.as_bytes())
+}
+
+/// The pending merges of a repository, found through the index.  Index
+/// entries pointing at a merge which is gone are removed.
+pub fn for_repo(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+) -> Result<Vec<QueueEntry>> {
+	let family = db::family(db, db::MERGE_REQUESTS)?;
+	let prefix = repo_index_prefix(owner, repo_name);
+	let mut found = Vec::new();
+	let mut dangling = Transaction::new(db);
+	for (key, sha) in family.iter_prefix(prefix.as_bytes()) {
+		match family.get(&sha)? {
+			Some(value) => found.push(QueueEntry {
+				sha: String::from_utf8_lossy(&sha).into_owned(),
+				request: migrations::decode(&value).ok(),
+			}),
+			None => {
+				log::warn!(
+					"Removing index entry {} of a missing merge",
+					String::from_utf8_lossy(&key)
+				);
+				dangling.delete(db::MERGE_REQUESTS, &key)?;
+			}
+		}
+	}
+	dangling.commit()?;
+	Ok(found)
+}
+
+/// Index the pending merges stored before the index existed.  Returns how many
+/// were indexed.
+pub fn reindex(db: &DB) -> Result<usize> {
+	let family = db::family(db, db::MERGE_REQUESTS)?;
+	db::atomically(db, |tx| {
+		for entry in entries(db)? {
+			if let Some(request) = &entry.request {
+				let key = index_key(request);
+				if family.get(key.as_bytes())?.is_none() {
+					tx.put(
+						db::MERGE_REQUESTS,
+						key.as_bytes(),
+						entry.sha.as_bytes(),
+					)?;
+				}
+			}
+		}
+		Ok(tx.len())
+	})
+}
+
+/// A pending merge named on the command line.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+	Sha(String),
+	PullRequest {
+		owner: String,
+		repo_name: String,
+		number: i64,
+	},
+}
+
+impl Target {
+	fn matches(&self, entry: &QueueEntry) -> bool {
+		match (self, &entry.request) {
+			(Target::Sha(sha), _) => entry.sha == *sha,
+			(
+				Target::PullRequest {
+					owner,
+					repo_name,
+					number,
+				},
+				Some(request),
+			) => {
+				// as in the index, which does not keep the case
+				request.owner.eq_ignore_ascii_case(owner)
+					&& request.repo_name.eq_ignore_ascii_case(repo_name)
+					&& request.number == *number
+			}
+			_ => false,
+		}
+	}
+}
+
+impl FromStr for Target {
+	type Err = String;
+
+	/// Parse `owner/repo#number` or a head sha.
+	fn from_str(s: &str) -> std::result::Result<Self, String> {
+		if is_queue_key(s.as_bytes()) {
+			return Ok(Target::Sha(s.to_lowercase()));
+		}
+		let invalid = || format!("expected owner/repo#number or a sha: {}", s);
+		let (repo, number) = s.split_at(s.find('#').ok_or_else(invalid)?);
+		let (owner, repo_name) =
+			repo.split_at(repo.find('/').ok_or_else(invalid)?);
+		let repo_name = &repo_name[1..];
+		if owner.is_empty() || repo_name.is_empty() {
+			return Err(invalid());
+		}
+		Ok(Target::PullRequest {
+			owner: owner.to_string(),
+			repo_name: repo_name.to_string(),
+			number: number[1..].parse().map_err(|_| invalid())?,
+		})
+	}
+}
+
+/// Remove the pending merges matching the target.  Returns the removed entries.
+pub fn remove(
+	tx: &mut Transaction,
+	target: &Target,
+) -> Result<Vec<QueueEntry>> {
+	let db = tx.db();
+	let candidates = match target {
+		Target::Sha(sha) => get(db, sha)?
+			.map(|value| QueueEntry {
+				sha: sha.clone(),
+				request: migrations::decode(&value).ok(),
+			})
+			.into_iter()
+			.collect(),
+		Target::PullRequest {
+			owner, repo_name, ..
+		} => for_repo(db, owner, repo_name)?,
+	};
+	let removed = candidates
+		.into_iter()
+		.filter(|entry| target.matches(entry))
+		.collect::<Vec<_>>();
+	for entry in &removed {
+		delete(tx, &entry.sha)?;
+	}
+	Ok(removed)
+}
+
+/// Remove all pending merges.  Returns how many there were.
+pub fn clear(db: &DB) -> Result<usize> {
+	let family = db::family(db, db::MERGE_REQUESTS)?;
+	db::atomically(db, |tx| {
+		let all = entries(db)?;
+		for entry in &all {
+			tx.delete(db::MERGE_REQUESTS, entry.sha.as_bytes())?;
+		}
+		for (key, _) in family.iter_prefix(INDEX_PREFIX.as_bytes()) {
+			tx.delete(db::MERGE_REQUESTS, &key)?;
+		}
+		Ok(all.len())
+	})
+}
+
+/// One line per entry, with the sha, PR and who requested the merge.
+pub fn format_table(entries: &[QueueEntry]) -> String {
+	let rows = entries
+		.iter()
+		.map(|entry| match &entry.request {
+			Some(request) => (
+				entry.sha.as_str(),
+				format!(
+					"{}/{}#{}",
+					request.owner, request.repo_name, request.number
+				),
+				request.requested_by.as_str(),
+			),
+			None => (entry.sha.as_str(), "<invalid entry>".to_string(), ""),
+		})
+		.collect::<Vec<_>>();
+	let width = rows
+		.iter()
+		.map(|(_, pr, _)| pr.len())
+		.chain(Some("PULL REQUEST".len()))
+		.max()
+		.unwrap_or_default();
+	Some(("SHA", "PULL REQUEST".to_string(), "REQUESTED BY"))
+		.into_iter()
+		.chain(rows)
+		.map(|(sha, pr, requested_by)| {
+			format!(
+				"{:<40}  {:<width$}  {}",
+				sha,
+				pr,
+				requested_by,
+				width = width
+			)
+			.trim_end()
+			.to_string()
+		})
+		.collect::<Vec<_>>()
+		.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::db::TempDb;
+	use crate::error::Error;
+
+	const SHA: &str = "0123456789abcdef0123456789abcdef01234567";
+
+	fn db(name: &str) -> TempDb {
+		TempDb::new(&format!("queue-index-{}", name))
+	}
+
+	fn request(repo_name: &str, number: i64) -> MergeRequest {
+		MergeRequest {
+			owner: "paritytech".to_string(),
+			repo_name: repo_name.to_string(),
+			number,
+			html_url: String::new(),
+			requested_by: "gavofyork".to_string(),
+			created_at: None,
+			origin: None,
+			not_before: None,
+		}
+	}
+
+	fn store_one(db: &DB, sha: &str, request: &MergeRequest) -> Result<()> {
+		db::atomically(db, |tx| store(tx, sha, request))
+	}
+
+	fn delete_one(db: &DB, sha: &str) -> Result<()> {
+		db::atomically(db, |tx| delete(tx, sha))
+	}
+
+	fn shas(entries: Vec<QueueEntry>) -> Vec<String> {
+		entries.into_iter().map(|entry| entry.sha).collect()
+	}
+
+	#[test]
+	fn test_index() {
+		let db = db("index");
+		let other = SHA.replace('0', "a");
+		let polkadot = SHA.replace('0', "b");
+		store_one(&db, SHA, &request("substrate", 1)).unwrap();
+		store_one(&db, &other, &request("substrate", 2)).unwrap();
+		store_one(&db, &polkadot, &request("polkadot", 1)).unwrap();
+		assert_eq!(
+			shas(for_repo(&db, "ParityTech", "substrate").unwrap()),
+			vec![SHA.to_string(), other.clone()]
+		);
+
+		delete_one(&db, SHA).unwrap();
+		assert_eq!(
+			shas(for_repo(&db, "paritytech", "substrate").unwrap()),
+			vec![other.clone()]
+		);
+		assert!(get(&db, "idx/paritytech/substrate/1").unwrap().is_none());
+
+		// a new head of the same PR takes over its index entry, which the
+		// removal of the old merge leaves in place
+		let new_head = SHA.replace('0', "c");
+		store_one(&db, &other, &request("substrate", 1)).unwrap();
+		store_one(&db, &new_head, &request("substrate", 1)).unwrap();
+		delete_one(&db, &other).unwrap();
+		assert_eq!(
+			shas(for_repo(&db, "paritytech", "substrate").unwrap()),
+			vec![new_head.clone()]
+		);
+
+		delete_one(&db, &new_head).unwrap();
+		delete_one(&db, &polkadot).unwrap();
+		assert!(db::family(&db, db::MERGE_REQUESTS)
+			.unwrap()
+			.iter()
+			.next()
+			.is_none());
+	}
+
+	#[test]
+	fn test_index_recovery() {
+		let db = db("recovery");
+		let family = db::family(&db, db::MERGE_REQUESTS).unwrap();
+		store_one(&db, SHA, &request("substrate", 1)).unwrap();
+		// a merge removed without its index entry
+		family.delete(SHA.as_bytes()).unwrap();
+		assert!(for_repo(&db, "paritytech", "substrate").unwrap().is_empty());
+		assert!(get(&db, "idx/paritytech/substrate/1").unwrap().is_none());
+
+		// a merge stored before the index existed
+		family
+			.put(
+				SHA.as_bytes(),
+				&bincode::serialize(&request("substrate", 1)).unwrap(),
+			)
+			.unwrap();
+		assert!(for_repo(&db, "paritytech", "substrate").unwrap().is_empty());
+		assert_eq!(reindex(&db).unwrap(), 1);
+		assert_eq!(reindex(&db).unwrap(), 0);
+		assert_eq!(
+			shas(for_repo(&db, "paritytech", "substrate").unwrap()),
+			vec![SHA.to_string()]
+		);
+	}
+
+	#[test]
+	fn test_atomic() {
+		let db = db("atomic");
+		let failed = || Error::Message {
+			msg: "failed".to_string(),
+		};
+		// as if the bot crashed between storing the merge and its index
+		let mut tx = Transaction::new(&db);
+		store(&mut tx, SHA, &request("substrate", 1)).unwrap();
+		drop(tx);
+		assert!(entries(&db).unwrap().is_empty());
+		assert!(get(&db, "idx/paritytech/substrate/1").unwrap().is_none());
+
+		store_one(&db, SHA, &request("substrate", 1)).unwrap();
+		let other = SHA.replace('0', "a");
+		store_one(&db, &other, &request("substrate", 2)).unwrap();
+		assert!(db::atomically(&db, |tx| {
+			remove(tx, &Target::Sha(SHA.to_string()))?;
+			delete(tx, &other)?;
+			Err::<(), _>(failed())
+		})
+		.is_err());
+		assert_eq!(
+			shas(for_repo(&db, "paritytech", "substrate").unwrap()),
+			vec![SHA.to_string(), other.clone()]
+		);
+	}
+
+	#[test]
+	fn test_remove_pull_request() {
+		let db = db("remove");
+		let other = SHA.replace('0', "a");
+		store_one(&db, SHA, &request("substrate", 1)).unwrap();
+		store_one(&db, &other, &request("substrate", 2)).unwrap();
+		let target = "ParityTech/Substrate#1".parse::<Target>().unwrap();
+		let removed = db::atomically(&db, |tx| remove(tx, &target)).unwrap();
+		assert_eq!(shas(removed), vec![SHA.to_string()]);
+		assert_eq!(
+			shas(for_repo(&db, "paritytech", "substrate").unwrap()),
+			vec![other]
+		);
+	}
+
+	#[test]
+	fn test_may_cancel() {
+		let request = MergeRequest {
+			owner: "paritytech".to_string(),
+			repo_name: "substrate".to_string(),
+			number: 1234,
+			html_url: "https://github.com/paritytech/substrate/pull/1234"
+				.to_string(),
+			requested_by: "maintainer".to_string(),
+			created_at: None,
+			origin: None,
+			not_before: None,
+		};
+		assert!(request.may_cancel("maintainer", "contributor"));
+		assert!(request.may_cancel("Maintainer", "contributor"));
+		assert!(request.may_cancel("contributor", "contributor"));
+		// anyone else needs the permission to command the bot
+		assert!(!request.may_cancel("stranger", "contributor"));
+	}
+
+	#[test]
+	fn test_parse_target() {
+		assert_eq!(
+			"paritytech/substrate#1234".parse(),
+			Ok(Target::PullRequest {
+				owner: "paritytech".to_string(),
+				repo_name: "substrate".to_string(),
+				number: 1234,
+			})
+		);
+		assert_eq!(
+			SHA.to_uppercase().parse(),
+			Ok(Target::Sha(SHA.to_string()))
+		);
+		for invalid in
+			&["substrate#1234", "paritytech/substrate", "/x#1", "a/b#c"]
+		{
+			assert!(invalid.parse::<Target>().is_err(), "{}", invalid);
+		}
+	}
+
+	#[test]
+	fn test_format_table() {
+		let entries = vec![
+			QueueEntry {
+				sha: SHA.to_string(),
+				request: Some(MergeRequest {
+					owner: "paritytech".to_string(),
+					repo_name: "substrate".to_string(),
+					number: 1234,
+					html_url: String::new(),
+					requested_by: "gavofyork".to_string(),
+					created_at: None,
+					origin: None,
+					not_before: None,
+				}),
+			},
+			QueueEntry {
+				sha: SHA.replace('0', "f"),
+				request: None,
+			},
+		];
+		assert_eq!(
+			format_table(&entries),
+			"SHA                                       PULL REQUEST               REQUESTED BY
+0123456789abcdef0123456789abcdef01234567  paritytech/substrate#1234  gavofyork
+f123456789abcdeff123456789abcdeff1234567  <invalid entry>"
+		);
+	}
+}
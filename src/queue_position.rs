@@ -0,0 +1,296 @@
+use rocksdb::{Direction, IteratorMode, DB};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+
+use crate::{
+	db::{self, Transaction},
+	error::*,
+	queue::MergeRequest,
+	scheduled_merge, Result,
+};
+
+const KEY_PREFIX: &str = "queue-position/";
+
+/// The comment telling a PR where its merge is in the queue of its
+/// repository, and the position it gave.  Kept apart from the
+/// `MergeRequest`, so that those already in the database can still be read.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tracked {
+	pub comment_id: i64,
+	pub position: usize,
+}
+
+/// What a change to the queue of a repository means for the comments
+/// tracked in it.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Changes {
+	/// PRs whose comment gives a position which is no longer theirs, with
+	/// the one to give
+	pub moved: Vec<(i64, Tracked)>,
+	/// PRs no longer waiting, whose comment is not updated anymore
+	pub gone: Vec<i64>,
+}
+
+fn repo_prefix(owner: &str, repo_name: &str) -> String {
+	format!(
+		"{}{}/{}/",
+		KEY_PREFIX,
+		owner.to_lowercase(),
+		repo_name.to_lowercase()
+	)
+}
+
+fn key(owner: &str, repo_name: &str, number: i64) -> String {
+	format!("{}{}", repo_prefix(owner, repo_name), number)
+}
+
+/// The pending merges of a repository in line, in the order they joined it,
+/// each with its position from 1.  Merges scheduled for later are not in line
+/// until they are due.
+pub fn positions(
+	requests: &[MergeRequest],
+	now: i64,
+) -> Vec<(&MergeRequest, usize)> {
+	let mut in_line = requests
+		.iter()
+		.filter(|request| !scheduled_merge::is_waiting(request, now))
+		.collect::<Vec<_>>();
+	// a scheduled merge joins the line once due, and merges requested before
+	// the time was recorded are the oldest
+	in_line.sort_by_key(|request| {
+		let joined = request.not_before.into_iter().chain(request.created_at);
+		(joined.max().unwrap_or(0), request.number)
+	});
+	in_line.into_iter().zip(1..).collect()
+}
+
+/// The position to tell PR `number` once its merge is queued, if others are
+/// in line.
+pub fn on_enqueue(
+	requests: &[MergeRequest],
+	number: i64,
+	now: i64,
+) -> Option<usize> {
+	let positions = positions(requests, now);
+	if positions.len() < 2 {
+		return None;
+	}
+	positions
+		.into_iter()
+		.find(|(request, _)| request.number == number)
+		.map(|(_, position)| position)
+}
+
+/// The comments to update after the queue changed to `requests`.
+pub fn changes(
+	requests: &[MergeRequest],
+	tracked: &[(i64, Tracked)],
+	now: i64,
+) -> Changes {
+	let positions = positions(requests, now);
+	let mut changes = Changes::default();
+	for (number, tracked) in tracked {
+		match positions
+			.iter()
+			.find(|(request, _)| request.number == *number)
+		{
+			Some((_, position)) if *position != tracked.position => {
+				changes.moved.push((
+					*number,
+					Tracked {
+						comment_id: tracked.comment_id,
+						position: *position,
+					},
+				))
+			}
+			Some(_) => {}
+			None => changes.gone.push(*number),
+		}
+	}
+	changes
+}
+
+/// The comment saying the merge waits for the checks, and where it is in
+/// line if it is not alone.
+pub fn comment(position: Option<usize>) -> String {
+	match position {
+		Some(position) => format!(
+			"Waiting for commit status. You are #{} in the merge queue for this repository.",
+			position
+		),
+		None => "Waiting for commit status.".to_string(),
+	}
+}
+
+pub fn track(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+	tracked: &Tracked,
+) -> Result<()> {
+	let bytes = serde_json::to_vec(tracked).context(Json)?;
+	db.put(key(owner, repo_name, number).as_bytes(), bytes)
+		.context(Db)
+}
+
+pub fn untrack(
+	tx: &mut Transaction,
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+) -> Result<()> {
+	tx.delete(db::DEFAULT, key(owner, repo_name, number).as_bytes())
+}
+
+/// The comments tracked in the queue of a repository, by PR.
+pub fn tracked(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+) -> Result<Vec<(i64, Tracked)>> {
+	let prefix = repo_prefix(owner, repo_name);
+	Ok(db
+		.iterator(IteratorMode::From(prefix.as_bytes(), Direction::Forward))
+		.take_while(|(k, _)| k.starts_with(prefix.as_bytes()))
+		.filter_map(|(k, v)| {
+			let number = String::from_utf8_lossy(&k[prefix.len()..])
+				.parse::<i64>()
+				.ok()?;
+			let tracked = serde_json::from_slice::<Tracked>(&v).ok()?;
+			Some((number, tracked))
+		})
+		.collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::db::TempDb;
+
+	fn request(number: i64, created_at: Option<i64>) -> MergeRequest {
+		MergeRequest {
+			owner: "paritytech".to_string(),
+			repo_name: "substrate".to_string(),
+			number,
+			html_url: String::new(),
+			requested_by: "alice".to_string(),
+			created_at,
+			origin: None,
+			not_before: None,
+		}
+	}
+
+	fn numbers(requests: &[MergeRequest], now: i64) -> Vec<(i64, usize)> {
+		positions(requests, now)
+			.into_iter()
+			.map(|(request, position)| (request.number, position))
+			.collect()
+	}
+
+	fn tracked(comment_id: i64, position: usize) -> Tracked {
+		Tracked {
+			comment_id,
+			position,
+		}
+	}
+
+	#[test]
+	fn test_insertions() {
+		let mut requests = vec![request(3, Some(1030)), request(1, Some(1010))];
+		assert_eq!(on_enqueue(&requests[..1], 3, 2000), None);
+		assert_eq!(numbers(&requests, 2000), vec![(1, 1), (3, 2)]);
+		assert_eq!(on_enqueue(&requests, 3, 2000), Some(2));
+		// a later request goes to the back, whatever its number
+		requests.push(request(2, Some(1040)));
+		assert_eq!(on_enqueue(&requests, 2, 2000), Some(3));
+		// one requested before the time was recorded is the oldest
+		requests.push(request(4, None));
+		assert_eq!(
+			numbers(&requests, 2000),
+			vec![(4, 1), (1, 2), (3, 3), (2, 4)]
+		);
+		// a merge scheduled for later is not in line until it is due, then
+		// joins the back
+		requests.push(MergeRequest {
+			not_before: Some(3000),
+			..request(5, Some(1000))
+		});
+		assert_eq!(on_enqueue(&requests, 5, 2000), None);
+		assert_eq!(on_enqueue(&requests, 5, 3000), Some(5));
+	}
+
+	#[test]
+	fn test_cancellations() {
+		let requests = vec![
+			request(1, Some(1010)),
+			request(2, Some(1020)),
+			request(3, Some(1030)),
+		];
+		let all = vec![(2, tracked(20, 2)), (3, tracked(30, 3))];
+		assert_eq!(changes(&requests, &all, 2000), Changes::default());
+		// the first merged: those behind move up
+		assert_eq!(
+			changes(&requests[1..], &all, 2000),
+			Changes {
+				moved: vec![(2, tracked(20, 1)), (3, tracked(30, 2))],
+				gone: vec![],
+			}
+		);
+		// the second cancelled: only those behind it move up
+		let remaining = vec![requests[0].clone(), requests[2].clone()];
+		assert_eq!(
+			changes(&remaining, &all, 2000),
+			Changes {
+				moved: vec![(3, tracked(30, 2))],
+				gone: vec![2],
+			}
+		);
+	}
+
+	#[test]
+	fn test_requested_again() {
+		let all = vec![(1, tracked(10, 1)), (3, tracked(30, 3))];
+		// asking again, eg. after a push, goes to the back of the line
+		let again = vec![
+			request(2, Some(1020)),
+			request(3, Some(1030)),
+			request(1, Some(1040)),
+		];
+		assert_eq!(numbers(&again, 2000), vec![(2, 1), (3, 2), (1, 3)]);
+		assert_eq!(
+			changes(&again, &all, 2000),
+			Changes {
+				moved: vec![(1, tracked(10, 3)), (3, tracked(30, 2))],
+				gone: vec![],
+			}
+		);
+	}
+
+	#[test]
+	fn test_comment() {
+		assert_eq!(comment(None), "Waiting for commit status.");
+		assert_eq!(
+			comment(Some(7)),
+			"Waiting for commit status. You are #7 in the merge queue for this repository."
+		);
+	}
+
+	#[test]
+	fn test_tracked() {
+		let db = TempDb::new("queue-position");
+		track(&db, "paritytech", "substrate", 2, &tracked(20, 2)).unwrap();
+		track(&db, "paritytech", "substrate", 12, &tracked(120, 3)).unwrap();
+		track(&db, "paritytech", "polkadot", 2, &tracked(21, 1)).unwrap();
+		assert_eq!(
+			super::tracked(&db, "Paritytech", "Substrate").unwrap(),
+			vec![(12, tracked(120, 3)), (2, tracked(20, 2))]
+		);
+		db::atomically(&db, |tx| untrack(tx, "paritytech", "substrate", 12))
+			.unwrap();
+		assert_eq!(
+			super::tracked(&db, "paritytech", "substrate").unwrap(),
+			vec![(2, tracked(20, 2))]
+		);
+	}
+}
@@ -0,0 +1,183 @@
+use rocksdb::DB;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Written and deleted again to check that the database is writable.
+const PROBE_KEY: &[u8] = b"readiness-probe";
+
+const WEBHOOK_BACKLOG: u8 = 1 << 0;
+const TOKEN_REFRESH_FAILED: u8 = 1 << 1;
+const DB_UNWRITABLE: u8 = 1 << 2;
+
+const DEGRADATIONS: [(u8, &str); 3] = [
+	(WEBHOOK_BACKLOG, "webhook backlog"),
+	(TOKEN_REFRESH_FAILED, "github token refresh failed"),
+	(DB_UNWRITABLE, "database unwritable"),
+];
+
+/// Whether the bot should receive webhooks, served at `/readyz`.  Webhooks
+/// are handled one at a time, so a long companion update leaves the others
+/// waiting; once too many are, the bot is not ready, though still alive.
+///
+/// Readiness is kept outside the lock on `AppState`, so that it can be served
+/// while a webhook is being handled.
+pub struct Readiness {
+	/// `DEGRADATIONS` found by the last check
+	degradations: AtomicU8,
+	pending_webhooks: AtomicUsize,
+	max_pending_webhooks: usize,
+}
+
+/// Counts a webhook as pending until dropped.
+pub struct PendingWebhook<'a>(&'a Readiness);
+
+impl Drop for PendingWebhook<'_> {
+	fn drop(&mut self) {
+		self.0.pending_webhooks.fetch_sub(1, Ordering::Relaxed);
+	}
+}
+
+impl Readiness {
+	pub fn new(max_pending_webhooks: usize) -> Self {
+		Self {
+			degradations: AtomicU8::new(0),
+			pending_webhooks: AtomicUsize::new(0),
+			max_pending_webhooks,
+		}
+	}
+
+	pub fn pending_webhook(&self) -> PendingWebhook<'_> {
+		self.pending_webhooks.fetch_add(1, Ordering::Relaxed);
+		PendingWebhook(self)
+	}
+
+	pub fn pending_webhooks(&self) -> usize {
+		self.pending_webhooks.load(Ordering::Relaxed)
+	}
+
+	pub fn update(&self, db_writable: bool, token_refresh_failed: bool) {
+		let mut degradations = 0;
+		if self.pending_webhooks() > self.max_pending_webhooks {
+			degradations |= WEBHOOK_BACKLOG;
+		}
+		if token_refresh_failed {
+			degradations |= TOKEN_REFRESH_FAILED;
+		}
+		if !db_writable {
+			degradations |= DB_UNWRITABLE;
+		}
+		self.degradations.store(degradations, Ordering::Relaxed);
+	}
+
+	pub fn degradations(&self) -> Vec<&'static str> {
+		let degradations = self.degradations.load(Ordering::Relaxed);
+		DEGRADATIONS
+			.iter()
+			.filter(|(bit, _)| degradations & bit != 0)
+			.map(|(_, name)| *name)
+			.collect()
+	}
+
+	pub fn is_ready(&self) -> bool {
+		self.degradations.load(Ordering::Relaxed) == 0
+	}
+
+	pub fn payload(&self) -> serde_json::Value {
+		serde_json::json!({
+			"status": if self.is_ready() { "ready" } else { "not ready" },
+			"pending_webhooks": self.pending_webhooks(),
+			"degradations": self.degradations(),
+		})
+	}
+}
+
+pub fn db_writable(db: &DB) -> bool {
+	match db.put(PROBE_KEY, b"").and_then(|_| db.delete(PROBE_KEY)) {
+		Ok(()) => true,
+		Err(e) => {
+			log::warn!("Database is not writable: {}", e);
+			false
+		}
+	}
+}
+
+/// Check readiness every `interval`, forever.
+pub async fn monitor(
+	readiness: Arc<Readiness>,
+	db: Arc<DB>,
+	token_refresh_failed: Arc<AtomicBool>,
+	interval: Duration,
+) {
+	loop {
+		readiness.update(
+			db_writable(&db),
+			token_refresh_failed.load(Ordering::Relaxed),
+		);
+		tokio::time::delay_for(interval).await;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::db::TempDb;
+
+	#[test]
+	fn test_webhook_backlog() {
+		let readiness = Readiness::new(1);
+		let first = readiness.pending_webhook();
+		readiness.update(true, false);
+		assert!(readiness.is_ready());
+
+		let second = readiness.pending_webhook();
+		readiness.update(true, false);
+		assert!(!readiness.is_ready());
+		assert_eq!(readiness.degradations(), vec!["webhook backlog"]);
+		assert_eq!(
+			readiness.payload(),
+			serde_json::json!({
+				"status": "not ready",
+				"pending_webhooks": 2,
+				"degradations": ["webhook backlog"],
+			})
+		);
+
+		drop(first);
+		drop(second);
+		readiness.update(true, false);
+		assert!(readiness.is_ready());
+		assert_eq!(readiness.pending_webhooks(), 0);
+	}
+
+	#[test]
+	fn test_token_refresh_failed() {
+		let readiness = Readiness::new(1);
+		readiness.update(true, true);
+		assert_eq!(
+			readiness.degradations(),
+			vec!["github token refresh failed"]
+		);
+		readiness.update(true, false);
+		assert!(readiness.is_ready());
+	}
+
+	#[test]
+	fn test_db_unwritable() {
+		let readiness = Readiness::new(1);
+		readiness.update(false, true);
+		assert_eq!(
+			readiness.degradations(),
+			vec!["github token refresh failed", "database unwritable"]
+		);
+		readiness.update(true, false);
+		assert!(readiness.is_ready());
+	}
+
+	#[test]
+	fn test_db_writable() {
+		let db = TempDb::new("readiness");
+		assert!(db_writable(&db));
+		assert_eq!(db.get(PROBE_KEY).unwrap(), None);
+	}
+}
@@ -0,0 +1,203 @@
+use crate::{error::*, Result};
+use chrono::{DateTime, Utc};
+use hyper::HeaderMap;
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+const REDACTED: &str = "[redacted]";
+
+/// A verified webhook as it was received, for replaying with `replay`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Envelope {
+	pub received_at: DateTime<Utc>,
+	/// headers of the request, without signatures or tokens
+	pub headers: BTreeMap<String, String>,
+	/// the body, with any tokens or secrets redacted
+	pub payload: serde_json::Value,
+}
+
+fn is_secret(name: &str) -> bool {
+	let name = name.to_lowercase();
+	["signature", "token", "secret", "authorization", "cookie"]
+		.iter()
+		.any(|secret| name.contains(secret))
+}
+
+/// Replace the string values of keys which look like they hold a secret.
+fn redact(value: &mut serde_json::Value) {
+	match value {
+		serde_json::Value::Object(map) => {
+			for (key, value) in map.iter_mut() {
+				if is_secret(key) && value.is_string() {
+					*value = REDACTED.into();
+				} else {
+					redact(value);
+				}
+			}
+		}
+		serde_json::Value::Array(values) => values.iter_mut().for_each(redact),
+		_ => {}
+	}
+}
+
+impl Envelope {
+	pub fn new(
+		headers: &HeaderMap,
+		body: &[u8],
+		received_at: DateTime<Utc>,
+	) -> Result<Self> {
+		let mut payload = serde_json::from_slice(body).context(Json)?;
+		redact(&mut payload);
+		Ok(Self {
+			received_at,
+			headers: headers
+				.iter()
+				.filter(|(name, _)| !is_secret(name.as_str()))
+				.filter_map(|(name, value)| {
+					Some((name.to_string(), value.to_str().ok()?.to_string()))
+				})
+				.collect(),
+			payload,
+		})
+	}
+
+	fn header(&self, name: &str) -> &str {
+		self.headers.get(name).map_or("unknown", String::as_str)
+	}
+
+	/// Sorting the file names sorts the recordings by when they were
+	/// received.
+	pub fn file_name(&self) -> String {
+		format!(
+			"{}-{}-{}.json",
+			self.received_at.format("%Y%m%dT%H%M%S%.3fZ"),
+			self.header("x-github-event"),
+			self.header("x-github-delivery"),
+		)
+	}
+
+	/// Write the envelope to its own file in `dir`, creating `dir` if needed.
+	pub fn record(&self, dir: &str) -> Result<PathBuf> {
+		let path = Path::new(dir).join(self.file_name());
+		let io_error = |source| Error::Recording {
+			path: path.display().to_string(),
+			source,
+		};
+		std::fs::create_dir_all(dir).map_err(io_error)?;
+		let contents = serde_json::to_vec_pretty(self).context(Json)?;
+		std::fs::write(&path, contents).map_err(io_error)?;
+		Ok(path)
+	}
+}
+
+/// Read a recorded envelope, or all of those in a directory in the order they
+/// were received.
+pub fn load(path: &Path) -> Result<Vec<(PathBuf, Envelope)>> {
+	let io_error = |path: &Path| {
+		let path = path.display().to_string();
+		move |source| Error::Recording { path, source }
+	};
+	let mut paths = if path.is_dir() {
+		std::fs::read_dir(path)
+			.map_err(io_error(path))?
+			.map(|entry| entry.map(|entry| entry.path()))
+			.collect::<std::io::Result<Vec<_>>>()
+			.map_err(io_error(path))?
+			.into_iter()
+			.filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+			.collect()
+	} else {
+		vec![path.to_path_buf()]
+	};
+	paths.sort();
+	paths
+		.into_iter()
+		.map(|path| {
+			let contents =
+				std::fs::read(&path).map_err(io_error(path.as_path()))?;
+			let envelope = serde_json::from_slice(&contents).context(Json)?;
+			Ok((path, envelope))
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use chrono::TimeZone;
+	use hyper::header::HeaderValue;
+
+	fn headers() -> HeaderMap {
+		let mut headers = HeaderMap::new();
+		for (name, value) in &[
+			("x-github-event", "status"),
+			("x-github-delivery", "72d3162e"),
+			("x-hub-signature", "sha1=abc"),
+			("x-hub-signature-256", "sha256=abc"),
+			("authorization", "token abc"),
+			("content-type", "application/json"),
+		] {
+			headers.insert(*name, HeaderValue::from_static(value));
+		}
+		headers
+	}
+
+	#[test]
+	fn test_envelope_strips_secrets() {
+		let body = br#"{
+			"sha": "abc",
+			"installation": { "id": 1, "access_tokens_url": "https://x", "token": "ghs_abc" },
+			"hooks": [{ "secret": "hunter2", "config": { "secret_count": 2 } }]
+		}"#;
+		let received_at = Utc.ymd(2020, 11, 1).and_hms_milli(12, 30, 0, 250);
+		let envelope = Envelope::new(&headers(), body, received_at).unwrap();
+		assert_eq!(
+			envelope.headers.into_iter().collect::<Vec<_>>(),
+			vec![
+				("content-type".to_string(), "application/json".to_string()),
+				("x-github-delivery".to_string(), "72d3162e".to_string()),
+				("x-github-event".to_string(), "status".to_string()),
+			]
+		);
+		assert_eq!(
+			envelope.payload,
+			serde_json::json!({
+				"sha": "abc",
+				"installation": { "id": 1, "access_tokens_url": REDACTED, "token": REDACTED },
+				"hooks": [{ "secret": REDACTED, "config": { "secret_count": 2 } }]
+			})
+		);
+	}
+
+	#[test]
+	fn test_record_and_load() {
+		let dir = std::env::temp_dir()
+			.join(format!("processbot-recording-{}", std::process::id()));
+		let _ = std::fs::remove_dir_all(&dir);
+		let dir_str = dir.to_str().unwrap();
+		let envelope = |minute| {
+			Envelope::new(
+				&headers(),
+				br#"{ "sha": "abc" }"#,
+				Utc.ymd(2020, 11, 1).and_hms(12, minute, 0),
+			)
+			.unwrap()
+		};
+		let later = envelope(31).record(dir_str).unwrap();
+		let earlier = envelope(30).record(dir_str).unwrap();
+		assert_eq!(
+			earlier.file_name().unwrap(),
+			"20201101T123000.000Z-status-72d3162e.json"
+		);
+
+		assert_eq!(load(&later).unwrap(), vec![(later.clone(), envelope(31))]);
+		assert_eq!(
+			load(&dir).unwrap(),
+			vec![(earlier, envelope(30)), (later, envelope(31))]
+		);
+		assert!(load(&dir.join("missing.json")).is_err());
+		let _ = std::fs::remove_dir_all(&dir);
+	}
+}
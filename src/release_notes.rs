@@ -0,0 +1,355 @@
+use regex::Regex;
+
+use crate::{
+	constants::*,
+	github::{PullRequest, RepoCommit},
+	github_bot::{commit::PER_PAGE, GithubBot},
+	Result,
+};
+
+/// Pages of commits listed at most when there are more than the compare
+/// endpoint lists.
+const MAX_LISTED_PAGES: usize = 50;
+
+/// The heading of PRs without a label of any category.
+const OTHER: &str = "Other";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReleaseNotesCommand {
+	pub from: String,
+	pub to: String,
+}
+
+/// Parse `bot release-notes <from> <to>`.  Tags keep their case.
+pub fn parse_command(body: &str) -> Option<ReleaseNotesCommand> {
+	let body = body.trim();
+	let command = body.get(..RELEASE_NOTES.len())?;
+	let rest = body.get(RELEASE_NOTES.len()..)?;
+	if !command.eq_ignore_ascii_case(RELEASE_NOTES)
+		|| !rest.starts_with(char::is_whitespace)
+	{
+		return None;
+	}
+	let mut words = rest.split_whitespace();
+	match (words.next(), words.next(), words.next()) {
+		(Some(from), Some(to), None)
+			if !from.starts_with('-') && !to.starts_with('-') =>
+		{
+			Some(ReleaseNotesCommand {
+				from: from.to_string(),
+				to: to.to_string(),
+			})
+		}
+		_ => None,
+	}
+}
+
+/// The PR a merge or squash commit came from, going by the first line of its
+/// message.
+pub fn pr_number(message: &str) -> Option<i64> {
+	let re = Regex::new(r"^Merge pull request #(\d+)\b|\(#(\d+)\)\s*$")
+		.expect("pr number regex");
+	let captures = re.captures(message.lines().next()?)?;
+	captures
+		.get(1)
+		.or_else(|| captures.get(2))
+		.and_then(|number| number.as_str().parse().ok())
+}
+
+/// The PRs of `commits`, in the order they first appear.
+pub fn pr_numbers(commits: &[RepoCommit]) -> Vec<i64> {
+	let mut numbers = Vec::new();
+	for number in commits
+		.iter()
+		.filter_map(|commit| pr_number(&commit.commit.message))
+	{
+		if !numbers.contains(&number) {
+			numbers.push(number);
+		}
+	}
+	numbers
+}
+
+/// Move the commits of a listed page into `commits` up to `merge_base`.
+/// Returns whether it was reached.
+fn take_until_merge_base(
+	page: Vec<RepoCommit>,
+	merge_base: &str,
+	commits: &mut Vec<RepoCommit>,
+) -> bool {
+	for commit in page {
+		if commit.sha == merge_base {
+			return true;
+		}
+		commits.push(commit);
+	}
+	false
+}
+
+/// The commits `to` is ahead of `from` by, oldest first.  If there are more
+/// than the compare endpoint lists, the commits reachable from `to` are
+/// listed instead, down to the merge base.
+pub async fn commits(
+	github_bot: &GithubBot,
+	owner: &str,
+	repo_name: &str,
+	from: &str,
+	to: &str,
+) -> Result<Vec<RepoCommit>> {
+	let comparison = github_bot
+		.compare_commits(owner, repo_name, from, to)
+		.await?;
+	if comparison.commits.len() >= comparison.total_commits {
+		return Ok(comparison.commits);
+	}
+	log::info!(
+		"{}...{} of {}/{} has {} commits, more than can be compared; listing them instead",
+		from,
+		to,
+		owner,
+		repo_name,
+		comparison.total_commits
+	);
+	let mut commits = Vec::new();
+	for page in 1..=MAX_LISTED_PAGES {
+		let listed = github_bot.commits(owner, repo_name, to, page).await?;
+		let last = listed.len() < PER_PAGE;
+		if take_until_merge_base(
+			listed,
+			&comparison.merge_base_commit.sha,
+			&mut commits,
+		) || last
+		{
+			break;
+		}
+	}
+	commits.reverse();
+	Ok(commits)
+}
+
+/// A merged PR to be listed in the notes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Note {
+	pub number: i64,
+	pub title: String,
+	pub labels: Vec<String>,
+}
+
+impl Note {
+	pub fn new(pr: &PullRequest) -> Self {
+		Note {
+			number: pr.number,
+			title: pr.title.clone().unwrap_or_default(),
+			labels: pr.labels.iter().map(|label| label.name.clone()).collect(),
+		}
+	}
+}
+
+/// The notes of the PRs `commits` came from.  Numbers which are not those of
+/// PRs of the repository, eg. issues mentioned in a message, are skipped.
+pub async fn notes(
+	github_bot: &GithubBot,
+	owner: &str,
+	repo_name: &str,
+	commits: &[RepoCommit],
+) -> Result<Vec<Note>> {
+	let mut notes = Vec::new();
+	for number in pr_numbers(commits) {
+		match github_bot.pull_request(owner, repo_name, number).await {
+			Ok(pr) => notes.push(Note::new(&pr)),
+			Err(e) if e.is_not_found() => {
+				log::warn!(
+					"No PR #{} in {}/{} for the release notes",
+					number,
+					owner,
+					repo_name
+				);
+			}
+			Err(e) => return Err(e),
+		}
+	}
+	Ok(notes)
+}
+
+/// The heading of the first category, of `label=heading` pairs, one of whose
+/// labels the note has.
+fn category<'a>(note: &Note, categories: &'a [(String, String)]) -> &'a str {
+	categories
+		.iter()
+		.find(|(label, _)| note.labels.contains(label))
+		.map_or(OTHER, |(_, heading)| heading.as_str())
+}
+
+/// The notes under the headings of their categories, in the order the
+/// categories are configured, followed by the PRs in none of them.
+pub fn format(
+	from: &str,
+	to: &str,
+	notes: &[Note],
+	categories: &[(String, String)],
+) -> String {
+	if notes.is_empty() {
+		return format!(
+			"No pull requests were merged between `{}` and `{}`.",
+			from, to
+		);
+	}
+	let mut headings = Vec::<&str>::new();
+	for heading in categories
+		.iter()
+		.map(|(_, heading)| heading.as_str())
+		.chain(Some(OTHER))
+	{
+		if !headings.contains(&heading) {
+			headings.push(heading);
+		}
+	}
+	let mut lines = vec![format!("Changes from `{}` to `{}`:", from, to)];
+	for heading in headings {
+		let notes = notes
+			.iter()
+			.filter(|note| category(note, categories) == heading)
+			.collect::<Vec<_>>();
+		if notes.is_empty() {
+			continue;
+		}
+		lines.push(String::new());
+		lines.push(format!("### {}", heading));
+		lines.push(String::new());
+		for note in notes {
+			lines.push(format!("- {} (#{})", note.title.trim(), note.number));
+		}
+	}
+	lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::github::GitCommit;
+
+	fn commit(sha: &str, message: &str) -> RepoCommit {
+		RepoCommit {
+			sha: sha.to_string(),
+			commit: GitCommit {
+				message: message.to_string(),
+			},
+		}
+	}
+
+	fn note(number: i64, title: &str, labels: &[&str]) -> Note {
+		Note {
+			number,
+			title: title.to_string(),
+			labels: labels.iter().map(|label| label.to_string()).collect(),
+		}
+	}
+
+	fn categories() -> Vec<(String, String)> {
+		vec![
+			("B1-runtime".to_string(), "Runtime".to_string()),
+			("B3-client".to_string(), "Client".to_string()),
+			("B1-runtimenoteworthy".to_string(), "Runtime".to_string()),
+		]
+	}
+
+	#[test]
+	fn test_parse_command() {
+		assert_eq!(
+			parse_command(" Bot release-notes v0.8.25 V0.8.26 "),
+			Some(ReleaseNotesCommand {
+				from: "v0.8.25".to_string(),
+				to: "V0.8.26".to_string(),
+			})
+		);
+		assert_eq!(parse_command("bot release-notes"), None);
+		assert_eq!(parse_command("bot release-notes v0.8.25"), None);
+		assert_eq!(parse_command("bot release-notes a b c"), None);
+		assert_eq!(parse_command("bot release-notes --all v1"), None);
+		assert_eq!(parse_command("bot release-notesv1 v2"), None);
+	}
+
+	#[test]
+	fn test_pr_number() {
+		assert_eq!(
+			pr_number("Merge pull request #123 from paritytech/gav-fix\n\nFix"),
+			Some(123)
+		);
+		assert_eq!(pr_number("Fix the runtime (#7000)"), Some(7000));
+		assert_eq!(
+			pr_number("Fix the runtime (#7000)\n\n* Fix (#6999)"),
+			Some(7000)
+		);
+		// mentions are not where the PR is
+		assert_eq!(pr_number("Fix #7000 in the runtime"), None);
+		assert_eq!(pr_number("Merge branch 'master' into gav-fix"), None);
+		assert_eq!(pr_number(""), None);
+	}
+
+	#[test]
+	fn test_pr_numbers() {
+		let commits = vec![
+			commit("a", "Fix the runtime (#7000)"),
+			commit("b", "Merge branch 'master' into gav-fix"),
+			commit("c", "Merge pull request #7001 from paritytech/gav-fix"),
+			// cherry-picked again
+			commit("d", "Fix the runtime (#7000)"),
+		];
+		assert_eq!(pr_numbers(&commits), vec![7000, 7001]);
+	}
+
+	#[test]
+	fn test_take_until_merge_base() {
+		let mut commits = Vec::new();
+		assert!(!take_until_merge_base(
+			vec![commit("d", ""), commit("c", "")],
+			"a",
+			&mut commits
+		));
+		assert!(take_until_merge_base(
+			vec![commit("b", ""), commit("a", ""), commit("z", "")],
+			"a",
+			&mut commits
+		));
+		assert_eq!(
+			commits.iter().map(|c| c.sha.as_str()).collect::<Vec<_>>(),
+			vec!["d", "c", "b"]
+		);
+	}
+
+	#[test]
+	fn test_format() {
+		let notes = vec![
+			note(7000, "Fix the runtime ", &["B1-runtime", "B3-client"]),
+			note(7001, "Speed up the client", &["B3-client"]),
+			note(7002, "Update README", &["A0-pleasereview"]),
+			note(7003, "Add an extrinsic", &["B1-runtimenoteworthy"]),
+		];
+		assert_eq!(
+			format("v0.8.25", "v0.8.26", &notes, &categories()),
+			"Changes from `v0.8.25` to `v0.8.26`:
+
+### Runtime
+
+- Fix the runtime (#7000)
+- Add an extrinsic (#7003)
+
+### Client
+
+- Speed up the client (#7001)
+
+### Other
+
+- Update README (#7002)"
+		);
+		// without categories, every PR is in the same one
+		assert_eq!(
+			format("v1", "v2", &notes[1..2], &[]),
+			"Changes from `v1` to `v2`:\n\n### Other\n\n- Speed up the client (#7001)"
+		);
+		assert_eq!(
+			format("v1", "v2", &[], &categories()),
+			"No pull requests were merged between `v1` and `v2`."
+		);
+	}
+}
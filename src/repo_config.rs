@@ -0,0 +1,662 @@
+use crate::{
+	benchmark_check,
+	cache::RepoCache,
+	changelog::ChangelogRequirement,
+	config::BotConfig,
+	error,
+	github::{CheckRuns, CombinedStatus, MergeMethod},
+	github_bot::GithubBot,
+	native_queue::NativeQueuePolicy,
+	own_activity,
+	path_labels::PathLabel,
+	process,
+	rebase::RebaseStrategy,
+	sensitive_paths::SensitivePaths,
+	spec_version::SpecVersionCheck,
+	Result,
+};
+use rocksdb::DB;
+use serde::Deserialize;
+use snafu::ResultExt;
+use std::collections::HashMap;
+
+pub const REPO_CONFIG_FILE: &str = ".processbot.toml";
+
+const WARNING_KEY_PREFIX: &str = "repo-config-warning/";
+
+/// Settings a repository can override in its `.processbot.toml`.  Unset values
+/// fall back to the global `BotConfig`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RepoConfig {
+	pub min_reviewers: Option<usize>,
+	pub review_teams: Option<Vec<String>>,
+	pub lead_teams: Option<Vec<String>>,
+	pub insubstantial_labels: Option<Vec<String>>,
+	pub insubstantial_min_reviewers: Option<usize>,
+	pub blocked_labels: Option<Vec<String>>,
+	pub allowed_base_branches: Option<Vec<String>>,
+	pub ignored_checks: Option<Vec<String>>,
+	pub merge_method: Option<MergeMethod>,
+	pub rebase_strategy: Option<RebaseStrategy>,
+	pub native_merge_queue: Option<NativeQueuePolicy>,
+	pub merge_gate_status: Option<bool>,
+	pub require_changelog: Option<ChangelogRequirement>,
+	pub sensitive_paths: Option<SensitivePaths>,
+	pub spec_version: Option<SpecVersionCheck>,
+	pub path_labels: Option<Vec<PathLabel>>,
+	pub path_labels_opt_out: Option<String>,
+	/// empty to welcome nobody
+	pub welcome_template: Option<String>,
+}
+
+impl RepoConfig {
+	pub fn parse(contents: &str) -> Result<Self> {
+		toml::from_str(contents).context(error::Toml)
+	}
+
+	/// The global configuration with this repository's overrides applied.
+	pub fn apply(&self, global: &BotConfig) -> BotConfig {
+		let mut config = global.clone();
+		if let Some(min_reviewers) = self.min_reviewers {
+			config.min_reviewers = min_reviewers;
+		}
+		if let Some(review_teams) = &self.review_teams {
+			config.review_teams = review_teams.clone();
+		}
+		if let Some(lead_teams) = &self.lead_teams {
+			config.lead_teams = lead_teams.clone();
+		}
+		if let Some(insubstantial_labels) = &self.insubstantial_labels {
+			config.insubstantial_labels = insubstantial_labels.clone();
+		}
+		if let Some(min_reviewers) = self.insubstantial_min_reviewers {
+			config.insubstantial_min_reviewers = min_reviewers;
+		}
+		if let Some(blocked_labels) = &self.blocked_labels {
+			config.blocked_labels = blocked_labels.clone();
+		}
+		if let Some(branches) = &self.allowed_base_branches {
+			config.allowed_base_branches = branches.clone();
+		}
+		if let Some(ignored_checks) = &self.ignored_checks {
+			config.ignored_checks = ignored_checks.clone();
+		}
+		if let Some(merge_method) = self.merge_method {
+			config.merge_method = merge_method;
+		}
+		if let Some(rebase_strategy) = self.rebase_strategy {
+			config.rebase_strategy = rebase_strategy;
+		}
+		if let Some(policy) = self.native_merge_queue {
+			config.native_merge_queue = policy;
+		}
+		if let Some(merge_gate_status) = self.merge_gate_status {
+			config.merge_gate_status = merge_gate_status;
+		}
+		if let Some(require_changelog) = &self.require_changelog {
+			config.require_changelog = Some(require_changelog.clone());
+		}
+		if let Some(sensitive_paths) = &self.sensitive_paths {
+			config.sensitive_paths = Some(sensitive_paths.clone());
+		}
+		if let Some(spec_version) = &self.spec_version {
+			config.spec_version = Some(spec_version.clone());
+		}
+		if let Some(path_labels) = &self.path_labels {
+			config.path_labels = path_labels.clone();
+		}
+		if let Some(opt_out) = &self.path_labels_opt_out {
+			config.path_labels_opt_out = opt_out.clone();
+		}
+		if let Some(path) = &self.welcome_template {
+			config.welcome_template =
+				Some(path.clone()).filter(|path| !path.is_empty());
+		}
+		config
+	}
+}
+
+/// Parse `ORG_OVERRIDES`: an inline TOML array of tables, each with the `org`
+/// it applies to and keys of `.processbot.toml`.  Returns the overrides by
+/// lower-case organization.
+pub fn parse_org_overrides(
+	s: &str,
+) -> std::result::Result<HashMap<String, RepoConfig>, String> {
+	#[derive(Deserialize)]
+	struct Overrides {
+		org_overrides: Vec<toml::value::Table>,
+	}
+	toml::from_str::<Overrides>(&format!("org_overrides = {}", s))
+		.map_err(|e| e.to_string())?
+		.org_overrides
+		.into_iter()
+		.map(|mut table| {
+			let org = match table.remove("org") {
+				Some(toml::Value::String(org)) => org.to_lowercase(),
+				_ => return Err("override without an org".to_string()),
+			};
+			let config = toml::Value::Table(table)
+				.try_into::<RepoConfig>()
+				.map_err(|e| format!("{}: {}", org, e))?;
+			Ok((org, config))
+		})
+		.collect()
+}
+
+/// A fetched `.processbot.toml`: the parsed overrides, or why they could not
+/// be parsed, along with the blob sha of the file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FetchedRepoConfig {
+	pub sha: Option<String>,
+	pub config: std::result::Result<RepoConfig, String>,
+}
+
+pub type RepoConfigCache = RepoCache<FetchedRepoConfig>;
+
+/// Fetch the `.processbot.toml` of a repository from its default branch.  A
+/// repository without one has no overrides.
+pub async fn fetch(
+	github_bot: &GithubBot,
+	owner: &str,
+	repo_name: &str,
+) -> Result<FetchedRepoConfig> {
+	let default_branch = github_bot.default_branch(owner, repo_name).await?;
+	match github_bot
+		.contents(owner, repo_name, REPO_CONFIG_FILE, &default_branch)
+		.await
+	{
+		Ok(contents) => Ok(FetchedRepoConfig {
+			config: process::decode_contents(&contents)
+				.and_then(|s| RepoConfig::parse(&s))
+				.map_err(|e| e.to_string()),
+			sha: Some(contents.sha),
+		}),
+		Err(e) if e.is_not_found() => Ok(FetchedRepoConfig {
+			sha: None,
+			config: Ok(RepoConfig::default()),
+		}),
+		Err(e) => Err(e),
+	}
+}
+
+/// Return the configuration for a repository, from the cache if possible.
+/// Falls back to the global configuration if the repository's file cannot be
+/// fetched or is invalid.
+pub async fn get(
+	github_bot: &GithubBot,
+	cache: &RepoConfigCache,
+	global: &BotConfig,
+	owner: &str,
+	repo_name: &str,
+	now: i64,
+) -> (BotConfig, FetchedRepoConfig) {
+	let fetched = match cache.get(owner, repo_name, now) {
+		Some(fetched) => fetched,
+		None => match fetch(github_bot, owner, repo_name).await {
+			Ok(fetched) => {
+				cache.insert(owner, repo_name, fetched.clone(), now);
+				fetched
+			}
+			Err(e) => {
+				log::error!(
+					"Error fetching {} for {}/{}: {}",
+					REPO_CONFIG_FILE,
+					owner,
+					repo_name,
+					e
+				);
+				cache
+					.get_stale(owner, repo_name)
+					.unwrap_or(FetchedRepoConfig {
+						sha: None,
+						config: Ok(RepoConfig::default()),
+					})
+			}
+		},
+	};
+	let global = &global.for_org(owner);
+	let config = match &fetched.config {
+		Ok(overrides) => overrides.apply(global),
+		Err(e) => {
+			log::warn!(
+				"Invalid {} in {}/{}; using global configuration: {}",
+				REPO_CONFIG_FILE,
+				owner,
+				repo_name,
+				e
+			);
+			global.clone()
+		}
+	};
+	(config, fetched)
+}
+
+fn warning_key(owner: &str, repo_name: &str) -> String {
+	format!("{}{}/{}", WARNING_KEY_PREFIX, owner, repo_name)
+}
+
+/// Whether a warning about this version of the repository's file still has to
+/// be posted.
+pub fn warning_due(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+	sha: &str,
+) -> Result<bool> {
+	let warned = db
+		.get(warning_key(owner, repo_name).as_bytes())
+		.context(error::Db)?;
+	Ok(warned.as_deref() != Some(sha.as_bytes()))
+}
+
+pub fn record_warning(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+	sha: &str,
+) -> Result<()> {
+	db.put(warning_key(owner, repo_name).as_bytes(), sha.as_bytes())
+		.context(error::Db)
+}
+
+pub fn warning_message(error: &str) -> String {
+	format!(
+		"`{}` is invalid, so the global configuration is used for this repository until it is fixed:\n\n```\n{}\n```",
+		REPO_CONFIG_FILE, error
+	)
+}
+
+/// Drop statuses whose context is ignored, and the bot's own, eg. the merge
+/// gate, and recompute the combined state from the remaining ones.
+pub fn without_ignored_statuses(
+	mut status: CombinedStatus,
+	ignored: &[String],
+) -> CombinedStatus {
+	let before = status.statuses.len();
+	status.statuses.retain(|s| {
+		!ignored.contains(&s.context)
+			&& !own_activity::is_own_context(&s.context)
+	});
+	if status.statuses.len() == before {
+		return status;
+	}
+	status.recompute_state();
+	status
+}
+
+/// Drop check runs whose name is ignored, and the bot's own benchmarks.
+pub fn without_ignored_checks(
+	mut checks: CheckRuns,
+	ignored: &[String],
+) -> CheckRuns {
+	checks.check_runs.retain(|c| {
+		!ignored.contains(&c.name) && c.name != benchmark_check::NAME
+	});
+	checks.total_count = checks.check_runs.len() as i64;
+	checks
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		github::{CheckRun, Status, StatusState},
+		merge_gate,
+	};
+
+	fn global() -> BotConfig {
+		BotConfig {
+			min_reviewers: 2,
+			blocked_labels: vec!["A1-onice".to_string()],
+			merge_method: MergeMethod::Squash,
+			..BotConfig::default()
+		}
+	}
+
+	#[test]
+	fn test_parse() {
+		assert_eq!(RepoConfig::parse("").unwrap(), RepoConfig::default());
+		assert_eq!(
+			RepoConfig::parse(
+				r#"
+min_reviewers = 1
+blocked_labels = ["A3-inprogress"]
+allowed_base_branches = ["master", "release-*"]
+ignored_checks = ["continuous-integration/gitlab-check-labels"]
+merge_method = "merge"
+rebase_strategy = "rebase"
+native_merge_queue = "proceed"
+
+path_labels_opt_out = "A9-no-labels"
+welcome_template = ".github/WELCOME.md"
+
+[require_changelog]
+path = "docs/changelog/*.md"
+exempt_labels = ["B0-silent"]
+
+[sensitive_paths]
+paths = ["frame/*/src/migrations*"]
+label = "D1-audited"
+team = "runtime-audit"
+
+[spec_version]
+block = true
+
+[[path_labels]]
+pattern = "frame/"
+label = "A-runtime"
+"#
+			)
+			.unwrap(),
+			RepoConfig {
+				min_reviewers: Some(1),
+				blocked_labels: Some(vec!["A3-inprogress".to_string()]),
+				allowed_base_branches: Some(vec![
+					"master".to_string(),
+					"release-*".to_string()
+				]),
+				ignored_checks: Some(vec![
+					"continuous-integration/gitlab-check-labels".to_string()
+				]),
+				merge_method: Some(MergeMethod::Merge),
+				rebase_strategy: Some(RebaseStrategy::Rebase),
+				native_merge_queue: Some(NativeQueuePolicy::Proceed),
+				require_changelog: Some(ChangelogRequirement {
+					path: "docs/changelog/*.md".to_string(),
+					exempt_labels: vec!["B0-silent".to_string()],
+				}),
+				sensitive_paths: Some(SensitivePaths {
+					paths: vec!["frame/*/src/migrations*".to_string()],
+					label: "D1-audited".to_string(),
+					team: "runtime-audit".to_string(),
+				}),
+				spec_version: Some(SpecVersionCheck {
+					runtimes: vec!["runtime/*".to_string()],
+					block: true,
+				}),
+				path_labels: Some(vec![PathLabel {
+					pattern: "frame/".to_string(),
+					label: "A-runtime".to_string(),
+				}]),
+				path_labels_opt_out: Some("A9-no-labels".to_string()),
+				welcome_template: Some(".github/WELCOME.md".to_string()),
+				..RepoConfig::default()
+			}
+		);
+		assert_eq!(
+			RepoConfig::parse(
+				"require_changelog = { path = \"CHANGELOG.md\" }"
+			)
+			.unwrap()
+			.require_changelog
+			.unwrap()
+			.exempt_labels,
+			Vec::<String>::new()
+		);
+	}
+
+	#[test]
+	fn test_parse_invalid() {
+		// unknown keys are rejected rather than silently ignored
+		assert!(RepoConfig::parse("min_reviewer = 1").is_err());
+		assert!(RepoConfig::parse("min_reviewers = -1").is_err());
+		assert!(RepoConfig::parse("merge_method = \"fast-forward\"").is_err());
+		assert!(RepoConfig::parse("rebase_strategy = \"squash\"").is_err());
+		assert!(RepoConfig::parse("min_reviewers = ").is_err());
+		assert!(RepoConfig::parse("[require_changelog]").is_err());
+		assert!(RepoConfig::parse(
+			"require_changelog = { path = \"CHANGELOG.md\", exempt = [] }"
+		)
+		.is_err());
+	}
+
+	#[test]
+	fn test_parse_org_overrides() {
+		let overrides = parse_org_overrides(
+			r#"[{ org = "ParityTech-Stg", min_reviewers = 1 }, { org = "tetcoin", merge_method = "merge" }]"#,
+		)
+		.unwrap();
+		assert_eq!(overrides["paritytech-stg"].min_reviewers, Some(1));
+		assert_eq!(overrides["tetcoin"].merge_method, Some(MergeMethod::Merge));
+		assert!(parse_org_overrides(r#"[{ min_reviewers = 1 }]"#).is_err());
+		assert!(parse_org_overrides(
+			r#"[{ org = "tetcoin", min_reviewer = 1 }]"#
+		)
+		.is_err());
+	}
+
+	#[test]
+	fn test_org_then_repo_overrides() {
+		let mut global = global();
+		global.org_overrides = parse_org_overrides(
+			r#"[{ org = "tetcoin", min_reviewers = 1, blocked_labels = [] }]"#,
+		)
+		.unwrap();
+		let repo = RepoConfig {
+			min_reviewers: Some(3),
+			..RepoConfig::default()
+		};
+		let config = repo.apply(&global.for_org("Tetcoin"));
+		assert_eq!(config.min_reviewers, 3);
+		assert!(config.blocked_labels.is_empty());
+		// other organizations are left alone
+		assert_eq!(
+			global.for_org("paritytech").blocked_labels,
+			vec!["A1-onice"]
+		);
+	}
+
+	#[test]
+	fn test_apply() {
+		let global = global();
+		let config = RepoConfig::default().apply(&global);
+		assert_eq!(config.min_reviewers, 2);
+		assert_eq!(config.blocked_labels, vec!["A1-onice"]);
+		assert!(config.ignored_checks.is_empty());
+		assert_eq!(config.merge_method, MergeMethod::Squash);
+
+		let config = RepoConfig {
+			min_reviewers: Some(1),
+			ignored_checks: Some(vec!["check-labels".to_string()]),
+			..RepoConfig::default()
+		}
+		.apply(&global);
+		assert_eq!(config.min_reviewers, 1);
+		assert_eq!(config.blocked_labels, vec!["A1-onice"]);
+		assert_eq!(config.ignored_checks, vec!["check-labels"]);
+		assert_eq!(config.merge_method, MergeMethod::Squash);
+
+		// an empty list overrides the global one
+		let config = RepoConfig {
+			blocked_labels: Some(vec![]),
+			merge_method: Some(MergeMethod::Rebase),
+			..RepoConfig::default()
+		}
+		.apply(&global);
+		assert!(config.blocked_labels.is_empty());
+		assert_eq!(config.merge_method, MergeMethod::Rebase);
+		assert_eq!(config.min_reviewers, 2);
+
+		assert_eq!(
+			RepoConfig::default().apply(&global).rebase_strategy,
+			RebaseStrategy::MergeCommit
+		);
+		let config = RepoConfig {
+			rebase_strategy: Some(RebaseStrategy::Rebase),
+			..RepoConfig::default()
+		}
+		.apply(&global);
+		assert_eq!(config.rebase_strategy, RebaseStrategy::Rebase);
+		assert_eq!(config.native_merge_queue, NativeQueuePolicy::Refuse);
+		let config = RepoConfig {
+			native_merge_queue: Some(NativeQueuePolicy::Proceed),
+			..RepoConfig::default()
+		}
+		.apply(&global);
+		assert_eq!(config.native_merge_queue, NativeQueuePolicy::Proceed);
+		assert!(!config.merge_gate_status);
+		let config = RepoConfig {
+			merge_gate_status: Some(true),
+			..RepoConfig::default()
+		}
+		.apply(&global);
+		assert!(config.merge_gate_status);
+		assert!(config.require_changelog.is_none());
+		assert!(config.sensitive_paths.is_none());
+		assert!(config.spec_version.is_none());
+		assert!(config.path_labels.is_empty());
+
+		let global = BotConfig {
+			welcome_template: Some(".github/WELCOME.md".to_string()),
+			..global
+		};
+		assert_eq!(
+			RepoConfig::default().apply(&global).welcome_template,
+			global.welcome_template
+		);
+		let config = RepoConfig {
+			welcome_template: Some(String::new()),
+			..RepoConfig::default()
+		}
+		.apply(&global);
+		assert_eq!(config.welcome_template, None);
+	}
+
+	#[test]
+	fn test_apply_teams() {
+		let global = BotConfig {
+			review_teams: vec!["core-devs".to_string()],
+			lead_teams: vec!["substrateteamleads".to_string()],
+			insubstantial_labels: vec!["insubstantial".to_string()],
+			insubstantial_min_reviewers: 1,
+			..global()
+		};
+		let config = RepoConfig::parse(
+			r#"
+review_teams = ["runtime-devs", "core-devs"]
+lead_teams = []
+insubstantial_labels = ["B0-silent"]
+"#,
+		)
+		.unwrap()
+		.apply(&global);
+		assert_eq!(config.review_teams, vec!["runtime-devs", "core-devs"]);
+		// no lead team, so nobody can bypass the review teams
+		assert!(config.lead_teams.is_empty());
+		assert_eq!(config.insubstantial_labels, vec!["B0-silent"]);
+		assert_eq!(config.insubstantial_min_reviewers, 1);
+
+		let config = RepoConfig::default().apply(&global);
+		assert_eq!(config.review_teams, vec!["core-devs"]);
+		assert_eq!(config.lead_teams, vec!["substrateteamleads"]);
+	}
+
+	#[test]
+	fn test_repo_config_cache() {
+		let cache = RepoConfigCache::new(60);
+		let fetched = FetchedRepoConfig {
+			sha: Some("abc".to_string()),
+			config: Err("invalid".to_string()),
+		};
+		cache.insert("paritytech", "polkadot", fetched.clone(), 100);
+		assert_eq!(cache.get("paritytech", "polkadot", 160), Some(fetched));
+		// a merged change to the file drops the cached version
+		assert!(cache.invalidate("paritytech", "polkadot"));
+		assert_eq!(cache.get("paritytech", "polkadot", 100), None);
+	}
+
+	fn status(context: &str, state: StatusState) -> Status {
+		Status {
+			id: None,
+			node_id: None,
+			avatar_url: None,
+			url: None,
+			created_at: None,
+			updated_at: None,
+			state,
+			creator: None,
+			context: context.to_string(),
+			target_url: None,
+		}
+	}
+
+	#[test]
+	fn test_without_ignored_statuses() {
+		let combined = CombinedStatus {
+			state: StatusState::Failure,
+			sha: "abc".to_string(),
+			total_count: 3,
+			statuses: vec![
+				status("gitlab-ci", StatusState::Success),
+				status("check-labels", StatusState::Failure),
+				status("vulnerability-scan", StatusState::Pending),
+			],
+		};
+		assert_eq!(without_ignored_statuses(combined.clone(), &[]), combined);
+
+		let filtered = without_ignored_statuses(
+			combined.clone(),
+			&["check-labels".to_string()],
+		);
+		assert_eq!(filtered.state, StatusState::Pending);
+		assert_eq!(filtered.total_count, 2);
+
+		let filtered = without_ignored_statuses(
+			combined,
+			&["check-labels".to_string(), "vulnerability-scan".to_string()],
+		);
+		assert_eq!(filtered.state, StatusState::Success);
+		assert_eq!(
+			filtered.statuses,
+			vec![status("gitlab-ci", StatusState::Success)]
+		);
+
+		// the merge gate waits for the others, not the other way round
+		let gated = CombinedStatus {
+			state: StatusState::Pending,
+			sha: "abc".to_string(),
+			total_count: 2,
+			statuses: vec![
+				status("gitlab-ci", StatusState::Success),
+				status(merge_gate::CONTEXT, StatusState::Pending),
+			],
+		};
+		let filtered = without_ignored_statuses(gated, &[]);
+		assert_eq!(filtered.state, StatusState::Success);
+		assert_eq!(filtered.total_count, 1);
+	}
+
+	#[test]
+	fn test_without_ignored_checks() {
+		let check = |name: &str| CheckRun {
+			id: 1,
+			name: name.to_string(),
+			status: "completed".to_string(),
+			conclusion: Some("success".to_string()),
+			head_sha: "abc".to_string(),
+			pull_requests: vec![],
+			html_url: None,
+			started_at: None,
+		};
+		let checks = CheckRuns {
+			total_count: 2,
+			check_runs: vec![check("build"), check("lint")],
+		};
+		let filtered =
+			without_ignored_checks(checks.clone(), &["lint".to_string()]);
+		assert_eq!(filtered.total_count, 1);
+		assert_eq!(filtered.check_runs, vec![check("build")]);
+		assert_eq!(without_ignored_checks(checks.clone(), &[]), checks);
+		// the bot's own benchmarks are never waited for
+		let with_benchmarks = CheckRuns {
+			total_count: 3,
+			check_runs: vec![
+				check("build"),
+				check("lint"),
+				check(benchmark_check::NAME),
+			],
+		};
+		assert_eq!(without_ignored_checks(with_benchmarks, &[]), checks);
+	}
+}
@@ -0,0 +1,113 @@
+use url::Url;
+
+/// A repository, as named in a URL.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RepoRef {
+	pub owner: String,
+	pub repo: String,
+}
+
+impl RepoRef {
+	/// The repository of a web URL, eg. the `html_url` of a PR, or an API URL,
+	/// eg. the `repository_url` of an issue, on whichever host: GitHub
+	/// Enterprise serves the API under `/api/v3`.  The query, fragment and
+	/// trailing slashes are ignored.
+	pub fn from_url(url: &str) -> Option<Self> {
+		let url = Url::parse(url).ok()?;
+		let api_host = url.host_str()?.starts_with("api.");
+		let segments = url
+			.path_segments()?
+			.filter(|segment| !segment.is_empty())
+			.collect::<Vec<_>>();
+		let (owner, repo) = match segments.as_slice() {
+			["api", "v3", "repos", owner, repo, ..]
+			| ["repos", owner, repo, ..]
+				if api_host || segments[0] == "api" =>
+			{
+				(owner, repo)
+			}
+			["api", ..] => return None,
+			_ if api_host => return None,
+			[owner, repo, ..] => (owner, repo),
+			_ => return None,
+		};
+		let repo = repo.strip_suffix(".git").unwrap_or(repo);
+		if repo.is_empty() {
+			return None;
+		}
+		Some(Self {
+			owner: owner.to_string(),
+			repo: repo.to_string(),
+		})
+	}
+}
+
+impl std::fmt::Display for RepoRef {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "{}/{}", self.owner, self.repo)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_from_url() {
+		let substrate = Some(RepoRef {
+			owner: "paritytech".to_string(),
+			repo: "substrate".to_string(),
+		});
+		for (url, expected) in &[
+			("https://github.com/paritytech/substrate/pull/1234", &substrate),
+			("https://github.com/paritytech/substrate/pull/1234/", &substrate),
+			(
+				"https://github.com/paritytech/substrate/pull/1234?notification_referrer_id=1#issuecomment-2",
+				&substrate,
+			),
+			("https://github.com/paritytech/substrate", &substrate),
+			("https://github.com/paritytech/substrate/", &substrate),
+			("https://github.com/paritytech/substrate.git", &substrate),
+			("https://github.com//paritytech//substrate", &substrate),
+			("https://api.github.com/repos/paritytech/substrate", &substrate),
+			(
+				"https://api.github.com/repos/paritytech/substrate/issues/1234",
+				&substrate,
+			),
+			(
+				"https://api.github.com/repos/paritytech/substrate/?per_page=1",
+				&substrate,
+			),
+			(
+				"https://github.example.com/paritytech/substrate/pull/1234",
+				&substrate,
+			),
+			(
+				"https://github.example.com/api/v3/repos/paritytech/substrate",
+				&substrate,
+			),
+			(
+				"http://localhost:8080/api/v3/repos/paritytech/substrate/pulls/1",
+				&substrate,
+			),
+			("https://github.com/paritytech", &None),
+			("https://github.com/", &None),
+			("https://api.github.com/orgs/paritytech/members", &None),
+			("https://api.github.com/repos/paritytech", &None),
+			("https://github.example.com/api/v3/orgs/paritytech", &None),
+			("https://github.com/paritytech/.git", &None),
+			("github.com/paritytech/substrate", &None),
+			("", &None),
+		] {
+			assert_eq!(&RepoRef::from_url(url), *expected, "{}", url);
+		}
+	}
+
+	#[test]
+	fn test_display() {
+		let repo =
+			RepoRef::from_url("https://github.com/paritytech/polkadot-sdk")
+				.unwrap();
+		assert_eq!(repo.to_string(), "paritytech/polkadot-sdk");
+	}
+}
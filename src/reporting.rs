@@ -0,0 +1,229 @@
+use curl::easy::{Easy, List};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::Duration;
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// How long reporting an error may hold up the bot.
+const REPORT_TIMEOUT_SECS: u64 = 5;
+
+/// Where errors which would otherwise only be logged are sent.  Without one
+/// configured, no reports are built at all.
+pub trait ErrorSink: Send + Sync {
+	fn report(&self, report: &ErrorReport);
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorReport {
+	pub message: String,
+	pub tags: BTreeMap<&'static str, String>,
+}
+
+/// What the bot was doing when an error occurred.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ErrorContext {
+	/// `X-GitHub-Event`, or `gitlab` for Gitlab webhooks
+	pub event: Option<String>,
+	/// `X-GitHub-Delivery`
+	pub delivery: Option<String>,
+	/// `owner/repo`
+	pub repo: Option<String>,
+	pub pr: Option<i64>,
+	/// the bot command being handled, eg. `bot merge force`
+	pub command: Option<String>,
+}
+
+impl ErrorContext {
+	pub fn with_pr(&self, owner: &str, repo_name: &str, number: i64) -> Self {
+		Self {
+			repo: Some(format!("{}/{}", owner, repo_name)),
+			pr: Some(number),
+			..self.clone()
+		}
+	}
+
+	pub fn report(&self, message: impl Display) -> ErrorReport {
+		let mut tags = BTreeMap::new();
+		tags.insert("version", VERSION.to_string());
+		let optional = vec![
+			("event", self.event.clone()),
+			("delivery", self.delivery.clone()),
+			("repo", self.repo.clone()),
+			("pr", self.pr.map(|pr| pr.to_string())),
+			("command", self.command.clone()),
+		];
+		for (tag, value) in optional {
+			if let Some(value) = value {
+				tags.insert(tag, value);
+			}
+		}
+		ErrorReport {
+			message: message.to_string(),
+			tags,
+		}
+	}
+}
+
+/// A Sentry DSN, eg. `https://<key>@sentry.example.com/<project>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SentryDsn {
+	store_url: String,
+	public_key: String,
+}
+
+impl FromStr for SentryDsn {
+	type Err = String;
+
+	fn from_str(dsn: &str) -> Result<Self, String> {
+		let invalid =
+			|| format!("expected https://<key>@<host>/<project>: {}", dsn);
+		let url = url::Url::parse(dsn).map_err(|_| invalid())?;
+		let path = url.path().trim_end_matches('/');
+		let split = path.rfind('/').ok_or_else(invalid)?;
+		let (prefix, project) = (&path[..split], &path[split + 1..]);
+		if url.username().is_empty() || project.is_empty() {
+			return Err(invalid());
+		}
+		let host = match url.port() {
+			Some(port) => {
+				format!("{}:{}", url.host_str().ok_or_else(invalid)?, port)
+			}
+			None => url.host_str().ok_or_else(invalid)?.to_string(),
+		};
+		Ok(Self {
+			store_url: format!(
+				"{}://{}{}/api/{}/store/",
+				url.scheme(),
+				host,
+				prefix,
+				project
+			),
+			public_key: url.username().to_string(),
+		})
+	}
+}
+
+/// Sends errors to Sentry's store endpoint.  Sending blocks for at most
+/// `REPORT_TIMEOUT_SECS`, so that reports of panics get out before exiting.
+pub struct SentrySink {
+	dsn: SentryDsn,
+}
+
+impl SentrySink {
+	pub fn new(dsn: SentryDsn) -> Self {
+		Self { dsn }
+	}
+
+	fn event(report: &ErrorReport) -> serde_json::Value {
+		let mut id = [0u8; 16];
+		let _ = ring::rand::SecureRandom::fill(
+			&ring::rand::SystemRandom::new(),
+			&mut id,
+		);
+		serde_json::json!({
+			"event_id": base16::encode_lower(&id),
+			"timestamp": chrono::Utc::now().to_rfc3339(),
+			"platform": "other",
+			"level": "error",
+			"logger": "parity-processbot",
+			"release": VERSION,
+			"message": report.message,
+			"tags": report.tags,
+		})
+	}
+
+	fn send(&self, report: &ErrorReport) -> Result<u32, curl::Error> {
+		let mut headers = List::new();
+		headers.append("Content-Type: application/json")?;
+		headers.append(&format!(
+			"X-Sentry-Auth: Sentry sentry_version=7, sentry_client=parity-processbot/{}, sentry_key={}",
+			VERSION, self.dsn.public_key
+		))?;
+		let mut handle = Easy::new();
+		handle.url(&self.dsn.store_url)?;
+		handle.http_headers(headers)?;
+		handle.timeout(Duration::from_secs(REPORT_TIMEOUT_SECS))?;
+		handle.post_fields_copy(Self::event(report).to_string().as_bytes())?;
+		handle.perform()?;
+		handle.response_code()
+	}
+}
+
+impl ErrorSink for SentrySink {
+	fn report(&self, report: &ErrorReport) {
+		match self.send(report) {
+			Ok(status) if status < 300 => {}
+			Ok(status) => {
+				log::warn!("Sentry rejected an error report: {}", status)
+			}
+			Err(e) => log::warn!("Error reporting to Sentry: {}", e),
+		}
+	}
+}
+
+/// Collects reports in memory, for tests.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemorySink {
+	pub reports: parking_lot::Mutex<Vec<ErrorReport>>,
+}
+
+#[cfg(test)]
+impl ErrorSink for MemorySink {
+	fn report(&self, report: &ErrorReport) {
+		self.reports.lock().push(report.clone());
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_parse_dsn() {
+		assert_eq!(
+			"https://abc123@sentry.parity.io/42".parse(),
+			Ok(SentryDsn {
+				store_url: "https://sentry.parity.io/api/42/store/".to_string(),
+				public_key: "abc123".to_string(),
+			})
+		);
+		assert_eq!(
+			"http://abc123@localhost:9000/sentry/7/".parse(),
+			Ok(SentryDsn {
+				store_url: "http://localhost:9000/sentry/api/7/store/"
+					.to_string(),
+				public_key: "abc123".to_string(),
+			})
+		);
+		assert!("https://sentry.parity.io/42".parse::<SentryDsn>().is_err());
+		assert!("https://abc@sentry.parity.io".parse::<SentryDsn>().is_err());
+		assert!("sentry".parse::<SentryDsn>().is_err());
+	}
+
+	#[test]
+	fn test_report_tags() {
+		let context = ErrorContext {
+			event: Some("issue_comment".to_string()),
+			delivery: Some("72d3162e".to_string()),
+			..ErrorContext::default()
+		};
+		let report = context
+			.with_pr("paritytech", "substrate", 1234)
+			.report("boom");
+		assert_eq!(report.message, "boom");
+		assert_eq!(
+			report.tags.into_iter().collect::<Vec<_>>(),
+			vec![
+				("delivery", "72d3162e".to_string()),
+				("event", "issue_comment".to_string()),
+				("pr", "1234".to_string()),
+				("repo", "paritytech/substrate".to_string()),
+				("version", VERSION.to_string()),
+			]
+		);
+		assert_eq!(ErrorContext::default().report("boom").tags.len(), 1);
+	}
+}
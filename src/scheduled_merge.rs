@@ -0,0 +1,381 @@
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
+use rocksdb::DB;
+
+use crate::{
+	constants::*,
+	db,
+	queue::{self, MergeRequest},
+	Result,
+};
+
+/// How far ahead a merge can be scheduled.
+pub const MAX_AHEAD_DAYS: i64 = 14;
+
+/// Seconds between two looks for merges which are due.
+pub const SWEEP_INTERVAL_SECS: u64 = 60;
+
+/// The formats of an absolute time, after `normalize`.
+const FORMATS: [&str; 2] = ["%Y-%m-%dT%H:%M:%S%z", "%Y-%m-%dT%H:%M%z"];
+
+/// Parse `bot merge at`, followed by when to merge.  Returns the time, in
+/// UTC.
+pub fn parse_command(
+	body: &str,
+	now: DateTime<Utc>,
+) -> Option<std::result::Result<DateTime<Utc>, String>> {
+	let body = body.trim();
+	let rest = body
+		.get(AUTO_MERGE_AT.len()..)
+		.filter(|_| body.to_lowercase().starts_with(AUTO_MERGE_AT))?;
+	if rest.trim().is_empty() {
+		Some(Err(format!(
+			"expected a time after `{}`, eg. `2024-05-01T14:00Z` or `in 3h`",
+			AUTO_MERGE_AT
+		)))
+	} else if rest.starts_with(char::is_whitespace) {
+		Some(parse_time(rest, now))
+	} else {
+		None
+	}
+}
+
+/// Parse a time of the future: `2024-05-01T14:00Z`, with or without seconds,
+/// with a UTC offset such as `+02:00` instead of `Z`, or `UTC` after a space;
+/// or a time relative to `now`, eg. `in 3h` or `in 1d 12h 30m`.
+pub fn parse_time(
+	time: &str,
+	now: DateTime<Utc>,
+) -> std::result::Result<DateTime<Utc>, String> {
+	let time = time.trim();
+	let at = match time.get(..3) {
+		Some(head) if head.eq_ignore_ascii_case("in ") => {
+			now + parse_delay(&time[3..])?
+		}
+		_ => parse_absolute(time)?,
+	};
+	if at <= now {
+		Err(format!("{} has already passed", describe(at)))
+	} else if at - now > Duration::days(MAX_AHEAD_DAYS) {
+		Err(format!(
+			"{} is more than {} days ahead",
+			describe(at),
+			MAX_AHEAD_DAYS
+		))
+	} else {
+		Ok(at)
+	}
+}
+
+/// Parse a delay of days, hours and minutes, eg. `1d 12h` or `90m`.
+fn parse_delay(delay: &str) -> std::result::Result<Duration, String> {
+	let invalid = || {
+		format!(
+			"expected a delay such as `3h` or `1d 12h 30m`: {}",
+			delay.trim()
+		)
+	};
+	let compact = delay
+		.chars()
+		.filter(|c| !c.is_whitespace())
+		.collect::<String>()
+		.to_lowercase();
+	if compact.is_empty() {
+		return Err(invalid());
+	}
+	let mut total = Duration::zero();
+	let mut count = String::new();
+	for c in compact.chars() {
+		if c.is_ascii_digit() {
+			count.push(c);
+			continue;
+		}
+		let count = std::mem::take(&mut count)
+			.parse::<i64>()
+			.ok()
+			.filter(|count| *count <= MAX_AHEAD_DAYS * 24 * 60)
+			.ok_or_else(invalid)?;
+		total = total
+			+ match c {
+				'd' => Duration::days(count),
+				'h' => Duration::hours(count),
+				'm' => Duration::minutes(count),
+				_ => return Err(invalid()),
+			};
+	}
+	if count.is_empty() {
+		Ok(total)
+	} else {
+		// a count without its unit
+		Err(invalid())
+	}
+}
+
+/// The time with `T` between the date and the time of day, and its zone as an
+/// offset.
+fn normalize(time: &str) -> String {
+	let time = time.trim().to_uppercase();
+	let time = match time.strip_suffix("UTC") {
+		Some(time) => format!("{}+00:00", time.trim_end()),
+		None => time,
+	};
+	let time = match time.strip_suffix('Z') {
+		Some(time) => format!("{}+00:00", time),
+		None => time,
+	};
+	time.replacen(' ', "T", 1)
+}
+
+fn parse_absolute(time: &str) -> std::result::Result<DateTime<Utc>, String> {
+	let normalized = normalize(time);
+	if let Some(at) = FORMATS
+		.iter()
+		.find_map(|format| DateTime::parse_from_str(&normalized, format).ok())
+	{
+		return Ok(at.with_timezone(&Utc));
+	}
+	let naive = FORMATS.iter().any(|format| {
+		NaiveDateTime::parse_from_str(
+			&normalized,
+			format.trim_end_matches("%z"),
+		)
+		.is_ok()
+	});
+	if naive {
+		Err(format!(
+			"`{}` has no time zone; add one, eg. `{}Z` for UTC or `{}+02:00`",
+			time, time, time
+		))
+	} else {
+		Err(format!(
+			"expected a time such as `2024-05-01T14:00Z` or `in 3h`: {}",
+			time
+		))
+	}
+}
+
+/// The time as shown in comments, eg. `2024-05-01 14:00 UTC`.
+pub fn describe(at: DateTime<Utc>) -> String {
+	at.format("%Y-%m-%d %H:%M UTC").to_string()
+}
+
+/// When the merge is due, if it was scheduled.
+pub fn due_at(request: &MergeRequest) -> Option<DateTime<Utc>> {
+	request
+		.not_before
+		.map(|not_before| Utc.timestamp(not_before, 0))
+}
+
+/// Whether the merge was scheduled for later than `now`, in seconds since the
+/// epoch.
+pub fn is_waiting(request: &MergeRequest, now: i64) -> bool {
+	request
+		.not_before
+		.is_some_and(|not_before| now < not_before)
+}
+
+/// The scheduled merges which are due at `now`, by the head sha they wait
+/// for.
+pub fn due(db: &DB, now: i64) -> Result<Vec<(String, MergeRequest)>> {
+	Ok(queue::entries(db)?
+		.into_iter()
+		.filter_map(|entry| Some((entry.sha, entry.request?)))
+		.filter(|(_, request)| {
+			request.not_before.is_some() && !is_waiting(request, now)
+		})
+		.collect())
+}
+
+/// Make a scheduled merge an ordinary pending one, merged once its checks
+/// pass.
+pub fn promote(db: &DB, sha: &str, request: &MergeRequest) -> Result<()> {
+	let request = MergeRequest {
+		not_before: None,
+		..request.clone()
+	};
+	db::atomically(db, |tx| queue::store(tx, sha, &request))
+}
+
+/// Drop the scheduled merges of a PR, eg. once it is pushed to.  Returns the
+/// dropped ones.
+pub fn cancel(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+) -> Result<Vec<MergeRequest>> {
+	let scheduled = queue::for_repo(db, owner, repo_name)?
+		.into_iter()
+		.filter_map(|entry| Some((entry.sha, entry.request?)))
+		.filter(|(_, request)| {
+			request.number == number && request.not_before.is_some()
+		})
+		.collect::<Vec<_>>();
+	db::atomically(db, |tx| {
+		for (sha, _) in &scheduled {
+			queue::delete(tx, sha)?;
+		}
+		Ok(())
+	})?;
+	Ok(scheduled.into_iter().map(|(_, request)| request).collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::db::TempDb;
+
+	const SHA: &str = "0123456789abcdef0123456789abcdef01234567";
+
+	fn now() -> DateTime<Utc> {
+		Utc.ymd(2024, 4, 30).and_hms(12, 0, 0)
+	}
+
+	fn utc(time: &str) -> DateTime<Utc> {
+		DateTime::parse_from_rfc3339(time)
+			.unwrap()
+			.with_timezone(&Utc)
+	}
+
+	fn request(number: i64, not_before: Option<i64>) -> MergeRequest {
+		MergeRequest {
+			owner: "paritytech".to_string(),
+			repo_name: "substrate".to_string(),
+			number,
+			html_url: format!(
+				"https://github.com/paritytech/substrate/pull/{}",
+				number
+			),
+			requested_by: "gavofyork".to_string(),
+			created_at: Some(now().timestamp()),
+			origin: None,
+			not_before,
+		}
+	}
+
+	#[test]
+	fn test_parse_time() {
+		for (time, expected) in &[
+			("2024-05-01T14:00Z", "2024-05-01T14:00:00Z"),
+			("2024-05-01T14:00:30Z", "2024-05-01T14:00:30Z"),
+			("2024-05-01t14:00z", "2024-05-01T14:00:00Z"),
+			("2024-05-01 14:00 UTC", "2024-05-01T14:00:00Z"),
+			("2024-05-01 14:00Z", "2024-05-01T14:00:00Z"),
+			// offsets are converted to UTC, across days
+			("2024-05-01T14:00+02:00", "2024-05-01T12:00:00Z"),
+			("2024-05-01T01:30+0200", "2024-04-30T23:30:00Z"),
+			("2024-05-01T20:00-05:00", "2024-05-02T01:00:00Z"),
+			("in 3h", "2024-04-30T15:00:00Z"),
+			("in 90m", "2024-04-30T13:30:00Z"),
+			("in 1d 12h 30m", "2024-05-02T00:30:00Z"),
+			("In 2D", "2024-05-02T12:00:00Z"),
+		] {
+			assert_eq!(parse_time(time, now()), Ok(utc(expected)), "{}", time);
+		}
+	}
+
+	#[test]
+	fn test_parse_time_invalid() {
+		let error = |time| parse_time(time, now()).unwrap_err();
+		assert_eq!(
+			error("2024-05-01T14:00"),
+			"`2024-05-01T14:00` has no time zone; add one, eg. `2024-05-01T14:00Z` for UTC or `2024-05-01T14:00+02:00`"
+		);
+		assert!(error("2024-05-01 14:00:00").contains("no time zone"));
+		assert_eq!(
+			error("2024-04-30T11:00Z"),
+			"2024-04-30 11:00 UTC has already passed"
+		);
+		// exactly now is not in the future either
+		assert!(error("2024-04-30T12:00Z").contains("already passed"));
+		assert!(error("2024-04-30T13:00+02:00").contains("already passed"));
+		assert_eq!(
+			error("in 15d"),
+			"2024-05-15 12:00 UTC is more than 14 days ahead"
+		);
+		assert!(parse_time("in 14d", now()).is_ok());
+		for time in &[
+			"tomorrow",
+			"2024-05-01",
+			"2024-13-01T14:00Z",
+			"14:00Z",
+			"in",
+			"in 3",
+			"in h",
+			"in 3x",
+			"in 3h tomorrow",
+			"in 99999999999999999999h",
+		] {
+			assert!(parse_time(time, now()).is_err(), "{}", time);
+		}
+	}
+
+	#[test]
+	fn test_parse_command() {
+		assert_eq!(
+			parse_command(" Bot Merge At 2024-05-01T14:00Z\n", now()),
+			Some(Ok(utc("2024-05-01T14:00:00Z")))
+		);
+		assert_eq!(
+			parse_command("bot merge at in 3h", now()),
+			Some(Ok(utc("2024-04-30T15:00:00Z")))
+		);
+		assert!(parse_command("bot merge at", now()).unwrap().is_err());
+		assert!(parse_command("bot merge at noon", now()).unwrap().is_err());
+		for body in &["bot merge", "bot merge force", "bot merge atom"] {
+			assert_eq!(parse_command(body, now()), None, "{}", body);
+		}
+	}
+
+	#[test]
+	fn test_describe() {
+		assert_eq!(
+			describe(utc("2024-05-01T14:00:30+02:00")),
+			"2024-05-01 12:00 UTC"
+		);
+	}
+
+	#[test]
+	fn test_scheduler() {
+		let db = TempDb::new("scheduled-merge");
+		let at = now().timestamp() + 3600;
+		let other = SHA.replace('0', "f");
+		let ordinary = SHA.replace('0', "e");
+		db::atomically(&db, |tx| {
+			queue::store(tx, SHA, &request(1, Some(at)))?;
+			queue::store(tx, &other, &request(2, Some(at + 60)))?;
+			queue::store(tx, &ordinary, &request(3, None))
+		})
+		.unwrap();
+		let scheduled = request(1, Some(at));
+		assert!(is_waiting(&scheduled, at - 1));
+		assert!(!is_waiting(&scheduled, at));
+		assert!(!is_waiting(&request(3, None), at));
+		assert_eq!(due_at(&scheduled), Some(Utc.timestamp(at, 0)));
+
+		// the clock moves on
+		assert!(due(&db, at - 1).unwrap().is_empty());
+		assert_eq!(
+			due(&db, at).unwrap(),
+			vec![(SHA.to_string(), scheduled.clone())]
+		);
+		assert_eq!(due(&db, at + 60).unwrap().len(), 2);
+
+		promote(&db, SHA, &scheduled).unwrap();
+		assert_eq!(due(&db, at).unwrap(), vec![]);
+		let promoted = crate::migrations::decode::<MergeRequest>(
+			&queue::get(&db, SHA).unwrap().unwrap(),
+		)
+		.unwrap();
+		assert_eq!(promoted, request(1, None));
+
+		// only scheduled merges are dropped on a push
+		assert_eq!(cancel(&db, "paritytech", "substrate", 1).unwrap(), vec![]);
+		assert_eq!(cancel(&db, "paritytech", "substrate", 3).unwrap(), vec![]);
+		assert!(queue::get(&db, &ordinary).unwrap().is_some());
+		assert_eq!(
+			cancel(&db, "paritytech", "substrate", 2).unwrap(),
+			vec![request(2, Some(at + 60))]
+		);
+		assert!(queue::get(&db, &other).unwrap().is_none());
+	}
+}
@@ -0,0 +1,160 @@
+use serde::Deserialize;
+
+use crate::{
+	approval, codeowners,
+	github::{Label, PullRequestFile, Review},
+};
+
+/// Paths whose changes a team must audit before a PR is merged, eg. runtime
+/// storage migrations.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SensitivePaths {
+	/// gitignore-style, as in CODEOWNERS, eg. `frame/*/src/migrations*`
+	pub paths: Vec<String>,
+	/// label of audited PRs, eg. `D1-audited`
+	pub label: String,
+	/// slug of the auditing team, in the organization of the repository
+	pub team: String,
+}
+
+impl SensitivePaths {
+	/// The files among `files` needing an audit.  Patterns which cannot be
+	/// understood are skipped.
+	pub fn matching_files(&self, files: &[PullRequestFile]) -> Vec<String> {
+		let patterns = self
+			.paths
+			.iter()
+			.filter_map(|pattern| match codeowners::glob_regex(pattern) {
+				Ok(regex) => Some(regex),
+				Err(e) => {
+					log::warn!(
+						"Ignoring invalid sensitive path '{}': {}",
+						pattern,
+						e
+					);
+					None
+				}
+			})
+			.collect::<Vec<_>>();
+		files
+			.iter()
+			.filter(|file| {
+				patterns.iter().any(|regex| regex.is_match(&file.filename))
+			})
+			.map(|file| file.filename.clone())
+			.collect()
+	}
+
+	pub fn is_labelled(&self, labels: &[Label]) -> bool {
+		labels.iter().any(|label| label.name == self.label)
+	}
+
+	/// Whether one of the `auditors`, the members of the team, approved.
+	pub fn is_approved(&self, reviews: &[Review], auditors: &[String]) -> bool {
+		approval::approvals_by(reviews, auditors) >= 1
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::github::{ReviewState, User};
+
+	fn sensitive() -> SensitivePaths {
+		SensitivePaths {
+			paths: vec![
+				"frame/*/src/migrations*".to_string(),
+				"bin/node/runtime/src/lib.rs".to_string(),
+			],
+			label: "D1-audited".to_string(),
+			team: "runtime-audit".to_string(),
+		}
+	}
+
+	fn files(names: &[&str]) -> Vec<PullRequestFile> {
+		names
+			.iter()
+			.map(|name| PullRequestFile {
+				filename: name.to_string(),
+				status: "modified".to_string(),
+				..PullRequestFile::default()
+			})
+			.collect()
+	}
+
+	fn review(login: &str, state: ReviewState) -> Review {
+		Review {
+			user: User {
+				login: login.to_string(),
+				..User::default()
+			},
+			state: Some(state),
+			..Review::default()
+		}
+	}
+
+	#[test]
+	fn test_matching_files() {
+		let sensitive = sensitive();
+		assert_eq!(
+			sensitive.matching_files(&files(&[
+				"frame/balances/src/lib.rs",
+				"frame/balances/src/migrations.rs",
+				"frame/staking/src/migrations/v7.rs",
+				"bin/node/runtime/src/lib.rs",
+				"frame/src/migrations.rs",
+			])),
+			vec![
+				"frame/balances/src/migrations.rs",
+				"frame/staking/src/migrations/v7.rs",
+				"bin/node/runtime/src/lib.rs",
+			]
+		);
+		// anchored to the root
+		assert!(sensitive
+			.matching_files(&files(&[
+				"vendor/frame/balances/src/migrations.rs"
+			]))
+			.is_empty());
+		assert!(sensitive.matching_files(&[]).is_empty());
+	}
+
+	#[test]
+	fn test_is_labelled() {
+		let label = |name: &str| Label {
+			name: name.to_string(),
+			..Label::default()
+		};
+		let sensitive = sensitive();
+		assert!(sensitive
+			.is_labelled(&[label("A0-pleasereview"), label("D1-audited")]));
+		assert!(!sensitive.is_labelled(&[label("D1-auditedd")]));
+		assert!(!sensitive.is_labelled(&[]));
+	}
+
+	#[test]
+	fn test_is_approved() {
+		let sensitive = sensitive();
+		let auditors =
+			vec!["kianenigma".to_string(), "shawntabrizi".to_string()];
+		assert!(sensitive.is_approved(
+			&[
+				review("gavofyork", ReviewState::Approved),
+				review("kianenigma", ReviewState::Approved),
+			],
+			&auditors
+		));
+		// approvals by others, or other reviews by auditors, do not count
+		assert!(!sensitive.is_approved(
+			&[
+				review("gavofyork", ReviewState::Approved),
+				review("kianenigma", ReviewState::Commented),
+				review("shawntabrizi", ReviewState::ChangesRequested),
+			],
+			&auditors
+		));
+		assert!(!sensitive
+			.is_approved(&[review("kianenigma", ReviewState::Approved)], &[]));
+	}
+}
@@ -1,4 +1,4 @@
-use crate::webhook::*;
+use crate::{scheduled_merge, webhook::*};
 use anyhow::{Context, Result};
 use async_std::pin::Pin;
 use futures_util::FutureExt;
@@ -99,8 +99,20 @@ impl std::error::Error for Error {
 /// to serve metrics.
 pub async fn init_server(
 	addr: SocketAddr,
-	state: Arc<Mutex<AppState>>,
+	state: AppState,
 ) -> anyhow::Result<()> {
+	let readiness = Arc::clone(&state.readiness);
+	let checks_debounce = Arc::clone(&state.checks_debounce);
+	let state = Arc::new(Mutex::new(state));
+	tokio::spawn(recheck_debounced(
+		Arc::clone(&state),
+		checks_debounce,
+		std::time::Duration::from_secs(1),
+	));
+	tokio::spawn(merge_scheduled(
+		Arc::clone(&state),
+		std::time::Duration::from_secs(scheduled_merge::SWEEP_INTERVAL_SECS),
+	));
 	let listener = async_std::net::TcpListener::bind(&addr)
 		.await
 		.map_err(|_| Error::PortInUse(addr))?;
@@ -109,10 +121,10 @@ pub async fn init_server(
 
 	let service = make_service_fn(move |_| {
 		let state = Arc::clone(&state);
+		let readiness = Arc::clone(&readiness);
 		async move {
 			Ok::<_, hyper::Error>(service_fn(move |req: Request<Body>| {
-				let state = Arc::clone(&state);
-				webhook(req, state)
+				webhook(req, Arc::clone(&state), Arc::clone(&readiness))
 			}))
 		}
 	});
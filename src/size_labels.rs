@@ -0,0 +1,209 @@
+use crate::{
+	codeowners,
+	github::{Label, PullRequestFile},
+};
+
+/// A label for the PRs changing at least `min_lines` lines, added and
+/// deleted, and fewer than the next bucket's.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SizeBucket {
+	pub label: String,
+	pub min_lines: u64,
+}
+
+/// The bucket of a PR changing `lines` lines, of `buckets` in ascending order.
+pub fn bucket(buckets: &[SizeBucket], lines: u64) -> Option<&SizeBucket> {
+	buckets
+		.iter()
+		.rev()
+		.find(|bucket| bucket.min_lines <= lines)
+}
+
+/// Whether leaving out some of the `lines` a PR changes could put it in a
+/// smaller bucket, so that its files are worth listing.
+pub fn may_shrink(buckets: &[SizeBucket], lines: u64) -> bool {
+	bucket(buckets, lines).is_some_and(|bucket| Some(bucket) != buckets.first())
+}
+
+/// The lines changed in `files` which match none of the `ignored` patterns,
+/// eg. lockfiles.  Patterns which cannot be understood are skipped.
+pub fn counted_lines(files: &[PullRequestFile], ignored: &[String]) -> u64 {
+	let ignored = ignored
+		.iter()
+		.filter_map(|pattern| match codeowners::glob_regex(pattern) {
+			Ok(regex) => Some(regex),
+			Err(e) => {
+				log::warn!(
+					"Ignoring invalid size pattern '{}': {}",
+					pattern,
+					e
+				);
+				None
+			}
+		})
+		.collect::<Vec<_>>();
+	files
+		.iter()
+		.filter(|file| {
+			!ignored.iter().any(|regex| regex.is_match(&file.filename))
+		})
+		.map(|file| file.additions + file.deletions)
+		.sum()
+}
+
+/// The changes bringing the size labels of a PR in line with its bucket.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LabelSwap {
+	pub add: Option<String>,
+	pub remove: Vec<String>,
+}
+
+impl LabelSwap {
+	pub fn is_empty(&self) -> bool {
+		self.add.is_none() && self.remove.is_empty()
+	}
+}
+
+/// Add the label of `wanted` unless the PR has it, and remove those of the
+/// other buckets.
+pub fn swap(
+	buckets: &[SizeBucket],
+	current: &[Label],
+	wanted: Option<&SizeBucket>,
+) -> LabelSwap {
+	let has = |label: &str| current.iter().any(|current| current.name == label);
+	LabelSwap {
+		add: wanted
+			.filter(|wanted| !has(&wanted.label))
+			.map(|wanted| wanted.label.clone()),
+		remove: buckets
+			.iter()
+			.filter(|bucket| Some(*bucket) != wanted && has(&bucket.label))
+			.map(|bucket| bucket.label.clone())
+			.collect(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn buckets() -> Vec<SizeBucket> {
+		[
+			("size/XS", 0),
+			("size/S", 10),
+			("size/M", 100),
+			("size/L", 500),
+		]
+		.iter()
+		.map(|(label, min_lines)| SizeBucket {
+			label: label.to_string(),
+			min_lines: *min_lines,
+		})
+		.collect()
+	}
+
+	fn label(name: &str) -> Label {
+		Label {
+			name: name.to_string(),
+			..Label::default()
+		}
+	}
+
+	#[test]
+	fn test_bucket() {
+		let buckets = buckets();
+		let label = |lines| bucket(&buckets, lines).map(|b| b.label.as_str());
+		assert_eq!(label(0), Some("size/XS"));
+		assert_eq!(label(9), Some("size/XS"));
+		assert_eq!(label(10), Some("size/S"));
+		assert_eq!(label(499), Some("size/M"));
+		assert_eq!(label(500), Some("size/L"));
+		assert_eq!(label(100_000), Some("size/L"));
+		assert_eq!(bucket(&buckets[1..], 5), None);
+		assert_eq!(bucket(&[], 5), None);
+	}
+
+	#[test]
+	fn test_may_shrink() {
+		let buckets = buckets();
+		assert!(!may_shrink(&buckets, 9));
+		assert!(may_shrink(&buckets, 10));
+		assert!(may_shrink(&buckets, 1000));
+		assert!(!may_shrink(&buckets[1..], 5));
+		assert!(!may_shrink(&[], 1000));
+	}
+
+	#[test]
+	fn test_counted_lines() {
+		let file = |filename: &str, additions, deletions| PullRequestFile {
+			filename: filename.to_string(),
+			status: "modified".to_string(),
+			additions,
+			deletions,
+		};
+		let files = vec![
+			file("src/lib.rs", 20, 5),
+			file("Cargo.lock", 900, 300),
+			file("client/Cargo.lock", 10, 0),
+			file("primitives/src/generated/api.rs", 4000, 0),
+		];
+		assert_eq!(counted_lines(&files, &[]), 5235);
+		assert_eq!(counted_lines(&files, &["Cargo.lock".to_string()]), 4025);
+		assert_eq!(
+			counted_lines(
+				&files,
+				&["Cargo.lock".to_string(), "**/generated/**".to_string()]
+			),
+			25
+		);
+	}
+
+	#[test]
+	fn test_swap() {
+		let buckets = buckets();
+		// a new PR
+		assert_eq!(
+			swap(&buckets, &[label("A0-pleasereview")], Some(&buckets[1])),
+			LabelSwap {
+				add: Some("size/S".to_string()),
+				remove: vec![],
+			}
+		);
+		// grown
+		assert_eq!(
+			swap(
+				&buckets,
+				&[label("size/S"), label("A0-pleasereview")],
+				Some(&buckets[2])
+			),
+			LabelSwap {
+				add: Some("size/M".to_string()),
+				remove: vec!["size/S".to_string()],
+			}
+		);
+		// unchanged
+		assert!(
+			swap(&buckets, &[label("size/M")], Some(&buckets[2])).is_empty()
+		);
+		// labelled by hand as well
+		assert_eq!(
+			swap(
+				&buckets,
+				&[label("size/M"), label("size/XS")],
+				Some(&buckets[2])
+			),
+			LabelSwap {
+				add: None,
+				remove: vec!["size/XS".to_string()],
+			}
+		);
+		assert_eq!(
+			swap(&buckets[1..], &[label("size/S")], None),
+			LabelSwap {
+				add: None,
+				remove: vec!["size/S".to_string()],
+			}
+		);
+	}
+}
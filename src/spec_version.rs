@@ -0,0 +1,306 @@
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::{
+	codeowners, github::PullRequestFile, github_bot::GithubBot, process, Result,
+};
+
+/// Runtimes whose `spec_version` must be bumped by the PRs changing their
+/// source.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SpecVersionCheck {
+	/// gitignore-style patterns of runtime directories, each with its
+	/// `RuntimeVersion` in `src/lib.rs`
+	#[serde(default = "default_runtimes")]
+	pub runtimes: Vec<String>,
+	/// refuse to merge rather than warn
+	#[serde(default)]
+	pub block: bool,
+}
+
+fn default_runtimes() -> Vec<String> {
+	vec!["runtime/*".to_string()]
+}
+
+/// The `spec_version` of the `RuntimeVersion` in a runtime's `lib.rs`.
+/// Comments are skipped, and digits may be separated by underscores.
+pub fn parse(source: &str) -> Option<u64> {
+	let re = Regex::new(r"\bspec_version\s*:\s*([0-9][0-9_]*)")
+		.expect("spec version regex");
+	let source = source
+		.lines()
+		.map(|line| line.split("//").next().unwrap_or(""))
+		.collect::<Vec<_>>()
+		.join("\n");
+	// the field of the `VERSION` constant, rather than eg. a test's
+	let start = source.find("RuntimeVersion {").unwrap_or(0);
+	re.captures(&source[start..])?
+		.get(1)?
+		.as_str()
+		.replace('_', "")
+		.parse()
+		.ok()
+}
+
+/// The runtime directories whose source `files` change, in order.
+pub fn changed_runtimes(
+	check: &SpecVersionCheck,
+	files: &[PullRequestFile],
+) -> Vec<String> {
+	let patterns = check
+		.runtimes
+		.iter()
+		.filter_map(|pattern| match codeowners::glob_regex(pattern) {
+			Ok(regex) => Some(regex),
+			Err(e) => {
+				log::warn!(
+					"Ignoring invalid runtime path '{}': {}",
+					pattern,
+					e
+				);
+				None
+			}
+		})
+		.collect::<Vec<_>>();
+	let mut runtimes = Vec::<String>::new();
+	for file in files {
+		let components = file.filename.split('/').collect::<Vec<_>>();
+		// the shortest leading directory matching a pattern
+		let runtime = (1..components.len())
+			.map(|len| components[..len].join("/"))
+			.find(|dir| patterns.iter().any(|regex| regex.is_match(dir)));
+		if let Some(runtime) = runtime {
+			let in_source = file.filename[runtime.len()..].starts_with("/src/");
+			if in_source && !runtimes.contains(&runtime) {
+				runtimes.push(runtime);
+			}
+		}
+	}
+	runtimes
+}
+
+/// The `spec_version` of a runtime before and after a PR, where it could be
+/// read.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeVersions {
+	pub runtime: String,
+	pub base: Option<u64>,
+	pub head: Option<u64>,
+}
+
+impl RuntimeVersions {
+	/// Whether the version did not increase.  A runtime without one on the
+	/// base branch, eg. a new one, needs no bump.
+	pub fn is_not_bumped(&self) -> bool {
+		match (self.base, self.head) {
+			(Some(base), Some(head)) => head <= base,
+			(Some(_), None) => true,
+			(None, _) => false,
+		}
+	}
+
+	fn describe(&self) -> String {
+		let version = |version: Option<u64>| {
+			version.map_or("?".to_string(), |v| v.to_string())
+		};
+		format!(
+			"`{}`: {} → {}, {}",
+			self.runtime,
+			version(self.base),
+			version(self.head),
+			match (self.base, self.head) {
+				(None, _) => "none on the base branch",
+				(Some(_), None) => "none at the head",
+				_ if self.is_not_bumped() => "not bumped",
+				_ => "bumped",
+			}
+		)
+	}
+}
+
+/// One line per runtime.
+pub fn report(versions: &[RuntimeVersions]) -> String {
+	versions
+		.iter()
+		.map(|versions| format!("- {}", versions.describe()))
+		.collect::<Vec<_>>()
+		.join("\n")
+}
+
+async fn version_at(
+	github_bot: &GithubBot,
+	owner: &str,
+	repo_name: &str,
+	runtime: &str,
+	ref_field: &str,
+) -> Result<Option<u64>> {
+	let path = format!("{}/src/lib.rs", runtime);
+	match github_bot
+		.contents(owner, repo_name, &path, ref_field)
+		.await
+		.and_then(|contents| process::decode_contents(&contents))
+	{
+		Ok(source) => Ok(parse(&source)),
+		Err(e) if e.is_not_found() => Ok(None),
+		Err(e) => Err(e),
+	}
+}
+
+/// The versions of each of `runtimes` on the base branch and at the head of a
+/// PR.
+pub async fn versions(
+	github_bot: &GithubBot,
+	owner: &str,
+	repo_name: &str,
+	base_ref: &str,
+	head_sha: &str,
+	runtimes: &[String],
+) -> Result<Vec<RuntimeVersions>> {
+	let mut versions = Vec::new();
+	for runtime in runtimes {
+		versions.push(RuntimeVersions {
+			runtime: runtime.clone(),
+			base: version_at(github_bot, owner, repo_name, runtime, base_ref)
+				.await?,
+			head: version_at(github_bot, owner, repo_name, runtime, head_sha)
+				.await?,
+		});
+	}
+	Ok(versions)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const KUSAMA: &str = r#"
+/// Runtime version (Kusama).
+pub const VERSION: RuntimeVersion = RuntimeVersion {
+	spec_name: create_runtime_str!("kusama"),
+	impl_name: create_runtime_str!("parity-kusama"),
+	authoring_version: 2,
+	spec_version: 2026,
+	impl_version: 0,
+	#[cfg(not(feature = "disable-runtime-api"))]
+	apis: RUNTIME_API_VERSIONS,
+	transaction_version: 4,
+};
+"#;
+
+	const WESTEND: &str = r#"
+// The spec_version: 1 of the genesis runtime is long gone.
+pub const VERSION: RuntimeVersion = RuntimeVersion { spec_name: create_runtime_str!("westend"), impl_name: create_runtime_str!("parity-westend"), authoring_version: 2, spec_version : 9_000,
+	impl_version: 0, apis: RUNTIME_API_VERSIONS, transaction_version: 4 };
+"#;
+
+	fn files(names: &[&str]) -> Vec<PullRequestFile> {
+		names
+			.iter()
+			.map(|name| PullRequestFile {
+				filename: name.to_string(),
+				status: "modified".to_string(),
+				..PullRequestFile::default()
+			})
+			.collect()
+	}
+
+	fn check() -> SpecVersionCheck {
+		SpecVersionCheck {
+			runtimes: default_runtimes(),
+			block: false,
+		}
+	}
+
+	#[test]
+	fn test_parse() {
+		assert_eq!(parse(KUSAMA), Some(2026));
+		assert_eq!(parse(WESTEND), Some(9000));
+		assert_eq!(
+			parse("#[test]\nfn f() { let spec_version: u32 = 0; }\npub const VERSION: RuntimeVersion = RuntimeVersion {\n\tspec_version: 25,\n};"),
+			Some(25)
+		);
+		assert_eq!(parse("pub const VERSION: u32 = 1;"), None);
+		assert_eq!(parse("// spec_version: 1"), None);
+	}
+
+	#[test]
+	fn test_changed_runtimes() {
+		assert_eq!(
+			changed_runtimes(
+				&check(),
+				&files(&[
+					"runtime/westend/src/lib.rs",
+					"runtime/kusama/src/constants.rs",
+					"runtime/kusama/src/lib.rs",
+					"runtime/polkadot/Cargo.toml",
+					"runtime/common/src/lib.rs",
+					"node/service/src/lib.rs",
+				])
+			),
+			vec!["runtime/westend", "runtime/kusama", "runtime/common"]
+		);
+		let check = SpecVersionCheck {
+			runtimes: vec![
+				"runtime/kusama".to_string(),
+				"bin/*/runtime".to_string(),
+			],
+			block: true,
+		};
+		assert_eq!(
+			changed_runtimes(
+				&check,
+				&files(&[
+					"runtime/common/src/lib.rs",
+					"bin/node/runtime/src/impls.rs",
+					"runtime/kusama/src/lib.rs",
+				])
+			),
+			vec!["bin/node/runtime", "runtime/kusama"]
+		);
+		assert!(changed_runtimes(&check, &[]).is_empty());
+	}
+
+	#[test]
+	fn test_report() {
+		let versions = |runtime: &str, base, head| RuntimeVersions {
+			runtime: runtime.to_string(),
+			base,
+			head,
+		};
+		let results = vec![
+			versions("runtime/kusama", Some(2026), Some(2026)),
+			versions("runtime/westend", Some(46), Some(47)),
+			versions("runtime/rococo", None, Some(1)),
+			versions("runtime/polkadot", Some(26), None),
+		];
+		assert_eq!(
+			results
+				.iter()
+				.map(RuntimeVersions::is_not_bumped)
+				.collect::<Vec<_>>(),
+			vec![true, false, false, true]
+		);
+		assert!(
+			versions("runtime/kusama", Some(2026), Some(2025)).is_not_bumped()
+		);
+		assert_eq!(
+			report(&results),
+			"- `runtime/kusama`: 2026 → 2026, not bumped
+- `runtime/westend`: 46 → 47, bumped
+- `runtime/rococo`: ? → 1, none on the base branch
+- `runtime/polkadot`: 26 → ?, none at the head"
+		);
+	}
+
+	#[test]
+	fn test_config() {
+		let check: SpecVersionCheck = toml::from_str("").unwrap();
+		assert_eq!(check, self::check());
+		let check: SpecVersionCheck =
+			toml::from_str("runtimes = [\"runtime/kusama\"]\nblock = true")
+				.unwrap();
+		assert!(check.block);
+		assert!(toml::from_str::<SpecVersionCheck>("warn = true").is_err());
+	}
+}
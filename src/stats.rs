@@ -0,0 +1,458 @@
+use chrono::Utc;
+use rocksdb::DB;
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{
+	constants::*,
+	db::{self, Transaction},
+	error::*,
+	queue::{MergeRequest, QueueEntry},
+	Result,
+};
+
+const KEY_PREFIX: &str = "outcome/";
+
+/// The period `bot stats` covers unless it is given one.
+pub const DEFAULT_PERIOD_DAYS: i64 = 30;
+
+pub const DAY_SECS: i64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MergeResult {
+	Merged,
+	Failed,
+}
+
+/// How a requested merge ended.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MergeOutcome {
+	pub owner: String,
+	pub repo_name: String,
+	pub number: i64,
+	pub requested_by: String,
+	/// seconds since the epoch; unknown for merges requested before it was
+	/// recorded
+	pub requested_at: Option<i64>,
+	pub completed_at: i64,
+	pub result: MergeResult,
+}
+
+impl MergeOutcome {
+	pub fn new(
+		request: &MergeRequest,
+		completed_at: i64,
+		result: MergeResult,
+	) -> Self {
+		MergeOutcome {
+			owner: request.owner.clone(),
+			repo_name: request.repo_name.clone(),
+			number: request.number,
+			requested_by: request.requested_by.clone(),
+			requested_at: request.created_at,
+			completed_at,
+			result,
+		}
+	}
+
+	/// Seconds from the request to the end of the merge.
+	pub fn duration(&self) -> Option<i64> {
+		self.requested_at
+			.map(|requested_at| self.completed_at - requested_at)
+			.filter(|secs| *secs >= 0)
+	}
+}
+
+fn db_key(outcome: &MergeOutcome) -> String {
+	format!(
+		"{}{:020}/{}/{}/{}",
+		KEY_PREFIX,
+		outcome.completed_at.max(0),
+		outcome.owner,
+		outcome.repo_name,
+		outcome.number
+	)
+}
+
+fn since_key(since: i64) -> String {
+	format!("{}{:020}", KEY_PREFIX, since.max(0))
+}
+
+pub fn record(tx: &mut Transaction, outcome: &MergeOutcome) -> Result<()> {
+	let bytes = serde_json::to_vec(outcome).context(Json)?;
+	tx.put(db::MERGE_OUTCOMES, db_key(outcome).as_bytes(), &bytes)
+}
+
+/// The merges which ended at `since` or later, oldest first.
+pub fn outcomes(db: &DB, since: i64) -> Result<Vec<MergeOutcome>> {
+	db::family(db, db::MERGE_OUTCOMES)?
+		.iter_from(since_key(since).as_bytes(), KEY_PREFIX.as_bytes())
+		.map(|(_, v)| serde_json::from_slice(&v).context(Json))
+		.collect()
+}
+
+/// Delete the merges which ended before `before`.  Returns how many there
+/// were.
+pub fn prune(db: &DB, before: i64) -> Result<usize> {
+	let family = db::family(db, db::MERGE_OUTCOMES)?;
+	let end = since_key(before);
+	db::atomically(db, |tx| {
+		for (key, _) in family
+			.iter_prefix(KEY_PREFIX.as_bytes())
+			.take_while(|(key, _)| &**key < end.as_bytes())
+		{
+			tx.delete(db::MERGE_OUTCOMES, &key)?;
+		}
+		Ok(tx.len())
+	})
+}
+
+/// Prune the merges older than `retention_days` every `interval`.
+pub async fn periodic(db: Arc<DB>, retention_days: i64, interval: Duration) {
+	loop {
+		let before = Utc::now().timestamp() - retention_days * DAY_SECS;
+		match prune(&db, before) {
+			Ok(0) => {}
+			Ok(pruned) => log::info!("Pruned {} merge outcomes", pruned),
+			Err(e) => log::error!("Error pruning merge outcomes: {}", e),
+		}
+		tokio::time::delay_for(interval).await;
+	}
+}
+
+/// The merge numbers of a repository, or of all of them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RepoStats {
+	/// `owner/repo`, or `None` for the total
+	pub repo: Option<String>,
+	pub merged: usize,
+	pub failed: usize,
+	/// merges requested which have not ended yet
+	pub pending: usize,
+	/// of the merges which ended
+	pub failure_rate: Option<f64>,
+	/// seconds from the request to the merge, of the merges which succeeded
+	pub median_secs: Option<i64>,
+	pub p90_secs: Option<i64>,
+}
+
+/// The nearest-rank percentile of sorted `values`.
+fn percentile(values: &[i64], percent: usize) -> Option<i64> {
+	if values.is_empty() {
+		return None;
+	}
+	let rank = (percent * values.len()).div_ceil(100);
+	Some(values[rank.clamp(1, values.len()) - 1])
+}
+
+fn repo_stats(
+	repo: Option<String>,
+	outcomes: &[&MergeOutcome],
+	pending: usize,
+) -> RepoStats {
+	let merged = outcomes
+		.iter()
+		.filter(|outcome| outcome.result == MergeResult::Merged)
+		.count();
+	let failed = outcomes.len() - merged;
+	let mut durations = outcomes
+		.iter()
+		.filter(|outcome| outcome.result == MergeResult::Merged)
+		.filter_map(|outcome| outcome.duration())
+		.collect::<Vec<_>>();
+	durations.sort_unstable();
+	RepoStats {
+		repo,
+		merged,
+		failed,
+		pending,
+		failure_rate: if outcomes.is_empty() {
+			None
+		} else {
+			Some(failed as f64 / outcomes.len() as f64)
+		},
+		median_secs: percentile(&durations, 50),
+		p90_secs: percentile(&durations, 90),
+	}
+}
+
+/// The numbers of each repository with merges which ended or are pending,
+/// followed by the total.
+pub fn aggregate(
+	outcomes: &[MergeOutcome],
+	pending: &[QueueEntry],
+) -> Vec<RepoStats> {
+	let mut repos = BTreeMap::<String, (Vec<&MergeOutcome>, usize)>::new();
+	for outcome in outcomes {
+		let repo = format!("{}/{}", outcome.owner, outcome.repo_name);
+		repos
+			.entry(repo.to_lowercase())
+			.or_default()
+			.0
+			.push(outcome);
+	}
+	let mut total_pending = 0;
+	for request in pending.iter().filter_map(|entry| entry.request.as_ref()) {
+		let repo = format!("{}/{}", request.owner, request.repo_name);
+		repos.entry(repo.to_lowercase()).or_default().1 += 1;
+		total_pending += 1;
+	}
+	repos
+		.into_iter()
+		.map(|(repo, (outcomes, pending))| {
+			repo_stats(Some(repo), &outcomes, pending)
+		})
+		.chain(Some(repo_stats(
+			None,
+			&outcomes.iter().collect::<Vec<_>>(),
+			total_pending,
+		)))
+		.collect()
+}
+
+/// Parse a period of days or weeks, eg. `30d` or `2w`.  Returns seconds.
+pub fn parse_period(period: &str) -> std::result::Result<i64, String> {
+	let period = period.trim().to_lowercase();
+	let invalid = || format!("expected a period such as 30d or 2w: {}", period);
+	let (count, unit_secs) = if let Some(count) = period.strip_suffix('d') {
+		(count, DAY_SECS)
+	} else if let Some(count) = period.strip_suffix('w') {
+		(count, 7 * DAY_SECS)
+	} else {
+		return Err(invalid());
+	};
+	count
+		.parse::<i64>()
+		.ok()
+		.filter(|count| *count > 0)
+		.and_then(|count| count.checked_mul(unit_secs))
+		.ok_or_else(invalid)
+}
+
+/// Parse `bot stats`, optionally followed by a period.  Returns the period
+/// in seconds.
+pub fn parse_command(body: &str) -> Option<std::result::Result<i64, String>> {
+	let body = body.trim().to_lowercase();
+	let period = body.strip_prefix(BOT_STATS)?;
+	if period.trim().is_empty() {
+		Some(Ok(DEFAULT_PERIOD_DAYS * DAY_SECS))
+	} else if period.starts_with(char::is_whitespace) {
+		Some(parse_period(period))
+	} else {
+		None
+	}
+}
+
+fn format_secs(secs: Option<i64>) -> String {
+	match secs {
+		None => "-".to_string(),
+		Some(secs) if secs < 60 * 60 => format!("{}m", secs / 60),
+		Some(secs) if secs < DAY_SECS => {
+			format!("{}h {}m", secs / 3600, secs % 3600 / 60)
+		}
+		Some(secs) => {
+			format!("{}d {}h", secs / DAY_SECS, secs % DAY_SECS / 3600)
+		}
+	}
+}
+
+/// A markdown table of `stats` over the last `period` seconds.
+pub fn format_table(stats: &[RepoStats], period: i64) -> String {
+	let mut lines = vec![
+		format!("Merges over the last {} days:", period / DAY_SECS),
+		String::new(),
+		"| Repository | Merged | Failed | Failure rate | Pending | Median time | 90th percentile |".to_string(),
+		"|---|---:|---:|---:|---:|---:|---:|".to_string(),
+	];
+	for repo in stats {
+		lines.push(format!(
+			"| {} | {} | {} | {} | {} | {} | {} |",
+			repo.repo
+				.as_deref()
+				.map_or("**Total**".to_string(), |repo| repo.to_string()),
+			repo.merged,
+			repo.failed,
+			repo.failure_rate.map_or("-".to_string(), |rate| format!(
+				"{:.0}%",
+				rate * 100.0
+			)),
+			repo.pending,
+			format_secs(repo.median_secs),
+			format_secs(repo.p90_secs),
+		));
+	}
+	lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::db::TempDb;
+
+	fn outcome(
+		repo_name: &str,
+		requested_at: Option<i64>,
+		completed_at: i64,
+		result: MergeResult,
+	) -> MergeOutcome {
+		MergeOutcome {
+			owner: "paritytech".to_string(),
+			repo_name: repo_name.to_string(),
+			number: 1,
+			requested_by: "gavofyork".to_string(),
+			requested_at,
+			completed_at,
+			result,
+		}
+	}
+
+	fn pending(repo_name: &str) -> QueueEntry {
+		QueueEntry {
+			sha: "0".repeat(40),
+			request: Some(MergeRequest {
+				owner: "paritytech".to_string(),
+				repo_name: repo_name.to_string(),
+				number: 2,
+				html_url: String::new(),
+				requested_by: "gavofyork".to_string(),
+				created_at: Some(0),
+				origin: None,
+				not_before: None,
+			}),
+		}
+	}
+
+	#[test]
+	fn test_percentile() {
+		assert_eq!(percentile(&[], 50), None);
+		assert_eq!(percentile(&[7], 50), Some(7));
+		assert_eq!(percentile(&[7], 90), Some(7));
+		assert_eq!(percentile(&[1, 2, 3, 4], 50), Some(2));
+		assert_eq!(percentile(&[1, 2, 3, 4, 5], 50), Some(3));
+		let values = (1..=10).collect::<Vec<_>>();
+		assert_eq!(percentile(&values, 90), Some(9));
+		assert_eq!(percentile(&values, 100), Some(10));
+		assert_eq!(percentile(&values, 0), Some(1));
+	}
+
+	#[test]
+	fn test_aggregate() {
+		use MergeResult::*;
+		let outcomes = vec![
+			outcome("substrate", Some(0), 600, Merged),
+			outcome("substrate", Some(0), 60, Merged),
+			outcome("substrate", Some(0), 6000, Merged),
+			outcome("substrate", Some(100), 200, Failed),
+			// requested before the time was recorded
+			outcome("polkadot", None, 100, Merged),
+			outcome("polkadot", Some(0), 100, Failed),
+		];
+		let stats = aggregate(
+			&outcomes,
+			&[
+				pending("substrate"),
+				// pending without any merge which ended
+				pending("cumulus"),
+				// cannot be read, so not counted
+				QueueEntry {
+					sha: "1".repeat(40),
+					request: None,
+				},
+			],
+		);
+		assert_eq!(
+			stats,
+			vec![
+				RepoStats {
+					repo: Some("paritytech/cumulus".to_string()),
+					merged: 0,
+					failed: 0,
+					pending: 1,
+					failure_rate: None,
+					median_secs: None,
+					p90_secs: None,
+				},
+				RepoStats {
+					repo: Some("paritytech/polkadot".to_string()),
+					merged: 1,
+					failed: 1,
+					pending: 0,
+					failure_rate: Some(0.5),
+					median_secs: None,
+					p90_secs: None,
+				},
+				RepoStats {
+					repo: Some("paritytech/substrate".to_string()),
+					merged: 3,
+					failed: 1,
+					pending: 1,
+					failure_rate: Some(0.25),
+					median_secs: Some(600),
+					p90_secs: Some(6000),
+				},
+				RepoStats {
+					repo: None,
+					merged: 4,
+					failed: 2,
+					pending: 2,
+					failure_rate: Some(2.0 / 6.0),
+					median_secs: Some(600),
+					p90_secs: Some(6000),
+				},
+			]
+		);
+		assert_eq!(
+			aggregate(&[], &[]),
+			vec![RepoStats {
+				repo: None,
+				..RepoStats::default()
+			}]
+		);
+
+		assert_eq!(
+			format_table(&stats[2..], 30 * DAY_SECS),
+			"Merges over the last 30 days:
+
+| Repository | Merged | Failed | Failure rate | Pending | Median time | 90th percentile |
+|---|---:|---:|---:|---:|---:|---:|
+| paritytech/substrate | 3 | 1 | 25% | 1 | 10m | 1h 40m |
+| **Total** | 4 | 2 | 33% | 2 | 10m | 1h 40m |"
+		);
+	}
+
+	#[test]
+	fn test_parse_command() {
+		assert_eq!(parse_command("bot stats"), Some(Ok(30 * DAY_SECS)));
+		assert_eq!(parse_command(" Bot Stats 7D "), Some(Ok(7 * DAY_SECS)));
+		assert_eq!(parse_command("bot stats 2w"), Some(Ok(14 * DAY_SECS)));
+		assert!(matches!(parse_command("bot stats 0d"), Some(Err(_))));
+		assert!(matches!(parse_command("bot stats soon"), Some(Err(_))));
+		assert!(matches!(
+			parse_command("bot stats 999999999999999999w"),
+			Some(Err(_))
+		));
+		assert_eq!(parse_command("bot statsd"), None);
+		assert_eq!(parse_command("bot merge"), None);
+	}
+
+	#[test]
+	fn test_record_and_prune() {
+		let db = TempDb::new("stats");
+		let old = outcome("substrate", Some(0), 100, MergeResult::Merged);
+		let new = outcome("substrate", Some(0), 300, MergeResult::Failed);
+		db::atomically(&db, |tx| {
+			record(tx, &new)?;
+			record(tx, &old)
+		})
+		.unwrap();
+		assert_eq!(outcomes(&db, 0).unwrap(), vec![old.clone(), new.clone()]);
+		assert_eq!(outcomes(&db, 200).unwrap(), vec![new.clone()]);
+
+		assert_eq!(prune(&db, 300).unwrap(), 1);
+		assert_eq!(outcomes(&db, 0).unwrap(), vec![new]);
+	}
+}
@@ -0,0 +1,356 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+pub const BURNIN_PROMPT: &str = "burnin_prompt";
+pub const BURNIN_LIMITS_EXCEEDED: &str = "burnin_limits_exceeded";
+pub const BURNIN_SUBMIT_FAILED: &str = "burnin_submit_failed";
+pub const BURNIN_CANCEL_FAILED: &str = "burnin_cancel_failed";
+pub const PROCESS_HELP: &str = "process_help";
+pub const APPROVAL_REVIEWERS: &str = "approval_reviewers";
+pub const APPROVAL_HELP: &str = "approval_help";
+
+pub const DEFAULT_FAQ_URL: &str =
+	"https://github.com/paritytech/parity-processbot#faq";
+pub const DEFAULT_BURNIN_CONTACT: &str = "Staking Ops on Matrix";
+
+/// Placeholders filled from the configuration, which any template may use.
+const DEPLOYMENT: [&str; 3] = ["faq_url", "burnin_contact", "min_reviewers"];
+
+/// A text the bot posts, with the placeholders it is rendered with besides
+/// those of the deployment.
+struct Template {
+	name: &'static str,
+	placeholders: &'static [&'static str],
+	text: &'static str,
+}
+
+const BUILT_IN: [Template; 7] = [
+	Template {
+		name: BURNIN_PROMPT,
+		placeholders: &["added_by", "org", "pr_url", "commit_sha", "burnin"],
+		text: r#"@{{added_by}} to request a burn-in test for this PR, please submit a comment in the format below.
+
+Only members of the Github organization `{{org}}` are authorized to perform burn-in tests.
+
+For now, only full nodes on Kusama are available via automation. If you need something else, please contact {{burnin_contact}}.
+
+Requests are expressed as ToML files. Here's an example for deploying a binary built from the most recent commit on this PR to a single node (The comment must start with "{{burnin}}" and use a \``` block for the ToML):
+
+{{burnin}}
+```toml
+pull_request = "{{pr_url}}"
+commit_sha = "{{commit_sha}}"
+requested_by = "{{added_by}}"
+sync_from_scratch = false
+
+[node_types]
+fullnode = 1
+sentry = 0
+validator = 0
+```
+
+`pull_request` and `requested_by` are always filled in from this PR and the commenter. `commit_sha` is replaced with the head of the PR unless it names another commit of the PR."#,
+	},
+	Template {
+		name: BURNIN_LIMITS_EXCEEDED,
+		placeholders: &["requested_by", "limits", "violations"],
+		text: "@{{requested_by}} the request exceeds the limits for automated burn-ins ({{limits}}):\n{{violations}}\n\nIf you need more, please contact {{burnin_contact}}.",
+	},
+	Template {
+		name: BURNIN_SUBMIT_FAILED,
+		placeholders: &["requested_by"],
+		text: "@{{requested_by}} your request could not be processed: Gitlab rejected the request. Please contact {{burnin_contact}}.",
+	},
+	Template {
+		name: BURNIN_CANCEL_FAILED,
+		placeholders: &["requested_by"],
+		text: "@{{requested_by}} your cancellation could not be processed. Please contact {{burnin_contact}}.",
+	},
+	Template {
+		name: PROCESS_HELP,
+		placeholders: &[],
+		text: "See {{faq_url}} for how the process applies.",
+	},
+	Template {
+		name: APPROVAL_REVIEWERS,
+		placeholders: &[],
+		text: "Get approvals from enough members of the review teams (by default {{min_reviewers}} of `core-devs`, or one if the PR is labelled insubstantial).",
+	},
+	Template {
+		name: APPROVAL_HELP,
+		placeholders: &[],
+		text: "See {{faq_url}} for how approvals are counted.",
+	},
+];
+
+fn built_in(name: &str) -> &'static Template {
+	BUILT_IN
+		.iter()
+		.find(|template| template.name == name)
+		.unwrap_or_else(|| panic!("unknown template {}", name))
+}
+
+/// The names of the placeholders of `text`, eg. `org` for `{{org}}`.  Fails
+/// on a `{{` left open.
+fn placeholders(text: &str) -> Result<Vec<&str>, String> {
+	let mut names = Vec::new();
+	let mut rest = text;
+	while let Some(start) = rest.find("{{") {
+		let after = &rest[start + 2..];
+		let end = after
+			.find("}}")
+			.ok_or_else(|| "a `{{` is not closed".to_string())?;
+		names.push(after[..end].trim());
+		rest = &after[end + 2..];
+	}
+	Ok(names)
+}
+
+/// `text` with each `{{name}}` replaced by its value in `values`.  Those
+/// without one are left as they are, which `check` rules out.
+pub fn fill(text: &str, values: &HashMap<&str, &str>) -> String {
+	let mut filled = String::with_capacity(text.len());
+	let mut rest = text;
+	while let Some(start) = rest.find("{{") {
+		let after = &rest[start + 2..];
+		let end = match after.find("}}") {
+			Some(end) => end,
+			None => break,
+		};
+		filled.push_str(&rest[..start]);
+		match values.get(after[..end].trim()) {
+			Some(value) => filled.push_str(value),
+			None => filled.push_str(&rest[start..start + end + 4]),
+		}
+		rest = &after[end + 2..];
+	}
+	filled.push_str(rest);
+	filled
+}
+
+/// Fails if `text` uses a placeholder the template `name` is not rendered
+/// with.
+fn check(name: &str, text: &str) -> Result<(), String> {
+	let template = built_in(name);
+	let unknown = placeholders(text)?
+		.into_iter()
+		.filter(|placeholder| {
+			!template.placeholders.contains(placeholder)
+				&& !DEPLOYMENT.contains(placeholder)
+		})
+		.map(|placeholder| format!("{{{{{}}}}}", placeholder))
+		.collect::<Vec<_>>();
+	if unknown.is_empty() {
+		Ok(())
+	} else {
+		Err(format!("unknown placeholders {}", unknown.join(", ")))
+	}
+}
+
+/// The texts the bot posts, the built-in ones unless a deployment overrides
+/// them, and the values of the deployment they are rendered with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Templates {
+	/// texts read from the templates directory, by name
+	overrides: HashMap<String, String>,
+	faq_url: String,
+	burnin_contact: String,
+	min_reviewers: usize,
+}
+
+impl Default for Templates {
+	fn default() -> Self {
+		Self::new(DEFAULT_FAQ_URL, DEFAULT_BURNIN_CONTACT, 2)
+	}
+}
+
+impl Templates {
+	pub fn new(
+		faq_url: &str,
+		burnin_contact: &str,
+		min_reviewers: usize,
+	) -> Self {
+		Self {
+			overrides: HashMap::new(),
+			faq_url: faq_url.to_string(),
+			burnin_contact: burnin_contact.to_string(),
+			min_reviewers,
+		}
+	}
+
+	/// Render `name` with `text` instead of its built-in text.  Fails if
+	/// there is no such template, or `text` uses a placeholder it is not
+	/// rendered with.
+	pub fn with_override(
+		mut self,
+		name: &str,
+		text: &str,
+	) -> Result<Self, String> {
+		if !BUILT_IN.iter().any(|template| template.name == name) {
+			return Err(format!("there is no template {}", name));
+		}
+		check(name, text)?;
+		self.overrides.insert(name.to_string(), text.to_string());
+		Ok(self)
+	}
+
+	/// Override templates with the files of `dir` named after them, eg.
+	/// `burnin_prompt.md`, listing every file which cannot be used.
+	pub fn load_dir(mut self, dir: &Path) -> Result<Self, Vec<String>> {
+		let entries = std::fs::read_dir(dir)
+			.map_err(|e| vec![format!("{}: {}", dir.display(), e)])?;
+		let mut paths = entries
+			.filter_map(|entry| entry.ok())
+			.map(|entry| entry.path())
+			.collect::<Vec<_>>();
+		paths.sort();
+		let mut errors = Vec::new();
+		for path in paths {
+			if path.extension().and_then(|ext| ext.to_str()) != Some("md") {
+				continue;
+			}
+			let name = path
+				.file_stem()
+				.and_then(|stem| stem.to_str())
+				.unwrap_or_default()
+				.to_string();
+			let result = std::fs::read_to_string(&path)
+				.map_err(|e| e.to_string())
+				.and_then(|text| self.clone().with_override(&name, &text));
+			match result {
+				Ok(templates) => self = templates,
+				Err(e) => errors.push(format!("{}: {}", path.display(), e)),
+			}
+		}
+		if errors.is_empty() {
+			Ok(self)
+		} else {
+			Err(errors)
+		}
+	}
+
+	/// The text of `name` with the placeholders filled in from `context` and
+	/// the deployment.
+	pub fn render(&self, name: &str, context: &[(&str, &str)]) -> String {
+		let text = self
+			.overrides
+			.get(name)
+			.map(String::as_str)
+			.unwrap_or(built_in(name).text);
+		let min_reviewers = self.min_reviewers.to_string();
+		let mut values = context.iter().cloned().collect::<HashMap<_, _>>();
+		values.insert("faq_url", &self.faq_url);
+		values.insert("burnin_contact", &self.burnin_contact);
+		values.insert("min_reviewers", &min_reviewers);
+		fill(text, &values)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_fill() {
+		let values = vec![("org", "paritytech"), ("n", "2")]
+			.into_iter()
+			.collect::<HashMap<_, _>>();
+		assert_eq!(
+			fill("Members of {{org}}, {{ n }} of them.", &values),
+			"Members of paritytech, 2 of them."
+		);
+		// left as they are
+		assert_eq!(
+			fill("{{unknown}} {{org}}", &values),
+			"{{unknown}} paritytech"
+		);
+		assert_eq!(fill("{{org", &values), "{{org");
+		assert_eq!(placeholders("{{org}} and {{ n }}"), Ok(vec!["org", "n"]));
+		assert!(placeholders("{{org").is_err());
+	}
+
+	#[test]
+	fn test_built_in() {
+		for template in BUILT_IN.iter() {
+			assert_eq!(check(template.name, template.text), Ok(()));
+		}
+		let templates = Templates::default();
+		assert_eq!(
+			templates.render(BURNIN_SUBMIT_FAILED, &[("requested_by", "alice")]),
+			"@alice your request could not be processed: Gitlab rejected the request. Please contact Staking Ops on Matrix."
+		);
+		let templates = Templates::new("https://example.com/faq", "@ops", 1);
+		assert_eq!(
+			templates.render(APPROVAL_HELP, &[]),
+			"See https://example.com/faq for how approvals are counted."
+		);
+		assert!(templates
+			.render(APPROVAL_REVIEWERS, &[])
+			.contains("by default 1 of `core-devs`"));
+		assert!(templates
+			.render(BURNIN_CANCEL_FAILED, &[("requested_by", "alice")])
+			.ends_with("Please contact @ops."));
+	}
+
+	#[test]
+	fn test_override() {
+		let error = Templates::default()
+			.with_override(
+				BURNIN_SUBMIT_FAILED,
+				"@{{requested_by}}: ask {{burnin_contact}} in {{org}}.",
+			)
+			.unwrap_err();
+		assert_eq!(error, "unknown placeholders {{org}}");
+		assert_eq!(
+			Templates::default()
+				.with_override("troubleshoot", "")
+				.unwrap_err(),
+			"there is no template troubleshoot"
+		);
+
+		let dir = std::env::temp_dir()
+			.join(format!("processbot-templates-{}", std::process::id()));
+		let _ = std::fs::remove_dir_all(&dir);
+		std::fs::create_dir_all(&dir).unwrap();
+		std::fs::write(
+			dir.join("burnin_submit_failed.md"),
+			"@{{requested_by}} Gitlab said no; ask {{burnin_contact}}.",
+		)
+		.unwrap();
+		std::fs::write(dir.join("README"), "not a template").unwrap();
+		let templates = Templates::default().load_dir(&dir).unwrap();
+		// the file beats the built-in text
+		assert_eq!(
+			templates
+				.render(BURNIN_SUBMIT_FAILED, &[("requested_by", "alice")]),
+			"@alice Gitlab said no; ask Staking Ops on Matrix."
+		);
+		// others are still built in
+		assert_eq!(
+			templates.render(PROCESS_HELP, &[]),
+			format!("See {} for how the process applies.", DEFAULT_FAQ_URL)
+		);
+
+		std::fs::write(
+			dir.join("burnin_prompt.md"),
+			"{{added_by}} {{reviewer}}",
+		)
+		.unwrap();
+		std::fs::write(dir.join("troubleshoot.md"), "").unwrap();
+		let errors = Templates::default().load_dir(&dir).unwrap_err();
+		assert_eq!(
+			errors,
+			vec![
+				format!(
+					"{}: unknown placeholders {{{{reviewer}}}}",
+					dir.join("burnin_prompt.md").display()
+				),
+				format!(
+					"{}: there is no template troubleshoot",
+					dir.join("troubleshoot.md").display()
+				),
+			]
+		);
+		let _ = std::fs::remove_dir_all(&dir);
+	}
+}
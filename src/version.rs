@@ -0,0 +1,92 @@
+use crate::burnin;
+
+/// The version of the crate the bot was built from.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The commit the bot was built from, if the build knew it.
+pub const GIT_SHA: Option<&str> = option_env!("PROCESSBOT_GIT_SHA");
+
+/// Eg. "processbot v0.4.2 (abc1234)".
+pub fn describe() -> String {
+	describe_build(VERSION, GIT_SHA)
+}
+
+fn describe_build(version: &str, git_sha: Option<&str>) -> String {
+	format!("processbot v{} ({})", version, git_sha.unwrap_or("unknown"))
+}
+
+/// `comment` signed with the build which posted it, so that a report of it
+/// tells which build was live.
+pub fn with_footer(comment: &str) -> String {
+	footer(comment, &describe())
+}
+
+fn footer(comment: &str, build: &str) -> String {
+	format!("{}\n\n<sub>{}</sub>", comment, build)
+}
+
+/// The reply to `bot version`.
+pub fn reply(config_fingerprint: &str, uptime_secs: i64) -> String {
+	format!(
+		"{}, configuration `{}`, up for {}.",
+		describe(),
+		config_fingerprint,
+		burnin::format_duration(uptime_secs.max(0))
+	)
+}
+
+/// The build, configuration and uptime, for `/health`.
+pub fn payload(
+	config_fingerprint: &str,
+	uptime_secs: i64,
+) -> serde_json::Value {
+	serde_json::json!({
+		"version": VERSION,
+		"git_sha": GIT_SHA,
+		"config_fingerprint": config_fingerprint,
+		"uptime_secs": uptime_secs.max(0),
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_describe() {
+		assert_eq!(
+			describe_build("0.4.2", Some("abc1234")),
+			"processbot v0.4.2 (abc1234)"
+		);
+		assert_eq!(
+			describe_build("0.4.2", None),
+			"processbot v0.4.2 (unknown)"
+		);
+		assert!(describe().starts_with(&format!("processbot v{} (", VERSION)));
+	}
+
+	#[test]
+	fn test_footer() {
+		assert_eq!(
+			footer("Merge failed: checks failed.", "processbot v0.4.2 (abc1234)"),
+			"Merge failed: checks failed.\n\n<sub>processbot v0.4.2 (abc1234)</sub>"
+		);
+		assert!(with_footer("Merge failed.")
+			.ends_with(&format!("<sub>{}</sub>", describe())));
+	}
+
+	#[test]
+	fn test_reply() {
+		assert_eq!(
+			reply("0123456789ab", 3723),
+			format!(
+				"{}, configuration `0123456789ab`, up for 1h 2m 3s.",
+				describe()
+			)
+		);
+		let payload = payload("0123456789ab", 60);
+		assert_eq!(payload["version"], VERSION);
+		assert_eq!(payload["config_fingerprint"], "0123456789ab");
+		assert_eq!(payload["uptime_secs"], 60);
+	}
+}
@@ -1,79 +1,384 @@
-use chrono::Utc;
+use chrono::{DateTime, TimeZone, Utc};
 use futures::StreamExt;
 use futures_util::future::TryFutureExt;
-use hyper::{http::StatusCode, Body, Request, Response};
+use hyper::{http::StatusCode, Body, Method, Request, Response};
 use itertools::Itertools;
-use ring::hmac;
 use rocksdb::DB;
-use serde::{Deserialize, Serialize};
 use snafu::{OptionExt, ResultExt};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
 use crate::{
-	auth::GithubUserAuthenticator, companion::*, config::BotConfig,
-	constants::*, error::*, github::*, github_bot::GithubBot, gitlab_bot::*,
-	matrix_bot::MatrixBot, performance, process, rebase::*, Result,
+	approval, audit,
+	auth::GithubUserAuthenticator,
+	auto_update::{self, AutoUpdate},
+	automerge,
+	backport::{self, Picked},
+	benchmark_check, burnin, cherry_pick, codeowners, command_access,
+	companion::*,
+	config::BotConfig,
+	constants::*,
+	cooldown, db,
+	debounce::ChecksDebounce,
+	effects::*,
+	error::*,
+	error_comments::{self, ErrorComment},
+	force_merge, freeze,
+	github::*,
+	github_bot::GithubBot,
+	gitlab,
+	gitlab_bot::*,
+	health::Health,
+	help, ignore,
+	in_flight::{self, InFlight},
+	installations,
+	journal::{self, JournalEntry, Phase, Recovery},
+	matrix_bot::MatrixBot,
+	merge_confirmation::{self, Confirmation, PendingConfirmation},
+	merge_gate,
+	merge_policy::{self, Block, MergeInputs, MergeIntent, MergeVerdict},
+	migrations, native_queue, own_activity, path_labels, performance,
+	permissions::{Capabilities, Capability},
+	process,
+	process_report::ProcessReport,
+	quarantine,
+	queue::{self, MergeOrigin, MergeRequest},
+	queue_position,
+	readiness::Readiness,
+	rebase::{self, rebase},
+	recording::Envelope,
+	release_notes::{self, ReleaseNotesCommand},
+	repo_config,
+	repo_ref::RepoRef,
+	reporting::*,
+	scheduled_merge, size_labels, spec_version,
+	stats::{self, MergeOutcome, MergeResult},
+	templates, version, welcome, working_clone, Result,
 };
 
+pub mod signature;
+
 /// This data gets passed along with each webhook to the webhook handler.
 pub struct AppState {
-	pub db: DB,
+	pub db: Arc<DB>,
 	pub github_bot: GithubBot,
 	pub matrix_bot: MatrixBot,
 	pub gitlab_bot: GitlabBot,
 
 	pub bot_config: BotConfig,
+	/// what the permissions of the GitHub App allow, as probed at startup
+	pub capabilities: Capabilities,
+	pub process_cache: process::ProcessCache,
+	pub repo_config_cache: repo_config::RepoConfigCache,
+	pub effects: Effects,
+	/// results of the startup checks
+	pub health: Health,
+	pub readiness: Arc<Readiness>,
+	pub checks_debounce: Arc<ChecksDebounce>,
+	pub error_sink: Option<Arc<dyn ErrorSink>>,
+	/// error comments not posted because they were posted recently
+	pub suppressed_error_comments: AtomicU64,
+	/// pending merges quarantined because they could not be read
+	pub quarantined_merge_requests: AtomicU64,
+	/// webhooks skipped as they echo what the bot did itself
+	pub skipped_own_events: AtomicU64,
 	pub webhook_secret: String,
+	pub gitlab_webhook_secret: String,
+	/// bearer token for `/audit`, which is not served if empty
+	pub audit_token: String,
+	/// bearer token for `/admin/queue` and `/admin/stats`, which are not served
+	/// if empty
+	pub admin_token: String,
+	/// tells the configuration the bot runs with apart from others
+	pub config_fingerprint: String,
+	pub started_at: DateTime<Utc>,
 }
 
-/// This stores information about a pull request while we wait for checks to complete.
-#[derive(Debug, Serialize, Deserialize)]
-#[repr(C)]
-pub struct MergeRequest {
-	owner: String,
-	repo_name: String,
-	number: i64,
-	html_url: String,
-	requested_by: String,
+/// Check the secret token Gitlab sends with each webhook.  Without a secret,
+/// no token is accepted, not even an empty one.
+fn verify_gitlab_token(secret: &[u8], req: &Request<Body>) -> bool {
+	!secret.is_empty()
+		&& req.headers().get("x-gitlab-token").is_some_and(|token| {
+			ring::constant_time::verify_slices_are_equal(
+				secret,
+				token.as_bytes(),
+			)
+			.is_ok()
+		})
+}
+
+/// Check the bearer token sent to `/audit`, `/admin/queue` or `/admin/stats`.
+fn verify_bearer_token(token: &[u8], req: &Request<Body>) -> bool {
+	req.headers()
+		.get("authorization")
+		.and_then(|value| value.as_bytes().strip_prefix(b"Bearer "))
+		.is_some_and(|sent| {
+			ring::constant_time::verify_slices_are_equal(token, sent).is_ok()
+		})
+}
+
+/// The audit log entries selected by the `repo` and `since` query parameters.
+fn audit_log(state: &AppState, query: Option<&str>) -> (StatusCode, String) {
+	let mut filter = audit::Filter::default();
+	for (key, value) in
+		url::form_urlencoded::parse(query.unwrap_or("").as_bytes())
+	{
+		match &*key {
+			"repo" => filter.repo = Some(value.to_string()),
+			"since" => match audit::parse_since(&value) {
+				Ok(since) => filter.since = Some(since),
+				Err(e) => return (StatusCode::BAD_REQUEST, e),
+			},
+			_ => {}
+		}
+	}
+	match audit::query(&state.db, &filter) {
+		Ok(entries) => (
+			StatusCode::OK,
+			serde_json::to_string(&entries).unwrap_or_default(),
+		),
+		Err(e) => {
+			log::error!("Error reading the audit log: {}", e);
+			(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+		}
+	}
+}
+
+/// The merge statistics over the `period` query parameter, 30 days by default.
+fn admin_stats(
+	state: &AppState,
+	method: &Method,
+	query: Option<&str>,
+	now: i64,
+) -> (StatusCode, String) {
+	if method != Method::GET {
+		return (StatusCode::METHOD_NOT_ALLOWED, String::new());
+	}
+	let mut period = Ok(stats::DEFAULT_PERIOD_DAYS * stats::DAY_SECS);
+	for (key, value) in
+		url::form_urlencoded::parse(query.unwrap_or("").as_bytes())
+	{
+		if key == "period" {
+			period = stats::parse_period(&value);
+		}
+	}
+	let period = match period {
+		Ok(period) => period,
+		Err(e) => return (StatusCode::BAD_REQUEST, e),
+	};
+	match stats::outcomes(&state.db, now - period).and_then(|outcomes| {
+		Ok(stats::aggregate(&outcomes, &queue::entries(&state.db)?))
+	}) {
+		Ok(stats) => (
+			StatusCode::OK,
+			serde_json::to_string(&stats).unwrap_or_default(),
+		),
+		Err(e) => {
+			log::error!("Error reading the merge statistics: {}", e);
+			(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+		}
+	}
+}
+
+/// Answer a request to `/admin/queue` followed by `path`: `GET` lists the
+/// pending merges, and `DELETE /<owner>/<repo>/<number>` cancels the merge of a
+/// PR.  Also returns the cancelled merges.
+fn admin_queue(
+	state: &AppState,
+	method: &Method,
+	path: &str,
+	now: i64,
+) -> (StatusCode, String, Vec<MergeRequest>) {
+	match (method, path.trim_end_matches('/')) {
+		(&Method::GET, "") => {
+			let entries = match queue::entries(&state.db) {
+				Ok(entries) => entries,
+				Err(e) => {
+					log::error!("Error reading the queue: {}", e);
+					return (
+						StatusCode::INTERNAL_SERVER_ERROR,
+						e.to_string(),
+						Vec::new(),
+					);
+				}
+			};
+			let entries = entries
+				.into_iter()
+				.map(|entry| {
+					let age = entry
+						.request
+						.as_ref()
+						.and_then(|request| request.created_at)
+						.map(|created_at| now - created_at);
+					serde_json::json!({
+						"sha": entry.sha,
+						"request": entry.request,
+						"age_secs": age,
+					})
+				})
+				.collect::<Vec<_>>();
+			(
+				StatusCode::OK,
+				serde_json::Value::from(entries).to_string(),
+				Vec::new(),
+			)
+		}
+		(_, "") => (StatusCode::METHOD_NOT_ALLOWED, String::new(), Vec::new()),
+		(&Method::DELETE, path) => {
+			let target = match path.split('/').collect::<Vec<_>>()[..] {
+				["", owner, repo_name, number] => {
+					number.parse().ok().map(|number| {
+						queue::Target::PullRequest {
+							owner: owner.to_string(),
+							repo_name: repo_name.to_string(),
+							number,
+						}
+					})
+				}
+				_ => None,
+			};
+			let target = match target {
+				Some(target) => target,
+				None => {
+					return (
+						StatusCode::NOT_FOUND,
+						"Not found.".to_string(),
+						Vec::new(),
+					)
+				}
+			};
+			let removed = db::atomically(&state.db, |tx| {
+				let removed = queue::remove(tx, &target)?;
+				for entry in &removed {
+					performance::clear_skip(tx, &entry.sha)?;
+				}
+				Ok(removed)
+			});
+			match removed {
+				Ok(removed) if removed.is_empty() => (
+					StatusCode::NOT_FOUND,
+					"No pending merge for this pull request.".to_string(),
+					Vec::new(),
+				),
+				Ok(removed) => (
+					StatusCode::OK,
+					serde_json::to_string(&removed).unwrap_or_default(),
+					removed
+						.into_iter()
+						.filter_map(|entry| entry.request)
+						.collect(),
+				),
+				Err(e) => {
+					log::error!(
+						"Error removing {:?} from the queue: {}",
+						target,
+						e
+					);
+					(
+						StatusCode::INTERNAL_SERVER_ERROR,
+						e.to_string(),
+						Vec::new(),
+					)
+				}
+			}
+		}
+		_ => (StatusCode::NOT_FOUND, "Not found.".to_string(), Vec::new()),
+	}
 }
 
-/// Check the SHA1 signature on a webhook payload.
-fn verify(
-	secret: &[u8],
-	msg: &[u8],
-	signature: &[u8],
-) -> Result<(), ring::error::Unspecified> {
-	let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, secret);
-	hmac::verify(&key, msg, signature)
+/// Let the PRs whose merges were cancelled at `/admin/queue` know, and those
+/// behind them where they are now.
+async fn notify_admin_cancelled(state: &AppState, cancelled: &[MergeRequest]) {
+	let repos = cancelled
+		.iter()
+		.map(|request| (request.owner.clone(), request.repo_name.clone()))
+		.unique()
+		.collect::<Vec<_>>();
+	for (owner, repo_name) in repos {
+		update_queue_positions(
+			&state.github_bot,
+			&state.db,
+			&owner,
+			&repo_name,
+		)
+		.await;
+	}
+	for request in cancelled {
+		let result = state
+			.github_bot
+			.create_issue_comment(
+				&request.owner,
+				&request.repo_name,
+				request.number,
+				&format!(
+					"An operator cancelled the pending merge (requested by @{}).",
+					request.requested_by
+				),
+			)
+			.await;
+		if let Err(e) = &result {
+			log::error!("Error posting comment: {}", e);
+		}
+		record_audit(
+			state,
+			"operator",
+			&request.owner,
+			&request.repo_name,
+			request.number,
+			"DELETE /admin/queue",
+			&result,
+		);
+	}
 }
 
 /// Receive a webhook and state object, acquire lock on state object.
+/// `/livez` and `/readyz` are answered without the lock.
 pub async fn webhook(
 	req: Request<Body>,
 	state: Arc<Mutex<AppState>>,
+	readiness: Arc<Readiness>,
 ) -> Result<Response<Body>> {
-	if req.uri().path() == "/webhook" {
-		// lock here to prevent double merge requests being sent (which often happens when checks
-		// complete because we receive redundant status hooks).
-		let state = &*state.lock().await;
-		let sig = req
-			.headers()
-			.get("x-hub-signature")
+	if req.uri().path() == "/livez" {
+		Response::builder()
+			.status(StatusCode::OK)
+			.body(Body::from(""))
+			.ok()
 			.context(Message {
-				msg: format!("Missing x-hub-signature"),
-			})?
-			.to_str()
+				msg: "Error building response".to_string(),
+			})
+	} else if req.uri().path() == "/readyz" {
+		let status = if readiness.is_ready() {
+			StatusCode::OK
+		} else {
+			StatusCode::SERVICE_UNAVAILABLE
+		};
+		Response::builder()
+			.status(status)
+			.header("content-type", "application/json")
+			.body(Body::from(readiness.payload().to_string()))
 			.ok()
 			.context(Message {
-				msg: format!("Error parsing x-hub-signature"),
-			})?
-			.to_string();
-		log::info!("Lock acquired for {:?}", sig);
-		if let Err(e) = webhook_inner(req, state).await {
-			handle_error(e, state).await;
+				msg: "Error building response".to_string(),
+			})
+	} else if req.uri().path() == "/webhook" {
+		let _pending = readiness.pending_webhook();
+		// lock here to prevent double merge requests being sent (which often happens when checks
+		// complete because we receive redundant status hooks).
+		let state = &*state.lock().await;
+		let mut context = ErrorContext {
+			event: header_value(&req, "x-github-event"),
+			delivery: header_value(&req, "x-github-delivery"),
+			..ErrorContext::default()
+		};
+		// the signature is checked once the body is read
+		let delivery = context.delivery.clone().unwrap_or_default();
+		log::info!("Lock acquired for delivery {:?}", delivery);
+		if let Err(e) = webhook_inner(req, state, &mut context).await {
+			handle_error(e, state, &context).await;
 		}
-		log::info!("Will release lock for {:?}", sig);
+		report_dry_run(state).await;
+		log::info!("Will release lock for delivery {:?}", delivery);
 		Response::builder()
 			.status(StatusCode::OK)
 			.body(Body::from(""))
@@ -81,6 +386,139 @@ pub async fn webhook(
 			.context(Message {
 				msg: format!("Error building response"),
 			})
+	} else if req.uri().path() == "/gitlab-webhook" {
+		let _pending = readiness.pending_webhook();
+		let state = &*state.lock().await;
+		let status = if verify_gitlab_token(
+			state.gitlab_webhook_secret.trim().as_bytes(),
+			&req,
+		) {
+			if let Err(e) = gitlab_webhook_inner(req, state).await {
+				log::error!("Error handling Gitlab webhook: {}", e);
+				if let Some(sink) = &state.error_sink {
+					let context = ErrorContext {
+						event: Some("gitlab".to_string()),
+						..ErrorContext::default()
+					};
+					sink.report(&context.report(&e));
+				}
+			}
+			StatusCode::OK
+		} else {
+			log::warn!("Rejected Gitlab webhook with invalid X-Gitlab-Token");
+			StatusCode::UNAUTHORIZED
+		};
+		Response::builder()
+			.status(status)
+			.body(Body::from(""))
+			.ok()
+			.context(Message {
+				msg: "Error building response".to_string(),
+			})
+	} else if req.uri().path() == "/audit" {
+		let state = &*state.lock().await;
+		let (status, body) = if state.audit_token.trim().is_empty() {
+			(StatusCode::NOT_FOUND, "Not found.".to_string())
+		} else if !verify_bearer_token(
+			state.audit_token.trim().as_bytes(),
+			&req,
+		) {
+			log::warn!("Rejected /audit request with invalid token");
+			(StatusCode::UNAUTHORIZED, String::new())
+		} else {
+			audit_log(state, req.uri().query())
+		};
+		Response::builder()
+			.status(status)
+			.header("content-type", "application/json")
+			.body(Body::from(body))
+			.ok()
+			.context(Message {
+				msg: "Error building response".to_string(),
+			})
+	} else if req.uri().path() == "/admin/stats" {
+		let state = &*state.lock().await;
+		let (status, body) = if state.admin_token.trim().is_empty() {
+			(StatusCode::NOT_FOUND, "Not found.".to_string())
+		} else if !verify_bearer_token(
+			state.admin_token.trim().as_bytes(),
+			&req,
+		) {
+			log::warn!("Rejected /admin/stats request with invalid token");
+			(StatusCode::UNAUTHORIZED, String::new())
+		} else {
+			admin_stats(
+				state,
+				req.method(),
+				req.uri().query(),
+				Utc::now().timestamp(),
+			)
+		};
+		Response::builder()
+			.status(status)
+			.header("content-type", "application/json")
+			.body(Body::from(body))
+			.ok()
+			.context(Message {
+				msg: "Error building response".to_string(),
+			})
+	} else if let Some(path) = req.uri().path().strip_prefix("/admin/queue") {
+		let state = &*state.lock().await;
+		let (status, body) = if state.admin_token.trim().is_empty() {
+			(StatusCode::NOT_FOUND, "Not found.".to_string())
+		} else if !verify_bearer_token(
+			state.admin_token.trim().as_bytes(),
+			&req,
+		) {
+			log::warn!("Rejected /admin/queue request with invalid token");
+			(StatusCode::UNAUTHORIZED, String::new())
+		} else {
+			let (status, body, cancelled) =
+				admin_queue(state, req.method(), path, Utc::now().timestamp());
+			notify_admin_cancelled(state, &cancelled).await;
+			(status, body)
+		};
+		Response::builder()
+			.status(status)
+			.header("content-type", "application/json")
+			.body(Body::from(body))
+			.ok()
+			.context(Message {
+				msg: "Error building response".to_string(),
+			})
+	} else if req.uri().path() == "/health" {
+		let state = &*state.lock().await;
+		let mut payload = state.health.payload();
+		payload["github_retries"] = state.github_bot.client.retries().into();
+		payload["comment_queue_depth"] =
+			state.github_bot.comments.depth().into();
+		payload["comment_queue_pauses"] =
+			state.github_bot.comments.pauses().into();
+		payload["suppressed_error_comments"] = state
+			.suppressed_error_comments
+			.load(Ordering::Relaxed)
+			.into();
+		payload["quarantined_merge_requests"] = state
+			.quarantined_merge_requests
+			.load(Ordering::Relaxed)
+			.into();
+		payload["skipped_own_events"] =
+			state.skipped_own_events.load(Ordering::Relaxed).into();
+		payload["coalesced_check_events"] =
+			state.checks_debounce.coalesced().into();
+		payload["missing_permissions"] = state.capabilities.payload();
+		payload["build"] = version::payload(
+			&state.config_fingerprint,
+			(Utc::now() - state.started_at).num_seconds(),
+		);
+		Response::builder()
+			.status(StatusCode::OK)
+			.header("content-type", "application/json")
+			.body(Body::from(payload.to_string()))
+			.ok()
+			.context(Message {
+				msg: "Error building response".to_string(),
+			})
 	} else {
 		Response::builder()
 			.status(StatusCode::NOT_FOUND)
@@ -92,987 +530,1401 @@ pub async fn webhook(
 	}
 }
 
-/// Parse webhook body and verify.
+fn header_value(req: &Request<Body>, name: &str) -> Option<String> {
+	req.headers()
+		.get(name)
+		.and_then(|value| value.to_str().ok())
+		.map(str::to_string)
+}
+
+/// Log what a dry run would have done for the last webhook, and optionally post
+/// it on the PR the first of those changes was for.
+async fn report_dry_run(state: &AppState) {
+	let plan = state.effects.take_plan();
+	if plan.is_empty() {
+		return;
+	}
+	let msg = plan_message(&plan);
+	log::info!("{}", msg);
+	if state.bot_config.dry_run_comment {
+		let (owner, repo_name, number) = plan[0].pull_request();
+		if let Err(e) = state
+			.github_bot
+			.create_issue_comment(owner, repo_name, number, &msg)
+			.await
+		{
+			log::error!("Error posting comment: {}", e);
+		}
+	}
+}
+
+/// Verify a webhook from GitHub, record it if configured to, and handle it,
+/// noting the bot command it carries in `context`.
 pub async fn webhook_inner(
-	mut req: Request<Body>,
+	req: Request<Body>,
 	state: &AppState,
+	context: &mut ErrorContext,
 ) -> Result<()> {
-	let mut msg_bytes = vec![];
-	while let Some(item) = req.body_mut().next().await {
-		msg_bytes.extend_from_slice(&item.ok().context(Message {
-			msg: format!("Error getting bytes from request body"),
-		})?);
+	let (headers, msg_bytes) = verified_body(req, state).await?;
+	if let Some(dir) = &state.bot_config.record_payloads_dir {
+		match Envelope::new(&headers, &msg_bytes, Utc::now())
+			.and_then(|envelope| envelope.record(dir))
+		{
+			Ok(path) => log::info!("Recorded payload to {}", path.display()),
+			Err(e) => log::error!("Error recording payload: {}", e),
+		}
 	}
 
-	let sig = req
-		.headers()
-		.get("x-hub-signature")
-		.context(Message {
-			msg: format!("Missing x-hub-signature"),
-		})?
-		.to_str()
-		.ok()
-		.context(Message {
-			msg: format!("Error parsing x-hub-signature"),
-		})?
-		.replace("sha1=", "");
-	let sig_bytes = base16::decode(sig.as_bytes()).ok().context(Message {
-		msg: format!("Error decoding x-hub-signature"),
-	})?;
-
-	verify(
-		state.webhook_secret.trim().as_bytes(),
-		&msg_bytes,
-		&sig_bytes,
-	)
-	.ok()
-	.context(Message {
-		msg: format!("Validation signature does not match"),
-	})?;
-
 	let payload = serde_json::from_slice::<Payload>(&msg_bytes).ok().context(
 		Message {
 			msg: format!("Error parsing request body"),
 		},
 	)?;
+	if let Payload::IssueComment { comment, .. } = &payload {
+		context.command = state
+			.bot_config
+			.command(&comment.body)
+			.and_then(|command| bot_command(&command));
+	}
 
 	handle_payload(payload, state).await
 }
 
-/// Match different kinds of payload.
-async fn handle_payload(payload: Payload, state: &AppState) -> Result<()> {
-	match payload {
-		Payload::IssueComment {
-			action: IssueCommentAction::Created,
-			comment:
-				Comment {
-					body,
-					user: User { login, .. },
-					..
-				},
-			issue:
-				Issue {
-					number,
-					html_url,
-					repository_url: Some(repo_url),
-					pull_request: Some(_), // indicates the issue is a pr
-					..
-				},
-		} => {
-			handle_comment(body, login, number, html_url, repo_url, state).await
+fn bot_command(body: &str) -> Option<String> {
+	let body = body.trim().to_lowercase();
+	if body.starts_with("bot ") {
+		Some(body)
+	} else {
+		None
+	}
+}
+
+/// Remember that the bot pushed `sha`, so that the checks it sets on it are
+/// known as its own.
+fn record_push(state: &AppState, sha: &str) {
+	if let Err(e) =
+		own_activity::record_push(&state.db, sha, Utc::now().timestamp())
+	{
+		log::error!("Error recording push of {}: {}", sha, e);
+	}
+}
+
+/// What the payload echoes of the bot's own doing, if it does: a comment it
+/// posted, a status it set, or a check run it set on a commit it pushed.
+/// Check runs set by others on those commits are handled as usual.
+fn own_activity(payload: &Payload, state: &AppState) -> Result<Option<String>> {
+	let bot_login = &state.bot_config.bot_login;
+	Ok(match payload {
+		Payload::IssueComment { comment, .. }
+			if own_activity::is_own_login(&comment.user.login, bot_login) =>
+		{
+			Some(format!("comment {} posted by the bot", comment.id))
+		}
+		Payload::CommitStatus { sha, context, .. }
+			if own_activity::is_own_context(context) =>
+		{
+			Some(format!("status {} set by the bot on {}", context, sha))
 		}
-		Payload::CommitStatus {
-			sha, state: status, ..
-		} => handle_status(sha, status, state).await,
 		Payload::CheckRun {
-			check_run: CheckRun {
-				status, head_sha, ..
-			},
-			..
-		} => handle_check(status, head_sha, state).await,
-		Payload::PullRequest {
-			action: PullRequestAction::Labeled,
-			label: Label { name: label, .. },
-			sender: User {
-				login: added_by, ..
-			},
-			repository:
-				Repository {
-					name: repo_name,
-					owner: User {
-						login: repo_owner, ..
-					},
-					..
-				},
-			pull_request,
+			check_run,
+			sender: Some(sender),
 			..
-		} => {
-			handle_label_added(
-				label,
-				added_by,
-				repo_name,
-				repo_owner,
-				pull_request,
-				&state.github_bot,
-			)
-			.await
+		} if own_activity::is_own_login(&sender.login, bot_login)
+			&& own_activity::recently_pushed(
+				&state.db,
+				&check_run.head_sha,
+				Utc::now().timestamp(),
+			)? =>
+		{
+			Some(format!(
+				"check run {} set by the bot on {}, which it pushed",
+				check_run.name, check_run.head_sha
+			))
 		}
-		_event => Ok(()),
+		_ => None,
+	})
+}
+
+/// Whether a comment is to be handled as a new one: when it is created, or
+/// when an edit turns it into a command, eg. by fixing a typo in it.  An edit
+/// of a command, even into another one, is not, so that no command runs twice.
+fn is_new_comment(
+	action: &IssueCommentAction,
+	changes: Option<&CommentChanges>,
+	body: &str,
+) -> bool {
+	match action {
+		IssueCommentAction::Created => true,
+		IssueCommentAction::Edited => changes
+			.and_then(|changes| changes.body.as_ref())
+			.is_some_and(|old| {
+				help::is_command(body) && !help::is_command(&old.from)
+			}),
+		IssueCommentAction::Deleted => false,
 	}
 }
 
-/// If a check completes, query if all statuses and checks are complete.
-async fn handle_check(
-	status: String,
-	commit_sha: String,
-	state: &AppState,
-) -> Result<()> {
-	let db = &state.db;
-	let github_bot = &state.github_bot;
+/// Handle a recorded webhook as if it had just been received.  Its signature
+/// was checked when it was recorded.  Unlike a live webhook, errors are
+/// returned rather than commented on the PR.
+pub async fn replay(envelope: Envelope, state: &AppState) -> Result<()> {
+	let payload =
+		serde_json::from_value::<Payload>(envelope.payload).context(Json)?;
+	let result = handle_payload(payload, state).await;
+	report_dry_run(state).await;
+	result
+}
 
-	if status == "completed".to_string() {
-		checks_and_status(github_bot, &commit_sha, db).await?;
+/// Read the webhook body and check its signature.
+async fn verified_body(
+	mut req: Request<Body>,
+	state: &AppState,
+) -> Result<(hyper::HeaderMap, Vec<u8>)> {
+	let mut msg_bytes = vec![];
+	while let Some(item) = req.body_mut().next().await {
+		msg_bytes.extend_from_slice(&item.ok().context(Message {
+			msg: format!("Error getting bytes from request body"),
+		})?);
 	}
 
-	Ok(())
+	signature::verify(
+		state.webhook_secret.as_bytes(),
+		req.headers(),
+		&msg_bytes,
+	)?;
+
+	Ok((req.into_parts().0.headers, msg_bytes))
 }
 
-/// If we receive a status other than `Pending`, query if all statuses and checks are complete.
-async fn handle_status(
-	commit_sha: String,
-	status: StatusState,
+/// Parse a Gitlab webhook body. Events other than pipeline and job events are
+/// ignored.
+async fn gitlab_webhook_inner(
+	mut req: Request<Body>,
 	state: &AppState,
 ) -> Result<()> {
-	let db = &state.db;
-	let github_bot = &state.github_bot;
+	let mut msg_bytes = vec![];
+	while let Some(item) = req.body_mut().next().await {
+		msg_bytes.extend_from_slice(&item.ok().context(Message {
+			msg: "Error getting bytes from request body".to_string(),
+		})?);
+	}
 
-	if status != StatusState::Pending {
-		checks_and_status(github_bot, &commit_sha, db).await?;
+	match serde_json::from_slice::<gitlab::Payload>(&msg_bytes) {
+		Ok(payload) => handle_gitlab_payload(payload, state).await,
+		Err(e) => {
+			log::info!("Ignoring Gitlab event: {}", e);
+			Ok(())
+		}
 	}
-	Ok(())
 }
 
-/// Check that no commit has been pushed since the merge request was received.  Query checks and
-/// statuses and if they are green, attempt merge.
-async fn checks_and_status(
-	github_bot: &GithubBot,
-	commit_sha: &str,
-	db: &DB,
+/// Update the burn-in record a Gitlab pipeline or job belongs to, and report
+/// the outcome on the PR once the pipeline has finished.  Events for
+/// pipelines which were not triggered by a burn-in request are ignored.
+async fn handle_gitlab_payload(
+	payload: gitlab::Payload,
+	state: &AppState,
 ) -> Result<()> {
-	if let Some(b) = db.get(commit_sha.trim().as_bytes()).context(Db)? {
-		let m = bincode::deserialize(&b).context(Bincode)?;
-		log::info!("Deserialized merge request: {:?}", m);
-		let MergeRequest {
-			owner,
-			repo_name,
-			number,
-			html_url,
-			requested_by: _,
-		} = m;
-		let pr = github_bot.pull_request(&owner, &repo_name, number).await?;
-
-		// Head sha should not have changed since request was made.
-		if commit_sha == pr.head.sha {
-			log::info!(
-				"Commit sha {} matches head of {}",
-				commit_sha,
-				html_url
-			);
-
-			// Delay after status hook to avoid false success
-			tokio::time::delay_for(std::time::Duration::from_millis(1000))
-				.await;
-
-			let checks = github_bot
-				.check_runs(&owner, &repo_name, &commit_sha)
-				.await?;
-			log::info!("{:?}", checks);
-			if checks
-				.check_runs
-				.iter()
-				.all(|r| r.conclusion == Some("success".to_string()))
+	let now = Utc::now().timestamp();
+	let records = burnin::all_records(&state.db)?;
+	match payload {
+		gitlab::Payload::Pipeline(event) => {
+			let attrs = &event.object_attributes;
+			let mut record = match burnin::find_record_for_commit(
+				records,
+				&attrs.sha,
+				event.commit.as_ref().map(|c| c.message.as_str()),
+				now,
+			) {
+				Some(record) => record,
+				None => {
+					log::info!("Ignoring unknown Gitlab pipeline {}", attrs.id);
+					return Ok(());
+				}
+			};
+			let pipeline_url = event.web_url();
+			let new_state =
+				burnin::BurninState::from_pipeline_status(&attrs.status);
+			// Gitlab may deliver the same event more than once
+			if record.state == new_state
+				&& record.pipeline_url.as_ref() == Some(&pipeline_url)
 			{
-				log::info!("All checks success");
-				let status =
-					github_bot.status(&owner, &repo_name, &commit_sha).await?;
-				log::info!("{:?}", status);
-				match status {
-					CombinedStatus {
-						state: StatusState::Success,
-						..
-					} => {
-						log::info!("{} is green; attempting merge.", html_url);
-
-						// to reach here merge must be allowed
-						merge(github_bot, &owner, &repo_name, &pr).await?;
+				return Ok(());
+			}
+			record.gitlab_commit = Some(attrs.sha.clone());
+			record.pipeline_url = Some(pipeline_url.clone());
+			record.state = new_state;
+			burnin::store_record(&state.db, &record)?;
 
-						// clean db
-						db.delete(pr.head.sha.trim().as_bytes())
-							.context(Db)
-							.map_err(|e| {
-								e.map_issue(Some((
-									owner.to_string(),
-									repo_name.to_string(),
-									pr.number,
-								)))
-							})?;
-
-						// update companion if necessary
-						update_companion(github_bot, &repo_name, &pr, db)
-							.await?;
-					}
-					CombinedStatus {
-						state: StatusState::Failure,
-						..
-					} => {
-						log::info!("{} status failure.", html_url);
-						Err(Error::ChecksFailed {
-							commit_sha: commit_sha.to_string(),
-						}
-						.map_issue(Some((
-							owner.to_string(),
-							repo_name.to_string(),
-							pr.number,
-						))))?;
-					}
-					CombinedStatus {
-						state: StatusState::Error,
-						..
-					} => {
-						log::info!("{} status error.", html_url);
-						Err(Error::ChecksFailed {
-							commit_sha: commit_sha.to_string(),
-						}
-						.map_issue(Some((
-							owner.to_string(),
-							repo_name.to_string(),
-							pr.number,
-						))))?;
-					}
-					CombinedStatus {
-						state: StatusState::Pending,
-						..
-					} => {
-						log::info!("{} is pending.", html_url);
-					}
+			if record.state.is_finished() {
+				let outcome = match record.state {
+					burnin::BurninState::Completed => "succeeded",
+					_ => "failed",
+				};
+				let duration = attrs
+					.duration
+					.map(|secs| {
+						format!(" after {}", burnin::format_duration(secs))
+					})
+					.unwrap_or_default();
+				let msg = format!(
+					"@{} burn-in of {} {}{}: {}",
+					record.requested_by,
+					record.commit_sha,
+					outcome,
+					duration,
+					pipeline_url
+				);
+				if let Err(e) = state
+					.github_bot
+					.create_issue_comment(
+						&record.owner,
+						&record.repo_name,
+						record.number,
+						&msg,
+					)
+					.await
+				{
+					log::error!("Error posting comment: {:?}", e);
 				}
-			} else if checks
-				.check_runs
-				.iter()
-				.all(|r| r.status == "completed".to_string())
-			{
-				log::info!("{} checks were unsuccessful", html_url);
-				Err(Error::ChecksFailed {
-					commit_sha: commit_sha.to_string(),
+
+				let matrix_msg = format!(
+					r#"Burn-in for <a href="{}">{}#{}</a> {}{}: <a href="{}">pipeline</a>"#,
+					record.html_url,
+					record.repo_name,
+					record.number,
+					outcome,
+					duration,
+					pipeline_url,
+				);
+				if let Err(e) = state.matrix_bot.send_html_to_room(
+					&state.bot_config.burnin_room_id,
+					&matrix_msg,
+				) {
+					log::error!("Error sending Matrix message: {:?}", e);
 				}
-				.map_issue(Some((
-					owner.to_string(),
-					repo_name.to_string(),
-					pr.number,
-				))))?;
-			} else {
-				log::info!("{} checks incomplete", html_url);
 			}
-		} else {
-			// Head sha has changed since merge request.
-			log::info!(
-				"Head sha has changed since merge was requested on {}",
-				html_url
-			);
-			Err(Error::HeadChanged {
-				commit_sha: commit_sha.to_string(),
+		}
+		gitlab::Payload::Job(event) => {
+			if let Some(mut record) = burnin::find_record_for_commit(
+				records,
+				&event.sha,
+				Some(&event.commit.message),
+				now,
+			) {
+				if record.state == burnin::BurninState::Submitted {
+					record.gitlab_commit = Some(event.sha);
+					record.state = burnin::BurninState::Running;
+					burnin::store_record(&state.db, &record)?;
+				}
 			}
-			.map_issue(Some((
-				owner.to_string(),
-				repo_name.to_string(),
-				pr.number,
-			))))?;
 		}
 	}
-
 	Ok(())
 }
 
-/// Parse bot commands in pull request comments.  Possible commands include:
-/// `bot merge`
-/// `bot merge force`
-/// `bot merge cancel`
-/// `bot compare substrate`
-/// `bot rebase`
-/// `bot burnin`
-///
-/// See also README.md.
-async fn handle_comment(
-	body: String,
-	requested_by: String,
-	number: i64,
-	html_url: String,
-	repo_url: String,
+/// The audit log entry of a privileged action, unless this is a dry run.
+fn audit_entry<T>(
 	state: &AppState,
-) -> Result<()> {
-	let db = &state.db;
-	let github_bot = &state.github_bot;
-	let bot_config = &state.bot_config;
-
-	let owner = GithubBot::owner_from_html_url(&html_url).context(Message {
-		msg: format!("Failed parsing owner in url: {}", html_url),
-	})?;
-
-	let repo_name =
-		repo_url.rsplit('/').next().map(|s| s.to_string()).context(
-			Message {
-				msg: format!("Failed parsing repo name in url: {}", repo_url),
-			},
-		)?;
-
-	// Fetch the pr to get all fields (eg. mergeable).
-	let pr = github_bot
-		.pull_request(owner, &repo_name, number)
-		.await
-		.map_err(|e| {
-			e.map_issue(Some((
-				owner.to_string(),
-				repo_name.to_string(),
-				number,
-			)))
-		})?;
-
-	let auth =
-		GithubUserAuthenticator::new(&requested_by, owner, &repo_name, number);
-
-	if body.to_lowercase().trim() == AUTO_MERGE_REQUEST.to_lowercase().trim() {
-		//
-		// MERGE
-		//
-		log::info!(
-			"Received merge request for PR {} from user {}",
-			html_url,
-			requested_by
-		);
+	actor: &str,
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+	command: &str,
+	result: &Result<T>,
+) -> Option<audit::AuditEntry> {
+	if state.effects.is_dry_run() {
+		return None;
+	}
+	Some(audit::AuditEntry::new(
+		actor, owner, repo_name, number, command, result,
+	))
+}
 
-		auth.check_org_membership(&github_bot).await?;
+fn write_audit(state: &AppState, entry: Option<audit::AuditEntry>) {
+	if let Some(entry) = entry {
+		if let Err(e) =
+			db::atomically(&state.db, |tx| audit::record(tx, &entry))
+		{
+			log::error!("Error recording {:?} in the audit log: {}", entry, e);
+		}
+	}
+}
 
-		//
-		// merge allowed
-		//
-		merge_allowed(
-			github_bot,
-			owner,
-			&repo_name,
-			&pr,
-			&bot_config,
-			&requested_by,
-		)
-		.await?;
+/// Record a privileged action in the audit log, whether or not it succeeded.
+fn record_audit<T>(
+	state: &AppState,
+	actor: &str,
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+	command: &str,
+	result: &Result<T>,
+) {
+	write_audit(
+		state,
+		audit_entry(state, actor, owner, repo_name, number, command, result),
+	);
+}
 
-		//
-		// status and merge
-		//
-		if ready_to_merge(github_bot, owner, &repo_name, &pr).await? {
-			prepare_to_merge(
-				github_bot,
-				owner,
-				&repo_name,
-				pr.number,
-				&pr.html_url,
-			)
-			.await?;
+/// The comment as a `bot` command, as the commands are matched whatever their
+/// configured prefix, or none if it is no command.  The body an edit replaced
+/// is rewritten likewise, to tell whether it was a command.
+fn with_canonical_command(
+	payload: Payload,
+	config: &BotConfig,
+) -> Option<Payload> {
+	match payload {
+		Payload::IssueComment {
+			action,
+			issue,
+			mut comment,
+			mut changes,
+		} => {
+			comment.body = config.command(&comment.body)?;
+			if let Some(old) =
+				changes.as_mut().and_then(|changes| changes.body.as_mut())
+			{
+				old.from = config.command(&old.from).unwrap_or_default();
+			}
+			Some(Payload::IssueComment {
+				action,
+				issue,
+				comment,
+				changes,
+			})
+		}
+		payload => Some(payload),
+	}
+}
 
-			merge(github_bot, owner, &repo_name, &pr).await?;
-			update_companion(github_bot, &repo_name, &pr, db).await?;
-		} else {
-			wait_to_merge(
-				github_bot,
-				owner,
-				&repo_name,
-				pr.number,
-				&pr.html_url,
-				&requested_by,
-				&pr.head.sha,
-				db,
+/// Match different kinds of payload.
+async fn handle_payload(payload: Payload, state: &AppState) -> Result<()> {
+	if let Some(what) = own_activity(&payload, state)? {
+		log::debug!("Skipping {}", what);
+		state.skipped_own_events.fetch_add(1, Ordering::Relaxed);
+		return Ok(());
+	}
+	// comments other than commands, eg. with a prefix not configured, are
+	// ignored
+	let payload = match with_canonical_command(payload, &state.bot_config) {
+		Some(payload) => payload,
+		None => return Ok(()),
+	};
+	match payload {
+		Payload::IssueComment {
+			action,
+			comment:
+				Comment {
+					id: comment_id,
+					body,
+					user: User { login, .. },
+					..
+				},
+			issue:
+				Issue {
+					number,
+					html_url,
+					repository_url: Some(repo_url),
+					pull_request: Some(_), // indicates the issue is a pr
+					..
+				},
+			changes,
+		} if is_new_comment(&action, changes.as_ref(), &body) => {
+			// as commented, so that eg. the reason for a forced merge keeps
+			// its case
+			let command = bot_command(&body).map(|_| body.trim().to_string());
+			let result = handle_comment(
+				body,
+				comment_id,
+				login.clone(),
+				number,
+				html_url.clone(),
+				repo_url.clone(),
+				state,
 			)
-			.await?;
+			.await;
+			if let Some(command) =
+				command.filter(|command| audit::is_privileged(command))
+			{
+				if let Some(RepoRef { owner, repo }) =
+					issue_repo(&repo_url, &html_url)
+				{
+					record_audit(
+						state, &login, &owner, &repo, number, &command, &result,
+					);
+				}
+			}
+			result
 		}
-	} else if body.to_lowercase().trim()
-		== AUTO_MERGE_FORCE.to_lowercase().trim()
-	{
-		//
-		// MERGE
-		//
-		log::info!(
-			"Received merge request for PR {} from user {}",
-			html_url,
-			requested_by
-		);
-
-		auth.check_org_membership(&github_bot).await?;
-
-		//
-		// merge allowed
-		//
-		merge_allowed(
-			github_bot,
-			owner,
-			&repo_name,
-			&pr,
-			&bot_config,
-			&requested_by,
-		)
-		.await?;
-
-		//
-		// attempt merge without wait for checks
-		//
-		prepare_to_merge(
-			github_bot,
-			owner,
-			&repo_name,
-			pr.number,
-			&pr.html_url,
-		)
-		.await?;
-		merge(github_bot, owner, &repo_name, &pr).await?;
-		update_companion(github_bot, &repo_name, &pr, db).await?;
-	} else if body.to_lowercase().trim()
-		== AUTO_MERGE_CANCEL.to_lowercase().trim()
-	{
-		//
-		// CANCEL MERGE
-		//
-		log::info!(
-			"Received merge cancel for PR {} from user {}",
-			html_url,
-			requested_by
-		);
-		log::info!("Deleting merge request for {}", &html_url);
-		db.delete(pr.head.sha.trim().as_bytes())
-			.context(Db)
-			.map_err(|e| {
-				e.map_issue(Some((
-					owner.to_string(),
-					repo_name.to_string(),
+		Payload::IssueComment {
+			action,
+			comment: Comment { body, .. },
+			issue:
+				Issue {
 					number,
-				)))
-			})?;
-		let _ = github_bot
-			.create_issue_comment(
-				owner,
-				&repo_name,
-				pr.number,
-				"Merge cancelled.",
+					html_url,
+					repository_url: Some(repo_url),
+					pull_request: None,
+					..
+				},
+			changes,
+		} if is_new_comment(&action, changes.as_ref(), &body) => {
+			handle_issue_comment(&body, number, &html_url, &repo_url, state)
+				.await
+		}
+		Payload::CommitStatus {
+			sha, state: status, ..
+		} => handle_status(sha, status, state).await,
+		Payload::PullRequestReview {
+			action:
+				PullRequestReviewAction::Submitted
+				| PullRequestReviewAction::Dismissed,
+			pull_request,
+			repository,
+			..
+		} => {
+			refresh_merge_gate(
+				state,
+				&repository.owner.login,
+				&repository.name,
+				pull_request.number,
 			)
-			.await
-			.map_err(|e| {
-				log::error!("Error posting comment: {}", e);
-			});
-	} else if repo_name == "polkadot"
-		&& body.to_lowercase().trim()
-			== COMPARE_RELEASE_REQUEST.to_lowercase().trim()
-	{
-		//
-		// DIFF
-		//
-		log::info!(
-			"Received diff request for PR {} from user {}",
-			html_url,
-			requested_by
-		);
-		let rel = github_bot.latest_release(owner, &repo_name).await.map_err(
-			|e| {
-				e.map_issue(Some((
-					owner.to_string(),
-					repo_name.to_string(),
-					number,
-				)))
-			},
-		)?;
-		let release_tag = github_bot
-			.tag(owner, &repo_name, &rel.tag_name)
-			.await
-			.map_err(|e| {
-				e.map_issue(Some((
-					owner.to_string(),
-					repo_name.to_string(),
-					number,
-				)))
-			})?;
-		let release_substrate_commit = github_bot
-			.substrate_commit_from_polkadot_commit(&release_tag.object.sha)
-			.await;
-		let branch_substrate_commit = github_bot
-			.substrate_commit_from_polkadot_commit(&pr.head.sha)
 			.await;
-		if release_substrate_commit.is_ok() && branch_substrate_commit.is_ok() {
-			let link = github_bot.diff_url(
-				owner,
-				"substrate",
-				&release_substrate_commit.unwrap(),
-				&branch_substrate_commit.unwrap(),
-			);
-
-			// post link
-			log::info!("Posting link to substrate diff: {}", &link);
-			let _ = github_bot
-				.create_issue_comment(owner, &repo_name, number, &link)
-				.await
-				.map_err(|e| {
-					log::error!("Error posting comment: {}", e);
-				});
-		} else {
-			if let Err(e) = release_substrate_commit {
-				log::error!("Error getting release substrate commit: {}", e);
-				Err(e.map_issue(Some((
-					owner.to_string(),
-					repo_name.to_string(),
-					number,
-				))))?;
+			Ok(())
+		}
+		Payload::CheckRun {
+			check_run: CheckRun {
+				status, head_sha, ..
+			},
+			..
+		} => handle_check(status, head_sha, state).await,
+		Payload::PullRequest {
+			action: PullRequestAction::Labeled,
+			label: Some(Label { name: label, .. }),
+			sender: User {
+				login: added_by, ..
+			},
+			repository:
+				Repository {
+					name: repo_name,
+					owner: User {
+						login: repo_owner, ..
+					},
+					..
+				},
+			pull_request,
+			..
+		} => {
+			if ignore::skips(
+				&state.db,
+				&repo_owner,
+				&repo_name,
+				pull_request.number,
+				"label handling",
+			)? {
+				return Ok(());
 			}
-			if let Err(e) = branch_substrate_commit {
-				log::error!("Error getting branch substrate commit: {}", e);
-				Err(e.map_issue(Some((
-					owner.to_string(),
-					repo_name.to_string(),
-					number,
-				))))?;
+			if let Err(e) = request_process_reviews(
+				&pull_request,
+				&repo_owner,
+				&repo_name,
+				state,
+			)
+			.await
+			{
+				log::error!(
+					"Error requesting reviews on {}: {}",
+					pull_request.html_url,
+					e
+				);
 			}
+			let number = pull_request.number;
+			let result = handle_label_added(
+				label,
+				added_by,
+				repo_name.clone(),
+				repo_owner.clone(),
+				pull_request,
+				state,
+			)
+			.await;
+			refresh_merge_gate(state, &repo_owner, &repo_name, number).await;
+			result
 		}
-	} else if body.to_lowercase().trim() == REBASE.to_lowercase().trim() {
-		log::info!("Rebase {} requested by {}", html_url, requested_by);
-		if let PullRequest {
-			head:
-				Head {
-					ref_field: head_branch,
-					repo:
-						HeadRepo {
-							name: head_repo,
-							owner:
-								Some(User {
-									login: head_owner, ..
-								}),
-							..
-						},
+		Payload::PullRequest {
+			action: PullRequestAction::Unlabeled,
+			label: Some(Label { name: label, .. }),
+			repository:
+				Repository {
+					name: repo_name,
+					owner: User {
+						login: repo_owner, ..
+					},
 					..
 				},
+			pull_request,
 			..
-		} = pr.clone()
-		{
-			let _ = github_bot
-				.create_issue_comment(owner, &repo_name, pr.number, "Rebasing.")
-				.await
-				.map_err(|e| {
-					log::error!("Error posting comment: {}", e);
-				});
-			rebase(
-				github_bot,
-				owner,
+		} => {
+			if is_auto_update_label(&state.bot_config, &label) {
+				auto_update::remove(
+					&state.db,
+					&repo_owner,
+					&repo_name,
+					pull_request.number,
+				)?;
+			}
+			// so that it is not added back on the next push
+			path_labels::record(
+				&state.db,
+				&repo_owner,
 				&repo_name,
-				&head_owner,
-				&head_repo,
-				&head_branch,
+				pull_request.number,
+				&[label],
+			)?;
+			refresh_merge_gate(
+				state,
+				&repo_owner,
+				&repo_name,
+				pull_request.number,
 			)
-			.await?;
-		} else {
-			Err(Error::Message {
-				msg: format!(
-					"PR response is missing required fields; rebase aborted."
-				),
+			.await;
+			Ok(())
+		}
+		Payload::PullRequest {
+			action:
+				action @ (PullRequestAction::Opened
+				| PullRequestAction::Reopened
+				| PullRequestAction::Synchronized),
+			pull_request,
+			repository,
+			..
+		} => {
+			if action == PullRequestAction::Synchronized {
+				// a merge requested before the push would merge unseen changes
+				merge_confirmation::remove(
+					&state.db,
+					&repository.owner.login,
+					&repository.name,
+					pull_request.number,
+				)?;
+				cancel_scheduled_merges(
+					state,
+					&repository.owner.login,
+					&repository.name,
+					pull_request.number,
+				)
+				.await?;
 			}
-			.map_issue(Some((
-				owner.to_string(),
-				repo_name.to_string(),
-				number,
-			))))?;
+			if ignore::skips(
+				&state.db,
+				&repository.owner.login,
+				&repository.name,
+				pull_request.number,
+				"labels, checks and welcome",
+			)? {
+				return Ok(());
+			}
+			if action == PullRequestAction::Opened {
+				if let Err(e) =
+					welcome_first_contributor(&pull_request, &repository, state)
+						.await
+				{
+					log::error!(
+						"Error welcoming the author of {}: {}",
+						pull_request.html_url,
+						e
+					);
+				}
+			}
+			handle_pull_request_updated(pull_request, repository, state).await
 		}
-	} else if body
-		.to_lowercase()
-		.trim()
-		.starts_with(BURNIN_REQUEST.to_lowercase().trim())
-	{
-		auth.check_org_membership(github_bot).await?;
-
-		handle_burnin_request(
-			&body,
-			owner,
-			&requested_by,
-			&repo_name,
-			&pr,
-			state,
-		)
-		.await;
+		Payload::PullRequest {
+			action: PullRequestAction::Closed,
+			pull_request,
+			repository,
+			..
+		} => handle_pull_request_closed(pull_request, repository, state).await,
+		Payload::Push {
+			ref_field,
+			commits,
+			repository,
+		} => {
+			handle_push(&ref_field, commits, &repository, state);
+			update_labelled_branches(&ref_field, &repository, state).await
+		}
+		Payload::ProjectCard {
+			action: ProjectCardAction::Created | ProjectCardAction::Moved,
+			project_card,
+		} => handle_project_card(project_card, state).await,
+		Payload::Installation {
+			action,
+			installation,
+		} => handle_installation(action, installation, state),
+		_event => Ok(()),
 	}
+}
 
-	Ok(())
+/// Keep track of the accounts the app is installed on, so that requests
+/// about each are sent with its installation's token.
+fn handle_installation(
+	action: InstallationAction,
+	installation: AppInstallation,
+	state: &AppState,
+) -> Result<()> {
+	let account = &installation.account.login;
+	let client = &state.github_bot.client;
+	match action {
+		InstallationAction::Deleted | InstallationAction::Suspend => {
+			log::info!("The app was uninstalled from {}", account);
+			client.remove_installation(account);
+			installations::remove(&state.db, account)
+		}
+		_ => {
+			if client.installation_id(account) != Some(installation.id) {
+				log::info!(
+					"The app is installed on {} as installation {}",
+					account,
+					installation.id
+				);
+			}
+			client.set_installation(account, installation.id);
+			installations::record(&state.db, account, installation.id)
+		}
+	}
 }
 
-async fn handle_label_added(
-	label: String,
-	added_by: String,
-	repo_name: String,
-	repo_owner: String,
+/// Label a PR by the paths it changes, and validate the proposed process files
+/// of a PR that changes them, commenting with the result.
+async fn handle_pull_request_updated(
 	pr: PullRequest,
-	github_bot: &GithubBot,
+	repository: Repository,
+	state: &AppState,
 ) -> Result<()> {
-	if label != "A1-needsburnin" {
+	let github_bot = &state.github_bot;
+	let owner = &repository.owner.login;
+	let repo_name = &repository.name;
+	let files = github_bot
+		.pull_request_files(owner, repo_name, pr.number)
+		.await?;
+	if let Err(e) = label_by_paths(&pr, owner, repo_name, &files, state).await {
+		log::error!("Error labelling {} by its paths: {}", pr.html_url, e);
+	}
+	if let Err(e) = label_by_size(&pr, owner, repo_name, &files, state).await {
+		log::error!("Error labelling {} by its size: {}", pr.html_url, e);
+	}
+	if let Err(e) = merge_if_trusted(&pr, owner, repo_name, &files, state).await
+	{
+		log::error!("Error merging {} automatically: {}", pr.html_url, e);
+	}
+	let formats = files
+		.into_iter()
+		.filter(|f| f.status != "removed")
+		.filter_map(|f| process::ProcessFormat::from_path(&f.filename))
+		.collect::<Vec<_>>();
+	if formats.is_empty() {
 		return Ok(());
 	}
+	log::info!("{} changes process files; validating", pr.html_url);
 
-	let msg = format!(
-		r#"@{added_by} to request a burn-in test for this PR, please submit a comment in the format below.
-
-Only members of the Github organization `{repo_owner}` are authorized to perform burn-in tests.
-
-For now, only full nodes on Kusama are available via automation. If you need something else, please contact Staking Ops on Matrix.
-
-Requests are expressed as ToML files. Here's an example for deploying a binary built from the most recent commit on this PR to a single node (The comment must start with "bot burnin" and use a \``` block for the ToML):
-
-bot burnin
-```toml
-pull_request = "{pr_url}"
-commit_sha = "{commit_sha}"
-requested_by = "{added_by}"
-sync_from_scratch = false
-
-[node_types]
-fullnode = 1
-sentry = 0
-validator = 0
-```"#,
-		added_by = added_by,
-		repo_owner = repo_owner,
-		pr_url = pr.html_url,
-		commit_sha = pr.head.sha,
-	);
-
+	let projects =
+		process::project_columns(github_bot, owner, repo_name).await?;
+	let mut results = Vec::new();
+	for format in formats {
+		let contents = github_bot
+			.contents(owner, repo_name, format.file_name(), &pr.head.sha)
+			.await
+			.and_then(|c| process::decode_contents(&c))?;
+		let problems = process::validate(&contents, format, &projects);
+		results.push(if problems.is_empty() {
+			format!("✅ `{}` at {} is valid.", format.file_name(), pr.head.sha)
+		} else {
+			format!(
+				"`{}` at {} has problems:\n{}",
+				format.file_name(),
+				pr.head.sha,
+				problems
+					.iter()
+					.map(|problem| format!("- {}", problem))
+					.join("\n")
+			)
+		});
+	}
 	github_bot
-		.create_issue_comment(&repo_owner, &repo_name, pr.number, &msg)
+		.create_issue_comment(
+			owner,
+			repo_name,
+			pr.number,
+			&results.join("\n\n"),
+		)
 		.await
 }
 
-async fn handle_burnin_request(
-	comment_body: &str,
+/// Wait for the checks of a PR by a trusted author, eg. a dependency bump, and
+/// merge it as if its head had been commented with `bot merge`, unless it was
+/// turned off with `bot automerge off`.  The PR is queued again whenever it
+/// is pushed to, if the merge policy allows it but for the approval.
+async fn merge_if_trusted(
+	pr: &PullRequest,
 	owner: &str,
-	requested_by: &str,
 	repo_name: &str,
-	pr: &PullRequest,
+	files: &[PullRequestFile],
 	state: &AppState,
-) {
-	let v: Vec<&str> = comment_body.split("```").collect();
-	if v.len() < 2 {
-		let msg = format!(
-			"@{} invalid burn-in command: no \\``` block found",
-			requested_by
-		);
-		if let Err(e) = state
-			.github_bot
-			.create_issue_comment(owner, &repo_name, pr.number, &msg)
+) -> Result<()> {
+	let bot_config = &repo_bot_config(state, owner, repo_name, None).await;
+	if pr.draft == Some(true)
+		|| !automerge::is_eligible(
+			&bot_config.automerge_authors,
+			&bot_config.automerge_files,
+			&pr.user.login,
+			files,
+		) || automerge::is_off(&state.db, owner, repo_name, pr.number)?
+	{
+		return Ok(());
+	}
+	let target = queue::Target::PullRequest {
+		owner: owner.to_string(),
+		repo_name: repo_name.to_string(),
+		number: pr.number,
+	};
+	// the entry of a previous head, which will never see its checks complete
+	let previous = db::atomically(&state.db, |tx| queue::remove(tx, &target))?;
+	if let Err(e) = merge_allowed(
+		state,
+		bot_config,
+		owner,
+		repo_name,
+		pr,
+		&bot_config.automerge_identity,
+		MergeIntent::Automerge,
+	)
+	.await
+	{
+		log::info!("Not merging {} automatically: {}", pr.html_url, e);
+		return Ok(());
+	}
+	log::info!(
+		"{} is by {} and changes only {}; merging once its checks pass",
+		pr.html_url,
+		pr.user.login,
+		bot_config.automerge_files.join(", ")
+	);
+	let request =
+		merge_request(owner, repo_name, pr, &bot_config.automerge_identity);
+	if previous.is_empty() {
+		wait_to_merge(&state.github_bot, &request, &pr.head.sha, &state.db)
 			.await
-		{
-			log::error!("Error posting comment: {:?}", e);
-		}
-		return;
+	} else {
+		create_merge_request(&request, &pr.head.sha, &state.db).await
 	}
+}
 
-	let mut msg = format!("@{} your request has been forwarded", requested_by);
+/// Add the labels of the repository's path rules which the PR's files match,
+/// unless it has the opt-out label.  A label is added once, so that one
+/// removed by hand stays removed.
+async fn label_by_paths(
+	pr: &PullRequest,
+	owner: &str,
+	repo_name: &str,
+	files: &[PullRequestFile],
+	state: &AppState,
+) -> Result<()> {
+	let bot_config = repo_bot_config(state, owner, repo_name, None).await;
+	if bot_config.path_labels.is_empty()
+		|| pr
+			.labels
+			.iter()
+			.any(|label| label.name == bot_config.path_labels_opt_out)
+	{
+		return Ok(());
+	}
+	let wanted = path_labels::matching_labels(&bot_config.path_labels, files);
+	let handled = path_labels::handled(&state.db, owner, repo_name, pr.number)?;
+	let labels = path_labels::labels_to_add(&wanted, &pr.labels, &handled);
+	if labels.is_empty()
+		|| !state.effects.allow(Effect::AddLabels {
+			owner: owner.to_string(),
+			repo_name: repo_name.to_string(),
+			number: pr.number,
+			labels: labels.clone(),
+		}) {
+		return Ok(());
+	}
+	state
+		.github_bot
+		.add_labels(owner, repo_name, pr.number, &labels)
+		.await?;
+	path_labels::record(&state.db, owner, repo_name, pr.number, &labels)
+}
 
-	let mut matrix_msg = format!(
-		r#"Submitted burn-in request for <a href="{}">{}#{}</a> (requested by @{})"#,
-		pr.html_url, repo_name, pr.number, requested_by,
+/// Give a PR the size label of its bucket in place of any other.  The lines
+/// changed in ignored files are only left out if that could make it smaller.
+async fn label_by_size(
+	pr: &PullRequest,
+	owner: &str,
+	repo_name: &str,
+	files: &[PullRequestFile],
+	state: &AppState,
+) -> Result<()> {
+	let bot_config = &state.bot_config;
+	let buckets = &bot_config.size_labels;
+	let lines = match (pr.additions, pr.deletions) {
+		(Some(additions), Some(deletions)) => additions + deletions,
+		_ => return Ok(()),
+	};
+	if buckets.is_empty() {
+		return Ok(());
+	}
+	let lines = if !bot_config.size_ignored_files.is_empty()
+		&& size_labels::may_shrink(buckets, lines)
+	{
+		size_labels::counted_lines(files, &bot_config.size_ignored_files)
+	} else {
+		lines
+	};
+	let swap = size_labels::swap(
+		buckets,
+		&pr.labels,
+		size_labels::bucket(buckets, lines),
 	);
+	for label in swap.remove {
+		if state.effects.allow(Effect::RemoveLabel {
+			owner: owner.to_string(),
+			repo_name: repo_name.to_string(),
+			number: pr.number,
+			label: label.clone(),
+		}) {
+			state
+				.github_bot
+				.remove_label(owner, repo_name, pr.number, &label)
+				.await?;
+		}
+	}
+	if let Some(label) = swap.add {
+		let labels = vec![label];
+		if state.effects.allow(Effect::AddLabels {
+			owner: owner.to_string(),
+			repo_name: repo_name.to_string(),
+			number: pr.number,
+			labels: labels.clone(),
+		}) {
+			state
+				.github_bot
+				.add_labels(owner, repo_name, pr.number, &labels)
+				.await?;
+		}
+	}
+	Ok(())
+}
 
-	let path = format!("requests/request-{}.toml", Utc::now().timestamp());
-	let commit_msg = format!("Add request for {}#{}", repo_name, pr.number);
-	let toml = v[1].strip_prefix('\n').unwrap_or(v[1]);
+/// Welcome the author of a PR who has not contributed to the repository before,
+/// once per author, with the repository's template.
+async fn welcome_first_contributor(
+	pr: &PullRequest,
+	repository: &Repository,
+	state: &AppState,
+) -> Result<()> {
+	let owner = &repository.owner.login;
+	let repo_name = &repository.name;
+	let author = &pr.user.login;
+	if !welcome::is_first_contribution(pr)
+		|| welcome::welcomed(&state.db, owner, repo_name, author)?
+	{
+		return Ok(());
+	}
+	let bot_config = repo_bot_config(state, owner, repo_name, None).await;
+	let path = match &bot_config.welcome_template {
+		Some(path) => path,
+		None => return Ok(()),
+	};
+	let default_branch =
+		repository.default_branch.as_deref().unwrap_or("master");
+	let template = state
+		.github_bot
+		.contents(owner, repo_name, path, default_branch)
+		.await
+		.and_then(|c| process::decode_contents(&c))?;
+	log::info!("Welcoming {} on {}", author, pr.html_url);
+	state
+		.github_bot
+		.create_issue_comment(
+			owner,
+			repo_name,
+			pr.number,
+			&welcome::render(&template, author, owner, repo_name),
+		)
+		.await?;
+	welcome::record(&state.db, owner, repo_name, author, Utc::now().timestamp())
+}
 
-	if let Err(e) = state
-		.gitlab_bot
-		.create_file(&path, "master", &commit_msg, toml)
+/// Ask the process owners of a PR added to a project column for their review.
+/// Cards of issues and notes are ignored.
+async fn handle_project_card(
+	card: ProjectCard,
+	state: &AppState,
+) -> Result<()> {
+	let (owner, repo_name, number) = match card.issue() {
+		Some(issue) => issue,
+		None => return Ok(()),
+	};
+	let pr = match state
+		.github_bot
+		.pull_request(&owner, &repo_name, number)
 		.await
 	{
-		msg = format!(
-			"@{} your request could not be processed. Please contact Staking Ops on Matrix.",
-			requested_by,
-		);
+		Ok(pr) => pr,
+		Err(e) if e.is_not_found() => return Ok(()),
+		Err(e) => return Err(e),
+	};
+	request_process_reviews(&pr, &owner, &repo_name, state).await
+}
 
-		matrix_msg = format!(
-			r#"Submitting burn-in request for <a href="{}">{}#{}</a> (requested by @{}) failed: {:?}"#,
-			pr.html_url, repo_name, pr.number, requested_by, e,
+/// Ask the owners and delegated reviewers of the process entry of an open PR
+/// for their review, once per PR, unless it has the opt-out label.
+async fn request_process_reviews(
+	pr: &PullRequest,
+	owner: &str,
+	repo_name: &str,
+	state: &AppState,
+) -> Result<()> {
+	let bot_config = &state.bot_config;
+	if pr.state.as_deref() != Some("open")
+		|| pr
+			.labels
+			.iter()
+			.any(|label| label.name == bot_config.review_request_opt_out)
+		|| ignore::skips(
+			&state.db,
+			owner,
+			repo_name,
+			pr.number,
+			"review requests",
+		)? || process::review_requested(&state.db, owner, repo_name, pr.number)?
+	{
+		return Ok(());
+	}
+	let process = process::get_process(
+		&state.github_bot,
+		&state.process_cache,
+		owner,
+		repo_name,
+		pr.number,
+		&pr.labels,
+		bot_config.process_matching(repo_name),
+	)
+	.await?;
+	if process.is_empty() {
+		// it may match once labelled or added to a column
+		return Ok(());
+	}
+	let users = process.reviewers(&pr.user.login);
+	if !users.is_empty() {
+		if !state.effects.allow(Effect::RequestReviewers {
+			owner: owner.to_string(),
+			repo_name: repo_name.to_string(),
+			number: pr.number,
+			users: users.clone(),
+		}) {
+			return Ok(());
+		}
+		log::info!(
+			"Requesting reviews on {} from {}",
+			pr.html_url,
+			users.join(", ")
 		);
+		state
+			.github_bot
+			.request_reviewers(owner, repo_name, pr.number, &users, &[])
+			.await?;
 	}
+	process::record_review_request(
+		&state.db,
+		owner,
+		repo_name,
+		pr.number,
+		Utc::now().timestamp(),
+	)
+}
 
-	if let Err(e) = state
+/// Forget the cached process file or `.processbot.toml` of a repository when a
+/// merged PR changes it on the default branch, and stop updating, labelling or
+/// requesting reviews of the PR, or keeping it from being merged
+/// automatically.
+async fn handle_pull_request_closed(
+	pr: PullRequest,
+	repository: Repository,
+	state: &AppState,
+) -> Result<()> {
+	auto_update::remove(
+		&state.db,
+		&repository.owner.login,
+		&repository.name,
+		pr.number,
+	)?;
+	path_labels::remove(
+		&state.db,
+		&repository.owner.login,
+		&repository.name,
+		pr.number,
+	)?;
+	process::remove_review_request(
+		&state.db,
+		&repository.owner.login,
+		&repository.name,
+		pr.number,
+	)?;
+	automerge::remove(
+		&state.db,
+		&repository.owner.login,
+		&repository.name,
+		pr.number,
+	)?;
+	merge_confirmation::remove(
+		&state.db,
+		&repository.owner.login,
+		&repository.name,
+		pr.number,
+	)?;
+	if pr.merged == Some(true) {
+		// a closed PR may be reopened, and stay ignored
+		ignore::unignore(
+			&state.db,
+			&repository.owner.login,
+			&repository.name,
+			pr.number,
+		)?;
+	}
+	let default_branch =
+		repository.default_branch.as_deref().unwrap_or("master");
+	if pr.merged != Some(true) || pr.base.ref_field != default_branch {
+		return Ok(());
+	}
+	let files = state
 		.github_bot
-		.create_issue_comment(owner, &repo_name, pr.number, &msg)
-		.await
+		.pull_request_files(
+			&repository.owner.login,
+			&repository.name,
+			pr.number,
+		)
+		.await?;
+	if process::touches_process_file(files.iter().map(|f| f.filename.as_str()))
+		&& state
+			.process_cache
+			.invalidate(&repository.owner.login, &repository.name)
 	{
-		log::error!("Error posting comment: {:?}", e);
+		log::info!(
+			"{} changed the process file; cleared cached entries",
+			pr.html_url
+		);
 	}
-
-	if let Err(e) = state
-		.matrix_bot
-		.send_html_to_room(&state.bot_config.burnin_room_id, &matrix_msg)
+	if files
+		.iter()
+		.any(|f| f.filename == repo_config::REPO_CONFIG_FILE)
+		&& state
+			.repo_config_cache
+			.invalidate(&repository.owner.login, &repository.name)
 	{
-		log::error!("Error sending Matrix message: {:?}", e);
+		log::info!(
+			"{} changed {}; cleared cached configuration",
+			pr.html_url,
+			repo_config::REPO_CONFIG_FILE
+		);
 	}
+	Ok(())
 }
 
-/// Check if the pull request is mergeable and approved.
-async fn merge_allowed(
-	github_bot: &GithubBot,
-	owner: &str,
-	repo_name: &str,
-	pr: &PullRequest,
-	bot_config: &BotConfig,
-	requested_by: &str,
-) -> Result<()> {
-	let mergeable = pr.mergeable.unwrap_or(false);
-	if !mergeable {
-		log::info!("{} is unmergeable", pr.html_url);
-		Err(Error::Message {
-			msg: format!("The PR is currently unmergeable."),
-		}
-		.map_issue(Some((
-			owner.to_string(),
-			repo_name.to_string(),
-			pr.number,
-		))))?;
-	} else {
-		log::info!("{} is mergeable.", pr.html_url);
+/// Forget the cached process file or `.processbot.toml` of a repository when a
+/// push to the default branch changes it.
+fn handle_push(
+	ref_field: &str,
+	commits: Vec<PushCommit>,
+	repository: &PushRepository,
+	state: &AppState,
+) {
+	let default_branch =
+		repository.default_branch.as_deref().unwrap_or("master");
+	if ref_field != format!("refs/heads/{}", default_branch) {
+		return;
+	}
+	if process::touches_process_file(
+		commits.iter().flat_map(|c| c.changed_files()),
+	) && state
+		.process_cache
+		.invalidate(&repository.owner.login, &repository.name)
+	{
+		log::info!(
+			"Push to {}/{} changed the process file; cleared cached entries",
+			repository.owner.login,
+			repository.name
+		);
+	}
+	if commits
+		.iter()
+		.flat_map(|c| c.changed_files())
+		.any(|f| f == repo_config::REPO_CONFIG_FILE)
+		&& state
+			.repo_config_cache
+			.invalidate(&repository.owner.login, &repository.name)
+	{
+		log::info!(
+			"Push to {}/{} changed {}; cleared cached configuration",
+			repository.owner.login,
+			repository.name,
+			repo_config::REPO_CONFIG_FILE
+		);
+	}
+}
 
-		let team_leads = github_bot
-			.team(owner, "substrateteamleads")
-			.and_then(|team| github_bot.team_members(team.id))
-			.await
-			.unwrap_or_else(|e| {
-				log::error!("Error getting core devs: {}", e);
-				vec![]
-			});
+fn is_auto_update_label(bot_config: &BotConfig, label: &str) -> bool {
+	!bot_config.auto_update_label.is_empty()
+		&& label.eq_ignore_ascii_case(&bot_config.auto_update_label)
+}
 
-		if team_leads.iter().any(|lead| lead.login == requested_by) {
-			//
-			// MERGE ALLOWED
-			//
-			log::info!("{} merge requested by a team lead.", pr.html_url);
-		} else {
-			fn label_insubstantial(label: &&Label) -> bool {
-				label.name.contains("insubstantial")
-			}
-			let min_reviewers =
-				if pr.labels.iter().find(label_insubstantial).is_some() {
-					1
-				} else {
-					bot_config.min_reviewers
-				};
-			let core_devs = github_bot
-				.team(owner, "core-devs")
-				.and_then(|team| github_bot.team_members(team.id))
-				.await
-				.unwrap_or_else(|e| {
-					log::error!("Error getting core devs: {}", e);
-					vec![]
-				});
-			let reviews =
-				github_bot.reviews(&pr.url).await.unwrap_or_else(|e| {
-					log::error!("Error getting reviews: {}", e);
-					vec![]
-				});
-			let core_approved = reviews
-				.iter()
-				.filter(|r| {
-					core_devs.iter().any(|u| u.login == r.user.login)
-						&& r.state == Some(ReviewState::Approved)
-				})
-				.count() >= min_reviewers;
-			let lead_approved = reviews
-				.iter()
-				.filter(|r| {
-					team_leads.iter().any(|u| u.login == r.user.login)
-						&& r.state == Some(ReviewState::Approved)
-				})
-				.count() >= 1;
-			if core_approved || lead_approved {
-				//
-				// MERGE ALLOWED
-				//
-				log::info!("{} has core or team lead approval.", pr.html_url);
-			} else {
-				// get process info
-				let process = process::get_process(
-					github_bot, owner, repo_name, pr.number,
-				)
-				.await
-				.map_err(|e| {
-					Error::ProcessFile {
-						source: Box::new(e),
-					}
-					.map_issue(Some((
-						owner.to_string(),
-						repo_name.to_string(),
-						pr.number,
-					)))
-				})?;
-				let owner_approved = reviews
-					.iter()
-					.sorted_by_key(|r| r.submitted_at)
-					.rev()
-					.find(|r| process.is_owner(&r.user.login))
-					.map_or(false, |r| r.state == Some(ReviewState::Approved));
-
-				let owner_requested = process.is_owner(&requested_by);
-
-				if owner_approved || owner_requested {
-					//
-					// MERGE ALLOWED
-					//
-					log::info!("{} has owner approval.", pr.html_url);
-				} else {
-					if process.is_empty() {
-						Err(Error::ProcessInfo {}.map_issue(Some((
-							owner.to_string(),
-							repo_name.to_string(),
-							pr.number,
-						))))?;
-					} else {
-						Err(Error::Approval {}.map_issue(Some((
-							owner.to_string(),
-							repo_name.to_string(),
-							pr.number,
-						))))?;
-					}
-				}
-			}
+/// Update the PRs labelled to be kept up to date with the branch pushed to.
+async fn update_labelled_branches(
+	ref_field: &str,
+	repository: &PushRepository,
+	state: &AppState,
+) -> Result<()> {
+	let base = match ref_field.strip_prefix("refs/heads/") {
+		Some(base) => base,
+		None => return Ok(()),
+	};
+	let now = Utc::now().timestamp();
+	for entry in auto_update::list(
+		&state.db,
+		&repository.owner.login,
+		&repository.name,
+		base,
+	)? {
+		let (owner, repo_name, number) =
+			(entry.owner.clone(), entry.repo_name.clone(), entry.number);
+		if let Err(e) = update_labelled_branch(entry, now, state).await {
+			log::error!(
+				"Error updating {}/{}#{}: {}",
+				owner,
+				repo_name,
+				number,
+				e
+			);
 		}
 	}
-
 	Ok(())
 }
 
-/// Query checks and statuses.
-///
-/// This function is used when a merge request is first received, to decide whether to store the
-/// request and wait for checks -- if so they will later be handled by `checks_and_status`.
-async fn ready_to_merge(
-	github_bot: &GithubBot,
-	owner: &str,
-	repo_name: &str,
-	pr: &PullRequest,
-) -> Result<bool> {
-	//
-	// status
-	//
-	match github_bot
-		.status(owner, &repo_name, &pr.head.sha)
-		.await
-		.map_err(|e| {
-			e.map_issue(Some((
-				owner.to_string(),
-				repo_name.to_string(),
-				pr.number,
-			)))
-		})? {
-		CombinedStatus {
-			state: StatusState::Success,
-			..
-		} => {
-			log::info!("{} is green.", pr.html_url);
-			//
-			// checks
-			//
-			let checks = github_bot
-				.check_runs(&owner, &repo_name, &pr.head.sha)
-				.await
-				.map_err(|e| {
-					e.map_issue(Some((
-						owner.to_string(),
-						repo_name.to_string(),
-						pr.number,
-					)))
-				})?;
-			log::info!("{:?}", checks);
-			if checks
-				.check_runs
-				.iter()
-				.all(|r| r.conclusion == Some("success".to_string()))
-			{
-				//
-				// status/checks green
-				//
-				return Ok(true);
-			} else if checks
-				.check_runs
-				.iter()
-				.all(|r| r.status == "completed".to_string())
-			{
-				//
-				// status/checks failure
-				//
-				log::info!("{} checks were unsuccessful.", pr.html_url);
-				return Err(Error::ChecksFailed {
-					commit_sha: pr.head.sha.clone(),
-				}
-				.map_issue(Some((
-					owner.to_string(),
-					repo_name.to_string(),
-					pr.number,
-				))));
-			} else {
-				//
-				// status/checks pending
-				//
-				return Ok(false);
-			}
-		}
-		CombinedStatus {
-			state: StatusState::Pending,
-			..
-		} => {
-			//
-			// status/checks pending
-			//
-			return Ok(false);
-		}
-		CombinedStatus {
-			state: StatusState::Failure,
-			..
-		} => {
-			//
-			// status/checks failure
-			//
-			log::info!("{} status failure.", pr.html_url);
-			return Err(Error::ChecksFailed {
-				commit_sha: pr.head.sha.to_string(),
-			}
-			.map_issue(Some((
-				owner.to_string(),
-				repo_name.to_string(),
-				pr.number,
-			))));
+async fn update_labelled_branch(
+	entry: AutoUpdate,
+	now: i64,
+	state: &AppState,
+) -> Result<()> {
+	let (owner, repo_name, number) =
+		(&entry.owner, &entry.repo_name, entry.number);
+	if ignore::skips(&state.db, owner, repo_name, number, "branch update")? {
+		return Ok(());
+	}
+	if !entry.due(now, state.bot_config.auto_update_interval) {
+		log::info!(
+			"{}/{}#{} was updated recently; not updating it again yet",
+			owner,
+			repo_name,
+			number
+		);
+		return Ok(());
+	}
+	let pr = state
+		.github_bot
+		.pull_request(owner, repo_name, number)
+		.await?;
+	if pr.state.as_deref() != Some("open") {
+		// the event closing it was missed
+		return auto_update::remove(&state.db, owner, repo_name, number);
+	}
+	if !pr.head_pushable() {
+		log::info!("Maintainers may not edit {}; not updating it", pr.html_url);
+		return Ok(());
+	}
+	if !state.effects.allow(Effect::UpdateBranch {
+		owner: owner.clone(),
+		repo_name: repo_name.clone(),
+		number,
+	}) {
+		return Ok(());
+	}
+	match state
+		.github_bot
+		.update_pull_request_branch(owner, repo_name, number, &pr.head.sha)
+		.await
+	{
+		Ok(()) => log::info!("Updated {} with its base", pr.html_url),
+		// GitHub refuses to update a branch which is not behind its base
+		Err(e)
+			if matches!(
+				e.github_api_error(),
+				Some(crate::http::GithubApiError::Validation(_))
+			) =>
+		{
+			log::info!("{} is not behind its base: {}", pr.html_url, e);
+			return Ok(());
 		}
-		CombinedStatus {
-			state: StatusState::Error,
-			..
-		} => {
-			//
-			// status/checks failure
-			//
-			log::info!("{} status error.", pr.html_url);
-			return Err(Error::ChecksFailed {
-				commit_sha: pr.head.sha.to_string(),
-			}
-			.map_issue(Some((
-				owner.to_string(),
-				repo_name.to_string(),
-				pr.number,
-			))));
+		Err(e) => return Err(e),
+	}
+	auto_update::record(
+		&state.db,
+		&AutoUpdate {
+			updated_at: Some(now),
+			..entry
+		},
+	)
+}
+
+/// If a check completes, query if all statuses and checks are complete.
+async fn handle_check(
+	status: String,
+	commit_sha: String,
+	state: &AppState,
+) -> Result<()> {
+	if status == "completed".to_string() {
+		debounced_checks_and_status(state, &commit_sha).await?;
+	}
+
+	Ok(())
+}
+
+/// If we receive a status other than `Pending`, query if all statuses and checks are complete.
+async fn handle_status(
+	commit_sha: String,
+	status: StatusState,
+	state: &AppState,
+) -> Result<()> {
+	if status != StatusState::Pending {
+		debounced_checks_and_status(state, &commit_sha).await?;
+	}
+	Ok(())
+}
+
+/// `checks_and_status`, unless the checks of the commit were evaluated within
+/// the debounce window, in which case they are evaluated again once it is
+/// over.
+async fn debounced_checks_and_status(
+	state: &AppState,
+	commit_sha: &str,
+) -> Result<()> {
+	if state
+		.checks_debounce
+		.admit(commit_sha.trim(), Utc::now().timestamp())
+	{
+		checks_and_status(state, commit_sha).await
+	} else {
+		log::info!(
+			"Checks of {} were just evaluated; evaluating them again shortly",
+			commit_sha
+		);
+		Ok(())
+	}
+}
+
+/// The pending merge of `sha`.  One which cannot be read, eg. as it was
+/// written by another version of the bot, is quarantined and treated as
+/// missing, so that it does not fail every status of the commit.
+fn pending_merge(state: &AppState, sha: &str) -> Result<Option<MergeRequest>> {
+	let bytes = match queue::get(&state.db, sha)? {
+		Some(bytes) => bytes,
+		None => return Ok(None),
+	};
+	match migrations::decode(&bytes) {
+		Ok(request) => Ok(Some(request)),
+		Err(e) => {
+			log::warn!("Quarantining the pending merge of {}: {}", sha, e);
+			db::atomically(&state.db, |tx| {
+				quarantine::quarantine(
+					tx,
+					db::MERGE_REQUESTS,
+					sha.as_bytes(),
+					&bytes,
+					Utc::now(),
+				)
+			})?;
+			state
+				.quarantined_merge_requests
+				.fetch_add(1, Ordering::Relaxed);
+			Ok(None)
 		}
 	}
 }
 
-/// Create a merge request object.
-///
-/// If this has been called, error handling must remove the db entry.
-async fn create_merge_request(
+/// Record how the merge of `request` ended, unless this is a dry run.
+fn record_outcome(
+	tx: &mut db::Transaction,
+	state: &AppState,
+	request: &MergeRequest,
+	result: MergeResult,
+) -> Result<()> {
+	if state.effects.is_dry_run() {
+		return Ok(());
+	}
+	stats::record(
+		tx,
+		&MergeOutcome::new(request, Utc::now().timestamp(), result),
+	)
+}
+
+/// Record the failure of a merge which was attempted at once, without
+/// waiting in the queue.
+fn record_failed_merge(state: &AppState, request: &MergeRequest) {
+	if let Err(e) = db::atomically(&state.db, |tx| {
+		record_outcome(tx, state, request, MergeResult::Failed)
+	}) {
+		log::error!("Error recording merge outcome: {}", e);
+	}
+}
+
+/// Mark the PR of a request as being merged, so that another merge command is
+/// turned away meanwhile.
+fn mark_merging(state: &AppState, request: &MergeRequest) -> Result<()> {
+	in_flight::mark(&state.db, request, Utc::now().timestamp()).map_err(|e| {
+		e.map_issue(Some((
+			request.owner.clone(),
+			request.repo_name.clone(),
+			request.number,
+		)))
+	})
+}
+
+/// Clear the mark of a merge once done, however it went.
+fn unmark_merging(state: &AppState, request: &MergeRequest) {
+	if let Err(e) = in_flight::clear(&state.db, request) {
+		log::error!(
+			"Error clearing the merge mark of {}: {}",
+			request.html_url,
+			e
+		);
+	}
+}
+
+/// Reply to a merge command that a merge of the PR is already under way, if
+/// there is one.  Returns whether there was.
+async fn already_in_flight(
+	state: &AppState,
 	owner: &str,
 	repo_name: &str,
 	number: i64,
-	html_url: &str,
-	requested_by: &str,
-	commit_sha: &str,
-	db: &DB,
-) -> Result<()> {
-	let m = MergeRequest {
-		owner: owner.to_string(),
-		repo_name: repo_name.to_string(),
-		number: number,
-		html_url: html_url.to_string(),
-		requested_by: requested_by.to_string(),
+	in_flight: Option<InFlight>,
+) -> Result<bool> {
+	let in_flight = match in_flight {
+		Some(in_flight) => in_flight,
+		None => return Ok(false),
 	};
-	log::info!("Serializing merge request: {:?}", m);
-	let bytes = bincode::serialize(&m).context(Bincode).map_err(|e| {
-		e.map_issue(Some((owner.to_string(), repo_name.to_string(), number)))
-	})?;
-	log::info!("Writing merge request to db (head sha: {})", commit_sha);
-	db.put(commit_sha.trim().as_bytes(), bytes)
-		.context(Db)
+	log::info!(
+		"{}/{}#{} is already being merged: {:?}",
+		owner,
+		repo_name,
+		number,
+		in_flight
+	);
+	state
+		.github_bot
+		.create_issue_comment(owner, repo_name, number, &in_flight.comment())
+		.await
 		.map_err(|e| {
 			e.map_issue(Some((
 				owner.to_string(),
@@ -1080,407 +1932,5876 @@ async fn create_merge_request(
 				number,
 			)))
 		})?;
-	Ok(())
+	Ok(true)
 }
 
-/// Create a merge request, add it to the database, and post a comment stating the merge is
-/// pending.
-async fn wait_to_merge(
-	github_bot: &GithubBot,
-	owner: &str,
-	repo_name: &str,
-	number: i64,
-	html_url: &str,
-	requested_by: &str,
+/// Evaluate the merge policy again for a queued merge, which it may no longer
+/// allow since it was queued.  If so, the merge request is dropped and the
+/// reason returned, to be posted on the PR.
+async fn recheck_merge_allowed(
+	state: &AppState,
+	bot_config: &BotConfig,
+	request: &MergeRequest,
 	commit_sha: &str,
-	db: &DB,
+	pr: &PullRequest,
+	intent: MergeIntent,
 ) -> Result<()> {
-	log::info!("{} checks incomplete.", html_url);
-	create_merge_request(
+	let MergeRequest {
 		owner,
 		repo_name,
-		number,
-		html_url,
 		requested_by,
-		commit_sha,
-		db,
+		..
+	} = request;
+	let e = match merge_allowed(
+		state,
+		bot_config,
+		owner,
+		repo_name,
+		pr,
+		requested_by,
+		intent,
 	)
-	.await?;
-	log::info!("Waiting for commit status.");
-	let _ = github_bot
-		.create_issue_comment(
-			owner,
-			&repo_name,
-			number,
-			"Waiting for commit status.",
-		)
-		.await
-		.map_err(|e| {
-			log::error!("Error posting comment: {}", e);
-		});
-	Ok(())
+	.await
+	{
+		Ok(()) => return Ok(()),
+		Err(e) => e,
+	};
+	log::info!("{} may no longer be merged: {}", request.html_url, e);
+	db::atomically(&state.db, |tx| {
+		queue::delete(tx, commit_sha)?;
+		performance::clear_skip(tx, commit_sha)
+	})?;
+	update_queue_positions(&state.github_bot, &state.db, owner, repo_name)
+		.await;
+	Err(e)
 }
 
-/// Post a comment stating the merge will be attempted.
-async fn prepare_to_merge(
-	github_bot: &GithubBot,
-	owner: &str,
-	repo_name: &str,
-	number: i64,
-	html_url: &str,
-) -> Result<()> {
-	log::info!("{} checks successful; trying merge.", html_url);
-	let _ = github_bot
-		.create_issue_comment(owner, &repo_name, number, "Trying merge.")
-		.await
-		.map_err(|e| {
-			log::error!("Error posting comment: {}", e);
-		});
-	Ok(())
-}
+/// Check that no commit has been pushed since the merge request was received.  Query checks and
+/// statuses and if they are green, attempt merge.
+async fn checks_and_status(state: &AppState, commit_sha: &str) -> Result<()> {
+	let db = &state.db;
+	let github_bot = &state.github_bot;
 
-/// Send a merge request.
-async fn merge(
-	github_bot: &GithubBot,
-	owner: &str,
-	repo_name: &str,
-	pr: &PullRequest,
-) -> Result<()> {
-	github_bot
-		.merge_pull_request(owner, repo_name, pr.number, &pr.head.sha)
-		.await
-		.map_err(|e| {
-			Error::Merge {
-				source: Box::new(e),
-				commit_sha: pr.head.sha.clone(),
+	if let Some(m) = pending_merge(state, commit_sha.trim())? {
+		log::info!("Deserialized merge request: {:?}", m);
+		if scheduled_merge::is_waiting(&m, Utc::now().timestamp()) {
+			log::info!("{} is scheduled to merge later", m.html_url);
+			return Ok(());
+		}
+		let MergeRequest {
+			owner,
+			repo_name,
+			number,
+			html_url,
+			requested_by,
+			..
+		} = m.clone();
+		let pr = github_bot.pull_request(&owner, &repo_name, number).await?;
+		let bot_config = repo_bot_config(state, &owner, &repo_name, None).await;
+
+		if native_queue::steps_aside(bot_config.native_merge_queue, &pr) {
+			log::info!(
+				"{} is in GitHub's merge queue; dropping its pending merge",
+				html_url
+			);
+			db::atomically(db, |tx| {
+				queue::delete(tx, commit_sha.trim())?;
+				performance::clear_skip(tx, commit_sha.trim())
+			})?;
+			update_queue_positions(github_bot, db, &owner, &repo_name).await;
+			let _ = github_bot
+				.create_issue_comment(
+					&owner,
+					&repo_name,
+					number,
+					&format!(
+						"GitHub's auto-merge was enabled on the PR, so the bot stepped aside and dropped the merge requested by @{}.",
+						requested_by
+					),
+				)
+				.await
+				.map_err(|e| {
+					log::error!("Error posting comment: {}", e);
+				});
+			return Ok(());
+		}
+
+		// Head sha should not have changed since request was made.
+		if commit_sha == pr.head.sha {
+			log::info!(
+				"Commit sha {} matches head of {}",
+				commit_sha,
+				html_url
+			);
+
+			// Delay after status hook to avoid false success
+			tokio::time::delay_for(std::time::Duration::from_millis(1000))
+				.await;
+
+			state
+				.capabilities
+				.require(Capability::ReadCheckRuns)
+				.map_err(|e| {
+					e.map_issue(Some((
+						owner.clone(),
+						repo_name.clone(),
+						number,
+					)))
+				})?;
+			let checks = repo_config::without_ignored_checks(
+				github_bot
+					.check_runs(&owner, &repo_name, &commit_sha)
+					.await?
+					.latest(),
+				&bot_config.ignored_checks,
+			);
+			log::info!("{:?}", checks);
+			if checks
+				.check_runs
+				.iter()
+				.all(|r| r.conclusion == Some("success".to_string()))
+			{
+				log::info!("All checks success");
+				let status = repo_config::without_ignored_statuses(
+					github_bot
+						.status(&owner, &repo_name, &commit_sha)
+						.await?
+						.latest(),
+					&bot_config.ignored_checks,
+				);
+				log::info!("{:?}", status);
+				match status {
+					CombinedStatus {
+						state: StatusState::Success,
+						..
+					} => {
+						log::info!("{} is green; attempting merge.", html_url);
+
+						// the gates may have changed while the checks ran
+						if requested_by == bot_config.automerge_identity {
+							recheck_merge_allowed(
+								state,
+								&bot_config,
+								&m,
+								commit_sha,
+								&pr,
+								MergeIntent::Automerge,
+							)
+							.await?;
+						}
+
+						let skipped = performance::is_skipped(db, &commit_sha)?;
+						performance_regression(
+							github_bot,
+							db,
+							&bot_config,
+							&owner,
+							&repo_name,
+							&pr,
+							skipped.then_some(requested_by.as_str()),
+						)
+						.await?;
+
+						// to reach here merge must be allowed
+						mark_merging(state, &m)?;
+						let result = start_merge(
+							state,
+							&m,
+							&pr,
+							true,
+							bot_config.merge_method,
+						)
+						.await;
+						if result.is_err() {
+							record_audit(
+								state,
+								&requested_by,
+								&owner,
+								&repo_name,
+								number,
+								"merge",
+								&result,
+							);
+						}
+						let result = match result {
+							Ok(entry) => finish_merge(state, entry, &pr).await,
+							Err(e) => Err(e),
+						};
+						unmark_merging(state, &m);
+						result?;
+						notify_origin(state, &m, None).await;
+					}
+					CombinedStatus {
+						state: StatusState::Failure,
+						..
+					} => {
+						log::info!("{} status failure.", html_url);
+						Err(Error::ChecksFailed {
+							commit_sha: commit_sha.to_string(),
+							failed: status.failed_checks(),
+						}
+						.map_issue(Some((
+							owner.to_string(),
+							repo_name.to_string(),
+							pr.number,
+						))))?;
+					}
+					CombinedStatus {
+						state: StatusState::Error,
+						..
+					} => {
+						log::info!("{} status error.", html_url);
+						Err(Error::ChecksFailed {
+							commit_sha: commit_sha.to_string(),
+							failed: status.failed_checks(),
+						}
+						.map_issue(Some((
+							owner.to_string(),
+							repo_name.to_string(),
+							pr.number,
+						))))?;
+					}
+					CombinedStatus {
+						state: StatusState::Pending,
+						..
+					} => {
+						log::info!("{} is pending.", html_url);
+					}
+				}
+			} else if checks
+				.check_runs
+				.iter()
+				.all(|r| r.status == "completed".to_string())
+			{
+				log::info!("{} checks were unsuccessful", html_url);
+				Err(Error::ChecksFailed {
+					commit_sha: commit_sha.to_string(),
+					failed: checks.failed_checks(),
+				}
+				.map_issue(Some((
+					owner.to_string(),
+					repo_name.to_string(),
+					pr.number,
+				))))?;
+			} else {
+				log::info!("{} checks incomplete", html_url);
+			}
+		} else {
+			// Head sha has changed since merge request.
+			log::info!(
+				"Head sha has changed since merge was requested on {}",
+				html_url
+			);
+			Err(Error::HeadChanged {
+				commit_sha: commit_sha.to_string(),
 			}
 			.map_issue(Some((
 				owner.to_string(),
 				repo_name.to_string(),
 				pr.number,
-			)))
-		})?;
-	log::info!("{} merged successfully.", pr.html_url);
+			))))?;
+		}
+	}
+
 	Ok(())
 }
 
-#[allow(dead_code)]
-async fn performance_regression(
-	github_bot: &GithubBot,
-	owner: &str,
-	repo_name: &str,
-	pr: &PullRequest,
+/// The repository of an issue, from its API `repository_url`, or failing that
+/// its `html_url`.
+fn issue_repo(repo_url: &str, html_url: &str) -> Option<RepoRef> {
+	RepoRef::from_url(repo_url).or_else(|| RepoRef::from_url(html_url))
+}
+
+/// Parse bot commands in pull request comments.  Possible commands include:
+/// `bot merge`
+/// `bot merge force`
+/// `bot merge cancel`
+/// `bot merge confirm`
+/// `bot automerge off`
+/// `bot compare substrate`
+/// `bot rebase`
+/// `bot burnin`
+/// `bot stats`
+/// `bot release-notes <from> <to>`
+/// `bot ignore`
+/// `bot unignore`
+///
+/// See also README.md.
+async fn handle_comment(
+	body: String,
+	comment_id: i64,
+	requested_by: String,
+	number: i64,
+	html_url: String,
+	repo_url: String,
+	state: &AppState,
 ) -> Result<()> {
-	let _ = github_bot
-		.create_issue_comment(
+	let db = &state.db;
+	let github_bot = &state.github_bot;
+
+	let RepoRef {
+		owner,
+		repo: repo_name,
+	} = issue_repo(&repo_url, &html_url).context(Message {
+		msg: format!("Failed parsing repository in url: {}", repo_url),
+	})?;
+	let owner = owner.as_str();
+
+	if let Some(command) = bot_command(&body) {
+		command_access::check(&state.bot_config, db, &requested_by, &command)
+			.map_err(|e| {
+			e.map_issue(Some((
+				owner.to_string(),
+				repo_name.to_string(),
+				number,
+			)))
+		})?;
+		// eg. the comment was submitted twice
+		if cooldown::throttled(
+			db,
+			&state.bot_config.command_cooldowns,
 			owner,
 			&repo_name,
-			pr.number,
-			"Running performance regression.",
-		)
+			number,
+			&command,
+			Utc::now().timestamp(),
+		)? {
+			log::info!(
+				"{} on {} was run within its cooldown; skipping",
+				command,
+				html_url
+			);
+			if let Err(e) = github_bot
+				.create_comment_reaction(owner, &repo_name, comment_id, "eyes")
+				.await
+			{
+				log::error!("Error reacting to comment: {}", e);
+			}
+			return Ok(());
+		}
+	}
+
+	// Fetch the pr to get all fields (eg. mergeable).
+	let pr = github_bot
+		.pull_request(owner, &repo_name, number)
 		.await
 		.map_err(|e| {
-			log::error!("Error posting comment: {}", e);
-		});
-	if let PullRequest {
-		head:
-			Head {
-				ref_field: head_branch,
-				repo:
-					HeadRepo {
-						name: head_repo,
-						owner: Some(User {
-							login: head_owner, ..
-						}),
-						..
-					},
+			e.map_issue(Some((
+				owner.to_string(),
+				repo_name.to_string(),
+				number,
+			)))
+		})?;
+
+	// an invalid per-repository configuration is reported on the first bot
+	// command after it changes
+	let bot_config = if body.to_lowercase().trim().starts_with("bot ") {
+		repo_bot_config(state, owner, &repo_name, Some(number)).await
+	} else {
+		state.bot_config.clone()
+	};
+
+	let auth = GithubUserAuthenticator::new(
+		&requested_by,
+		owner,
+		&repo_name,
+		number,
+		bot_config.required_permission,
+	);
+
+	let command = body.to_lowercase().trim().to_string();
+	if command == BOT_IGNORE || command == BOT_UNIGNORE {
+		//
+		// IGNORE
+		//
+		if !requested_by.eq_ignore_ascii_case(&pr.user.login) {
+			auth.check_permission(github_bot).await?;
+		}
+		let msg = if command == BOT_IGNORE {
+			ignore::ignore(db, owner, &repo_name, number).map(|_| {
+				format!(
+					"The bot will leave this PR alone until `{}`; only `{}` merges it.",
+					BOT_UNIGNORE, AUTO_MERGE_FORCE
+				)
+			})
+		} else {
+			ignore::unignore(db, owner, &repo_name, number).map(|ignored| {
+				if ignored {
+					"The bot will look after this PR again.".to_string()
+				} else {
+					"This PR was not ignored.".to_string()
+				}
+			})
+		}
+		.map_err(|e| {
+			e.map_issue(Some((
+				owner.to_string(),
+				repo_name.to_string(),
+				number,
+			)))
+		})?;
+		log::info!("{} on {} by {}", command, html_url, requested_by);
+		return github_bot
+			.create_issue_comment(owner, &repo_name, number, &msg)
+			.await
+			.map_err(|e| {
+				e.map_issue(Some((
+					owner.to_string(),
+					repo_name.to_string(),
+					number,
+				)))
+			});
+	}
+	if bot_command(&body).is_some()
+		&& !ignore::allowed_while_ignored(&body)
+		&& ignore::skips(db, owner, &repo_name, number, &command).map_err(
+			|e| {
+				e.map_issue(Some((
+					owner.to_string(),
+					repo_name.to_string(),
+					number,
+				)))
+			},
+		)? {
+		if command == AUTO_MERGE_REQUEST
+			|| command == AUTO_MERGE_SKIP_BENCH
+			|| command == AUTO_MERGE_CONFIRM
+		{
+			return Err(Error::Message {
+				msg: format!(
+					"The PR is ignored by the bot; comment `{}` to merge it anyway, or `{}` first.",
+					AUTO_MERGE_FORCE, BOT_UNIGNORE
+				),
+			}
+			.map_issue(Some((
+				owner.to_string(),
+				repo_name.to_string(),
+				number,
+			))));
+		}
+		return Ok(());
+	}
+
+	let mut skip_bench = body.to_lowercase().trim() == AUTO_MERGE_SKIP_BENCH;
+	let confirm = body.to_lowercase().trim() == AUTO_MERGE_CONFIRM;
+	if body.to_lowercase().trim() == AUTO_MERGE_REQUEST.to_lowercase().trim()
+		|| skip_bench
+		|| confirm
+	{
+		//
+		// MERGE
+		//
+		log::info!(
+			"Received merge request for PR {} from user {}",
+			html_url,
+			requested_by
+		);
+
+		auth.check_permission(&github_bot).await?;
+		if native_queue::steps_aside(bot_config.native_merge_queue, &pr) {
+			return Err(Error::NativelyQueued {}.map_issue(Some((
+				owner.to_string(),
+				repo_name.to_string(),
+				number,
+			))));
+		}
+		// a lead may skip the benchmarks of a merge waiting for its checks
+		let in_flight = in_flight::check(
+			db,
+			owner,
+			&repo_name,
+			&pr,
+			Utc::now().timestamp(),
+		)
+		.map_err(|e| {
+			e.map_issue(Some((
+				owner.to_string(),
+				repo_name.to_string(),
+				number,
+			)))
+		})?
+		.filter(|in_flight| {
+			!skip_bench || !matches!(in_flight, InFlight::Pending { .. })
+		});
+		if already_in_flight(state, owner, &repo_name, number, in_flight)
+			.await?
+		{
+			return Ok(());
+		}
+		if skip_bench {
+			let team_leads = approval::team_members(
+				github_bot,
+				owner,
+				&bot_config.lead_teams,
+			)
+			.await;
+			if !team_leads.iter().any(|lead| lead == &requested_by) {
+				return Err(Error::Message {
+					msg: format!(
+						"Only members of a lead team can skip the performance regression; comment `{}` instead.",
+						AUTO_MERGE_REQUEST
+					),
+				}
+				.map_issue(Some((
+					owner.to_string(),
+					repo_name.to_string(),
+					number,
+				))));
+			}
+		}
+
+		//
+		// confirmation of large PRs
+		//
+		let now = Utc::now().timestamp();
+		let confirmation = if confirm {
+			merge_confirmation::confirm(
+				db,
+				owner,
+				&repo_name,
+				number,
+				&requested_by,
+				&pr.head.sha,
+				now,
+			)
+			.map(Some)
+		} else if merge_confirmation::needs_confirmation(
+			&pr,
+			bot_config.confirm_merge_lines,
+			bot_config.confirm_merge_files,
+			false,
+		) {
+			merge_confirmation::request(
+				db,
+				owner,
+				&repo_name,
+				number,
+				&PendingConfirmation {
+					requested_by: requested_by.clone(),
+					head_sha: pr.head.sha.clone(),
+					skip_bench,
+					expires_at: now + bot_config.confirm_merge_window,
+					not_before: None,
+				},
+			)
+			.map(|_| None)
+		} else {
+			Ok(Some(Confirmation::Confirmed {
+				skip_bench,
+				not_before: None,
+			}))
+		}
+		.map_err(|e| {
+			e.map_issue(Some((
+				owner.to_string(),
+				repo_name.to_string(),
+				number,
+			)))
+		})?;
+		let msg = match confirmation {
+			Some(Confirmation::Confirmed {
+				not_before: Some(not_before),
+				..
+			}) => {
+				return schedule_merge(
+					state,
+					&bot_config,
+					owner,
+					&repo_name,
+					&pr,
+					&requested_by,
+					Utc.timestamp(not_before, 0),
+				)
+				.await;
+			}
+			Some(Confirmation::Confirmed {
+				skip_bench: skip, ..
+			}) => {
+				skip_bench = skip;
+				None
+			}
+			Some(Confirmation::Missing) => Some(format!(
+				"There is no merge to confirm; it may have expired, or the PR was pushed to since. Comment `{}` to request one.",
+				AUTO_MERGE_REQUEST
+			)),
+			Some(Confirmation::WrongUser {
+				requested_by: original,
+			}) => Some(format!(
+				"Only @{}, who requested the merge, can confirm it.",
+				original
+			)),
+			None => Some(confirmation_comment(&bot_config, &pr, &requested_by, None)),
+		};
+		if let Some(msg) = msg {
+			return github_bot
+				.create_issue_comment(owner, &repo_name, number, &msg)
+				.await
+				.map_err(|e| {
+					e.map_issue(Some((
+						owner.to_string(),
+						repo_name.to_string(),
+						number,
+					)))
+				});
+		}
+
+		//
+		// merge allowed
+		//
+		merge_allowed(
+			state,
+			&bot_config,
+			owner,
+			&repo_name,
+			&pr,
+			&requested_by,
+			MergeIntent::Merge,
+		)
+		.await?;
+
+		//
+		// status and merge
+		//
+		state
+			.capabilities
+			.require(Capability::ReadCheckRuns)
+			.map_err(|e| {
+				e.map_issue(Some((
+					owner.to_string(),
+					repo_name.clone(),
+					pr.number,
+				)))
+			})?;
+		if ready_to_merge(github_bot, &bot_config, owner, &repo_name, &pr)
+			.await?
+		{
+			let request = merge_request(owner, &repo_name, &pr, &requested_by);
+			mark_merging(state, &request)?;
+			let result = async {
+				performance_regression(
+					github_bot,
+					db,
+					&bot_config,
+					owner,
+					&repo_name,
+					&pr,
+					skip_bench.then_some(requested_by.as_str()),
+				)
+				.await?;
+				prepare_to_merge(
+					github_bot,
+					owner,
+					&repo_name,
+					pr.number,
+					&pr.html_url,
+				)
+				.await?;
+
+				start_merge(
+					state,
+					&request,
+					&pr,
+					false,
+					bot_config.merge_method,
+				)
+				.await
+			}
+			.await;
+			if result.is_err() {
+				record_failed_merge(state, &request);
+			}
+			let result = match result {
+				Ok(entry) => finish_merge(state, entry, &pr).await,
+				Err(e) => Err(e),
+			};
+			unmark_merging(state, &request);
+			result?;
+		} else {
+			wait_to_merge(
+				github_bot,
+				&merge_request(owner, &repo_name, &pr, &requested_by),
+				&pr.head.sha,
+				db,
+			)
+			.await?;
+			if skip_bench {
+				db::atomically(db, |tx| performance::skip(tx, &pr.head.sha))
+					.map_err(|e| {
+						e.map_issue(Some((
+							owner.to_string(),
+							repo_name.to_string(),
+							number,
+						)))
+					})?;
+			}
+		}
+	} else if let Some(at) = scheduled_merge::parse_command(&body, Utc::now()) {
+		//
+		// SCHEDULED MERGE
+		//
+		let at = at.map_err(|msg| {
+			Error::Message { msg }.map_issue(Some((
+				owner.to_string(),
+				repo_name.to_string(),
+				number,
+			)))
+		})?;
+		log::info!(
+			"Received merge request for PR {} at {} from user {}",
+			html_url,
+			at,
+			requested_by
+		);
+
+		auth.check_permission(github_bot).await?;
+		handle_scheduled_merge(
+			at,
+			&pr,
+			owner,
+			&requested_by,
+			&repo_name,
+			&bot_config,
+			state,
+		)
+		.await?;
+	} else if let Some(force) = force_merge::parse_command(&body) {
+		//
+		// MERGE
+		//
+		log::info!(
+			"Received forced merge request for PR {} from user {}: {}",
+			html_url,
+			requested_by,
+			force.reason
+		);
+
+		auth.check_permission(&github_bot).await?;
+		let force_mergers = approval::team_members(
+			github_bot,
+			owner,
+			&bot_config.force_merge_teams,
+		)
+		.await;
+		force_merge::check(&bot_config, &requested_by, &force_mergers, &force)
+			.map_err(|e| {
+				e.map_issue(Some((
+					owner.to_string(),
+					repo_name.to_string(),
+					number,
+				)))
+			})?;
+		// forcing is what gets a merge waiting for its checks through
+		let in_flight = in_flight::check(
+			db,
+			owner,
+			&repo_name,
+			&pr,
+			Utc::now().timestamp(),
+		)
+		.map_err(|e| {
+			e.map_issue(Some((
+				owner.to_string(),
+				repo_name.to_string(),
+				number,
+			)))
+		})?
+		.filter(|in_flight| !matches!(in_flight, InFlight::Pending { .. }));
+		if already_in_flight(state, owner, &repo_name, number, in_flight)
+			.await?
+		{
+			return Ok(());
+		}
+
+		//
+		// merge allowed
+		//
+		merge_allowed(
+			state,
+			&bot_config,
+			owner,
+			&repo_name,
+			&pr,
+			&requested_by,
+			MergeIntent::Force,
+		)
+		.await?;
+
+		//
+		// attempt merge without wait for checks
+		//
+		let _ = github_bot
+			.create_issue_comment(
+				owner,
+				&repo_name,
+				pr.number,
+				&force_merge::comment(&requested_by, &force),
+			)
+			.await
+			.map_err(|e| {
+				log::error!("Error posting comment: {}", e);
+			});
+		let request = merge_request(owner, &repo_name, &pr, &requested_by);
+		mark_merging(state, &request)?;
+		let result =
+			start_merge(state, &request, &pr, false, bot_config.merge_method)
+				.await;
+		if result.is_err() {
+			record_failed_merge(state, &request);
+		}
+		let result = match result {
+			Ok(entry) => finish_merge(state, entry, &pr).await,
+			Err(e) => Err(e),
+		};
+		unmark_merging(state, &request);
+		result?;
+	} else if body.to_lowercase().trim()
+		== AUTO_MERGE_CANCEL.to_lowercase().trim()
+	{
+		//
+		// CANCEL MERGE
+		//
+		log::info!(
+			"Received merge cancel for PR {} from user {}",
+			html_url,
+			requested_by
+		);
+		let pending = queue::get(db, pr.head.sha.trim())
+			.map_err(|e| {
+				e.map_issue(Some((
+					owner.to_string(),
+					repo_name.to_string(),
+					number,
+				)))
+			})?
+			// an entry which cannot be read can only be cancelled with the
+			// permission to command the bot
+			.map(|b| migrations::decode::<MergeRequest>(&b).ok());
+		let msg = match pending {
+			Some(request) => {
+				if !request.as_ref().is_some_and(|request| {
+					request.may_cancel(&requested_by, &pr.user.login)
+				}) {
+					auth.check_permission(github_bot).await?;
+				}
+				log::info!("Deleting merge request for {}", &html_url);
+				db::atomically(db, |tx| {
+					queue::delete(tx, pr.head.sha.trim())?;
+					performance::clear_skip(tx, &pr.head.sha)
+				})
+				.map_err(|e| {
+					e.map_issue(Some((
+						owner.to_string(),
+						repo_name.to_string(),
+						number,
+					)))
+				})?;
+				update_queue_positions(github_bot, db, owner, &repo_name).await;
+				match request {
+					Some(request) => format!(
+						"Merge cancelled by @{} (originally requested by @{}).",
+						requested_by, request.requested_by
+					),
+					None => format!("Merge cancelled by @{}.", requested_by),
+				}
+			}
+			None => "There is no pending merge to cancel.".to_string(),
+		};
+		let _ = github_bot
+			.create_issue_comment(owner, &repo_name, pr.number, &msg)
+			.await
+			.map_err(|e| {
+				log::error!("Error posting comment: {}", e);
+			});
+	} else if body.to_lowercase().trim() == AUTOMERGE_OFF {
+		//
+		// TURN OFF AUTOMATIC MERGE
+		//
+		auth.check_permission(github_bot).await?;
+		log::info!(
+			"Automatic merge of {} turned off by {}",
+			html_url,
+			requested_by
+		);
+		let identity = &state.bot_config.automerge_identity;
+		let result = automerge::turn_off(db, owner, &repo_name, number)
+			.and_then(|_| {
+				// a `bot merge` of someone else is kept
+				let queued = queue::get(db, pr.head.sha.trim())?
+					.and_then(|b| migrations::decode::<MergeRequest>(&b).ok())
+					.is_some_and(|request| &request.requested_by == identity);
+				if queued {
+					db::atomically(db, |tx| {
+						queue::delete(tx, pr.head.sha.trim())
+					})?;
+				}
+				Ok(())
+			});
+		result.map_err(|e| {
+			e.map_issue(Some((
+				owner.to_string(),
+				repo_name.to_string(),
+				number,
+			)))
+		})?;
+		let _ = github_bot
+			.create_issue_comment(
+				owner,
+				&repo_name,
+				pr.number,
+				&format!(
+					"Automatic merge turned off by @{}; comment `{}` to merge.",
+					requested_by, AUTO_MERGE_REQUEST
+				),
+			)
+			.await
+			.map_err(|e| {
+				log::error!("Error posting comment: {}", e);
+			});
+	} else if repo_name == "polkadot"
+		&& body.to_lowercase().trim()
+			== COMPARE_RELEASE_REQUEST.to_lowercase().trim()
+	{
+		//
+		// DIFF
+		//
+		log::info!(
+			"Received diff request for PR {} from user {}",
+			html_url,
+			requested_by
+		);
+		let rel = github_bot.latest_release(owner, &repo_name).await.map_err(
+			|e| {
+				e.map_issue(Some((
+					owner.to_string(),
+					repo_name.to_string(),
+					number,
+				)))
+			},
+		)?;
+		let release_tag = github_bot
+			.tag(owner, &repo_name, &rel.tag_name)
+			.await
+			.map_err(|e| {
+				e.map_issue(Some((
+					owner.to_string(),
+					repo_name.to_string(),
+					number,
+				)))
+			})?;
+		let release_substrate_commit = github_bot
+			.substrate_commit_from_polkadot_commit(&release_tag.object.sha)
+			.await;
+		let branch_substrate_commit = github_bot
+			.substrate_commit_from_polkadot_commit(&pr.head.sha)
+			.await;
+		if release_substrate_commit.is_ok() && branch_substrate_commit.is_ok() {
+			let link = github_bot.diff_url(
+				owner,
+				"substrate",
+				&release_substrate_commit.unwrap(),
+				&branch_substrate_commit.unwrap(),
+			);
+
+			// post link
+			log::info!("Posting link to substrate diff: {}", &link);
+			let _ = github_bot
+				.create_issue_comment(owner, &repo_name, number, &link)
+				.await
+				.map_err(|e| {
+					log::error!("Error posting comment: {}", e);
+				});
+		} else {
+			if let Err(e) = release_substrate_commit {
+				log::error!("Error getting release substrate commit: {}", e);
+				Err(e.map_issue(Some((
+					owner.to_string(),
+					repo_name.to_string(),
+					number,
+				))))?;
+			}
+			if let Err(e) = branch_substrate_commit {
+				log::error!("Error getting branch substrate commit: {}", e);
+				Err(e.map_issue(Some((
+					owner.to_string(),
+					repo_name.to_string(),
+					number,
+				))))?;
+			}
+		}
+	} else if let Some(command) = backport::parse_command(&body) {
+		//
+		// BACKPORT
+		//
+		log::info!("Backport of {} requested by {}", html_url, requested_by);
+		let issue = Some((owner.to_string(), repo_name.clone(), number));
+		auth.check_permission(github_bot).await?;
+		let command = command.map_err(|e| {
+			Error::Message {
+				msg: format!("Cannot backport: {}.", e),
+			}
+			.map_issue(issue.clone())
+		})?;
+		let commit_sha = backport::landed_commit(&pr)
+			.context(Message {
+				msg: "Only merged PRs can be backported.".to_string(),
+			})
+			.map_err(|e| e.map_issue(issue.clone()))?
+			.to_string();
+		let mut outcomes = Vec::new();
+		// each branch gets its own PR, whether or not the others could
+		for branch in &command.branches {
+			outcomes.push(
+				backport_to(
+					state,
+					owner,
+					&repo_name,
+					&pr,
+					&commit_sha,
+					branch,
+					&requested_by,
+				)
+				.await,
+			);
+		}
+		github_bot
+			.create_issue_comment(
+				owner,
+				&repo_name,
+				number,
+				&backport::summary(&outcomes),
+			)
+			.await
+			.map_err(|e| e.map_issue(issue.clone()))?;
+	} else if let Some(command) = cherry_pick::parse_command(&body) {
+		//
+		// CHERRY-PICK
+		//
+		log::info!("Cherry-pick requested by {} on {}", requested_by, html_url);
+		let issue = Some((owner.to_string(), repo_name.clone(), number));
+		let comment = async {
+			let command = command.map_err(|e| Error::Message {
+				msg: format!("Cannot cherry-pick: {}.", e),
+			})?;
+			cherry_pick_to(
+				state,
+				owner,
+				&repo_name,
+				&pr,
+				&command,
+				&requested_by,
+				&bot_config,
+			)
+			.await
+		}
+		.await
+		.map_err(|e| e.map_issue(issue.clone()))?;
+		github_bot
+			.create_issue_comment(owner, &repo_name, number, &comment)
+			.await
+			.map_err(|e| e.map_issue(issue.clone()))?;
+	} else if let Some(command) = rebase::parse_command(&body) {
+		handle_rebase_command(
+			command,
+			&pr,
+			owner,
+			&requested_by,
+			&repo_name,
+			&bot_config,
+			state,
+		)
+		.await?;
+	} else if body.to_lowercase().trim() == REFRESH_PROCESS {
+		log::info!(
+			"Process refresh for {} requested by {}",
+			html_url,
+			requested_by
+		);
+		state.process_cache.invalidate(owner, &repo_name);
+		github_bot
+			.create_issue_comment(
+				owner,
+				&repo_name,
+				number,
+				"The process file will be fetched again the next time it is needed.",
+			)
+			.await
+			.map_err(|e| {
+				e.map_issue(Some((
+					owner.to_string(),
+					repo_name.to_string(),
+					number,
+				)))
+			})?;
+	} else if let Some(period) = stats::parse_command(&body) {
+		log::info!(
+			"Merge stats for {} requested by {}",
+			html_url,
+			requested_by
+		);
+		let comment = match period {
+			Ok(period) => stats::outcomes(db, Utc::now().timestamp() - period)
+				.and_then(|outcomes| {
+					Ok(stats::aggregate(&outcomes, &queue::entries(db)?))
+				})
+				.map(|stats| stats::format_table(&stats, period)),
+			Err(e) => Ok(format!("Cannot show the merge stats: {}", e)),
+		};
+		let result = match comment {
+			Ok(comment) => {
+				github_bot
+					.create_issue_comment(owner, &repo_name, number, &comment)
+					.await
+			}
+			Err(e) => Err(e),
+		};
+		result.map_err(|e| {
+			e.map_issue(Some((
+				owner.to_string(),
+				repo_name.to_string(),
+				number,
+			)))
+		})?;
+	} else if let Some(command) = release_notes::parse_command(&body) {
+		handle_release_notes_command(
+			command,
+			owner,
+			&requested_by,
+			&repo_name,
+			number,
+			&bot_config,
+			state,
+		)
+		.await
+		.map_err(|e| {
+			e.map_issue(Some((
+				owner.to_string(),
+				repo_name.to_string(),
+				number,
+			)))
+		})?;
+	} else if let Some(command) = freeze::parse_command(&body) {
+		handle_freeze_command(
+			command,
+			owner,
+			&requested_by,
+			&repo_name,
+			&pr,
+			&bot_config,
+			state,
+		)
+		.await
+		.map_err(|e| {
+			e.map_issue(Some((
+				owner.to_string(),
+				repo_name.to_string(),
+				number,
+			)))
+		})?;
+	} else if let Some(command) = command_access::parse_command(&body) {
+		handle_admin_command(
+			command,
+			owner,
+			&requested_by,
+			&repo_name,
+			&pr,
+			state,
+		)
+		.await
+		.map_err(|e| {
+			e.map_issue(Some((
+				owner.to_string(),
+				repo_name.to_string(),
+				number,
+			)))
+		})?;
+	} else if let Some(command) = burnin::parse_command(&body) {
+		// the requester of a burn-in may cancel it even without the required
+		// permission, so `handle_burnin_cancel` checks it itself
+		if command != burnin::BurninCommand::Cancel {
+			auth.check_permission(github_bot).await?;
+		}
+
+		match command {
+			burnin::BurninCommand::Request => {
+				handle_burnin_request(
+					&body,
+					owner,
+					&requested_by,
+					&repo_name,
+					&pr,
+					state,
+				)
+				.await
+			}
+			burnin::BurninCommand::Status => {
+				handle_burnin_status(owner, &repo_name, &pr, state)
+					.await
+					.map_err(|e| {
+						e.map_issue(Some((
+							owner.to_string(),
+							repo_name.to_string(),
+							number,
+						)))
+					})?
+			}
+			burnin::BurninCommand::Cancel => handle_burnin_cancel(
+				owner,
+				&requested_by,
+				&repo_name,
+				&pr,
+				&auth,
+				state,
+			)
+			.await
+			.map_err(|e| {
+				e.map_issue(Some((
+					owner.to_string(),
+					repo_name.to_string(),
+					number,
+				)))
+			})?,
+		}
+	} else if body.to_lowercase().trim() == BOT_CHECK {
+		log::info!("Merge gate of {} checked by {}", html_url, requested_by);
+		update_merge_gate(
+			state,
+			&bot_config,
+			owner,
+			&repo_name,
+			&pr,
+			&requested_by,
+		)
+		.await?;
+	} else if body.to_lowercase().trim() == BOT_PROCESS {
+		log::info!(
+			"Process info of {} requested by {}",
+			html_url,
+			requested_by
+		);
+		let report = process_report(
+			state,
+			&bot_config,
+			owner,
+			&repo_name,
+			&pr,
+			&requested_by,
+		)
+		.await
+		.map_err(|e| {
+			e.map_issue(Some((
+				owner.to_string(),
+				repo_name.to_string(),
+				number,
+			)))
+		})?;
+		github_bot
+			.create_issue_comment(owner, &repo_name, number, &report.render())
+			.await
+			.map_err(|e| {
+				e.map_issue(Some((
+					owner.to_string(),
+					repo_name.to_string(),
+					number,
+				)))
+			})?;
+	} else if body.to_lowercase().trim() == BOT_VERSION {
+		github_bot
+			.create_issue_comment(
+				owner,
+				&repo_name,
+				number,
+				&version::reply(
+					&state.config_fingerprint,
+					(Utc::now() - state.started_at).num_seconds(),
+				),
+			)
+			.await
+			.map_err(|e| {
+				e.map_issue(Some((
+					owner.to_string(),
+					repo_name.to_string(),
+					number,
+				)))
+			})?;
+	} else if body.to_lowercase().trim() == BOT_HELP {
+		github_bot
+			.create_issue_comment(
+				owner,
+				&repo_name,
+				number,
+				&help::help(&state.bot_config.command_prefix),
+			)
+			.await
+			.map_err(|e| {
+				e.map_issue(Some((
+					owner.to_string(),
+					repo_name.to_string(),
+					number,
+				)))
+			})?;
+	}
+
+	Ok(())
+}
+
+/// Reply to a command commented on an issue which is not a PR, rather than
+/// ignore it.
+async fn handle_issue_comment(
+	body: &str,
+	number: i64,
+	html_url: &str,
+	repo_url: &str,
+	state: &AppState,
+) -> Result<()> {
+	let reply = match help::issue_reply(body, &state.bot_config.command_prefix)
+	{
+		Some(reply) => reply,
+		None => return Ok(()),
+	};
+	let RepoRef { owner, repo } =
+		issue_repo(repo_url, html_url).context(Message {
+			msg: format!("Failed parsing repository in url: {}", repo_url),
+		})?;
+	state
+		.github_bot
+		.create_issue_comment(&owner, &repo, number, &reply)
+		.await
+}
+
+async fn handle_label_added(
+	label: String,
+	added_by: String,
+	repo_name: String,
+	repo_owner: String,
+	pr: PullRequest,
+	state: &AppState,
+) -> Result<()> {
+	if is_auto_update_label(&state.bot_config, &label) {
+		log::info!(
+			"{} labelled by {} to be kept up to date",
+			pr.html_url,
+			added_by
+		);
+		return auto_update::record(
+			&state.db,
+			&AutoUpdate::new(
+				&repo_owner,
+				&repo_name,
+				pr.number,
+				&pr.base.ref_field,
+			),
+		);
+	}
+	if label != "A1-needsburnin" {
+		return Ok(());
+	}
+	let github_bot = &state.github_bot;
+
+	let msg = burnin::prompt(
+		&added_by,
+		&repo_owner,
+		&pr,
+		&state.bot_config.command_prefix,
+		&state.bot_config.templates,
+	);
+
+	github_bot
+		.create_issue_comment(&repo_owner, &repo_name, pr.number, &msg)
+		.await
+}
+
+async fn handle_burnin_request(
+	comment_body: &str,
+	owner: &str,
+	requested_by: &str,
+	repo_name: &str,
+	pr: &PullRequest,
+	state: &AppState,
+) {
+	let pr_commits = match state
+		.github_bot
+		.pull_request_commits(owner, repo_name, pr.number)
+		.await
+	{
+		Ok(commits) => commits.into_iter().map(|c| c.sha).collect(),
+		Err(e) => {
+			log::error!("Error getting commits of {}: {:?}", pr.html_url, e);
+			vec![]
+		}
+	};
+
+	let request = match burnin::extract_toml(comment_body) {
+		Some(toml) => burnin::parse_request(toml),
+		None => Err("no \\``` block found".to_string()),
+	}
+	.and_then(|request| {
+		let (request, notes) =
+			burnin::normalize_request(request, pr, requested_by, &pr_commits);
+		toml::to_string(&request)
+			.map(|toml| (toml, request, notes))
+			.map_err(|e| e.to_string())
+	});
+
+	let (toml, request, notes) = match request {
+		Ok(request) => request,
+		Err(reason) => {
+			let msg = format!(
+				"@{} invalid burn-in command: {}",
+				requested_by, reason
+			);
+			if let Err(e) = state
+				.github_bot
+				.create_issue_comment(owner, &repo_name, pr.number, &msg)
+				.await
+			{
+				log::error!("Error posting comment: {:?}", e);
+			}
+			return;
+		}
+	};
+
+	let limits = &state.bot_config.burnin_limits;
+	let requested_by_ops = !limits.violations(&request.node_types).is_empty()
+		&& is_burnin_ops(state, owner, requested_by).await;
+	let overridden_limits = match burnin::check_limits(
+		&request.node_types,
+		limits,
+		requested_by_ops,
+	) {
+		burnin::LimitCheck::Within => vec![],
+		burnin::LimitCheck::Overridden(violations) => violations,
+		burnin::LimitCheck::Exceeded(violations) => {
+			let msg = state.bot_config.templates.render(
+				templates::BURNIN_LIMITS_EXCEEDED,
+				&[
+					("requested_by", requested_by),
+					("limits", &limits.describe()),
+					(
+						"violations",
+						&violations
+							.iter()
+							.map(|violation| format!("- {}", violation))
+							.join("\n"),
+					),
+				],
+			);
+			if let Err(e) = state
+				.github_bot
+				.create_issue_comment(owner, repo_name, pr.number, &msg)
+				.await
+			{
+				log::error!("Error posting comment: {:?}", e);
+			}
+			return;
+		}
+	};
+
+	let mut msg = format!(
+		"@{} your request has been forwarded as:\n\n```toml\n{}```",
+		requested_by, toml
+	);
+	if !notes.is_empty() {
+		msg = format!(
+			"{}\n\nPlease double-check the following:\n- {}",
+			msg,
+			notes.join("\n- ")
+		);
+	}
+
+	let mut matrix_msg = format!(
+		r#"Submitted burn-in request for <a href="{}">{}#{}</a> (requested by @{})"#,
+		pr.html_url, repo_name, pr.number, requested_by,
+	);
+	if !overridden_limits.is_empty() {
+		matrix_msg = format!(
+			"{}, exceeding the limits as a member of the ops team: {}",
+			matrix_msg,
+			overridden_limits.join("; ")
+		);
+	}
+
+	if !state.effects.allow(Effect::CreateGitlabFile {
+		owner: owner.to_string(),
+		repo_name: repo_name.to_string(),
+		number: pr.number,
+		commit_message: burnin::commit_message(repo_name, pr.number),
+	}) {
+		return;
+	}
+
+	let now = Utc::now().timestamp();
+
+	match burnin::submit_request(
+		&state.gitlab_bot,
+		repo_name,
+		pr.number,
+		now,
+		&toml,
+	)
+	.await
+	{
+		Err(e) => {
+			msg = match e {
+				Error::GitlabUnreachable { attempts, .. } => format!(
+					"@{} your request could not be processed: Gitlab is unreachable and will not be retried further after {} attempts. Please try again later.",
+					requested_by, attempts,
+				),
+				_ => state.bot_config.templates.render(
+					templates::BURNIN_SUBMIT_FAILED,
+					&[("requested_by", requested_by)],
+				),
+			};
+
+			matrix_msg = format!(
+				r#"Submitting burn-in request for <a href="{}">{}#{}</a> (requested by @{}) failed: {}"#,
+				pr.html_url, repo_name, pr.number, requested_by, e,
+			);
+		}
+		Ok(path) => {
+			let record = burnin::BurninRecord {
+				owner: owner.to_string(),
+				repo_name: repo_name.to_string(),
+				number: pr.number,
+				html_url: pr.html_url.clone(),
+				requested_by: requested_by.to_string(),
+				commit_sha: request.commit_sha.clone(),
+				gitlab_path: path,
+				gitlab_commit: None,
+				pipeline_url: None,
+				created_at: now,
+				state: burnin::BurninState::Submitted,
+			};
+			if let Err(e) = burnin::remove_expired_records(&state.db, now)
+				.and_then(|_| burnin::store_record(&state.db, &record))
+			{
+				log::error!("Error storing burn-in record: {:?}", e);
+			}
+			msg = format!(
+				"{}\n\nPost `{}` to check on its progress.",
+				msg, BURNIN_STATUS
+			);
+		}
+	}
+
+	if let Err(e) = state
+		.github_bot
+		.create_issue_comment(owner, &repo_name, pr.number, &msg)
+		.await
+	{
+		log::error!("Error posting comment: {:?}", e);
+	}
+
+	if let Err(e) = state
+		.matrix_bot
+		.send_html_to_room(&state.bot_config.burnin_room_id, &matrix_msg)
+	{
+		log::error!("Error sending Matrix message: {:?}", e);
+	}
+}
+
+/// Check if a user belongs to the team allowed to exceed the burn-in limits.
+async fn is_burnin_ops(state: &AppState, owner: &str, login: &str) -> bool {
+	let team = match &state.bot_config.burnin_ops_team {
+		Some(team) => team,
+		None => return false,
+	};
+	match state
+		.github_bot
+		.team(owner, team)
+		.and_then(|team| state.github_bot.team_members(team.id))
+		.await
+	{
+		Ok(members) => members.iter().any(|m| m.login == login),
+		Err(e) => {
+			log::error!("Error getting members of {}: {:?}", team, e);
+			false
+		}
+	}
+}
+
+/// Look up the burn-in submitted for a PR, refresh its state from the Gitlab
+/// pipelines and post it on the PR.
+async fn handle_burnin_status(
+	owner: &str,
+	repo_name: &str,
+	pr: &PullRequest,
+	state: &AppState,
+) -> Result<()> {
+	let now = Utc::now().timestamp();
+	let msg = match burnin::get_record(
+		&state.db, owner, repo_name, pr.number, now,
+	)? {
+		Some(record) if record.state == burnin::BurninState::Cancelled => {
+			format!(
+				"Burn-in of {} requested by @{} was cancelled.",
+				record.commit_sha, record.requested_by
+			)
+		}
+		Some(mut record) => {
+			if record.gitlab_commit.is_none() {
+				record.gitlab_commit = state
+					.gitlab_bot
+					.last_commit_for_path(&record.gitlab_path, "master")
+					.await?
+					.map(|commit| commit.id);
+			}
+			if let Some(gitlab_commit) = &record.gitlab_commit {
+				if let Some(pipeline) = state
+					.gitlab_bot
+					.pipelines_for_commit(gitlab_commit)
+					.await?
+					.into_iter()
+					.next()
+				{
+					record.state = burnin::BurninState::from_pipeline_status(
+						&pipeline.status,
+					);
+					record.pipeline_url = Some(pipeline.web_url);
+				}
+			}
+			burnin::store_record(&state.db, &record)?;
+
+			let submitted_at =
+				chrono::NaiveDateTime::from_timestamp(record.created_at, 0);
+			match &record.pipeline_url {
+				Some(url) => format!(
+					"Burn-in of {} requested by @{} at {} UTC is {}: {}",
+					record.commit_sha,
+					record.requested_by,
+					submitted_at,
+					record.state,
+					url
+				),
+				None => format!(
+					"Burn-in of {} requested by @{} at {} UTC is {}; no pipeline has started yet.",
+					record.commit_sha,
+					record.requested_by,
+					submitted_at,
+					record.state,
+				),
+			}
+		}
+		None => "No recent burn-in request found for this PR.".to_string(),
+	};
+
+	state
+		.github_bot
+		.create_issue_comment(owner, repo_name, pr.number, &msg)
+		.await
+}
+
+/// Remove the request file of a burn-in from Gitlab, provided no pipeline has
+/// picked it up yet.  Only the original requester or a user with the required
+/// permission may cancel.
+async fn handle_burnin_cancel(
+	owner: &str,
+	requested_by: &str,
+	repo_name: &str,
+	pr: &PullRequest,
+	auth: &GithubUserAuthenticator,
+	state: &AppState,
+) -> Result<()> {
+	let now = Utc::now().timestamp();
+	let mut record = match burnin::get_record(
+		&state.db, owner, repo_name, pr.number, now,
+	)? {
+		Some(record) => record,
+		None => {
+			return state
+				.github_bot
+				.create_issue_comment(
+					owner,
+					repo_name,
+					pr.number,
+					"No recent burn-in request found for this PR.",
+				)
+				.await;
+		}
+	};
+
+	if record.requested_by != requested_by {
+		auth.check_permission(&state.github_bot).await?;
+	}
+
+	if record.state == burnin::BurninState::Submitted
+		&& !state.effects.allow(Effect::DeleteGitlabFile {
+			owner: owner.to_string(),
+			repo_name: repo_name.to_string(),
+			number: pr.number,
+			path: record.gitlab_path.clone(),
+		}) {
+		return Ok(());
+	}
+
+	let mut matrix_msg = None;
+	let msg = if record.state != burnin::BurninState::Submitted {
+		format!(
+			"@{} burn-in of {} is {}; cancellation is no longer possible.",
+			requested_by, record.commit_sha, record.state
+		)
+	} else {
+		match state
+			.gitlab_bot
+			.delete_file(
+				&record.gitlab_path,
+				"master",
+				&burnin::cancel_commit_message(repo_name, pr.number),
+			)
+			.await
+		{
+			Ok(true) => {
+				record.state = burnin::BurninState::Cancelled;
+				burnin::store_record(&state.db, &record)?;
+				matrix_msg = Some(format!(
+					r#"Cancelled burn-in request for <a href="{}">{}#{}</a> (cancelled by @{})"#,
+					pr.html_url, repo_name, pr.number, requested_by,
+				));
+				format!(
+					"@{} burn-in of {} has been cancelled.",
+					requested_by, record.commit_sha
+				)
+			}
+			Ok(false) => format!(
+				"@{} the request for {} has already been picked up on Gitlab; cancellation is no longer possible.",
+				requested_by, record.commit_sha
+			),
+			Err(e) => {
+				log::error!("Error deleting burn-in request file: {:?}", e);
+				state.bot_config.templates.render(
+					templates::BURNIN_CANCEL_FAILED,
+					&[("requested_by", requested_by)],
+				)
+			}
+		}
+	};
+
+	if let Some(matrix_msg) = matrix_msg {
+		if let Err(e) = state
+			.matrix_bot
+			.send_html_to_room(&state.bot_config.burnin_room_id, &matrix_msg)
+		{
+			log::error!("Error sending Matrix message: {:?}", e);
+		}
+	}
+
+	state
+		.github_bot
+		.create_issue_comment(owner, repo_name, pr.number, &msg)
+		.await
+}
+
+/// The comment asking for the confirmation of the merge of a large PR, which
+/// may be scheduled for `at`.
+fn confirmation_comment(
+	bot_config: &BotConfig,
+	pr: &PullRequest,
+	requested_by: &str,
+	at: Option<DateTime<Utc>>,
+) -> String {
+	format!(
+		"@{}, this PR changes {} lines in {} files. Comment `{}` within {} minutes to merge it{}.",
+		requested_by,
+		pr.additions.unwrap_or(0) + pr.deletions.unwrap_or(0),
+		pr.changed_files.unwrap_or(0),
+		AUTO_MERGE_CONFIRM,
+		bot_config.confirm_merge_window / 60,
+		at.map(|at| format!(" at {}", scheduled_merge::describe(at)))
+			.unwrap_or_default()
+	)
+}
+
+/// Schedule the merge of a PR for `at`, through the same gates as a merge
+/// asked for now: none may be in progress, and that of a large PR must be
+/// confirmed first.
+async fn handle_scheduled_merge(
+	at: DateTime<Utc>,
+	pr: &PullRequest,
+	owner: &str,
+	requested_by: &str,
+	repo_name: &str,
+	bot_config: &BotConfig,
+	state: &AppState,
+) -> Result<()> {
+	let db = &state.db;
+	let issue = Some((owner.to_string(), repo_name.to_string(), pr.number));
+	if native_queue::steps_aside(bot_config.native_merge_queue, pr) {
+		return Err(Error::NativelyQueued {}.map_issue(issue));
+	}
+	let now = Utc::now().timestamp();
+	let in_flight = in_flight::check(db, owner, repo_name, pr, now)
+		.map_err(|e| e.map_issue(issue.clone()))?;
+	if already_in_flight(state, owner, repo_name, pr.number, in_flight).await? {
+		return Ok(());
+	}
+	if merge_confirmation::needs_confirmation(
+		pr,
+		bot_config.confirm_merge_lines,
+		bot_config.confirm_merge_files,
+		false,
+	) {
+		merge_confirmation::request(
+			db,
+			owner,
+			repo_name,
+			pr.number,
+			&PendingConfirmation {
+				requested_by: requested_by.to_string(),
+				head_sha: pr.head.sha.clone(),
+				skip_bench: false,
+				expires_at: now + bot_config.confirm_merge_window,
+				not_before: Some(at.timestamp()),
+			},
+		)
+		.map_err(|e| e.map_issue(issue.clone()))?;
+		return state
+			.github_bot
+			.create_issue_comment(
+				owner,
+				repo_name,
+				pr.number,
+				&confirmation_comment(bot_config, pr, requested_by, Some(at)),
+			)
+			.await
+			.map_err(|e| e.map_issue(issue));
+	}
+	schedule_merge(state, bot_config, owner, repo_name, pr, requested_by, at)
+		.await
+}
+
+/// Queue the merge of a PR not to happen before `at`, if the merge policy
+/// allows it now; it is evaluated again once due.
+async fn schedule_merge(
+	state: &AppState,
+	bot_config: &BotConfig,
+	owner: &str,
+	repo_name: &str,
+	pr: &PullRequest,
+	requested_by: &str,
+	at: DateTime<Utc>,
+) -> Result<()> {
+	// refused now rather than when it is due
+	merge_allowed(
+		state,
+		bot_config,
+		owner,
+		repo_name,
+		pr,
+		requested_by,
+		MergeIntent::Merge,
+	)
+	.await?;
+
+	let request = MergeRequest {
+		not_before: Some(at.timestamp()),
+		..merge_request(owner, repo_name, pr, requested_by)
+	};
+	create_merge_request(&request, &pr.head.sha, &state.db).await?;
+	let _ = state
+		.github_bot
+		.create_issue_comment(
+			owner,
+			repo_name,
+			pr.number,
+			&format!(
+				"Merge scheduled for {} by @{}; it will be merged then, once its checks pass. Comment `{}` to cancel it; pushing to the PR cancels it too.",
+				scheduled_merge::describe(at),
+				requested_by,
+				AUTO_MERGE_CANCEL
+			),
+		)
+		.await
+		.map_err(|e| {
+			log::error!("Error posting comment: {}", e);
+		});
+	Ok(())
+}
+
+/// Rebase a PR, or merge its base into it, and move it onto another base if
+/// asked to, which only those allowed to command the bot may do.
+async fn handle_rebase_command(
+	command: rebase::RebaseCommand,
+	pr: &PullRequest,
+	owner: &str,
+	requested_by: &str,
+	repo_name: &str,
+	bot_config: &BotConfig,
+	state: &AppState,
+) -> Result<()> {
+	let github_bot = &state.github_bot;
+	let number = pr.number;
+	log::info!("Rebase {} requested by {}", pr.html_url, requested_by);
+	if let PullRequest {
+		head:
+			Head {
+				ref_field: head_branch,
+				repo:
+					HeadRepo {
+						name: head_repo,
+						owner: Some(User {
+							login: head_owner, ..
+						}),
+						..
+					},
+				..
+			},
+		..
+	} = pr.clone()
+	{
+		let issue = Some((owner.to_string(), repo_name.to_string(), number));
+		// retargeting the PR needs the permission to command the bot, whoever
+		// may rebase it
+		if command.onto.is_some() {
+			GithubUserAuthenticator::new(
+				requested_by,
+				owner,
+				repo_name,
+				number,
+				bot_config.required_permission,
+			)
+			.check_permission(github_bot)
+			.await?;
+		}
+		// before cloning anything
+		rebase::check_push_access(pr)
+			.map_err(|e| e.map_issue(issue.clone()))?;
+		let strategy = bot_config.rebase_strategy;
+		rebase::check_consent(
+			strategy,
+			requested_by,
+			&pr.user.login,
+			&bot_config.rebase_consent,
+		)
+		.map_err(|e| e.map_issue(issue.clone()))?;
+		let onto = match &command.onto {
+			Some(onto) => {
+				rebase::check_target(github_bot, owner, repo_name, onto)
+					.await
+					.map_err(|e| e.map_issue(issue.clone()))?;
+				onto.clone()
+			}
+			None => github_bot
+				.default_branch(owner, repo_name)
+				.await
+				.map_err(|e| e.map_issue(issue.clone()))?,
+		};
+		let msg = rebase::start_comment(command.onto.as_deref(), strategy);
+		let _ = github_bot
+			.create_issue_comment(owner, repo_name, pr.number, &msg)
+			.await
+			.map_err(|e| {
+				log::error!("Error posting comment: {}", e);
+			});
+		if state.effects.allow(Effect::Rebase {
+			owner: owner.to_string(),
+			repo_name: repo_name.to_string(),
+			number: pr.number,
+			branch: head_branch.clone(),
+			onto: onto.clone(),
+		}) {
+			let pushed = async {
+				let clone = working_clone::open(
+					github_bot,
+					&state.bot_config.sandbox,
+					owner,
+					repo_name,
+				)
+				.await?;
+				rebase(
+					github_bot,
+					&clone,
+					owner,
+					&HeadBranch {
+						owner: &head_owner,
+						repo: &head_repo,
+						branch: &head_branch,
+					},
+					&pr.head.sha,
+					&onto,
+					strategy,
+				)
+				.await
+			}
+			.await
+			.map_err(|e| match e {
+				Error::RebaseConflict { .. }
+				| Error::Message { .. }
+				| Error::CommandTimedOut { .. }
+				| Error::DiskSpace { .. } => e.map_issue(issue.clone()),
+				e => e,
+			})?;
+			record_push(state, &pushed);
+		}
+		// the base is only changed once the head was pushed
+		if command.onto.is_some()
+			&& onto != pr.base.ref_field
+			&& state.effects.allow(Effect::UpdatePullRequestBase {
+				owner: owner.to_string(),
+				repo_name: repo_name.to_string(),
+				number: pr.number,
+				base: onto.clone(),
+			}) {
+			github_bot
+				.update_pull_request_base(owner, repo_name, number, &onto)
+				.await
+				.map_err(|e| e.map_issue(issue))?;
+		}
+		Ok(())
+	} else {
+		Err(Error::Message {
+			msg: format!(
+				"PR response is missing required fields; rebase aborted."
+			),
+		}
+		.map_issue(Some((owner.to_string(), repo_name.to_string(), number))))
+	}
+}
+
+/// Comment the notes of the PRs merged between two tags, or create a draft
+/// release with them if so configured.  Only members of the organization may
+/// ask for them.
+async fn handle_release_notes_command(
+	command: ReleaseNotesCommand,
+	owner: &str,
+	requested_by: &str,
+	repo_name: &str,
+	number: i64,
+	bot_config: &BotConfig,
+	state: &AppState,
+) -> Result<()> {
+	let github_bot = &state.github_bot;
+	log::info!(
+		"Release notes of {}/{} from {} to {} requested by {}",
+		owner,
+		repo_name,
+		command.from,
+		command.to,
+		requested_by
+	);
+	state.capabilities.require(Capability::ReadOrgMembership)?;
+	if !github_bot.org_member(owner, requested_by).await? {
+		return Err(Error::Message {
+			msg: format!(
+				"Only members of {} can ask for release notes.",
+				owner
+			),
+		});
+	}
+	let commits = release_notes::commits(
+		github_bot,
+		owner,
+		repo_name,
+		&command.from,
+		&command.to,
+	)
+	.await?;
+	let notes =
+		release_notes::notes(github_bot, owner, repo_name, &commits).await?;
+	let body = release_notes::format(
+		&command.from,
+		&command.to,
+		&notes,
+		&bot_config.release_notes_categories,
+	);
+	let comment = if bot_config.release_notes_draft_release
+		&& !notes.is_empty()
+		&& state.effects.allow(Effect::CreateDraftRelease {
+			owner: owner.to_string(),
+			repo_name: repo_name.to_string(),
+			number,
+			tag_name: command.to.clone(),
+		}) {
+		let release = github_bot
+			.create_draft_release(owner, repo_name, &command.to, &body)
+			.await?;
+		format!(
+			"Created a draft release of `{}` with the notes: {}",
+			command.to, release.html_url
+		)
+	} else {
+		body
+	};
+	github_bot
+		.create_issue_comment(owner, repo_name, number, &comment)
+		.await
+}
+
+/// Freeze or unfreeze merges into a repository.  Only members of a lead team
+/// may do so.
+async fn handle_freeze_command(
+	command: freeze::FreezeCommand,
+	owner: &str,
+	requested_by: &str,
+	repo_name: &str,
+	pr: &PullRequest,
+	bot_config: &BotConfig,
+	state: &AppState,
+) -> Result<()> {
+	let team_leads = approval::team_members(
+		&state.github_bot,
+		owner,
+		&bot_config.lead_teams,
+	)
+	.await;
+	if !team_leads.iter().any(|lead| lead == requested_by) {
+		return Err(Error::Message {
+			msg: "Only members of a lead team can freeze or unfreeze merges."
+				.to_string(),
+		});
+	}
+
+	let now = Utc::now();
+	let msg = match command {
+		freeze::FreezeCommand::Freeze { reason } if reason.is_empty() => {
+			return Err(Error::Message {
+				msg: format!(
+					"Please give a reason, eg. `{} release v0.8.26`.",
+					MERGE_FREEZE
+				),
+			});
+		}
+		freeze::FreezeCommand::Freeze { reason } => {
+			freeze::store_freeze(
+				&state.db,
+				owner,
+				&freeze::MergeFreeze {
+					repo: repo_name.to_string(),
+					branch: None,
+					start: Some(now),
+					end: None,
+					until_further_notice: true,
+					reason,
+					frozen_by: Some(requested_by.to_string()),
+				},
+			)?;
+			format!(
+				"Merges into {} are frozen until `{}`.",
+				repo_name, MERGE_UNFREEZE
+			)
+		}
+		freeze::FreezeCommand::Unfreeze => {
+			let msg = if freeze::remove_freeze(&state.db, owner, repo_name)? {
+				format!("Merges into {} are no longer frozen.", repo_name)
+			} else {
+				format!("There was no `{}` to undo.", MERGE_FREEZE)
+			};
+			// freezes from the configuration cannot be lifted by command
+			match freeze::active_freeze(
+				&bot_config.merge_freezes,
+				repo_name,
+				&pr.base.ref_field,
+				now,
+			) {
+				Some(active) => format!(
+					"{} However, a configured freeze still applies.\n\n{}",
+					msg,
+					freeze::frozen_message(active, &pr.base.ref_field)
+				),
+				None => msg,
+			}
+		}
+	};
+	state
+		.github_bot
+		.create_issue_comment(owner, repo_name, pr.number, &msg)
+		.await
+}
+
+/// Deny a user any command, or lift that.  Only `COMMAND_ADMINS` may do so.
+async fn handle_admin_command(
+	command: command_access::AdminCommand,
+	owner: &str,
+	requested_by: &str,
+	repo_name: &str,
+	pr: &PullRequest,
+	state: &AppState,
+) -> Result<()> {
+	if !command_access::is_admin(&state.bot_config, requested_by) {
+		return Err(Error::CommandDenied {
+			login: requested_by.to_string(),
+		});
+	}
+	let msg = match command {
+		command_access::AdminCommand::Deny { login } => {
+			command_access::deny(&state.db, &login)?;
+			log::info!("{} denied {} any command", requested_by, login);
+			format!("@{} may no longer command the bot.", login)
+		}
+		command_access::AdminCommand::Undeny { login } => {
+			if command_access::undeny(&state.db, &login)? {
+				log::info!("{} lifted the denial of {}", requested_by, login);
+				format!(
+					"@{} may command the bot again, unless the configuration denies it.",
+					login
+				)
+			} else {
+				format!("@{} was not denied with `{} deny`.", login, BOT_ADMIN)
+			}
+		}
+		command_access::AdminCommand::Unknown => {
+			return Err(Error::Message {
+				msg: format!(
+					"Usage: `{admin} deny <login>` or `{admin} undeny <login>`.",
+					admin = BOT_ADMIN
+				),
+			});
+		}
+	};
+	state
+		.github_bot
+		.create_issue_comment(owner, repo_name, pr.number, &msg)
+		.await
+}
+
+/// The configuration for a repository, with its `.processbot.toml` applied.  If
+/// the file is invalid and `number` is given, a warning is posted on that PR,
+/// once per version of the file.
+async fn repo_bot_config(
+	state: &AppState,
+	owner: &str,
+	repo_name: &str,
+	number: Option<i64>,
+) -> BotConfig {
+	let (config, fetched) = repo_config::get(
+		&state.github_bot,
+		&state.repo_config_cache,
+		&state.bot_config,
+		owner,
+		repo_name,
+		Utc::now().timestamp(),
+	)
+	.await;
+	if let (Some(number), Some(sha), Err(e)) =
+		(number, &fetched.sha, &fetched.config)
+	{
+		match repo_config::warning_due(&state.db, owner, repo_name, sha) {
+			Ok(true) => {
+				if let Err(e) = state
+					.github_bot
+					.create_issue_comment(
+						owner,
+						repo_name,
+						number,
+						&repo_config::warning_message(e),
+					)
+					.await
+				{
+					log::error!("Error posting comment: {}", e);
+				} else if let Err(e) = repo_config::record_warning(
+					&state.db, owner, repo_name, sha,
+				) {
+					log::error!("Error storing warning in db: {}", e);
+				}
+			}
+			Ok(false) => {}
+			Err(e) => log::error!("Error reading warning from db: {}", e),
+		}
+	}
+	config
+}
+
+/// Set the merge gate status on the head of a PR to whether it would be
+/// merged if `requested_by` asked.  An error which leaves that undecided is
+/// returned, and the status left as it was.
+async fn update_merge_gate(
+	state: &AppState,
+	bot_config: &BotConfig,
+	owner: &str,
+	repo_name: &str,
+	pr: &PullRequest,
+	requested_by: &str,
+) -> Result<()> {
+	let outcome = merge_allowed(
+		state,
+		bot_config,
+		owner,
+		repo_name,
+		pr,
+		requested_by,
+		MergeIntent::Check,
+	)
+	.await;
+	let status = match merge_gate::status(&outcome, &bot_config.review_teams) {
+		Some(status) => status,
+		None => return outcome,
+	};
+	log::info!(
+		"Merge gate of {}: {:?} ({})",
+		pr.html_url,
+		status.state,
+		status.description
+	);
+	state
+		.github_bot
+		.create_status(owner, repo_name, &pr.head.sha, &status)
+		.await
+		.map_err(|e| {
+			e.map_issue(Some((
+				owner.to_string(),
+				repo_name.to_string(),
+				pr.number,
+			)))
+		})
+}
+
+/// Update the merge gate status of a PR after it was reviewed or labelled, if
+/// the repository has it enabled.  It is judged as if the author asked for the
+/// merge; errors are only logged.
+async fn refresh_merge_gate(
+	state: &AppState,
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+) {
+	let result = async {
+		let bot_config = repo_bot_config(state, owner, repo_name, None).await;
+		if !bot_config.merge_gate_status
+			|| ignore::skips(
+				&state.db,
+				owner,
+				repo_name,
+				number,
+				"merge gate status",
+			)? {
+			return Ok(());
+		}
+		let pr = state
+			.github_bot
+			.pull_request(owner, repo_name, number)
+			.await?;
+		update_merge_gate(
+			state,
+			&bot_config,
+			owner,
+			repo_name,
+			&pr,
+			&pr.user.login,
+		)
+		.await
+	}
+	.await;
+	if let Err(e) = result {
+		log::error!(
+			"Error updating the merge gate of {}/{}#{}: {}",
+			owner,
+			repo_name,
+			number,
+			e
+		);
+	}
+}
+
+/// What `bot process` replies about a PR: the projects it resolves to, and its
+/// approval evaluated as `merge_allowed` does.
+async fn process_report(
+	state: &AppState,
+	bot_config: &BotConfig,
+	owner: &str,
+	repo_name: &str,
+	pr: &PullRequest,
+	requested_by: &str,
+) -> Result<ProcessReport> {
+	let github_bot = &state.github_bot;
+	let matching = bot_config.process_matching(repo_name);
+	let projects = process::get_process(
+		github_bot,
+		&state.process_cache,
+		owner,
+		repo_name,
+		pr.number,
+		&pr.labels,
+		matching,
+	)
+	.await
+	.map_err(|e| Error::ProcessFile {
+		source: Box::new(e),
+	})?;
+	let (defined, codeowners) = if projects.is_empty() {
+		let defined = process::process_entries(
+			github_bot,
+			&state.process_cache,
+			owner,
+			repo_name,
+		)
+		.await?
+		.into_iter()
+		.map(|entry| entry.project_name)
+		.collect();
+		let codeowners =
+			codeowners::pull_request_owners(github_bot, owner, repo_name, pr)
+				.await
+				.unwrap_or_else(|e| {
+					log::error!("Error getting code owners: {}", e);
+					None
+				});
+		(defined, codeowners)
+	} else {
+		(Vec::new(), None)
+	};
+
+	if !bot_config.lead_teams.is_empty() || !bot_config.review_teams.is_empty()
+	{
+		state.capabilities.require(Capability::ReadTeams)?;
+	}
+	let team_leads =
+		approval::team_members(github_bot, owner, &bot_config.lead_teams).await;
+	let reviewers =
+		approval::team_members(github_bot, owner, &bot_config.review_teams)
+			.await;
+	let reviews = github_bot.reviews(&pr.url).await?;
+	let owners = if projects.is_empty() {
+		codeowners.clone()
+	} else {
+		Some(projects.iter_owners().cloned().collect::<Vec<_>>())
+	};
+	let approval = approval::evaluate(
+		requested_by,
+		&reviews,
+		&team_leads,
+		&reviewers,
+		owners.as_deref(),
+		approval::required_reviewers(bot_config, &pr.labels),
+	);
+
+	Ok(ProcessReport {
+		projects: projects.iter().cloned().collect(),
+		defined,
+		matching,
+		codeowners,
+		requested_by: requested_by.to_string(),
+		approval,
+	})
+}
+
+/// Fetch what the merge policy decides on for a PR.  The owners are only
+/// looked up if the teams do not allow the merge, as that takes several
+/// requests.
+async fn fetch_merge_inputs(
+	state: &AppState,
+	bot_config: &BotConfig,
+	owner: &str,
+	repo_name: &str,
+	pr: &PullRequest,
+	requested_by: &str,
+	intent: MergeIntent,
+) -> Result<MergeInputs> {
+	let github_bot = &state.github_bot;
+
+	// approvals are counted from the members of the teams
+	if !bot_config.lead_teams.is_empty() || !bot_config.review_teams.is_empty()
+	{
+		state.capabilities.require(Capability::ReadTeams)?;
+	}
+
+	let changelog = bot_config
+		.require_changelog
+		.as_ref()
+		.filter(|requirement| !requirement.is_exempt(&pr.labels));
+	let sensitive = bot_config
+		.sensitive_paths
+		.as_ref()
+		.filter(|sensitive| !sensitive.is_labelled(&pr.labels));
+	let files = if changelog.is_some()
+		|| sensitive.is_some()
+		|| bot_config.spec_version.is_some()
+	{
+		github_bot
+			.pull_request_files(owner, repo_name, pr.number)
+			.await?
+	} else {
+		Vec::new()
+	};
+
+	let reviews = github_bot.reviews(&pr.url).await.unwrap_or_else(|e| {
+		log::error!("Error getting reviews: {}", e);
+		vec![]
+	});
+
+	let auditors = match sensitive {
+		Some(sensitive) if !sensitive.matching_files(&files).is_empty() => {
+			approval::team_members(
+				github_bot,
+				owner,
+				std::slice::from_ref(&sensitive.team),
+			)
+			.await
+		}
+		_ => Vec::new(),
+	};
+
+	let spec_versions = match &bot_config.spec_version {
+		Some(check) => {
+			spec_version::versions(
+				github_bot,
+				owner,
+				repo_name,
+				&pr.base.ref_field,
+				&pr.head.sha,
+				&spec_version::changed_runtimes(check, &files),
+			)
+			.await?
+		}
+		None => Vec::new(),
+	};
+
+	let stored_freeze = freeze::get_freeze(&state.db, owner, repo_name)
+		.unwrap_or_else(|e| {
+			log::error!("Error reading merge freeze from db: {}", e);
+			None
+		});
+
+	let team_leads =
+		approval::team_members(github_bot, owner, &bot_config.lead_teams).await;
+	let reviewers =
+		approval::team_members(github_bot, owner, &bot_config.review_teams)
+			.await;
+
+	let team_approval = approval::team_approval(
+		requested_by,
+		&reviews,
+		&team_leads,
+		&reviewers,
+		approval::required_reviewers(bot_config, &pr.labels),
+	);
+	let (process, codeowners) = if team_approval.is_some() {
+		(None, None)
+	} else {
+		let process = process::get_process(
+			github_bot,
+			&state.process_cache,
+			owner,
+			repo_name,
+			pr.number,
+			&pr.labels,
+			bot_config.process_matching(repo_name),
+		)
+		.await;
+		// Process.json takes precedence; CODEOWNERS is consulted for PRs it
+		// does not cover
+		let codeowners = match &process {
+			Ok(process) if !process.is_empty() => None,
+			_ => codeowners::pull_request_owners(
+				github_bot, owner, repo_name, pr,
+			)
+			.await
+			.unwrap_or_else(|e| {
+				log::error!("Error getting code owners: {}", e);
+				None
+			}),
+		};
+		(Some(process), codeowners)
+	};
+
+	Ok(MergeInputs {
+		owner: owner.to_string(),
+		repo_name: repo_name.to_string(),
+		labels: pr.labels.clone(),
+		base_branch: pr.base.ref_field.clone(),
+		// GitHub has often not worked it out yet when a PR by a trusted
+		// author is pushed to, and it is checked again once its checks pass
+		mergeable: pr.mergeable.unwrap_or(intent == MergeIntent::Automerge),
+		requested_by: requested_by.to_string(),
+		intent,
+		files,
+		auditors,
+		spec_versions,
+		stored_freeze,
+		now: Utc::now(),
+		team_leads,
+		reviewers,
+		reviews,
+		process,
+		codeowners,
+	})
+}
+
+/// The error reporting the rule which blocks a merge.
+fn blocked_merge_error(
+	block: Block,
+	base_branch: String,
+	process: Option<Result<process::CombinedProcessInfo>>,
+	number: i64,
+) -> Error {
+	match block {
+		Block::Label(label) => Error::BlockedLabel { label },
+		Block::BaseBranch { branch, allowed } => {
+			Error::BaseBranchNotAllowed { branch, allowed }
+		}
+		Block::MissingChangelog { path } => {
+			Error::MissingChangelog { path, number }
+		}
+		Block::Unaudited { files, label, team } => {
+			Error::Unaudited { files, label, team }
+		}
+		Block::SpecVersionNotBumped { versions } => {
+			Error::SpecVersionNotBumped { versions }
+		}
+		Block::Frozen(freeze) => Error::MergeFrozen {
+			freeze,
+			branch: base_branch,
+		},
+		Block::Unmergeable => Error::Message {
+			msg: format!("The PR is currently unmergeable."),
+		},
+		Block::ChangesRequested(reviewer) => {
+			Error::ChangesRequested { reviewer }
+		}
+		Block::NoOwners => match process {
+			Some(Err(e)) => Error::ProcessFile {
+				source: Box::new(e),
+			},
+			_ => Error::ProcessInfo {},
+		},
+		Block::Approval {
+			owners,
+			missing_reviewers,
+		} => Error::Approval {
+			owners,
+			missing_reviewers,
+		},
+	}
+}
+
+/// Check if the pull request is mergeable and approved.
+async fn merge_allowed(
+	state: &AppState,
+	bot_config: &BotConfig,
+	owner: &str,
+	repo_name: &str,
+	pr: &PullRequest,
+	requested_by: &str,
+	intent: MergeIntent,
+) -> Result<()> {
+	let github_bot = &state.github_bot;
+	let issue = Some((owner.to_string(), repo_name.to_string(), pr.number));
+
+	// checked before anything is fetched, so that a failed request does not
+	// hide them and a PR they block costs none
+	if let Some(block) = merge_policy::unfetched_blocks(
+		&pr.labels,
+		&pr.base.ref_field,
+		bot_config,
+	)
+	.into_iter()
+	.next()
+	{
+		log::info!("{} merge blocked: {:?}", pr.html_url, block);
+		return Err(blocked_merge_error(
+			block,
+			pr.base.ref_field.clone(),
+			None,
+			pr.number,
+		)
+		.map_issue(issue));
+	}
+
+	let inputs = fetch_merge_inputs(
+		state,
+		bot_config,
+		owner,
+		repo_name,
+		pr,
+		requested_by,
+		intent,
+	)
+	.await
+	.map_err(|e| e.map_issue(issue.clone()))?;
+	let verdict = merge_policy::evaluate_merge_policy(&inputs, bot_config);
+
+	for note in verdict.notes() {
+		log::info!("{} merges despite {:?}", pr.html_url, note);
+		let _ = github_bot
+			.create_issue_comment(
+				owner,
+				repo_name,
+				pr.number,
+				&note.message(requested_by),
+			)
+			.await
+			.map_err(|e| {
+				log::error!("Error posting comment: {}", e);
+			});
+	}
+
+	let block = match verdict {
+		MergeVerdict::Allowed { reason, .. } => {
+			log::info!("{} merge allowed: {:?}", pr.html_url, reason);
+			return Ok(());
+		}
+		MergeVerdict::Blocked { mut reasons, .. } => reasons.remove(0),
+	};
+	log::info!("{} merge blocked: {:?}", pr.html_url, block);
+	// a check is not a request to merge
+	if let (Block::Approval { .. }, Some(Ok(process))) =
+		(&block, &inputs.process)
+	{
+		if intent != MergeIntent::Check {
+			ping_missing_approval(
+				state,
+				owner,
+				repo_name,
+				pr,
+				requested_by,
+				process,
+			)
+		}
+	}
+	Err(blocked_merge_error(
+		block,
+		inputs.base_branch,
+		inputs.process,
+		pr.number,
+	)
+	.map_issue(issue))
+}
+
+/// Ask the owners of the projects a PR belongs to for a review in the projects'
+/// Matrix rooms, at most once per `APPROVAL_PING_INTERVAL_SECS`.
+fn ping_missing_approval(
+	state: &AppState,
+	owner: &str,
+	repo_name: &str,
+	pr: &PullRequest,
+	requested_by: &str,
+	process: &process::CombinedProcessInfo,
+) {
+	let now = Utc::now().timestamp();
+	match process::last_approval_ping(&state.db, owner, repo_name, pr.number) {
+		Ok(last_ping) if !process::approval_ping_due(last_ping, now) => {
+			log::info!("Owners of {} were pinged recently", pr.html_url);
+			return;
+		}
+		Ok(_) => {}
+		Err(e) => {
+			log::error!("Error reading approval ping from db: {}", e);
+			return;
+		}
+	}
+	for project in process.iter() {
+		let msg =
+			process::approval_ping_message(&pr.html_url, requested_by, project);
+		if let Err(e) =
+			state.matrix_bot.send_to_room(&project.matrix_room_id, &msg)
+		{
+			log::error!("Error sending Matrix message: {:?}", e);
+		}
+	}
+	if let Err(e) = process::record_approval_ping(
+		&state.db, owner, repo_name, pr.number, now,
+	) {
+		log::error!("Error storing approval ping in db: {}", e);
+	}
+}
+
+/// Query checks and statuses.
+///
+/// This function is used when a merge request is first received, to decide whether to store the
+/// request and wait for checks -- if so they will later be handled by `checks_and_status`.
+async fn ready_to_merge(
+	github_bot: &GithubBot,
+	bot_config: &BotConfig,
+	owner: &str,
+	repo_name: &str,
+	pr: &PullRequest,
+) -> Result<bool> {
+	//
+	// status
+	//
+	match github_bot
+		.status(owner, &repo_name, &pr.head.sha)
+		.await
+		.map(|status| {
+			repo_config::without_ignored_statuses(
+				status.latest(),
+				&bot_config.ignored_checks,
+			)
+		})
+		.map_err(|e| {
+			e.map_issue(Some((
+				owner.to_string(),
+				repo_name.to_string(),
+				pr.number,
+			)))
+		})? {
+		CombinedStatus {
+			state: StatusState::Success,
+			..
+		} => {
+			log::info!("{} is green.", pr.html_url);
+			//
+			// checks
+			//
+			let checks = github_bot
+				.check_runs(&owner, &repo_name, &pr.head.sha)
+				.await
+				.map(|checks| {
+					repo_config::without_ignored_checks(
+						checks.latest(),
+						&bot_config.ignored_checks,
+					)
+				})
+				.map_err(|e| {
+					e.map_issue(Some((
+						owner.to_string(),
+						repo_name.to_string(),
+						pr.number,
+					)))
+				})?;
+			log::info!("{:?}", checks);
+			if checks
+				.check_runs
+				.iter()
+				.all(|r| r.conclusion == Some("success".to_string()))
+			{
+				//
+				// status/checks green
+				//
+				return Ok(true);
+			} else if checks
+				.check_runs
+				.iter()
+				.all(|r| r.status == "completed".to_string())
+			{
+				//
+				// status/checks failure
+				//
+				log::info!("{} checks were unsuccessful.", pr.html_url);
+				return Err(Error::ChecksFailed {
+					commit_sha: pr.head.sha.clone(),
+					failed: checks.failed_checks(),
+				}
+				.map_issue(Some((
+					owner.to_string(),
+					repo_name.to_string(),
+					pr.number,
+				))));
+			} else {
+				//
+				// status/checks pending
+				//
+				return Ok(false);
+			}
+		}
+		CombinedStatus {
+			state: StatusState::Pending,
+			..
+		} => {
+			//
+			// status/checks pending
+			//
+			return Ok(false);
+		}
+		status @ CombinedStatus {
+			state: StatusState::Failure,
+			..
+		} => {
+			//
+			// status/checks failure
+			//
+			log::info!("{} status failure.", pr.html_url);
+			return Err(Error::ChecksFailed {
+				commit_sha: pr.head.sha.to_string(),
+				failed: status.failed_checks(),
+			}
+			.map_issue(Some((
+				owner.to_string(),
+				repo_name.to_string(),
+				pr.number,
+			))));
+		}
+		status @ CombinedStatus {
+			state: StatusState::Error,
+			..
+		} => {
+			//
+			// status/checks failure
+			//
+			log::info!("{} status error.", pr.html_url);
+			return Err(Error::ChecksFailed {
+				commit_sha: pr.head.sha.to_string(),
+				failed: status.failed_checks(),
+			}
+			.map_issue(Some((
+				owner.to_string(),
+				repo_name.to_string(),
+				pr.number,
+			))));
+		}
+	}
+}
+
+/// The merge of a PR, requested now.
+fn merge_request(
+	owner: &str,
+	repo_name: &str,
+	pr: &PullRequest,
+	requested_by: &str,
+) -> MergeRequest {
+	MergeRequest {
+		owner: owner.to_string(),
+		repo_name: repo_name.to_string(),
+		number: pr.number,
+		html_url: pr.html_url.to_string(),
+		requested_by: requested_by.to_string(),
+		created_at: Some(Utc::now().timestamp()),
+		origin: None,
+		not_before: None,
+	}
+}
+
+/// Add a merge request to the database.
+///
+/// If this has been called, error handling must remove the db entry.
+async fn create_merge_request(
+	request: &MergeRequest,
+	commit_sha: &str,
+	db: &DB,
+) -> Result<()> {
+	log::info!("Writing merge request to db (head sha: {})", commit_sha);
+	db::atomically(db, |tx| queue::store(tx, commit_sha.trim(), request))
+		.map_err(|e| {
+			e.map_issue(Some((
+				request.owner.to_string(),
+				request.repo_name.to_string(),
+				request.number,
+			)))
+		})
+}
+
+/// Add a merge request to the database, and post a comment stating the merge
+/// is pending, and where it is in line if others are.  That comment is
+/// updated by `update_queue_positions` as those ahead leave the queue.
+async fn wait_to_merge(
+	github_bot: &GithubBot,
+	request: &MergeRequest,
+	commit_sha: &str,
+	db: &DB,
+) -> Result<()> {
+	log::info!("{} checks incomplete.", request.html_url);
+	create_merge_request(request, commit_sha, db).await?;
+	log::info!("Waiting for commit status.");
+	let position = pending_requests(db, &request.owner, &request.repo_name)
+		.map(|requests| {
+			queue_position::on_enqueue(
+				&requests,
+				request.number,
+				Utc::now().timestamp(),
+			)
+		})
+		.unwrap_or_else(|e| {
+			log::error!("Error reading the merge queue: {}", e);
+			None
+		});
+	match github_bot
+		.post_issue_comment(
+			&request.owner,
+			&request.repo_name,
+			request.number,
+			&queue_position::comment(position),
+		)
+		.await
+	{
+		Ok(comment) => {
+			if let Some(position) = position {
+				let tracked = queue_position::Tracked {
+					comment_id: comment.id,
+					position,
+				};
+				let _ = queue_position::track(
+					db,
+					&request.owner,
+					&request.repo_name,
+					request.number,
+					&tracked,
+				)
+				.map_err(|e| {
+					log::error!("Error tracking the queue position: {}", e);
+				});
+			}
+		}
+		Err(e) => log::error!("Error posting comment: {}", e),
+	}
+	Ok(())
+}
+
+/// The pending merges of a repository which can be read.
+fn pending_requests(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+) -> Result<Vec<MergeRequest>> {
+	Ok(queue::for_repo(db, owner, repo_name)?
+		.into_iter()
+		.filter_map(|entry| entry.request)
+		.collect())
+}
+
+/// Update the comments giving the PRs waiting in the queue of a repository
+/// their position, after a merge left it.
+async fn update_queue_positions(
+	github_bot: &GithubBot,
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+) {
+	let changes = pending_requests(db, owner, repo_name).and_then(|requests| {
+		let tracked = queue_position::tracked(db, owner, repo_name)?;
+		Ok(queue_position::changes(
+			&requests,
+			&tracked,
+			Utc::now().timestamp(),
+		))
+	});
+	let changes = match changes {
+		Ok(changes) => changes,
+		Err(e) => {
+			log::error!("Error reading the merge queue: {}", e);
+			return;
+		}
+	};
+	let _ = db::atomically(db, |tx| {
+		for number in &changes.gone {
+			queue_position::untrack(tx, owner, repo_name, *number)?;
+		}
+		Ok(())
+	})
+	.map_err(|e| {
+		log::error!("Error untracking queue positions: {}", e);
+	});
+	for (number, tracked) in changes.moved {
+		log::info!(
+			"{}/{}#{} is now #{} in the merge queue",
+			owner,
+			repo_name,
+			number,
+			tracked.position
+		);
+		let res = github_bot
+			.edit_issue_comment(
+				owner,
+				repo_name,
+				tracked.comment_id,
+				&queue_position::comment(Some(tracked.position)),
+			)
+			.await
+			.and_then(|_| {
+				queue_position::track(db, owner, repo_name, number, &tracked)
+			});
+		if let Err(e) = res {
+			log::error!("Error updating the queue position: {}", e);
+		}
+	}
+}
+
+/// Drop the merges of a PR scheduled with `bot merge at`, as it was pushed
+/// to, and say so on the PR.
+async fn cancel_scheduled_merges(
+	state: &AppState,
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+) -> Result<()> {
+	for request in scheduled_merge::cancel(&state.db, owner, repo_name, number)?
+	{
+		log::info!(
+			"{} was pushed to; dropping its scheduled merge",
+			request.html_url
+		);
+		let at = scheduled_merge::due_at(&request)
+			.map(scheduled_merge::describe)
+			.unwrap_or_default();
+		let _ = state
+			.github_bot
+			.create_issue_comment(
+				owner,
+				repo_name,
+				number,
+				&format!(
+					"The merge scheduled for {} by @{} was cancelled, as the PR was pushed to. Comment `{} <time>` to schedule it again.",
+					at, request.requested_by, AUTO_MERGE_AT
+				),
+			)
+			.await
+			.map_err(|e| {
+				log::error!("Error posting comment: {}", e);
+			});
+	}
+	Ok(())
+}
+
+/// Every `interval`, hand the merges scheduled with `bot merge at` which are
+/// due over to the ordinary pending merges, and try them as if their checks
+/// had just changed.
+pub async fn merge_scheduled(
+	state: Arc<Mutex<AppState>>,
+	interval: std::time::Duration,
+) {
+	loop {
+		tokio::time::delay_for(interval).await;
+		let state = &*state.lock().await;
+		merge_due(state, Utc::now().timestamp()).await;
+	}
+}
+
+/// Every `interval`, evaluate again the checks of the commits whose status
+/// and check webhooks were coalesced.  The lock on the state is only taken if
+/// there are any.
+pub async fn recheck_debounced(
+	state: Arc<Mutex<AppState>>,
+	debounce: Arc<ChecksDebounce>,
+	interval: std::time::Duration,
+) {
+	loop {
+		tokio::time::delay_for(interval).await;
+		let due = debounce.due(Utc::now().timestamp());
+		if due.is_empty() {
+			continue;
+		}
+		let state = &*state.lock().await;
+		for sha in due {
+			log::info!("Evaluating the coalesced checks of {}", sha);
+			let context = ErrorContext {
+				event: Some("debounced_checks".to_string()),
+				..ErrorContext::default()
+			};
+			if let Err(e) = checks_and_status(state, &sha).await {
+				handle_error(e, state, &context).await;
+			}
+		}
+	}
+}
+
+async fn merge_due(state: &AppState, now: i64) {
+	let due = match scheduled_merge::due(&state.db, now) {
+		Ok(due) => due,
+		Err(e) => {
+			log::error!("Error reading scheduled merges: {}", e);
+			return;
+		}
+	};
+	for (sha, request) in due {
+		log::info!(
+			"The merge of {} scheduled by {} is due",
+			request.html_url,
+			request.requested_by
+		);
+		let context = ErrorContext {
+			event: Some("scheduled_merge".to_string()),
+			command: Some(AUTO_MERGE_AT.to_string()),
+			..ErrorContext::default()
+		}
+		.with_pr(&request.owner, &request.repo_name, request.number);
+		if let Err(e) = merge_when_due(state, &sha, &request).await {
+			handle_error(e, state, &context).await;
+		}
+	}
+}
+
+/// Evaluate the merge policy for a scheduled merge which is due, as its gates
+/// may have changed since it was scheduled, and try the merge if it still
+/// allows it.  If not, the merge is dropped and the reason returned; if the
+/// policy cannot be evaluated, it stays scheduled.
+async fn merge_when_due(
+	state: &AppState,
+	sha: &str,
+	request: &MergeRequest,
+) -> Result<()> {
+	let MergeRequest {
+		owner,
+		repo_name,
+		number,
+		..
+	} = request;
+	let pr = state
+		.github_bot
+		.pull_request(owner, repo_name, *number)
+		.await
+		.map_err(|e| {
+			e.map_issue(Some((
+				owner.to_string(),
+				repo_name.to_string(),
+				*number,
+			)))
+		})?;
+	let bot_config = repo_bot_config(state, owner, repo_name, None).await;
+	recheck_merge_allowed(
+		state,
+		&bot_config,
+		request,
+		sha,
+		&pr,
+		MergeIntent::Merge,
+	)
+	.await?;
+	scheduled_merge::promote(&state.db, sha, request)?;
+	checks_and_status(state, sha).await
+}
+
+/// Post a comment stating the merge will be attempted.
+async fn prepare_to_merge(
+	github_bot: &GithubBot,
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+	html_url: &str,
+) -> Result<()> {
+	log::info!("{} checks successful; trying merge.", html_url);
+	let _ = github_bot
+		.create_issue_comment(owner, &repo_name, number, "Trying merge.")
+		.await
+		.map_err(|e| {
+			log::error!("Error posting comment: {}", e);
+		});
+	Ok(())
+}
+
+/// Cherry-pick a merged PR onto `branch` and open a PR with it.
+async fn backport_to(
+	state: &AppState,
+	owner: &str,
+	repo_name: &str,
+	pr: &PullRequest,
+	commit_sha: &str,
+	branch: &str,
+	requested_by: &str,
+) -> backport::Outcome {
+	if !state.effects.allow(Effect::Backport {
+		owner: owner.to_string(),
+		repo_name: repo_name.to_string(),
+		number: pr.number,
+		branch: branch.to_string(),
+	}) {
+		return backport::Outcome::Planned {
+			branch: branch.to_string(),
+		};
+	}
+	let github_bot = &state.github_bot;
+	let working = backport::working_branch(pr.number, branch);
+	let result = async {
+		let clone = working_clone::open(
+			github_bot,
+			&state.bot_config.sandbox,
+			owner,
+			repo_name,
+		)
+		.await?;
+		match backport::pick(&clone, commit_sha, branch, &working).await? {
+			Picked::Conflict(files) => Ok(backport::Outcome::Conflict {
+				branch: branch.to_string(),
+				files,
+			}),
+			Picked::Pushed => {
+				let opened = github_bot
+					.create_pull_request(
+						owner,
+						repo_name,
+						&backport::title(branch, pr),
+						&backport::body(branch, pr, commit_sha, requested_by),
+						&working,
+						branch,
+					)
+					.await?;
+				Ok(backport::Outcome::Opened {
+					branch: branch.to_string(),
+					html_url: opened.html_url,
+				})
+			}
+		}
+	}
+	.await;
+	result.unwrap_or_else(|e: Error| {
+		log::error!("Error backporting {} to {}: {}", pr.html_url, branch, e);
+		backport::Outcome::Failed {
+			branch: branch.to_string(),
+			reason: e.to_string(),
+		}
+	})
+}
+
+/// Cherry-pick a commit onto a branch for `bot cherry-pick`, commented on
+/// `pr`, and open a PR with it.  Only members of a lead team may do so.
+/// Returns the comment saying how it went.
+async fn cherry_pick_to(
+	state: &AppState,
+	owner: &str,
+	repo_name: &str,
+	pr: &PullRequest,
+	command: &cherry_pick::CherryPickCommand,
+	requested_by: &str,
+	bot_config: &BotConfig,
+) -> Result<String> {
+	let github_bot = &state.github_bot;
+	let team_leads =
+		approval::team_members(github_bot, owner, &bot_config.lead_teams).await;
+	if !team_leads.iter().any(|lead| lead == requested_by) {
+		return Err(Error::Message {
+			msg: "Only members of a lead team can cherry-pick commits."
+				.to_string(),
+		});
+	}
+	let branch = &command.branch;
+	let commit = github_bot
+		.commit(owner, repo_name, &command.sha)
+		.await
+		.map_err(|e| {
+			if cherry_pick::is_unknown_commit(&e) {
+				Error::Message {
+					msg: format!(
+						"Cannot cherry-pick: there is no commit `{}` in {}.",
+						command.sha, repo_name
+					),
+				}
+			} else {
+				e
+			}
+		})?;
+	if !state.effects.allow(Effect::CherryPick {
+		owner: owner.to_string(),
+		repo_name: repo_name.to_string(),
+		number: pr.number,
+		sha: commit.sha.clone(),
+		branch: branch.to_string(),
+	}) {
+		return Ok(cherry_pick::comment(
+			&commit.sha,
+			&backport::Outcome::Planned {
+				branch: branch.to_string(),
+			},
+		));
+	}
+	let result = async {
+		let clone = working_clone::open(
+			github_bot,
+			&state.bot_config.sandbox,
+			owner,
+			repo_name,
+		)
+		.await?;
+		let working = cherry_pick::working_branch(
+			&commit.sha,
+			branch,
+			&clone.remote_branches().await?,
+		);
+		match backport::pick(&clone, &commit.sha, branch, &working).await? {
+			Picked::Conflict(files) => Ok(backport::Outcome::Conflict {
+				branch: branch.to_string(),
+				files,
+			}),
+			Picked::Pushed => {
+				let opened = github_bot
+					.create_pull_request(
+						owner,
+						repo_name,
+						&cherry_pick::title(branch, &commit.commit.message),
+						&cherry_pick::body(
+							branch,
+							&commit.sha,
+							requested_by,
+							&pr.html_url,
+						),
+						&working,
+						branch,
+					)
+					.await?;
+				Ok(backport::Outcome::Opened {
+					branch: branch.to_string(),
+					html_url: opened.html_url,
+				})
+			}
+		}
+	}
+	.await;
+	let outcome = result.unwrap_or_else(|e: Error| {
+		log::error!(
+			"Error cherry-picking {} onto {}: {}",
+			commit.sha,
+			branch,
+			e
+		);
+		backport::Outcome::Failed {
+			branch: branch.to_string(),
+			reason: e.to_string(),
+		}
+	});
+	Ok(cherry_pick::comment(&commit.sha, &outcome))
+}
+
+/// Send a merge request.
+async fn merge(
+	state: &AppState,
+	owner: &str,
+	repo_name: &str,
+	pr: &PullRequest,
+	merge_method: MergeMethod,
+) -> Result<()> {
+	if !state.effects.allow(Effect::Merge {
+		owner: owner.to_string(),
+		repo_name: repo_name.to_string(),
+		number: pr.number,
+		head_sha: pr.head.sha.clone(),
+		merge_method,
+	}) {
+		return Ok(());
+	}
+	state
+		.github_bot
+		.merge_pull_request(
+			owner,
+			repo_name,
+			pr.number,
+			&pr.head.sha,
+			merge_method,
+		)
+		.await
+		.map_err(|e| {
+			Error::Merge {
+				source: Box::new(e),
+				commit_sha: pr.head.sha.clone(),
+			}
+			.map_issue(Some((
+				owner.to_string(),
+				repo_name.to_string(),
+				pr.number,
+			)))
+		})?;
+	log::info!("{} merged successfully.", pr.html_url);
+	Ok(())
+}
+
+/// Merge a PR, journaling the attempt so that a restart before the phases
+/// after it are done can finish them.  A failed attempt is forgotten.
+async fn start_merge(
+	state: &AppState,
+	request: &MergeRequest,
+	pr: &PullRequest,
+	queued: bool,
+	merge_method: MergeMethod,
+) -> Result<JournalEntry> {
+	let entry = JournalEntry {
+		request: request.clone(),
+		head_sha: pr.head.sha.trim().to_string(),
+		queued,
+		phase: Phase::Merge,
+	};
+	journal::record(&state.db, &entry)?;
+	let result =
+		merge(state, &request.owner, &request.repo_name, pr, merge_method)
+			.await;
+	if result.is_err() {
+		journal::finish(
+			&state.db,
+			&request.owner,
+			&request.repo_name,
+			request.number,
+		)?;
+	}
+	result.map(|()| entry)
+}
+
+/// Carry out the phases after a merge, from the one the entry was in.
+async fn finish_merge(
+	state: &AppState,
+	mut entry: JournalEntry,
+	pr: &PullRequest,
+) -> Result<()> {
+	let db = &state.db;
+	if entry.phase <= Phase::Cleanup {
+		entry.phase = Phase::Cleanup;
+		journal::record(db, &entry)?;
+		clean_up_after_merge(state, &entry)?;
+		if entry.queued {
+			update_queue_positions(
+				&state.github_bot,
+				db,
+				&entry.request.owner,
+				&entry.request.repo_name,
+			)
+			.await;
+		}
+	}
+	entry.phase = Phase::Companion;
+	journal::record(db, &entry)?;
+	let result = update_companion(state, &entry.request.repo_name, pr).await;
+	journal::finish(
+		db,
+		&entry.request.owner,
+		&entry.request.repo_name,
+		entry.request.number,
+	)?;
+	result
+}
+
+/// Remove a merged PR from the queue and record the merge, along with
+/// auditing it if it was queued.
+fn clean_up_after_merge(state: &AppState, entry: &JournalEntry) -> Result<()> {
+	let request = &entry.request;
+	let audit = if entry.queued {
+		audit_entry(
+			state,
+			&request.requested_by,
+			&request.owner,
+			&request.repo_name,
+			request.number,
+			"merge",
+			&Ok(()),
+		)
+	} else {
+		None
+	};
+	let cleaned = db::atomically(&state.db, |tx| {
+		if entry.queued {
+			queue::delete(tx, &entry.head_sha)?;
+			performance::clear_skip(tx, &entry.head_sha)?;
+		}
+		record_outcome(tx, state, request, MergeResult::Merged)?;
+		match &audit {
+			Some(audit) => audit::record(tx, audit),
+			None => Ok(()),
+		}
+	});
+	if cleaned.is_err() {
+		// the merge happened all the same
+		write_audit(state, audit);
+	}
+	cleaned.map_err(|e| {
+		e.map_issue(Some((
+			request.owner.to_string(),
+			request.repo_name.to_string(),
+			request.number,
+		)))
+	})
+}
+
+/// Recover a merge interrupted by a restart: finish the phases after it if
+/// the PR was merged, or else forget it.  Returns the comment to post about
+/// it.
+async fn recover_merge(
+	state: &AppState,
+	entry: JournalEntry,
+	pr: &PullRequest,
+) -> Result<String> {
+	let recovery = journal::recovery(&entry, pr.merged.unwrap_or(false));
+	let comment = journal::recovery_comment(&entry, recovery);
+	match recovery {
+		Recovery::Finish => finish_merge(state, entry, pr).await?,
+		Recovery::RollBack => {
+			let request = &entry.request;
+			if !entry.queued {
+				record_failed_merge(state, request);
+			}
+			journal::finish(
+				&state.db,
+				&request.owner,
+				&request.repo_name,
+				request.number,
+			)?;
+		}
+	}
+	Ok(comment)
+}
+
+/// Recover the merges which were in progress when the bot last stopped.
+pub async fn recover_merges(state: &AppState) {
+	// left by a crash, they would turn away merge commands until they expire
+	match in_flight::clear_all(&state.db) {
+		Ok(0) => {}
+		Ok(n) => log::info!("Cleared {} stale merge marks", n),
+		Err(e) => log::error!("Error clearing stale merge marks: {}", e),
+	}
+	let entries = match journal::entries(&state.db) {
+		Ok(entries) => entries,
+		Err(e) => {
+			log::error!("Error reading the merge journal: {}", e);
+			return;
+		}
+	};
+	for entry in entries {
+		let MergeRequest {
+			owner,
+			repo_name,
+			number,
+			html_url,
+			..
+		} = entry.request.clone();
+		log::info!("Recovering the interrupted merge of {}", html_url);
+		let recovered = match state
+			.github_bot
+			.pull_request(&owner, &repo_name, number)
+			.await
+		{
+			Ok(pr) => recover_merge(state, entry, &pr).await,
+			Err(e) => Err(e),
+		};
+		match recovered {
+			Ok(comment) => {
+				let _ = state
+					.github_bot
+					.create_issue_comment(&owner, &repo_name, number, &comment)
+					.await
+					.map_err(|e| {
+						log::error!("Error posting comment: {}", e);
+					});
+			}
+			Err(e) => log::error!(
+				"Error recovering the interrupted merge of {}: {}",
+				html_url,
+				e
+			),
+		}
+	}
+}
+
+/// Benchmark the PR against master if its repository has a performance gate,
+/// failing the merge if it regresses too much.  A benchmark which cannot be
+/// run does not hold up the merge.
+async fn performance_regression(
+	github_bot: &GithubBot,
+	db: &DB,
+	bot_config: &BotConfig,
+	owner: &str,
+	repo_name: &str,
+	pr: &PullRequest,
+	skipped_by: Option<&str>,
+) -> Result<()> {
+	let gate = match performance::gate(&bot_config.performance_gates, repo_name)
+	{
+		Some(gate) => gate,
+		None => return Ok(()),
+	};
+	let msg = match skipped_by {
+		Some(login) => {
+			format!(
+				"Skipping performance regression, as requested by @{}.",
+				login
+			)
+		}
+		None => "Running performance regression.".to_string(),
+	};
+	let _ = github_bot
+		.create_issue_comment(owner, repo_name, pr.number, &msg)
+		.await
+		.map_err(|e| {
+			log::error!("Error posting comment: {}", e);
+		});
+	if let Some(login) = skipped_by {
+		let _ = github_bot
+			.create_check_run(
+				owner,
+				repo_name,
+				&benchmark_check::skipped(&pr.head.sha, login),
+			)
+			.await
+			.map_err(|e| {
+				log::error!("Error creating benchmark check run: {}", e);
+			});
+		return Ok(());
+	}
+
+	// the check run only reports the gate, so failing to create it does not
+	// stop the merge
+	let check_run_id = github_bot
+		.create_check_run(
+			owner,
+			repo_name,
+			&benchmark_check::started(&pr.head.sha),
+		)
+		.await
+		.map(|check_run| check_run.id)
+		.map_err(|e| {
+			log::error!("Error creating benchmark check run: {}", e);
+		})
+		.ok();
+
+	let regression = match &pr.head {
+		Head {
+			ref_field: head_branch,
+			repo:
+				HeadRepo {
+					name: head_repo,
+					owner: Some(User {
+						login: head_owner, ..
+					}),
+					..
+				},
+			..
+		} => performance::regression(
+			github_bot,
+			&bot_config.sandbox,
+			owner,
+			repo_name,
+			&HeadBranch {
+				owner: head_owner,
+				repo: head_repo,
+				branch: head_branch,
+			},
+			gate,
+			&performance::BaselineCache {
+				db,
+				ttl: bot_config.bench_cache_ttl,
+				max_entries: bot_config.bench_cache_max_entries,
+			},
+		)
+		.await
+		.map_err(|e| e.to_string()),
+		_ => Err("the head repository is gone".to_string()),
+	};
+	let decision = gate.decide(
+		regression
+			.as_ref()
+			.map(|regression| regression.as_ref().and_then(|r| r.factor()))
+			.map_err(Clone::clone),
+	);
+	if let Some(check_run_id) = check_run_id {
+		let _ = github_bot
+			.update_check_run(
+				owner,
+				repo_name,
+				check_run_id,
+				&benchmark_check::completed(
+					&pr.head.sha,
+					&decision,
+					regression.as_ref().ok().and_then(Option::as_ref),
+					gate.regression_threshold,
+				),
+			)
+			.await
+			.map_err(|e| {
+				log::error!("Error completing benchmark check run: {}", e);
+			});
+	}
+	match decision {
+		performance::Decision::Passed { factor } => {
+			log::info!(
+				"Performance regression shows factor {} change in benchmark average.",
+				factor
+			);
+			Ok(())
+		}
+		performance::Decision::Regressed { factor } => {
+			log::error!(
+				"Performance regression shows factor {} change in benchmark average.",
+				factor
+			);
+			Err(Error::PerformanceRegression {
+				commit_sha: pr.head.sha.clone(),
+				factor,
+				threshold: gate.regression_threshold,
+			}
+			.map_issue(Some((
+				owner.to_string(),
+				repo_name.to_string(),
+				pr.number,
+			))))
+		}
+		performance::Decision::BenchFailed { reason } => {
+			log::error!(
+				"Failed to complete performance regression: {}",
+				reason
+			);
+			let _ = github_bot
+				.create_issue_comment(
+					owner,
+					repo_name,
+					pr.number,
+					&format!(
+						"Could not complete the performance regression ({}); see logs; continuing merge.",
+						reason
+					),
+				)
+				.await
+				.map_err(|e| {
+					log::error!("Error posting comment: {}", e);
+				});
+			Ok(())
+		}
+	}
+}
+
+/// Check for a Polkadot companion and update it if found.
+async fn update_companion(
+	state: &AppState,
+	repo_name: &str,
+	pr: &PullRequest,
+) -> Result<()> {
+	let github_bot = &state.github_bot;
+	let db = &state.db;
+
+	if let Some(RepoRef { owner, .. }) = RepoRef::from_url(&pr.html_url) {
+		if ignore::skips(db, &owner, repo_name, pr.number, "companion update")?
+		{
+			return Ok(());
+		}
+	}
+
+	if repo_name == "substrate" {
+		log::info!("Checking for companion.");
+		if let Some(body) = &pr.body {
+			// check for link in pr body
+			if let Some((comp_html_url, comp_owner, comp_repo, comp_number)) =
+				companion_parse(&body, &state.bot_config.companion_placeholders)
+			{
+				log::info!("Found companion {}", comp_html_url);
+				let comp_pr = github_bot
+					.pull_request(&comp_owner, &comp_repo, comp_number)
+					.await
+					.map_err(|e| {
+						e.map_issue(Some((
+							comp_owner.to_string(),
+							comp_repo.to_string(),
+							comp_number,
+						)))
+					})?;
+
+				let allowed_branches = state
+					.bot_config
+					.companion_branches
+					.get(&comp_repo)
+					.map(Vec::as_slice)
+					.unwrap_or_default();
+				let msg = match companion_check(&comp_pr, allowed_branches) {
+					CompanionCheck::Update => None,
+					CompanionCheck::Skip(msg) => {
+						log::info!("{}", msg);
+						Some(msg)
+					}
+					CompanionCheck::Warn(msg) => {
+						log::warn!("{}", msg);
+						Some(msg)
+					}
+				};
+				if let Some(msg) = msg {
+					if let Some(RepoRef { owner, .. }) =
+						RepoRef::from_url(&pr.html_url)
+					{
+						let _ = github_bot
+							.create_issue_comment(
+								&owner, repo_name, pr.number, &msg,
+							)
+							.await
+							.map_err(|e| {
+								log::error!("Error posting comment: {}", e);
+							});
+					}
+					return Ok(());
+				}
+
+				if let PullRequest {
+					head:
+						Head {
+							ref_field: comp_head_branch,
+							repo:
+								HeadRepo {
+									name: comp_head_repo,
+									owner:
+										Some(User {
+											login: comp_head_owner,
+											..
+										}),
+									..
+								},
+							..
+						},
+					..
+				} = comp_pr.clone()
+				{
+					rebase::check_push_access(&comp_pr).map_err(|e| {
+						e.map_issue(Some((
+							comp_owner.to_string(),
+							comp_repo.to_string(),
+							comp_number,
+						)))
+					})?;
+					if !state.effects.allow(Effect::CompanionUpdate {
+						owner: comp_owner.clone(),
+						repo_name: comp_repo.clone(),
+						number: comp_number,
+						branch: comp_head_branch.clone(),
+					}) {
+						return Ok(());
+					}
+					log::info!("Updating companion {}", comp_html_url);
+					if let Some(updated_sha) = companion_update(
+						github_bot,
+						&state.bot_config.sandbox,
+						&comp_owner,
+						&comp_repo,
+						&comp_pr.base.ref_field,
+						&HeadBranch {
+							owner: &comp_head_owner,
+							repo: &comp_head_repo,
+							branch: &comp_head_branch,
+						},
+					)
+					.await
+					.map_err(|e| {
+						Error::Companion {
+							source: Box::new(e),
+						}
+						.map_issue(Some((
+							comp_owner.to_string(),
+							comp_repo.to_string(),
+							comp_number,
+						)))
+					})? {
+						log::info!(
+							"Companion updated; waiting for checks on {}",
+							comp_html_url
+						);
+						record_push(state, &updated_sha);
+
+						// wait for checks on the update commit
+						wait_to_merge(
+							github_bot,
+							&companion_merge_request(
+								&comp_owner,
+								&comp_repo,
+								&comp_pr,
+								repo_name,
+								pr,
+							),
+							&updated_sha,
+							db,
+						)
+						.await?;
+					} else {
+						log::info!(
+							"Failed updating companion {}",
+							comp_html_url
+						);
+
+						Err(Error::Message {
+							msg: format!("Failed updating substrate."),
+						}
+						.map_issue(Some((
+							comp_owner.to_string(),
+							comp_repo.to_string(),
+							comp_number,
+						))))?;
+					}
+				} else {
+					Err(Error::Companion {
+						source: Box::new(Error::Message {
+							msg: format!(
+								"Companion PR is missing required fields."
+							),
+						}),
+					}
+					.map_issue(Some((
+						comp_owner.to_string(),
+						comp_repo.to_string(),
+						comp_number,
+					))))?;
+				}
+			} else {
+				log::info!("No companion found.");
+			}
+		} else {
+			log::info!("No PR body found.");
+		}
+	}
+
+	Ok(())
+}
+
+/// The merge of a companion, updated after `pr` merged, which is told how the
+/// merge goes.
+fn companion_merge_request(
+	comp_owner: &str,
+	comp_repo: &str,
+	comp_pr: &PullRequest,
+	repo_name: &str,
+	pr: &PullRequest,
+) -> MergeRequest {
+	MergeRequest {
+		origin: RepoRef::from_url(&pr.html_url).map(
+			|RepoRef { owner, .. }| MergeOrigin {
+				owner,
+				repo_name: repo_name.to_string(),
+				number: pr.number,
+				html_url: pr.html_url.clone(),
+			},
+		),
+		..merge_request(
+			comp_owner,
+			comp_repo,
+			comp_pr,
+			"parity-processbot[bot]",
+		)
+	}
+}
+
+/// Tell the PR a merge was requested for, if any, how the merge went: merged,
+/// or failed with `failure`.  Failures are noted on Matrix too.
+async fn notify_origin(
+	state: &AppState,
+	request: &MergeRequest,
+	failure: Option<&str>,
+) {
+	let origin = match &request.origin {
+		Some(origin) => origin,
+		None => return,
+	};
+	if let Err(e) = state
+		.github_bot
+		.create_issue_comment(
+			&origin.owner,
+			&origin.repo_name,
+			origin.number,
+			&companion_outcome_comment(request, failure),
+		)
+		.await
+	{
+		log::error!("Error posting comment: {}", e);
+	}
+	if let Some(failure) = failure {
+		if let Err(e) = state
+			.matrix_bot
+			.send_to_default(&companion_failure_note(request, origin, failure))
+		{
+			log::error!("Error sending Matrix message: {:?}", e);
+		}
+	}
+}
+
+/// Distinguish required statuses.
+#[allow(dead_code)]
+fn status_failure_allowed(ci: &str, context: &str) -> bool {
+	let d: serde_yaml::Result<serde_yaml::Value> = serde_yaml::from_str(ci);
+	match d {
+		Ok(v) => v[context]["allow_failure"].as_bool().unwrap_or(false),
+		Err(e) => {
+			log::error!("Error parsing value from ci yaml: {}", e);
+			false
+		}
+	}
+}
+
+/// Whether the error is explained on the PR as part of the normal process, so
+/// there is no need to report it.
+fn is_explained(e: &Error) -> bool {
+	matches!(
+		e,
+		Error::ProcessInfo {}
+			| Error::InsufficientPermission { .. }
+			| Error::MissingPermission { .. }
+			| Error::CommandDenied { .. }
+			| Error::ForceMergeRestricted { .. }
+			| Error::ForceMergeReasonMissing {}
+			| Error::MergeFrozen { .. }
+			| Error::Approval { .. }
+			| Error::ChangesRequested { .. }
+			| Error::BlockedLabel { .. }
+			| Error::BaseBranchNotAllowed { .. }
+			| Error::HeadChanged { .. }
+			| Error::ChecksFailed { .. }
+			| Error::PerformanceRegression { .. }
+			| Error::RebaseConflict { .. }
+			| Error::MaintainerEditsDisabled { .. }
+			| Error::Message { .. }
+	)
+}
+
+/// Send an error to the error sink, if there is one, tagged with the PR it
+/// concerns.
+fn report_error(state: &AppState, context: &ErrorContext, e: &Error) {
+	let sink = match &state.error_sink {
+		Some(sink) => sink,
+		None => return,
+	};
+	let context = match e {
+		Error::WithIssue {
+			source,
+			issue: Some((owner, repo, number)),
+		} => {
+			if is_explained(source) {
+				return;
+			}
+			context.with_pr(owner, repo, *number)
+		}
+		_ => context.clone(),
+	};
+	sink.report(&context.report(e));
+}
+
+async fn handle_error(e: Error, state: &AppState, context: &ErrorContext) {
+	log::error!("{}", e);
+	report_error(state, context, &e);
+	match e {
+		Error::WithIssue {
+			source,
+			issue: Some((owner, repo, number)),
+			..
+		} => {
+			match &*source {
+				// keep the merge request, so that the merge is tried again
+				// when the statuses or checks next change
+				Error::Merge { source, .. }
+					if source.is_rate_limited() || source.is_transient() =>
+				{
+					log::info!("Keeping merge request after failed merge");
+				}
+				Error::Merge { commit_sha, .. }
+				| Error::HeadChanged { commit_sha }
+				| Error::ChecksFailed { commit_sha, .. }
+				| Error::PerformanceRegression { commit_sha, .. } => {
+					// clean db, noting that the merge failed
+					let request = queue::get(&state.db, commit_sha)
+						.ok()
+						.flatten()
+						.and_then(|bytes| migrations::decode(&bytes).ok());
+					let _ = db::atomically(&state.db, |tx| {
+						if let Some(request) = &request {
+							record_outcome(
+								tx,
+								state,
+								request,
+								MergeResult::Failed,
+							)?;
+						}
+						queue::delete(tx, commit_sha)?;
+						performance::clear_skip(tx, commit_sha)
+					})
+					.map_err(|e| {
+						log::error!(
+							"Error deleting merge request from db: {}",
+							e
+						);
+					});
+					if let Some(request) = &request {
+						notify_origin(
+							state,
+							request,
+							Some(&source.to_string()),
+						)
+						.await;
+					}
+					update_queue_positions(
+						&state.github_bot,
+						&state.db,
+						&owner,
+						&repo,
+					)
+					.await;
+				}
+				_ => {}
+			}
+			let kind = source.kind();
+			let msg =
+				ErrorComment::for_error(&source, &state.bot_config.templates)
+					.render();
+			let hash = error_comments::hash(&owner, &repo, number, kind, &msg);
+			if !error_comment_due(state, context, &hash) {
+				log::info!(
+					"Not commenting the same error on {}/{}#{} again",
+					owner,
+					repo,
+					number
+				);
+				state
+					.suppressed_error_comments
+					.fetch_add(1, Ordering::Relaxed);
+				return;
+			}
+			match state
+				.github_bot
+				.create_issue_comment(
+					&owner,
+					&repo,
+					number,
+					&version::with_footer(&msg),
+				)
+				.await
+			{
+				Ok(()) => {
+					let now = Utc::now().timestamp();
+					if let Err(e) =
+						error_comments::record(&state.db, &hash, now)
+					{
+						log::error!("Error recording error comment: {}", e);
+					}
+				}
+				Err(e) => log::error!("Error posting comment: {}", e),
+			}
+		}
+		_ => {}
+	}
+}
+
+/// Whether an error comment was not already posted within the window, which
+/// is configured separately for `bot merge force`, where the user asked for
+/// the merge despite anything that might go wrong.
+fn error_comment_due(
+	state: &AppState,
+	context: &ErrorContext,
+	hash: &str,
+) -> bool {
+	let config = &state.bot_config;
+	let window =
+		if context.command.as_deref().is_some_and(|command| {
+			force_merge::parse_command(command).is_some()
+		}) {
+			config.force_merge_error_comment_window
+		} else {
+			config.error_comment_window
+		};
+	let now = Utc::now().timestamp();
+	let longest_window = config
+		.error_comment_window
+		.max(config.force_merge_error_comment_window);
+	error_comments::remove_expired(&state.db, now, longest_window)
+		.and_then(|_| error_comments::due(&state.db, hash, now, window))
+		.unwrap_or_else(|e| {
+			log::error!("Error reading error comment records: {}", e);
+			true
+		})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		health::Health, process::ProcessCache, recording, reporting::MemorySink,
+	};
+	use ring::hmac;
+
+	fn state(name: &str, error_sink: Option<Arc<dyn ErrorSink>>) -> AppState {
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		let db_path = std::env::temp_dir().join(format!(
+			"processbot-webhook-{}-{}",
+			name,
+			std::process::id()
+		));
+		let _ = std::fs::remove_dir_all(&db_path);
+		AppState {
+			db: Arc::new(crate::db::open(&db_path).expect("db")),
+			github_bot: rt
+				.block_on(GithubBot::new(Vec::new(), "paritytech"))
+				.expect("github_bot"),
+			matrix_bot: MatrixBot::new_with_token(
+				"https://matrix.parity.io",
+				"",
+				"",
+				true,
+			),
+			gitlab_bot: rt
+				.block_on(GitlabBot::new_with_token(
+					"gitlab.parity.io",
+					"parity/polkadot",
+					"",
+				))
+				.expect("gitlab_bot"),
+			bot_config: BotConfig {
+				command_prefix: "bot".to_string(),
+				bot_login: "parity-processbot[bot]".to_string(),
+				..BotConfig::default()
+			},
+			capabilities: Capabilities::default(),
+			process_cache: ProcessCache::new(600),
+			repo_config_cache: repo_config::RepoConfigCache::new(600),
+			effects: Effects::new(false),
+			health: Health::default(),
+			readiness: Arc::new(Readiness::new(1)),
+			checks_debounce: Arc::new(ChecksDebounce::new(0)),
+			suppressed_error_comments: AtomicU64::new(0),
+			quarantined_merge_requests: AtomicU64::new(0),
+			skipped_own_events: AtomicU64::new(0),
+			error_sink,
+			webhook_secret: String::new(),
+			gitlab_webhook_secret: String::new(),
+			audit_token: String::new(),
+			admin_token: String::new(),
+			config_fingerprint: String::new(),
+			started_at: Utc::now(),
+		}
+	}
+
+	#[test]
+	fn test_handle_error_reports() {
+		let sink = Arc::new(MemorySink::default());
+		let state = state("reports", Some(sink.clone()));
+		let context = ErrorContext {
+			event: Some("issue_comment".to_string()),
+			delivery: Some("72d3162e".to_string()),
+			..ErrorContext::default()
+		};
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		rt.block_on(handle_error(
+			Error::Message {
+				msg: "Missing x-hub-signature".to_string(),
+			},
+			&state,
+			&context,
+		));
+		rt.block_on(handle_error(
+			Error::ProcessInfo {}.map_issue(None),
+			&state,
+			&context,
+		));
+
+		let reports = sink.reports.lock();
+		assert_eq!(reports.len(), 2);
+		assert_eq!(reports[0].message, "Error: Missing x-hub-signature");
+		assert_eq!(reports[0].tags["event"], "issue_comment");
+		assert_eq!(reports[0].tags["delivery"], "72d3162e");
+		assert_eq!(reports[0].tags["version"], env!("CARGO_PKG_VERSION"));
+		assert!(!reports[0].tags.contains_key("repo"));
+	}
+
+	#[test]
+	fn test_report_error_tags_pr() {
+		let sink = Arc::new(MemorySink::default());
+		let state = state("tags-pr", Some(sink.clone()));
+		let context = ErrorContext {
+			event: Some("check_run".to_string()),
+			..ErrorContext::default()
+		};
+		let pr =
+			Some(("paritytech".to_string(), "substrate".to_string(), 1234));
+		report_error(
+			&state,
+			&context,
+			&Error::Companion {
+				source: Box::new(Error::Message {
+					msg: "merge conflict".to_string(),
+				}),
+			}
+			.map_issue(pr.clone()),
+		);
+		// explained on the PR, so not reported
+		report_error(
+			&state,
+			&context,
+			&Error::Approval {
+				owners: vec![],
+				missing_reviewers: 2,
+			}
+			.map_issue(pr.clone()),
+		);
+		report_error(
+			&state,
+			&context,
+			&Error::ChangesRequested {
+				reviewer: "rphmeier".to_string(),
+			}
+			.map_issue(pr.clone()),
+		);
+		report_error(
+			&state,
+			&context,
+			&Capabilities::without(&[Capability::ReadTeams])
+				.require(Capability::ReadTeams)
+				.unwrap_err()
+				.map_issue(pr),
+		);
+
+		let reports = sink.reports.lock();
+		assert_eq!(reports.len(), 1);
+		assert_eq!(reports[0].tags["event"], "check_run");
+		assert_eq!(reports[0].tags["repo"], "paritytech/substrate");
+		assert_eq!(reports[0].tags["pr"], "1234");
+	}
+
+	#[test]
+	fn test_merge_allowed_without_permission() {
+		let mut state = state("without-permission", None);
+		state.capabilities = Capabilities::without(&[Capability::ReadTeams]);
+		let bot_config = BotConfig {
+			review_teams: vec!["core-devs".to_string()],
+			..state.bot_config.clone()
+		};
+		let pr = PullRequest {
+			number: 1234,
+			mergeable: Some(true),
+			..PullRequest::default()
+		};
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		let outcome = rt.block_on(merge_allowed(
+			&state,
+			&bot_config,
+			"paritytech",
+			"substrate",
+			&pr,
+			"gavofyork",
+			MergeIntent::Merge,
+		));
+		// not a verdict on the PR
+		assert_eq!(merge_gate::verdict(&outcome, &[]), None);
+		match outcome {
+			Err(Error::WithIssue { source, issue }) => {
+				assert_eq!(
+					issue,
+					Some((
+						"paritytech".to_string(),
+						"substrate".to_string(),
+						1234
+					))
+				);
+				assert_eq!(
+					source.to_string(),
+					"The bot's GitHub App lacks the Members read permission, which it needs to read the members of teams."
+				);
+			}
+			outcome => panic!("unexpected outcome {:?}", outcome),
+		}
+	}
+
+	#[test]
+	fn test_merge_blocked_before_fetching() {
+		let mut state = state("blocked-before-fetching", None);
+		// reading the teams fails, as would any request
+		state.capabilities = Capabilities::without(&[Capability::ReadTeams]);
+		let bot_config = BotConfig {
+			review_teams: vec!["core-devs".to_string()],
+			blocked_labels: vec!["A3-in_progress".to_string()],
+			..state.bot_config.clone()
+		};
+		let pr = PullRequest {
+			number: 1234,
+			mergeable: Some(true),
+			labels: vec![Label {
+				name: "A3-in_progress".to_string(),
+				..Label::default()
+			}],
+			..PullRequest::default()
+		};
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		let outcome = rt.block_on(merge_allowed(
+			&state,
+			&bot_config,
+			"paritytech",
+			"substrate",
+			&pr,
+			"gavofyork",
+			MergeIntent::Merge,
+		));
+		match outcome {
+			Err(Error::WithIssue { source, .. }) => match *source {
+				Error::BlockedLabel { label } => {
+					assert_eq!(label, "A3-in_progress")
+				}
+				e => panic!("unexpected error {:?}", e),
+			},
+			outcome => panic!("unexpected outcome {:?}", outcome),
+		}
+	}
+
+	#[test]
+	fn test_trusted_pr_blocked_is_not_queued() {
+		let mut state = state("trusted-blocked", None);
+		state.bot_config.automerge_authors =
+			vec!["dependabot[bot]".to_string()];
+		state.bot_config.automerge_files = vec!["Cargo.lock".to_string()];
+		state.bot_config.blocked_labels = vec!["A3-in_progress".to_string()];
+		state.repo_config_cache.insert(
+			"paritytech",
+			"substrate",
+			repo_config::FetchedRepoConfig {
+				sha: None,
+				config: Ok(repo_config::RepoConfig::default()),
+			},
+			Utc::now().timestamp(),
+		);
+		let pr = PullRequest {
+			number: 1,
+			html_url: "https://github.com/paritytech/substrate/pull/1"
+				.to_string(),
+			user: User {
+				login: "dependabot[bot]".to_string(),
+				..User::default()
+			},
+			head: Head {
+				sha: "def".to_string(),
+				..Head::default()
+			},
+			labels: vec![Label {
+				name: "A3-in_progress".to_string(),
+				..Label::default()
+			}],
+			..PullRequest::default()
+		};
+		// queued for a previous head
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		let request = merge_request(
+			"paritytech",
+			"substrate",
+			&pr,
+			&state.bot_config.automerge_identity,
+		);
+		rt.block_on(create_merge_request(&request, "abc", &state.db))
+			.unwrap();
+		let files = vec![PullRequestFile {
+			filename: "Cargo.lock".to_string(),
+			..PullRequestFile::default()
+		}];
+		rt.block_on(merge_if_trusted(
+			&pr,
+			"paritytech",
+			"substrate",
+			&files,
+			&state,
+		))
+		.unwrap();
+		assert_eq!(queue::get(&state.db, "abc").unwrap(), None);
+		assert_eq!(queue::get(&state.db, "def").unwrap(), None);
+	}
+
+	const REPOS: [&str; 2] = ["substrate", "polkadot"];
+
+	fn push(repo_name: &str, branch: &str) -> String {
+		serde_json::json!({
+			"ref": format!("refs/heads/{}", branch),
+			"commits": [{ "id": "abc", "modified": [repo_config::REPO_CONFIG_FILE] }],
+			"repository": {
+				"name": repo_name,
+				"owner": { "login": "paritytech" },
+				"default_branch": "master",
+			},
+		})
+		.to_string()
+	}
+
+	fn signed_request(
+		delivery: &str,
+		body: &str,
+		secret: &str,
+	) -> Request<Body> {
+		let key = hmac::Key::new(
+			hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY,
+			secret.as_bytes(),
+		);
+		let sig =
+			base16::encode_lower(hmac::sign(&key, body.as_bytes()).as_ref());
+		Request::builder()
+			.uri("/webhook")
+			.header("x-github-event", "push")
+			.header("x-github-delivery", delivery)
+			.header("x-hub-signature", format!("sha1={}", sig))
+			.body(Body::from(body.to_string()))
+			.unwrap()
+	}
+
+	fn cache_repo_configs(state: &AppState) {
+		for repo_name in &REPOS {
+			state.repo_config_cache.insert(
+				"paritytech",
+				repo_name,
+				repo_config::FetchedRepoConfig {
+					sha: None,
+					config: Ok(repo_config::RepoConfig::default()),
+				},
+				0,
+			);
+		}
+	}
+
+	fn cached_repo_configs(state: &AppState) -> Vec<&'static str> {
+		REPOS
+			.iter()
+			.copied()
+			.filter(|repo_name| {
+				state
+					.repo_config_cache
+					.get_stale("paritytech", repo_name)
+					.is_some()
+			})
+			.collect()
+	}
+
+	#[test]
+	fn test_replay_recorded_payloads() {
+		let dir = std::env::temp_dir()
+			.join(format!("processbot-replay-{}", std::process::id()));
+		let _ = std::fs::remove_dir_all(&dir);
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+
+		let mut live = state("replay-live", None);
+		live.webhook_secret = "secret".to_string();
+		live.bot_config.record_payloads_dir =
+			Some(dir.to_str().unwrap().to_string());
+		cache_repo_configs(&live);
+		for (delivery, body) in &[
+			("1", push("substrate", "master")),
+			("2", push("polkadot", "feature")),
+		] {
+			rt.block_on(webhook_inner(
+				signed_request(delivery, body, "secret"),
+				&live,
+				&mut ErrorContext::default(),
+			))
+			.unwrap();
+		}
+		// webhooks failing verification are not recorded
+		assert!(rt
+			.block_on(webhook_inner(
+				signed_request("3", &push("polkadot", "master"), "forged"),
+				&live,
+				&mut ErrorContext::default(),
+			))
+			.is_err());
+		assert_eq!(cached_repo_configs(&live), vec!["polkadot"]);
+
+		let recorded = recording::load(&dir).unwrap();
+		assert_eq!(recorded.len(), 2);
+		assert!(recorded.iter().all(|(_, envelope)| {
+			!envelope.headers.contains_key("x-hub-signature")
+		}));
+
+		let replayed = state("replay-replayed", None);
+		cache_repo_configs(&replayed);
+		for (_, envelope) in recorded {
+			rt.block_on(replay(envelope, &replayed)).unwrap();
+		}
+		assert_eq!(cached_repo_configs(&replayed), cached_repo_configs(&live));
+		let _ = std::fs::remove_dir_all(&dir);
+	}
+
+	#[test]
+	fn test_probes_answer_while_handling_a_webhook() {
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		let state = state("probes", None);
+		let readiness = Arc::clone(&state.readiness);
+		let state = Arc::new(Mutex::new(state));
+		let probe = |path: &str| {
+			let req = Request::builder().uri(path).body(Body::empty()).unwrap();
+			webhook(req, Arc::clone(&state), Arc::clone(&readiness))
+		};
+
+		rt.block_on(async {
+			// as if a webhook were being handled
+			let _lock = state.lock().await;
+			let _pending =
+				(readiness.pending_webhook(), readiness.pending_webhook());
+			assert_eq!(probe("/livez").await.unwrap().status(), StatusCode::OK);
+			assert_eq!(
+				probe("/readyz").await.unwrap().status(),
+				StatusCode::OK
+			);
+
+			readiness.update(true, false);
+			assert_eq!(
+				probe("/readyz").await.unwrap().status(),
+				StatusCode::SERVICE_UNAVAILABLE
+			);
+			assert_eq!(probe("/livez").await.unwrap().status(), StatusCode::OK);
+		});
+		readiness.update(true, false);
+		assert_eq!(
+			rt.block_on(probe("/readyz")).unwrap().status(),
+			StatusCode::OK
+		);
+	}
+
+	#[test]
+	fn test_verify_gitlab_token() {
+		let req = |token: Option<&str>| {
+			let mut req = Request::builder().uri("/gitlab-webhook");
+			if let Some(token) = token {
+				req = req.header("x-gitlab-token", token);
+			}
+			req.body(Body::empty()).unwrap()
+		};
+		assert!(verify_gitlab_token(b"s3cret", &req(Some("s3cret"))));
+		assert!(!verify_gitlab_token(b"s3cret", &req(Some("wrong"))));
+		assert!(!verify_gitlab_token(b"s3cret", &req(Some(""))));
+		assert!(!verify_gitlab_token(b"s3cret", &req(None)));
+		// a secret left empty does not let anyone in
+		assert!(!verify_gitlab_token(b"", &req(Some(""))));
+		assert!(!verify_gitlab_token(b"", &req(None)));
+	}
+
+	#[test]
+	fn test_admin_queue() {
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		let state = state("admin-queue", None);
+		let sha = "0123456789abcdef0123456789abcdef01234567";
+		let request = MergeRequest {
+			owner: "paritytech".to_string(),
+			repo_name: "substrate".to_string(),
+			number: 1234,
+			html_url: String::new(),
+			requested_by: "gavofyork".to_string(),
+			created_at: Some(1000),
+			origin: None,
+			not_before: None,
+		};
+		db::atomically(&state.db, |tx| queue::store(tx, sha, &request))
+			.unwrap();
+		let readiness = Arc::clone(&state.readiness);
+		let state = Arc::new(Mutex::new(state));
+		let send = |method: &str, path: &str, token: Option<&str>| {
+			let mut req = Request::builder().method(method).uri(path);
+			if let Some(token) = token {
+				req = req.header("authorization", format!("Bearer {}", token));
+			}
+			webhook(
+				req.body(Body::empty()).unwrap(),
+				Arc::clone(&state),
+				Arc::clone(&readiness),
+			)
+		};
+		let mut status = |method: &str, path: &str, token: Option<&str>| {
+			rt.block_on(send(method, path, token)).unwrap().status()
+		};
+
+		// not served without a token
+		assert_eq!(
+			status("GET", "/admin/queue", Some("")),
+			StatusCode::NOT_FOUND
+		);
+		futures::executor::block_on(state.lock()).admin_token =
+			"s3cret".to_string();
+		assert_eq!(
+			status("GET", "/admin/queue", None),
+			StatusCode::UNAUTHORIZED
+		);
+		assert_eq!(
+			status("GET", "/admin/queue", Some("wrong")),
+			StatusCode::UNAUTHORIZED
+		);
+		let path = "/admin/queue/paritytech/substrate/1234";
+		assert_eq!(
+			status("DELETE", path, Some("wrong")),
+			StatusCode::UNAUTHORIZED
+		);
+		assert_eq!(
+			status("GET", "/admin/queue", Some("s3cret")),
+			StatusCode::OK
+		);
+		assert_eq!(
+			status("DELETE", "/admin/queue", Some("s3cret")),
+			StatusCode::METHOD_NOT_ALLOWED
+		);
+		assert_eq!(
+			status("DELETE", "/admin/queue/substrate/1234", Some("s3cret")),
+			StatusCode::NOT_FOUND
+		);
+		assert_eq!(
+			status(
+				"DELETE",
+				"/admin/queue/paritytech/polkadot/1234",
+				Some("s3cret")
+			),
+			StatusCode::NOT_FOUND
+		);
+		assert_eq!(
+			queue::entries(&futures::executor::block_on(state.lock()).db)
+				.unwrap()
+				.len(),
+			1
+		);
+
+		// a successful delete also comments on the PR, so is not sent through
+		// `webhook` without a GitHub App
+		let state = futures::executor::block_on(state.lock());
+		let (status, body, _) = admin_queue(&state, &Method::GET, "", 1600);
+		assert_eq!(status, StatusCode::OK);
+		let entries: serde_json::Value = serde_json::from_str(&body).unwrap();
+		assert_eq!(entries[0]["sha"], sha);
+		assert_eq!(entries[0]["request"]["number"], 1234);
+		assert_eq!(entries[0]["age_secs"], 600);
+
+		let (status, _, cancelled) = admin_queue(
+			&state,
+			&Method::DELETE,
+			"/paritytech/substrate/1234",
+			1600,
+		);
+		assert_eq!(status, StatusCode::OK);
+		assert_eq!(cancelled, vec![request]);
+		assert!(queue::entries(&state.db).unwrap().is_empty());
+	}
+
+	#[test]
+	fn test_admin_stats() {
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		let mut state = state("admin-stats", None);
+		state.bot_config.error_comment_window = 3600;
+		let sha = "0123456789abcdef0123456789abcdef01234567";
+		let request = |number| MergeRequest {
+			owner: "paritytech".to_string(),
+			repo_name: "substrate".to_string(),
+			number,
+			html_url: String::new(),
+			requested_by: "gavofyork".to_string(),
+			created_at: Some(1000),
+			origin: None,
+			not_before: None,
+		};
+		db::atomically(&state.db, |tx| {
+			queue::store(tx, sha, &request(1))?;
+			queue::store(tx, &sha.replace('0', "f"), &request(2))?;
+			record_outcome(tx, &state, &request(3), MergeResult::Merged)
+		})
+		.unwrap();
+
+		// the merge ends when its checks fail; the error comment was already
+		// posted, so github is not reached
+		let checks_failed = Error::ChecksFailed {
+			commit_sha: sha.to_string(),
+			failed: Vec::new(),
+		};
+		let hash = error_comments::hash(
+			"paritytech",
+			"substrate",
+			1,
+			"ChecksFailed",
+			&ErrorComment::for_error(
+				&checks_failed,
+				&state.bot_config.templates,
+			)
+			.render(),
+		);
+		error_comments::record(&state.db, &hash, Utc::now().timestamp())
+			.unwrap();
+		let error = checks_failed.map_issue(Some((
+			"paritytech".to_string(),
+			"substrate".to_string(),
+			1,
+		)));
+		rt.block_on(handle_error(error, &state, &ErrorContext::default()));
+		assert!(queue::get(&state.db, sha).unwrap().is_none());
+
+		let now = Utc::now().timestamp();
+		let (status, body) = admin_stats(&state, &Method::GET, None, now);
+		assert_eq!(status, StatusCode::OK);
+		let stats: serde_json::Value = serde_json::from_str(&body).unwrap();
+		assert_eq!(stats[0]["repo"], "paritytech/substrate");
+		assert_eq!(stats[0]["merged"], 1);
+		assert_eq!(stats[0]["failed"], 1);
+		assert_eq!(stats[0]["pending"], 1);
+		assert_eq!(stats[1]["repo"], serde_json::Value::Null);
+
+		// the merges ended just now, so are in any period
+		let (_, body) =
+			admin_stats(&state, &Method::GET, Some("period=1d"), now);
+		assert_eq!(
+			serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+			stats
+		);
+		let (status, _) =
+			admin_stats(&state, &Method::GET, Some("period=soon"), now);
+		assert_eq!(status, StatusCode::BAD_REQUEST);
+		let (status, _) = admin_stats(&state, &Method::DELETE, None, now);
+		assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+
+		// not served without a token
+		let readiness = Arc::clone(&state.readiness);
+		let state = Arc::new(Mutex::new(state));
+		let mut status = |token: &str| {
+			let req = Request::builder()
+				.uri("/admin/stats")
+				.header("authorization", format!("Bearer {}", token))
+				.body(Body::empty())
+				.unwrap();
+			rt.block_on(webhook(
+				req,
+				Arc::clone(&state),
+				Arc::clone(&readiness),
+			))
+			.unwrap()
+			.status()
+		};
+		assert_eq!(status("s3cret"), StatusCode::NOT_FOUND);
+		futures::executor::block_on(state.lock()).admin_token =
+			"s3cret".to_string();
+		assert_eq!(status("wrong"), StatusCode::UNAUTHORIZED);
+		assert_eq!(status("s3cret"), StatusCode::OK);
+	}
+
+	#[test]
+	fn test_repeated_error_comment_suppressed() {
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		let mut state = state("error-comments", None);
+		state.bot_config.error_comment_window = 3600;
+		let checks_failed = Error::ChecksFailed {
+			commit_sha: "abc".to_string(),
+			failed: Vec::new(),
+		};
+		let hash = error_comments::hash(
+			"paritytech",
+			"substrate",
+			1,
+			"ChecksFailed",
+			&ErrorComment::for_error(
+				&checks_failed,
+				&state.bot_config.templates,
+			)
+			.render(),
+		);
+		let context = ErrorContext::default();
+		assert!(error_comment_due(&state, &context, &hash));
+
+		error_comments::record(&state.db, &hash, Utc::now().timestamp())
+			.unwrap();
+		assert!(!error_comment_due(&state, &context, &hash));
+		let forced = ErrorContext {
+			command: bot_command(" Bot merge force\n"),
+			..ErrorContext::default()
+		};
+		assert_eq!(forced.command.as_deref(), Some(AUTO_MERGE_FORCE));
+		assert!(error_comment_due(&state, &forced, &hash));
+
+		// suppressed before reaching github
+		let error = checks_failed.map_issue(Some((
+			"paritytech".to_string(),
+			"substrate".to_string(),
+			1,
+		)));
+		rt.block_on(handle_error(error, &state, &context));
+		assert_eq!(state.suppressed_error_comments.load(Ordering::Relaxed), 1);
+	}
+
+	#[test]
+	fn test_project_card_payload() {
+		let card = |content_url: &str| {
+			format!(
+				r#"{{
+					"action": "moved",
+					"changes": {{ "column_id": {{ "from": 1 }} }},
+					"project_card": {{
+						"id": 3,
+						"column_id": 2,
+						"content_url": {}
+					}},
+					"sender": {{ "login": "sjeohp" }}
+				}}"#,
+				content_url
+			)
+		};
+		match serde_json::from_str::<Payload>(&card(
+			r#""https://api.github.com/repos/paritytech/substrate/issues/7000""#,
+		))
+		.unwrap()
+		{
+			Payload::ProjectCard {
+				action: ProjectCardAction::Moved,
+				project_card,
+			} => assert_eq!(
+				project_card.issue(),
+				Some(("paritytech".to_string(), "substrate".to_string(), 7000))
+			),
+			payload => panic!("unexpected payload {:?}", payload),
+		}
+		// a note
+		match serde_json::from_str::<Payload>(&card("null")).unwrap() {
+			Payload::ProjectCard { project_card, .. } => {
+				assert_eq!(project_card.issue(), None)
+			}
+			payload => panic!("unexpected payload {:?}", payload),
+		}
+	}
+
+	#[test]
+	fn test_ignored_pr_is_left_alone() {
+		let state = state("ignored", None);
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		let repository = Repository {
+			name: "substrate".to_string(),
+			owner: User {
+				login: "paritytech".to_string(),
+				..User::default()
+			},
+			..Repository::default()
+		};
+		let pr = PullRequest {
+			number: 1,
+			html_url: "https://github.com/paritytech/substrate/pull/1"
+				.to_string(),
+			state: Some("open".to_string()),
+			body: Some("polkadot companion: paritytech/polkadot#2".to_string()),
+			..PullRequest::default()
+		};
+		let payload = |action, label: Option<&str>| Payload::PullRequest {
+			action,
+			number: 1,
+			pull_request: pr.clone(),
+			repository: repository.clone(),
+			sender: User::default(),
+			label: label.map(|name| Label {
+				name: name.to_string(),
+				..Label::default()
+			}),
+		};
+		// each would ask GitHub for something, which panics in tests
+		let automations = |rt: &mut tokio::runtime::Runtime| {
+			vec![
+				rt.block_on(handle_payload(
+					payload(PullRequestAction::Opened, None),
+					&state,
+				)),
+				rt.block_on(handle_payload(
+					payload(PullRequestAction::Synchronized, None),
+					&state,
+				)),
+				rt.block_on(handle_payload(
+					payload(PullRequestAction::Labeled, Some("A1-needsburnin")),
+					&state,
+				)),
+				rt.block_on(update_companion(&state, "substrate", &pr)),
+			]
+		};
+		ignore::ignore(&state.db, "paritytech", "substrate", 1).unwrap();
+		for result in automations(&mut rt) {
+			assert!(result.is_ok(), "{:?}", result);
+		}
+		// the flag is checked before the PR is fetched
+		assert!(rt
+			.block_on(request_process_reviews(
+				&pr,
+				"paritytech",
+				"substrate",
+				&state
+			))
+			.is_ok());
+		assert!(rt
+			.block_on(update_labelled_branch(
+				AutoUpdate::new("paritytech", "substrate", 1, "master"),
+				0,
+				&state
+			))
+			.is_ok());
+
+		ignore::unignore(&state.db, "paritytech", "substrate", 1).unwrap();
+		assert!(
+			!ignore::skips(&state.db, "paritytech", "substrate", 1, "all")
+				.unwrap()
+		);
+	}
+
+	/// A comment on issue 1, which is not a PR, or an edit of it from
+	/// `edited_from`.  The repository is not known unless `known`.
+	fn issue_comment(
+		body: &str,
+		edited_from: Option<&str>,
+		known: bool,
+	) -> Payload {
+		let (html_url, repository_url) = if known {
+			(
+				"https://github.com/paritytech/substrate/issues/1",
+				"https://api.github.com/repos/paritytech/substrate",
+			)
+		} else {
+			("not a url", "not a url")
+		};
+		Payload::IssueComment {
+			action: if edited_from.is_some() {
+				IssueCommentAction::Edited
+			} else {
+				IssueCommentAction::Created
+			},
+			issue: Issue {
+				number: 1,
+				html_url: html_url.to_string(),
+				repository_url: Some(repository_url.to_string()),
+				pull_request: None,
+				..Issue::default()
+			},
+			comment: Comment {
+				id: 2,
+				body: body.to_string(),
+				user: User {
+					login: "gavofyork".to_string(),
+					..User::default()
+				},
+				node_id: None,
+				url: None,
+				html_url: None,
+				created_at: Utc::now(),
+				updated_at: Utc::now(),
+			},
+			changes: edited_from.map(|from| CommentChanges {
+				body: Some(ChangedFrom {
+					from: from.to_string(),
+				}),
+			}),
+		}
+	}
+
+	/// Whether the payload was answered, which, as the repository is not
+	/// known, fails before GitHub is asked anything.
+	fn answered(
+		rt: &mut tokio::runtime::Runtime,
+		payload: Payload,
+		state: &AppState,
+	) -> bool {
+		match rt.block_on(handle_payload(payload, state)) {
+			Ok(()) => false,
+			Err(Error::Message { msg }) => {
+				assert!(msg.contains("Failed parsing repository"), "{}", msg);
+				true
+			}
+			result => panic!("unexpected result {:?}", result),
+		}
+	}
+
+	#[test]
+	fn test_comment_on_issue() {
+		let state = state("issue-comment", None);
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		// neither the issue nor GitHub is looked at
+		for body in &["LGTM", "the bot merge failed", ""] {
+			assert!(rt
+				.block_on(handle_payload(
+					issue_comment(body, None, true),
+					&state
+				))
+				.is_ok());
+		}
+		assert!(answered(
+			&mut rt,
+			issue_comment("bot merge", None, false),
+			&state
+		));
+	}
+
+	#[test]
+	fn test_edited_comment() {
+		let state = state("edited-comment", None);
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		// a typo fixed
+		assert!(answered(
+			&mut rt,
+			issue_comment("bot merge", Some("bot mrege"), false),
+			&state
+		));
+		assert!(answered(
+			&mut rt,
+			issue_comment("bot help", Some("LGTM"), false),
+			&state
+		));
+		for (from, body) in &[
+			// edited again, or reworded
+			("bot merge", "bot merge"),
+			("bot merge", " Bot Merge\n"),
+			// another command
+			("bot merge", "bot merge cancel"),
+			// the command removed
+			("bot merge", "LGTM"),
+			("bot mrege", "LGTM"),
+		] {
+			assert!(
+				!answered(
+					&mut rt,
+					issue_comment(body, Some(from), false),
+					&state
+				),
+				"{:?} to {:?}",
+				from,
+				body
+			);
+		}
+	}
+
+	#[test]
+	fn test_command_prefixes() {
+		let mut state = state("command-prefixes", None);
+		state.bot_config.command_prefix = "/".to_string();
+		state.bot_config.command_aliases = vec!["processbot".to_string()];
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		for body in &["/merge", "/ merge", "ProcessBot merge", "/help"] {
+			assert!(
+				answered(&mut rt, issue_comment(body, None, false), &state),
+				"{}",
+				body
+			);
+		}
+		// a prefix not configured
+		for body in &["bot merge", "bot help", "processbotmerge"] {
+			assert!(
+				!answered(&mut rt, issue_comment(body, None, false), &state),
+				"{}",
+				body
+			);
+		}
+		// fixed to the configured prefix
+		assert!(answered(
+			&mut rt,
+			issue_comment("/merge", Some("bot merge"), false),
+			&state
+		));
+		assert!(!answered(
+			&mut rt,
+			issue_comment("processbot merge", Some("/merge"), false),
+			&state
+		));
+	}
+
+	#[test]
+	fn test_is_new_comment() {
+		let changes = |from: &str| CommentChanges {
+			body: Some(ChangedFrom {
+				from: from.to_string(),
+			}),
+		};
+		let edited = IssueCommentAction::Edited;
+		assert!(is_new_comment(&IssueCommentAction::Created, None, "LGTM"));
+		assert!(is_new_comment(
+			&edited,
+			Some(&changes("bot mrege")),
+			"bot merge"
+		));
+		assert!(!is_new_comment(
+			&edited,
+			Some(&changes("bot merge")),
+			"bot merge"
+		));
+		assert!(!is_new_comment(
+			&edited,
+			Some(&changes("bot merge")),
+			"LGTM"
+		));
+		// the body was not edited
+		assert!(!is_new_comment(&edited, None, "bot merge"));
+		assert!(!is_new_comment(
+			&edited,
+			Some(&CommentChanges::default()),
+			"bot merge"
+		));
+		assert!(!is_new_comment(
+			&IssueCommentAction::Deleted,
+			None,
+			"bot merge"
+		));
+	}
+
+	#[test]
+	fn test_edited_comment_payload() {
+		let payload = serde_json::from_value::<Payload>(serde_json::json!({
+			"action": "edited",
+			"issue": serde_json::to_value(Issue::default()).unwrap(),
+			"comment": {
+				"id": 2,
+				"body": "bot merge",
+				"user": serde_json::to_value(User {
+					login: "gavofyork".to_string(),
+					..User::default()
+				})
+				.unwrap(),
+				"created_at": "2021-01-01T00:00:00Z",
+				"updated_at": "2021-01-01T00:01:00Z"
+			},
+			"changes": { "body": { "from": "bot mrege" } }
+		}))
+		.unwrap();
+		match payload {
+			Payload::IssueComment {
+				action: IssueCommentAction::Edited,
+				changes: Some(CommentChanges { body: Some(old) }),
 				..
+			} => assert_eq!(old.from, "bot mrege"),
+			payload => panic!("unexpected payload {:?}", payload),
+		}
+	}
+
+	#[test]
+	fn test_merge_gate_status_is_ignored() {
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		let state = state("merge-gate-status", None);
+		let request = MergeRequest {
+			owner: "paritytech".to_string(),
+			repo_name: "substrate".to_string(),
+			number: 1,
+			html_url: "https://github.com/paritytech/substrate/pull/1"
+				.to_string(),
+			requested_by: "gavofyork".to_string(),
+			created_at: Some(100),
+			origin: None,
+			not_before: None,
+		};
+		db::atomically(&state.db, |tx| queue::store(tx, "abc", &request))
+			.unwrap();
+		// any other status of a commit with a pending merge asks GitHub about
+		// it, which it cannot here
+		for status in &[StatusState::Success, StatusState::Failure] {
+			let payload = Payload::CommitStatus {
+				sha: "abc".to_string(),
+				state: *status,
+				context: merge_gate::CONTEXT.to_string(),
+				description: "allowed to merge once checks pass".to_string(),
+				target_url: String::new(),
+				repository: Repository::default(),
+				branches: vec![],
+			};
+			assert!(rt.block_on(handle_payload(payload, &state)).is_ok());
+		}
+		assert!(queue::get(&state.db, "abc").unwrap().is_some());
+		assert_eq!(state.skipped_own_events.load(Ordering::Relaxed), 2);
+	}
+
+	#[test]
+	fn test_scheduled_merge_waits() {
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		let state = state("scheduled-merge", None);
+		let now = Utc::now().timestamp();
+		let request = |not_before| MergeRequest {
+			owner: "paritytech".to_string(),
+			repo_name: "substrate".to_string(),
+			number: 1,
+			html_url: "https://github.com/paritytech/substrate/pull/1"
+				.to_string(),
+			requested_by: "gavofyork".to_string(),
+			created_at: Some(now),
+			origin: None,
+			not_before,
+		};
+		db::atomically(&state.db, |tx| {
+			queue::store(tx, "abc", &request(Some(now + 3600)))
+		})
+		.unwrap();
+		// a merge which is not due is not looked at, which would ask GitHub
+		// about it
+		let payload = Payload::CommitStatus {
+			sha: "abc".to_string(),
+			state: StatusState::Success,
+			context: "continuous-integration/gitlab-test".to_string(),
+			description: String::new(),
+			target_url: String::new(),
+			repository: Repository::default(),
+			branches: vec![],
+		};
+		assert!(rt.block_on(handle_payload(payload, &state)).is_ok());
+		rt.block_on(merge_due(&state, now));
+		assert_eq!(
+			pending_merge(&state, "abc").unwrap(),
+			Some(request(Some(now + 3600)))
+		);
+	}
+
+	#[test]
+	fn test_scheduled_merge_gates() {
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		let mut state = state("scheduled-merge-gates", None);
+		state.bot_config.confirm_merge_lines = 1000;
+		state.bot_config.confirm_merge_window = 600;
+		unreachable_github(&mut state);
+		let pr = PullRequest {
+			number: 1,
+			html_url: "https://github.com/paritytech/substrate/pull/1"
+				.to_string(),
+			head: Head {
+				sha: "abc".to_string(),
+				..Head::default()
 			},
-		..
-	} = pr.clone()
-	{
-		match performance::regression(
-			github_bot,
-			owner,
-			&repo_name,
-			&head_owner,
-			&head_repo,
-			&head_branch,
-		)
-		.await
-		{
-			Ok(Some(reg)) => {
-				if reg > 2. {
-					log::error!("Performance regression shows factor {} change in benchmark average.", reg);
-					Err(Error::Message {
-							msg: format!("Performance regression shows greater than 2x increase in benchmark average; aborting merge."),
-						}
-						.map_issue(Some((
-							owner.to_string(),
-							repo_name.to_string(),
-							pr.number,
-						))))?;
-				}
-			}
-			Ok(None) => {
-				log::error!("Failed to complete performance regression.");
-				let _ = github_bot
-					.create_issue_comment(owner, &repo_name, pr.number, "Failed to complete performance regression; see logs; continuing merge.")
-					.await
-					.map_err(|e| {
-						log::error!("Error posting comment: {}", e);
-					});
-			}
-			Err(e) => {
-				log::error!("Error running performance regression: {}", e);
-				let _ = github_bot
-					.create_issue_comment(owner, &repo_name, pr.number, "Error running performance regression; see logs; continuing merge.")
-					.await
-					.map_err(|e| {
-						log::error!("Error posting comment: {}", e);
-					});
+			additions: Some(2000),
+			deletions: Some(0),
+			..PullRequest::default()
+		};
+		let at = Utc::now() + chrono::Duration::hours(1);
+		let schedule = || {
+			handle_scheduled_merge(
+				at,
+				&pr,
+				"paritytech",
+				"gavofyork",
+				"substrate",
+				&state.bot_config,
+				&state,
+			)
+		};
+
+		// a merge in progress is not overwritten, whatever its size; saying
+		// so on the PR fails
+		let request = merge_request("paritytech", "substrate", &pr, "rphmeier");
+		let now = Utc::now().timestamp();
+		in_flight::mark(&state.db, &request, now).unwrap();
+		assert!(rt.block_on(Box::pin(schedule())).is_err());
+		assert_eq!(queue::get(&state.db, "abc").unwrap(), None);
+		assert_eq!(
+			merge_confirmation::confirm(
+				&state.db,
+				"paritytech",
+				"substrate",
+				1,
+				"gavofyork",
+				"abc",
+				now
+			)
+			.unwrap(),
+			Confirmation::Missing
+		);
+		in_flight::clear_all(&state.db).unwrap();
+
+		// a large PR waits for the confirmation, which keeps the time
+		assert!(rt.block_on(Box::pin(schedule())).is_err());
+		assert_eq!(queue::get(&state.db, "abc").unwrap(), None);
+		assert_eq!(
+			merge_confirmation::confirm(
+				&state.db,
+				"paritytech",
+				"substrate",
+				1,
+				"gavofyork",
+				"abc",
+				now
+			)
+			.unwrap(),
+			Confirmation::Confirmed {
+				skip_bench: false,
+				not_before: Some(at.timestamp())
 			}
+		);
+	}
+
+	#[test]
+	fn test_due_merge_is_evaluated_again() {
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		let mut state = state("due-merge", None);
+		state.bot_config.blocked_labels = vec!["A3-in_progress".to_string()];
+		unreachable_github(&mut state);
+		let now = Utc::now().timestamp();
+		let pr = PullRequest {
+			number: 1,
+			html_url: "https://github.com/paritytech/substrate/pull/1"
+				.to_string(),
+			head: Head {
+				sha: "abc".to_string(),
+				..Head::default()
+			},
+			labels: vec![Label {
+				name: "A3-in_progress".to_string(),
+				..Label::default()
+			}],
+			..PullRequest::default()
+		};
+		let request = MergeRequest {
+			not_before: Some(now - 60),
+			..merge_request("paritytech", "substrate", &pr, "gavofyork")
+		};
+		db::atomically(&state.db, |tx| queue::store(tx, "abc", &request))
+			.unwrap();
+		// the PR cannot be fetched, so the merge waits
+		rt.block_on(merge_due(&state, now));
+		assert_eq!(
+			pending_merge(&state, "abc").unwrap(),
+			Some(request.clone())
+		);
+		// labelled as blocked since it was scheduled
+		match rt.block_on(recheck_merge_allowed(
+			&state,
+			&state.bot_config,
+			&request,
+			"abc",
+			&pr,
+			MergeIntent::Merge,
+		)) {
+			Err(Error::WithIssue { source, .. }) => match *source {
+				Error::BlockedLabel { label } => {
+					assert_eq!(label, "A3-in_progress")
+				}
+				e => panic!("unexpected error {:?}", e),
+			},
+			result => panic!("unexpected result {:?}", result),
 		}
+		assert_eq!(pending_merge(&state, "abc").unwrap(), None);
 	}
-	Ok(())
-}
 
-/// Check for a Polkadot companion and update it if found.
-async fn update_companion(
-	github_bot: &GithubBot,
-	repo_name: &str,
-	pr: &PullRequest,
-	db: &DB,
-) -> Result<()> {
-	if repo_name == "substrate" {
-		log::info!("Checking for companion.");
-		if let Some(body) = &pr.body {
-			// check for link in pr body
-			if let Some((comp_html_url, comp_owner, comp_repo, comp_number)) =
-				companion_parse(&body)
-			{
-				log::info!("Found companion {}", comp_html_url);
-				let comp_pr = github_bot
-					.pull_request(&comp_owner, &comp_repo, comp_number)
-					.await
-					.map_err(|e| {
-						e.map_issue(Some((
-							comp_owner.to_string(),
-							comp_repo.to_string(),
-							comp_number,
-						)))
-					})?;
+	#[test]
+	fn test_own_events_are_skipped() {
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		let state = state("own-events", None);
+		let skipped = || state.skipped_own_events.load(Ordering::Relaxed);
+		own_activity::record_push(&state.db, "abc", Utc::now().timestamp())
+			.unwrap();
+		let check_run = |sha: &str, sender: &str| Payload::CheckRun {
+			action: CheckRunAction::Created,
+			check_run: CheckRun {
+				id: 1,
+				name: "build".to_string(),
+				status: "in_progress".to_string(),
+				conclusion: None,
+				head_sha: sha.to_string(),
+				pull_requests: vec![],
+				html_url: None,
+				started_at: None,
+			},
+			sender: Some(Sender {
+				login: sender.to_string(),
+			}),
+		};
+		let handle = |rt: &mut tokio::runtime::Runtime, payload| {
+			rt.block_on(handle_payload(payload, &state)).unwrap()
+		};
 
-				if let PullRequest {
-					head:
-						Head {
-							ref_field: comp_head_branch,
-							repo:
-								HeadRepo {
-									name: comp_head_repo,
-									owner:
-										Some(User {
-											login: comp_head_owner,
-											..
-										}),
-									..
-								},
-							..
-						},
-					..
-				} = comp_pr.clone()
-				{
-					log::info!("Updating companion {}", comp_html_url);
-					if let Some(updated_sha) = companion_update(
-						github_bot,
-						&comp_owner,
-						&comp_repo,
-						&comp_head_owner,
-						&comp_head_repo,
-						&comp_head_branch,
-					)
-					.await
-					.map_err(|e| {
-						Error::Companion {
-							source: Box::new(e),
-						}
-						.map_issue(Some((
-							comp_owner.to_string(),
-							comp_repo.to_string(),
-							comp_number,
-						)))
-					})? {
-						log::info!(
-							"Companion updated; waiting for checks on {}",
-							comp_html_url
-						);
+		handle(&mut rt, check_run("abc", "Parity-Processbot[bot]"));
+		assert_eq!(skipped(), 1);
+		// others' checks of the same commit, and the bot's of commits it did
+		// not push
+		handle(&mut rt, check_run("abc", "github-actions[bot]"));
+		handle(&mut rt, check_run("def", "parity-processbot[bot]"));
+		assert_eq!(skipped(), 1);
 
-						// wait for checks on the update commit
-						wait_to_merge(
-							github_bot,
-							&comp_owner,
-							&comp_repo,
-							comp_pr.number,
-							&comp_pr.html_url,
-							&format!("parity-processbot[bot]"),
-							&updated_sha,
-							db,
-						)
-						.await?;
-					} else {
-						log::info!(
-							"Failed updating companion {}",
-							comp_html_url
-						);
+		let mut comment = issue_comment("bot merge", None, false);
+		if let Payload::IssueComment { comment, .. } = &mut comment {
+			comment.user.login = "parity-processbot[bot]".to_string();
+		}
+		handle(&mut rt, comment);
+		assert_eq!(skipped(), 2);
+		assert!(answered(
+			&mut rt,
+			issue_comment("bot merge", None, false),
+			&state
+		));
+		assert_eq!(skipped(), 2);
+	}
 
-						Err(Error::Message {
-							msg: format!("Failed updating substrate."),
-						}
-						.map_issue(Some((
-							comp_owner.to_string(),
-							comp_repo.to_string(),
-							comp_number,
-						))))?;
-					}
-				} else {
-					Err(Error::Companion {
-						source: Box::new(Error::Message {
-							msg: format!(
-								"Companion PR is missing required fields."
-							),
-						}),
-					}
-					.map_issue(Some((
-						comp_owner.to_string(),
-						comp_repo.to_string(),
-						comp_number,
-					))))?;
-				}
-			} else {
-				log::info!("No companion found.");
+	#[test]
+	fn test_commit_status_payload() {
+		let payload: Payload = serde_json::from_value(serde_json::json!({
+			"sha": "abc",
+			"state": "success",
+			"context": merge_gate::CONTEXT,
+			"description": "allowed to merge once checks pass",
+			"target_url": "",
+			"repository": Repository::default(),
+			"branches": [],
+		}))
+		.unwrap();
+		match payload {
+			Payload::CommitStatus { context, .. } => {
+				assert!(own_activity::is_own_context(&context))
 			}
-		} else {
-			log::info!("No PR body found.");
+			payload => panic!("unexpected payload {:?}", payload),
 		}
 	}
 
-	Ok(())
-}
-
-/// Distinguish required statuses.
-#[allow(dead_code)]
-fn status_failure_allowed(ci: &str, context: &str) -> bool {
-	let d: serde_yaml::Result<serde_yaml::Value> = serde_yaml::from_str(ci);
-	match d {
-		Ok(v) => v[context]["allow_failure"].as_bool().unwrap_or(false),
-		Err(e) => {
-			log::error!("Error parsing value from ci yaml: {}", e);
-			false
+	#[test]
+	fn test_review_payload() {
+		let payload: Payload = serde_json::from_value(serde_json::json!({
+			"action": "submitted",
+			"review": { "id": 3, "state": "approved" },
+			"pull_request": PullRequest {
+				number: 1,
+				..PullRequest::default()
+			},
+			"repository": Repository::default(),
+		}))
+		.unwrap();
+		match payload {
+			Payload::PullRequestReview {
+				action: PullRequestReviewAction::Submitted,
+				pull_request,
+				..
+			} => assert_eq!(pull_request.number, 1),
+			payload => panic!("unexpected payload {:?}", payload),
 		}
 	}
-}
 
-const TROUBLESHOOT_MSG: &str = "Merge can be attempted if:\n- The PR has approval from two core-devs (or one if the PR is labelled insubstantial).\n- The PR has approval from a member of `substrateteamleads`.\n- The PR is attached to a project column and has approval from the project owner.\n\nSee https://github.com/paritytech/parity-processbot#faq";
+	#[test]
+	fn test_recover_interrupted_merge() {
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		// polkadot has no companions, so updating them asks GitHub nothing
+		let request = MergeRequest {
+			owner: "paritytech".to_string(),
+			repo_name: "polkadot".to_string(),
+			number: 1,
+			html_url: "https://github.com/paritytech/polkadot/pull/1"
+				.to_string(),
+			requested_by: "gavofyork".to_string(),
+			created_at: Some(100),
+			origin: None,
+			not_before: None,
+		};
+		let pr = |merged| PullRequest {
+			number: 1,
+			html_url: request.html_url.clone(),
+			merged: Some(merged),
+			..PullRequest::default()
+		};
+		let cases = [
+			(Phase::Merge, false, Recovery::RollBack),
+			(Phase::Merge, true, Recovery::Finish),
+			(Phase::Cleanup, true, Recovery::Finish),
+			(Phase::Companion, true, Recovery::Finish),
+		];
+		for (i, (phase, merged, recovery)) in cases.iter().enumerate() {
+			let state = state(&format!("recover-{}", i), None);
+			let db = &state.db;
+			db::atomically(db, |tx| queue::store(tx, "abc", &request)).unwrap();
+			// cleaning up was done before the companion phase
+			if *phase == Phase::Companion {
+				db::atomically(db, |tx| queue::delete(tx, "abc")).unwrap();
+			}
+			let entry = JournalEntry {
+				request: request.clone(),
+				head_sha: "abc".to_string(),
+				queued: true,
+				phase: *phase,
+			};
+			// interrupted by a restart
+			journal::record(db, &entry).unwrap();
 
-async fn handle_error(e: Error, state: &AppState) {
-	log::error!("{}", e);
-	match e {
-		Error::WithIssue {
-			source,
-			issue: Some((owner, repo, number)),
-			..
-		} => {
-			let msg = match *source {
-				Error::Companion { source } => {
-					format!("Error updating substrate: {}", *source)
+			let comment = rt
+				.block_on(recover_merge(&state, entry.clone(), &pr(*merged)))
+				.unwrap();
+			assert_eq!(comment, journal::recovery_comment(&entry, *recovery));
+			assert!(journal::entries(db).unwrap().is_empty());
+			let outcomes = stats::outcomes(db, 0).unwrap();
+			match recovery {
+				Recovery::RollBack => {
+					// left to be attempted again
+					assert!(queue::get(db, "abc").unwrap().is_some());
+					assert!(outcomes.is_empty());
 				}
-				Error::Merge { source, commit_sha } => {
-					// clean db
-					let _ =
-						state.db.delete(commit_sha.as_bytes()).map_err(|e| {
-							log::error!(
-								"Error deleting merge request from db: {}",
-								e
-							);
-						});
-					match *source {
-						Error::Response {
-							body: serde_json::Value::Object(m),
-							..
-						} => format!("Merge failed: `{}`", m["message"]),
-						Error::Http { source, .. } => format!(
-							"Merge failed due to network error:\n\n{}",
-							source
-						),
-						e => format!(
-							"Merge failed due to unexpected error:\n\n{}",
-							e
-						),
-					}
+				Recovery::Finish => {
+					assert!(queue::get(db, "abc").unwrap().is_none());
+					// recorded by the cleanup phase, whichever ran it
+					let expected =
+						if *phase == Phase::Companion { 0 } else { 1 };
+					assert_eq!(outcomes.len(), expected, "{:?}", phase);
 				}
-				Error::ProcessFile { source } => match *source {
-					Error::Response {
-						body: serde_json::Value::Object(m),
-						..
-					} => format!(
-						"Error getting Process.json: `{}`",
-						m["message"]
-					),
-					Error::Http { source, .. } => format!(
-						"Network error getting Process.json:\n\n{}",
-						source
-					),
-					e => format!(
-						"Unexpected error getting Process.json:\n\n{}",
-						e
-					),
+			}
+		}
+	}
+
+	/// Two `bot merge` commented back to back on a PR whose merge mark a
+	/// crash left behind: the mark is cleared on recovery, the first command
+	/// is handled and the second skipped.
+	/// Make requests to GitHub fail at once, as if it could not be reached.
+	fn unreachable_github(state: &mut AppState) {
+		let client = &mut state.github_bot.client;
+		client.client = reqwest::Client::builder()
+			.proxy(reqwest::Proxy::all("http://127.0.0.1:9").unwrap())
+			.build()
+			.unwrap();
+		client.set_retry_policy(crate::http::RetryPolicy {
+			max_retries: 0,
+			..Default::default()
+		});
+		client.set_token(
+			"paritytech",
+			Utc::now() + chrono::Duration::minutes(40),
+			"ghs_abc",
+		);
+	}
+
+	#[test]
+	fn test_rebase_onto_needs_permission() {
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		let mut state = state("rebase-onto", None);
+		state.effects = Effects::new(true);
+		// neither the permission nor the membership of the commenter can be
+		// read
+		unreachable_github(&mut state);
+		let pr = PullRequest {
+			number: 1,
+			user: User {
+				login: "alice".to_string(),
+				..User::default()
+			},
+			head: Head {
+				ref_field: "alice-patch".to_string(),
+				sha: "abc".to_string(),
+				repo: HeadRepo {
+					name: "substrate".to_string(),
+					owner: Some(User {
+						login: "paritytech".to_string(),
+						..User::default()
+					}),
+					..HeadRepo::default()
 				},
-				Error::ProcessInfo {} => {
-					format!("Missing process info; check that the PR belongs to a project column.\n\n{}", TROUBLESHOOT_MSG)
-				}
-				Error::Approval {} => {
-					format!("Missing approval from the project owner or a minimum of core developers.\n\n{}", TROUBLESHOOT_MSG)
-				}
-				Error::HeadChanged { commit_sha } => {
-					// clean db
-					let _ =
-						state.db.delete(commit_sha.as_bytes()).map_err(|e| {
-							log::error!(
-								"Error deleting merge request from db: {}",
-								e
-							);
-						});
-					format!("Head SHA changed; merge aborted.")
-				}
-				Error::ChecksFailed { commit_sha } => {
-					// clean db
-					let _ =
-						state.db.delete(commit_sha.as_bytes()).map_err(|e| {
-							log::error!(
-								"Error deleting merge request from db: {}",
-								e
-							);
-						});
-					format!("Checks failed; merge aborted.")
-				}
-				Error::OrganizationMembership { source } => {
-					format!("Error getting organization membership: {}", source)
+				..Head::default()
+			},
+			..PullRequest::default()
+		};
+		let result = rt.block_on(handle_rebase_command(
+			rebase::RebaseCommand {
+				onto: Some("v0.9".to_string()),
+			},
+			&pr,
+			"paritytech",
+			"mallory",
+			"substrate",
+			&state.bot_config,
+			&state,
+		));
+		match result {
+			Err(Error::WithIssue { source, issue }) => {
+				assert_eq!(
+					issue,
+					Some((
+						"paritytech".to_string(),
+						"substrate".to_string(),
+						1
+					))
+				);
+				match *source {
+					Error::OrganizationMembership { .. } => {}
+					e => panic!("unexpected error {:?}", e),
 				}
-				Error::Message { msg } => format!("{}", msg),
-				Error::Response {
-					body: serde_json::Value::Object(m),
-					..
-				} => format!("Error: `{}`", m["message"]),
-				_ => "Unexpected error; see logs.".to_string(),
-			};
-			let _ = state
-				.github_bot
-				.create_issue_comment(&owner, &repo, number, &msg)
-				.await
-				.map_err(|e| {
-					log::error!("Error posting comment: {}", e);
-				});
+			}
+			result => panic!("unexpected result {:?}", result),
 		}
-		_ => {}
+		// neither rebased nor moved onto the branch
+		assert!(state.effects.take_plan().is_empty());
+	}
+
+	#[test]
+	fn test_back_to_back_merge_commands() {
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		let mut state = state("back-to-back", None);
+		state.bot_config.command_cooldowns = cooldown::default_cooldowns();
+		unreachable_github(&mut state);
+		let request = MergeRequest {
+			owner: "paritytech".to_string(),
+			repo_name: "substrate".to_string(),
+			number: 1,
+			html_url: "https://github.com/paritytech/substrate/pull/1"
+				.to_string(),
+			requested_by: "gavofyork".to_string(),
+			created_at: Some(100),
+			origin: None,
+			not_before: None,
+		};
+		let now = Utc::now().timestamp();
+		in_flight::mark(&state.db, &request, now).unwrap();
+		rt.block_on(recover_merges(&state));
+		assert_eq!(
+			in_flight::marker(&state.db, "paritytech", "substrate", 1, now)
+				.unwrap(),
+			None
+		);
+
+		let merge = || {
+			let mut payload = issue_comment("bot merge", None, true);
+			if let Payload::IssueComment { issue, .. } = &mut payload {
+				issue.pull_request = Some(IssuePullRequest::default());
+			}
+			payload
+		};
+		// handled as far as fetching the PR
+		match rt.block_on(Box::pin(handle_payload(merge(), &state))) {
+			Err(Error::WithIssue { issue, .. }) => assert_eq!(
+				issue,
+				Some(("paritytech".to_string(), "substrate".to_string(), 1))
+			),
+			result => panic!("unexpected result {:?}", result),
+		}
+		// within the cooldown of the first
+		assert!(rt
+			.block_on(Box::pin(handle_payload(merge(), &state)))
+			.is_ok());
+		assert!(journal::entries(&state.db).unwrap().is_empty());
+	}
+
+	#[test]
+	fn test_installation_webhooks() {
+		let state = state("installation", None);
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		let payload = |action: &str, id: i64| {
+			serde_json::from_value::<Payload>(serde_json::json!({
+				"action": action,
+				"installation": {
+					"id": id,
+					"account": { "login": "ParityTech-Stg", "id": 7 },
+					"app_id": 1
+				},
+				"repositories_added": [],
+				"sender": { "login": "gavofyork" }
+			}))
+			.unwrap()
+		};
+		assert_eq!(
+			payload("created", 1),
+			Payload::Installation {
+				action: InstallationAction::Created,
+				installation: AppInstallation {
+					id: 1,
+					account: InstallationAccount {
+						login: "ParityTech-Stg".to_string()
+					}
+				}
+			}
+		);
+		let client = &state.github_bot.client;
+		rt.block_on(handle_payload(payload("created", 1), &state))
+			.unwrap();
+		assert_eq!(client.installation_id("paritytech-stg"), Some(1));
+		// repositories were added to a reinstalled app
+		rt.block_on(handle_payload(payload("added", 2), &state))
+			.unwrap();
+		assert_eq!(client.installation_id("paritytech-stg"), Some(2));
+		assert_eq!(
+			installations::all(&state.db).unwrap(),
+			vec![("paritytech-stg".to_string(), 2)]
+		);
+		rt.block_on(handle_payload(payload("deleted", 2), &state))
+			.unwrap();
+		assert_eq!(client.installation_id("paritytech-stg"), None);
+		assert!(installations::all(&state.db).unwrap().is_empty());
+	}
+
+	#[test]
+	fn test_no_sink() {
+		let state = state("no-sink", None);
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		rt.block_on(handle_error(
+			Error::Message {
+				msg: "Missing x-hub-signature".to_string(),
+			},
+			&state,
+			&ErrorContext::default(),
+		));
+	}
+
+	#[test]
+	fn test_quarantine_pending_merge() {
+		let state = state("quarantine", None);
+		let sha = "0123456789abcdef0123456789abcdef01234567";
+		db::family(&state.db, db::MERGE_REQUESTS)
+			.unwrap()
+			.put(sha.as_bytes(), b"garbage")
+			.unwrap();
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		// as if no merge were pending, every time
+		rt.block_on(checks_and_status(&state, sha)).unwrap();
+		rt.block_on(checks_and_status(&state, sha)).unwrap();
+		assert!(queue::get(&state.db, sha).unwrap().is_none());
+		let quarantined = quarantine::list(&state.db).unwrap();
+		assert_eq!(quarantined.len(), 1);
+		assert_eq!(quarantined[0].key, sha);
+		assert_eq!(quarantined[0].value, base64::encode("garbage"));
+		assert_eq!(state.quarantined_merge_requests.load(Ordering::Relaxed), 1);
+	}
+
+	#[test]
+	fn test_companion_merge_request() {
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		let state = state("companion-origin", None);
+		let pr = PullRequest {
+			number: 1000,
+			html_url: "https://github.com/paritytech/substrate/pull/1000"
+				.to_string(),
+			..PullRequest::default()
+		};
+		let comp_pr = PullRequest {
+			number: 1234,
+			html_url: "https://github.com/paritytech/polkadot/pull/1234"
+				.to_string(),
+			..PullRequest::default()
+		};
+		let request = companion_merge_request(
+			"paritytech",
+			"polkadot",
+			&comp_pr,
+			"substrate",
+			&pr,
+		);
+		assert_eq!(
+			(request.repo_name.as_str(), request.number),
+			("polkadot", 1234)
+		);
+		assert_eq!(request.requested_by, "parity-processbot[bot]");
+		assert_eq!(
+			request.origin,
+			Some(MergeOrigin {
+				owner: "paritytech".to_string(),
+				repo_name: "substrate".to_string(),
+				number: 1000,
+				html_url: pr.html_url.clone(),
+			})
+		);
+		// kept with the pending merge of the companion
+		rt.block_on(create_merge_request(&request, "abc", &state.db))
+			.unwrap();
+		assert_eq!(pending_merge(&state, "abc").unwrap(), Some(request));
 	}
 }
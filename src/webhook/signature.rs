@@ -0,0 +1,275 @@
+use futures::future::{self, BoxFuture, FutureExt};
+use hyper::HeaderMap;
+use ring::hmac;
+use snafu::OptionExt;
+
+use crate::{error::*, health::StartupCheck, Result};
+
+/// The signature headers GitHub sends, the strongest first, with the prefix
+/// of their values.
+const HEADERS: [(&str, &str); 2] = [
+	("x-hub-signature-256", "sha256="),
+	("x-hub-signature", "sha1="),
+];
+
+fn algorithm(header: &str) -> hmac::Algorithm {
+	if header == HEADERS[0].0 {
+		hmac::HMAC_SHA256
+	} else {
+		hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY
+	}
+}
+
+/// The webhook secret as it is used, without the whitespace around it, eg. a
+/// trailing newline in a mounted file.  Refused if empty; whitespace within it
+/// is kept, but warned about.
+pub fn normalize_secret(secret: &str) -> std::result::Result<String, String> {
+	let trimmed = secret.trim();
+	if trimmed.is_empty() {
+		return Err("WEBHOOK_SECRET is empty".to_string());
+	}
+	for warning in suspicious(secret) {
+		log::warn!("WEBHOOK_SECRET {}", warning);
+	}
+	Ok(trimmed.to_string())
+}
+
+/// What looks wrong with a secret, eg. whitespace copied along with it.
+fn suspicious(secret: &str) -> Vec<&'static str> {
+	let trimmed = secret.trim();
+	let mut warnings = Vec::new();
+	if trimmed.len() != secret.len() {
+		warnings.push("has whitespace around it, which is ignored");
+	}
+	if trimmed.contains(char::is_whitespace) {
+		warnings.push("contains whitespace, which is kept");
+	}
+	warnings
+}
+
+/// Check the signature GitHub sent with a webhook body: the SHA-256 one if
+/// there is one, else the SHA-1 one.  A missing or malformed header and a
+/// signature which does not match fail with different messages, as the first
+/// two point at a proxy or GitHub's configuration, the last at the secret or
+/// a forged request.
+pub fn verify(secret: &[u8], headers: &HeaderMap, body: &[u8]) -> Result<()> {
+	let (header, prefix, value) = HEADERS
+		.iter()
+		.find_map(|(header, prefix)| {
+			headers.get(*header).map(|value| (*header, *prefix, value))
+		})
+		.context(Message {
+			msg: "Missing x-hub-signature-256 and x-hub-signature headers"
+				.to_string(),
+		})?;
+	let malformed = |why: &str| Error::Message {
+		msg: format!("Malformed {}: {}", header, why),
+	};
+	let value = value.to_str().map_err(|_| malformed("not text"))?.trim();
+	// the prefix was optional for SHA-1
+	let hex = match value.get(..prefix.len()) {
+		Some(head) if head.eq_ignore_ascii_case(prefix) => {
+			&value[prefix.len()..]
+		}
+		_ => value,
+	};
+	let signature = base16::decode(hex.as_bytes())
+		.map_err(|_| malformed("not hexadecimal"))?;
+	let algorithm = algorithm(header);
+	if signature.len() != algorithm.digest_algorithm().output_len {
+		return Err(malformed("wrong length"));
+	}
+	hmac::verify(&hmac::Key::new(algorithm, secret), body, &signature).map_err(
+		|_| Error::Message {
+			msg: format!(
+				"{} does not match the body; check WEBHOOK_SECRET",
+				header
+			),
+		},
+	)
+}
+
+/// The headers GitHub would send with `body` signed with `secret`.
+fn sign(secret: &[u8], body: &[u8], header: &'static str) -> HeaderMap {
+	let key = hmac::Key::new(algorithm(header), secret);
+	let prefix = HEADERS
+		.iter()
+		.find(|(name, _)| *name == header)
+		.map_or("", |(_, prefix)| prefix);
+	let mut headers = HeaderMap::new();
+	headers.insert(
+		header,
+		format!(
+			"{}{}",
+			prefix,
+			base16::encode_lower(hmac::sign(&key, body).as_ref())
+		)
+		.parse()
+		.expect("hex is a valid header value"),
+	);
+	headers
+}
+
+/// Signs a body with the secret as GitHub does, and checks that it verifies,
+/// and that a changed body does not.
+pub struct SelfTest<'a>(pub &'a str);
+
+impl StartupCheck for SelfTest<'_> {
+	fn name(&self) -> &'static str {
+		"webhook-signature"
+	}
+
+	fn check(&self) -> BoxFuture<'_, std::result::Result<(), String>> {
+		let secret = self.0.as_bytes();
+		let body = br#"{"zen":"Keep it logically awesome."}"#;
+		let result = HEADERS.iter().try_for_each(|(header, _)| {
+			let headers = sign(secret, body, header);
+			verify(secret, &headers, body).map_err(|e| e.to_string())?;
+			match verify(secret, &headers, b"{}") {
+				Ok(()) => Err(format!("{} verifies any body", header)),
+				Err(_) => Ok(()),
+			}
+		});
+		future::ready(result).boxed()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use hyper::{Body, Request};
+
+	const SECRET: &[u8] = b"secret";
+	const BODY: &[u8] = br#"{"action":"created"}"#;
+
+	/// The headers of a request with these headers, as hyper reads them.
+	fn request(headers: &[(&str, &[u8])]) -> HeaderMap {
+		headers
+			.iter()
+			.fold(Request::builder(), |builder, (name, value)| {
+				builder.header(*name, *value)
+			})
+			.body(Body::empty())
+			.unwrap()
+			.into_parts()
+			.0
+			.headers
+	}
+
+	fn signature(header: &'static str, secret: &[u8]) -> String {
+		sign(secret, BODY, header)[header]
+			.to_str()
+			.unwrap()
+			.to_string()
+	}
+
+	fn message(result: Result<()>) -> String {
+		match result {
+			Err(Error::Message { msg }) => msg,
+			result => panic!("unexpected result {:?}", result),
+		}
+	}
+
+	#[test]
+	fn test_verify() {
+		let sha256 = signature("x-hub-signature-256", SECRET);
+		let sha1 = signature("x-hub-signature", SECRET);
+		assert!(sha256.starts_with("sha256="));
+		for headers in &[
+			vec![("x-hub-signature-256", sha256.as_bytes())],
+			vec![("x-hub-signature", sha1.as_bytes())],
+			// as sent through a proxy
+			vec![("X-Hub-Signature-256", sha256.to_uppercase().as_bytes())],
+			vec![("X-HUB-SIGNATURE", sha1.to_uppercase().as_bytes())],
+			// the prefix was optional
+			vec![("x-hub-signature", &sha1.as_bytes()["sha1=".len()..])],
+			vec![
+				("x-hub-signature-256", sha256.as_bytes()),
+				("x-hub-signature", &b"sha1=0000"[..]),
+			],
+		] {
+			assert!(
+				verify(SECRET, &request(headers), BODY).is_ok(),
+				"{:?}",
+				headers
+			);
+		}
+	}
+
+	#[test]
+	fn test_verify_failures() {
+		let verify = |headers: &[(&str, &[u8])]| {
+			message(verify(SECRET, &request(headers), BODY))
+		};
+		assert_eq!(
+			verify(&[]),
+			"Missing x-hub-signature-256 and x-hub-signature headers"
+		);
+		assert_eq!(
+			verify(&[("x-hub-signature-256", b"sha256=not-hex")]),
+			"Malformed x-hub-signature-256: not hexadecimal"
+		);
+		assert_eq!(
+			verify(&[("x-hub-signature", b"sha1=abcd")]),
+			"Malformed x-hub-signature: wrong length"
+		);
+		assert_eq!(
+			verify(&[("x-hub-signature", b"sha1=\xff")]),
+			"Malformed x-hub-signature: not text"
+		);
+		// a SHA-1 signature sent as a SHA-256 one
+		let sha1 = signature("x-hub-signature", SECRET);
+		assert_eq!(
+			verify(&[(
+				"x-hub-signature-256",
+				sha1.replace("sha1=", "sha256=").as_bytes()
+			)]),
+			"Malformed x-hub-signature-256: wrong length"
+		);
+		let forged = signature("x-hub-signature-256", b"forged");
+		assert_eq!(
+			verify(&[("x-hub-signature-256", forged.as_bytes())]),
+			"x-hub-signature-256 does not match the body; check WEBHOOK_SECRET"
+		);
+		// the stronger signature is the one checked
+		assert_eq!(
+			verify(&[
+				("x-hub-signature-256", forged.as_bytes()),
+				("x-hub-signature", sha1.as_bytes()),
+			]),
+			"x-hub-signature-256 does not match the body; check WEBHOOK_SECRET"
+		);
+		let sha256 = signature("x-hub-signature-256", SECRET);
+		assert!(message(super::verify(
+			SECRET,
+			&request(&[("x-hub-signature-256", sha256.as_bytes())]),
+			b"{}"
+		))
+		.contains("does not match"));
+	}
+
+	#[test]
+	fn test_normalize_secret() {
+		assert_eq!(normalize_secret("secret\n").as_deref(), Ok("secret"));
+		assert_eq!(normalize_secret(" secret ").as_deref(), Ok("secret"));
+		assert_eq!(normalize_secret("sec ret").as_deref(), Ok("sec ret"));
+		assert!(normalize_secret("").is_err());
+		assert!(normalize_secret(" \n").is_err());
+		assert!(suspicious("secret").is_empty());
+		assert_eq!(
+			suspicious("secret\n"),
+			vec!["has whitespace around it, which is ignored"]
+		);
+		assert_eq!(
+			suspicious("sec ret"),
+			vec!["contains whitespace, which is kept"]
+		);
+	}
+
+	#[test]
+	fn test_self_test() {
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		assert_eq!(rt.block_on(SelfTest("secret").check()), Ok(()));
+		assert_eq!(SelfTest("secret").name(), "webhook-signature");
+	}
+}
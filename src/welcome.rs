@@ -0,0 +1,113 @@
+use rocksdb::DB;
+use snafu::ResultExt;
+
+use crate::{error::*, github::PullRequest, Result};
+
+const KEY_PREFIX: &str = "welcome/";
+
+/// Author associations of those with no commits in a repository yet.
+const FIRST_TIME: [&str; 2] = ["FIRST_TIME_CONTRIBUTOR", "FIRST_TIMER"];
+
+/// Whether the author of a PR has not contributed to its repository before.
+pub fn is_first_contribution(pr: &PullRequest) -> bool {
+	pr.author_association
+		.as_deref()
+		.is_some_and(|association| FIRST_TIME.contains(&association))
+}
+
+/// The welcome of `author` from a template, with `{author}`, `{owner}` and
+/// `{repo}` filled in.
+pub fn render(
+	template: &str,
+	author: &str,
+	owner: &str,
+	repo_name: &str,
+) -> String {
+	template
+		.replace("{author}", author)
+		.replace("{owner}", owner)
+		.replace("{repo}", repo_name)
+}
+
+fn key(owner: &str, repo_name: &str, author: &str) -> String {
+	format!(
+		"{}{}/{}/{}",
+		KEY_PREFIX,
+		owner.to_lowercase(),
+		repo_name.to_lowercase(),
+		author.to_lowercase()
+	)
+}
+
+/// Whether `author` was welcomed to the repository already.
+pub fn welcomed(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+	author: &str,
+) -> Result<bool> {
+	db.get(key(owner, repo_name, author).as_bytes())
+		.context(Db)
+		.map(|bytes| bytes.is_some())
+}
+
+pub fn record(
+	db: &DB,
+	owner: &str,
+	repo_name: &str,
+	author: &str,
+	now: i64,
+) -> Result<()> {
+	let bytes = bincode::serialize(&now).context(Bincode)?;
+	db.put(key(owner, repo_name, author).as_bytes(), bytes)
+		.context(Db)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::db::TempDb;
+
+	#[test]
+	fn test_is_first_contribution() {
+		let pr = |association: Option<&str>| PullRequest {
+			author_association: association.map(str::to_string),
+			..PullRequest::default()
+		};
+		assert!(is_first_contribution(&pr(Some("FIRST_TIME_CONTRIBUTOR"))));
+		assert!(is_first_contribution(&pr(Some("FIRST_TIMER"))));
+		assert!(!is_first_contribution(&pr(Some("CONTRIBUTOR"))));
+		assert!(!is_first_contribution(&pr(Some("MEMBER"))));
+		assert!(!is_first_contribution(&pr(None)));
+	}
+
+	#[test]
+	fn test_render() {
+		assert_eq!(
+			render(
+				"Welcome @{author}! See https://github.com/{owner}/{repo}/blob/master/CONTRIBUTING.md, and comment `bot help` for the commands of {repo}.",
+				"sjeohp",
+				"paritytech",
+				"substrate"
+			),
+			"Welcome @sjeohp! See https://github.com/paritytech/substrate/blob/master/CONTRIBUTING.md, and comment `bot help` for the commands of substrate."
+		);
+		// unknown placeholders are kept
+		assert_eq!(
+			render("Hi {author}, {user}", "sjeohp", "paritytech", "substrate"),
+			"Hi sjeohp, {user}"
+		);
+	}
+
+	#[test]
+	fn test_welcomed_once() {
+		let db = TempDb::new("welcome");
+		assert!(!welcomed(&db, "paritytech", "substrate", "sjeohp").unwrap());
+		record(&db, "paritytech", "substrate", "sjeohp", 100).unwrap();
+		assert!(welcomed(&db, "paritytech", "substrate", "sjeohp").unwrap());
+		assert!(welcomed(&db, "ParityTech", "Substrate", "SJeohp").unwrap());
+		// per repository and author
+		assert!(!welcomed(&db, "paritytech", "polkadot", "sjeohp").unwrap());
+		assert!(!welcomed(&db, "paritytech", "substrate", "tomaka").unwrap());
+	}
+}
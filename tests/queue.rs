@@ -0,0 +1,153 @@
+use parity_processbot::{db, migrations, queue::MergeRequest};
+use rocksdb::DB;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+const SUBSTRATE_SHA: &str = "1111111111111111111111111111111111111111";
+const POLKADOT_SHA: &str = "2222222222222222222222222222222222222222";
+const INVALID_SHA: &str = "3333333333333333333333333333333333333333";
+const OTHER_KEY: &str = "burnin-record/paritytech/substrate/1";
+const SUBSTRATE_INDEX: &str = "idx/paritytech/substrate/1234";
+
+/// A database with two pending merges, one entry which does not deserialize
+/// and one which is not part of the queue, as left by a bot which kept them
+/// all in the default column family.
+struct TempDb(PathBuf);
+
+impl TempDb {
+	fn new(name: &str) -> Self {
+		let path = std::env::temp_dir().join(format!(
+			"processbot-queue-{}-{}",
+			name,
+			std::process::id()
+		));
+		let _ = std::fs::remove_dir_all(&path);
+		let db = DB::open_default(&path).unwrap();
+		for (sha, repo_name, number) in &[
+			(SUBSTRATE_SHA, "substrate", 1234),
+			(POLKADOT_SHA, "polkadot", 567),
+		] {
+			let request = MergeRequest {
+				owner: "paritytech".to_string(),
+				repo_name: repo_name.to_string(),
+				number: *number,
+				html_url: format!(
+					"https://github.com/paritytech/{}/pull/{}",
+					repo_name, number
+				),
+				requested_by: "gavofyork".to_string(),
+				created_at: Some(1_600_000_000),
+				origin: None,
+				not_before: None,
+			};
+			db.put(sha, migrations::encode(&request).unwrap()).unwrap();
+		}
+		db.put(INVALID_SHA, b"garbage").unwrap();
+		db.put(OTHER_KEY, b"record").unwrap();
+		TempDb(path)
+	}
+
+	fn run(&self, args: &[&str]) -> Output {
+		Command::new(env!("CARGO_BIN_EXE_parity-processbot"))
+			.arg("queue")
+			.args(args)
+			.arg("--db")
+			.arg(&self.0)
+			.output()
+			.unwrap()
+	}
+
+	/// The keys of the default column family, then those of the others.
+	fn keys(&self) -> Vec<String> {
+		let db = db::open(&self.0).unwrap();
+		let keys = db
+			.iterator(rocksdb::IteratorMode::Start)
+			.chain(
+				db::FAMILIES
+					.iter()
+					.flat_map(|name| db::family(&db, name).unwrap().iter()),
+			)
+			.map(|(key, _)| String::from_utf8(key.to_vec()).unwrap())
+			.collect();
+		keys
+	}
+}
+
+impl Drop for TempDb {
+	fn drop(&mut self) {
+		let _ = std::fs::remove_dir_all(&self.0);
+	}
+}
+
+fn stdout(output: &Output) -> String {
+	assert!(
+		output.status.success(),
+		"{}",
+		String::from_utf8_lossy(&output.stderr)
+	);
+	String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[test]
+fn test_list() {
+	let db = TempDb::new("list");
+	assert_eq!(
+		stdout(&db.run(&["list"])),
+		format!(
+			"SHA                                       PULL REQUEST               REQUESTED BY
+{}  paritytech/substrate#1234  gavofyork
+{}  paritytech/polkadot#567    gavofyork
+{}  <invalid entry>
+",
+			SUBSTRATE_SHA, POLKADOT_SHA, INVALID_SHA
+		)
+	);
+}
+
+#[test]
+fn test_list_json() {
+	let db = TempDb::new("list-json");
+	let entries: serde_json::Value =
+		serde_json::from_str(&stdout(&db.run(&["list", "--json"]))).unwrap();
+	assert_eq!(entries.as_array().unwrap().len(), 3);
+	assert_eq!(entries[0]["sha"], SUBSTRATE_SHA);
+	assert_eq!(entries[0]["request"]["repo_name"], "substrate");
+	assert_eq!(entries[0]["request"]["number"], 1234);
+	assert_eq!(entries[2]["sha"], INVALID_SHA);
+	assert!(entries[2]["request"].is_null());
+}
+
+#[test]
+fn test_remove() {
+	let db = TempDb::new("remove");
+	assert_eq!(
+		stdout(&db.run(&["remove", "paritytech/polkadot#567"])),
+		format!("Removed {}\n", POLKADOT_SHA)
+	);
+	// entries which do not deserialize can still be removed by sha
+	assert_eq!(
+		stdout(&db.run(&["remove", INVALID_SHA])),
+		format!("Removed {}\n", INVALID_SHA)
+	);
+	assert_eq!(db.keys(), vec![OTHER_KEY, SUBSTRATE_SHA, SUBSTRATE_INDEX]);
+
+	let missing = db.run(&["remove", "paritytech/polkadot#567"]);
+	assert!(!missing.status.success());
+	assert_eq!(
+		String::from_utf8_lossy(&missing.stderr),
+		"No pending merge matches paritytech/polkadot#567\n"
+	);
+	assert!(!db.run(&["remove", "polkadot#567"]).status.success());
+	assert_eq!(db.keys(), vec![OTHER_KEY, SUBSTRATE_SHA, SUBSTRATE_INDEX]);
+}
+
+#[test]
+fn test_clear() {
+	let db = TempDb::new("clear");
+	assert_eq!(stdout(&db.run(&["clear"])), "Removed 3 pending merges\n");
+	assert_eq!(db.keys(), vec![OTHER_KEY]);
+	assert_eq!(
+		stdout(&db.run(&["list"])),
+		format!("{:<40}  PULL REQUEST  REQUESTED BY\n", "SHA")
+	);
+}